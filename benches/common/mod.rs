@@ -0,0 +1,211 @@
+//! Asynchronous LSP client for benchmarks against a running server.
+//!
+//! Unlike the blocking test client, responses are routed to their requests
+//! by a reader task, so a request costs one round trip and no polling.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+    process::{self, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+};
+
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::{Mutex as AsyncMutex, oneshot},
+    task::JoinHandle,
+};
+
+type Pending = Arc<Mutex<HashMap<i64, oneshot::Sender<Value>>>>;
+
+pub struct BenchClient {
+    child: Child,
+    writer: Arc<AsyncMutex<ChildStdin>>,
+    pending: Pending,
+    next_id: AtomicI64,
+    reader: JoinHandle<()>,
+}
+
+impl BenchClient {
+    /// Start the language server at `binary`, speaking LSP over stdio.
+    pub fn start(binary: &Path) -> Result<Self> {
+        let mut child = Command::new(binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let writer = Arc::new(AsyncMutex::new(child.stdin.take().unwrap()));
+        let pending = Pending::default();
+        let reader = tokio::spawn(read_messages(
+            child.stdout.take().unwrap(),
+            pending.clone(),
+            writer.clone(),
+        ));
+        Ok(Self {
+            child,
+            writer,
+            pending,
+            next_id: AtomicI64::new(1),
+            reader,
+        })
+    }
+
+    /// Process id of the server, `None` once it exited.
+    pub fn pid(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Send a request and wait for its response.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        let mut request = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        // requests without parameters omit them
+        if !params.is_null() {
+            request["params"] = params;
+        }
+        send(&self.writer, &request).await?;
+        receiver
+            .await
+            .map_err(|e| Error::new(ErrorKind::BrokenPipe, e))
+    }
+
+    pub async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        let mut notification = json!({ "jsonrpc": "2.0", "method": method });
+        if !params.is_null() {
+            notification["params"] = params;
+        }
+        send(&self.writer, &notification).await
+    }
+
+    /// Initialize the connection for the workspace at `root_uri`.
+    pub async fn initialize(&self, root_uri: &str) -> Result<Value> {
+        let response = self
+            .request(
+                "initialize",
+                json!({
+                    "processId": process::id(),
+                    "rootUri": root_uri,
+                    "capabilities": {},
+                }),
+            )
+            .await?;
+        self.notify("initialized", json!({})).await?;
+        Ok(response)
+    }
+
+    pub async fn open_document(&self, uri: &str, text: &str) -> Result<()> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "rust",
+                    "version": 1,
+                    "text": text
+                }
+            }),
+        )
+        .await
+    }
+
+    /// Request the decorations at a position.
+    pub async fn cursor(&self, uri: &str, line: u32, character: u32) -> Result<Value> {
+        self.request(
+            "ferrous-owl/cursor",
+            json!({
+                "position": { "line": line, "character": character },
+                "document": { "uri": uri }
+            }),
+        )
+        .await
+    }
+
+    /// Shut the server down and wait for it to exit.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.request("shutdown", Value::Null).await?;
+        self.notify("exit", Value::Null).await?;
+        // the server stops reading once its input is closed
+        self.reader.abort();
+        let _ = self.reader.await;
+        drop(self.writer);
+        self.child.wait().await?;
+        Ok(())
+    }
+}
+
+/// Resident set size of the process `pid` in KiB, where the platform reports
+/// it.
+pub fn rss_kib(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+async fn send(writer: &AsyncMutex<ChildStdin>, message: &Value) -> Result<()> {
+    let content = serde_json::to_string(message)?;
+    let mut writer = writer.lock().await;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", content.len()).as_bytes())
+        .await?;
+    writer.write_all(content.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Route responses to their pending requests until the server closes its
+/// output. Requests of the server are answered with an empty result.
+async fn read_messages(
+    reader: impl AsyncRead + Unpin,
+    pending: Pending,
+    writer: Arc<AsyncMutex<ChildStdin>>,
+) {
+    let mut reader = BufReader::new(reader);
+    while let Some(message) = read_message(&mut reader).await {
+        let id = message.get("id").and_then(Value::as_i64);
+        if message.get("method").is_some() {
+            if let Some(id) = id {
+                let response = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+                if send(&writer, &response).await.is_err() {
+                    break;
+                }
+            }
+        } else if let Some(sender) = id.and_then(|id| pending.lock().unwrap().remove(&id)) {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+async fn read_message(reader: &mut (impl AsyncBufReadExt + Unpin)) -> Option<Value> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await.ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse().ok();
+        }
+    }
+    let mut content = vec![0; content_length?];
+    reader.read_exact(&mut content).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
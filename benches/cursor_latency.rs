@@ -0,0 +1,147 @@
+//! End-to-end latency of cursor requests on an analyzed workspace.
+//!
+//! Starts the server once against `benches/dummy`, waits for its analysis,
+//! then times `ferrous-owl/cursor` requests at random positions of the
+//! fixture. Latency percentiles and the resident memory of the server are
+//! printed and written to `target/owl-bench.json`.
+//!
+//! Run with `cargo bench --bench cursor_latency`, adding `-- --quick` for a
+//! short run.
+
+mod common;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+use tokio::time;
+
+use crate::common::BenchClient;
+
+const REQUESTS: usize = 200;
+const QUICK_REQUESTS: usize = 20;
+const ANALYSIS_TIMEOUT: Duration = Duration::from_secs(600);
+/// Position of `guard` in the fixture, polled until the analysis finished
+const PROBE: (u32, u32) = (85, 12);
+
+/// Positions on the non-empty lines of `code`, picked by a xorshift with a
+/// fixed seed so that runs are comparable.
+fn random_positions(code: &str, count: usize) -> Vec<(u32, u32)> {
+    let lines: Vec<(u32, u32)> = code
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            (
+                u32::try_from(index).unwrap(),
+                u32::try_from(line.chars().count()).unwrap(),
+            )
+        })
+        .collect();
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..count)
+        .map(|_| {
+            let (line, len) = lines[usize::try_from(next() % lines.len() as u64).unwrap()];
+            (line, u32::try_from(next() % u64::from(len)).unwrap())
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of sorted `durations`, in milliseconds.
+fn percentile(durations: &[Duration], percent: usize) -> f64 {
+    let rank = (durations.len() * percent).div_ceil(100).max(1);
+    durations[rank - 1].as_secs_f64() * 1000.0
+}
+
+async fn wait_for_analysis(client: &BenchClient, uri: &str) {
+    let start = Instant::now();
+    loop {
+        let response = client.cursor(uri, PROBE.0, PROBE.1).await.unwrap();
+        let result = &response["result"];
+        if result["is_analyzed"] == json!(true) && result["status"] == json!("finished") {
+            return;
+        }
+        assert!(
+            start.elapsed() < ANALYSIS_TIMEOUT,
+            "analysis did not finish: {response}"
+        );
+        time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+fn report_path() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR").map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("target"),
+        PathBuf::from,
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    let quick = env::args().any(|arg| arg == "--quick");
+    let count = if quick { QUICK_REQUESTS } else { REQUESTS };
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/dummy");
+    let file = fixture.join("src/lib.rs");
+    let code = fs::read_to_string(&file).expect("Failed to read fixture");
+    let uri = format!("file://{}", file.display());
+
+    let client = BenchClient::start(Path::new(env!("CARGO_BIN_EXE_ferrous-owl")))
+        .expect("Failed to start ferrous-owl");
+    client
+        .initialize(&format!("file://{}", fixture.display()))
+        .await
+        .unwrap();
+    client.open_document(&uri, &code).await.unwrap();
+    let analysis_start = Instant::now();
+    wait_for_analysis(&client, &uri).await;
+    let analysis = analysis_start.elapsed();
+    let pid = client.pid().expect("server exited");
+    let rss_before = common::rss_kib(pid);
+
+    let mut latencies = Vec::with_capacity(count);
+    for (line, character) in random_positions(&code, count) {
+        let start = Instant::now();
+        let response = client.cursor(&uri, line, character).await.unwrap();
+        latencies.push(start.elapsed());
+        assert!(response.get("error").is_none(), "{response}");
+    }
+    let rss_after = common::rss_kib(pid);
+    client.shutdown().await.unwrap();
+
+    latencies.sort();
+    #[allow(
+        clippy::cast_precision_loss,
+        reason = "request counts are far below 2^52"
+    )]
+    let mean = latencies.iter().sum::<Duration>().as_secs_f64() * 1000.0 / count as f64;
+    let report = json!({
+        "requests": count,
+        "quick": quick,
+        "analysis_ms": analysis.as_secs_f64() * 1000.0,
+        "latency_ms": {
+            "p50": percentile(&latencies, 50),
+            "p95": percentile(&latencies, 95),
+            "mean": mean,
+            "max": percentile(&latencies, 100),
+        },
+        "rss_kib": { "before": rss_before, "after": rss_after },
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+    let dir = report_path();
+    fs::create_dir_all(&dir).expect("Failed to create target directory");
+    fs::write(
+        dir.join("owl-bench.json"),
+        serde_json::to_string_pretty(&report).unwrap(),
+    )
+    .expect("Failed to write report");
+}
@@ -0,0 +1,82 @@
+#![feature(rustc_private)]
+
+//! Serialized size of the analysis results of `benches/dummy`.
+//!
+//! Analyzes the fixture, then compares the size of its results as sent by
+//! the compiler, with the types of declarations interned per file, to the
+//! size with every declaration naming its type, as former versions did. The
+//! sizes are printed and written to `target/owl-payload.json`.
+//!
+//! Run with `cargo bench --bench payload_size`.
+
+use std::{
+    env, fs, mem,
+    path::{Path, PathBuf},
+};
+
+use ferrous_owl::api::{self, AnalyzeOptions, DeclType, MirDecl, Workspace};
+use serde_json::json;
+
+fn report_path() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR").map_or_else(
+        || Path::new(env!("CARGO_MANIFEST_DIR")).join("target"),
+        PathBuf::from,
+    )
+}
+
+/// `workspace` with the type of every declaration named instead of interned.
+fn with_named_types(mut workspace: Workspace) -> Workspace {
+    for file in workspace
+        .crates
+        .values_mut()
+        .flat_map(|krate| krate.0.values_mut())
+    {
+        let types = mem::take(&mut file.types);
+        for decl in file
+            .items
+            .iter_mut()
+            .flat_map(|item| &mut item.function_mut().decls)
+        {
+            let (MirDecl::User { ty, .. } | MirDecl::Other { ty, .. }) = decl;
+            *ty = DeclType::Name(ty.name(&types).to_owned());
+        }
+    }
+    workspace
+}
+
+#[tokio::main]
+async fn main() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/dummy");
+    let options = AnalyzeOptions {
+        compiler: Some(PathBuf::from(env!("CARGO_BIN_EXE_ferrous-owl"))),
+        force: true,
+        ..AnalyzeOptions::default()
+    };
+    let workspace = api::analyze_package(&fixture, options)
+        .await
+        .expect("Failed to analyze the fixture");
+
+    let interned = serde_json::to_string(&workspace).unwrap().len();
+    let named = serde_json::to_string(&with_named_types(workspace))
+        .unwrap()
+        .len();
+    #[allow(
+        clippy::cast_precision_loss,
+        reason = "payload sizes are far below 2^52"
+    )]
+    let reduction = 100.0 * (1.0 - interned as f64 / named as f64);
+    let report = json!({
+        "named_bytes": named,
+        "interned_bytes": interned,
+        "reduction_percent": reduction,
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+    let dir = report_path();
+    fs::create_dir_all(&dir).expect("Failed to create target directory");
+    fs::write(
+        dir.join("owl-payload.json"),
+        serde_json::to_string_pretty(&report).unwrap(),
+    )
+    .expect("Failed to write report");
+}
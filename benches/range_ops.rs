@@ -0,0 +1,131 @@
+#![feature(rustc_private)]
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use ferrous_owl::{
+    CharOffset, Range,
+    range_ops::{self, common_range, merge_ranges},
+};
+
+/// Random ranges of up to 100 locations in a function of `count * 10`
+/// locations, generated by a xorshift with a fixed seed.
+fn random_ranges(count: u32, mut seed: u64) -> Vec<Range> {
+    let mut below = |bound: u32| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        u32::try_from(seed % u64::from(bound)).unwrap()
+    };
+    (0..count)
+        .map(|_| {
+            let from = below(count * 10);
+            let until = from + 1 + below(100);
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        })
+        .collect()
+}
+
+/// Pairwise merging, as done before the sweep.
+fn pairwise_eliminated_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+    let mut i = 0;
+    'outer: while i < ranges.len() {
+        let mut j = 0;
+        while j < ranges.len() {
+            if i != j
+                && let Some(merged) = merge_ranges(ranges[i], ranges[j])
+            {
+                ranges[i] = merged;
+                ranges.remove(j);
+                continue 'outer;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    ranges
+}
+
+/// Pairwise subtraction, as done before the interval tree.
+fn pairwise_exclude_ranges(mut from: Vec<Range>, excludes: &[Range]) -> Vec<Range> {
+    let mut i = 0;
+    'outer: while i < from.len() {
+        let mut j = 0;
+        while j < excludes.len() {
+            if let Some(common) = common_range(from[i], excludes[j]) {
+                if let Some(r) = Range::new(from[i].from(), common.from() - 1) {
+                    from.push(r);
+                }
+                if let Some(r) = Range::new(common.until() + 1, from[i].until()) {
+                    from.push(r);
+                }
+                from.remove(i);
+                continue 'outer;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    pairwise_eliminated_ranges(from)
+}
+
+/// Pairwise intersection, as done before the sweep.
+fn pairwise_common_ranges(ranges: &[Range]) -> Vec<Range> {
+    let mut common_ranges = Vec::new();
+    for i in 0..ranges.len() {
+        for j in i + 1..ranges.len() {
+            if let Some(common) = common_range(ranges[i], ranges[j]) {
+                common_ranges.push(common);
+            }
+        }
+    }
+    pairwise_eliminated_ranges(common_ranges)
+}
+
+fn bench_range_ops(c: &mut Criterion) {
+    let mut group = c.benchmark_group("range_ops");
+    group.sample_size(10);
+    for count in [1_000, 10_000] {
+        let ranges = random_ranges(count, 0x5eed);
+        let excludes = random_ranges(count, 0xfeed);
+
+        group.bench_with_input(
+            BenchmarkId::new("eliminated/pairwise", count),
+            &count,
+            |b, _| {
+                b.iter(|| black_box(pairwise_eliminated_ranges(ranges.clone())));
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("eliminated/sweep", count),
+            &count,
+            |b, _| {
+                b.iter(|| black_box(range_ops::eliminated_ranges(ranges.clone())));
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("common/pairwise", count),
+            &count,
+            |b, _| {
+                b.iter(|| black_box(pairwise_common_ranges(&ranges)));
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("common/sweep", count), &count, |b, _| {
+            b.iter(|| black_box(range_ops::common_ranges(&ranges)));
+        });
+        group.bench_with_input(
+            BenchmarkId::new("exclude/pairwise", count),
+            &count,
+            |b, _| {
+                b.iter(|| black_box(pairwise_exclude_ranges(ranges.clone(), &excludes)));
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("exclude/tree", count), &count, |b, _| {
+            b.iter(|| black_box(range_ops::exclude_ranges(ranges.clone(), &excludes)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_range_ops);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+#![feature(rustc_private)]
+
+use std::{fs, hint::black_box};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use ferrous_owl::SourceIndex;
+
+/// The bench fixture repeated `copies` times, like a large source file.
+fn fixture(copies: usize) -> String {
+    fs::read_to_string("./benches/dummy/src/lib.rs")
+        .expect("Failed to read bench fixture")
+        .repeat(copies)
+}
+
+/// Byte positions of every line start, like the spans of a function with a
+/// statement per line.
+fn span_positions(source: &str) -> Vec<u32> {
+    source
+        .match_indices('\n')
+        .map(|(pos, _)| u32::try_from(pos + 1).unwrap())
+        .collect()
+}
+
+/// Conversion rescanning the source for each position, as done before
+/// [`SourceIndex`].
+fn scan_byte_to_char(source: &str, byte_pos: u32) -> usize {
+    let source_clean = source.replace('\r', "");
+    source_clean
+        .char_indices()
+        .position(|(byte_idx, _)| (byte_pos as usize) <= byte_idx)
+        .unwrap_or_else(|| source_clean.chars().count())
+}
+
+fn bench_byte_to_char(c: &mut Criterion) {
+    let mut group = c.benchmark_group("byte_to_char");
+    for copies in [1, 10] {
+        let source = fixture(copies);
+        let positions = span_positions(&source);
+
+        group.bench_with_input(BenchmarkId::new("scan", copies), &copies, |b, _| {
+            b.iter(|| {
+                for &pos in &positions {
+                    black_box(scan_byte_to_char(&source, pos));
+                }
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("source_index", copies), &copies, |b, _| {
+            b.iter(|| {
+                let index = SourceIndex::new(&source);
+                for &pos in &positions {
+                    black_box(index.byte_to_char(pos));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_byte_to_char);
+criterion_main!(benches);
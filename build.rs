@@ -9,6 +9,10 @@ fn main() {
     let sysroot = get_sysroot();
     println!("cargo::rustc-env=COMPILE_TIME_SYSROOT={sysroot}");
 
+    // Set commit hash of the compiler whose libraries are linked
+    let commit_hash = get_commit_hash();
+    println!("cargo::rustc-env=RUSTC_COMMIT_HASH={commit_hash}");
+
     // Set rpath for dynamic linking to rustc libraries
     #[cfg(target_os = "macos")]
     println!("cargo::rustc-link-arg=-Wl,-rpath,@executable_path/../lib");
@@ -37,3 +41,18 @@ fn get_sysroot() -> String {
         .map(|v| String::from_utf8(v.stdout).unwrap().trim().to_string())
         .expect("failed to obtain sysroot")
 }
+
+fn get_commit_hash() -> String {
+    Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()))
+        .arg("-vV")
+        .output()
+        .map(|v| {
+            String::from_utf8(v.stdout)
+                .unwrap()
+                .lines()
+                .find_map(|line| line.strip_prefix("commit-hash: "))
+                .unwrap_or("unknown")
+                .to_string()
+        })
+        .expect("failed to obtain commit hash")
+}
@@ -20,6 +20,8 @@ type LspDecorationType =
   | "mut_borrow"
   | "move"
   | "call"
+  | "drop"
+  | "dead_write"
   | "shared_mut"
   | "outlive";
 
@@ -28,11 +30,12 @@ interface LspDecoration {
   readonly range: Range;
   readonly hover_text?: string | null;
   readonly overlapped: boolean;
+  readonly layer: number;
 }
 
 interface LspCursorResponse {
   readonly is_analyzed: boolean;
-  readonly status: "analyzing" | "finished" | "error";
+  readonly status: "analyzing" | "finished" | "error" | "no_variable_selected" | "stale" | "skipped";
   readonly decorations: readonly LspDecoration[];
 }
 
@@ -80,6 +83,18 @@ const lspRangeToVscodeRange = (range: Range): vscode.Range =>
     new vscode.Position(range.end.line, range.end.character),
   );
 
+/** Whether a decoration of a higher layer overlaps `deco`, hiding it. */
+const isCovered = (deco: LspDecoration, decorations: readonly LspDecoration[]): boolean => {
+  const range = lspRangeToVscodeRange(deco.range);
+  return decorations.some((other) => {
+    if (other.layer <= deco.layer) {
+      return false;
+    }
+    const common = range.intersection(lspRangeToVscodeRange(other.range));
+    return common !== undefined && !common.isEmpty;
+  });
+};
+
 const createDecorationType = (
   color: string,
   thickness: string,
@@ -96,6 +111,7 @@ const categorizeDecoration = (
 ): "lifetime" | "immut" | "mut" | "moveCall" | "sharedMut" | "outlive" => {
   switch (deco.type) {
     case "lifetime":
+    case "drop":
       return "lifetime";
     case "imm_borrow":
       return "immut";
@@ -105,6 +121,7 @@ const categorizeDecoration = (
     case "move":
       return "moveCall";
     case "shared_mut":
+    case "dead_write":
       return "sharedMut";
     case "outlive":
       return "outlive";
@@ -155,7 +172,7 @@ class DecorationManager {
     for (const deco of data.decorations) {
       const range = lspRangeToVscodeRange(deco.range);
 
-      if (!deco.overlapped) {
+      if (!isCovered(deco, data.decorations)) {
         const category = categorizeDecoration(deco);
         grouped[category].push({ range });
       }
@@ -192,6 +209,7 @@ class StatusBarManager {
   public updateFromLspStatus(status: string): void {
     switch (status) {
       case "finished":
+      case "no_variable_selected":
         this.statusBar.text = "$(check) FerrousOwl";
         this.statusBar.tooltip = "Analysis finished";
         break;
@@ -199,6 +217,14 @@ class StatusBarManager {
         this.statusBar.text = "$(loading~spin) FerrousOwl";
         this.statusBar.tooltip = "Analyzing...";
         break;
+      case "stale":
+        this.statusBar.text = "$(sync~spin) FerrousOwl";
+        this.statusBar.tooltip = "File changed since its analysis, analyzing again...";
+        break;
+      case "skipped":
+        this.statusBar.text = "$(warning) FerrousOwl";
+        this.statusBar.tooltip = "File too large to analyze";
+        break;
       default:
         this.statusBar.text = "$(error) FerrousOwl";
         this.statusBar.tooltip = "Analysis failed";
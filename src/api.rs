@@ -0,0 +1,204 @@
+//! Analysis of packages and the decorations of their variables, for tools
+//! embedding the analysis without the language server.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use ferrous_owl::api::{self, AnalyzeOptions, CharOffset};
+//!
+//! # async fn run() -> Result<(), api::AnalysisError> {
+//! let options = AnalyzeOptions {
+//!     compiler: Some("/usr/local/bin/ferrous-owl".into()),
+//!     ..AnalyzeOptions::default()
+//! };
+//! let workspace = api::analyze_package(Path::new("my-crate"), options).await?;
+//! let file = Path::new("/home/me/my-crate/src/lib.rs");
+//! for deco in api::decorations_for(&workspace, file, CharOffset::from(120u32)) {
+//!     println!("{}: {}", deco.kind(), deco.hover_text());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    error, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[allow(deprecated, reason = "the former name stays for dependents")]
+pub use crate::models::Loc;
+pub use crate::{
+    lsp_decoration::Deco,
+    lsp_workspace::{AnalyzerError, MAX_FILE_BYTES_ENV, TargetKind},
+    models::{
+        CharOffset, Crate, DeclType, File, FnLocal, Function, LineCol, MirBasicBlock, MirDecl,
+        MirRval, MirStatement, MirTerminator, Range, Workspace,
+    },
+};
+use crate::{
+    lsp_selection,
+    lsp_workspace::{Analyzer, AnalyzerEvent},
+    path_norm,
+};
+
+/// Options of [`analyze_package`]
+#[derive(Clone, Debug, Default)]
+pub struct AnalyzeOptions {
+    /// Kinds of targets analyzed, libraries and binaries if empty
+    pub target_kinds: Vec<TargetKind>,
+    /// Analyze with all features of the packages enabled
+    pub all_features: bool,
+    /// Analyze again even if the sources did not change since the stored
+    /// results
+    pub force: bool,
+    /// The `ferrous-owl` binary, which cargo runs as the compiler of the
+    /// package.
+    ///
+    /// If `None`, the current executable is run, which must then call
+    /// [`crate::run_as_rustc_wrapper`] when started by cargo.
+    pub compiler: Option<PathBuf>,
+}
+
+/// Reasons an analysis has no results
+#[derive(Debug)]
+pub enum AnalysisError {
+    /// The path cannot be analyzed, like when it is neither in a cargo
+    /// package, nor a workspace with a `rust-project.json`, nor a Rust file,
+    /// or when cargo or the toolchain is unusable
+    InvalidTarget {
+        path: PathBuf,
+        source: AnalyzerError,
+    },
+    /// The compiler rejected the code, with the location and message of each
+    /// error
+    Compile(Vec<String>),
+    /// The single file is too large to be analyzed, see
+    /// [`MAX_FILE_BYTES_ENV`]
+    Skipped { file: PathBuf, bytes: u64 },
+    /// Single files and workspaces without cargo are analyzed in this
+    /// process, which needs the `compiler` feature
+    SingleFile(PathBuf),
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTarget { path, source } => {
+                write!(f, "invalid analysis target {}: {source}", path.display())
+            }
+            Self::Compile(errors) => {
+                write!(f, "{} compiler error(s):", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  {error}")?;
+                }
+                Ok(())
+            }
+            Self::Skipped { file, bytes } => write!(
+                f,
+                "{} is too large to be analyzed ({bytes} bytes)",
+                file.display()
+            ),
+            Self::SingleFile(path) => write!(
+                f,
+                "{} is not in a package, analyzing single files needs the `compiler` feature",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl error::Error for AnalysisError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::InvalidTarget { source, .. } => Some(source),
+            Self::Compile(_) | Self::Skipped { .. } | Self::SingleFile(_) => None,
+        }
+    }
+}
+
+/// Analysis results of the package containing `path`, of the workspace
+/// described by a `rust-project.json` in the directory `path`, or of the
+/// single Rust file `path`, once the analysis finished.
+///
+/// Packages are compiled by cargo in separate processes, see
+/// [`AnalyzeOptions::compiler`]. The crates of a `rust-project.json` and a
+/// single file are compiled in this process, if the crate is built with the
+/// `compiler` feature.
+pub async fn analyze_package(
+    path: &Path,
+    opts: AnalyzeOptions,
+) -> Result<Workspace, AnalysisError> {
+    let analyzer = match opts.compiler {
+        Some(compiler) => Analyzer::with_compiler(path, compiler).await,
+        None => Analyzer::new(path).await,
+    }
+    .map_err(|source| AnalysisError::InvalidTarget {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if !cfg!(feature = "compiler") && !analyzer.is_package() {
+        return Err(AnalysisError::SingleFile(path.to_path_buf()));
+    }
+    let mut events = analyzer
+        .analyze(&opts.target_kinds, opts.all_features, opts.force, None)
+        .await;
+
+    let mut workspace = Workspace::new(HashMap::new());
+    let mut errors = Vec::new();
+    while let Some(event) = events.next_event().await {
+        match event {
+            AnalyzerEvent::Analyzed(results) => workspace.merge(results),
+            AnalyzerEvent::CompileError {
+                file,
+                message,
+                span,
+            } => errors.push(format!(
+                "{}:{}:{}: {message}",
+                file.display(),
+                span.line_start,
+                span.column_start
+            )),
+            AnalyzerEvent::Skipped { file, bytes } => {
+                return Err(AnalysisError::Skipped { file, bytes });
+            }
+            AnalyzerEvent::CrateFailed { package, message } => {
+                log::warn!("cannot analyze {package}: {message}");
+            }
+            AnalyzerEvent::CrateChecked { .. } | AnalyzerEvent::WaitingForLock => {}
+        }
+    }
+    if errors.is_empty() {
+        Ok(workspace)
+    } else {
+        Err(AnalysisError::Compile(errors))
+    }
+}
+
+/// Decorations of the variable at the character `offset` of `file`, as shown
+/// by the language server with its default settings, or none if no variable
+/// is there or `file` cannot be read.
+///
+/// Files are matched by the path the compiler was given, which is absolute
+/// for packages. The variable is selected in the text of `file` on disk.
+#[must_use]
+pub fn decorations_for(workspace: &Workspace, file: &Path, offset: CharOffset) -> Vec<Deco> {
+    let file_key = path_norm::normalize_path(file);
+    let Ok(text) = fs::read_to_string(file) else {
+        return Vec::new();
+    };
+    // the results of all crates, merged as by the language server
+    let Some(analyzed) = workspace
+        .crates
+        .values()
+        .filter_map(|krate| krate.0.get(&file_key))
+        .cloned()
+        .reduce(|mut merged, file| {
+            merged.merge(file);
+            merged
+        })
+    else {
+        return Vec::new();
+    };
+    lsp_selection::decorations_at(&analyzed, &text, offset)
+}
@@ -0,0 +1,111 @@
+//! Regions of source text compiled only for tests, found by a cheap scan of
+//! the text rather than by the compiler.
+
+use crate::models::TEST_CONTEXT;
+
+/// Attributes of items and statements compiled only for tests
+const TEST_ATTRIBUTES: [&str; 2] = ["#[cfg(test)]", "#[test]"];
+
+/// [`crate::models::Function::context`] of the functions to show at `line`
+/// of `text`: the test harness inside items and statements marked by one of
+/// [`TEST_ATTRIBUTES`], else the library or binary.
+#[must_use]
+pub fn context_at(text: &str, line: u32) -> &'static str {
+    let line = line as usize;
+    if test_regions(text)
+        .into_iter()
+        .any(|(start, end)| (start..=end).contains(&line))
+    {
+        TEST_CONTEXT
+    } else {
+        ""
+    }
+}
+
+/// Lines, inclusive, from each test attribute of `text` to the end of the
+/// item or statement it marks: its closing brace at the same depth, or its
+/// `;` if that comes first.
+///
+/// Braces in strings and comments are not told apart, which at worst widens
+/// or narrows a region.
+fn test_regions(text: &str) -> Vec<(usize, usize)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut regions = Vec::new();
+    for (start, line) in lines.iter().enumerate() {
+        if line.trim_start().starts_with("//") {
+            continue;
+        }
+        let Some(after) = TEST_ATTRIBUTES
+            .iter()
+            .filter_map(|attribute| line.find(attribute).map(|at| at + attribute.len()))
+            .min()
+        else {
+            continue;
+        };
+        let mut depth = 0_usize;
+        let mut end = lines.len().saturating_sub(1);
+        'scan: for (index, rest) in lines.iter().enumerate().skip(start) {
+            let rest = if index == start { &rest[after..] } else { rest };
+            for c in rest.chars() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth <= 1 => {
+                        end = index;
+                        break 'scan;
+                    }
+                    '}' => depth -= 1,
+                    ';' if depth == 0 => {
+                        end = index;
+                        break 'scan;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        regions.push((start, end));
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "pub fn lib() {
+    let s = String::new();
+    #[cfg(test)]
+    let t = s.clone();
+    drop(s);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test() {
+        let u = 1;
+    }
+}
+";
+
+    #[test]
+    fn test_regions_end_at_their_item_or_statement() {
+        assert_eq!(test_regions(CODE), vec![(2, 3), (7, 13), (9, 12)]);
+    }
+
+    #[test]
+    fn test_context_at() {
+        let contexts: Vec<_> = (0..15).map(|line| context_at(CODE, line)).collect();
+        let test = TEST_CONTEXT;
+        assert_eq!(
+            contexts,
+            vec![
+                "", "", test, test, "", "", "", test, test, test, test, test, test, test, ""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commented_attributes_are_ignored() {
+        assert_eq!(context_at("// #[cfg(test)]\nfn f() {}", 1), "");
+    }
+}
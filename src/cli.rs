@@ -1,13 +1,57 @@
-use std::{env, path::PathBuf, process::exit};
+use std::{
+    env, error,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    process::{Stdio, exit},
+    time::Duration,
+};
 
 use clap::{ArgAction, Args, Parser, Subcommand, ValueHint};
-use tokio::{fs::remove_dir_all, io};
+use tokio::{
+    fs::{self, remove_dir_all},
+    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    process::Command,
+};
 use tower_lsp::{LspService, Server};
 
-use crate::lsp_server::Backend;
+use crate::{
+    deco_kind::DecoKind,
+    graph,
+    lsp_server::Backend,
+    lsp_stats::StatsResponse,
+    lsp_workspace::{AnalyzerError, TargetKind},
+    mir_algorithm::{AlgorithmChoice, POLONIUS_ALGO_ENV},
+    models::Workspace,
+    report::{self, OwnershipReport, ReportFormat},
+    toolchain,
+    watch::{self, WatchOptions},
+};
+
+/// Environment variables, listed at the end of `--help`.
+const ENV_HELP: &str = "\
+Environment variables:
+  FERROUS_OWL_FN_TIMEOUT_SECS  Seconds the analysis of a single function may take
+                               before it is shown without lifetimes and borrows
+                               [default: 60]
+  FERROUS_OWL_POLONIUS_ALGO    Polonius algorithm of the borrow check, one of naive,
+                               datafrog-opt, location-insensitive, hybrid
+                               [default: datafrog-opt]
+  FERROUS_OWL_LOG_FILE         Path of a file that receives the log as JSON lines,
+                               see --log-file
+  FERROUS_OWL_MAX_FILE_BYTES   Size in bytes above which files outside of cargo
+                               packages are not analyzed [default: 2097152]
+  FERROUS_OWL_OFFLINE          Set to 1 to never access the network or install
+                               toolchain components, like --offline
+  FERROUS_OWL_HEALTH_INTERVAL_SECS
+                               Seconds between checks whether the client process
+                               is alive [default: 30]
+  FERROUS_OWL_MAX_LOCK_WAIT_SECS
+                               Seconds to wait for another cargo process to release
+                               the build directory before analysis [default: 60]";
 
 #[derive(Debug, Parser)]
-#[command(author)]
+#[command(author, after_help = ENV_HELP)]
 pub struct Cli {
     /// Print version.
     #[arg(short('V'), long)]
@@ -17,6 +61,38 @@ pub struct Cli {
     #[arg(short, long, action(ArgAction::Count))]
     pub quiet: u8,
 
+    /// Also write the log as JSON lines to this file, with a heartbeat every
+    /// 10 seconds. The file is moved to `<path>.1` when it exceeds 20 MB.
+    #[arg(
+        long,
+        global = true,
+        value_name("path"),
+        value_hint(ValueHint::FilePath)
+    )]
+    pub log_file: Option<PathBuf>,
+
+    /// Serve the language server over TCP at this address instead of stdio,
+    /// to one client at a time. The bound address is printed, so port 0
+    /// picks a free port.
+    #[arg(long, value_name("host:port"))]
+    pub listen: Option<String>,
+
+    /// Exit once the first client of `--listen` disconnects, instead of
+    /// serving the next one.
+    #[arg(long, requires("listen"))]
+    pub single_client: bool,
+
+    /// Never access the network or install toolchain components; fail if
+    /// they are missing instead.
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Serve the language server over this named pipe instead of stdio, to a
+    /// single client.
+    #[cfg(windows)]
+    #[arg(long, value_name("name"), conflicts_with("listen"))]
+    pub pipe: Option<String>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -28,6 +104,47 @@ pub enum Commands {
 
     /// Remove artifacts from the target directory.
     Clean,
+
+    /// Write the borrows, moves and clones between the locals of a function
+    /// as a Graphviz graph.
+    Graph(Graph),
+
+    /// Inspect the toolchain used for analysis.
+    #[command(subcommand)]
+    Toolchain(ToolchainCommands),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ToolchainCommands {
+    /// Check that the toolchain is installed and usable.
+    Status(Status),
+}
+
+#[derive(Args, Debug)]
+pub struct Status {
+    /// Print the result as JSON.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct Graph {
+    /// Name of the function, or the line its signature starts at.
+    #[arg(long = "fn", value_name("name|line"))]
+    pub function: String,
+
+    /// The path of the file of the function.
+    #[arg(long, value_name("path"), value_hint(ValueHint::FilePath))]
+    pub path: PathBuf,
+
+    /// The path of the DOT file to write.
+    #[arg(long, value_name("path"), value_hint(ValueHint::FilePath))]
+    pub out: PathBuf,
+
+    /// Also render the graph as SVG next to the DOT file, with the `dot`
+    /// command of Graphviz.
+    #[arg(long)]
+    pub render: bool,
 }
 
 #[derive(Args, Debug)]
@@ -46,6 +163,10 @@ pub struct Check {
     )]
     pub all_targets: bool,
 
+    /// Kind of targets to check, repeatable [default: lib and bin].
+    #[arg(long = "target-kind", value_enum, value_name("kind"))]
+    pub target_kinds: Vec<TargetKind>,
+
     /// Whether to check for all features
     /// (default: false).
     #[arg(
@@ -55,37 +176,315 @@ pub struct Check {
         help = "Run the check for all features instead of the current active ones only"
     )]
     pub all_features: bool,
+
+    /// Analyze again even if the sources did not change since the last
+    /// check.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Polonius algorithm of the borrow check: naive, datafrog-opt,
+    /// location-insensitive or hybrid.
+    #[arg(long, value_name("algorithm"))]
+    pub polonius_algo: Option<AlgorithmChoice>,
+
+    /// Print the slowest functions and the analysis time per crate to stderr.
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Analyze again whenever the sources change, printing the number of
+    /// moves, shared-mut and outlive decorations per file.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Milliseconds between two looks at the sources in watch mode.
+    #[arg(long, value_name("ms"), default_value_t = 1000, requires("watch"))]
+    pub poll_interval: u64,
+
+    /// Write a report of the lifetimes, borrows, moves, shared-mut conflicts
+    /// and outlive requirements of all variables to this file.
+    #[arg(
+        long,
+        value_name("path"),
+        value_hint(ValueHint::FilePath),
+        conflicts_with("watch")
+    )]
+    pub report: Option<PathBuf>,
+
+    /// Format of the report.
+    #[arg(long, value_enum, value_name("format"), default_value("md"))]
+    pub report_format: ReportFormat,
+
+    /// Source lines shown at most per variable in the report.
+    #[arg(long, value_name("lines"), default_value_t = 40)]
+    pub report_max_lines: usize,
+
+    /// Print the decorations of all variables of the files matching this
+    /// path or glob, relative to the workspace root, as lines of JSON.
+    /// Repeatable.
+    #[arg(long, value_name("path"), conflicts_with("watch"))]
+    pub emit_decorations_for: Vec<String>,
+
+    /// Exit with code 4 if any printed decoration is of one of these
+    /// comma-separated kinds, like `shared-mut,outlive`.
+    #[arg(
+        long,
+        value_name("kinds"),
+        value_delimiter(','),
+        requires("emit_decorations_for")
+    )]
+    pub fail_on: Vec<DecoKind>,
 }
 
 impl Commands {
     /// Execute the command.
     pub async fn execute(self) {
         match self {
-            Self::Check(options) => {
-                let path = options.path.unwrap_or_else(|| env::current_dir().unwrap());
-
-                if Backend::check_with_options(&path, options.all_targets, options.all_features)
-                    .await
-                {
-                    log::info!("Successfully analyzed");
-                    exit(0);
+            Self::Check(options) => check(options).await,
+            Self::Graph(options) => {
+                if let Err(e) = toolchain::get_sysroot() {
+                    log::error!("{e}");
+                    exit(e.exit_code());
                 }
-                log::error!("Analyze failed");
-                exit(1);
+                let Some((workspace, _)) =
+                    Backend::check_with_options(&options.path, Vec::new(), false, false, None)
+                        .await
+                        .unwrap_or_else(|e| exit_invalid_target(&e))
+                else {
+                    log::error!("Analyze failed");
+                    exit(1);
+                };
+                if let Err(e) = write_graph(&workspace, &options).await {
+                    log::error!("cannot write the graph to {}: {e}", options.out.display());
+                    exit(1);
+                }
+                exit(0);
             }
             Self::Clean => {
-                if let Ok(meta) = cargo_metadata::MetadataCommand::new().exec() {
+                let mut metadata = cargo_metadata::MetadataCommand::new();
+                if toolchain::is_offline() {
+                    metadata.other_options(vec!["--offline".to_owned()]);
+                }
+                if let Ok(meta) = metadata.exec() {
                     let target = meta.target_directory.join("owl");
                     remove_dir_all(&target).await.ok();
                 }
             }
+            Self::Toolchain(ToolchainCommands::Status(options)) => {
+                let status = toolchain::toolchain_status();
+                if options.json {
+                    println!("{}", serde_json::to_string(&status).unwrap());
+                } else {
+                    print!("{status}");
+                }
+                exit(i32::from(!status.ok));
+            }
         }
     }
 }
 
+/// Print why the target cannot be analyzed, with the errors causing it, and
+/// exit with code 2.
+fn exit_invalid_target(error: &AnalyzerError) -> ! {
+    eprintln!("error: {error}");
+    let mut source = error::Error::source(error);
+    while let Some(cause) = source {
+        eprintln!("  caused by: {cause}");
+        source = cause.source();
+    }
+    exit(2);
+}
+
+/// Analyze the workspace of `options` and exit, with code 2 if it cannot be
+/// analyzed and code 3 if some of its packages could not be analyzed.
+async fn check(options: Check) -> ! {
+    if let Err(e) = toolchain::get_sysroot() {
+        log::error!("{e}");
+        exit(e.exit_code());
+    }
+    let path = options.path.unwrap_or_else(|| env::current_dir().unwrap());
+
+    let target_kinds = if options.all_targets {
+        TargetKind::ALL.to_vec()
+    } else {
+        options.target_kinds
+    };
+    if options.watch {
+        let watch_options = WatchOptions {
+            target_kinds,
+            all_features: options.all_features,
+            force: options.force,
+            polonius_algo: options.polonius_algo,
+            poll_interval: Duration::from_millis(options.poll_interval),
+        };
+        if let Err(e) = watch::watch(&path, watch_options).await {
+            exit_invalid_target(&e);
+        }
+        exit(0);
+    }
+    if let Some((workspace, failed_crates)) = Backend::check_with_options(
+        &path,
+        target_kinds,
+        options.all_features,
+        options.force,
+        options.polonius_algo,
+    )
+    .await
+    .unwrap_or_else(|e| exit_invalid_target(&e))
+    {
+        if options.stats
+            && let Some(stats) = &workspace.meta
+        {
+            eprint!("{}", StatsResponse::new(stats, None));
+        }
+        if let Some(report) = &options.report
+            && let Err(e) = write_report(
+                &workspace,
+                &path,
+                report,
+                options.report_format,
+                options.report_max_lines,
+            )
+            .await
+        {
+            log::error!("cannot write the report to {}: {e}", report.display());
+            exit(1);
+        }
+        let failing = !options.emit_decorations_for.is_empty()
+            && emit_decorations(
+                &workspace,
+                &path,
+                &options.emit_decorations_for,
+                &options.fail_on,
+            );
+        for (package, message) in &failed_crates {
+            eprintln!("cannot analyze {package}: {message}");
+        }
+        if failing {
+            log::warn!("Decorations of failing kinds found");
+            exit(4);
+        }
+        if !failed_crates.is_empty() {
+            log::warn!("Partially analyzed");
+            exit(3);
+        }
+        log::info!("Successfully analyzed");
+        exit(0);
+    }
+    log::error!("Analyze failed");
+    exit(1);
+}
+
+/// Directory of the workspace analyzed at `path`.
+fn workspace_root(path: &Path) -> &Path {
+    if path.is_file() {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    }
+}
+
+/// Write the ownership report of `workspace`, analyzed at `path`, to `report`
+/// in `format` with at most `max_lines` source lines per variable.
+async fn write_report(
+    workspace: &Workspace,
+    path: &Path,
+    report: &Path,
+    format: ReportFormat,
+    max_lines: usize,
+) -> io::Result<()> {
+    let rendered = OwnershipReport::new(workspace, |file| read_to_string(file).ok())
+        .relative_to(workspace_root(path))
+        .render(format, max_lines);
+    fs::write(report, rendered).await
+}
+
+/// Print the decorations of the files of `workspace`, analyzed at `path`,
+/// that match `patterns` as lines of JSON, returning whether any is of a kind
+/// in `fail_on`.
+fn emit_decorations(
+    workspace: &Workspace,
+    path: &Path,
+    patterns: &[String],
+    fail_on: &[DecoKind],
+) -> bool {
+    let root = workspace_root(path);
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_owned());
+    let emitted =
+        report::emitted_decorations(workspace, &root, patterns, |file| read_to_string(file).ok());
+    for decoration in &emitted {
+        println!("{}", serde_json::to_string(decoration).unwrap());
+    }
+    emitted
+        .iter()
+        .any(|decoration| fail_on.contains(&decoration.kind))
+}
+
+/// Write the graph of the function of `workspace` selected by `options` to
+/// its output, and render it as SVG if asked to.
+async fn write_graph(workspace: &Workspace, options: &Graph) -> Result<(), String> {
+    let wanted = options
+        .path
+        .canonicalize()
+        .map_err(|e| format!("cannot find {}: {e}", options.path.display()))?;
+    let (name, file) = workspace
+        .crates
+        .values()
+        .flat_map(|krate| &krate.0)
+        .find(|(name, _)| {
+            Path::new(name)
+                .canonicalize()
+                .map_or_else(|_| wanted.ends_with(name), |path| path == wanted)
+        })
+        .ok_or_else(|| format!("{} was not analyzed", options.path.display()))?;
+    let source =
+        read_to_string(&wanted).map_err(|e| format!("cannot read {}: {e}", wanted.display()))?;
+    let parts = graph::find_function(file, &source, &options.function);
+    if parts.is_empty() {
+        return Err(format!("no function `{}` in {name}", options.function));
+    }
+    let dot = graph::Graph::new(&options.function, &parts, &file.types, &source).to_dot();
+    fs::write(&options.out, &dot)
+        .await
+        .map_err(|e| e.to_string())?;
+    if options.render {
+        render_svg(&dot, &options.out.with_extension("svg")).await?;
+    }
+    Ok(())
+}
+
+/// Render `dot` as SVG to `svg` with the `dot` command of Graphviz.
+async fn render_svg(dot: &str, svg: &Path) -> Result<(), String> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .arg("-o")
+        .arg(svg)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run `dot` of Graphviz: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(dot.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`dot` failed with {status}"))
+    }
+}
+
 impl Cli {
     /// Run the CLI application.
     pub async fn run(self) {
+        if self.offline {
+            toolchain::set_offline();
+        }
+        if let Err(e) = AlgorithmChoice::from_env() {
+            log::error!("invalid {POLONIUS_ALGO_ENV}: {e}");
+            exit(1);
+        }
         if let Some(command) = self.command {
             command.execute().await;
         } else if self.version {
@@ -93,20 +492,109 @@ impl Cli {
                 print!("{} ", env!("CARGO_PKG_NAME"));
             }
             println!("v{}", clap::crate_version!());
+        } else if let Some(address) = self.listen {
+            if let Err(e) = listen_lsp_server(&address, self.single_client).await {
+                log::error!("failed to serve the language server at {address}: {e}");
+                exit(1);
+            }
         } else {
+            #[cfg(windows)]
+            if let Some(name) = self.pipe {
+                if let Err(e) = pipe_lsp_server(&name).await {
+                    log::error!("failed to serve the language server at pipe {name}: {e}");
+                    exit(1);
+                }
+                return;
+            }
             start_lsp_server().await;
         }
     }
 }
 
-async fn start_lsp_server() {
-    let stdin = io::stdin();
-    let stdout = io::stdout();
-
+async fn serve_lsp<I, O>(input: I, output: O)
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+{
     let (service, socket) = LspService::build(Backend::new)
         .custom_method("ferrous-owl/cursor", Backend::cursor)
+        .custom_method("ferrous-owl/lineHints", Backend::line_hints)
         .custom_method("ferrous-owl/analyze", Backend::analyze)
+        .custom_method("ferrous-owl/stats", Backend::stats)
+        .custom_method("ferrous-owl/status", Backend::status)
+        .custom_method(
+            "window/workDoneProgress/cancel",
+            Backend::work_done_progress_cancel,
+        )
         .finish();
+    let subprocesses = service.inner().subprocesses();
+
+    Server::new(input, output, socket).serve(service).await;
+    log::info!("client disconnected, stopping running analyses");
+    subprocesses.shutdown().await;
+}
+
+async fn start_lsp_server() {
+    serve_lsp(io::stdin(), io::stdout()).await;
+}
+
+/// Serve the clients connecting to `address` over TCP one after another, or
+/// only the first one if `single_client` is set. Clients connecting while
+/// another is served are refused.
+///
+/// The bound address is printed, so port 0 picks a free port.
+async fn listen_lsp_server(address: &str, single_client: bool) -> io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    let local_addr = listener.local_addr()?;
+    log::info!("listening at {local_addr}");
+    println!("listening at {local_addr}");
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        log::info!("client connected from {peer}");
+        let (input, output) = io::split(stream);
+        let serve = serve_lsp(input, output);
+        tokio::pin!(serve);
+        loop {
+            tokio::select! {
+                () = &mut serve => break,
+                accepted = listener.accept() => match accepted {
+                    // dropping the stream closes the connection
+                    Ok((_, other)) => {
+                        log::warn!("refused connection from {other}, already serving {peer}");
+                    }
+                    Err(e) => log::warn!("failed to accept a connection: {e}"),
+                },
+            }
+        }
+        log::info!("client from {peer} disconnected");
+        if single_client {
+            return Ok(());
+        }
+    }
+}
+
+/// Serve a single client connecting to the named pipe `name`, until it
+/// disconnects.
+///
+/// Only one instance of the pipe is created, so further clients get
+/// `ERROR_PIPE_BUSY` while the first is connected.
+#[cfg(windows)]
+async fn pipe_lsp_server(name: &str) -> io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
 
-    Server::new(stdin, stdout, socket).serve(service).await;
+    let path = if name.starts_with(r"\\.\pipe\") {
+        name.to_owned()
+    } else {
+        format!(r"\\.\pipe\{name}")
+    };
+    let pipe = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)?;
+    log::info!("waiting for a client at {path}");
+    pipe.connect().await?;
+    log::info!("client connected at {path}");
+    let (input, output) = io::split(pipe);
+    serve_lsp(input, output).await;
+    log::info!("client at {path} disconnected");
+    Ok(())
 }
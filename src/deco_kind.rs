@@ -0,0 +1,308 @@
+//! Kinds of decorations, shared by the server and the test framework.
+//!
+//! A kind has two names: the kebab-case one of diagnostic codes and test
+//! case files, like `imm-borrow`, and the snake-case one of the `type` field
+//! of decorations, like `imm_borrow`. Both are parsed, and both are derived
+//! from this single enum, so that they cannot drift apart.
+//!
+//! Codes of borrows taken implicitly by auto-ref of a method receiver end in
+//! [`AUTO_BORROW_SUFFIX`], like `ferrous-owl:imm-borrow:auto-borrow`.
+
+use std::{error, fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
+
+/// Suffix of the diagnostic codes of implicit borrows of method receivers
+pub const AUTO_BORROW_SUFFIX: &str = ":auto-borrow";
+
+/// Kind of a decoration, one per variant of the server's `Deco`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecoKind {
+    Lifetime,
+    ImmBorrow,
+    MutBorrow,
+    Move,
+    Call,
+    Drop,
+    DeadWrite,
+    SharedMut,
+    Outlive,
+    Scope,
+    Allocation,
+    Summary,
+    Binding,
+    HeldAcrossAwait,
+    SharedClone,
+    Conflict,
+    LeakOrForget,
+}
+
+impl DecoKind {
+    pub const ALL: [Self; 17] = [
+        Self::Lifetime,
+        Self::ImmBorrow,
+        Self::MutBorrow,
+        Self::Move,
+        Self::Call,
+        Self::Drop,
+        Self::DeadWrite,
+        Self::SharedMut,
+        Self::Outlive,
+        Self::Scope,
+        Self::Allocation,
+        Self::Summary,
+        Self::Binding,
+        Self::HeldAcrossAwait,
+        Self::SharedClone,
+        Self::Conflict,
+        Self::LeakOrForget,
+    ];
+
+    /// Name of the kind, as in the suffix of diagnostic codes
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Lifetime => "lifetime",
+            Self::ImmBorrow => "imm-borrow",
+            Self::MutBorrow => "mut-borrow",
+            Self::Move => "move",
+            Self::Call => "call",
+            Self::Drop => "drop",
+            Self::DeadWrite => "dead-write",
+            Self::SharedMut => "shared-mut",
+            Self::Outlive => "outlive",
+            Self::Scope => "scope",
+            Self::Allocation => "allocation",
+            Self::Summary => "summary",
+            Self::Binding => "binding",
+            Self::HeldAcrossAwait => "held-across-await",
+            Self::SharedClone => "shared-clone",
+            Self::Conflict => "conflict",
+            Self::LeakOrForget => "leak-or-forget",
+        }
+    }
+
+    /// Name of the kind, as in the `type` field of decorations
+    #[must_use]
+    pub const fn type_name(self) -> &'static str {
+        match self {
+            Self::Lifetime => "lifetime",
+            Self::ImmBorrow => "imm_borrow",
+            Self::MutBorrow => "mut_borrow",
+            Self::Move => "move",
+            Self::Call => "call",
+            Self::Drop => "drop",
+            Self::DeadWrite => "dead_write",
+            Self::SharedMut => "shared_mut",
+            Self::Outlive => "outlive",
+            Self::Scope => "scope",
+            Self::Allocation => "allocation",
+            Self::Summary => "summary",
+            Self::Binding => "binding",
+            Self::HeldAcrossAwait => "held_across_await",
+            Self::SharedClone => "shared_clone",
+            Self::Conflict => "conflict",
+            Self::LeakOrForget => "leak_or_forget",
+        }
+    }
+
+    /// Diagnostic code of decorations of this kind published by this
+    /// package, like `ferrous-owl:imm-borrow`
+    #[must_use]
+    pub fn diagnostic_code(self) -> String {
+        DiagnosticCode {
+            source: env!("CARGO_PKG_NAME"),
+            kind: self,
+            auto_borrow: false,
+        }
+        .to_string()
+    }
+
+    /// Diagnostic code of implicit borrows of this kind published by this
+    /// package, like `ferrous-owl:imm-borrow:auto-borrow`
+    #[must_use]
+    pub fn auto_borrow_code(self) -> String {
+        DiagnosticCode {
+            source: env!("CARGO_PKG_NAME"),
+            kind: self,
+            auto_borrow: true,
+        }
+        .to_string()
+    }
+
+    /// Whether the kind is a borrow, which may be taken implicitly
+    #[must_use]
+    pub const fn is_borrow(self) -> bool {
+        matches!(self, Self::ImmBorrow | Self::MutBorrow)
+    }
+}
+
+impl fmt::Display for DecoKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DecoKind {
+    type Err = ParseDecoKindError;
+
+    /// Kind of either of its names.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s || kind.type_name() == s)
+            .ok_or_else(|| ParseDecoKindError(s.to_owned()))
+    }
+}
+
+impl Serialize for DecoKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for DecoKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecoKindError(String);
+
+impl fmt::Display for ParseDecoKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown decoration kind `{}`", self.0)
+    }
+}
+
+impl error::Error for ParseDecoKindError {}
+
+/// Code of a decoration diagnostic, like `ferrous-owl:mut-borrow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticCode<'a> {
+    /// Name of the package that published the diagnostic
+    pub source: &'a str,
+    pub kind: DecoKind,
+    /// Whether the borrow is taken implicitly by auto-ref of a method
+    /// receiver, see [`AUTO_BORROW_SUFFIX`]
+    pub auto_borrow: bool,
+}
+
+impl<'a> DiagnosticCode<'a> {
+    /// Split `code`, without an [`AUTO_BORROW_SUFFIX`] of a borrow, at its
+    /// last `:` into a source and a kind, or `None` if it has no source or
+    /// the kind is not exactly the name of one.
+    #[must_use]
+    pub fn parse(code: &'a str) -> Option<Self> {
+        let (code, auto_borrow) = code
+            .strip_suffix(AUTO_BORROW_SUFFIX)
+            .map_or((code, false), |code| (code, true));
+        let (source, name) = code.rsplit_once(':')?;
+        if source.is_empty() {
+            return None;
+        }
+        DecoKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name && (kind.is_borrow() || !auto_borrow))
+            .map(|kind| Self {
+                source,
+                kind,
+                auto_borrow,
+            })
+    }
+}
+
+impl fmt::Display for DiagnosticCode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source, self.kind)?;
+        if self.auto_borrow {
+            f.write_str(AUTO_BORROW_SUFFIX)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_every_kind_round_trips_through_its_names() {
+        for kind in DecoKind::ALL {
+            assert_eq!(kind.name().parse(), Ok(kind));
+            assert_eq!(kind.type_name().parse(), Ok(kind));
+            assert_eq!(kind.type_name(), kind.name().replace('-', "_"));
+
+            let code = kind.diagnostic_code();
+            assert_eq!(
+                DiagnosticCode::parse(&code),
+                Some(DiagnosticCode {
+                    source: env!("CARGO_PKG_NAME"),
+                    kind,
+                    auto_borrow: false,
+                }),
+                "{code}"
+            );
+
+            let json = serde_json::to_string(&kind).unwrap();
+            assert_eq!(json, format!("\"{}\"", kind.name()));
+            assert_eq!(serde_json::from_str::<DecoKind>(&json).unwrap(), kind);
+            let snake = format!("\"{}\"", kind.type_name());
+            assert_eq!(serde_json::from_str::<DecoKind>(&snake).unwrap(), kind);
+        }
+        let names: HashSet<_> = DecoKind::ALL.into_iter().map(DecoKind::name).collect();
+        assert_eq!(names.len(), DecoKind::ALL.len(), "names are unique");
+    }
+
+    #[test]
+    fn test_diagnostic_codes_need_a_source_and_an_exact_kind() {
+        let code = DiagnosticCode::parse("rustowl:shared-mut").unwrap();
+        assert_eq!(code.source, "rustowl");
+        assert_eq!(code.kind, DecoKind::SharedMut);
+        assert_eq!(code.to_string(), "rustowl:shared-mut");
+
+        assert_eq!(DiagnosticCode::parse("shared-mut"), None);
+        assert_eq!(DiagnosticCode::parse(":move"), None);
+        assert_eq!(DiagnosticCode::parse("ferrous-owl:mut"), None);
+        assert_eq!(
+            DiagnosticCode::parse("ferrous-owl:shared_mut"),
+            None,
+            "codes use the kebab-case names"
+        );
+    }
+
+    #[test]
+    fn test_auto_borrow_codes_are_of_borrows() {
+        let code = DecoKind::MutBorrow.auto_borrow_code();
+        assert_eq!(
+            code,
+            format!("{}:mut-borrow:auto-borrow", env!("CARGO_PKG_NAME"))
+        );
+        let parsed = DiagnosticCode::parse(&code).unwrap();
+        assert_eq!(parsed.kind, DecoKind::MutBorrow);
+        assert!(parsed.auto_borrow);
+        assert_eq!(parsed.to_string(), code);
+
+        assert!(
+            !DiagnosticCode::parse("rustowl:imm-borrow")
+                .unwrap()
+                .auto_borrow
+        );
+        assert_eq!(DiagnosticCode::parse("rustowl:move:auto-borrow"), None);
+        assert_eq!(DiagnosticCode::parse("auto-borrow"), None);
+    }
+
+    #[test]
+    fn test_unknown_kind() {
+        assert_eq!(
+            "borrow".parse::<DecoKind>().unwrap_err().to_string(),
+            "unknown decoration kind `borrow`"
+        );
+        assert!(serde_json::from_str::<DecoKind>("\"borrow\"").is_err());
+    }
+}
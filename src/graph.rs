@@ -0,0 +1,467 @@
+//! Graphs of the borrows, moves and clones between the locals of a function,
+//! written by `graph` in the DOT language of Graphviz.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    models::{CharOffset, File, FnLocal, Function, Item, MirDecl, MirRval, MirStatement, Range},
+    text_conversion::SourceIndex,
+};
+
+/// Relation between two locals of a function
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeKind {
+    /// A reference to a shared borrow of a local
+    SharedBorrow,
+    /// A reference to a mutable borrow of a local
+    MutableBorrow,
+    /// A local moved into another
+    Move,
+    /// A reference counted pointer cloned into another
+    Clone,
+    /// A local captured by an upvar of a closure
+    Capture,
+}
+
+impl EdgeKind {
+    const fn label(self) -> &'static str {
+        match self {
+            Self::SharedBorrow => "borrow",
+            Self::MutableBorrow => "mut borrow",
+            Self::Move => "move",
+            Self::Clone => "clone",
+            Self::Capture => "capture",
+        }
+    }
+
+    const fn style(self) -> &'static str {
+        match self {
+            Self::SharedBorrow => "dashed",
+            Self::MutableBorrow => "solid",
+            Self::Move => "bold",
+            Self::Clone | Self::Capture => "dotted",
+        }
+    }
+}
+
+/// One-based first and last line
+type Lines = (u32, u32);
+
+/// Edge to the node `to`, at `lines` of the source if known
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Edge {
+    pub to: usize,
+    pub kind: EdgeKind,
+    pub lines: Option<Lines>,
+}
+
+/// Local of a function, labelled with its name or id and its type
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Node {
+    pub local: FnLocal,
+    pub name: Option<String>,
+    pub ty: String,
+    /// Lines the local is live at
+    pub lines: Option<Lines>,
+}
+
+impl Node {
+    fn label(&self) -> String {
+        let name = self
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("_{}", self.local.id));
+        let mut label = format!("{name}: {}", self.ty);
+        if let Some(lines) = self.lines {
+            label.push('\n');
+            label.push_str(&lines_text(lines));
+        }
+        label
+    }
+}
+
+/// Borrows, moves, clones and captures between the locals of a function,
+/// as lists of the edges leaving each node
+///
+/// User variables are nodes, other locals only if an edge starts or ends at
+/// them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Graph {
+    pub name: String,
+    pub nodes: Vec<Node>,
+    /// Edges leaving the node of the same index
+    pub adjacency: Vec<Vec<Edge>>,
+}
+
+impl Graph {
+    /// Graph named `name` of the function of `parts`, the parts of a split
+    /// function or the function itself, in `source`, with the `types` of
+    /// its file.
+    #[must_use]
+    pub fn new(name: &str, parts: &[&Function], types: &[String], source: &str) -> Self {
+        let index = SourceIndex::new(source);
+        let lines = |ranges: &[Range]| {
+            let from = ranges.iter().map(|range| range.from()).min()?;
+            let until = ranges.iter().map(|range| range.until()).max()?;
+            Some(line_span(&index, from, until))
+        };
+        let decls: HashMap<FnLocal, Node> = parts
+            .iter()
+            .flat_map(|part| &part.decls)
+            .map(|decl| match decl {
+                MirDecl::User {
+                    local,
+                    name,
+                    ty,
+                    lives,
+                    ..
+                } => Node {
+                    local: *local,
+                    name: Some(name.clone()),
+                    ty: ty.name(types).to_owned(),
+                    lines: lines(lives),
+                },
+                MirDecl::Other {
+                    local, ty, lives, ..
+                } => Node {
+                    local: *local,
+                    name: None,
+                    ty: ty.name(types).to_owned(),
+                    lines: lines(lives),
+                },
+            })
+            .map(|node| (node.local, node))
+            .collect();
+
+        let mut graph = Self {
+            name: name.to_owned(),
+            nodes: Vec::new(),
+            adjacency: Vec::new(),
+        };
+        let mut user_vars: Vec<_> = decls.values().filter(|node| node.name.is_some()).collect();
+        user_vars.sort_by_key(|node| node.local.id);
+        for node in user_vars {
+            graph.node(&decls, node.local);
+        }
+        for part in parts {
+            for statement in part.basic_blocks.iter().flat_map(|bb| &bb.statements) {
+                let MirStatement::Assign {
+                    target_local,
+                    rval: Some(rval),
+                    ..
+                } = statement
+                else {
+                    continue;
+                };
+                let (from, to, kind, range) = match rval {
+                    MirRval::Borrow {
+                        target_local: borrowed,
+                        range,
+                        mutable,
+                        ..
+                    } => {
+                        let kind = if *mutable {
+                            EdgeKind::MutableBorrow
+                        } else {
+                            EdgeKind::SharedBorrow
+                        };
+                        (*target_local, *borrowed, kind, range)
+                    }
+                    MirRval::Move {
+                        target_local: moved,
+                        range,
+                        ..
+                    } => (*moved, *target_local, EdgeKind::Move, range),
+                };
+                let lines = line_span(&index, range.from(), range.until());
+                graph.edge(&decls, from, to, kind, Some(lines));
+            }
+        }
+        for part in parts {
+            for (from, to) in &part.clone_edges {
+                graph.edge(&decls, *from, *to, EdgeKind::Clone, None);
+            }
+            for (upvar, captured) in &part.captures {
+                graph.edge(&decls, *upvar, *captured, EdgeKind::Capture, None);
+            }
+        }
+        graph
+    }
+
+    /// Index of the node of `local`, added if needed.
+    fn node(&mut self, decls: &HashMap<FnLocal, Node>, local: FnLocal) -> usize {
+        if let Some(index) = self.nodes.iter().position(|node| node.local == local) {
+            return index;
+        }
+        self.nodes.push(decls.get(&local).cloned().unwrap_or(Node {
+            local,
+            name: None,
+            ty: String::new(),
+            lines: None,
+        }));
+        self.adjacency.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    fn edge(
+        &mut self,
+        decls: &HashMap<FnLocal, Node>,
+        from: FnLocal,
+        to: FnLocal,
+        kind: EdgeKind,
+        lines: Option<Lines>,
+    ) {
+        let from = self.node(decls, from);
+        let to = self.node(decls, to);
+        let edge = Edge { to, kind, lines };
+        if !self.adjacency[from].contains(&edge) {
+            self.adjacency[from].push(edge);
+        }
+    }
+
+    /// The graph in the DOT language.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "digraph {} {{", quote(&self.name))?;
+        writeln!(f, "    node [shape=box];")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            writeln!(f, "    n{index} [label={}];", quote(&node.label()))?;
+        }
+        for (from, edges) in self.adjacency.iter().enumerate() {
+            for edge in edges {
+                let label = edge.lines.map_or_else(
+                    || edge.kind.label().to_owned(),
+                    |lines| format!("{}, {}", edge.kind.label(), lines_text(lines)),
+                );
+                writeln!(
+                    f,
+                    "    n{from} -> n{} [label={}, style={}];",
+                    edge.to,
+                    quote(&label),
+                    edge.kind.style()
+                )?;
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// One-based lines from `from` to `until`
+fn line_span(index: &SourceIndex, from: CharOffset, until: CharOffset) -> Lines {
+    (
+        index.char_to_line_col(from).line + 1,
+        index.char_to_line_col(until).line + 1,
+    )
+}
+
+fn lines_text((first, last): Lines) -> String {
+    if first == last {
+        format!("line {first}")
+    } else {
+        format!("lines {first}-{last}")
+    }
+}
+
+/// `text` as a quoted DOT string
+fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// Whether the signature `signature` of a function declares `name`, like
+/// `fn name(..)` or `pub fn name<T>(..)`.
+fn declares(signature: &str, name: &str) -> bool {
+    signature.split("fn ").skip(1).any(|rest| {
+        rest.trim_start()
+            .strip_prefix(name)
+            .is_some_and(|after| !after.starts_with(|c: char| c == '_' || c.is_alphanumeric()))
+    })
+}
+
+/// Parts of the function of `file` selected by `selector`, the name of the
+/// function or the one-based line its signature starts at, in `source`.
+#[must_use]
+pub fn find_function<'a>(file: &'a File, source: &str, selector: &str) -> Vec<&'a Function> {
+    let index = SourceIndex::new(source);
+    let starts_at = |span: Range, line: u32| index.char_to_line_col(span.from()).line + 1 == line;
+    let is_named = |span: Range| {
+        let signature: String = source
+            .chars()
+            .skip(u32::from(span.from()) as usize)
+            .take(span.size() as usize)
+            .collect();
+        declares(&signature, selector)
+    };
+    let line = selector.parse::<u32>().ok();
+    let selected = file.items_in("").find(|item| {
+        item.span()
+            .is_some_and(|span| line.map_or_else(|| is_named(span), |line| starts_at(span, line)))
+    });
+    let Some(selected) = selected else {
+        return Vec::new();
+    };
+    file.items_in("")
+        .map(Item::function)
+        .filter(|function| function.fn_key == selected.function().fn_key)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MirBasicBlock;
+
+    const SOURCE: &str =
+        "fn test() {\n    let s = String::new();\n    let r = &s;\n    let t = s;\n}\n";
+
+    fn range(text: &str) -> Range {
+        let from = u32::try_from(SOURCE.find(text).unwrap()).unwrap();
+        let until = from + u32::try_from(text.len()).unwrap();
+        Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+    }
+
+    fn user(id: u32, name: &str, ty: &str, lives: Range) -> MirDecl {
+        MirDecl::User {
+            local: FnLocal::new(id, 1),
+            name: name.to_owned(),
+            span: range(name),
+            scope: None,
+            ty: ty.into(),
+            lives: vec![lives],
+            shared_borrow: Vec::new(),
+            mutable_borrow: Vec::new(),
+            shared_mut: Vec::new(),
+            drop: false,
+            drop_range: Vec::new(),
+            must_live_at: Vec::new(),
+            must_live_from_signature: Vec::new(),
+            dead_writes: Vec::new(),
+        }
+    }
+
+    fn assign(target: u32, rval: MirRval) -> MirStatement {
+        MirStatement::Assign {
+            target_local: FnLocal::new(target, 1),
+            range: range("s;"),
+            rval: Some(rval),
+            through_ref: None,
+            from_macro: false,
+        }
+    }
+
+    fn function() -> Function {
+        let mut function: Function = serde_json::from_value(serde_json::json!({
+            "fn_id": 1,
+            "fn_key": 1,
+            "basic_blocks": [],
+            "decls": [],
+        }))
+        .unwrap();
+        let lives = Range::new(range("s = String").from(), range("t = s").until()).unwrap();
+        function.decls = vec![
+            user(1, "s", "String", lives),
+            user(2, "r", "&String", range("r = &s")),
+            user(3, "t", "String", range("t = s")),
+        ];
+        function.basic_blocks = vec![MirBasicBlock {
+            statements: vec![
+                assign(
+                    2,
+                    MirRval::Borrow {
+                        target_local: FnLocal::new(1, 1),
+                        range: range("&s"),
+                        mutable: false,
+                        outlive: None,
+                        through_deref: false,
+                        from_macro: false,
+                    },
+                ),
+                assign(
+                    3,
+                    MirRval::Move {
+                        target_local: FnLocal::new(1, 1),
+                        range: range("t = s"),
+                        moved_into: None,
+                        from_macro: false,
+                    },
+                ),
+            ],
+            terminator: None,
+            is_cleanup: false,
+        }];
+        function
+    }
+
+    #[test]
+    fn test_dot_of_a_borrow_and_a_move() {
+        let function = function();
+        let graph = Graph::new("test", &[&function], &[], SOURCE);
+        assert_eq!(
+            graph.to_dot(),
+            "\
+digraph \"test\" {
+    node [shape=box];
+    n0 [label=\"s: String\\nlines 2-4\"];
+    n1 [label=\"r: &String\\nline 3\"];
+    n2 [label=\"t: String\\nline 4\"];
+    n0 -> n2 [label=\"move, line 4\", style=bold];
+    n1 -> n0 [label=\"borrow, line 3\", style=dashed];
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_nodes_of_other_locals_only_with_edges() {
+        let mut function = function();
+        function.decls.push(MirDecl::Other {
+            local: FnLocal::new(4, 1),
+            ty: "usize".into(),
+            lives: Vec::new(),
+            shared_borrow: Vec::new(),
+            mutable_borrow: Vec::new(),
+            shared_mut: Vec::new(),
+            drop: false,
+            drop_range: Vec::new(),
+            must_live_at: Vec::new(),
+            must_live_from_signature: Vec::new(),
+            dead_writes: Vec::new(),
+        });
+        let graph = Graph::new("test", &[&function], &[], SOURCE);
+        assert_eq!(graph.nodes.len(), 3);
+
+        function.clone_edges = vec![(FnLocal::new(1, 1), FnLocal::new(4, 1))];
+        let graph = Graph::new("test", &[&function], &[], SOURCE);
+        assert_eq!(graph.nodes[3].label(), "_4: usize");
+        assert_eq!(
+            graph.adjacency[0][1],
+            Edge {
+                to: 3,
+                kind: EdgeKind::Clone,
+                lines: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a \"b\"\n\\"), "\"a \\\"b\\\"\\n\\\\\"");
+    }
+
+    #[test]
+    fn test_declares() {
+        assert!(declares("fn test()", "test"));
+        assert!(declares("pub(crate) async fn test<T>(t: T)", "test"));
+        assert!(!declares("fn test_more()", "test"));
+        assert!(!declares("fn tes()", "test"));
+    }
+}
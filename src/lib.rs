@@ -1,39 +1,103 @@
-#![feature(rustc_private)]
+#![cfg_attr(feature = "compiler", feature(rustc_private))]
 
+#[cfg(feature = "compiler")]
 extern crate indexmap;
+#[cfg(feature = "compiler")]
 extern crate polonius_engine;
+#[cfg(feature = "compiler")]
 extern crate rustc_borrowck;
+#[cfg(feature = "compiler")]
 extern crate rustc_data_structures;
+#[cfg(feature = "compiler")]
 extern crate rustc_driver;
+#[cfg(feature = "compiler")]
 extern crate rustc_errors;
+#[cfg(feature = "compiler")]
 extern crate rustc_hash;
+#[cfg(feature = "compiler")]
 extern crate rustc_hir;
+#[cfg(feature = "compiler")]
 extern crate rustc_index;
+#[cfg(feature = "compiler")]
 extern crate rustc_interface;
+#[cfg(feature = "compiler")]
 extern crate rustc_middle;
+#[cfg(feature = "compiler")]
 extern crate rustc_query_system;
+#[cfg(feature = "compiler")]
 extern crate rustc_session;
+#[cfg(feature = "compiler")]
 extern crate rustc_span;
+#[cfg(feature = "compiler")]
 extern crate rustc_stable_hash;
+#[cfg(feature = "compiler")]
 extern crate rustc_type_ir;
+#[cfg(feature = "compiler")]
 extern crate smallvec;
 
+pub mod api;
+mod cfg_test;
 mod cli;
+mod deco_kind;
+mod graph;
+mod logging;
+mod lsp_budget;
+mod lsp_cargo_lock;
+mod lsp_compare;
+mod lsp_cursor_cache;
 mod lsp_decoration;
+mod lsp_documents;
+mod lsp_fingerprint;
+pub mod lsp_framing;
+mod lsp_health;
+mod lsp_ignore;
+mod lsp_line_hints;
+mod lsp_ownership;
 mod lsp_progress;
+mod lsp_selection;
 mod lsp_server;
+mod lsp_settings;
+mod lsp_stats;
+mod lsp_status;
 mod lsp_workspace;
+mod mir_algorithm;
+#[cfg(feature = "compiler")]
 mod mir_analysis;
 mod mir_cache;
+#[cfg(feature = "compiler")]
 mod mir_polonius;
+#[cfg(feature = "compiler")]
 mod mir_transform;
 mod models;
-mod range_ops;
+mod path_norm;
+#[cfg_attr(
+    not(feature = "compiler"),
+    allow(dead_code, reason = "only built in this process")
+)]
+mod project_json;
+pub mod range_ops;
+mod report;
+#[cfg(feature = "compiler")]
 mod rustc_wrapper;
 mod test_framework;
 mod text_conversion;
 mod toolchain;
+mod watch;
 
 pub use cli::Cli;
+pub use deco_kind::{AUTO_BORROW_SUFFIX, DiagnosticCode};
+pub use logging::{LOG_FILE_ENV, init_logging};
+pub use lsp_cargo_lock::MAX_LOCK_WAIT_ENV;
+pub use lsp_health::HEALTH_INTERVAL_ENV;
+#[allow(deprecated, reason = "the former name stays for dependents")]
+pub use models::Loc;
+pub use models::{CharOffset, LineCol, Range};
+#[cfg(feature = "compiler")]
 pub use rustc_wrapper::run_as_rustc_wrapper;
-pub use test_framework::{DecoKind, ExpectedDeco, TestCase, run_tests};
+pub use test_framework::{
+    AnalysisStatusKind, CLEAN_STALE_ENV, DecoKind, DecoSpan, ExpectedDeco, ForbiddenDeco,
+    LSP_ADDR_ENV, LspClient, OWL_BINARY_ENV, TestCase, TestPackage, TestServer, run_tests,
+    run_tests_from_dir, setup_workspace,
+};
+pub use text_conversion::SourceIndex;
+pub use toolchain::CACHE_DIR_ENV;
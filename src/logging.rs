@@ -0,0 +1,270 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{Log, Metadata, Record};
+use serde::Serialize;
+
+use crate::lsp_progress::AnalysisStatus;
+
+/// Path of a file that receives the log as JSON lines, next to stderr
+pub const LOG_FILE_ENV: &str = "FERROUS_OWL_LOG_FILE";
+
+/// Size after which the log file is moved to `<path>.1` and started anew
+const MAX_LOG_FILE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Time between two heartbeat lines in the log file
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+static LOG_FILE: OnceLock<LogFile> = OnceLock::new();
+
+/// State of the server, written periodically to the log file so that hangs
+/// can be located afterwards.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Heartbeat {
+    pub status: AnalysisStatus,
+    /// Number of running analyses
+    pub processes: usize,
+    /// Number of analyzed files
+    pub analyzed: usize,
+}
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    /// Milliseconds since the Unix epoch
+    timestamp: u128,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(flatten)]
+    heartbeat: Option<&'a Heartbeat>,
+}
+
+impl LogLine<'_> {
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn record_line(record: &Record<'_>) -> String {
+    LogLine {
+        timestamp: now_ms(),
+        level: record.level().as_str(),
+        target: record.target(),
+        message: record.args().to_string(),
+        heartbeat: None,
+    }
+    .to_json()
+}
+
+fn heartbeat_line(heartbeat: &Heartbeat) -> String {
+    LogLine {
+        timestamp: now_ms(),
+        level: log::Level::Info.as_str(),
+        target: concat!(module_path!(), "::heartbeat"),
+        message: "heartbeat".to_owned(),
+        heartbeat: Some(heartbeat),
+    }
+    .to_json()
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Log file shared by all threads, rotated once it reaches `max_bytes`
+struct LogFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Mutex<File>,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.max_bytes <= file.metadata()?.len() {
+            fs::rename(&self.path, rotated_path(&self.path))?;
+            *file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&self.path)?;
+        }
+        // a single write keeps lines of concurrent processes apart
+        file.write_all(format!("{line}\n").as_bytes())
+    }
+
+    fn flush(&self) {
+        self.file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush()
+            .ok();
+    }
+}
+
+/// Logs to stderr and, if configured, to the log file with the same filter
+struct Logger {
+    stderr: env_logger::Logger,
+    file: Option<&'static LogFile>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        self.stderr.log(record);
+        if let Some(file) = self.file {
+            file.write_line(&record_line(record)).ok();
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some(file) = self.file {
+            file.flush();
+        }
+    }
+}
+
+/// Initialize logging to stderr, filtered by `RUST_LOG`.
+///
+/// Records are also written to `log_file`, or the file named by
+/// [`LOG_FILE_ENV`], as JSON lines.
+pub fn init_logging(log_file: Option<PathBuf>) {
+    let stderr =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+            .format_timestamp(None)
+            .target(env_logger::Target::Stderr)
+            .build();
+
+    let mut open_error = None;
+    let path = log_file.or_else(|| env::var_os(LOG_FILE_ENV).map(PathBuf::from));
+    if let Some(path) = path {
+        match LogFile::open(path.clone(), MAX_LOG_FILE_BYTES) {
+            Ok(file) => {
+                LOG_FILE.set(file).ok();
+            }
+            Err(e) => open_error = Some((path, e)),
+        }
+    }
+
+    log::set_max_level(stderr.filter());
+    log::set_boxed_logger(Box::new(Logger {
+        stderr,
+        file: LOG_FILE.get(),
+    }))
+    .ok();
+
+    if let Some((path, e)) = open_error {
+        log::error!("cannot open log file {}: {e}", path.display());
+    }
+}
+
+/// Path of the log file, if logging to a file.
+pub fn log_file_path() -> Option<&'static Path> {
+    LOG_FILE.get().map(|file| file.path.as_path())
+}
+
+/// Write `heartbeat` to the log file, if logging to a file.
+pub fn log_heartbeat(heartbeat: &Heartbeat) {
+    if let Some(file) = LOG_FILE.get() {
+        file.write_line(&heartbeat_line(heartbeat)).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_line_format() {
+        let line = record_line(
+            &Record::builder()
+                .level(log::Level::Warn)
+                .target("ferrous_owl::lsp_workspace")
+                .args(format_args!("start analyzing package {}", "demo"))
+                .build(),
+        );
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["level"], "WARN");
+        assert_eq!(json["target"], "ferrous_owl::lsp_workspace");
+        assert_eq!(json["message"], "start analyzing package demo");
+        assert!(json["timestamp"].as_u64().is_some_and(|t| 0 < t));
+        assert!(json.get("status").is_none(), "no heartbeat fields: {line}");
+    }
+
+    #[test]
+    fn test_heartbeat_line_format() {
+        let line = heartbeat_line(&Heartbeat {
+            status: AnalysisStatus::Analyzing,
+            processes: 2,
+            analyzed: 5,
+        });
+        assert!(!line.contains('\n'));
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["message"], "heartbeat");
+        assert_eq!(json["status"], "analyzing");
+        assert_eq!(json["processes"], 2);
+        assert_eq!(json["analyzed"], 5);
+    }
+
+    #[test]
+    fn test_log_file_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("owl.log");
+        let file = LogFile::open(path.clone(), 16).unwrap();
+
+        file.write_line("first line").unwrap();
+        file.write_line("second line").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "first line\nsecond line\n"
+        );
+
+        file.write_line("third line").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third line\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path)).unwrap(),
+            "first line\nsecond line\n"
+        );
+
+        file.write_line("fourth line").unwrap();
+        file.write_line("fifth line").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fifth line\n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path)).unwrap(),
+            "third line\nfourth line\n",
+            "older rotations are replaced"
+        );
+    }
+}
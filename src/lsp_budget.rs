@@ -0,0 +1,150 @@
+//! Cap on the decorations returned for a selection, so that selecting a
+//! variable that lives through a huge function does not flood the editor.
+//!
+//! Lifetimes and scopes are split into a range per line and statement, and
+//! make up most decorations of such a selection. They are dropped first,
+//! farthest from the cursor first, so that the moves, borrows, calls and
+//! other events of the selection are kept.
+
+use crate::{
+    deco_kind::DecoKind,
+    lsp_decoration::Deco,
+    models::{CharOffset, Range},
+};
+
+/// Default of the `max_decorations` initialization option
+pub const DEFAULT_MAX_DECORATIONS: usize = 2000;
+
+/// Whether decorations of `kind` only fill the budget left by the others
+const fn is_filler(kind: DecoKind) -> bool {
+    matches!(kind, DecoKind::Lifetime | DecoKind::Scope)
+}
+
+/// Characters between `range` and `cursor`, 0 if the range contains it
+fn distance(range: Range, cursor: CharOffset) -> u32 {
+    if cursor < range.from() {
+        u32::from(range.from()) - u32::from(cursor)
+    } else if range.until() < cursor {
+        u32::from(cursor) - u32::from(range.until())
+    } else {
+        0
+    }
+}
+
+/// At most `max` of `decorations`, in their order: all but lifetimes and
+/// scopes first, then the lifetimes and scopes, each nearest to `cursor`
+/// first.
+#[must_use]
+pub fn limit(decorations: Vec<Deco>, cursor: CharOffset, max: usize) -> Vec<Deco> {
+    if decorations.len() <= max {
+        return decorations;
+    }
+    let mut ranked: Vec<_> = decorations.into_iter().enumerate().collect();
+    ranked.sort_by_key(|(index, deco)| {
+        (
+            is_filler(deco.deco_kind()),
+            distance(deco.range(), cursor),
+            *index,
+        )
+    });
+    ranked.truncate(max);
+    ranked.sort_unstable_by_key(|(index, _)| *index);
+    ranked.into_iter().map(|(_, deco)| deco).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FnLocal;
+
+    fn range(from: u32, until: u32) -> Range {
+        Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+    }
+
+    fn lifetime(from: u32, until: u32) -> Deco {
+        Deco::Lifetime {
+            local: FnLocal::new(1, 1),
+            range: range(from, until),
+            hover_text: String::new(),
+            overlapped: false,
+            layer: 0,
+        }
+    }
+
+    fn moved(from: u32, until: u32) -> Deco {
+        Deco::Move {
+            local: FnLocal::new(1, 1),
+            range: range(from, until),
+            hover_text: String::new(),
+            overlapped: false,
+            layer: 0,
+        }
+    }
+
+    fn kinds_and_starts(decorations: &[Deco]) -> Vec<(DecoKind, u32)> {
+        decorations
+            .iter()
+            .map(|deco| (deco.deco_kind(), u32::from(deco.range().from())))
+            .collect()
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(range(10, 20), CharOffset::from(15_u32)), 0);
+        assert_eq!(distance(range(10, 20), CharOffset::from(10_u32)), 0);
+        assert_eq!(distance(range(10, 20), CharOffset::from(20_u32)), 0);
+        assert_eq!(distance(range(10, 20), CharOffset::from(4_u32)), 6);
+        assert_eq!(distance(range(10, 20), CharOffset::from(27_u32)), 7);
+    }
+
+    #[test]
+    fn test_events_are_kept_before_lifetimes() {
+        let decorations = vec![
+            lifetime(0, 5),
+            moved(100, 105),
+            lifetime(10, 15),
+            moved(200, 205),
+        ];
+        assert_eq!(
+            kinds_and_starts(&limit(decorations, CharOffset::from(0_u32), 3)),
+            vec![
+                (DecoKind::Lifetime, 0),
+                (DecoKind::Move, 100),
+                (DecoKind::Move, 200)
+            ],
+            "in their original order"
+        );
+    }
+
+    #[test]
+    fn test_lifetimes_nearest_to_the_cursor_are_kept() {
+        let decorations: Vec<_> = (0..10).map(|i| lifetime(i * 10, i * 10 + 5)).collect();
+        assert_eq!(
+            kinds_and_starts(&limit(decorations, CharOffset::from(52_u32), 3)),
+            vec![
+                (DecoKind::Lifetime, 40),
+                (DecoKind::Lifetime, 50),
+                (DecoKind::Lifetime, 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_exceeding_the_cap() {
+        let decorations = vec![moved(0, 5), lifetime(6, 9), moved(10, 15), moved(20, 25)];
+        assert_eq!(
+            kinds_and_starts(&limit(decorations, CharOffset::from(22_u32), 2)),
+            vec![(DecoKind::Move, 10), (DecoKind::Move, 20)],
+            "the events nearest to the cursor"
+        );
+    }
+
+    #[test]
+    fn test_within_the_cap() {
+        let decorations = vec![lifetime(0, 5), moved(10, 15)];
+        assert_eq!(
+            limit(decorations.clone(), CharOffset::from(0_u32), 2),
+            decorations
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    env,
+    fs::{File, TryLockError},
+    path::Path,
+    time::Duration,
+};
+
+use tokio::time;
+
+use crate::lsp_progress::ProgressToken;
+
+/// Environment variable overriding [`DEFAULT_MAX_LOCK_WAIT`], in seconds
+pub const MAX_LOCK_WAIT_ENV: &str = "FERROUS_OWL_MAX_LOCK_WAIT_SECS";
+
+/// Time to wait for another cargo process to release the build directory
+/// before analysis starts anyway, and cargo waits by itself
+const DEFAULT_MAX_LOCK_WAIT: Duration = Duration::from_secs(60);
+
+/// Delay before the lock is probed the second time, doubled after every probe
+const FIRST_RETRY: Duration = Duration::from_millis(100);
+
+/// Start of the line cargo prints on stderr while it waits for a lock, like
+/// `Blocking waiting for file lock on build directory`
+const LOCK_MESSAGE: &str = "Blocking waiting for file lock";
+
+/// Progress message while analysis waits for a cargo lock
+pub const WAITING_MESSAGE: &str = "waiting for cargo lock";
+
+/// Longest wait for a cargo lock from [`MAX_LOCK_WAIT_ENV`], else the
+/// default.
+pub fn max_lock_wait() -> Duration {
+    env::var(MAX_LOCK_WAIT_ENV)
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map_or(DEFAULT_MAX_LOCK_WAIT, Duration::from_secs)
+}
+
+/// Whether the line of cargo's stderr tells it waits for a lock held by
+/// another process.
+pub fn is_lock_message(line: &str) -> bool {
+    line.trim_start().starts_with(LOCK_MESSAGE)
+}
+
+/// Delays between probes of a lock, doubling from [`FIRST_RETRY`] until they
+/// add up to the maximum wait
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    next: Duration,
+    remaining: Duration,
+}
+
+impl Backoff {
+    pub const fn new(max_wait: Duration) -> Self {
+        Self {
+            next: FIRST_RETRY,
+            remaining: max_wait,
+        }
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        if self.remaining.is_zero() {
+            return None;
+        }
+        let delay = self.next.min(self.remaining);
+        self.remaining -= delay;
+        self.next = self.next.saturating_mul(2);
+        Some(delay)
+    }
+}
+
+/// Whether another process holds the exclusive lock of the file at `path`,
+/// as cargo does on `.cargo-lock` in the build directory while it builds.
+///
+/// The file is only opened for reading, a missing file is not locked.
+pub fn is_locked(path: &Path) -> bool {
+    File::open(path)
+        .is_ok_and(|file| matches!(file.try_lock_shared(), Err(TryLockError::WouldBlock)))
+}
+
+/// Wait until the lock of the file at `path` is free, probing it after each
+/// delay of [`Backoff`] and reporting the wait once to `progress`.
+///
+/// Returns whether the lock is free, which it may still not be after
+/// `max_wait`.
+pub async fn wait_until_unlocked(
+    path: &Path,
+    max_wait: Duration,
+    progress: Option<&ProgressToken>,
+) -> bool {
+    let mut reported = false;
+    for delay in Backoff::new(max_wait) {
+        if !is_locked(path) {
+            return true;
+        }
+        if !reported {
+            log::info!("{WAITING_MESSAGE} on {}", path.display());
+            if let Some(progress) = progress {
+                progress.report(Some(WAITING_MESSAGE), None).await;
+            }
+            reported = true;
+        }
+        time::sleep(delay).await;
+    }
+    !is_locked(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_wait() {
+        let delays: Vec<_> = Backoff::new(Duration::from_millis(1000)).collect();
+        let millis: Vec<_> = delays.iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, vec![100, 200, 400, 300]);
+        assert_eq!(Backoff::new(Duration::ZERO).count(), 0);
+    }
+
+    #[test]
+    fn test_lock_messages() {
+        assert!(is_lock_message(
+            "    Blocking waiting for file lock on build directory"
+        ));
+        assert!(is_lock_message(
+            "Blocking waiting for file lock on package cache"
+        ));
+        assert!(!is_lock_message("    Checking foo v0.1.0"));
+        assert!(!is_lock_message("error: Blocking waiting for file lock"));
+    }
+
+    #[tokio::test]
+    async fn test_waits_until_lock_is_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".cargo-lock");
+        assert!(!is_locked(&path), "missing files are not locked");
+        fs::write(&path, "").unwrap();
+        assert!(!is_locked(&path));
+
+        let holder = File::create(&path).unwrap();
+        holder.lock().unwrap();
+        assert!(is_locked(&path));
+        assert!(!wait_until_unlocked(&path, Duration::from_millis(250), None).await);
+
+        let release = tokio::spawn(async move {
+            time::sleep(Duration::from_millis(300)).await;
+            holder.unlock().unwrap();
+        });
+        assert!(wait_until_unlocked(&path, Duration::from_secs(10), None).await);
+        release.await.unwrap();
+    }
+}
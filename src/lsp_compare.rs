@@ -0,0 +1,124 @@
+//! Differences between the decorations of two selections, reported by the
+//! `ferrous-owl.compareSelections` command.
+
+use serde::Serialize;
+
+use crate::lsp_decoration::Deco;
+
+/// Decorations of selection A and B, split by whether the other selection
+/// has a matching one
+#[derive(Serialize, Clone, Debug)]
+pub struct SelectionDiff<R> {
+    pub only_a: Vec<Deco<R>>,
+    pub only_b: Vec<Deco<R>>,
+    /// Decorations of A matched by one of B
+    pub common: Vec<Deco<R>>,
+}
+
+/// Hover text without the names and types in backticks, which differ
+/// between any two variables.
+fn normalized_hover_text(hover_text: &str) -> String {
+    hover_text
+        .split('`')
+        .enumerate()
+        .map(|(i, part)| if i % 2 == 1 { "_" } else { part })
+        .collect::<Vec<_>>()
+        .join("`")
+}
+
+fn comparison_key<R>(deco: &Deco<R>) -> (&'static str, String) {
+    (deco.kind(), normalized_hover_text(deco.hover_text()))
+}
+
+impl<R> SelectionDiff<R> {
+    /// Match the decorations of `a` and `b` by type and normalized hover
+    /// text, ignoring their ranges.
+    ///
+    /// Each decoration matches at most one of the other selection, so a
+    /// selection moved twice differs from one moved once.
+    #[must_use]
+    pub fn new(a: Vec<Deco<R>>, b: Vec<Deco<R>>) -> Self {
+        let mut only_b: Vec<_> = b.into_iter().map(Some).collect();
+        let mut only_a = Vec::new();
+        let mut common = Vec::new();
+        for deco in a {
+            let key = comparison_key(&deco);
+            let matched = only_b
+                .iter_mut()
+                .find(|other| other.as_ref().is_some_and(|o| comparison_key(o) == key));
+            if let Some(matched) = matched {
+                *matched = None;
+                common.push(deco);
+            } else {
+                only_a.push(deco);
+            }
+        }
+        Self {
+            only_a,
+            only_b: only_b.into_iter().flatten().collect(),
+            common,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CharOffset, FnLocal, Range};
+
+    fn deco(kind: &str, local: u32, at: u32, hover_text: &str) -> Deco {
+        let local = FnLocal::new(local, 1);
+        let range = Range::new(CharOffset::from(at), CharOffset::from(at + 1)).unwrap();
+        Deco::new(kind.parse().unwrap(), local, range, hover_text.to_owned())
+    }
+
+    fn kinds<R>(decorations: &[Deco<R>]) -> Vec<&'static str> {
+        decorations.iter().map(Deco::kind).collect()
+    }
+
+    #[test]
+    fn test_normalized_hover_text() {
+        assert_eq!(
+            normalized_hover_text("lifetime of variable `s`"),
+            "lifetime of variable `_`"
+        );
+        assert_eq!(
+            normalized_hover_text("written through `&mut` reference `r`"),
+            "written through `_` reference `_`"
+        );
+        assert_eq!(
+            normalized_hover_text("immutable borrow"),
+            "immutable borrow"
+        );
+    }
+
+    #[test]
+    fn test_diff_ignores_ranges_and_names() {
+        let a = vec![
+            deco("lifetime", 1, 0, "lifetime of variable `a`"),
+            deco("move", 1, 10, "variable `a` moved"),
+        ];
+        let b = vec![
+            deco("lifetime", 2, 20, "lifetime of variable `b`"),
+            deco("imm_borrow", 2, 30, "immutable borrow"),
+        ];
+        let diff = SelectionDiff::new(a, b);
+        assert_eq!(kinds(&diff.only_a), vec!["move"]);
+        assert_eq!(kinds(&diff.only_b), vec!["imm_borrow"]);
+        assert_eq!(kinds(&diff.common), vec!["lifetime"]);
+        assert_eq!(diff.common[0].hover_text(), "lifetime of variable `a`");
+    }
+
+    #[test]
+    fn test_diff_matches_each_decoration_once() {
+        let a = vec![
+            deco("move", 1, 10, "variable `a` moved"),
+            deco("move", 1, 20, "variable `a` moved"),
+        ];
+        let b = vec![deco("move", 2, 30, "variable `b` moved")];
+        let diff = SelectionDiff::new(a, b);
+        assert_eq!(kinds(&diff.only_a), vec!["move"]);
+        assert!(diff.only_b.is_empty());
+        assert_eq!(kinds(&diff.common), vec!["move"]);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+use crate::lsp_decoration::Deco;
+
+/// Number of documents whose last decorations are kept
+const CAPACITY: usize = 16;
+
+/// Decorations last computed at the cursor of recently used documents, all of
+/// them before they are limited, with the id of the selection they were
+/// computed for
+#[derive(Default, Debug)]
+pub struct CursorCache {
+    /// Most recently used first
+    entries: VecDeque<(PathBuf, u64, Vec<Deco>)>,
+}
+
+impl CursorCache {
+    /// Decorations of `path` if they were last computed for `selection_id`.
+    pub fn get(&mut self, path: &Path, selection_id: u64) -> Option<Vec<Deco>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(cached, id, _)| cached == path && *id == selection_id)?;
+        let entry = self.entries.remove(index)?;
+        let decorations = entry.2.clone();
+        self.entries.push_front(entry);
+        Some(decorations)
+    }
+
+    /// Keep `decorations` as the last ones of `path`, forgetting the least
+    /// recently used document if there are too many.
+    pub fn insert(&mut self, path: PathBuf, selection_id: u64, decorations: Vec<Deco>) {
+        self.entries.retain(|(cached, _, _)| *cached != path);
+        self.entries.push_front((path, selection_id, decorations));
+        self.entries.truncate(CAPACITY);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CharOffset, FnLocal, Range};
+
+    fn decorations(hover_text: &str) -> Vec<Deco> {
+        vec![Deco::Move {
+            local: FnLocal::new(1, 1),
+            range: Range::new(CharOffset::from(0_u32), CharOffset::from(1_u32)).unwrap(),
+            hover_text: hover_text.to_owned(),
+            overlapped: false,
+            layer: 0,
+        }]
+    }
+
+    #[test]
+    fn test_hit_only_for_same_selection() {
+        let mut cache = CursorCache::default();
+        let path = Path::new("/src/lib.rs");
+        cache.insert(path.to_path_buf(), 1, decorations("first"));
+        assert!(cache.get(path, 2).is_none());
+        assert!(cache.get(Path::new("/src/main.rs"), 1).is_none());
+        let hit = cache.get(path, 1).unwrap();
+        assert_eq!(hit[0].hover_text(), "first");
+
+        // one entry per document
+        cache.insert(path.to_path_buf(), 2, decorations("second"));
+        assert!(cache.get(path, 1).is_none());
+        assert_eq!(cache.get(path, 2).unwrap()[0].hover_text(), "second");
+    }
+
+    #[test]
+    fn test_least_recently_used_document_is_forgotten() {
+        let mut cache = CursorCache::default();
+        let path = |i: usize| PathBuf::from(format!("/src/{i}.rs"));
+        for i in 0..CAPACITY {
+            cache.insert(path(i), 0, Vec::new());
+        }
+        assert!(cache.get(&path(0), 0).is_some());
+        cache.insert(path(CAPACITY), 0, Vec::new());
+        assert!(cache.get(&path(0), 0).is_some(), "recently used");
+        assert!(cache.get(&path(1), 0).is_none(), "least recently used");
+        assert_eq!(cache.entries.len(), CAPACITY);
+    }
+}
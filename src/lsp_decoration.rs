@@ -1,13 +1,44 @@
-use std::{collections::HashSet, mem, path::PathBuf};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    iter, mem,
+    path::PathBuf,
+    sync::Arc,
+};
 
+use rayon::prelude::*;
 use tower_lsp::lsp_types;
 
 use crate::{
+    deco_kind::DecoKind,
     lsp_progress::AnalysisStatus,
-    models::{FnLocal, Loc, MirDecl, MirRval, MirStatement, MirTerminator, Range},
-    range_ops, text_conversion,
+    models::{
+        CallReceiver, CharOffset, FnLocal, Item, MirDecl, MirRval, MirStatement, MirTerminator,
+        Range, ReceiverKind,
+    },
+    range_ops,
+    text_conversion::SourceIndex,
 };
 
+/// Visit the indices of `bounds`, pairs of the start and end of ranges, by
+/// their starts, each with the indices of the ones visited before that it
+/// overlaps.
+fn sweep<P: Ord + Copy>(bounds: &[(P, P)], mut visit: impl FnMut(usize, &[usize])) {
+    let mut order: Vec<_> = (0..bounds.len()).collect();
+    order.sort_by_key(|&i| (bounds[i].0, i));
+    let mut active: Vec<usize> = Vec::new();
+    for i in order {
+        let (from, until) = bounds[i];
+        active.retain(|&j| from < bounds[j].1);
+        if from < until {
+            visit(i, &active);
+            active.push(i);
+        } else {
+            visit(i, &[]);
+        }
+    }
+}
+
 impl<R> Deco<R> {
     /// Returns whether this decoration should be shown as a diagnostic.
     /// Lifetime decorations are filtered out as they are too verbose.
@@ -18,17 +49,31 @@ impl<R> Deco<R> {
     /// Returns the diagnostic severity for this decoration type.
     /// Each type gets a distinct severity for better visual differentiation:
     /// - Outlive -> Error (red - critical ownership issues)
-    /// - `SharedMut`, Move -> Warning (yellow/orange - ownership/aliasing)
-    /// - `MutBorrow`, Call -> Information (blue - mutable access/calls)
-    /// - `ImmBorrow`, Lifetime -> Hint (gray/dim - immutable borrow info)
+    /// - `SharedMut`, Move, `DeadWrite`, `HeldAcrossAwait`, Conflict,
+    ///   `LeakOrForget` -> Warning (yellow/orange - ownership/aliasing)
+    /// - `MutBorrow`, Call, Binding -> Information (blue - mutable
+    ///   access/calls)
+    /// - `ImmBorrow`, Lifetime, Drop, Scope, Allocation, Summary, `SharedClone`
+    ///   -> Hint (gray/dim - borrow info)
     pub const fn diagnostic_severity(&self) -> lsp_types::DiagnosticSeverity {
         match self {
             Self::Outlive { .. } => lsp_types::DiagnosticSeverity::ERROR,
-            Self::SharedMut { .. } | Self::Move { .. } => lsp_types::DiagnosticSeverity::WARNING,
-            Self::MutBorrow { .. } | Self::Call { .. } => {
+            Self::SharedMut { .. }
+            | Self::Move { .. }
+            | Self::DeadWrite { .. }
+            | Self::HeldAcrossAwait { .. }
+            | Self::Conflict { .. }
+            | Self::LeakOrForget { .. } => lsp_types::DiagnosticSeverity::WARNING,
+            Self::MutBorrow { .. } | Self::Call { .. } | Self::Binding { .. } => {
                 lsp_types::DiagnosticSeverity::INFORMATION
             }
-            Self::ImmBorrow { .. } | Self::Lifetime { .. } => lsp_types::DiagnosticSeverity::HINT,
+            Self::ImmBorrow { .. }
+            | Self::Lifetime { .. }
+            | Self::Drop { .. }
+            | Self::Scope { .. }
+            | Self::Allocation { .. }
+            | Self::Summary { .. }
+            | Self::SharedClone { .. } => lsp_types::DiagnosticSeverity::HINT,
         }
     }
 
@@ -40,38 +85,415 @@ impl<R> Deco<R> {
             | Self::MutBorrow { hover_text, .. }
             | Self::Move { hover_text, .. }
             | Self::Call { hover_text, .. }
+            | Self::Drop { hover_text, .. }
+            | Self::DeadWrite { hover_text, .. }
             | Self::SharedMut { hover_text, .. }
-            | Self::Outlive { hover_text, .. } => hover_text,
+            | Self::Outlive { hover_text, .. }
+            | Self::Scope { hover_text, .. }
+            | Self::Allocation { hover_text, .. }
+            | Self::Summary { hover_text, .. }
+            | Self::Binding { hover_text, .. }
+            | Self::HeldAcrossAwait { hover_text, .. }
+            | Self::SharedClone { hover_text, .. }
+            | Self::LeakOrForget { hover_text, .. }
+            | Self::Conflict { hover_text, .. } => hover_text,
+        }
+    }
+
+    /// Local the decoration belongs to
+    pub const fn local(&self) -> FnLocal {
+        match self {
+            Self::Lifetime { local, .. }
+            | Self::ImmBorrow { local, .. }
+            | Self::MutBorrow { local, .. }
+            | Self::Move { local, .. }
+            | Self::Call { local, .. }
+            | Self::Drop { local, .. }
+            | Self::DeadWrite { local, .. }
+            | Self::SharedMut { local, .. }
+            | Self::Outlive { local, .. }
+            | Self::Scope { local, .. }
+            | Self::Allocation { local, .. }
+            | Self::Summary { local, .. }
+            | Self::Binding { local, .. }
+            | Self::HeldAcrossAwait { local, .. }
+            | Self::SharedClone { local, .. }
+            | Self::LeakOrForget { local, .. }
+            | Self::Conflict { local, .. } => *local,
+        }
+    }
+
+    /// Whether the decoration is stacked on others, on a layer above 0.
+    ///
+    /// Until [`CalcDecos::handle_overlapping`] assigns the layers, whether it
+    /// is covered by a later decoration.
+    pub const fn overlapped(&self) -> bool {
+        match self {
+            Self::Lifetime { overlapped, .. }
+            | Self::ImmBorrow { overlapped, .. }
+            | Self::MutBorrow { overlapped, .. }
+            | Self::Move { overlapped, .. }
+            | Self::Call { overlapped, .. }
+            | Self::Drop { overlapped, .. }
+            | Self::DeadWrite { overlapped, .. }
+            | Self::SharedMut { overlapped, .. }
+            | Self::Outlive { overlapped, .. }
+            | Self::Scope { overlapped, .. }
+            | Self::Allocation { overlapped, .. }
+            | Self::Summary { overlapped, .. }
+            | Self::Binding { overlapped, .. }
+            | Self::HeldAcrossAwait { overlapped, .. }
+            | Self::SharedClone { overlapped, .. }
+            | Self::LeakOrForget { overlapped, .. }
+            | Self::Conflict { overlapped, .. } => *overlapped,
+        }
+    }
+
+    /// Position of the decoration in the stack of those sharing its span,
+    /// from 0 for the bottom one, to be drawn over those of lower layers
+    pub const fn layer(&self) -> u8 {
+        match self {
+            Self::Lifetime { layer, .. }
+            | Self::ImmBorrow { layer, .. }
+            | Self::MutBorrow { layer, .. }
+            | Self::Move { layer, .. }
+            | Self::Call { layer, .. }
+            | Self::Drop { layer, .. }
+            | Self::DeadWrite { layer, .. }
+            | Self::SharedMut { layer, .. }
+            | Self::Outlive { layer, .. }
+            | Self::Scope { layer, .. }
+            | Self::Allocation { layer, .. }
+            | Self::Summary { layer, .. }
+            | Self::Binding { layer, .. }
+            | Self::HeldAcrossAwait { layer, .. }
+            | Self::SharedClone { layer, .. }
+            | Self::LeakOrForget { layer, .. }
+            | Self::Conflict { layer, .. } => *layer,
+        }
+    }
+
+    /// Put the decoration on `new_layer`, overlapping the ones below if it is
+    /// above 0.
+    const fn set_layer(&mut self, new_layer: u8) {
+        match self {
+            Self::Lifetime {
+                layer, overlapped, ..
+            }
+            | Self::ImmBorrow {
+                layer, overlapped, ..
+            }
+            | Self::MutBorrow {
+                layer, overlapped, ..
+            }
+            | Self::Move {
+                layer, overlapped, ..
+            }
+            | Self::Call {
+                layer, overlapped, ..
+            }
+            | Self::Drop {
+                layer, overlapped, ..
+            }
+            | Self::DeadWrite {
+                layer, overlapped, ..
+            }
+            | Self::SharedMut {
+                layer, overlapped, ..
+            }
+            | Self::Outlive {
+                layer, overlapped, ..
+            }
+            | Self::Scope {
+                layer, overlapped, ..
+            }
+            | Self::Allocation {
+                layer, overlapped, ..
+            }
+            | Self::Summary {
+                layer, overlapped, ..
+            }
+            | Self::Binding {
+                layer, overlapped, ..
+            }
+            | Self::HeldAcrossAwait {
+                layer, overlapped, ..
+            }
+            | Self::SharedClone {
+                layer, overlapped, ..
+            }
+            | Self::LeakOrForget {
+                layer, overlapped, ..
+            }
+            | Self::Conflict {
+                layer, overlapped, ..
+            } => {
+                *layer = new_layer;
+                *overlapped = new_layer > 0;
+            }
+        }
+    }
+
+    /// Kind of the decoration.
+    ///
+    /// Matches every variant, so that a new variant needs a new [`DecoKind`].
+    pub const fn deco_kind(&self) -> DecoKind {
+        match self {
+            Self::Lifetime { .. } => DecoKind::Lifetime,
+            Self::ImmBorrow { .. } => DecoKind::ImmBorrow,
+            Self::MutBorrow { .. } => DecoKind::MutBorrow,
+            Self::Move { .. } => DecoKind::Move,
+            Self::Call { .. } => DecoKind::Call,
+            Self::Drop { .. } => DecoKind::Drop,
+            Self::DeadWrite { .. } => DecoKind::DeadWrite,
+            Self::SharedMut { .. } => DecoKind::SharedMut,
+            Self::Outlive { .. } => DecoKind::Outlive,
+            Self::Scope { .. } => DecoKind::Scope,
+            Self::Allocation { .. } => DecoKind::Allocation,
+            Self::Summary { .. } => DecoKind::Summary,
+            Self::Binding { .. } => DecoKind::Binding,
+            Self::HeldAcrossAwait { .. } => DecoKind::HeldAcrossAwait,
+            Self::SharedClone { .. } => DecoKind::SharedClone,
+            Self::LeakOrForget { .. } => DecoKind::LeakOrForget,
+            Self::Conflict { .. } => DecoKind::Conflict,
+        }
+    }
+
+    /// Name of the decoration type, as in its `type` field
+    pub const fn kind(&self) -> &'static str {
+        self.deco_kind().type_name()
+    }
+
+    /// Terse label of the decoration type, for virtual text at the end of a
+    /// line
+    pub const fn label(&self) -> &'static str {
+        match self {
+            Self::Lifetime { .. } => "live",
+            Self::ImmBorrow { .. } => "&",
+            Self::MutBorrow { .. } => "&mut",
+            Self::Move { .. } => "←move",
+            Self::Call { .. } => "call",
+            Self::Drop { .. } => "⊙drop",
+            Self::DeadWrite { .. } => "✗write",
+            Self::SharedMut { .. } => "&⇄mut",
+            Self::Outlive { .. } => "outlive",
+            Self::Scope { .. } => "scope",
+            Self::Allocation { .. } => "alloc",
+            Self::Summary { .. } => "Σ",
+            Self::Binding { .. } => "bind",
+            Self::HeldAcrossAwait { .. } => "⏸await",
+            Self::SharedClone { .. } => "rc+1",
+            Self::LeakOrForget { .. } => "∞leak",
+            Self::Conflict { .. } => "⚡conflict",
         }
     }
 
-    /// Returns a diagnostic code for this decoration type
+    /// Returns a diagnostic code for this decoration type, with a suffix
+    /// for implicit borrows
     pub fn diagnostic_code(&self) -> String {
-        let pkg = env!("CARGO_PKG_NAME");
         match self {
-            Self::Lifetime { .. } => format!("{pkg}:lifetime"),
-            Self::ImmBorrow { .. } => format!("{pkg}:imm-borrow"),
-            Self::MutBorrow { .. } => format!("{pkg}:mut-borrow"),
-            Self::Move { .. } => format!("{pkg}:move"),
-            Self::Call { .. } => format!("{pkg}:call"),
-            Self::SharedMut { .. } => format!("{pkg}:shared-mut"),
-            Self::Outlive { .. } => format!("{pkg}:outlive"),
+            Self::ImmBorrow { implicit: true, .. } | Self::MutBorrow { implicit: true, .. } => {
+                self.deco_kind().auto_borrow_code()
+            }
+            _ => self.deco_kind().diagnostic_code(),
+        }
+    }
+
+    /// Decoration of `kind`, not overlapped.
+    ///
+    /// Matches every kind, so that a new [`DecoKind`] needs a new variant.
+    #[allow(clippy::too_many_lines, reason = "one arm per decoration kind")]
+    pub const fn new(kind: DecoKind, local: FnLocal, range: R, hover_text: String) -> Self {
+        let overlapped = false;
+        let layer = 0;
+        match kind {
+            DecoKind::Lifetime => Self::Lifetime {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::ImmBorrow => Self::ImmBorrow {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+                implicit: false,
+                reborrow: false,
+            },
+            DecoKind::MutBorrow => Self::MutBorrow {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+                implicit: false,
+                reborrow: false,
+            },
+            DecoKind::Move => Self::Move {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Call => Self::Call {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Drop => Self::Drop {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::DeadWrite => Self::DeadWrite {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::SharedMut => Self::SharedMut {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Outlive => Self::Outlive {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Scope => Self::Scope {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Allocation => Self::Allocation {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Summary => Self::Summary {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Binding => Self::Binding {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::HeldAcrossAwait => Self::HeldAcrossAwait {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::SharedClone => Self::SharedClone {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::LeakOrForget => Self::LeakOrForget {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            },
+            DecoKind::Conflict => Self::Conflict {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+                related: None,
+            },
         }
     }
 }
 
 impl Deco<lsp_types::Range> {
-    /// Convert this decoration to an LSP diagnostic
+    /// Whether each of `decorations` is covered by an overlapping one of a
+    /// higher layer, and hidden by clients drawing only the top of each stack
+    #[must_use]
+    pub fn covered(decorations: &[Self]) -> Vec<bool> {
+        let bounds: Vec<_> = decorations
+            .iter()
+            .map(|deco| (deco.lsp_range().start, deco.lsp_range().end))
+            .collect();
+        let mut covered = vec![false; decorations.len()];
+        sweep(&bounds, |i, below| {
+            for &j in below {
+                match decorations[i].layer().cmp(&decorations[j].layer()) {
+                    Ordering::Less => covered[i] = true,
+                    Ordering::Greater => covered[j] = true,
+                    Ordering::Equal => {}
+                }
+            }
+        });
+        covered
+    }
+
+    /// Range of the decoration in the document
     #[must_use]
-    pub fn to_diagnostic(&self) -> lsp_types::Diagnostic {
-        let range = match self {
+    pub const fn lsp_range(&self) -> lsp_types::Range {
+        match self {
             Self::Lifetime { range, .. }
             | Self::ImmBorrow { range, .. }
             | Self::MutBorrow { range, .. }
             | Self::Move { range, .. }
             | Self::Call { range, .. }
+            | Self::Drop { range, .. }
+            | Self::DeadWrite { range, .. }
             | Self::SharedMut { range, .. }
-            | Self::Outlive { range, .. } => *range,
+            | Self::Outlive { range, .. }
+            | Self::Scope { range, .. }
+            | Self::Allocation { range, .. }
+            | Self::Summary { range, .. }
+            | Self::Binding { range, .. }
+            | Self::HeldAcrossAwait { range, .. }
+            | Self::SharedClone { range, .. }
+            | Self::LeakOrForget { range, .. }
+            | Self::Conflict { range, .. } => *range,
+        }
+    }
+
+    /// Convert this decoration of the document `uri` to an LSP diagnostic
+    #[must_use]
+    pub fn to_diagnostic(&self, uri: &lsp_types::Url) -> lsp_types::Diagnostic {
+        let range = self.lsp_range();
+        let related_information = match self {
+            Self::Conflict {
+                related: Some(related),
+                ..
+            } => Some(vec![lsp_types::DiagnosticRelatedInformation {
+                location: lsp_types::Location::new(uri.clone(), *related),
+                message: "borrow created here".to_owned(),
+            }]),
+            _ => None,
         };
 
         lsp_types::Diagnostic {
@@ -81,13 +503,142 @@ impl Deco<lsp_types::Range> {
             code_description: None,
             source: Some(env!("CARGO_PKG_NAME").to_string()),
             message: self.hover_text().to_string(),
-            related_information: None,
+            related_information,
             tags: None,
             data: None,
         }
     }
 }
 
+/// Types whose values own a heap allocation, by the last segment of their
+/// path
+const HEAP_TYPES: &[&str] = &["Box", "Vec", "String", "Rc", "Arc"];
+
+/// Guards releasing a lock or a `RefCell` borrow when dropped, by the last
+/// segment of their path
+const GUARD_TYPES: &[&str] = &[
+    "MutexGuard",
+    "RwLockReadGuard",
+    "RwLockWriteGuard",
+    "Ref",
+    "RefMut",
+];
+
+/// Functions and methods returning a new heap allocation, by the last segment
+/// of their path
+const ALLOCATING_FUNCTIONS: &[&str] = &[
+    "new",
+    "from",
+    "with_capacity",
+    "to_string",
+    "to_owned",
+    "to_vec",
+    "clone",
+    "collect",
+];
+
+/// Macros expanding to a new heap allocation
+const ALLOCATING_MACROS: &[&str] = &["vec!", "format!"];
+
+/// Whether values of the type `ty`, as printed by the compiler, own a heap
+/// allocation. References and pointers to such values do not.
+#[must_use]
+pub fn owns_heap_allocation(ty: &str) -> bool {
+    let path = ty.split('<').next().unwrap_or_default().trim();
+    // references, pointers, arrays and tuples start with a symbol
+    path.chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | ':'))
+        && path
+            .rsplit("::")
+            .next()
+            .is_some_and(|name| HEAP_TYPES.contains(&name))
+}
+
+/// Whether values of the type `ty`, as printed by the compiler, hold a lock
+/// or a `RefCell` borrow until they are dropped.
+fn is_guard(ty: &str) -> bool {
+    ty.split('<')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .rsplit("::")
+        .next()
+        .is_some_and(|name| GUARD_TYPES.contains(&name))
+}
+
+/// Identifier containing or ending at character `column` of `line`.
+fn identifier_at(line: &str, column: usize) -> Option<String> {
+    let is_ident = |c: &&char| c.is_alphanumeric() || **c == '_';
+    let chars: Vec<char> = line.chars().collect();
+    let column = column.min(chars.len());
+    let start = column - chars[..column].iter().rev().take_while(is_ident).count();
+    let end = column + chars[column..].iter().take_while(is_ident).count();
+    (start < end).then(|| chars[start..end].iter().collect())
+}
+
+/// Whether the call with callee text `callee`, like `Box::new` or
+/// `vec![1, 2]`, allocates on the heap.
+#[must_use]
+pub fn is_allocating_call(callee: &str) -> bool {
+    let callee = callee.trim();
+    if ALLOCATING_MACROS.iter().any(|mac| callee.starts_with(mac)) {
+        return true;
+    }
+    callee
+        .rsplit("::")
+        .find(|segment| !segment.starts_with('<'))
+        .is_some_and(|name| ALLOCATING_FUNCTIONS.contains(&name.trim()))
+}
+
+/// Functions taking ownership of their argument without running its
+/// destructor, as the last two segments of their path, with the name shown
+/// in hovers. A bare `forget` is left out, as it may be a function of the
+/// user.
+const LEAKING_FUNCTIONS: &[(&str, &str, &str)] = &[
+    ("mem", "forget", "mem::forget"),
+    ("ManuallyDrop", "new", "ManuallyDrop::new"),
+    ("Box", "leak", "Box::leak"),
+];
+
+/// Name of the function the text of a callee, like `std::mem::forget` or
+/// `ManuallyDrop::<String>::new`, calls if it takes ownership of its
+/// argument without running its destructor.
+#[must_use]
+pub fn leaking_function(callee: &str) -> Option<&'static str> {
+    let mut segments = callee
+        .trim()
+        .rsplit("::")
+        .filter(|segment| !segment.starts_with('<'))
+        .map(str::trim);
+    let name = segments.next()?;
+    let parent = segments.next().unwrap_or_default();
+    LEAKING_FUNCTIONS
+        .iter()
+        .find(|(path, function, _)| *function == name && *path == parent)
+        .map(|(_, _, shown)| *shown)
+}
+
+/// Name of the function at a path resolved by the compiler, like
+/// `std::mem::ManuallyDrop::<T>::new`, if it is one of the standard library
+/// taking ownership of its argument without running its destructor.
+#[must_use]
+pub fn leaking_std_function(path: &str) -> Option<&'static str> {
+    let root = path.trim().split("::").next()?;
+    if !matches!(root, "std" | "core" | "alloc") {
+        return None;
+    }
+    leaking_function(path)
+}
+
+/// Suffix of the hover text of the last lifetime range of a local that is
+/// forgotten or leaked
+const NEVER_DROPPED_SUFFIX: &str = " (never dropped in this function)";
+
+/// Suffix of the hover text of the last move of a local that is not dropped
+/// after it
+const OWNERSHIP_TRANSFERRED: &str =
+    " — ownership transferred; this variable is no longer responsible for dropping the value";
+
 // TODO: Variable name should be checked?
 // const ASYNC_MIR_VARS: [&str; 2] = ["_task_context", "__awaitee"];
 const ASYNC_RESUME_TY: [&str; 2] = [
@@ -103,69 +654,184 @@ pub enum Deco<R = Range> {
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
     },
     ImmBorrow {
         local: FnLocal,
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
+        /// Whether the borrow is taken by auto-ref of a method receiver,
+        /// without a `&` in the source
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        implicit: bool,
+        /// Whether the borrow is a reborrow through a reference, which is
+        /// not counted in the summary
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        reborrow: bool,
     },
     MutBorrow {
         local: FnLocal,
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
+        /// Whether the borrow is taken by auto-ref of a method receiver,
+        /// without a `&` in the source
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        implicit: bool,
+        /// Whether the borrow is a reborrow through a reference, which is
+        /// not counted in the summary
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        reborrow: bool,
     },
     Move {
         local: FnLocal,
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
     },
     Call {
         local: FnLocal,
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
+    },
+    /// Point where the value of the local is dropped
+    Drop {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Assignment to the local whose value is never read
+    DeadWrite {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
     },
     SharedMut {
         local: FnLocal,
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
     },
     Outlive {
         local: FnLocal,
         range: R,
         hover_text: String,
         overlapped: bool,
+        layer: u8,
+    },
+    /// Part of the lexical scope of a variable where it is not live, shown in
+    /// teaching mode
+    Scope {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Call allocating the heap memory a local owns
+    Allocation {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Moves, borrows and drops of a variable, shown at its declaration
+    Summary {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Declaration of a pattern binding that a part of the local is moved
+    /// into
+    Binding {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Suspension of an `async` body at an `.await` while a borrow of the
+    /// local, or a guard it holds, is alive
+    HeldAcrossAwait {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Clone of an `Rc` or `Arc` sharing the allocation of the local
+    SharedClone {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Call taking ownership of the local without running its destructor,
+    /// like `mem::forget`
+    LeakOrForget {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+    },
+    /// Move of the local where a borrow of it is live or required to live
+    Conflict {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+        layer: u8,
+        /// Creation of the borrow, if it is known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        related: Option<R>,
     },
 }
 impl Deco<Range> {
-    fn convert_range(s: &str, range: Range) -> lsp_types::Range {
-        let start = text_conversion::index_to_line_char(s, range.from());
-        let end = text_conversion::index_to_line_char(s, range.until());
+    /// Range of lines and characters of `range` in the text of `s`
+    #[must_use]
+    pub fn convert_range(s: &SourceIndex, range: Range) -> lsp_types::Range {
         lsp_types::Range {
-            start: lsp_types::Position {
-                line: start.0,
-                character: start.1,
-            },
-            end: lsp_types::Position {
-                line: end.0,
-                character: end.1,
-            },
+            start: s.char_to_position(range.from()),
+            end: s.char_to_position(range.until()),
         }
     }
 
-    const fn range(&self) -> Range {
+    #[must_use]
+    pub const fn range(&self) -> Range {
         match self {
             Self::Lifetime { range, .. }
             | Self::ImmBorrow { range, .. }
             | Self::MutBorrow { range, .. }
             | Self::Move { range, .. }
             | Self::Call { range, .. }
+            | Self::Drop { range, .. }
+            | Self::DeadWrite { range, .. }
             | Self::SharedMut { range, .. }
-            | Self::Outlive { range, .. } => *range,
+            | Self::Outlive { range, .. }
+            | Self::Scope { range, .. }
+            | Self::Allocation { range, .. }
+            | Self::Summary { range, .. }
+            | Self::Binding { range, .. }
+            | Self::HeldAcrossAwait { range, .. }
+            | Self::SharedClone { range, .. }
+            | Self::LeakOrForget { range, .. }
+            | Self::Conflict { range, .. } => *range,
         }
     }
 
@@ -186,15 +852,46 @@ impl Deco<Range> {
             | Self::Call {
                 range, overlapped, ..
             }
+            | Self::Drop {
+                range, overlapped, ..
+            }
+            | Self::DeadWrite {
+                range, overlapped, ..
+            }
             | Self::SharedMut {
                 range, overlapped, ..
             }
             | Self::Outlive {
                 range, overlapped, ..
+            }
+            | Self::Scope {
+                range, overlapped, ..
+            }
+            | Self::Allocation {
+                range, overlapped, ..
+            }
+            | Self::Summary {
+                range, overlapped, ..
+            }
+            | Self::Binding {
+                range, overlapped, ..
+            }
+            | Self::HeldAcrossAwait {
+                range, overlapped, ..
+            }
+            | Self::SharedClone {
+                range, overlapped, ..
+            }
+            | Self::LeakOrForget {
+                range, overlapped, ..
+            }
+            | Self::Conflict {
+                range, overlapped, ..
             } => (*range, *overlapped),
         }
     }
 
+    #[allow(clippy::too_many_lines, reason = "one arm per decoration kind")]
     fn with_range(&self, new_range: Range, overlapped: bool) -> Self {
         match self {
             Self::Lifetime {
@@ -204,22 +901,37 @@ impl Deco<Range> {
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
             },
             Self::ImmBorrow {
-                local, hover_text, ..
+                local,
+                hover_text,
+                implicit,
+                reborrow,
+                ..
             } => Self::ImmBorrow {
                 local: *local,
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
+                implicit: *implicit,
+                reborrow: *reborrow,
             },
             Self::MutBorrow {
-                local, hover_text, ..
+                local,
+                hover_text,
+                implicit,
+                reborrow,
+                ..
             } => Self::MutBorrow {
                 local: *local,
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
+                implicit: *implicit,
+                reborrow: *reborrow,
             },
             Self::Move {
                 local, hover_text, ..
@@ -228,6 +940,7 @@ impl Deco<Range> {
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
             },
             Self::Call {
                 local, hover_text, ..
@@ -236,6 +949,25 @@ impl Deco<Range> {
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
+            },
+            Self::Drop {
+                local, hover_text, ..
+            } => Self::Drop {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::DeadWrite {
+                local, hover_text, ..
+            } => Self::DeadWrite {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
             },
             Self::SharedMut {
                 local, hover_text, ..
@@ -244,6 +976,7 @@ impl Deco<Range> {
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
             },
             Self::Outlive {
                 local, hover_text, ..
@@ -252,89 +985,321 @@ impl Deco<Range> {
                 range: new_range,
                 hover_text: hover_text.clone(),
                 overlapped,
+                layer: 0,
+            },
+            Self::Scope {
+                local, hover_text, ..
+            } => Self::Scope {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::Allocation {
+                local, hover_text, ..
+            } => Self::Allocation {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::Summary {
+                local, hover_text, ..
+            } => Self::Summary {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::Binding {
+                local, hover_text, ..
+            } => Self::Binding {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::HeldAcrossAwait {
+                local, hover_text, ..
+            } => Self::HeldAcrossAwait {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::SharedClone {
+                local, hover_text, ..
+            } => Self::SharedClone {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::LeakOrForget {
+                local, hover_text, ..
+            } => Self::LeakOrForget {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+            },
+            Self::Conflict {
+                local,
+                hover_text,
+                related,
+                ..
+            } => Self::Conflict {
+                local: *local,
+                range: new_range,
+                hover_text: hover_text.clone(),
+                overlapped,
+                layer: 0,
+                related: *related,
             },
         }
     }
 
     #[must_use]
-    pub fn to_lsp_range(&self, s: &str) -> Deco<lsp_types::Range> {
+    #[allow(clippy::too_many_lines, reason = "one arm per decoration kind")]
+    pub fn to_lsp_range(&self, s: &SourceIndex) -> Deco<lsp_types::Range> {
         match self.clone() {
             Self::Lifetime {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
             } => Deco::Lifetime {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
             },
             Self::ImmBorrow {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
+                implicit,
+                reborrow,
             } => Deco::ImmBorrow {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
+                implicit,
+                reborrow,
             },
             Self::MutBorrow {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
+                implicit,
+                reborrow,
             } => Deco::MutBorrow {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
+                implicit,
+                reborrow,
             },
             Self::Move {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
             } => Deco::Move {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
             },
             Self::Call {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
             } => Deco::Call {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
+            },
+            Self::Drop {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::Drop {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::DeadWrite {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::DeadWrite {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
             },
             Self::SharedMut {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
             } => Deco::SharedMut {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
             },
             Self::Outlive {
                 local,
                 range,
                 hover_text,
                 overlapped,
+                layer,
             } => Deco::Outlive {
                 local,
                 range: Self::convert_range(s, range),
                 hover_text,
                 overlapped,
+                layer,
+            },
+            Self::Scope {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::Scope {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::Allocation {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::Allocation {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::Summary {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::Summary {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::Binding {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::Binding {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::HeldAcrossAwait {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::HeldAcrossAwait {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::SharedClone {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::SharedClone {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::LeakOrForget {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+            } => Deco::LeakOrForget {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+            },
+            Self::Conflict {
+                local,
+                range,
+                hover_text,
+                overlapped,
+                layer,
+                related,
+            } => Deco::Conflict {
+                local,
+                range: Self::convert_range(s, range),
+                hover_text,
+                overlapped,
+                layer,
+                related: related.map(|related| Self::convert_range(s, related)),
             },
         }
     }
@@ -344,8 +1309,100 @@ pub struct Decorations {
     pub is_analyzed: bool,
     pub status: AnalysisStatus,
     pub path: Option<PathBuf>,
+    /// Identifies the selected variables and the analysis results of the
+    /// file, if a variable is selected, and the cursor position if the
+    /// decorations are truncated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_id: Option<u64>,
     #[serde(rename = "decorations")]
     pub items: Vec<Deco<lsp_types::Range>>,
+    /// Whether decorations were left out of `items` because there are more
+    /// than the `max_decorations` initialization option
+    pub truncated: bool,
+    /// Number of decorations of the selection, including the ones left out
+    pub total: usize,
+    /// Locals under the cursor that can be selected instead, the one selected
+    /// by default first, see [`CursorRequest::selected_index`]
+    pub candidates: Vec<Candidate>,
+    /// Functions the decorations in `items` belong to, by the `fn_key` of
+    /// their local
+    pub functions: BTreeMap<u64, FunctionInfo>,
+}
+
+/// Function of decorations, see [`Decorations::functions`]
+#[derive(serde::Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct FunctionInfo {
+    /// Path of the function, see [`crate::models::Function::fn_name`]
+    pub name: String,
+    /// Signature of the function, see [`Item::span`]
+    pub span: Option<lsp_types::Range>,
+}
+
+impl FunctionInfo {
+    /// Functions among `items` the decorations `decos` belong to, by their
+    /// [`crate::models::Function::fn_key`].
+    #[must_use]
+    pub fn of_decorations<R>(
+        items: &[&Item],
+        decos: &[Deco<R>],
+        index: &SourceIndex,
+    ) -> BTreeMap<u64, Self> {
+        let keys: HashSet<_> = decos.iter().map(|deco| deco.local().fn_key).collect();
+        let mut functions = BTreeMap::new();
+        for item in items {
+            let function = item.function();
+            if keys.contains(&function.fn_key) {
+                functions.entry(function.fn_key).or_insert_with(|| Self {
+                    name: function.fn_name.clone(),
+                    span: item.span().map(|span| Deco::convert_range(index, span)),
+                });
+            }
+        }
+        functions
+    }
+}
+
+/// Local under the cursor, see [`SelectLocal::candidates`]
+#[derive(serde::Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    pub local: FnLocal,
+    /// Name of a user variable
+    pub name: Option<String>,
+    pub ty: String,
+}
+
+/// Response to a [`CursorRequest`], without the decorations if they are those
+/// of its `previous_selection_id`
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum CursorResponse {
+    Full(Decorations),
+    Unchanged {
+        is_analyzed: bool,
+        status: AnalysisStatus,
+        path: Option<PathBuf>,
+        /// Always `true`
+        unchanged: bool,
+        selection_id: u64,
+    },
+}
+
+impl CursorResponse {
+    /// Response to a request that received the decorations of
+    /// `previous_selection_id` before.
+    #[must_use]
+    pub fn new(decorations: Decorations, previous_selection_id: Option<u64>) -> Self {
+        match decorations.selection_id {
+            Some(selection_id) if previous_selection_id == Some(selection_id) => Self::Unchanged {
+                is_analyzed: decorations.is_analyzed,
+                status: decorations.status,
+                path: decorations.path,
+                unchanged: true,
+                selection_id,
+            },
+            _ => Self::Full(decorations),
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -353,8 +1410,24 @@ pub struct Decorations {
 pub struct CursorRequest {
     pub position: lsp_types::Position,
     pub document: lsp_types::TextDocumentIdentifier,
+    /// Whether a summary is shown at the declaration of selected variables,
+    /// on by default
+    #[serde(default)]
+    pub summary: Option<bool>,
+    /// `selection_id` of the decorations the client shows, which are not
+    /// sent again if they did not change
+    #[serde(default)]
+    pub previous_selection_id: Option<u64>,
+    /// Index in the `candidates` of the response of the local to decorate
+    /// instead of the one selected by default
+    #[serde(default)]
+    pub selected_index: Option<usize>,
 }
 impl CursorRequest {
+    #[must_use]
+    pub fn summary(&self) -> bool {
+        self.summary.unwrap_or(true)
+    }
     #[must_use]
     pub fn path(&self) -> Option<PathBuf> {
         self.document.uri.to_file_path().ok()
@@ -365,6 +1438,24 @@ impl CursorRequest {
     }
 }
 
+/// Ranges where borrows of a local are live, or where the local must live
+/// for them
+#[derive(Clone, Default, Debug)]
+struct LiveBorrows {
+    shared: Vec<Range>,
+    mutable: Vec<Range>,
+    must_live_at: Vec<Range>,
+}
+
+/// Moves, borrows and drops of a local
+#[derive(Clone, Default, Debug)]
+struct Tally {
+    moves: usize,
+    shared: usize,
+    mutable: usize,
+    drops: Vec<Range>,
+}
+
 #[derive(Clone, Copy, Debug)]
 enum SelectReason {
     Var,
@@ -374,17 +1465,104 @@ enum SelectReason {
 }
 #[derive(Clone, Debug)]
 pub struct SelectLocal {
-    pos: Loc,
+    pos: CharOffset,
     candidate_local_decls: Vec<FnLocal>,
     selected: Option<(SelectReason, FnLocal, Range)>,
+    /// Signature under the cursor and the parameters of its function, used
+    /// when no local is selected
+    signature: Option<(Range, Vec<FnLocal>)>,
+    /// Whether compiler temporaries can be selected
+    show_temporaries: bool,
+    /// Temporaries and whether they are dropped at the end of their lifetime
+    temporaries: HashMap<FnLocal, bool>,
+    /// Range of the first write to each local, used to select temporaries
+    first_writes: HashMap<FnLocal, Range>,
+    /// Identifier under the cursor
+    word: Option<String>,
+    /// User variables named like the identifier under the cursor
+    named_at_cursor: HashSet<FnLocal>,
+    /// Name, if it is a user variable, and type of each local
+    decls: HashMap<FnLocal, (Option<String>, String)>,
+    /// Locals with a range containing the cursor, with the preferred one
+    hits: HashMap<FnLocal, (SelectReason, Range)>,
+    /// [`crate::models::File::types`] of the visited functions
+    type_names: Arc<[String]>,
 }
 impl SelectLocal {
     #[must_use]
-    pub const fn new(pos: Loc) -> Self {
+    pub fn new(pos: CharOffset) -> Self {
         Self {
             pos,
             candidate_local_decls: Vec::new(),
             selected: None,
+            signature: None,
+            show_temporaries: false,
+            temporaries: HashMap::new(),
+            first_writes: HashMap::new(),
+            word: None,
+            named_at_cursor: HashSet::new(),
+            decls: HashMap::new(),
+            hits: HashMap::new(),
+            type_names: Arc::from([]),
+        }
+    }
+
+    /// Look up the types of declarations in `types`, the table of the file
+    /// of the visited functions.
+    #[must_use]
+    pub fn with_types(mut self, types: &[String]) -> Self {
+        self.type_names = Arc::from(types);
+        self
+    }
+
+    /// Prefer variables named like the identifier at character `column` of
+    /// `line`, the text of the line under the cursor.
+    #[must_use]
+    pub fn with_line(mut self, line: &str, column: u32) -> Self {
+        self.word = identifier_at(line, column as usize);
+        self
+    }
+
+    /// Also select temporaries, by the first write to them.
+    #[must_use]
+    pub const fn with_temporaries(mut self, show_temporaries: bool) -> Self {
+        self.show_temporaries = show_temporaries;
+        self
+    }
+
+    fn write(&mut self, local: FnLocal, range: Range) {
+        if self.show_temporaries {
+            self.first_writes.entry(local).or_insert(range);
+        }
+    }
+
+    fn contains(&self, range: Range) -> bool {
+        range.from() <= self.pos && self.pos <= range.until()
+    }
+
+    /// Order of candidates containing the cursor, the least is selected:
+    /// variables named at the cursor first, then the narrowest range, then
+    /// the range starting closest to the cursor.
+    fn rank(&self, local: FnLocal, range: Range) -> (bool, u32, u32) {
+        (
+            !self.named_at_cursor.contains(&local),
+            range.size(),
+            u32::from(self.pos).saturating_sub(u32::from(range.from())),
+        )
+    }
+
+    /// Order in which `select` prefers candidates: variables, then moves and
+    /// borrows by [`Self::rank`], then the widest call.
+    fn preference(
+        &self,
+        reason: SelectReason,
+        local: FnLocal,
+        range: Range,
+    ) -> (u8, (bool, u32, u32)) {
+        match reason {
+            SelectReason::Var => (0, self.rank(local, range)),
+            SelectReason::Move | SelectReason::Borrow => (1, self.rank(local, range)),
+            SelectReason::Call => (2, (false, u32::MAX - range.size(), 0)),
         }
     }
 
@@ -392,17 +1570,27 @@ impl SelectLocal {
         if !self.candidate_local_decls.contains(&local) {
             return;
         }
-        if range.from() <= self.pos && self.pos <= range.until() {
-            if let Some((old_reason, _, old_range)) = self.selected {
+        if self.contains(range) {
+            let preferred = self
+                .hits
+                .get(&local)
+                .is_none_or(|&(old_reason, old_range)| {
+                    self.preference(reason, local, range)
+                        < self.preference(old_reason, local, old_range)
+                });
+            if preferred {
+                self.hits.insert(local, (reason, range));
+            }
+            if let Some((old_reason, old_local, old_range)) = self.selected {
                 match (old_reason, reason) {
                     (_, SelectReason::Var) => {
-                        if range.size() < old_range.size() {
+                        if self.rank(local, range) < self.rank(old_local, old_range) {
                             self.selected = Some((reason, local, range));
                         }
                     }
                     (SelectReason::Var, _) => {}
                     (_, SelectReason::Move | SelectReason::Borrow) => {
-                        if range.size() < old_range.size() {
+                        if self.rank(local, range) < self.rank(old_local, old_range) {
                             self.selected = Some((reason, local, range));
                         }
                     }
@@ -420,30 +1608,146 @@ impl SelectLocal {
         }
     }
 
+    /// Temporary whose first write is under the cursor.
+    ///
+    /// Temporaries that are dropped, like a `MutexGuard` kept alive until the
+    /// end of the statement, are preferred over ones that are moved out, then
+    /// the narrowest write wins.
+    fn selected_temporary(&self) -> Option<FnLocal> {
+        self.first_writes
+            .iter()
+            .filter(|(local, range)| self.temporaries.contains_key(local) && self.contains(**range))
+            .min_by_key(|(local, range)| (!self.temporaries[local], range.size(), local.id))
+            .map(|(local, _)| *local)
+    }
+
+    /// The selected local, or all parameters of the function whose signature
+    /// is under the cursor.
+    ///
+    /// With temporaries, a temporary written under the cursor is preferred
+    /// over anything but a variable name.
+    #[must_use]
+    pub fn selected(&self) -> Vec<FnLocal> {
+        if !matches!(self.selected, Some((SelectReason::Var, _, _)))
+            && let Some(temporary) = self.selected_temporary()
+        {
+            return vec![temporary];
+        }
+        match (self.selected, &self.signature) {
+            (Some((_, local, _)), _) => vec![local],
+            (None, Some((_, params))) => params.clone(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Locals with a range containing the cursor, the selected one first and
+    /// the others in the order they are preferred in.
     #[must_use]
-    pub fn selected(&self) -> Option<FnLocal> {
-        self.selected.map(|v| v.1)
+    pub fn candidates(&self) -> Vec<Candidate> {
+        let mut hits: Vec<_> = self.hits.iter().collect();
+        hits.sort_by_key(|&(&local, &(reason, range))| {
+            (
+                self.preference(reason, local, range),
+                local.fn_key,
+                local.id,
+            )
+        });
+        let selected = match self.selected().as_slice() {
+            &[local] => Some(local),
+            _ => None,
+        };
+        selected
+            .into_iter()
+            .chain(
+                hits.into_iter()
+                    .map(|(local, _)| *local)
+                    .filter(|local| Some(*local) != selected),
+            )
+            .filter_map(|local| {
+                let (name, ty) = self.decls.get(&local)?;
+                Some(Candidate {
+                    local,
+                    name: name.clone(),
+                    ty: ty.clone(),
+                })
+            })
+            .collect()
     }
 }
 impl range_ops::MirVisitor for SelectLocal {
+    fn visit_item(&mut self, item: &Item) {
+        let Some(span) = item.span().filter(|span| self.contains(*span)) else {
+            return;
+        };
+        let func = item.function();
+        if self
+            .signature
+            .as_ref()
+            .is_some_and(|(old_span, _)| old_span.size() <= span.size())
+        {
+            return;
+        }
+        let params = func
+            .decls
+            .iter()
+            .filter_map(|decl| match decl {
+                MirDecl::User { local, .. } if (1..=func.arg_count).contains(&local.id) => {
+                    Some(*local)
+                }
+                MirDecl::User { .. } | MirDecl::Other { .. } => None,
+            })
+            .collect();
+        self.signature = Some((span, params));
+    }
     fn visit_decl(&mut self, decl: &MirDecl) {
         let (local, ty) = match decl {
             MirDecl::User { local, ty, .. } | MirDecl::Other { local, ty, .. } => (local, ty),
         };
+        let ty = ty.name(&self.type_names).to_owned();
         if ASYNC_RESUME_TY.contains(&ty.as_str()) {
             return;
         }
         self.candidate_local_decls.push(*local);
-        if let MirDecl::User { local, span, .. } = decl {
-            self.select(SelectReason::Var, *local, *span);
+        let name = match decl {
+            MirDecl::User { name, .. } => Some(name.clone()),
+            MirDecl::Other { .. } => None,
+        };
+        self.decls.insert(*local, (name, ty));
+        match decl {
+            MirDecl::User {
+                local, name, span, ..
+            } => {
+                if self.word.as_ref() == Some(name) {
+                    self.named_at_cursor.insert(*local);
+                }
+                self.select(SelectReason::Var, *local, *span);
+            }
+            MirDecl::Other {
+                local,
+                drop,
+                drop_range,
+                ..
+            } if self.show_temporaries => {
+                self.temporaries
+                    .insert(*local, *drop && !drop_range.is_empty());
+            }
+            MirDecl::Other { .. } => {}
         }
     }
     fn visit_stmt(&mut self, stmt: &MirStatement) {
-        if let MirStatement::Assign { rval, .. } = stmt {
+        if let MirStatement::Assign {
+            target_local,
+            range,
+            rval,
+            ..
+        } = stmt
+        {
+            self.write(*target_local, *range);
             match rval {
                 Some(MirRval::Move {
                     target_local,
                     range,
+                    ..
                 }) => {
                     self.select(SelectReason::Move, *target_local, *range);
                 }
@@ -461,50 +1765,893 @@ impl range_ops::MirVisitor for SelectLocal {
     fn visit_term(&mut self, term: &MirTerminator) {
         if let MirTerminator::Call {
             destination_local,
-            fn_span,
+            call_expr_span,
+            range,
+            receiver,
+            ..
         } = term
         {
-            self.select(SelectReason::Call, *destination_local, *fn_span);
+            self.write(*destination_local, range.unwrap_or(*call_expr_span));
+            self.select(SelectReason::Call, *destination_local, *call_expr_span);
+            if let Some(receiver) = receiver {
+                let reason = match receiver.kind {
+                    ReceiverKind::Move => SelectReason::Move,
+                    ReceiverKind::Ref | ReceiverKind::RefMut => SelectReason::Borrow,
+                };
+                self.select(reason, receiver.local, receiver.range);
+            }
         }
     }
 }
 #[derive(Clone, Debug)]
 pub struct CalcDecos {
     locals: HashSet<FnLocal>,
+    /// Closure upvars added to `locals` because they capture a selected local
+    captured: HashSet<FnLocal>,
+    /// Description and drop range of selected locals that need dropping
+    dropped: HashMap<FnLocal, (String, Vec<Range>)>,
+    /// References of the current function to the local they borrow, from
+    /// [`crate::models::Function::borrow_edges`] and the borrows assigned to
+    /// locals
+    references: HashMap<FnLocal, FnLocal>,
+    /// Names of user variables of the current function
+    names: HashMap<FnLocal, String>,
+    /// Declaration spans of user variables of the current function
+    spans: HashMap<FnLocal, Range>,
+    /// Types of the locals of the current function
+    types: HashMap<FnLocal, String>,
+    /// Number of parameters of the current function, locals
+    /// `1..=arg_count`
+    arg_count: u32,
+    /// Moves of selected variables in the current basic block, that a call
+    /// terminating it may take as arguments
+    block_moves: Vec<(FnLocal, Range)>,
+    /// Notes at the declaration of bindings that parts of selected locals
+    /// are moved into, added after overlapping decorations are split
+    bindings: Vec<Deco>,
+    /// Await points of the current function
+    await_points: Vec<Range>,
+    /// [`crate::models::Function::clone_edges`] of the current function
+    clones: Vec<(FnLocal, FnLocal)>,
+    /// Description of selected locals owning a heap allocation
+    heap_owners: HashMap<FnLocal, String>,
+    /// Description and declaration span of selected user variables, where
+    /// their summary is shown
+    declarations: HashMap<FnLocal, (String, Range)>,
+    /// Occurrences of selected locals, counted before overlapping
+    /// decorations are split
+    tallies: HashMap<FnLocal, Tally>,
+    /// Live borrows of selected locals, that moves conflict with
+    live_borrows: HashMap<FnLocal, LiveBorrows>,
+    /// Borrows of selected locals, other than reborrows, as (local, range,
+    /// mutable)
+    borrow_creations: Vec<(FnLocal, Range, bool)>,
+    /// Text of the file without CR characters, to recognize allocating calls
+    source: Arc<str>,
+    /// Index of [`Self::source`], to slice it at ranges
+    source_index: Arc<SourceIndex>,
+    /// [`crate::models::File::types`] of the visited functions
+    type_names: Arc<[String]>,
     decorations: Vec<Deco>,
-    current_fn_id: u32,
+    current_fn_key: u64,
+    /// Whether temporaries are described by their type
+    show_temporaries: bool,
+    /// Whether the lexical scopes of variables are shown next to their
+    /// lifetimes
+    teaching_mode: bool,
+}
+/// Suffix of the hover texts of decorations at macro calls
+const MACRO_SUFFIX: &str = " (in macro expansion)";
+
+/// `text` with [`MACRO_SUFFIX`] if the decoration is `from_macro`.
+fn expanded_text(text: String, from_macro: bool) -> String {
+    if from_macro {
+        text + MACRO_SUFFIX
+    } else {
+        text
+    }
 }
+
 impl CalcDecos {
     pub fn new(locals: impl IntoIterator<Item = FnLocal>) -> Self {
         Self {
             locals: locals.into_iter().collect(),
+            captured: HashSet::new(),
+            dropped: HashMap::new(),
+            references: HashMap::new(),
+            names: HashMap::new(),
+            spans: HashMap::new(),
+            types: HashMap::new(),
+            arg_count: 0,
+            block_moves: Vec::new(),
+            bindings: Vec::new(),
+            await_points: Vec::new(),
+            clones: Vec::new(),
+            heap_owners: HashMap::new(),
+            declarations: HashMap::new(),
+            tallies: HashMap::new(),
+            live_borrows: HashMap::new(),
+            borrow_creations: Vec::new(),
+            source: Arc::from(""),
+            source_index: Arc::new(SourceIndex::new("")),
+            type_names: Arc::from([]),
             decorations: Vec::new(),
-            current_fn_id: 0,
+            current_fn_key: 0,
+            show_temporaries: false,
+            teaching_mode: false,
         }
     }
 
-    const fn get_deco_order(deco: &Deco) -> u8 {
-        match deco {
-            Deco::Lifetime { .. } => 0,
-            Deco::ImmBorrow { .. } => 1,
-            Deco::MutBorrow { .. } => 2,
-            Deco::Move { .. } => 3,
-            Deco::Call { .. } => 4,
-            Deco::SharedMut { .. } => 5,
-            Deco::Outlive { .. } => 6,
-        }
+    /// Describe selected temporaries by their type.
+    #[must_use]
+    pub const fn with_temporaries(mut self, show_temporaries: bool) -> Self {
+        self.show_temporaries = show_temporaries;
+        self
     }
 
-    fn sort_by_definition(&mut self) {
-        self.decorations.sort_by_key(Self::get_deco_order);
+    /// Mark the calls allocating the heap memory of selected locals, and
+    /// the method receivers borrowed by auto-ref, which are recognized in the
+    /// text `source` of the file.
+    #[must_use]
+    pub fn with_source(mut self, source: &str) -> Self {
+        // ranges are character offsets of the text without CR characters
+        self.source = if source.contains('\r') {
+            Arc::from(source.replace('\r', ""))
+        } else {
+            Arc::from(source)
+        };
+        self.source_index = Arc::new(SourceIndex::new(&self.source));
+        self
     }
 
-    fn process_overlap(prev: &Deco, current_range: Range) -> Option<(Deco, Vec<Deco>)> {
-        let (prev_range, prev_overlapped) = prev.range_and_overlapped();
-        if prev_overlapped {
-            return None;
-        }
-
+    /// Look up the types of declarations in `types`, the table of the file
+    /// of the visited functions.
+    #[must_use]
+    pub fn with_types(mut self, types: &[String]) -> Self {
+        self.type_names = Arc::from(types);
+        self
+    }
+
+    /// Show where selected variables are in scope but no longer live.
+    #[must_use]
+    pub const fn with_teaching_mode(mut self, teaching_mode: bool) -> Self {
+        self.teaching_mode = teaching_mode;
+        self
+    }
+
+    /// Extend the selection with closure upvars capturing a selected local.
+    ///
+    /// `captures` are (upvar, captured local) pairs as recorded on
+    /// [`crate::models::Function`]; nested closures are followed transitively.
+    #[must_use]
+    pub fn with_captures(mut self, captures: &[(FnLocal, FnLocal)]) -> Self {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (upvar, captured) in captures {
+                if self.locals.contains(captured) && self.locals.insert(*upvar) {
+                    self.captured.insert(*upvar);
+                    changed = true;
+                }
+            }
+        }
+        self
+    }
+
+    /// Visit `items` like [`range_ops::mir_visit`] does in order, each
+    /// function on a thread of its own, from a calculator that visited
+    /// nothing yet.
+    ///
+    /// Functions without a selected local are skipped. The parts of a split
+    /// function, which follow each other in [`crate::models::File::items`],
+    /// are visited together, as its drops and borrows span them.
+    pub fn visit_items(&mut self, items: &[&Item]) {
+        let fn_keys: HashSet<u64> = self.locals.iter().map(|local| local.fn_key).collect();
+        let items: Vec<_> = items
+            .iter()
+            .copied()
+            .filter(|item| fn_keys.contains(&item.function().fn_key))
+            .collect();
+        let functions: Vec<_> = items
+            .chunk_by(|a, b| a.function().fn_key == b.function().fn_key)
+            .collect();
+        let visited: Vec<_> = functions
+            .into_par_iter()
+            .map(|parts| {
+                let mut calc = self.fork();
+                for part in parts {
+                    range_ops::mir_visit(part, &mut calc);
+                }
+                calc
+            })
+            .collect();
+        for calc in visited {
+            self.merge(calc);
+        }
+    }
+
+    /// Calculator with the selection and options of this one, that visited
+    /// nothing yet
+    fn fork(&self) -> Self {
+        Self {
+            locals: self.locals.clone(),
+            captured: self.captured.clone(),
+            source: Arc::clone(&self.source),
+            source_index: Arc::clone(&self.source_index),
+            type_names: Arc::clone(&self.type_names),
+            show_temporaries: self.show_temporaries,
+            teaching_mode: self.teaching_mode,
+            ..Self::new(iter::empty())
+        }
+    }
+
+    /// Append what `other` found, after what this one found.
+    fn merge(&mut self, other: Self) {
+        self.dropped.extend(other.dropped);
+        self.bindings.extend(other.bindings);
+        self.declarations.extend(other.declarations);
+        self.live_borrows.extend(other.live_borrows);
+        self.borrow_creations.extend(other.borrow_creations);
+        self.decorations.extend(other.decorations);
+    }
+
+    fn hover_text(&self, local: FnLocal, text: &str) -> String {
+        if self.captured.contains(&local) {
+            format!("{text} (captured by closure)")
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// Whether a decoration of `kind` marks `local` at `range` already.
+    ///
+    /// Code expanded several times at the same macro call, like the clones
+    /// of `vec![x.clone(); 3]`, is marked once.
+    fn is_marked(&self, kind: DecoKind, local: FnLocal, range: Range) -> bool {
+        self.decorations
+            .iter()
+            .any(|deco| deco.deco_kind() == kind && deco.local() == local && deco.range() == range)
+    }
+
+    fn visit_move(
+        &mut self,
+        local: FnLocal,
+        range: Range,
+        moved_into: Option<FnLocal>,
+        from_macro: bool,
+    ) {
+        if !self.locals.contains(&local)
+            || from_macro && self.is_marked(DecoKind::Move, local, range)
+        {
+            return;
+        }
+        let binding = moved_into
+            .and_then(|binding| Some((self.names.get(&binding)?, *self.spans.get(&binding)?)));
+        let hover_text = if let Some((name, span)) = binding {
+            let owner = self.names.get(&local).map_or_else(
+                || "the matched value".to_owned(),
+                |owner| format!("`{owner}`"),
+            );
+            self.bindings.push(Deco::Binding {
+                local,
+                range: span,
+                hover_text: format!("binding `{name}` owns the part moved out of {owner}"),
+                overlapped: false,
+                layer: 0,
+            });
+            format!("partially moved into binding `{name}`")
+        } else {
+            if !from_macro && self.names.contains_key(&local) {
+                self.block_moves.push((local, range));
+            }
+            self.move_text(local)
+        };
+        self.decorations.push(Deco::Move {
+            local,
+            range,
+            hover_text: expanded_text(self.hover_text(local, &hover_text), from_macro),
+            overlapped: false,
+            layer: 0,
+        });
+    }
+
+    /// What was moved and why, as the type of a moved local does not
+    /// implement `Copy`.
+    fn move_text(&self, local: FnLocal) -> String {
+        let Some(ty) = self.types.get(&local) else {
+            return "variable moved".to_owned();
+        };
+        let moved = self.names.get(&local).map_or_else(
+            || format!("value of type `{ty}`"),
+            |name| format!("`{name}: {ty}`"),
+        );
+        format!("{moved} moved here (`{ty}` does not implement `Copy`)")
+    }
+
+    /// Suggest borrowing instead to the moves of the current basic block
+    /// that are arguments of the call at `call_expr_span` terminating it.
+    fn suggest_borrowed_arguments(&mut self, call_expr_span: Range) {
+        for (local, range) in mem::take(&mut self.block_moves) {
+            if !range_ops::is_super_range(call_expr_span, range) {
+                continue;
+            }
+            let Some(name) = self.names.get(&local) else {
+                continue;
+            };
+            let suggestion = format!(" — consider passing `&{name}` if the callee only reads it");
+            for deco in &mut self.decorations {
+                if let Deco::Move {
+                    local: moved,
+                    range: moved_at,
+                    hover_text,
+                    ..
+                } = deco
+                    && *moved == local
+                    && *moved_at == range
+                    && !hover_text.ends_with(&suggestion)
+                {
+                    hover_text.push_str(&suggestion);
+                }
+            }
+        }
+    }
+
+    /// Selected local that `reference` borrows, following reborrows.
+    fn borrowed_through(&self, reference: FnLocal) -> Option<FnLocal> {
+        let mut current = reference;
+        // bounded, as borrows of borrows may form cycles through loops
+        for _ in 0..self.references.len() {
+            current = *self.references.get(&current)?;
+            if self.locals.contains(&current) {
+                return Some(current);
+            }
+        }
+        None
+    }
+
+    /// Mark a borrow of `target_local`, which is a reborrow if
+    /// `through_deref`.
+    ///
+    /// Reborrows are also marked on the selected local the reborrowed
+    /// reference borrows.
+    fn visit_borrow(
+        &mut self,
+        target_local: FnLocal,
+        range: Range,
+        mutable: bool,
+        through_deref: bool,
+        from_macro: bool,
+    ) {
+        let kind = if mutable { "mutable" } else { "immutable" };
+        let (local, text) = if self.locals.contains(&target_local) {
+            if !through_deref {
+                self.borrow_creations.push((target_local, range, mutable));
+            }
+            let text = match (through_deref, self.names.get(&target_local)) {
+                (false, _) => format!("{kind} borrow"),
+                (true, Some(name)) => format!("{kind} reborrow of variable `{name}`"),
+                (true, None) => format!("{kind} reborrow"),
+            };
+            (target_local, text)
+        } else if through_deref && let Some(owner) = self.borrowed_through(target_local) {
+            let text = self.names.get(&target_local).map_or_else(
+                || format!("{kind} reborrow"),
+                |name| format!("{kind} reborrow through `{name}`"),
+            );
+            (owner, text)
+        } else {
+            return;
+        };
+        let kind = if mutable {
+            DecoKind::MutBorrow
+        } else {
+            DecoKind::ImmBorrow
+        };
+        if from_macro && self.is_marked(kind, local, range) {
+            return;
+        }
+        let hover_text = expanded_text(self.hover_text(local, &text), from_macro);
+        self.decorations.push(if mutable {
+            Deco::MutBorrow {
+                local,
+                range,
+                hover_text,
+                overlapped: false,
+                layer: 0,
+                implicit: false,
+                reborrow: through_deref,
+            }
+        } else {
+            Deco::ImmBorrow {
+                local,
+                range,
+                hover_text,
+                overlapped: false,
+                layer: 0,
+                implicit: false,
+                reborrow: through_deref,
+            }
+        });
+    }
+
+    /// Mark the receiver of a method call, replacing the decoration of the
+    /// move or borrow that prepared it.
+    ///
+    /// A receiver borrowed without a `&` in the source is borrowed by
+    /// auto-ref, for the method named at `fn_span`.
+    fn visit_receiver(&mut self, receiver: &CallReceiver, fn_span: Range) {
+        if !self.locals.contains(&receiver.local) {
+            return;
+        }
+        self.decorations.retain(|deco| match deco {
+            Deco::Move { local, range, .. }
+            | Deco::ImmBorrow { local, range, .. }
+            | Deco::MutBorrow { local, range, .. } => {
+                *local != receiver.local || *range != receiver.range
+            }
+            _ => true,
+        });
+        let (local, range) = (receiver.local, receiver.range);
+        // without the source, borrows are not told apart
+        let receiver_text = self.source_text(range);
+        let implicit =
+            !receiver_text.is_empty() && !receiver_text.trim_start_matches('(').starts_with('&');
+        let borrow_text = |kind: &str, adverb: &str| {
+            if implicit {
+                let method = self.source_text(fn_span);
+                let method = method.split("::").next().unwrap_or_default().trim();
+                format!("implicit {kind} borrow for method call `{method}`")
+            } else {
+                format!("{adverb} borrowed as method receiver")
+            }
+        };
+        self.decorations.push(match receiver.kind {
+            ReceiverKind::Move => Deco::Move {
+                local,
+                range,
+                hover_text: self.hover_text(local, "moved as method receiver"),
+                overlapped: false,
+                layer: 0,
+            },
+            ReceiverKind::Ref => Deco::ImmBorrow {
+                local,
+                range,
+                hover_text: self.hover_text(local, &borrow_text("immutable", "immutably")),
+                overlapped: false,
+                layer: 0,
+                implicit,
+                reborrow: false,
+            },
+            ReceiverKind::RefMut => Deco::MutBorrow {
+                local,
+                range,
+                hover_text: self.hover_text(local, &borrow_text("mutable", "mutably")),
+                overlapped: false,
+                layer: 0,
+                implicit,
+                reborrow: false,
+            },
+        });
+    }
+
+    /// Decorate where a local is required to live but is not, explaining the
+    /// requirements of the function signature.
+    fn push_outlive(&mut self, decl: &MirDecl, required_text: &str, lives: &[Range]) {
+        let (MirDecl::User {
+            local,
+            must_live_at,
+            must_live_from_signature,
+            ..
+        }
+        | MirDecl::Other {
+            local,
+            must_live_at,
+            must_live_from_signature,
+            ..
+        }) = decl;
+        let from_signature = range_ops::exclude_ranges(must_live_from_signature.clone(), lives);
+        let generic = range_ops::exclude_ranges(must_live_at.clone(), must_live_from_signature);
+        for range in range_ops::exclude_ranges(generic, lives) {
+            self.decorations.push(Deco::Outlive {
+                local: *local,
+                range,
+                hover_text: required_text.to_owned(),
+                overlapped: false,
+                layer: 0,
+            });
+        }
+        for range in from_signature {
+            self.decorations.push(Deco::Outlive {
+                local: *local,
+                range,
+                hover_text: "must live as long as the function's returned reference (signature \
+                             lifetime)"
+                    .to_owned(),
+                overlapped: false,
+                layer: 0,
+            });
+        }
+    }
+
+    /// Remember where borrows of a selected local are live, for
+    /// [`Self::push_conflicts`].
+    fn record_live_borrows(&mut self, decl: &MirDecl) {
+        let (MirDecl::User {
+            local,
+            shared_borrow,
+            mutable_borrow,
+            must_live_at,
+            ..
+        }
+        | MirDecl::Other {
+            local,
+            shared_borrow,
+            mutable_borrow,
+            must_live_at,
+            ..
+        }) = decl;
+        if self.locals.contains(local) {
+            self.live_borrows.insert(
+                *local,
+                LiveBorrows {
+                    shared: shared_borrow.clone(),
+                    mutable: mutable_borrow.clone(),
+                    must_live_at: must_live_at.clone(),
+                },
+            );
+        }
+    }
+
+    /// Hover texts of the lifetime of `decl` and of where it is required to
+    /// live beyond it.
+    ///
+    /// The borrow of a reference parameter is created by the caller, so it
+    /// is only used and required to live in the function.
+    fn lifetime_texts(&mut self, decl: &MirDecl, var_str: &str) -> (String, String) {
+        match decl {
+            MirDecl::User {
+                local, name, ty, ..
+            } if (1..=self.arg_count).contains(&local.id)
+                && ty.name(&self.type_names).starts_with('&') =>
+            {
+                (
+                    format!("reference parameter `{name}` is usable here"),
+                    "the referenced value must outlive this region".to_owned(),
+                )
+            }
+            MirDecl::User { .. } | MirDecl::Other { .. } => (
+                self.lifetime_text(decl, var_str),
+                format!("{var_str} is required to live here"),
+            ),
+        }
+    }
+
+    /// Hover text of the lifetime of a selected local, noting whether it owns
+    /// a heap allocation.
+    fn lifetime_text(&mut self, decl: &MirDecl, var_str: &str) -> String {
+        let (MirDecl::User { local, ty, .. } | MirDecl::Other { local, ty, .. }) = decl;
+        let heap = if owns_heap_allocation(ty.name(&self.type_names)) {
+            self.heap_owners.insert(*local, var_str.to_owned());
+            " (owns heap allocation)"
+        } else {
+            ""
+        };
+        let shared = match self.sharing_with(*local).len() - 1 {
+            0 => String::new(),
+            clones => format!(" (shared ownership — {clones} live clones in this function)"),
+        };
+        format!("lifetime of {var_str}{heap}{shared}")
+    }
+
+    /// `local` and the locals sharing its allocation through
+    /// [`Self::clones`], directly or through other clones.
+    fn sharing_with(&self, local: FnLocal) -> HashSet<FnLocal> {
+        let mut sharing = HashSet::from([local]);
+        let mut pending = vec![local];
+        while let Some(current) = pending.pop() {
+            for &(from, to) in &self.clones {
+                for (a, b) in [(from, to), (to, from)] {
+                    if a == current && sharing.insert(b) {
+                        pending.push(b);
+                    }
+                }
+            }
+        }
+        sharing
+    }
+
+    /// Mark the call of `clone` at `range` into `destination` when it shares
+    /// the allocation of a selected local.
+    fn push_shared_clone(&mut self, destination: FnLocal, range: Range) {
+        let Some(&(source, _)) = self.clones.iter().find(|(_, to)| *to == destination) else {
+            return;
+        };
+        let Some(selected) = self
+            .sharing_with(source)
+            .into_iter()
+            .filter(|local| self.locals.contains(local))
+            .min_by_key(|local| local.id)
+        else {
+            return;
+        };
+        let target = self
+            .names
+            .get(&destination)
+            .map_or_else(|| "a temporary".to_owned(), |name| format!("`{name}`"));
+        self.decorations.push(Deco::SharedClone {
+            local: selected,
+            range,
+            hover_text: format!("shared ownership: cloned into {target}"),
+            overlapped: false,
+            layer: 0,
+        });
+    }
+
+    /// Mark a call whose returned value keeps the selected locals of
+    /// `borrows_from` borrowed, at its `call_expr_span`.
+    fn push_returned_borrows(&mut self, borrows_from: &[FnLocal], call_expr_span: Range) {
+        for local in borrows_from {
+            if !self.locals.contains(local) {
+                continue;
+            }
+            let mutable = self.live_borrows.get(local).is_some_and(|borrows| {
+                borrows
+                    .mutable
+                    .iter()
+                    .any(|range| range_ops::common_range(*range, call_expr_span).is_some())
+            });
+            let hover_text = "borrowed by the value returned from this call".to_owned();
+            let deco = if mutable {
+                Deco::MutBorrow {
+                    local: *local,
+                    range: call_expr_span,
+                    hover_text,
+                    overlapped: false,
+                    layer: 0,
+                    implicit: false,
+                    reborrow: false,
+                }
+            } else {
+                Deco::ImmBorrow {
+                    local: *local,
+                    range: call_expr_span,
+                    hover_text,
+                    overlapped: false,
+                    layer: 0,
+                    implicit: false,
+                    reborrow: false,
+                }
+            };
+            self.decorations.push(deco);
+        }
+    }
+
+    /// Mark a call allocating the heap memory owned by the selected
+    /// `destination`, recognized by the text of the callee at `fn_span`.
+    fn push_allocation(&mut self, destination: FnLocal, fn_span: Range) {
+        let Some(var_str) = self.heap_owners.get(&destination) else {
+            return;
+        };
+        if is_allocating_call(self.source_text(fn_span)) {
+            self.decorations.push(Deco::Allocation {
+                local: destination,
+                range: fn_span,
+                hover_text: format!("heap allocation for {var_str}"),
+                overlapped: false,
+                layer: 0,
+            });
+        }
+    }
+
+    /// Text of the file at `range`.
+    fn source_text(&self, range: Range) -> &str {
+        let from = self.source_index.char_to_byte(range.from());
+        let until = self.source_index.char_to_byte(range.until());
+        &self.source[from..until]
+    }
+
+    /// Mark a call of a [`leaking_std_function`] at the resolved `callee`
+    /// path, or of a [`leaking_function`] by the text of the callee at
+    /// `fn_span` if it is not known, that a selected local is moved into at
+    /// `range`, and note at the end of the lifetime of the local that it is
+    /// never dropped.
+    fn push_leak(&mut self, callee: Option<&str>, fn_span: Range, range: Range) {
+        let function = callee.map_or_else(
+            || leaking_function(self.source_text(fn_span)),
+            leaking_std_function,
+        );
+        let Some(function) = function else {
+            return;
+        };
+        let Some(local) = self.decorations.iter().find_map(|deco| match deco {
+            Deco::Move {
+                local,
+                range: moved,
+                ..
+            } if self.locals.contains(local) && range_ops::is_super_range(range, *moved) => {
+                Some(*local)
+            }
+            _ => None,
+        }) else {
+            return;
+        };
+        self.decorations.push(Deco::LeakOrForget {
+            local,
+            range,
+            hover_text: format!(
+                "ownership transferred without running the destructor (`{function}`)"
+            ),
+            overlapped: false,
+            layer: 0,
+        });
+        let last_lifetime = self
+            .decorations
+            .iter_mut()
+            .filter_map(|deco| match deco {
+                Deco::Lifetime {
+                    local: lifetime_local,
+                    range,
+                    hover_text,
+                    ..
+                } if *lifetime_local == local => Some((range.until(), hover_text)),
+                _ => None,
+            })
+            .max_by_key(|(until, _)| *until);
+        if let Some((_, hover_text)) = last_lifetime
+            && !hover_text.ends_with(NEVER_DROPPED_SUFFIX)
+        {
+            hover_text.push_str(NEVER_DROPPED_SUFFIX);
+        }
+    }
+
+    /// Decorate the lexical scope of a user variable where it is not live, in
+    /// teaching mode.
+    fn push_scope(&mut self, decl: &MirDecl, var_str: &str, lives: &[Range]) {
+        if self.teaching_mode
+            && let MirDecl::User {
+                local,
+                scope: Some(scope),
+                ..
+            } = decl
+        {
+            for range in range_ops::exclude_ranges(vec![*scope], lives) {
+                self.decorations.push(Deco::Scope {
+                    local: *local,
+                    range,
+                    hover_text: format!("lexical scope of {var_str}"),
+                    overlapped: false,
+                    layer: 0,
+                });
+            }
+        }
+    }
+
+    /// Mark the await points where a borrow of a selected local, or a guard
+    /// that is a selected local, is alive.
+    ///
+    /// References to selected locals are attributed to the borrowed local.
+    fn push_held_across_await(&mut self, decl: &MirDecl) {
+        if self.await_points.is_empty() {
+            return;
+        }
+        let (MirDecl::User {
+            local,
+            ty,
+            lives,
+            drop,
+            drop_range,
+            ..
+        }
+        | MirDecl::Other {
+            local,
+            ty,
+            lives,
+            drop,
+            drop_range,
+            ..
+        }) = decl;
+        let (owner, held) = if self.locals.contains(local) {
+            let ty = ty.name(&self.type_names);
+            if *drop && is_guard(ty) {
+                (*local, drop_range)
+            } else if ty.starts_with('&') {
+                (*local, lives)
+            } else {
+                return;
+            }
+        } else if let Some(owner) = self.borrowed_through(*local) {
+            (owner, lives)
+        } else {
+            return;
+        };
+        let var_str = self.names.get(&owner).map_or_else(
+            || "anonymous variable".to_owned(),
+            |name| format!("variable `{name}`"),
+        );
+        let points: Vec<Range> = self
+            .await_points
+            .iter()
+            .copied()
+            .filter(|point| {
+                held.iter()
+                    .any(|held| held.from() <= point.from() && point.until() <= held.until())
+            })
+            .collect();
+        for range in points {
+            let emitted = self.decorations.iter().any(|deco| {
+                matches!(deco, Deco::HeldAcrossAwait { local: l, range: r, .. }
+                    if *l == owner && *r == range)
+            });
+            if !emitted {
+                self.decorations.push(Deco::HeldAcrossAwait {
+                    local: owner,
+                    range,
+                    hover_text: format!("borrow of {var_str} is held across this await"),
+                    overlapped: false,
+                    layer: 0,
+                });
+            }
+        }
+    }
+
+    /// Mark a drop of a selected local.
+    ///
+    /// Drops are scheduled for every scope exit, including unwinding paths
+    /// and paths where the value was moved out before. Only drops within the
+    /// drop range of the local are shown, once per site.
+    fn visit_drop(&mut self, local: FnLocal, range: Range, from_macro: bool) {
+        let Some((var_str, drop_range)) = self.dropped.get(&local) else {
+            return;
+        };
+        let live = drop_range
+            .iter()
+            .any(|live| live.from() <= range.from() && range.until() <= live.until());
+        let emitted = self.decorations.iter().any(|deco| {
+            matches!(deco, Deco::Drop { local: l, range: r, .. } if *l == local && *r == range)
+        });
+        if live && !emitted {
+            self.decorations.push(Deco::Drop {
+                local,
+                range,
+                hover_text: expanded_text(format!("{var_str} dropped here"), from_macro),
+                overlapped: false,
+                layer: 0,
+            });
+        }
+    }
+
+    const fn get_deco_order(deco: &Deco) -> u8 {
+        match deco {
+            Deco::Lifetime { .. } => 0,
+            Deco::ImmBorrow { .. } => 1,
+            Deco::MutBorrow { .. } => 2,
+            Deco::Move { .. } => 3,
+            Deco::Call { .. } => 4,
+            Deco::Drop { .. } => 5,
+            Deco::DeadWrite { .. } => 6,
+            Deco::SharedMut { .. } => 7,
+            Deco::Outlive { .. } => 8,
+            Deco::Scope { .. } => 9,
+            Deco::Allocation { .. } => 10,
+            Deco::Summary { .. } => 11,
+            Deco::Binding { .. } => 12,
+            Deco::HeldAcrossAwait { .. } => 13,
+            Deco::SharedClone { .. } => 14,
+            Deco::Conflict { .. } => 15,
+            Deco::LeakOrForget { .. } => 16,
+        }
+    }
+
+    fn sort_by_definition(&mut self) {
+        self.decorations.sort_by_key(Self::get_deco_order);
+    }
+
+    fn process_overlap(prev: &Deco, current_range: Range) -> Option<(Deco, Vec<Deco>)> {
+        let (prev_range, prev_overlapped) = prev.range_and_overlapped();
+        if prev_overlapped {
+            return None;
+        }
+
         range_ops::common_range(current_range, prev_range).map(|common| {
             let non_overlapping = range_ops::exclude_ranges(vec![prev_range], &[common]);
             let new_decos: Vec<_> = non_overlapping
@@ -516,7 +2663,106 @@ impl CalcDecos {
         })
     }
 
+    /// Explain why the lifetime of a selected local ends at its last move
+    /// when no drop of it follows, as the value now belongs elsewhere, like
+    /// a container returned by the function.
+    fn note_ownership_transfers(&mut self) {
+        for &local in &self.locals {
+            let last = self
+                .decorations
+                .iter()
+                .filter(|deco| {
+                    deco.local() == local && matches!(deco, Deco::Move { .. } | Deco::Drop { .. })
+                })
+                .max_by_key(|deco| (deco.range().from(), deco.range().until()));
+            let Some(Deco::Move { range: last, .. }) = last else {
+                continue;
+            };
+            let last = *last;
+            for deco in &mut self.decorations {
+                if let Deco::Move {
+                    local: moved,
+                    range,
+                    hover_text,
+                    ..
+                } = deco
+                    && *moved == local
+                    && *range == last
+                    && !hover_text.ends_with(OWNERSHIP_TRANSFERRED)
+                {
+                    hover_text.push_str(OWNERSHIP_TRANSFERRED);
+                }
+            }
+        }
+    }
+
+    /// Mark the moves of selected locals where a borrow of them is live, or
+    /// where they must live for a borrow.
+    ///
+    /// The code compiles, so such moves are near misses at the granularity of
+    /// spans, like a move in the body of an `if` whose condition uses a
+    /// borrow. The borrow is the last one of the local created before the
+    /// move.
+    fn push_conflicts(&mut self) {
+        let moves: Vec<_> = self
+            .decorations
+            .iter()
+            .filter_map(|deco| match deco {
+                Deco::Move { local, range, .. } => Some((*local, *range)),
+                _ => None,
+            })
+            .collect();
+        for (local, range) in moves {
+            let Some(live) = self.live_borrows.get(&local) else {
+                continue;
+            };
+            let conflict = [
+                (&live.mutable, Some(true), "a mutable borrow is still live"),
+                (
+                    &live.shared,
+                    Some(false),
+                    "an immutable borrow is still live",
+                ),
+                (&live.must_live_at, None, "it must still live for a borrow"),
+            ]
+            .into_iter()
+            .find_map(|(ranges, mutable, text)| {
+                ranges
+                    .iter()
+                    .find_map(|live| range_ops::common_range(*live, range))
+                    .map(|common| (common, mutable, text))
+            });
+            let Some((common, mutable, text)) = conflict else {
+                continue;
+            };
+            let related = self
+                .borrow_creations
+                .iter()
+                .filter(|(borrowed, borrow, borrow_mutable)| {
+                    *borrowed == local
+                        && borrow.until() <= range.from()
+                        && mutable.is_none_or(|mutable| mutable == *borrow_mutable)
+                })
+                .max_by_key(|(_, borrow, _)| borrow.from())
+                .map(|(_, borrow, _)| *borrow);
+            self.decorations.push(Deco::Conflict {
+                local,
+                range: common,
+                hover_text: self.hover_text(local, &format!("moved here while {text}")),
+                overlapped: false,
+                layer: 0,
+                related,
+            });
+        }
+    }
+
+    /// Mark conflicts of moves with borrows, split decorations overlapped by
+    /// later ones, then add the notes at bindings that parts of selected
+    /// locals are moved into, which share their span with the move.
     pub fn handle_overlapping(&mut self) {
+        self.note_ownership_transfers();
+        self.push_conflicts();
+        self.tally();
         self.sort_by_definition();
 
         let mut result: Vec<Deco> = Vec::with_capacity(self.decorations.len());
@@ -551,140 +2797,297 @@ impl CalcDecos {
             result.push(current);
         }
 
+        result.append(&mut self.bindings);
+        Self::assign_layers(&mut result);
         self.decorations = result;
     }
 
+    /// Put each decoration on the lowest layer free of those overlapping it.
+    ///
+    /// Decorations are in the order of their kinds, which breaks ties of
+    /// equal starts, and overlapping ones are split to share their spans, so
+    /// the layer is the depth of the decoration in the stack on its span,
+    /// from the widest underlying one up.
+    fn assign_layers(decorations: &mut [Deco]) {
+        let bounds: Vec<_> = decorations
+            .iter()
+            .map(|deco| (deco.range().from(), deco.range().until()))
+            .collect();
+        let mut layers = vec![0; decorations.len()];
+        sweep(&bounds, |i, below| {
+            layers[i] = (0..=u8::MAX)
+                .find(|layer| below.iter().all(|&j| layers[j] != *layer))
+                .unwrap_or(u8::MAX);
+        });
+        for (deco, layer) in decorations.iter_mut().zip(layers) {
+            deco.set_layer(layer);
+        }
+    }
+
+    /// Count the moves, borrows and drops of each local, once per distinct
+    /// decoration.
+    ///
+    /// Reborrows through references use an existing borrow, so they do not
+    /// count as borrows.
+    fn tally(&mut self) {
+        self.tallies.clear();
+        for (i, deco) in self.decorations.iter().enumerate() {
+            if self.decorations[..i].contains(deco) {
+                continue;
+            }
+            match deco {
+                Deco::Move { local, .. } => self.tallies.entry(*local).or_default().moves += 1,
+                Deco::ImmBorrow {
+                    local,
+                    reborrow: false,
+                    ..
+                } => {
+                    self.tallies.entry(*local).or_default().shared += 1;
+                }
+                Deco::MutBorrow {
+                    local,
+                    reborrow: false,
+                    ..
+                } => {
+                    self.tallies.entry(*local).or_default().mutable += 1;
+                }
+                Deco::Drop { local, range, .. } => {
+                    self.tallies.entry(*local).or_default().drops.push(*range);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Add a summary of the moves, borrows and drops of each selected
+    /// variable at its declaration, after [`Self::handle_overlapping`].
+    ///
+    /// Drops are located by their 1-based line in `index`.
+    pub fn add_summaries(&mut self, index: &SourceIndex) {
+        let mut declarations: Vec<_> = self.declarations.iter().collect();
+        declarations.sort_by_key(|(_, (_, span))| span.from());
+        for (local, (var_str, span)) in declarations {
+            let tally = self.tallies.get(local).cloned().unwrap_or_default();
+            let mut drop_lines: Vec<_> = tally
+                .drops
+                .iter()
+                .map(|range| index.char_to_line_col(range.from()).line + 1)
+                .collect();
+            drop_lines.sort_unstable();
+            drop_lines.dedup();
+            let dropped = match drop_lines.as_slice() {
+                [] => String::new(),
+                [line] => format!(", dropped at line {line}"),
+                lines => {
+                    let lines: Vec<_> = lines.iter().map(ToString::to_string).collect();
+                    format!(", dropped at lines {}", lines.join(", "))
+                }
+            };
+            let hover_text = format!(
+                "{var_str}: moved {}×, borrowed {}× ({} shared, {} mut){dropped}",
+                tally.moves,
+                tally.shared + tally.mutable,
+                tally.shared,
+                tally.mutable,
+            );
+            self.decorations.push(Deco::Summary {
+                local: *local,
+                range: *span,
+                hover_text,
+                overlapped: false,
+                layer: 0,
+            });
+        }
+    }
+
     #[must_use]
     pub fn decorations(self) -> Vec<Deco> {
         self.decorations
     }
 }
 impl range_ops::MirVisitor for CalcDecos {
-    fn visit_decl(&mut self, decl: &MirDecl) {
-        let (local, lives, shared_borrow, mutable_borrow, drop_range, must_live_at, name, drop) =
-            match decl {
-                MirDecl::User {
-                    local,
-                    name,
-                    lives,
-                    shared_borrow,
-                    mutable_borrow,
-                    drop_range,
-                    must_live_at,
-                    drop,
-                    ..
-                } => (
-                    *local,
-                    lives,
-                    shared_borrow,
-                    mutable_borrow,
-                    drop_range,
-                    must_live_at,
-                    Some(name),
-                    drop,
-                ),
-                MirDecl::Other {
-                    local,
-                    lives,
-                    shared_borrow,
-                    mutable_borrow,
-                    drop_range,
-                    must_live_at,
-                    drop,
+    fn visit_item(&mut self, item: &Item) {
+        let func = item.function();
+        let borrows = func
+            .basic_blocks
+            .iter()
+            .flat_map(|bb| &bb.statements)
+            .filter_map(|stmt| match stmt {
+                MirStatement::Assign {
+                    target_local,
+                    rval:
+                        Some(MirRval::Borrow {
+                            target_local: borrowed,
+                            ..
+                        }),
+                    through_ref: None,
                     ..
-                } => (
-                    *local,
-                    lives,
-                    shared_borrow,
-                    mutable_borrow,
-                    drop_range,
-                    must_live_at,
-                    None,
-                    drop,
-                ),
-            };
-        self.current_fn_id = local.fn_id;
+                } => Some((*target_local, *borrowed)),
+                _ => None,
+            });
+        self.references = func.borrow_edges.iter().copied().chain(borrows).collect();
+        self.names.clear();
+        self.spans.clear();
+        self.types.clear();
+        self.arg_count = func.arg_count;
+        self.block_moves.clear();
+        // before the declarations, which are not ordered
+        for decl in &func.decls {
+            if let MirDecl::User {
+                local, name, span, ..
+            } = decl
+            {
+                self.names.insert(*local, name.clone());
+                self.spans.insert(*local, *span);
+            }
+        }
+        self.heap_owners.clear();
+        self.await_points.clone_from(&func.await_points);
+        self.clones.clone_from(&func.clone_edges);
+    }
+    fn visit_decl(&mut self, decl: &MirDecl) {
+        self.record_live_borrows(decl);
+        let (local, lives, shared_mut, drop_range, dead_writes, name, ty, drop) = match decl {
+            MirDecl::User {
+                local,
+                name,
+                lives,
+                shared_mut,
+                drop_range,
+                dead_writes,
+                drop,
+                ..
+            } => (
+                *local,
+                lives,
+                shared_mut,
+                drop_range,
+                dead_writes,
+                Some(name),
+                None,
+                drop,
+            ),
+            MirDecl::Other {
+                local,
+                ty,
+                lives,
+                shared_mut,
+                drop_range,
+                dead_writes,
+                drop,
+                ..
+            } => (
+                *local,
+                lives,
+                shared_mut,
+                drop_range,
+                dead_writes,
+                None,
+                Some(ty),
+                drop,
+            ),
+        };
+        self.current_fn_key = local.fn_key;
+        let type_names = Arc::clone(&self.type_names);
+        let ty = ty.map(|ty| ty.name(&type_names));
+        self.types
+            .insert(local, decl.ty().name(&type_names).to_owned());
         if self.locals.contains(&local) {
-            let var_str = name.map_or_else(
-                || "anonymous variable".to_owned(),
-                |mir_var_name| format!("variable `{mir_var_name}`"),
-            );
+            let var_str = match (name, ty) {
+                (Some(mir_var_name), _) => format!("variable `{mir_var_name}`"),
+                (None, Some(ty)) if self.show_temporaries => format!("temporary of type `{ty}`"),
+                (None, _) => "anonymous variable".to_owned(),
+            };
+            if let MirDecl::User { span, .. } = decl
+                && !self.captured.contains(&local)
+            {
+                self.declarations.insert(local, (var_str.clone(), *span));
+            }
             // merge Drop object lives
             let drop_copy_live = if *drop {
                 range_ops::eliminated_ranges(drop_range.clone())
             } else {
                 range_ops::eliminated_ranges(lives.clone())
             };
+            let (lifetime_text, required_text) = self.lifetime_texts(decl, &var_str);
             for range in &drop_copy_live {
                 self.decorations.push(Deco::Lifetime {
                     local,
                     range: *range,
-                    hover_text: format!("lifetime of {var_str}"),
+                    hover_text: lifetime_text.clone(),
                     overlapped: false,
+                    layer: 0,
                 });
             }
-            let mut borrow_ranges = shared_borrow.clone();
-            borrow_ranges.extend_from_slice(mutable_borrow);
-            let shared_mut = range_ops::common_ranges(&borrow_ranges);
-            for range in shared_mut {
+            for range in shared_mut.iter().copied() {
                 self.decorations.push(Deco::SharedMut {
                     local,
                     range,
                     hover_text: format!("immutable and mutable borrows of {var_str} exist here"),
                     overlapped: false,
+                    layer: 0,
                 });
             }
-            let outlive = range_ops::exclude_ranges(must_live_at.clone(), &drop_copy_live);
-            for range in outlive {
-                self.decorations.push(Deco::Outlive {
+            for range in dead_writes.iter().copied() {
+                self.decorations.push(Deco::DeadWrite {
                     local,
                     range,
-                    hover_text: format!("{var_str} is required to live here"),
+                    hover_text: "value assigned here is never read".to_owned(),
                     overlapped: false,
+                    layer: 0,
                 });
             }
+            if *drop {
+                self.dropped
+                    .insert(local, (var_str.clone(), drop_range.clone()));
+            }
+            self.push_outlive(decl, &required_text, &drop_copy_live);
+            self.push_scope(decl, &var_str, &drop_copy_live);
         }
+        self.push_held_across_await(decl);
     }
 
     fn visit_stmt(&mut self, stmt: &MirStatement) {
+        if let MirStatement::Assign {
+            range,
+            through_ref: Some(reference),
+            from_macro,
+            ..
+        } = stmt
+            && let Some(borrowed) = self.borrowed_through(*reference)
+            && !(*from_macro && self.is_marked(DecoKind::MutBorrow, borrowed, *range))
+        {
+            let hover_text = self.names.get(reference).map_or_else(
+                || "written through `&mut` reference".to_owned(),
+                |name| format!("written through `&mut` reference `{name}`"),
+            );
+            self.decorations.push(Deco::MutBorrow {
+                local: borrowed,
+                range: *range,
+                hover_text: expanded_text(self.hover_text(borrowed, &hover_text), *from_macro),
+                overlapped: false,
+                layer: 0,
+                implicit: false,
+                reborrow: false,
+            });
+        }
         if let MirStatement::Assign { rval, .. } = stmt {
             match rval {
                 Some(MirRval::Move {
                     target_local,
                     range,
-                }) => {
-                    if self.locals.contains(target_local) {
-                        self.decorations.push(Deco::Move {
-                            local: *target_local,
-                            range: *range,
-                            hover_text: "variable moved".to_string(),
-                            overlapped: false,
-                        });
-                    }
-                }
+                    moved_into,
+                    from_macro,
+                }) => self.visit_move(*target_local, *range, *moved_into, *from_macro),
                 Some(MirRval::Borrow {
                     target_local,
                     range,
                     mutable,
+                    through_deref,
+                    from_macro,
                     ..
                 }) => {
-                    if self.locals.contains(target_local) {
-                        if *mutable {
-                            self.decorations.push(Deco::MutBorrow {
-                                local: *target_local,
-                                range: *range,
-                                hover_text: "mutable borrow".to_string(),
-                                overlapped: false,
-                            });
-                        } else {
-                            self.decorations.push(Deco::ImmBorrow {
-                                local: *target_local,
-                                range: *range,
-                                hover_text: "immutable borrow".to_string(),
-                                overlapped: false,
-                            });
-                        }
-                    }
+                    self.visit_borrow(*target_local, *range, *mutable, *through_deref, *from_macro);
                 }
                 _ => {}
             }
@@ -692,9 +3095,57 @@ impl range_ops::MirVisitor for CalcDecos {
     }
 
     fn visit_term(&mut self, term: &MirTerminator) {
+        match term {
+            MirTerminator::Call { call_expr_span, .. } => {
+                self.suggest_borrowed_arguments(*call_expr_span);
+            }
+            _ => self.block_moves.clear(),
+        }
+        if let MirTerminator::Drop {
+            local,
+            range,
+            from_macro,
+        } = term
+        {
+            self.visit_drop(*local, *range, *from_macro);
+        }
+        if let MirTerminator::Call {
+            receiver: Some(receiver),
+            fn_span,
+            ..
+        } = term
+        {
+            self.visit_receiver(receiver, *fn_span);
+        }
+        if let MirTerminator::Call {
+            destination_local,
+            fn_span,
+            callee,
+            call_expr_span,
+            range,
+            ..
+        } = term
+        {
+            self.push_allocation(*destination_local, *fn_span);
+            self.push_shared_clone(*destination_local, range.unwrap_or(*call_expr_span));
+            self.push_leak(
+                callee.as_deref(),
+                *fn_span,
+                range.unwrap_or(*call_expr_span),
+            );
+        }
+        if let MirTerminator::Call {
+            call_expr_span,
+            borrows_from,
+            ..
+        } = term
+        {
+            self.push_returned_borrows(borrows_from, *call_expr_span);
+        }
         if let MirTerminator::Call {
             destination_local,
             fn_span,
+            ..
         } = term
             && self.locals.contains(destination_local)
         {
@@ -724,9 +3175,1105 @@ impl range_ops::MirVisitor for CalcDecos {
                 range: *fn_span,
                 hover_text: "function call".to_string(),
                 overlapped: false,
+                layer: 0,
             });
         }
     }
 }
 
 // TODO: new test
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Crate, File, Function, ItemKind, MirBasicBlock};
+
+    #[test]
+    fn test_owns_heap_allocation() {
+        for ty in [
+            "std::boxed::Box<dyn Trait + Send>",
+            "Box<i32>",
+            "std::vec::Vec<u8>",
+            "std::string::String",
+            "String",
+            "std::rc::Rc<std::cell::RefCell<Vec<u8>>>",
+            "std::sync::Arc<str>",
+        ] {
+            assert!(owns_heap_allocation(ty), "{ty}");
+        }
+        for ty in [
+            "&std::boxed::Box<i32>",
+            "&mut Vec<u8>",
+            "*const String",
+            "&str",
+            "std::option::Option<std::boxed::Box<i32>>",
+            "StringBuilder",
+            "my::Boxed<u8>",
+            "[std::boxed::Box<i32>; 2]",
+            "(String, u8)",
+        ] {
+            assert!(!owns_heap_allocation(ty), "{ty}");
+        }
+    }
+
+    #[test]
+    fn test_is_allocating_call() {
+        for callee in [
+            "Box::new",
+            "Box::<i32>::new",
+            "std::vec::Vec::with_capacity",
+            "String::from",
+            "to_string",
+            "vec![1, 2, 3]",
+            "format!(\"{x}\")",
+        ] {
+            assert!(is_allocating_call(callee), "{callee}");
+        }
+        for callee in ["len", "Box::leak", "renew", "std::mem::take", "my_vec!"] {
+            assert!(!is_allocating_call(callee), "{callee}");
+        }
+    }
+
+    #[test]
+    fn test_leaking_function() {
+        for (callee, function) in [
+            ("mem::forget", "mem::forget"),
+            ("std::mem::forget", "mem::forget"),
+            ("core::mem::forget::<String>", "mem::forget"),
+            ("ManuallyDrop::new", "ManuallyDrop::new"),
+            (
+                "std::mem::ManuallyDrop::<Vec<u8>>::new",
+                "ManuallyDrop::new",
+            ),
+            ("Box::leak", "Box::leak"),
+        ] {
+            assert_eq!(leaking_function(callee), Some(function), "{callee}");
+        }
+        for callee in [
+            "forget",
+            "forget_me",
+            "my::forget",
+            "Box::new",
+            "ManuallyDrop::drop",
+            "leak",
+            "Vec::leak",
+        ] {
+            assert_eq!(leaking_function(callee), None, "{callee}");
+        }
+    }
+
+    #[test]
+    fn test_leaking_std_function() {
+        for (path, function) in [
+            ("std::mem::forget", "mem::forget"),
+            ("core::mem::forget", "mem::forget"),
+            ("std::mem::ManuallyDrop::<T>::new", "ManuallyDrop::new"),
+            ("std::boxed::Box::<T, A>::leak", "Box::leak"),
+        ] {
+            assert_eq!(leaking_std_function(path), Some(function), "{path}");
+        }
+        for path in ["forget", "my::mem::forget", "std::vec::Vec::<T, A>::leak"] {
+            assert_eq!(leaking_std_function(path), None, "{path}");
+        }
+    }
+
+    /// Function with definition index 3, as in two targets of a crate whose
+    /// `cfg(test)` items shift the indices, living in `lives`
+    fn function_with_key(fn_key: u64, lives: Range) -> Function {
+        Function {
+            fn_id: 3,
+            fn_key,
+            fn_name: String::new(),
+            arg_count: 0,
+            basic_blocks: Vec::new(),
+            decls: vec![MirDecl::User {
+                local: FnLocal::new(1, fn_key),
+                name: "x".to_owned(),
+                span: lives,
+                scope: None,
+                ty: "i32".into(),
+                lives: vec![lives],
+                shared_borrow: Vec::new(),
+                mutable_borrow: Vec::new(),
+                shared_mut: Vec::new(),
+                drop: false,
+                drop_range: Vec::new(),
+                must_live_at: Vec::new(),
+                must_live_from_signature: Vec::new(),
+                dead_writes: Vec::new(),
+            }],
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            part: None,
+            context: String::new(),
+        }
+    }
+
+    /// Item of `function`, without a signature
+    fn item(function: Function) -> Item {
+        Item::Function {
+            span: None,
+            kind: ItemKind::Fn,
+            function,
+        }
+    }
+
+    /// Function declaring the user variables `vars`, as (name, span) pairs
+    fn function_with_vars(vars: &[(&str, Range)]) -> Function {
+        let mut function = function_with_key(1, vars[0].1);
+        function.decls = vars
+            .iter()
+            .zip(1..)
+            .map(|(&(name, span), id)| MirDecl::User {
+                local: FnLocal::new(id, 1),
+                name: name.to_owned(),
+                span,
+                scope: None,
+                ty: "i32".into(),
+                lives: vec![span],
+                shared_borrow: Vec::new(),
+                mutable_borrow: Vec::new(),
+                shared_mut: Vec::new(),
+                drop: false,
+                drop_range: Vec::new(),
+                must_live_at: Vec::new(),
+                must_live_from_signature: Vec::new(),
+                dead_writes: Vec::new(),
+            })
+            .collect();
+        function
+    }
+
+    #[test]
+    fn test_identifier_at() {
+        let line = "    let (a, bb) = (1, 2);";
+        assert_eq!(identifier_at(line, 9).as_deref(), Some("a"));
+        assert_eq!(identifier_at(line, 12).as_deref(), Some("bb"));
+        // just after the identifier
+        assert_eq!(identifier_at(line, 14).as_deref(), Some("bb"));
+        assert_eq!(identifier_at(line, 16), None);
+        assert_eq!(identifier_at(line, 100), None);
+    }
+
+    #[test]
+    fn test_select_ties_broken_by_name_at_cursor() {
+        // `(a, b)` as the span of both bindings
+        let line = "let (a, b) = (make_a(), make_b());";
+        let pattern = Range::new(CharOffset::from(4u32), CharOffset::from(10u32)).unwrap();
+        let function = item(function_with_vars(&[("a", pattern), ("b", pattern)]));
+
+        let mut selected = SelectLocal::new(CharOffset::from(8u32)).with_line(line, 8);
+        range_ops::mir_visit(&function, &mut selected);
+        assert_eq!(selected.selected(), vec![FnLocal::new(2, 1)]);
+
+        let mut selected = SelectLocal::new(CharOffset::from(5u32)).with_line(line, 5);
+        range_ops::mir_visit(&function, &mut selected);
+        assert_eq!(selected.selected(), vec![FnLocal::new(1, 1)]);
+    }
+
+    #[test]
+    fn test_select_ties_broken_by_distance_to_start() {
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let function = item(function_with_vars(&[
+            ("a", range(4, 8)),
+            ("b", range(6, 10)),
+        ]));
+        let mut selected = SelectLocal::new(CharOffset::from(7u32));
+        range_ops::mir_visit(&function, &mut selected);
+        assert_eq!(selected.selected(), vec![FnLocal::new(2, 1)]);
+    }
+
+    #[test]
+    fn test_candidates_in_order_of_preference() {
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let function = function_with_vars(&[
+            ("outer", range(0, 20)),
+            ("inner", range(5, 10)),
+            ("middle", range(4, 12)),
+            ("apart", range(12, 14)),
+        ]);
+        let mut selected = SelectLocal::new(CharOffset::from(7u32));
+        range_ops::mir_visit(&item(function), &mut selected);
+        let names: Vec<_> = selected
+            .candidates()
+            .into_iter()
+            .map(|candidate| candidate.name.unwrap())
+            .collect();
+        assert_eq!(names, vec!["inner", "middle", "outer"]);
+        assert_eq!(selected.selected(), vec![FnLocal::new(2, 1)]);
+        assert_eq!(selected.candidates()[0].ty, "i32");
+    }
+
+    #[test]
+    fn test_interned_types_give_the_same_decorations() {
+        let lives = Range::new(CharOffset::from(0u32), CharOffset::from(10u32)).unwrap();
+        let mut function = function_with_key(1, lives);
+        if let MirDecl::User { ty, .. } = &mut function.decls[0] {
+            *ty = "std::string::String".into();
+        }
+        let function = item(function);
+        let decorate = |item: &Item, types: &[String]| {
+            let mut calc = CalcDecos::new([FnLocal::new(1, 1)]).with_types(types);
+            range_ops::mir_visit(item, &mut calc);
+            calc.decorations()
+        };
+        let select = |item: &Item, types: &[String]| {
+            let mut selected = SelectLocal::new(CharOffset::from(5u32)).with_types(types);
+            range_ops::mir_visit(item, &mut selected);
+            selected.candidates()
+        };
+        let named = decorate(&function, &[]);
+        assert!(
+            named
+                .iter()
+                .any(|deco| deco.hover_text().contains("owns heap allocation")),
+            "{named:?}"
+        );
+        let candidates = select(&function, &[]);
+
+        let mut file = File {
+            items: vec![function],
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        };
+        file.intern_types();
+        assert_eq!(file.types, ["std::string::String"]);
+        assert_eq!(decorate(&file.items[0], &file.types), named);
+        assert_eq!(select(&file.items[0], &file.types), candidates);
+        assert_eq!(candidates[0].ty, "std::string::String");
+    }
+
+    #[test]
+    fn test_colliding_fn_ids_keep_decorations_apart() {
+        let lib = Range::new(CharOffset::from(0u32), CharOffset::from(10u32)).unwrap();
+        let test = Range::new(CharOffset::from(20u32), CharOffset::from(30u32)).unwrap();
+        let file = |function| File {
+            items: vec![item(function)],
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        };
+        let mut krate = Crate(HashMap::from([(
+            "lib.rs".to_owned(),
+            file(function_with_key(10, lib)),
+        )]));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_owned(),
+            file(function_with_key(20, test)),
+        )])));
+        let items = &krate.0["lib.rs"].items;
+        assert_eq!(items.len(), 2, "functions of both targets are kept");
+
+        let mut calc = CalcDecos::new([FnLocal::new(1, 10)]);
+        for item in items {
+            range_ops::mir_visit(item, &mut calc);
+        }
+        let decorations = calc.decorations();
+        assert!(!decorations.is_empty());
+        for deco in &decorations {
+            assert!(deco.range().until() <= lib.until(), "{deco:?}");
+        }
+    }
+
+    #[test]
+    fn test_parallel_visit_matches_sequential() {
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let mut items = Vec::new();
+        for fn_key in 1..=12_u32 {
+            let at =
+                |offset: u32, len: u32| range(fn_key * 100 + offset, fn_key * 100 + offset + len);
+            let local = |id: u32| FnLocal::new(id, u64::from(fn_key));
+            let mut function = function_with_vars(&[("a", at(0, 90)), ("b", at(40, 50))]);
+            function.fn_key = u64::from(fn_key);
+            for decl in &mut function.decls {
+                if let MirDecl::User {
+                    local: decl_local,
+                    drop,
+                    drop_range,
+                    lives,
+                    ..
+                } = decl
+                {
+                    decl_local.fn_key = u64::from(fn_key);
+                    *drop = true;
+                    drop_range.clone_from(lives);
+                }
+            }
+            let assign = |target, rval| MirStatement::Assign {
+                target_local: local(target),
+                range: at(10, 40),
+                rval: Some(rval),
+                through_ref: None,
+                from_macro: false,
+            };
+            function.basic_blocks = (0..4)
+                .map(|block| MirBasicBlock {
+                    statements: vec![
+                        assign(
+                            3,
+                            MirRval::Borrow {
+                                target_local: local(1),
+                                range: at(10 + block, 1),
+                                mutable: block % 2 == 0,
+                                outlive: None,
+                                through_deref: false,
+                                from_macro: false,
+                            },
+                        ),
+                        assign(
+                            2,
+                            MirRval::Move {
+                                target_local: local(1),
+                                range: at(40 + block, 1),
+                                moved_into: None,
+                                from_macro: false,
+                            },
+                        ),
+                    ],
+                    terminator: Some(MirTerminator::Drop {
+                        local: local(2),
+                        range: at(89, 1),
+                        from_macro: false,
+                    }),
+                    is_cleanup: false,
+                })
+                .collect();
+            if fn_key == 5 {
+                let parts = function.split(1000);
+                assert!(parts.len() > 1, "split into {} parts", parts.len());
+                items.extend(parts.into_iter().map(item));
+            } else {
+                items.push(item(function));
+            }
+        }
+        let selected = [1, 4, 5, 9, 12].map(|fn_key| FnLocal::new(1, fn_key));
+        let selected = selected.into_iter().chain([FnLocal::new(2, 5)]);
+
+        let mut sequential = CalcDecos::new(selected.clone());
+        for item in &items {
+            range_ops::mir_visit(item, &mut sequential);
+        }
+        sequential.handle_overlapping();
+        let mut parallel = CalcDecos::new(selected);
+        parallel.visit_items(&items.iter().collect::<Vec<_>>());
+        parallel.handle_overlapping();
+
+        let sequential = sequential.decorations();
+        assert!(
+            sequential
+                .iter()
+                .any(|deco| matches!(deco, Deco::Drop { .. }))
+        );
+        assert_eq!(parallel.decorations(), sequential);
+    }
+
+    #[test]
+    fn test_clones_of_shared_pointers_are_marked() {
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let mut function = function_with_vars(&[
+            ("a", range(0, 40)),
+            ("b", range(10, 40)),
+            ("c", range(20, 40)),
+        ]);
+        let clone_call = |destination, from, until| MirBasicBlock {
+            statements: Vec::new(),
+            terminator: Some(MirTerminator::Call {
+                destination_local: FnLocal::new(destination, 1),
+                fn_span: range(from, until),
+                callee: None,
+                call_expr_span: range(from, until),
+                range: Some(range(from, until)),
+                receiver: None,
+                borrows_from: Vec::new(),
+            }),
+            is_cleanup: false,
+        };
+        function.basic_blocks = vec![clone_call(2, 10, 19), clone_call(3, 20, 29)];
+        function.clone_edges = vec![
+            (FnLocal::new(1, 1), FnLocal::new(2, 1)),
+            (FnLocal::new(2, 1), FnLocal::new(3, 1)),
+        ];
+        let mut function = item(function);
+
+        for selected in [1, 3] {
+            let mut calc = CalcDecos::new([FnLocal::new(selected, 1)]);
+            range_ops::mir_visit(&function, &mut calc);
+            let decorations = calc.decorations();
+            let clones: Vec<_> = decorations
+                .iter()
+                .filter(|deco| matches!(deco, Deco::SharedClone { .. }))
+                .map(|deco| (deco.range(), deco.hover_text()))
+                .collect();
+            assert_eq!(
+                clones,
+                vec![
+                    (range(10, 19), "shared ownership: cloned into `b`"),
+                    (range(20, 29), "shared ownership: cloned into `c`"),
+                ]
+            );
+            let lifetime = decorations
+                .iter()
+                .find(|deco| matches!(deco, Deco::Lifetime { .. }))
+                .unwrap();
+            assert!(
+                lifetime
+                    .hover_text()
+                    .ends_with("(shared ownership — 2 live clones in this function)"),
+                "{lifetime:?}"
+            );
+        }
+
+        function.function_mut().clone_edges.clear();
+        let mut calc = CalcDecos::new([FnLocal::new(1, 1)]);
+        range_ops::mir_visit(&function, &mut calc);
+        let decorations = calc.decorations();
+        assert!(
+            decorations
+                .iter()
+                .all(|deco| !matches!(deco, Deco::SharedClone { .. })
+                    && !deco.hover_text().contains("shared ownership"))
+        );
+    }
+
+    #[test]
+    fn test_reference_parameters_are_usable_where_they_live() {
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let texts = |arg_count: u32, ty: &str| {
+            let mut function = function_with_key(1, range(0, 10));
+            function.arg_count = arg_count;
+            if let MirDecl::User {
+                ty: decl_ty,
+                must_live_at,
+                ..
+            } = &mut function.decls[0]
+            {
+                *decl_ty = ty.into();
+                *must_live_at = vec![range(0, 20)];
+            }
+            let mut calc = CalcDecos::new([FnLocal::new(1, 1)]);
+            range_ops::mir_visit(&item(function), &mut calc);
+            let mut texts: Vec<_> = calc
+                .decorations
+                .iter()
+                .map(|deco| (deco.kind(), deco.hover_text().to_owned()))
+                .collect();
+            texts.sort_unstable();
+            texts
+        };
+        assert_eq!(
+            texts(1, "&str"),
+            vec![
+                (
+                    "lifetime",
+                    "reference parameter `x` is usable here".to_owned()
+                ),
+                (
+                    "outlive",
+                    "the referenced value must outlive this region".to_owned()
+                ),
+            ]
+        );
+        // a reference that is not a parameter
+        assert_eq!(
+            texts(0, "&str"),
+            vec![
+                ("lifetime", "lifetime of variable `x`".to_owned()),
+                (
+                    "outlive",
+                    "variable `x` is required to live here".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stacked_decorations_get_increasing_layers() {
+        let local = FnLocal::new(1, 1);
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let mut calc = CalcDecos::new([local]);
+        calc.decorations = vec![
+            Deco::new(
+                DecoKind::Move,
+                local,
+                range(10, 12),
+                "variable moved".to_owned(),
+            ),
+            Deco::new(
+                DecoKind::Lifetime,
+                local,
+                range(0, 40),
+                "lifetime".to_owned(),
+            ),
+            Deco::new(
+                DecoKind::ImmBorrow,
+                local,
+                range(8, 14),
+                "immutable borrow".to_owned(),
+            ),
+        ];
+        calc.handle_overlapping();
+
+        let layers = |from: u32, until: u32| {
+            let mut layers: Vec<_> = calc
+                .decorations
+                .iter()
+                .filter(|deco| range_ops::common_range(deco.range(), range(from, until)).is_some())
+                .map(|deco| (deco.kind(), deco.layer(), deco.overlapped()))
+                .collect();
+            layers.sort_unstable();
+            layers
+        };
+        assert_eq!(
+            layers(10, 12),
+            vec![
+                ("imm_borrow", 1, true),
+                ("lifetime", 0, false),
+                ("move", 2, true)
+            ]
+        );
+        assert_eq!(
+            layers(8, 10),
+            vec![("imm_borrow", 1, true), ("lifetime", 0, false)]
+        );
+        assert_eq!(layers(0, 8), vec![("lifetime", 0, false)]);
+        assert_eq!(layers(14, 40), vec![("lifetime", 0, false)]);
+    }
+
+    #[test]
+    fn test_decorations_under_higher_layers_are_covered() {
+        let local = FnLocal::new(1, 1);
+        let deco = |kind: DecoKind, line: u32, layer: u8| {
+            let position = |character| lsp_types::Position::new(line, character);
+            let mut deco = Deco::new(
+                kind,
+                local,
+                lsp_types::Range::new(position(0), position(4)),
+                String::new(),
+            );
+            deco.set_layer(layer);
+            deco
+        };
+        let decorations = [
+            deco(DecoKind::Lifetime, 1, 0),
+            deco(DecoKind::ImmBorrow, 1, 1),
+            deco(DecoKind::Move, 1, 2),
+            deco(DecoKind::Lifetime, 2, 0),
+        ];
+        assert_eq!(Deco::covered(&decorations), vec![true, true, false, false]);
+    }
+
+    #[test]
+    fn test_separate_decorations_stay_on_the_base_layer() {
+        let local = FnLocal::new(1, 1);
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let mut calc = CalcDecos::new([local]);
+        calc.decorations = vec![
+            Deco::new(
+                DecoKind::ImmBorrow,
+                local,
+                range(0, 4),
+                "immutable borrow".to_owned(),
+            ),
+            Deco::new(
+                DecoKind::Move,
+                local,
+                range(4, 8),
+                "variable moved".to_owned(),
+            ),
+        ];
+        calc.handle_overlapping();
+
+        assert!(
+            calc.decorations
+                .iter()
+                .all(|deco| deco.layer() == 0 && !deco.overlapped()),
+            "{:?}",
+            calc.decorations
+        );
+    }
+
+    #[test]
+    fn test_moves_while_borrowed_are_conflicts() {
+        let local = FnLocal::new(1, 1);
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let mut calc = CalcDecos::new([local]);
+        calc.live_borrows.insert(
+            local,
+            LiveBorrows {
+                shared: vec![range(10, 20)],
+                mutable: Vec::new(),
+                must_live_at: vec![range(10, 26)],
+            },
+        );
+        calc.borrow_creations = vec![
+            (local, range(2, 4), false),
+            (local, range(5, 7), true),
+            (local, range(30, 32), false),
+        ];
+        calc.decorations = vec![
+            Deco::new(
+                DecoKind::Move,
+                local,
+                range(12, 13),
+                "variable moved".to_owned(),
+            ),
+            Deco::new(
+                DecoKind::Move,
+                local,
+                range(24, 25),
+                "variable moved".to_owned(),
+            ),
+            Deco::new(
+                DecoKind::Move,
+                local,
+                range(40, 41),
+                "variable moved".to_owned(),
+            ),
+        ];
+        calc.handle_overlapping();
+
+        let conflicts: Vec<_> = calc
+            .decorations
+            .iter()
+            .filter_map(|deco| match deco {
+                Deco::Conflict {
+                    range,
+                    hover_text,
+                    related,
+                    ..
+                } => Some((*range, hover_text.as_str(), *related)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            conflicts,
+            vec![
+                (
+                    range(12, 13),
+                    "moved here while an immutable borrow is still live",
+                    Some(range(2, 4))
+                ),
+                (
+                    range(24, 25),
+                    "moved here while it must still live for a borrow",
+                    Some(range(5, 7))
+                ),
+            ]
+        );
+        let layer = |kind: &str| {
+            calc.decorations
+                .iter()
+                .find(|deco| deco.kind() == kind && deco.range() == range(12, 13))
+                .map(Deco::layer)
+        };
+        assert!(
+            layer("move") < layer("conflict"),
+            "the conflict is drawn over the move"
+        );
+
+        let index = SourceIndex::new(&" ".repeat(50));
+        let uri = lsp_types::Url::parse("file:///src/lib.rs").unwrap();
+        let conflict = calc
+            .decorations
+            .iter()
+            .find(|deco| deco.kind() == "conflict")
+            .unwrap()
+            .to_lsp_range(&index);
+        let related = conflict.to_diagnostic(&uri).related_information.unwrap();
+        assert_eq!(related[0].location.uri, uri);
+        assert_eq!(related[0].location.range.start.character, 2);
+        assert_eq!(related[0].message, "borrow created here");
+    }
+
+    #[test]
+    fn test_leaks_of_selected_locals_are_marked() {
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        // `s` is moved into the call at 12..13 of `mem::forget(s)`
+        let leaking_call = |callee: Option<&str>, callee_end: u32| MirBasicBlock {
+            statements: vec![MirStatement::Assign {
+                target_local: FnLocal::new(2, 1),
+                range: range(callee_end + 1, callee_end + 2),
+                rval: Some(MirRval::Move {
+                    target_local: FnLocal::new(1, 1),
+                    range: range(callee_end + 1, callee_end + 2),
+                    moved_into: None,
+                    from_macro: false,
+                }),
+                through_ref: None,
+                from_macro: false,
+            }],
+            terminator: Some(MirTerminator::Call {
+                destination_local: FnLocal::new(3, 1),
+                fn_span: range(0, callee_end),
+                callee: callee.map(str::to_owned),
+                call_expr_span: range(0, callee_end + 3),
+                range: Some(range(0, callee_end + 3)),
+                receiver: None,
+                borrows_from: Vec::new(),
+            }),
+            is_cleanup: false,
+        };
+        let decorations_of = |source: &str, callee: Option<&str>| {
+            let callee_end = u32::try_from(source.find('(').unwrap()).unwrap();
+            let mut function = function_with_vars(&[("s", range(0, callee_end + 3))]);
+            function.basic_blocks = vec![leaking_call(callee, callee_end)];
+            let mut calc = CalcDecos::new([FnLocal::new(1, 1)]).with_source(source);
+            range_ops::mir_visit(&item(function), &mut calc);
+            calc.decorations()
+        };
+        let decorations = |source: &str| decorations_of(source, None);
+        let lifetime_text = |decorations: &[Deco]| {
+            decorations
+                .iter()
+                .find(|deco| matches!(deco, Deco::Lifetime { .. }))
+                .map(|deco| deco.hover_text().to_owned())
+                .unwrap()
+        };
+
+        let forgotten = decorations("mem::forget(s)");
+        let leaks: Vec<_> = forgotten
+            .iter()
+            .filter(|deco| matches!(deco, Deco::LeakOrForget { .. }))
+            .map(|deco| (deco.range(), deco.hover_text()))
+            .collect();
+        assert_eq!(
+            leaks,
+            vec![(
+                range(0, 14),
+                "ownership transferred without running the destructor (`mem::forget`)"
+            )]
+        );
+        assert!(
+            lifetime_text(&forgotten).ends_with(NEVER_DROPPED_SUFFIX),
+            "{forgotten:?}"
+        );
+
+        let moved = decorations("forget_me(s)");
+        assert!(
+            moved
+                .iter()
+                .all(|deco| !matches!(deco, Deco::LeakOrForget { .. })),
+            "{moved:?}"
+        );
+        assert!(!lifetime_text(&moved).ends_with(NEVER_DROPPED_SUFFIX));
+
+        // A `forget` of the user is not `mem::forget`, whether its path is
+        // resolved or not
+        for callee in [Some("forget"), None] {
+            let shadowed = decorations_of("forget(s)", callee);
+            assert!(
+                shadowed
+                    .iter()
+                    .all(|deco| !matches!(deco, Deco::LeakOrForget { .. })),
+                "{callee:?}: {shadowed:?}"
+            );
+        }
+        let imported = decorations_of("forget(s)", Some("std::mem::forget"));
+        assert!(
+            imported
+                .iter()
+                .any(|deco| matches!(deco, Deco::LeakOrForget { .. })),
+            "{imported:?}"
+        );
+    }
+
+    #[test]
+    fn test_summary_counts_decorations_before_overlapping() {
+        let local = FnLocal::new(1, 1);
+        let range = |from: u32, until: u32| {
+            Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+        };
+        let deco = |kind: &str, range: Range, hover_text: &str| {
+            Deco::new(kind.parse().unwrap(), local, range, hover_text.to_owned())
+        };
+        let mut calc = CalcDecos::new([local]);
+        calc.declarations
+            .insert(local, ("variable `s`".to_owned(), range(4, 5)));
+        calc.decorations = vec![
+            // the call covers the moved argument, hiding the move
+            deco("move", range(10, 11), "variable moved"),
+            deco("call", range(8, 12), "function call"),
+            deco("imm_borrow", range(14, 16), "immutable borrow"),
+            deco("imm_borrow", range(14, 16), "immutable borrow"),
+            // counted by its flag, whatever the names in its text
+            deco("mut_borrow", range(18, 20), "mutable borrow of `reborrow`"),
+            Deco::ImmBorrow {
+                local,
+                range: range(22, 24),
+                hover_text: "immutable reborrow through `r`".to_owned(),
+                overlapped: false,
+                layer: 0,
+                implicit: false,
+                reborrow: true,
+            },
+            deco("drop", range(26, 27), "variable `s` dropped here"),
+        ];
+        calc.handle_overlapping();
+        calc.add_summaries(&SourceIndex::new(&format!(
+            "{}\n{}",
+            " ".repeat(25),
+            " ".repeat(5)
+        )));
+
+        let summaries: Vec<_> = calc
+            .decorations()
+            .into_iter()
+            .filter(|deco| deco.kind() == "summary")
+            .collect();
+        assert_eq!(
+            summaries,
+            vec![Deco::Summary {
+                local,
+                range: range(4, 5),
+                hover_text: "variable `s`: moved 1×, borrowed 2× (1 shared, 1 mut), dropped at \
+                             line 2"
+                    .to_owned(),
+                overlapped: false,
+                layer: 0,
+            }]
+        );
+    }
+
+    /// One decoration of every type, in declaration order.
+    fn every_kind() -> Vec<Deco<lsp_types::Range>> {
+        macro_rules! decos {
+            ($($kind:ident),*) => {
+                vec![$(Deco::new(
+                    DecoKind::$kind,
+                    FnLocal::new(1, 2),
+                    lsp_types::Range::default(),
+                    String::new(),
+                )),*]
+            };
+        }
+        let mut decorations = decos![
+            Lifetime,
+            ImmBorrow,
+            MutBorrow,
+            Move,
+            Call,
+            Drop,
+            DeadWrite,
+            SharedMut,
+            Outlive,
+            Scope,
+            Allocation,
+            Summary,
+            Binding,
+            HeldAcrossAwait,
+            SharedClone
+        ];
+        decorations.push(Deco::Conflict {
+            local: FnLocal::new(1, 2),
+            range: lsp_types::Range::default(),
+            hover_text: String::new(),
+            overlapped: false,
+            layer: 0,
+            related: None,
+        });
+        decorations.extend(decos![LeakOrForget]);
+        decorations
+    }
+
+    #[test]
+    fn test_diagnostic_severity_per_kind() {
+        use lsp_types::DiagnosticSeverity as S;
+        let severities: Vec<_> = every_kind()
+            .iter()
+            .map(|deco| (deco.kind(), deco.diagnostic_severity()))
+            .collect();
+        assert_eq!(
+            severities,
+            vec![
+                ("lifetime", S::HINT),
+                ("imm_borrow", S::HINT),
+                ("mut_borrow", S::INFORMATION),
+                ("move", S::WARNING),
+                ("call", S::INFORMATION),
+                ("drop", S::HINT),
+                ("dead_write", S::WARNING),
+                ("shared_mut", S::WARNING),
+                ("outlive", S::ERROR),
+                ("scope", S::HINT),
+                ("allocation", S::HINT),
+                ("summary", S::HINT),
+                ("binding", S::INFORMATION),
+                ("held_across_await", S::WARNING),
+                ("shared_clone", S::HINT),
+                ("conflict", S::WARNING),
+                ("leak_or_forget", S::WARNING),
+            ]
+        );
+        let hidden: Vec<_> = every_kind()
+            .into_iter()
+            .filter(|deco| !deco.should_show_as_diagnostic())
+            .map(|deco| deco.kind())
+            .collect();
+        assert_eq!(hidden, vec!["lifetime"]);
+    }
+
+    #[test]
+    fn test_every_variant_round_trips_through_its_kind() {
+        let uri = lsp_types::Url::parse("file:///src/lib.rs").unwrap();
+        let decorations = every_kind();
+        let kinds: Vec<_> = decorations.iter().map(Deco::deco_kind).collect();
+        assert_eq!(kinds, DecoKind::ALL);
+        for deco in decorations {
+            let kind = deco.deco_kind();
+            assert_eq!(
+                Deco::new(kind, deco.local(), deco.lsp_range(), String::new()),
+                deco
+            );
+            assert_eq!(
+                serde_json::to_value(&deco).unwrap()["type"],
+                kind.type_name(),
+                "the `type` field names the kind"
+            );
+            assert_eq!(
+                deco.to_diagnostic(&uri).code,
+                Some(lsp_types::NumberOrString::String(format!(
+                    "ferrous-owl:{}",
+                    kind.name()
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn test_decorations_response_shape() {
+        let response = Decorations {
+            is_analyzed: true,
+            status: AnalysisStatus::Finished,
+            path: Some(PathBuf::from("src/lib.rs")),
+            selection_id: None,
+            items: every_kind().into_iter().skip(3).take(1).collect(),
+            truncated: false,
+            total: 1,
+            candidates: vec![Candidate {
+                local: FnLocal::new(1, 2),
+                name: Some("s".to_owned()),
+                ty: "std::string::String".to_owned(),
+            }],
+            functions: BTreeMap::from([(
+                2,
+                FunctionInfo {
+                    name: "test".to_owned(),
+                    span: None,
+                },
+            )]),
+        };
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({
+                "is_analyzed": true,
+                "status": "finished",
+                "path": "src/lib.rs",
+                "decorations": [{
+                    "type": "move",
+                    "local": serde_json::to_value(FnLocal::new(1, 2)).unwrap(),
+                    "range": {
+                        "start": { "line": 0, "character": 0 },
+                        "end": { "line": 0, "character": 0 }
+                    },
+                    "hover_text": "",
+                    "overlapped": false,
+                    "layer": 0
+                }],
+                "truncated": false,
+                "total": 1,
+                "candidates": [{
+                    "local": serde_json::to_value(FnLocal::new(1, 2)).unwrap(),
+                    "name": "s",
+                    "ty": "std::string::String"
+                }],
+                "functions": { "2": { "name": "test", "span": null } }
+            })
+        );
+    }
+
+    #[test]
+    fn test_functions_of_decorations() {
+        let signature = Range::new(CharOffset::from(0u32), CharOffset::from(8u32)).unwrap();
+        let mut first = function_with_key(1, signature);
+        first.fn_name = "first".to_owned();
+        let first = Item::Function {
+            span: Some(signature),
+            kind: ItemKind::Fn,
+            function: first,
+        };
+        let mut second = function_with_key(2, signature);
+        second.fn_name = "second".to_owned();
+        let second = item(second);
+        let decos = [Deco::new(
+            DecoKind::Move,
+            FnLocal::new(1, 1),
+            signature,
+            String::new(),
+        )];
+        let functions = FunctionInfo::of_decorations(
+            &[&first, &second],
+            &decos,
+            &SourceIndex::new("fn first() {}"),
+        );
+        assert_eq!(functions.keys().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!(functions[&1].name, "first");
+        assert_eq!(
+            functions[&1].span.map(|span| span.end),
+            Some(lsp_types::Position::new(0, 8))
+        );
+    }
+
+    #[test]
+    fn test_unchanged_cursor_response_shape() {
+        let decorations = Decorations {
+            is_analyzed: true,
+            status: AnalysisStatus::Finished,
+            path: None,
+            selection_id: Some(7),
+            items: every_kind(),
+            truncated: false,
+            total: every_kind().len(),
+            candidates: Vec::new(),
+            functions: BTreeMap::new(),
+        };
+        let full = serde_json::to_value(CursorResponse::new(decorations.clone(), Some(6))).unwrap();
+        assert_eq!(full["selection_id"], 7);
+        assert_eq!(
+            full["decorations"].as_array().unwrap().len(),
+            every_kind().len()
+        );
+        assert!(full.get("unchanged").is_none());
+
+        assert_eq!(
+            serde_json::to_value(CursorResponse::new(decorations, Some(7))).unwrap(),
+            serde_json::json!({
+                "is_analyzed": true,
+                "status": "finished",
+                "path": null,
+                "unchanged": true,
+                "selection_id": 7
+            })
+        );
+    }
+}
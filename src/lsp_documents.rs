@@ -0,0 +1,318 @@
+//! Texts of the documents open in the editor, which differ from the files on
+//! disk while they have unsaved changes.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use tower_lsp::lsp_types;
+
+use crate::text_conversion::SourceIndex;
+
+/// Unit the `character` of positions counts, as negotiated in `initialize`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    /// The encoding of clients that announce none
+    #[default]
+    Utf16,
+    /// Characters, like the columns of [`crate::text_conversion::SourceIndex`]
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Encodings in the order the server prefers them
+    const PREFERRED: [Self; 3] = [Self::Utf32, Self::Utf16, Self::Utf8];
+
+    /// Encoding the server picks among the ones `offered` by the client.
+    pub fn negotiate(offered: Option<&[lsp_types::PositionEncodingKind]>) -> Self {
+        let offered = offered.unwrap_or_default();
+        Self::PREFERRED
+            .into_iter()
+            .find(|encoding| offered.contains(&encoding.kind()))
+            .unwrap_or_default()
+    }
+
+    pub const fn kind(self) -> lsp_types::PositionEncodingKind {
+        match self {
+            Self::Utf8 => lsp_types::PositionEncodingKind::UTF8,
+            Self::Utf16 => lsp_types::PositionEncodingKind::UTF16,
+            Self::Utf32 => lsp_types::PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Code units of `c` in this encoding
+    const fn units(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+
+    /// Code units in this encoding of a character of `len` bytes in UTF-8
+    pub const fn units_of_utf8(self, len: u32) -> u32 {
+        match self {
+            Self::Utf8 => len,
+            // only characters outside the basic plane take 4 bytes
+            Self::Utf16 if len == 4 => 2,
+            Self::Utf16 | Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Texts of the open documents, by path
+#[derive(Debug, Default)]
+pub struct Documents {
+    encoding: PositionEncoding,
+    texts: HashMap<PathBuf, String>,
+}
+
+impl Documents {
+    /// Count the characters of positions in edits from now on in `encoding`.
+    pub const fn set_encoding(&mut self, encoding: PositionEncoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn open(&mut self, path: PathBuf, text: String) {
+        self.texts.insert(path, text);
+    }
+
+    /// Forget the text of `path`, which is on disk again from now on.
+    pub fn close(&mut self, path: &Path) {
+        self.texts.remove(path);
+    }
+
+    /// Apply `changes` in order to the text of `path`, returning whether the
+    /// document is open.
+    pub fn change(
+        &mut self,
+        path: &Path,
+        changes: Vec<lsp_types::TextDocumentContentChangeEvent>,
+    ) -> bool {
+        let Some(text) = self.texts.get_mut(path) else {
+            return false;
+        };
+        for change in changes {
+            apply_change(text, change, self.encoding);
+        }
+        true
+    }
+
+    /// Text of `path` if it is open.
+    pub fn text(&self, path: &Path) -> Option<&str> {
+        self.texts.get(path).map(String::as_str)
+    }
+
+    /// Index of `text`, the text of a document, whose LSP positions count
+    /// characters in the negotiated encoding.
+    pub fn index(&self, text: &str) -> SourceIndex {
+        SourceIndex::new(text).with_encoding(self.encoding)
+    }
+}
+
+/// Replace the range of `change` in `text` by its text, or all of `text`
+/// if it has no range.
+fn apply_change(
+    text: &mut String,
+    change: lsp_types::TextDocumentContentChangeEvent,
+    encoding: PositionEncoding,
+) {
+    let Some(range) = change.range else {
+        *text = change.text;
+        return;
+    };
+    let start = byte_offset(text, range.start, encoding);
+    let end = byte_offset(text, range.end, encoding).max(start);
+    text.replace_range(start..end, &change.text);
+}
+
+/// Byte offset of `position` in `text`.
+///
+/// Lines end in `\n`, `\r\n` or `\r`. As required by the protocol, a
+/// character past the end of its line stands for the end of the line and a
+/// line past the end of the text for the end of the text. A character in the
+/// middle of a code point stands for its start.
+fn byte_offset(text: &str, position: lsp_types::Position, encoding: PositionEncoding) -> usize {
+    let Some(start) = line_start(text, position.line) else {
+        return text.len();
+    };
+    let mut units = 0;
+    for (offset, c) in text[start..].char_indices() {
+        units += encoding.units(c);
+        if c == '\n' || c == '\r' || units > position.character as usize {
+            return start + offset;
+        }
+    }
+    text.len()
+}
+
+/// Byte offset of the start of `line` in `text`, if it has that many lines.
+fn line_start(text: &str, line: u32) -> Option<usize> {
+    if line == 0 {
+        return Some(0);
+    }
+    let bytes = text.as_bytes();
+    let mut lines = 0;
+    for (offset, byte) in bytes.iter().enumerate() {
+        let ends_line = match byte {
+            b'\n' => true,
+            b'\r' => bytes.get(offset + 1) != Some(&b'\n'),
+            _ => false,
+        };
+        if ends_line {
+            lines += 1;
+            if lines == line {
+                return Some(offset + 1);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: u32, character: u32) -> lsp_types::Position {
+        lsp_types::Position::new(line, character)
+    }
+
+    fn edit(
+        start: (u32, u32),
+        end: (u32, u32),
+        text: &str,
+    ) -> lsp_types::TextDocumentContentChangeEvent {
+        lsp_types::TextDocumentContentChangeEvent {
+            range: Some(lsp_types::Range::new(
+                position(start.0, start.1),
+                position(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    /// The example of the protocol: `b` of `a𐐀b` is at character 5 in UTF-8,
+    /// 3 in UTF-16 and 2 in UTF-32.
+    #[test]
+    fn test_position_encodings() {
+        let text = "a𐐀b";
+        let b = text.find('b').unwrap();
+        assert_eq!(byte_offset(text, position(0, 5), PositionEncoding::Utf8), b);
+        assert_eq!(
+            byte_offset(text, position(0, 3), PositionEncoding::Utf16),
+            b
+        );
+        assert_eq!(
+            byte_offset(text, position(0, 2), PositionEncoding::Utf32),
+            b
+        );
+    }
+
+    #[test]
+    fn test_positions_inside_a_code_point_stand_for_its_start() {
+        let text = "a𐐀b";
+        assert_eq!(
+            byte_offset(text, position(0, 2), PositionEncoding::Utf16),
+            1
+        );
+        assert_eq!(byte_offset(text, position(0, 3), PositionEncoding::Utf8), 1);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let text = "a\r\nb\rc\nd";
+        for (line, expected) in [(0, 'a'), (1, 'b'), (2, 'c'), (3, 'd')] {
+            let offset = byte_offset(text, position(line, 0), PositionEncoding::Utf16);
+            assert_eq!(text[offset..].chars().next(), Some(expected), "line {line}");
+        }
+    }
+
+    #[test]
+    fn test_positions_past_the_end() {
+        let text = "ab\r\ncd";
+        assert_eq!(
+            byte_offset(text, position(0, 10), PositionEncoding::Utf16),
+            2,
+            "the end of the line, before its line break"
+        );
+        assert_eq!(
+            byte_offset(text, position(1, 10), PositionEncoding::Utf16),
+            6
+        );
+        assert_eq!(
+            byte_offset(text, position(7, 0), PositionEncoding::Utf16),
+            6
+        );
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let path = Path::new("/src/lib.rs");
+        let mut documents = Documents::default();
+        assert!(!documents.change(path, vec![edit((0, 0), (0, 0), "x")]));
+
+        documents.open(
+            path.to_path_buf(),
+            "fn main() {\n    let s = \"😀\";\n}\n".to_owned(),
+        );
+        assert!(documents.change(
+            path,
+            vec![
+                // insert a line
+                edit((0, 0), (0, 0), "// unsaved\n"),
+                // replace the emoji, two UTF-16 code units
+                edit((2, 13), (2, 15), "ü"),
+                // delete across lines
+                edit((2, 19), (3, 1), ""),
+                // append past the end
+                edit((9, 0), (9, 0), "}\n"),
+            ]
+        ));
+        assert_eq!(
+            documents.text(path),
+            Some("// unsaved\nfn main() {\n    let s = \"ü\";\n}\n")
+        );
+
+        let full = lsp_types::TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "fn f() {}".to_owned(),
+        };
+        documents.change(path, vec![full, edit((0, 3), (0, 4), "g")]);
+        assert_eq!(documents.text(path), Some("fn g() {}"));
+
+        documents.close(path);
+        assert_eq!(documents.text(path), None);
+    }
+
+    #[test]
+    fn test_changes_in_the_negotiated_encoding() {
+        let path = Path::new("/src/lib.rs");
+        let mut documents = Documents::default();
+        documents.set_encoding(PositionEncoding::Utf32);
+        documents.open(path.to_path_buf(), "a𐐀b".to_owned());
+        documents.change(path, vec![edit((0, 1), (0, 2), "")]);
+        assert_eq!(documents.text(path), Some("ab"));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        use lsp_types::PositionEncodingKind as Kind;
+        assert_eq!(PositionEncoding::negotiate(None), PositionEncoding::Utf16);
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[Kind::UTF8])),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[Kind::UTF8, Kind::UTF16, Kind::UTF32])),
+            PositionEncoding::Utf32
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[Kind::new("utf-7")])),
+            PositionEncoding::Utf16
+        );
+    }
+}
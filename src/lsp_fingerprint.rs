@@ -0,0 +1,242 @@
+//! Reuse of analysis results for workspaces whose sources did not change.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lsp_workspace::TargetKind,
+    mir_algorithm::AlgorithmChoice,
+    mir_cache::{self, CacheHeader},
+    models::Workspace,
+};
+
+/// File the results of the last analysis are stored in, under the owl target
+/// directory
+const RESULTS_FILE: &str = "results.json";
+
+/// Fingerprint of a set of files, independent of the order they are listed
+/// in.
+///
+/// `files` are pairs of (path relative to the package root, contents).
+#[must_use]
+pub fn fingerprint(mut files: Vec<(PathBuf, Vec<u8>)>) -> String {
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    mir_cache::hash_parts(
+        files.iter().flat_map(|(path, contents)| {
+            [path.as_os_str().as_encoded_bytes(), contents.as_slice()]
+        }),
+    )
+}
+
+/// Paths of the Rust sources and manifests under `root`.
+///
+/// Build output and hidden directories are skipped.
+#[must_use]
+pub fn source_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            if path.is_dir() {
+                if name != "target" && !name.to_string_lossy().starts_with('.') {
+                    dirs.push(path);
+                }
+            } else if name == "Cargo.toml" || path.extension().is_some_and(|ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Rust sources of the package in `root`, with its manifest.
+fn package_files(root: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    source_files(root)
+        .into_iter()
+        .filter_map(|path| {
+            let contents = fs::read(&path).ok()?;
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            Some((relative, contents))
+        })
+        .collect()
+}
+
+/// Fingerprint of the sources of every local package, by package name, and
+/// of the lock file.
+///
+/// Local packages are the members of the workspace and the path dependencies,
+/// which may be outside of the workspace, whereas other dependencies are
+/// pinned by the lock file.
+#[must_use]
+pub fn workspace_fingerprints(metadata: &cargo_metadata::Metadata) -> BTreeMap<String, String> {
+    let mut fingerprints: BTreeMap<_, _> = metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none())
+        .filter_map(|package| {
+            let root = package.manifest_path.parent()?.as_std_path();
+            Some((package.name.to_string(), fingerprint(package_files(root))))
+        })
+        .collect();
+    let lock_file = metadata.workspace_root.as_std_path().join("Cargo.lock");
+    if let Ok(contents) = fs::read(lock_file) {
+        fingerprints.insert(
+            "Cargo.lock".to_owned(),
+            fingerprint(vec![(PathBuf::from("Cargo.lock"), contents)]),
+        );
+    }
+    fingerprints
+}
+
+/// Analysis results together with what they were computed from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredResults {
+    /// Versions of ferrous-owl, the compiler and the models the results were
+    /// computed with
+    pub header: CacheHeader,
+    pub fingerprints: BTreeMap<String, String>,
+    /// Selected target kinds, see [`TargetKind::selection`]
+    #[serde(default)]
+    pub target_kinds: BTreeSet<TargetKind>,
+    pub all_features: bool,
+    #[serde(default)]
+    pub polonius_algo: AlgorithmChoice,
+    pub results: Vec<Workspace>,
+}
+
+impl StoredResults {
+    /// Stored results in `target_dir` that are still valid for the
+    /// fingerprints and options of `current`, whose results are ignored.
+    #[must_use]
+    pub fn load(target_dir: &Path, current: &Self) -> Option<Vec<Workspace>> {
+        let contents = fs::read_to_string(target_dir.join(RESULTS_FILE)).ok()?;
+        let stored: Self = serde_json::from_str(&contents).ok()?;
+        (stored.header == current.header
+            && stored.fingerprints == current.fingerprints
+            && stored.target_kinds == current.target_kinds
+            && stored.all_features == current.all_features
+            && stored.polonius_algo == current.polonius_algo
+            && !stored.results.is_empty())
+        .then_some(stored.results)
+    }
+
+    pub fn store(&self, target_dir: &Path) {
+        let written = fs::create_dir_all(target_dir).and_then(|()| {
+            fs::write(
+                target_dir.join(RESULTS_FILE),
+                serde_json::to_string(self).unwrap(),
+            )
+        });
+        if let Err(e) = written {
+            log::warn!("failed to store analysis results: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn file(path: &str, contents: &str) -> (PathBuf, Vec<u8>) {
+        (PathBuf::from(path), contents.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_file_order() {
+        let forward = fingerprint(vec![
+            file("src/lib.rs", "pub mod a;"),
+            file("src/a.rs", "pub fn a() {}"),
+        ]);
+        let backward = fingerprint(vec![
+            file("src/a.rs", "pub fn a() {}"),
+            file("src/lib.rs", "pub mod a;"),
+        ]);
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_contents_and_paths() {
+        let base = fingerprint(vec![file("src/lib.rs", "fn a() {}")]);
+        assert_ne!(base, fingerprint(vec![file("src/lib.rs", "fn b() {}")]));
+        assert_ne!(base, fingerprint(vec![file("src/main.rs", "fn a() {}")]));
+        // contents must not be confused with the next path
+        assert_ne!(
+            fingerprint(vec![file("a", "bc"), file("d", "")]),
+            fingerprint(vec![file("a", "b"), file("cd", "")]),
+        );
+    }
+
+    #[test]
+    fn test_package_fingerprint_invalidated_by_source_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]").unwrap();
+        fs::write(root.join("src/lib.rs"), "pub mod a;").unwrap();
+        fs::write(root.join("src/a.rs"), "pub fn a() {}").unwrap();
+        let before = fingerprint(package_files(root));
+
+        fs::write(root.join("target/generated.rs"), "fn ignored() {}").unwrap();
+        fs::write(root.join("README.md"), "ignored").unwrap();
+        assert_eq!(before, fingerprint(package_files(root)));
+
+        fs::write(root.join("src/a.rs"), "pub fn b() {}").unwrap();
+        assert_ne!(before, fingerprint(package_files(root)));
+    }
+
+    #[test]
+    fn test_stored_results_only_valid_for_same_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = StoredResults {
+            header: CacheHeader::current(),
+            fingerprints: BTreeMap::from([("krate".to_owned(), "1".to_owned())]),
+            target_kinds: TargetKind::ALL.into(),
+            all_features: false,
+            polonius_algo: AlgorithmChoice::DatafrogOpt,
+            results: Vec::new(),
+        };
+        StoredResults {
+            results: vec![Workspace::new(HashMap::new())],
+            ..current.clone()
+        }
+        .store(dir.path());
+
+        assert!(StoredResults::load(dir.path(), &current).is_some());
+        let other_targets = StoredResults {
+            target_kinds: TargetKind::DEFAULT.into(),
+            ..current.clone()
+        };
+        assert!(StoredResults::load(dir.path(), &other_targets).is_none());
+        let other_algorithm = StoredResults {
+            polonius_algo: AlgorithmChoice::Naive,
+            ..current.clone()
+        };
+        assert!(StoredResults::load(dir.path(), &other_algorithm).is_none());
+        let changed = StoredResults {
+            fingerprints: BTreeMap::from([("krate".to_owned(), "2".to_owned())]),
+            ..current.clone()
+        };
+        assert!(StoredResults::load(dir.path(), &changed).is_none());
+
+        // results of another version of ferrous-owl
+        let path = dir.path().join(RESULTS_FILE);
+        let mut stored: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        stored["header"]["owl_version"] = "0.0.0".into();
+        fs::write(&path, stored.to_string()).unwrap();
+        assert!(StoredResults::load(dir.path(), &current).is_none());
+    }
+}
@@ -0,0 +1,305 @@
+//! Framing of LSP messages: header lines of `Name: value`, ended by an empty
+//! line, then a JSON body of the length given by the `Content-Length` header.
+//!
+//! Reading is lenient: header names are case-insensitive, other headers are
+//! ignored and lines may end in `\r\n` or `\n`. A malformed frame is an
+//! [`ErrorKind::InvalidData`] error, after which the next read skips to the
+//! next `Content-Length` header, so that one bad frame does not desynchronize
+//! the rest of the stream.
+
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+
+use serde_json::Value;
+
+/// Largest body read by [`read_message`], in bytes
+pub const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Bytes of a line kept while skipping to the next header, enough for a
+/// `Content-Length` header with any length
+const MAX_LINE_LENGTH: usize = 256;
+
+const CONTENT_LENGTH: &str = "content-length";
+
+/// Read the next message, with a body of at most [`MAX_CONTENT_LENGTH`]
+/// bytes.
+///
+/// # Errors
+///
+/// [`ErrorKind::UnexpectedEof`] at the end of the stream,
+/// [`ErrorKind::InvalidData`] for a malformed frame, or the error of the
+/// reader.
+pub fn read_message(reader: impl BufRead) -> Result<Value> {
+    read_message_with_limit(reader, MAX_CONTENT_LENGTH)
+}
+
+/// Read the next message, with a body of at most `max_content_length` bytes.
+///
+/// Bytes before the first header line are skipped. A frame with a larger
+/// body is rejected without reading its body.
+///
+/// # Errors
+///
+/// See [`read_message`].
+pub fn read_message_with_limit(
+    mut reader: impl BufRead,
+    max_content_length: usize,
+) -> Result<Value> {
+    let mut line = Vec::new();
+    let mut content_length = None;
+    let mut in_header = false;
+    loop {
+        if !read_line(&mut reader, &mut line)? {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "end of the message stream",
+            ));
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        if in_header {
+            if text.is_empty() {
+                break;
+            }
+            let (name, value) =
+                parse_header(text).ok_or_else(|| invalid(format!("malformed header `{text}`")))?;
+            if name.eq_ignore_ascii_case(CONTENT_LENGTH) {
+                content_length = Some(parse_length(value)?);
+            }
+        } else if let Some((name, value)) = find_header(text) {
+            in_header = true;
+            if name.eq_ignore_ascii_case(CONTENT_LENGTH) {
+                content_length = Some(parse_length(value)?);
+            }
+        }
+    }
+    let content_length =
+        content_length.ok_or_else(|| invalid("header without a `Content-Length`".to_owned()))?;
+    if content_length > max_content_length {
+        return Err(invalid(format!(
+            "body of {content_length} bytes is longer than the limit of {max_content_length}"
+        )));
+    }
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content).map_err(|error| Error::new(ErrorKind::InvalidData, error))
+}
+
+/// Write `message` in a frame with only a `Content-Length` header, and flush
+/// the writer.
+///
+/// # Errors
+///
+/// The error of the writer.
+pub fn write_message(mut writer: impl Write, message: &Value) -> Result<()> {
+    let content = serde_json::to_string(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n", content.len())?;
+    writer.write_all(content.as_bytes())?;
+    writer.flush()
+}
+
+/// Read up to the next `\n` into `line`, keeping only its last
+/// [`MAX_LINE_LENGTH`] bytes, or return `false` at the end of the stream.
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> Result<bool> {
+    line.clear();
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        if available.is_empty() {
+            return Ok(!line.is_empty());
+        }
+        let (used, done) = available
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or((available.len(), false), |end| (end + 1, true));
+        line.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        if line.len() > MAX_LINE_LENGTH {
+            line.drain(..line.len() - MAX_LINE_LENGTH);
+        }
+        if done {
+            return Ok(true);
+        }
+    }
+}
+
+/// Header of `line`, from its last `Content-Length` if it has one, as after
+/// the body of a frame without a trailing newline.
+fn find_header(line: &str) -> Option<(&str, &str)> {
+    let start = line
+        .to_ascii_lowercase()
+        .rfind(CONTENT_LENGTH)
+        .unwrap_or_default();
+    parse_header(&line[start..])
+}
+
+/// Name and value of a header line, whose name is a token of letters, digits
+/// and dashes.
+fn parse_header(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.split_once(':')?;
+    let is_token = !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+    is_token.then(|| (name, value.trim()))
+}
+
+fn parse_length(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|error| invalid(format!("invalid `Content-Length` `{value}`: {error}")))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufReader, Cursor},
+        result,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn frame(message: &Value) -> Vec<u8> {
+        let mut frame = Vec::new();
+        write_message(&mut frame, message).unwrap();
+        frame
+    }
+
+    /// Results of reading `stream` to its end, as the messages or the kinds
+    /// of errors.
+    fn read_all(stream: &[u8], max_content_length: usize) -> Vec<result::Result<Value, ErrorKind>> {
+        let mut reader = Cursor::new(stream);
+        let mut results = Vec::new();
+        loop {
+            match read_message_with_limit(&mut reader, max_content_length) {
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => return results,
+                result => results.push(result.map_err(|error| error.kind())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = json!({ "jsonrpc": "2.0", "id": 1, "result": "ü" });
+        let framed = frame(&message);
+        let header = format!("Content-Length: {}\r\n\r\n", message.to_string().len());
+        assert!(framed.starts_with(header.as_bytes()));
+        assert_eq!(read_all(&framed, MAX_CONTENT_LENGTH), vec![Ok(message)]);
+    }
+
+    #[test]
+    fn test_several_headers() {
+        let stream = b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n\
+                       Content-Length: 2\r\n\r\n{}\
+                       content-length: 4\r\nContent-Type: x\r\n\r\n[1] ";
+        assert_eq!(
+            read_all(stream, MAX_CONTENT_LENGTH),
+            vec![Ok(json!({})), Ok(json!([1]))]
+        );
+    }
+
+    #[test]
+    fn test_lf_line_endings() {
+        let stream = b"Content-Length: 2\n\n{}Content-Length: 4\r\n\n[1] ";
+        assert_eq!(
+            read_all(stream, MAX_CONTENT_LENGTH),
+            vec![Ok(json!({})), Ok(json!([1]))]
+        );
+    }
+
+    #[test]
+    fn test_resynchronizes_after_malformed_frames() {
+        let mut stream = b"garbage\r\nContent-Length: x\r\n\r\n{}".to_vec();
+        stream.extend(b"Content-Length: 3\r\n\r\n{}}");
+        stream.extend(b"Content-Length: 20\r\nnot a header\r\n\r\n");
+        stream.extend(b"Content-Type: x\r\n\r\n");
+        stream.extend(frame(&json!(1)));
+        assert_eq!(
+            read_all(&stream, MAX_CONTENT_LENGTH),
+            vec![
+                Err(ErrorKind::InvalidData),
+                Err(ErrorKind::InvalidData),
+                Err(ErrorKind::InvalidData),
+                Err(ErrorKind::InvalidData),
+                Ok(json!(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_bodies_over_the_limit() {
+        let mut stream = b"Content-Length: 18446744073709551615\r\n\r\n{\"a\": 1}".to_vec();
+        stream.extend(frame(&json!([1, 2])));
+        stream.extend(frame(&json!("long enough")));
+        assert_eq!(
+            read_all(&stream, 8),
+            vec![
+                Err(ErrorKind::InvalidData),
+                Ok(json!([1, 2])),
+                Err(ErrorKind::InvalidData),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let framed = frame(&json!({ "id": 1 }));
+        let results = read_all(&framed[..framed.len() - 1], MAX_CONTENT_LENGTH);
+        assert!(results.is_empty(), "{results:?}");
+    }
+
+    #[test]
+    fn test_long_garbage_before_a_frame() {
+        let mut stream = vec![b'x'; 10 * MAX_LINE_LENGTH];
+        stream.extend(frame(&json!(true)));
+        let reader = BufReader::with_capacity(7, Cursor::new(stream));
+        assert_eq!(read_message(reader).unwrap(), json!(true));
+    }
+
+    /// Frames mutated by a xorshift with a fixed seed are read to the end of
+    /// the stream without panicking, and no message is longer than the limit.
+    #[test]
+    fn test_mutated_frames() {
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": { "diagnostics": [] } }),
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null }),
+        ];
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            usize::try_from(seed % u64::try_from(bound).unwrap()).unwrap()
+        };
+        for _ in 0..2000 {
+            let mut stream: Vec<u8> = messages.iter().flat_map(frame).collect();
+            for _ in 0..=random(4) {
+                if stream.is_empty() {
+                    break;
+                }
+                let at = random(stream.len());
+                match random(4) {
+                    0 => stream[at] = u8::try_from(random(256)).unwrap(),
+                    1 => {
+                        stream.remove(at);
+                    }
+                    2 => stream.insert(at, b"\r\n:0123456789"[random(13)]),
+                    _ => stream.truncate(at),
+                }
+            }
+            let max_content_length = 100;
+            for message in read_all(&stream, max_content_length).into_iter().flatten() {
+                assert!(message.to_string().len() <= max_content_length, "{message}");
+            }
+        }
+    }
+}
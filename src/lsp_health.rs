@@ -0,0 +1,85 @@
+use std::{env, time::Duration};
+
+use tokio::time;
+
+/// Environment variable overriding [`DEFAULT_HEALTH_INTERVAL`], in seconds
+pub const HEALTH_INTERVAL_ENV: &str = "FERROUS_OWL_HEALTH_INTERVAL_SECS";
+
+/// Time between two checks whether the client process is alive
+const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval of the client checks from [`HEALTH_INTERVAL_ENV`], else the
+/// default.
+pub fn health_interval() -> Duration {
+    env::var(HEALTH_INTERVAL_ENV)
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .filter(|&secs| secs > 0)
+        .map_or(DEFAULT_HEALTH_INTERVAL, Duration::from_secs)
+}
+
+/// Whether the client of the language server is still running
+pub trait ClientLiveness: Send + 'static {
+    fn is_alive(&mut self) -> bool;
+}
+
+/// Client process with the `process_id` of the `initialize` request
+pub struct ClientProcess(pub u32);
+
+impl ClientLiveness for ClientProcess {
+    fn is_alive(&mut self) -> bool {
+        process_alive::state(process_alive::Pid::from(self.0)).is_alive()
+    }
+}
+
+/// Check `client` every `interval` and return once it is no longer alive.
+pub async fn wait_for_client_exit(mut client: impl ClientLiveness, interval: Duration) {
+    loop {
+        time::sleep(interval).await;
+        if !client.is_alive() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    /// Client that dies after a number of checks
+    struct DiesAfter(u32);
+
+    impl ClientLiveness for DiesAfter {
+        fn is_alive(&mut self) -> bool {
+            self.0 = self.0.saturating_sub(1);
+            self.0 > 0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_returns_once_client_dies() {
+        let checked = time::timeout(
+            Duration::from_secs(5),
+            wait_for_client_exit(DiesAfter(3), Duration::from_millis(1)),
+        )
+        .await;
+        assert!(checked.is_ok(), "dead client not detected");
+    }
+
+    #[tokio::test]
+    async fn test_waits_while_client_lives() {
+        let checked = time::timeout(
+            Duration::from_millis(50),
+            wait_for_client_exit(DiesAfter(u32::MAX), Duration::from_millis(1)),
+        )
+        .await;
+        assert!(checked.is_err(), "live client reported dead");
+    }
+
+    #[test]
+    fn test_current_process_is_alive() {
+        assert!(ClientProcess(process::id()).is_alive());
+    }
+}
@@ -0,0 +1,228 @@
+//! Lines excluded from decoration by markers in comments.
+//!
+//! - `// ferrous-owl:ignore-next-line` ignores the line after the comment.
+//! - `// ferrous-owl:ignore-start` and `// ferrous-owl:ignore-end` ignore the
+//!   lines between and including them. Pairs nest, an unclosed start ignores
+//!   the rest of the file and an unmatched end is skipped.
+//! - `ferrous-owl:ignore` in the doc comment of a function ignores the lines
+//!   from the marker to the end of the function.
+//!
+//! Lines are scanned heuristically without parsing: a comment is the text
+//! after the first `//` of a line, so markers inside string literals count
+//! too, and the end of an ignored function is found by counting braces
+//! outside of comments, including those in string and char literals.
+
+use tower_lsp::lsp_types;
+
+const IGNORE_NEXT_LINE: &str = "ferrous-owl:ignore-next-line";
+const IGNORE_START: &str = "ferrous-owl:ignore-start";
+const IGNORE_END: &str = "ferrous-owl:ignore-end";
+const IGNORE: &str = "ferrous-owl:ignore";
+
+/// Ignored regions of a file, as inclusive ranges of 0-based lines
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct IgnoredLines(Vec<(u32, u32)>);
+
+/// Comment of `line`, with whether it is a doc comment.
+fn comment(line: &str) -> Option<(&str, bool)> {
+    let text = &line[line.find("//")? + 2..];
+    Some(
+        text.strip_prefix('/')
+            .map_or((text, false), |doc| (doc, true)),
+    )
+}
+
+fn has_marker(comment: &str, marker: &str) -> bool {
+    comment
+        .split(|c: char| !c.is_alphanumeric() && !matches!(c, '-' | ':' | '_'))
+        .any(|word| word == marker)
+}
+
+/// Code of `line` before its comment.
+fn code(line: &str) -> &str {
+    line.find("//").map_or(line, |start| &line[..start])
+}
+
+/// Index of the line closing the function declared after the doc comment on
+/// line `doc`, or the last line if it is not closed.
+fn function_end(lines: &[&str], doc: usize) -> usize {
+    let Some(signature) = (doc + 1..lines.len()).find(|&index| {
+        code(lines[index])
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .any(|word| word == "fn")
+    }) else {
+        return doc;
+    };
+    let mut depth = 0_usize;
+    let mut opened = false;
+    for (index, line) in lines.iter().enumerate().skip(signature) {
+        for c in code(line).chars() {
+            match c {
+                '{' => {
+                    depth += 1;
+                    opened = true;
+                }
+                '}' => depth = depth.saturating_sub(1),
+                // a declaration without body
+                ';' if !opened => return index,
+                _ => {}
+            }
+            if opened && depth == 0 {
+                return index;
+            }
+        }
+    }
+    lines.len() - 1
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    reason = "line indices of source files fit in u32"
+)]
+const fn line(index: usize) -> u32 {
+    index as u32
+}
+
+impl IgnoredLines {
+    /// Ignored lines of the source `text`.
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut ranges = Vec::new();
+        // first line of the outermost open start marker, with the depth
+        let mut open: Option<(usize, usize)> = None;
+        for (index, text) in lines.iter().enumerate() {
+            let Some((comment, doc)) = comment(text) else {
+                continue;
+            };
+            if doc {
+                if has_marker(comment, IGNORE) {
+                    ranges.push((line(index), line(function_end(&lines, index))));
+                }
+                continue;
+            }
+            if has_marker(comment, IGNORE_NEXT_LINE) && index + 1 < lines.len() {
+                ranges.push((line(index + 1), line(index + 1)));
+            }
+            if has_marker(comment, IGNORE_START) {
+                open = Some(open.map_or((index, 1), |(start, depth)| (start, depth + 1)));
+            }
+            if has_marker(comment, IGNORE_END) {
+                open = match open {
+                    Some((start, 1)) => {
+                        ranges.push((line(start), line(index)));
+                        None
+                    }
+                    Some((start, depth)) => Some((start, depth - 1)),
+                    None => None,
+                };
+            }
+        }
+        if let Some((start, _)) = open {
+            ranges.push((line(start), line(lines.len() - 1)));
+        }
+        ranges.sort_unstable();
+        Self(ranges)
+    }
+
+    /// Whether the 0-based `line` is ignored.
+    #[must_use]
+    pub fn contains(&self, line: u32) -> bool {
+        self.0
+            .iter()
+            .any(|&(start, end)| start <= line && line <= end)
+    }
+
+    /// Whether any line of `range` is ignored.
+    #[must_use]
+    pub fn intersects(&self, range: lsp_types::Range) -> bool {
+        self.0
+            .iter()
+            .any(|&(start, end)| start <= range.end.line && range.start.line <= end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(text: &str) -> Vec<u32> {
+        let ignored = IgnoredLines::parse(text);
+        (0..line(text.lines().count()))
+            .filter(|&line| ignored.contains(line))
+            .collect()
+    }
+
+    #[test]
+    fn test_ignore_next_line() {
+        let text = "let a = 1;\n// ferrous-owl:ignore-next-line\nlet b = a;\nlet c = b;";
+        assert_eq!(ignored(text), vec![2]);
+        assert_eq!(IgnoredLines::parse("let a = 1;"), IgnoredLines::default());
+        // at the end of the file there is no next line
+        assert_eq!(
+            IgnoredLines::parse("// ferrous-owl:ignore-next-line"),
+            IgnoredLines::default()
+        );
+    }
+
+    #[test]
+    fn test_ignore_blocks_nest() {
+        let text = "\
+a
+// ferrous-owl:ignore-start
+b
+// ferrous-owl:ignore-start
+c
+// ferrous-owl:ignore-end
+d
+// ferrous-owl:ignore-end
+e
+// ferrous-owl:ignore-end
+f";
+        assert_eq!(ignored(text), vec![1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_unclosed_start_ignores_rest() {
+        let text = "a\nb // ferrous-owl:ignore-start\nc\nd";
+        assert_eq!(ignored(text), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_ignore_function_by_doc_comment() {
+        let text = "\
+fn kept() {}
+/// Generated.
+/// ferrous-owl:ignore
+#[inline]
+pub fn generated(x: u8) -> u8 {
+    if x > 0 { x } else { 0 }
+}
+fn also_kept() {}";
+        assert_eq!(ignored(text), vec![2, 3, 4, 5, 6]);
+        // other markers are not the function marker
+        assert_eq!(
+            IgnoredLines::parse("/// ferrous-owl:ignore-start\nfn f() {}"),
+            IgnoredLines::default()
+        );
+    }
+
+    #[test]
+    fn test_markers_in_strings_count() {
+        let text = "let s = \"// ferrous-owl:ignore-next-line\";\nlet t = s;";
+        assert_eq!(ignored(text), vec![1]);
+    }
+
+    #[test]
+    fn test_intersects() {
+        let ignored = IgnoredLines::parse("a\n// ferrous-owl:ignore-next-line\nc\nd");
+        let range = |start, end| lsp_types::Range {
+            start: lsp_types::Position::new(start, 4),
+            end: lsp_types::Position::new(end, 0),
+        };
+        assert!(ignored.intersects(range(2, 2)));
+        assert!(ignored.intersects(range(0, 3)));
+        assert!(!ignored.intersects(range(3, 3)));
+        assert!(!ignored.intersects(range(0, 1)));
+    }
+}
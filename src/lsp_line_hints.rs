@@ -0,0 +1,152 @@
+//! Decorations summarized per line, reported by the `ferrous-owl/lineHints`
+//! request for editors showing virtual text at the end of lines.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::Serialize;
+use tower_lsp::lsp_types;
+
+use crate::{lsp_decoration::Deco, lsp_progress::AnalysisStatus};
+
+/// Decorations of one type on a line
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct LineHint {
+    /// Type of the decorations, as in their `type` field
+    pub kind: &'static str,
+    pub count: usize,
+    /// See [`Deco::label`]
+    pub short_label: &'static str,
+}
+
+/// Hints of a 0-based line, in the order their types first appear
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct LineHints {
+    pub line: u32,
+    pub hints: Vec<LineHint>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LineHintsResponse {
+    pub is_analyzed: bool,
+    pub status: AnalysisStatus,
+    pub path: Option<PathBuf>,
+    pub lines: Vec<LineHints>,
+}
+
+impl LineHintsResponse {
+    /// Summary of `decorations` per line, ascending by line.
+    ///
+    /// A decoration counts on the line it starts on. Decorations covered by
+    /// one of a higher layer are hidden, so they are not counted, see
+    /// [`Deco::covered`].
+    #[must_use]
+    pub fn new(
+        is_analyzed: bool,
+        status: AnalysisStatus,
+        path: Option<PathBuf>,
+        decorations: &[Deco<lsp_types::Range>],
+    ) -> Self {
+        let mut lines: BTreeMap<u32, Vec<LineHint>> = BTreeMap::new();
+        let covered = Deco::covered(decorations);
+        for (deco, _) in decorations
+            .iter()
+            .zip(covered)
+            .filter(|(_, covered)| !covered)
+        {
+            let hints = lines.entry(deco.lsp_range().start.line).or_default();
+            if let Some(hint) = hints.iter_mut().find(|hint| hint.kind == deco.kind()) {
+                hint.count += 1;
+            } else {
+                hints.push(LineHint {
+                    kind: deco.kind(),
+                    count: 1,
+                    short_label: deco.label(),
+                });
+            }
+        }
+        Self {
+            is_analyzed,
+            status,
+            path,
+            lines: lines
+                .into_iter()
+                .map(|(line, hints)| LineHints { line, hints })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FnLocal;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> lsp_types::Range {
+        lsp_types::Range {
+            start: lsp_types::Position::new(start.0, start.1),
+            end: lsp_types::Position::new(end.0, end.1),
+        }
+    }
+
+    fn deco(kind: &str, start: (u32, u32), end: (u32, u32)) -> Deco<lsp_types::Range> {
+        let local = FnLocal::new(1, 1);
+        let range = range(start, end);
+        Deco::new(kind.parse().unwrap(), local, range, String::new())
+    }
+
+    fn hint(kind: &'static str, count: usize, short_label: &'static str) -> LineHint {
+        LineHint {
+            kind,
+            count,
+            short_label,
+        }
+    }
+
+    #[test]
+    fn test_hints_collapse_per_line() {
+        let decorations = [
+            deco("move", (3, 4), (3, 5)),
+            deco("imm_borrow", (3, 8), (3, 10)),
+            deco("move", (3, 12), (3, 13)),
+            deco("move", (5, 0), (5, 1)),
+        ];
+        let response = LineHintsResponse::new(true, AnalysisStatus::Finished, None, &decorations);
+        assert_eq!(
+            response.lines,
+            vec![
+                LineHints {
+                    line: 3,
+                    hints: vec![hint("move", 2, "←move"), hint("imm_borrow", 1, "&")],
+                },
+                LineHints {
+                    line: 5,
+                    hints: vec![hint("move", 1, "←move")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiline_hint_counts_on_start_line() {
+        let hidden = deco("imm_borrow", (6, 0), (6, 3));
+        let mut covering = deco("move", (6, 0), (6, 3));
+        if let Deco::Move { layer, .. } = &mut covering {
+            *layer = 1;
+        }
+        let decorations = [deco("lifetime", (1, 8), (4, 1)), hidden, covering];
+        let response = LineHintsResponse::new(true, AnalysisStatus::Finished, None, &decorations);
+        assert_eq!(
+            response.lines,
+            vec![
+                LineHints {
+                    line: 1,
+                    hints: vec![hint("lifetime", 1, "live")],
+                },
+                LineHints {
+                    line: 6,
+                    hints: vec![hint("move", 1, "←move")],
+                },
+            ]
+        );
+    }
+}
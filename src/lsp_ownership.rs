@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tower_lsp::lsp_types;
+
+/// Time cursor positions of a document are collected for before its
+/// ownership diagnostics are published, so that only the last one is
+/// computed
+pub const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Tracks whether ownership diagnostics are enabled for each document
+#[derive(Default, Clone)]
+pub struct OwnershipState {
+    /// Map from file path to (enabled, `cursor_position`)
+    pub enabled_files: HashMap<PathBuf, (bool, Option<lsp_types::Position>)>,
+    /// Hash of the diagnostics last published for each file
+    published: HashMap<PathBuf, u64>,
+}
+
+impl OwnershipState {
+    /// Cursor position of `path` if its ownership diagnostics are enabled.
+    pub fn enabled_position(&self, path: &Path) -> Option<lsp_types::Position> {
+        self.enabled_files
+            .get(path)
+            .and_then(|(enabled, position)| position.filter(|_| *enabled))
+    }
+
+    /// Files with ownership diagnostics enabled, with their cursor position.
+    pub fn enabled(&self) -> Vec<(PathBuf, lsp_types::Position)> {
+        self.enabled_files
+            .keys()
+            .filter_map(|path| Some((path.clone(), self.enabled_position(path)?)))
+            .collect()
+    }
+
+    /// Remember `diagnostics` as the last published ones of `path`, returning
+    /// whether they differ from the ones published before.
+    pub fn record_published(&mut self, path: &Path, diagnostics: &[lsp_types::Diagnostic]) -> bool {
+        let hash = diagnostics_hash(diagnostics);
+        self.published.insert(path.to_path_buf(), hash) != Some(hash)
+    }
+
+    /// Forget the diagnostics published for `path`, once they are cleared.
+    pub fn forget_published(&mut self, path: &Path) {
+        self.published.remove(path);
+    }
+}
+
+/// `position` on the last line of `text` if the text has no line at it
+/// anymore, as after lines were deleted since it was stored.
+pub fn clamp_to_text(position: lsp_types::Position, text: &str) -> lsp_types::Position {
+    let last_line = u32::try_from(text.lines().count().saturating_sub(1)).unwrap_or(u32::MAX);
+    if position.line > last_line {
+        lsp_types::Position::new(last_line, position.character)
+    } else {
+        position
+    }
+}
+
+/// Hash of the serialized `diagnostics`.
+fn diagnostics_hash(diagnostics: &[lsp_types::Diagnostic]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(message: &str) -> lsp_types::Diagnostic {
+        lsp_types::Diagnostic {
+            message: message.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_identical_diagnostics_are_not_published_again() {
+        let mut state = OwnershipState::default();
+        let path = Path::new("/src/lib.rs");
+        let moved = [diagnostic("variable moved")];
+        assert!(state.record_published(path, &moved));
+        assert!(!state.record_published(path, &moved));
+        assert!(state.record_published(path, &[diagnostic("immutable borrow")]));
+        assert!(
+            state.record_published(Path::new("/src/other.rs"), &moved),
+            "hashes are kept per file"
+        );
+
+        state.forget_published(path);
+        assert!(
+            state.record_published(path, &[diagnostic("immutable borrow")]),
+            "cleared diagnostics are published again"
+        );
+    }
+
+    #[test]
+    fn test_enabled_position() {
+        let mut state = OwnershipState::default();
+        let path = PathBuf::from("/src/lib.rs");
+        let position = lsp_types::Position::new(1, 8);
+        assert_eq!(state.enabled_position(&path), None);
+        state
+            .enabled_files
+            .insert(path.clone(), (true, Some(position)));
+        assert_eq!(state.enabled_position(&path), Some(position));
+        assert_eq!(state.enabled(), vec![(path.clone(), position)]);
+        state
+            .enabled_files
+            .insert(path.clone(), (false, Some(position)));
+        assert_eq!(state.enabled_position(&path), None);
+        assert!(state.enabled().is_empty());
+    }
+
+    #[test]
+    fn test_clamp_to_text() {
+        let text = "fn f() {\n    let s = 1;\n}\n";
+        let position = lsp_types::Position::new(1, 8);
+        assert_eq!(clamp_to_text(position, text), position);
+        assert_eq!(
+            clamp_to_text(lsp_types::Position::new(7, 4), text),
+            lsp_types::Position::new(2, 4)
+        );
+        assert_eq!(clamp_to_text(position, ""), lsp_types::Position::new(0, 8));
+    }
+}
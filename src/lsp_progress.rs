@@ -10,6 +10,22 @@ pub enum AnalysisStatus {
     Analyzing,
     Finished,
     Error,
+    /// Analysis finished, but there is no variable under the cursor
+    NoVariableSelected,
+    /// The file changed since it was analyzed, its results are not shown
+    /// until it is analyzed again
+    Stale,
+    /// The file is too large to be analyzed
+    Skipped,
+}
+
+/// Message of the end of a progress the user cancelled
+pub const CANCELLED_MESSAGE: &str = "cancelled";
+
+/// Value of a new progress token, unique to the server
+#[must_use]
+pub fn new_token() -> lsp_types::NumberOrString {
+    lsp_types::NumberOrString::String(format!("{}", uuid::Uuid::new_v4()))
 }
 
 pub struct ProgressToken {
@@ -17,8 +33,13 @@ pub struct ProgressToken {
     token: Option<lsp_types::NumberOrString>,
 }
 impl ProgressToken {
-    pub async fn begin(client: Client, message: Option<impl ToString>) -> Self {
-        let token = lsp_types::NumberOrString::String(format!("{}", uuid::Uuid::new_v4()));
+    /// Progress with the value `token`, which the user may cancel with
+    /// `window/workDoneProgress/cancel`.
+    pub async fn begin(
+        client: Client,
+        token: lsp_types::NumberOrString,
+        message: Option<impl ToString>,
+    ) -> Self {
         client
             .send_request::<WorkDoneProgressCreate>(lsp_types::WorkDoneProgressCreateParams {
                 token: token.clone(),
@@ -29,7 +50,7 @@ impl ProgressToken {
         let value = lsp_types::ProgressParamsValue::WorkDone(lsp_types::WorkDoneProgress::Begin(
             lsp_types::WorkDoneProgressBegin {
                 title: "RustOwl".to_owned(),
-                cancellable: Some(false),
+                cancellable: Some(true),
                 message: message.map(|v| v.to_string()),
                 percentage: Some(0),
             },
@@ -47,11 +68,17 @@ impl ProgressToken {
         }
     }
 
+    /// Value of the token the client knows the progress by.
+    #[must_use]
+    pub const fn token(&self) -> Option<&lsp_types::NumberOrString> {
+        self.token.as_ref()
+    }
+
     pub async fn report(&self, message: Option<impl ToString>, percentage: Option<u32>) {
         if let (Some(client), Some(token)) = (self.client.clone(), self.token.clone()) {
             let value = lsp_types::ProgressParamsValue::WorkDone(
                 lsp_types::WorkDoneProgress::Report(lsp_types::WorkDoneProgressReport {
-                    cancellable: Some(false),
+                    cancellable: Some(true),
                     message: message.map(|v| v.to_string()),
                     percentage,
                 }),
@@ -62,9 +89,18 @@ impl ProgressToken {
         }
     }
 
-    pub async fn finish(mut self) {
+    pub async fn finish(self) {
+        self.end(None).await;
+    }
+
+    /// End the progress, telling the user it was cancelled.
+    pub async fn cancel(self) {
+        self.end(Some(CANCELLED_MESSAGE.to_owned())).await;
+    }
+
+    async fn end(mut self, message: Option<String>) {
         let value = lsp_types::ProgressParamsValue::WorkDone(lsp_types::WorkDoneProgress::End(
-            lsp_types::WorkDoneProgressEnd { message: None },
+            lsp_types::WorkDoneProgressEnd { message },
         ));
         if let (Some(client), Some(token)) = (self.client.take(), self.token.take()) {
             client
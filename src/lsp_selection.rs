@@ -0,0 +1,277 @@
+//! Selection of the locals under a cursor and calculation of their
+//! decorations, shared by the language server and [`crate::api`] so that both
+//! show the same decorations.
+
+use std::collections::HashSet;
+
+use crate::{
+    cfg_test,
+    lsp_decoration::{CalcDecos, Candidate, Deco, SelectLocal},
+    lsp_ignore::IgnoredLines,
+    lsp_settings::Settings,
+    models::{CharOffset, File, FnLocal, Item, LineCol, Range},
+    range_ops,
+    text_conversion::SourceIndex,
+};
+
+/// Options of the selection at a cursor and of its decorations
+#[derive(Clone, Copy, Debug)]
+pub struct SelectionOptions<'a> {
+    /// Select and decorate temporaries, see [`SelectLocal::with_temporaries`]
+    pub show_temporaries: bool,
+    /// See [`CalcDecos::with_teaching_mode`]
+    pub teaching_mode: bool,
+    /// Add summaries at the declarations, see [`CalcDecos::add_summaries`]
+    pub summary: bool,
+    /// Candidate decorated instead of the default selection, see
+    /// [`crate::lsp_decoration::CursorRequest::selected_index`]
+    pub selected_index: Option<usize>,
+    /// Settings choosing the shown decorations
+    pub settings: &'a Settings,
+}
+
+/// Keys of the functions of `file` with an item whose `bounds` contain
+/// `position`, or `None` if the bounds are not those of its items.
+///
+/// Only these functions can have a local selected at `position`. All parts
+/// of a split function are kept, as its declarations are in the first one.
+/// Selection cannot stop at the first of them, as a closure and the function
+/// around it compete for the cursor.
+fn functions_at(
+    file: &File,
+    bounds: &[Option<Range>],
+    position: CharOffset,
+) -> Option<HashSet<u64>> {
+    if bounds.len() != file.items.len() {
+        return None;
+    }
+    let under_cursor = file
+        .items
+        .iter()
+        .zip(bounds)
+        .filter(|(_, bounds)| {
+            bounds.is_some_and(|bounds| bounds.from() <= position && position <= bounds.until())
+        })
+        .map(|(item, _)| item.function().fn_key)
+        .collect();
+    Some(under_cursor)
+}
+
+/// Locals of an analyzed file selected at a cursor
+pub struct FileSelection<'a> {
+    file: &'a File,
+    text: &'a str,
+    /// Items of the file in the `cfg(test)` context of the cursor
+    pub items: Vec<&'a Item>,
+    /// Locals under the cursor, see [`SelectLocal::candidates`]
+    pub candidates: Vec<Candidate>,
+    /// Locals decorated, none if no variable is under the cursor
+    pub locals: Vec<FnLocal>,
+}
+
+impl<'a> FileSelection<'a> {
+    /// Locals of `file`, analyzed from `text`, selected at `position`.
+    ///
+    /// Only functions whose `bounds`, see [`range_ops::function_bounds`], are
+    /// around `position` are searched, or all of them without `bounds`.
+    #[must_use]
+    pub fn new(
+        file: &'a File,
+        text: &'a str,
+        index: &SourceIndex,
+        position: CharOffset,
+        bounds: Option<&[Option<Range>]>,
+        options: &SelectionOptions<'_>,
+    ) -> Self {
+        let LineCol { line, character } = index.char_to_line_col(position);
+        let line_text = text.lines().nth(line as usize).unwrap_or_default();
+        let context = cfg_test::context_at(text, line);
+        let items: Vec<_> = file.items_in(context).collect();
+        let mut selected = SelectLocal::new(position)
+            .with_temporaries(options.show_temporaries)
+            .with_line(line_text, character)
+            .with_types(&file.types);
+        let under_cursor = bounds.and_then(|bounds| functions_at(file, bounds, position));
+        for item in items.iter().copied().filter(|item| {
+            under_cursor
+                .as_ref()
+                .is_none_or(|under_cursor| under_cursor.contains(&item.function().fn_key))
+        }) {
+            range_ops::mir_visit(item, &mut selected);
+        }
+        let candidates = selected.candidates();
+        let locals = options
+            .selected_index
+            .and_then(|index| candidates.get(index))
+            .map_or_else(|| selected.selected(), |candidate| vec![candidate.local]);
+        Self {
+            file,
+            text,
+            items,
+            candidates,
+            locals,
+        }
+    }
+
+    /// Decorations of the selected locals, except those on `ignored` lines
+    /// and those of types not shown by the settings.
+    #[must_use]
+    pub fn decorations(
+        &self,
+        index: &SourceIndex,
+        ignored: &IgnoredLines,
+        options: &SelectionOptions<'_>,
+    ) -> Vec<Deco> {
+        let captures: Vec<_> = self
+            .items
+            .iter()
+            .flat_map(|item| item.function().captures.iter().copied())
+            .collect();
+        let mut calc = CalcDecos::new(self.locals.iter().copied())
+            .with_captures(&captures)
+            .with_types(&self.file.types)
+            .with_temporaries(options.show_temporaries)
+            .with_teaching_mode(options.teaching_mode)
+            .with_source(self.text);
+        calc.visit_items(&self.items);
+        calc.handle_overlapping();
+        if options.summary {
+            calc.add_summaries(index);
+        }
+        calc.decorations()
+            .into_iter()
+            .filter(|deco| options.settings.shows(deco.kind()))
+            .filter(|deco| !ignored.intersects(Deco::convert_range(index, deco.range())))
+            .collect()
+    }
+}
+
+/// Decorations of the variable at `position` of `file`, analyzed from
+/// `text`, as shown by the language server with the default settings, or
+/// none if no variable is there.
+#[must_use]
+pub fn decorations_at(file: &File, text: &str, position: CharOffset) -> Vec<Deco> {
+    let settings = Settings::default();
+    let options = SelectionOptions {
+        show_temporaries: false,
+        teaching_mode: false,
+        summary: false,
+        selected_index: None,
+        settings: &settings,
+    };
+    let index = SourceIndex::new(text);
+    let bounds: Vec<_> = file.items.iter().map(range_ops::function_bounds).collect();
+    let selection = FileSelection::new(file, text, &index, position, Some(&bounds), &options);
+    if selection.locals.is_empty() {
+        return Vec::new();
+    }
+    selection.decorations(&index, &IgnoredLines::parse(text), &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Function, ItemKind, MirDecl};
+
+    /// File with a function declaring `x` at the start of `lives`, the
+    /// ranges it lives over
+    fn file_with_x(lives: &[Range]) -> File {
+        let function = Function {
+            fn_id: 3,
+            fn_key: 1,
+            fn_name: "f".to_owned(),
+            arg_count: 0,
+            basic_blocks: Vec::new(),
+            decls: vec![MirDecl::User {
+                local: FnLocal::new(1, 1),
+                name: "x".to_owned(),
+                span: Range::new(lives[0].from(), lives[0].from() + 1).unwrap(),
+                scope: None,
+                ty: "i32".into(),
+                lives: lives.to_vec(),
+                shared_borrow: Vec::new(),
+                mutable_borrow: Vec::new(),
+                shared_mut: Vec::new(),
+                drop: false,
+                drop_range: Vec::new(),
+                must_live_at: Vec::new(),
+                must_live_from_signature: Vec::new(),
+                dead_writes: Vec::new(),
+            }],
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            part: None,
+            context: String::new(),
+        };
+        File {
+            items: vec![Item::Function {
+                span: lives.first().copied(),
+                kind: ItemKind::Fn,
+                function,
+            }],
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        }
+    }
+
+    #[test]
+    fn test_decorations_skip_ignored_lines() {
+        let text = "let x = 1;\n// ferrous-owl:ignore-next-line\nlet y = x;\nlet z = x;\n";
+        let index = SourceIndex::new(text);
+        let line = |line: u32| {
+            Range::new(
+                index.line_col_to_char(LineCol::new(line, 0)),
+                index.line_col_to_char(LineCol::new(line, 9)),
+            )
+            .unwrap()
+        };
+        let file = file_with_x(&[
+            Range::new(CharOffset::from(4u32), CharOffset::from(9u32)).unwrap(),
+            line(2),
+            line(3),
+        ]);
+
+        let decorations = decorations_at(&file, text, CharOffset::from(4u32));
+        let lines: Vec<_> = decorations
+            .iter()
+            .map(|deco| index.char_to_line_col(deco.range().from()).line)
+            .collect();
+        assert!(!lines.is_empty(), "{decorations:?}");
+        assert!(!lines.contains(&2), "{decorations:?}");
+        assert!(lines.contains(&3), "{decorations:?}");
+    }
+
+    #[test]
+    fn test_selection_outside_function_bounds_is_empty() {
+        let text = "let x = 1;\n\nfn g() {}\n";
+        let file =
+            file_with_x(&[Range::new(CharOffset::from(4u32), CharOffset::from(9u32)).unwrap()]);
+        let index = SourceIndex::new(text);
+        let settings = Settings::default();
+        let options = SelectionOptions {
+            show_temporaries: false,
+            teaching_mode: false,
+            summary: false,
+            selected_index: None,
+            settings: &settings,
+        };
+        let inside =
+            FileSelection::new(&file, text, &index, CharOffset::from(4u32), None, &options);
+        assert_eq!(inside.locals, [FnLocal::new(1, 1)]);
+        let bounds = [Some(
+            Range::new(CharOffset::from(12u32), CharOffset::from(21u32)).unwrap(),
+        )];
+        let outside = FileSelection::new(
+            &file,
+            text,
+            &index,
+            CharOffset::from(4u32),
+            Some(&bounds),
+            &options,
+        );
+        assert!(outside.locals.is_empty());
+    }
+}
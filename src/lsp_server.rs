@@ -1,19 +1,54 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fs,
+    future::Future,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
     path::{Path, PathBuf},
-    sync::Arc,
+    pin::Pin,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
 };
 
-use tokio::{sync::RwLock, task::JoinSet, time};
+use cargo_metadata::diagnostic::DiagnosticSpan;
+use tokio::{
+    sync::RwLock,
+    task::{JoinHandle, JoinSet},
+    time,
+};
 use tokio_util::sync::CancellationToken;
 use tower_lsp::{Client, LanguageServer, LspService, jsonrpc, lsp_types};
 
 use crate::{
-    lsp_decoration as decoration, lsp_progress as progress,
-    lsp_workspace::{Analyzer, AnalyzerEvent},
-    models::{Crate, Loc},
-    range_ops, text_conversion,
+    logging::{self, Heartbeat},
+    lsp_budget, lsp_cargo_lock,
+    lsp_compare::SelectionDiff,
+    lsp_cursor_cache::CursorCache,
+    lsp_decoration as decoration,
+    lsp_documents::{Documents, PositionEncoding},
+    lsp_health::{self, ClientProcess},
+    lsp_ignore::IgnoredLines,
+    lsp_line_hints::LineHintsResponse,
+    lsp_ownership::{self, OwnershipState},
+    lsp_progress as progress,
+    lsp_selection::{FileSelection, SelectionOptions},
+    lsp_settings::{SETTINGS_SECTION, Settings},
+    lsp_stats::{StatsRequest, StatsResponse},
+    lsp_status::{AnalysisUpdate, DidUpdateAnalysis, StatusReport},
+    lsp_workspace::{
+        AnalyzeEventIter, Analyzer, AnalyzerError, AnalyzerEvent, MAX_FILE_BYTES_ENV, TargetKind,
+        oversized,
+    },
+    mir_algorithm::AlgorithmChoice,
+    mir_cache,
+    models::{CharOffset, Crate, FnLocal, LineCol, Range, Workspace, WorkspaceMeta},
+    path_norm, range_ops,
+    text_conversion::SourceIndex,
+    toolchain,
 };
 
 /// Commands supported by workspace/executeCommand
@@ -21,258 +56,1275 @@ pub const CMD_TOGGLE_OWNERSHIP: &str = "ferrous-owl.toggleOwnership";
 pub const CMD_ENABLE_OWNERSHIP: &str = "ferrous-owl.enableOwnership";
 pub const CMD_DISABLE_OWNERSHIP: &str = "ferrous-owl.disableOwnership";
 pub const CMD_ANALYZE: &str = "ferrous-owl.analyze";
+pub const CMD_COMPARE_SELECTIONS: &str = "ferrous-owl.compareSelections";
+
+/// Source of diagnostics for compiler errors that prevented analysis
+pub const BUILD_DIAGNOSTIC_SOURCE: &str = concat!(env!("CARGO_PKG_NAME"), "-build");
+
+/// Recently opened or edited files analyzed on their own before the analysis
+/// of their packages, see [`Backend::warm_up`]
+const WARM_UP_FILES: usize = 2;
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct AnalyzeRequest {
+    /// Analyze again even if the sources did not change
+    #[serde(default)]
+    force: bool,
+    /// Target kinds to analyze from now on, the current ones if empty
+    #[serde(default)]
+    target_kinds: Vec<TargetKind>,
+    #[serde(default)]
+    scope: AnalyzeScope,
+    /// File the scope is chosen by, required unless the whole workspace is
+    /// analyzed
+    #[serde(default)]
+    uri: Option<lsp_types::Url>,
+    /// Analyze targets of every kind, for this analysis only
+    #[serde(default)]
+    all_targets: Option<bool>,
+    /// Analyze with all features enabled, for this analysis only
+    #[serde(default)]
+    all_features: Option<bool>,
+}
 
-#[derive(serde::Deserialize, Clone, Debug)]
+/// Part of the workspace analyzed by an [`AnalyzeRequest`]
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
-pub struct AnalyzeRequest {}
+pub enum AnalyzeScope {
+    /// Only the file, analyzed on its own
+    File,
+    /// The workspace member containing the file
+    Package,
+    /// All analysis targets
+    #[default]
+    Workspace,
+}
 #[derive(serde::Serialize, Clone, Debug)]
 pub struct AnalyzeResponse {}
 
-/// Tracks whether ownership diagnostics are enabled for each document
-#[derive(Default, Clone)]
-struct OwnershipState {
-    /// Map from file path to (enabled, `cursor_position`)
-    enabled_files: HashMap<PathBuf, (bool, Option<lsp_types::Position>)>,
+/// Options of the `initializationOptions` of the `initialize` request
+#[derive(serde::Deserialize, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+struct InitializationOptions {
+    #[serde(default)]
+    polonius_algo: Option<AlgorithmChoice>,
+    #[serde(default)]
+    target_kinds: Vec<TargetKind>,
+    /// Let the cursor select compiler temporaries, like the values of method
+    /// calls in a chain
+    #[serde(default)]
+    show_temporaries: bool,
+    /// Show the lexical scope of selected variables where they are no longer
+    /// live
+    #[serde(default)]
+    teaching_mode: bool,
+    /// Most decorations returned for a cursor,
+    /// [`lsp_budget::DEFAULT_MAX_DECORATIONS`] by default
+    #[serde(default)]
+    max_decorations: Option<usize>,
+}
+
+/// Part of an analysis target a running analysis covers, so that analyzing
+/// the same part again cancels it first
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ProcessScope {
+    /// All packages of the target
+    Workspace,
+    /// The workspace member with this name
+    Package(String),
+    /// This file, analyzed on its own
+    File(PathBuf),
+    /// This file, analyzed on its own ahead of its package, whose results
+    /// replace those of the file
+    WarmUp(PathBuf),
+}
+
+impl ProcessScope {
+    /// File this part of the target of `analyzer` is analyzed from on its
+    /// own, without cargo, if it is one.
+    fn single_file<'a>(&'a self, analyzer: &'a Analyzer) -> Option<&'a Path> {
+        match self {
+            Self::File(path) | Self::WarmUp(path) => Some(path),
+            Self::Workspace | Self::Package(_) => {
+                analyzer.is_single_file().then(|| analyzer.target_path())
+            }
+        }
+    }
+
+    /// Analyze this part of the target of `analyzer`, a single file from its
+    /// unsaved `text` if there is one.
+    async fn analyze(
+        &self,
+        analyzer: &Analyzer,
+        target_kinds: &[TargetKind],
+        all_features: bool,
+        force: bool,
+        polonius_algo: Option<AlgorithmChoice>,
+        text: Option<String>,
+    ) -> AnalyzeEventIter {
+        match self {
+            Self::File(path) | Self::WarmUp(path) => analyzer.analyze_single_file(path, text).await,
+            // the target of a single file has no packages
+            _ if analyzer.is_single_file() => {
+                analyzer
+                    .analyze_single_file(analyzer.target_path(), text)
+                    .await
+            }
+            Self::Workspace => {
+                analyzer
+                    .analyze(target_kinds, all_features, force, polonius_algo)
+                    .await
+            }
+            Self::Package(member) => {
+                analyzer
+                    .analyze_member(member, target_kinds, all_features, polonius_algo)
+                    .await
+            }
+        }
+    }
+}
+
+/// Which decorations are computed at a cursor
+#[derive(Clone, Copy)]
+struct CursorOptions {
+    /// Whether summaries are shown at declarations
+    summary: bool,
+    /// Candidate decorated instead of the default selection, see
+    /// [`decoration::CursorRequest::selected_index`]
+    selected_index: Option<usize>,
+}
+
+/// Decorations of the variables selected at a cursor
+struct Selection {
+    /// Id of the selection, see [`decoration::Decorations::selection_id`]
+    id: u64,
+    /// Decorations within the `max_decorations` initialization option
+    items: Vec<decoration::Deco<lsp_types::Range>>,
+    /// Number of decorations before they were limited
+    total: usize,
+    /// Locals under the cursor, see [`decoration::SelectLocal::candidates`]
+    candidates: Vec<decoration::Candidate>,
+    /// Functions of `items`, see [`decoration::Decorations::functions`]
+    functions: BTreeMap<u64, decoration::FunctionInfo>,
+}
+
+/// Cancellation tokens of running analyses, with the target path of their
+/// analyzer and the part of it they cover
+type ProcessTokens = BTreeMap<usize, (PathBuf, ProcessScope, CancellationToken)>;
+
+/// Largest integer JavaScript numbers hold exactly, `2^53 - 1`
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Selection id hashed from `value`, within [`MAX_SAFE_INTEGER`] so that
+/// JavaScript clients pass it back unchanged.
+fn selection_hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish() & MAX_SAFE_INTEGER
 }
 
 /// `FerrousOwl` LSP server backend
+#[derive(Clone)]
 pub struct Backend {
     client: Client,
     analyzers: Arc<RwLock<Vec<Analyzer>>>,
     status: Arc<RwLock<progress::AnalysisStatus>>,
     analyzed: Arc<RwLock<Option<Crate>>>,
+    /// Bounds of the items of each analyzed file, in their order, see
+    /// [`range_ops::function_bounds`]
+    bounds: Arc<RwLock<HashMap<String, Vec<Option<Range>>>>>,
+    /// Time at which results of each analyzed file last arrived
+    updated: Arc<RwLock<HashMap<String, SystemTime>>>,
     processes: Arc<RwLock<JoinSet<()>>>,
-    process_tokens: Arc<RwLock<BTreeMap<usize, CancellationToken>>>,
+    process_tokens: Arc<RwLock<ProcessTokens>>,
     work_done_progress: Arc<RwLock<bool>>,
+    /// Keys in `process_tokens` of the analyses the user can cancel, by the
+    /// token of their progress
+    progress_processes: Arc<RwLock<HashMap<lsp_types::NumberOrString, usize>>>,
+    /// Whether the user cancelled an analysis since the last one started
+    cancelled: Arc<AtomicBool>,
     /// Per-document state for ownership diagnostics display
     ownership_state: Arc<RwLock<OwnershipState>>,
+    /// Pending publications of ownership diagnostics, per document
+    ownership_tasks: Arc<RwLock<HashMap<PathBuf, JoinHandle<()>>>>,
+    /// Compiler errors of the last analysis, per file
+    build_errors: Arc<RwLock<HashMap<PathBuf, Vec<lsp_types::Diagnostic>>>>,
+    /// Workspace packages cargo could not check, with the first error of each
+    failed_crates: Arc<RwLock<Vec<(String, String)>>>,
+    /// Polonius algorithm requested by the client
+    polonius_algo: Arc<RwLock<Option<AlgorithmChoice>>>,
+    /// Target kinds requested by the client, see [`TargetKind::selection`]
+    target_kinds: Arc<RwLock<Vec<TargetKind>>>,
+    /// Whether the cursor selects compiler temporaries
+    show_temporaries: Arc<RwLock<bool>>,
+    /// Whether lexical scopes are shown next to lifetimes
+    teaching_mode: Arc<RwLock<bool>>,
+    /// Most decorations returned for a cursor, see [`lsp_budget::limit`]
+    max_decorations: Arc<RwLock<usize>>,
+    /// Lines ignored by markers in each file, with the hash of the text they
+    /// were found in
+    ignored: Arc<RwLock<HashMap<PathBuf, (u64, IgnoredLines)>>>,
+    /// Source hash each file with stale results was last analyzed again for
+    reanalyzed: Arc<RwLock<HashMap<PathBuf, String>>>,
+    /// Analysis timings of all analyzed functions
+    stats: Arc<RwLock<WorkspaceMeta>>,
+    /// Settings of the last configuration change
+    settings: Arc<RwLock<Settings>>,
+    /// Whether the client process is already watched, as `initialize` may be
+    /// sent again
+    client_watched: Arc<AtomicBool>,
+    /// Decorations last computed at the cursor of each document
+    cursor_cache: Arc<RwLock<CursorCache>>,
+    /// Texts of the documents open in the editor
+    documents: Arc<RwLock<Documents>>,
+    /// Files last opened or edited, the latest first, see [`Self::warm_up`]
+    recent: Arc<RwLock<VecDeque<PathBuf>>>,
+}
+
+/// Running analyses of a [`Backend`], which can be stopped after the service
+/// that owns the backend ended
+#[derive(Clone)]
+pub struct Subprocesses {
+    processes: Arc<RwLock<JoinSet<()>>>,
+    process_tokens: Arc<RwLock<ProcessTokens>>,
+}
+
+impl Subprocesses {
+    /// Cancel the running analyses and wait until they stopped.
+    pub async fn shutdown(&self) {
+        {
+            let mut tokens = self.process_tokens.write().await;
+            while let Some((_, (_, _, token))) = tokens.pop_last() {
+                token.cancel();
+            }
+        }
+        self.processes.write().await.shutdown().await;
+    }
 }
 
 impl Backend {
     #[must_use]
     pub fn new(client: Client) -> Self {
-        Self {
+        let backend = Self {
             client,
             analyzers: Arc::new(RwLock::new(Vec::new())),
             analyzed: Arc::new(RwLock::new(None)),
+            bounds: Arc::new(RwLock::new(HashMap::new())),
+            updated: Arc::new(RwLock::new(HashMap::new())),
             status: Arc::new(RwLock::new(progress::AnalysisStatus::Finished)),
             processes: Arc::new(RwLock::new(JoinSet::new())),
             process_tokens: Arc::new(RwLock::new(BTreeMap::new())),
             work_done_progress: Arc::new(RwLock::new(false)),
+            progress_processes: Arc::new(RwLock::new(HashMap::new())),
+            cancelled: Arc::new(AtomicBool::new(false)),
             ownership_state: Arc::new(RwLock::new(OwnershipState::default())),
+            ownership_tasks: Arc::new(RwLock::new(HashMap::new())),
+            build_errors: Arc::new(RwLock::new(HashMap::new())),
+            failed_crates: Arc::new(RwLock::new(Vec::new())),
+            polonius_algo: Arc::new(RwLock::new(None)),
+            target_kinds: Arc::new(RwLock::new(Vec::new())),
+            show_temporaries: Arc::new(RwLock::new(false)),
+            teaching_mode: Arc::new(RwLock::new(false)),
+            max_decorations: Arc::new(RwLock::new(lsp_budget::DEFAULT_MAX_DECORATIONS)),
+            ignored: Arc::new(RwLock::new(HashMap::new())),
+            reanalyzed: Arc::new(RwLock::new(HashMap::new())),
+            stats: Arc::new(RwLock::new(WorkspaceMeta::default())),
+            settings: Arc::new(RwLock::new(Settings::default())),
+            client_watched: Arc::new(AtomicBool::new(false)),
+            cursor_cache: Arc::new(RwLock::new(CursorCache::default())),
+            documents: Arc::new(RwLock::new(Documents::default())),
+            recent: Arc::new(RwLock::new(VecDeque::new())),
+        };
+        if logging::log_file_path().is_some() {
+            backend.spawn_heartbeat();
         }
+        backend
     }
 
-    async fn add_analyze_target(&self, path: &Path) -> bool {
-        if let Ok(new_analyzer) = Analyzer::new(&path).await {
-            let mut analyzers = self.analyzers.write().await;
-            for analyzer in &*analyzers {
-                if analyzer.target_path() == new_analyzer.target_path() {
-                    return true;
-                }
+    /// Periodically write the analysis state to the log file.
+    fn spawn_heartbeat(&self) {
+        let status = self.status.clone();
+        let process_tokens = self.process_tokens.clone();
+        let analyzed = self.analyzed.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(logging::HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                logging::log_heartbeat(&Heartbeat {
+                    status: *status.read().await,
+                    processes: process_tokens.read().await.len(),
+                    analyzed: analyzed.read().await.as_ref().map_or(0, |v| v.0.len()),
+                });
             }
+        });
+    }
+
+    /// Register `path` as an analysis target.
+    ///
+    /// Returns `true` if a new target was added and `false` if `path` belongs
+    /// to an already known target.
+    async fn add_analyze_target(&self, path: &Path) -> Result<bool, AnalyzerError> {
+        let new_analyzer = Analyzer::new(&path).await?;
+        let mut analyzers = self.analyzers.write().await;
+        let known = analyzers
+            .iter()
+            .any(|analyzer| analyzer.target_path() == new_analyzer.target_path());
+        if !known {
             analyzers.push(new_analyzer);
-            true
-        } else {
-            false
+        }
+        drop(analyzers);
+        Ok(!known)
+    }
+
+    /// Register `path` as an analysis target like [`Self::add_analyze_target`],
+    /// logging why it cannot be analyzed and warning the user if they can fix
+    /// it.
+    async fn add_analyze_target_or_warn(&self, path: &Path) -> Option<bool> {
+        match self.add_analyze_target(path).await {
+            Ok(added) => Some(added),
+            Err(e) => {
+                log::warn!("cannot analyze {}: {e}", path.display());
+                if e.is_actionable() {
+                    self.client
+                        .show_message(
+                            lsp_types::MessageType::WARNING,
+                            format!("FerrousOwl: cannot analyze {}: {e}", path.display()),
+                        )
+                        .await;
+                }
+                None
+            }
+        }
+    }
+
+    /// Forget the analysis targets in the workspace folder `root`.
+    ///
+    /// Their running analyses are cancelled, and results and diagnostics of
+    /// files under `root` are discarded.
+    async fn remove_analyze_target(&self, root: &Path) {
+        let root_key = path_norm::normalize_path(root);
+        self.analyzers
+            .write()
+            .await
+            .retain(|analyzer| !analyzer.target_path().starts_with(root));
+        self.process_tokens
+            .write()
+            .await
+            .retain(|_, (target, _, token)| {
+                let removed = target.starts_with(root);
+                if removed {
+                    token.cancel();
+                }
+                !removed
+            });
+        if let Some(analyzed) = &mut *self.analyzed.write().await {
+            analyzed
+                .0
+                .retain(|file, _| !Path::new(file).starts_with(&root_key));
+        }
+        self.bounds
+            .write()
+            .await
+            .retain(|file, _| !Path::new(file).starts_with(&root_key));
+        self.updated
+            .write()
+            .await
+            .retain(|file, _| !Path::new(file).starts_with(&root_key));
+        for files in self.stats.write().await.timings.values_mut() {
+            files.retain(|file, _| !Path::new(file).starts_with(&root_key));
+        }
+
+        let mut stale: HashSet<PathBuf> = HashSet::new();
+        self.build_errors.write().await.retain(|file, _| {
+            let removed = file.starts_with(root);
+            if removed {
+                stale.insert(file.clone());
+            }
+            !removed
+        });
+        self.ownership_state
+            .write()
+            .await
+            .enabled_files
+            .retain(|file, (enabled, _)| {
+                let removed = file.starts_with(root);
+                if removed && *enabled {
+                    stale.insert(file.clone());
+                }
+                !removed
+            });
+        for file in stale {
+            self.disable_ownership_diagnostics(&file).await;
         }
     }
 
-    pub async fn analyze(&self, _params: AnalyzeRequest) -> jsonrpc::Result<AnalyzeResponse> {
+    pub async fn analyze(&self, params: AnalyzeRequest) -> jsonrpc::Result<AnalyzeResponse> {
         log::info!("ferrous-owl/analyze request received");
-        self.do_analyze().await;
+        self.analyze_scope(params).await?;
         Ok(AnalyzeResponse {})
     }
-    async fn do_analyze(&self) {
-        self.shutdown_subprocesses().await;
-        // Use all_targets=true by default to include test code
-        self.analyze_with_options(true, false).await;
+
+    /// Analyze the scope of `params`.
+    ///
+    /// Running analyses of the same scope are cancelled first, those of
+    /// other files and packages keep running. Analyzing the workspace
+    /// cancels all of them.
+    async fn analyze_scope(&self, params: AnalyzeRequest) -> jsonrpc::Result<()> {
+        let path = match (params.scope, &params.uri) {
+            (AnalyzeScope::Workspace, _) => None,
+            (_, Some(uri)) => Some(
+                uri.to_file_path()
+                    .map_err(|()| jsonrpc::Error::invalid_params(format!("{uri} is not a file")))?,
+            ),
+            (_, None) => {
+                return Err(jsonrpc::Error::invalid_params(
+                    "the file and package scopes need a `uri`",
+                ));
+            }
+        };
+        if !params.target_kinds.is_empty() {
+            *self.target_kinds.write().await = params.target_kinds;
+        }
+        let settings = self.settings.read().await.clone();
+        let all_features = params.all_features.unwrap_or(settings.all_features);
+        let all_targets = params.all_targets.unwrap_or(settings.all_targets);
+        let Some(path) = path else {
+            self.analyze_with_options(all_features, all_targets, params.force)
+                .await;
+            return Ok(());
+        };
+
+        let analyzer = match self.analyzer_of(&path).await {
+            Some(analyzer) => analyzer,
+            None => Analyzer::new(&path).await.map_err(|e| {
+                jsonrpc::Error::invalid_params(format!(
+                    "{} cannot be analyzed: {e}",
+                    path.display()
+                ))
+            })?,
+        };
+        let scope = if params.scope == AnalyzeScope::File {
+            ProcessScope::File(path)
+        } else {
+            let member = analyzer.member_of(&path).ok_or_else(|| {
+                jsonrpc::Error::invalid_params(format!("{} is not in a package", path.display()))
+            })?;
+            ProcessScope::Package(member)
+        };
+        self.cancel_processes(analyzer.target_path(), &scope).await;
+        *self.status.write().await = progress::AnalysisStatus::Analyzing;
+        self.spawn_analysis(analyzer, scope, all_features, all_targets, params.force)
+            .await;
+        self.finish_analysis(HashSet::new());
+        Ok(())
     }
 
-    async fn analyze_with_options(&self, all_targets: bool, all_features: bool) {
-        log::info!("wait 100ms for rust-analyzer");
-        time::sleep(time::Duration::from_millis(100)).await;
+    async fn do_analyze(&self, force: bool) {
+        let settings = self.settings.read().await.clone();
+        self.analyze_with_options(settings.all_features, settings.all_targets, force)
+            .await;
+    }
 
+    async fn analyze_with_options(&self, all_features: bool, all_targets: bool, force: bool) {
         log::info!("stop running analysis processes");
         self.shutdown_subprocesses().await;
 
+        if let Err(e) = toolchain::get_sysroot() {
+            log::error!("cannot analyze: {e}");
+            *self.status.write().await = progress::AnalysisStatus::Error;
+            self.client
+                .show_message(lsp_types::MessageType::ERROR, format!("FerrousOwl: {e}"))
+                .await;
+            return;
+        }
+
         log::info!("start analysis");
         {
             *self.status.write().await = progress::AnalysisStatus::Analyzing;
         }
+        // published again as results arrive, rather than left stale
+        let enabled = self.ownership_state.read().await.enabled();
+        for (path, _) in enabled {
+            self.clear_ownership_diagnostics(&path).await;
+        }
+        self.warm_up(all_features, all_targets).await;
         let analyzers = { self.analyzers.read().await.clone() };
+        let stale_build_errors: HashSet<_> = mem::take(&mut *self.build_errors.write().await)
+            .into_keys()
+            .collect();
 
         log::info!("analyze {} packages...", analyzers.len());
         for analyzer in analyzers {
-            let analyzed = self.analyzed.clone();
-            let client = self.client.clone();
-            let work_done_progress = self.work_done_progress.clone();
-            let cancellation_token = CancellationToken::new();
-
-            let cancellation_token_key = {
-                let token = cancellation_token.clone();
-                let mut tokens = self.process_tokens.write().await;
-                let key = tokens
-                    .last_entry()
-                    .map(|v| *v.key())
-                    .map_or(1, |key| key + 1);
-                tokens.insert(key, token);
-                key
+            self.spawn_analysis(
+                analyzer,
+                ProcessScope::Workspace,
+                all_features,
+                all_targets,
+                force,
+            )
+            .await;
+        }
+
+        self.finish_analysis(stale_build_errors);
+    }
+
+    /// Analyze the files last opened or edited on their own, so that their
+    /// decorations do not wait for the analysis of their packages.
+    ///
+    /// Files that are targets of their own, too large, or no longer exist
+    /// are left to the analysis of their targets.
+    async fn warm_up(&self, all_features: bool, all_targets: bool) {
+        // only the compiler analyzes single files
+        if !cfg!(feature = "compiler") {
+            return;
+        }
+        let recent = self.recent.read().await.clone();
+        for path in recent {
+            let Some(analyzer) = self.analyzer_of(&path).await else {
+                continue;
             };
+            if analyzer.is_single_file() || !path.is_file() || oversized(&path).is_some() {
+                continue;
+            }
+            log::info!("warm up {}", path.display());
+            self.spawn_analysis(
+                analyzer,
+                ProcessScope::WarmUp(path),
+                all_features,
+                all_targets,
+                false,
+            )
+            .await;
+        }
+    }
 
-            let process_tokens = self.process_tokens.clone();
-            self.processes.write().await.spawn(async move {
-                #[allow(
-                    clippy::if_then_some_else_none,
-                    reason = "cannot use bool::then with async await"
-                )]
-                let progress_token = if *work_done_progress.read().await {
-                    Some(progress::ProgressToken::begin(client.clone(), None::<&str>).await)
-                } else {
-                    None
-                };
+    /// Put `path` first among the [`WARM_UP_FILES`] recent files.
+    async fn touch(&self, path: &Path) {
+        let mut recent = self.recent.write().await;
+        recent.retain(|recent| recent != path);
+        recent.push_front(path.to_path_buf());
+        recent.truncate(WARM_UP_FILES);
+    }
 
-                let mut iter = analyzer.analyze(all_targets, all_features).await;
-                let mut analyzed_package_count = 0;
-                while let Some(event) = tokio::select! {
-                    () = cancellation_token.cancelled() => None,
-                    event = iter.next_event() => event,
-                } {
-                    match event {
-                        AnalyzerEvent::CrateChecked {
-                            package,
-                            package_count,
-                        } => {
-                            analyzed_package_count += 1;
-                            if let Some(token) = &progress_token {
-                                let percentage =
-                                    (analyzed_package_count * 100 / package_count).min(100);
-                                #[allow(
-                                    clippy::cast_possible_truncation,
-                                    reason = "percentage is 0-100"
-                                )]
-                                let percentage_u32 = percentage as u32;
-                                token
-                                    .report(
-                                        Some(format!("{package} analyzed")),
-                                        Some(percentage_u32),
-                                    )
-                                    .await;
-                            }
+    /// Wait until no other cargo process, like the one of rust-analyzer,
+    /// builds in the directory of the analysis of `analyzer`.
+    async fn wait_for_build_lock(analyzer: &Analyzer, progress: Option<&progress::ProgressToken>) {
+        let Some(lock) = analyzer.build_lock() else {
+            return;
+        };
+        if !lsp_cargo_lock::wait_until_unlocked(&lock, lsp_cargo_lock::max_lock_wait(), progress)
+            .await
+        {
+            log::warn!("{} is still locked, analyze anyway", lock.display());
+        }
+    }
+
+    /// Spawn the analysis of the `scope` of `analyzer`, merging its results
+    /// as they arrive.
+    #[allow(clippy::too_many_lines, reason = "one arm per analyzer event")]
+    async fn spawn_analysis(
+        &self,
+        analyzer: Analyzer,
+        scope: ProcessScope,
+        all_features: bool,
+        all_targets: bool,
+        force: bool,
+    ) {
+        let polonius_algo = *self.polonius_algo.read().await;
+        let target_kinds = if all_targets {
+            TargetKind::ALL.to_vec()
+        } else {
+            self.target_kinds.read().await.clone()
+        };
+        let results = self.analyzed.clone();
+        let bounds = self.bounds.clone();
+        let updated = self.updated.clone();
+        let timings = self.stats.clone();
+        let status = self.status.clone();
+        let build_errors = self.build_errors.clone();
+        let failed_crates = self.failed_crates.clone();
+        let client = self.client.clone();
+        let work_done_progress = self.work_done_progress.clone();
+        let (cancellation_token_key, cancellation_token) = self
+            .register_process(analyzer.target_path(), scope.clone())
+            .await;
+        self.cancelled.store(false, Ordering::SeqCst);
+        let reanalyzed = match &scope {
+            ProcessScope::Workspace => analyzer.members(),
+            ProcessScope::Package(member) => vec![member.clone()],
+            ProcessScope::File(_) | ProcessScope::WarmUp(_) => Vec::new(),
+        };
+        failed_crates
+            .write()
+            .await
+            .retain(|(package, _)| !reanalyzed.contains(package));
+
+        let text = match scope.single_file(&analyzer) {
+            Some(file) => self.documents.read().await.text(file).map(str::to_owned),
+            None => None,
+        };
+
+        let process_tokens = self.process_tokens.clone();
+        let progress_processes = self.progress_processes.clone();
+        let backend = self.clone();
+        let warm_up = matches!(scope, ProcessScope::WarmUp(_));
+        self.processes.write().await.spawn(async move {
+            #[allow(
+                clippy::if_then_some_else_none,
+                reason = "cannot use bool::then with async await"
+            )]
+            // a warm-up is part of the analysis of its package
+            let progress_token = if *work_done_progress.read().await && !warm_up {
+                let token = progress::new_token();
+                progress_processes
+                    .write()
+                    .await
+                    .insert(token.clone(), cancellation_token_key);
+                Some(progress::ProgressToken::begin(client.clone(), token, None::<&str>).await)
+            } else {
+                None
+            };
+
+            // a single file is analyzed without cargo
+            if !matches!(scope, ProcessScope::File(_) | ProcessScope::WarmUp(_)) {
+                tokio::select! {
+                    () = cancellation_token.cancelled() => {}
+                    () = Self::wait_for_build_lock(&analyzer, progress_token.as_ref()) => {}
+                }
+            }
+
+            let mut iter = scope
+                .analyze(
+                    &analyzer,
+                    &target_kinds,
+                    all_features,
+                    force,
+                    polonius_algo,
+                    text,
+                )
+                .await;
+            let mut analyzed_package_count = 0;
+            while let Some(event) = tokio::select! {
+                () = cancellation_token.cancelled() => None,
+                event = iter.next_event() => event,
+            } {
+                match event {
+                    AnalyzerEvent::CrateChecked {
+                        package,
+                        package_count,
+                    } => {
+                        analyzed_package_count += 1;
+                        if let Some(token) = &progress_token {
+                            let percentage =
+                                (analyzed_package_count * 100 / package_count).min(100);
+                            #[allow(
+                                clippy::cast_possible_truncation,
+                                reason = "percentage is 0-100"
+                            )]
+                            let percentage_u32 = percentage as u32;
+                            token
+                                .report(Some(format!("{package} analyzed")), Some(percentage_u32))
+                                .await;
                         }
-                        AnalyzerEvent::Analyzed(ws) => {
-                            let write = &mut *analyzed.write().await;
-                            for krate in ws.0.into_values() {
-                                if let Some(write) = write {
-                                    write.merge(krate);
-                                } else {
-                                    *write = Some(krate);
+                    }
+                    AnalyzerEvent::WaitingForLock => {
+                        if let Some(token) = &progress_token {
+                            token
+                                .report(Some(lsp_cargo_lock::WAITING_MESSAGE), None)
+                                .await;
+                        }
+                    }
+                    AnalyzerEvent::Analyzed(mut ws) => {
+                        // the package is timed again, under its own name
+                        if warm_up {
+                            for krate in ws.crates.values_mut() {
+                                for file in krate.0.values_mut() {
+                                    file.provisional = true;
                                 }
                             }
+                        } else if let Some(meta) = ws.meta {
+                            timings.write().await.merge(meta);
                         }
+                        let paths =
+                            Self::merge_results(&client, &results, &bounds, &updated, ws.crates)
+                                .await;
+                        backend.republish_ownership_diagnostics(Some(&paths)).await;
+                    }
+                    // the package reports its own errors
+                    AnalyzerEvent::CompileError { file, message, .. } if warm_up => {
+                        log::info!(
+                            "cannot warm up {}, it does not build on its own: {message}",
+                            file.display()
+                        );
+                    }
+                    AnalyzerEvent::CompileError {
+                        file,
+                        message,
+                        span,
+                    } => {
+                        *status.write().await = progress::AnalysisStatus::Error;
+                        let index = match backend.read_document(&file).await {
+                            Some(text) => Some(backend.documents.read().await.index(&text)),
+                            None => None,
+                        };
+                        let diagnostic = build_diagnostic(message, &span, index.as_ref());
+                        Self::publish_build_error(&client, &build_errors, file, diagnostic).await;
+                    }
+                    AnalyzerEvent::Skipped { file, bytes } => {
+                        *status.write().await = progress::AnalysisStatus::Skipped;
+                        Self::warn_skipped(&client, &file, bytes).await;
+                    }
+                    AnalyzerEvent::CrateFailed { package, message } => {
+                        log::warn!("cannot analyze {package}: {message}");
+                        let mut failed_crates = failed_crates.write().await;
+                        failed_crates.retain(|(failed, _)| *failed != package);
+                        failed_crates.push((package, message));
                     }
                 }
-                // remove cancellation token from list
-                process_tokens.write().await.remove(&cancellation_token_key);
+            }
+            // remove cancellation token from list
+            process_tokens.write().await.remove(&cancellation_token_key);
+
+            if let Some(progress_token) = progress_token {
+                Self::end_progress(&progress_processes, progress_token).await;
+            }
+        });
+    }
+
+    /// End the progress of an analysis, as cancelled if the user cancelled
+    /// it, which removed it from `progress_processes`.
+    async fn end_progress(
+        progress_processes: &RwLock<HashMap<lsp_types::NumberOrString, usize>>,
+        progress_token: progress::ProgressToken,
+    ) {
+        let cancelled = if let Some(token) = progress_token.token() {
+            progress_processes.write().await.remove(token).is_none()
+        } else {
+            false
+        };
+        if cancelled {
+            progress_token.cancel().await;
+        } else {
+            progress_token.finish().await;
+        }
+    }
+
+    /// Stop the analysis whose progress the user cancelled. Its results so
+    /// far are kept, and the analysis neither fails nor succeeds.
+    pub async fn work_done_progress_cancel(&self, params: lsp_types::WorkDoneProgressCancelParams) {
+        let Some(key) = self.progress_processes.write().await.remove(&params.token) else {
+            return;
+        };
+        if let Some((target, _, token)) = self.process_tokens.write().await.remove(&key) {
+            log::info!("analysis of {} cancelled by the user", target.display());
+            self.cancelled.store(true, Ordering::SeqCst);
+            token.cancel();
+        }
+    }
+
+    /// Analyze the package of `path` again, as its results were computed from
+    /// another version of the source than the one with `source_hash`.
+    ///
+    /// Nothing is queued while an analysis of the package runs, or if the
+    /// package was already analyzed again for this version of the source.
+    /// A single file target is analyzed from the text of its editor, a
+    /// package from the files on disk.
+    async fn queue_reanalysis(&self, path: &Path, source_hash: &str) {
+        let Some(analyzer) = self.analyzer_of(path).await else {
+            return;
+        };
+        let running = self
+            .process_tokens
+            .read()
+            .await
+            .values()
+            .any(|(target, _, _)| target == analyzer.target_path());
+        if running {
+            return;
+        }
+        {
+            let mut reanalyzed = self.reanalyzed.write().await;
+            if reanalyzed.get(path).is_some_and(|hash| hash == source_hash) {
+                return;
+            }
+            reanalyzed.insert(path.to_path_buf(), source_hash.to_owned());
+        }
+        log::info!("results of {} are stale, analyze again", path.display());
+        *self.status.write().await = progress::AnalysisStatus::Analyzing;
+        let scope = analyzer
+            .member_of(path)
+            .map_or(ProcessScope::Workspace, ProcessScope::Package);
+        let settings = self.settings.read().await.clone();
+        self.spawn_analysis(
+            analyzer,
+            scope,
+            settings.all_features,
+            settings.all_targets,
+            false,
+        )
+        .await;
+        self.finish_analysis(HashSet::new());
+    }
+
+    /// Analyzer of the innermost analysis target containing `path`.
+    async fn analyzer_of(&self, path: &Path) -> Option<Analyzer> {
+        self.analyzers
+            .read()
+            .await
+            .iter()
+            .filter(|analyzer| path.starts_with(analyzer.target_path()))
+            .max_by_key(|analyzer| analyzer.target_path().components().count())
+            .cloned()
+    }
 
-                if let Some(progress_token) = progress_token {
-                    progress_token.finish().await;
+    /// Cancel the running analyses of `scope` of the target at
+    /// `target_path`, leaving the others alone.
+    async fn cancel_processes(&self, target_path: &Path, scope: &ProcessScope) {
+        self.process_tokens
+            .write()
+            .await
+            .retain(|_, (target, running, token)| {
+                let same = target == target_path && running == scope;
+                if same {
+                    token.cancel();
                 }
+                !same
             });
-        }
+    }
+
+    /// Register the cancellation token of an analysis process of the `scope`
+    /// of the workspace at `target_path`.
+    async fn register_process(
+        &self,
+        target_path: &Path,
+        scope: ProcessScope,
+    ) -> (usize, CancellationToken) {
+        let token = CancellationToken::new();
+        let mut tokens = self.process_tokens.write().await;
+        let key = tokens
+            .last_entry()
+            .map(|v| *v.key())
+            .map_or(1, |key| key + 1);
+        tokens.insert(key, (target_path.to_path_buf(), scope, token.clone()));
+        drop(tokens);
+        (key, token)
+    }
 
+    /// Settle the analysis status once all analysis processes are done,
+    /// clear compiler errors of files in `stale_build_errors` that build
+    /// again, warn about the packages that could not be analyzed and publish
+    /// the ownership diagnostics of enabled files again.
+    ///
+    /// Packages that failed do not fail the analysis as long as others have
+    /// results, and an analysis the user cancelled does not fail either.
+    fn finish_analysis(&self, stale_build_errors: HashSet<PathBuf>) {
         let processes = self.processes.clone();
         let status = self.status.clone();
         let analyzed = self.analyzed.clone();
+        let build_errors = self.build_errors.clone();
+        let failed_crates = self.failed_crates.clone();
+        let client = self.client.clone();
+        let cancelled = self.cancelled.clone();
+        let backend = self.clone();
         tokio::spawn(async move {
             while { processes.write().await.join_next().await }.is_some() {}
+            let fixed: Vec<_> = {
+                let build_errors = build_errors.read().await;
+                stale_build_errors
+                    .into_iter()
+                    .filter(|file| !build_errors.contains_key(file))
+                    .collect()
+            };
+            for file in fixed {
+                if let Ok(uri) = lsp_types::Url::from_file_path(&file) {
+                    client.publish_diagnostics(uri, Vec::new(), None).await;
+                }
+            }
             let mut status = status.write().await;
             let analyzed = analyzed.write().await;
-            if *status != progress::AnalysisStatus::Error {
-                if analyzed.as_ref().map_or(0, |v| v.0.len()) == 0 {
+            if !matches!(
+                *status,
+                progress::AnalysisStatus::Error | progress::AnalysisStatus::Skipped
+            ) {
+                if analyzed.as_ref().map_or(0, |v| v.0.len()) == 0
+                    && !cancelled.load(Ordering::SeqCst)
+                {
                     *status = progress::AnalysisStatus::Error;
                 } else {
                     *status = progress::AnalysisStatus::Finished;
                 }
             }
+            drop((status, analyzed));
+            let failed = failed_crates.read().await.clone();
+            if !failed.is_empty() {
+                let packages: Vec<_> = failed
+                    .iter()
+                    .map(|(package, message)| format!("{package} ({message})"))
+                    .collect();
+                client
+                    .show_message(
+                        lsp_types::MessageType::WARNING,
+                        format!("FerrousOwl: cannot analyze {}", packages.join(", ")),
+                    )
+                    .await;
+            }
+            backend.republish_ownership_diagnostics(None).await;
         });
     }
 
+    /// Merge analysis results into `analyzed`, update the `bounds` of their
+    /// items and announce their files, returning them.
+    async fn merge_results(
+        client: &Client,
+        analyzed: &RwLock<Option<Crate>>,
+        bounds: &RwLock<HashMap<String, Vec<Option<Range>>>>,
+        updated: &RwLock<HashMap<String, SystemTime>>,
+        crates: HashMap<String, Crate>,
+    ) -> Vec<String> {
+        let mut paths: Vec<_> = crates
+            .values()
+            .flat_map(|krate| krate.0.keys().cloned())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        {
+            let write = &mut *analyzed.write().await;
+            for krate in crates.into_values() {
+                if let Some(write) = write {
+                    write.merge(krate);
+                } else {
+                    *write = Some(krate);
+                }
+            }
+            let mut bounds = bounds.write().await;
+            for (path, file) in write.iter().flat_map(|krate| &krate.0) {
+                if paths.binary_search(path).is_ok() {
+                    let items = file.items.iter().map(range_ops::function_bounds);
+                    bounds.insert(path.clone(), items.collect());
+                }
+            }
+        }
+        if paths.is_empty() {
+            return paths;
+        }
+        let now = SystemTime::now();
+        updated
+            .write()
+            .await
+            .extend(paths.iter().map(|path| (path.clone(), now)));
+        client
+            .send_notification::<DidUpdateAnalysis>(AnalysisUpdate {
+                paths: paths.clone(),
+            })
+            .await;
+        paths
+    }
+
+    /// Record a compiler error of `file` and publish all errors of the file.
+    async fn publish_build_error(
+        client: &Client,
+        build_errors: &RwLock<HashMap<PathBuf, Vec<lsp_types::Diagnostic>>>,
+        file: PathBuf,
+        diagnostic: lsp_types::Diagnostic,
+    ) {
+        let Ok(uri) = lsp_types::Url::from_file_path(&file) else {
+            return;
+        };
+        let mut build_errors = build_errors.write().await;
+        let errors = build_errors.entry(file).or_default();
+        errors.push(diagnostic);
+        let diagnostics = errors.clone();
+        drop(build_errors);
+        client.publish_diagnostics(uri, diagnostics, None).await;
+    }
+
+    /// Tell the user that the file at `path` of `bytes` bytes is too large to
+    /// be analyzed.
+    async fn warn_skipped(client: &Client, path: &Path, bytes: u64) {
+        client
+            .show_message(
+                lsp_types::MessageType::WARNING,
+                format!(
+                    "FerrousOwl: {} is not analyzed, its size of {bytes} bytes exceeds the limit \
+                     set by {MAX_FILE_BYTES_ENV}",
+                    path.display()
+                ),
+            )
+            .await;
+    }
+
+    /// Text of the document at `path`: the one open in the editor, which may
+    /// have unsaved changes, else the file on disk.
+    async fn read_document(&self, path: &Path) -> Option<String> {
+        if let Some(text) = self.documents.read().await.text(path) {
+            return Some(text.to_owned());
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    /// Lines of the file at `path` ignored by markers in its `text`.
+    async fn ignored_lines(&self, path: &Path, text: &str) -> IgnoredLines {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some((cached, ignored)) = self.ignored.read().await.get(path)
+            && *cached == hash
+        {
+            return ignored.clone();
+        }
+        let ignored = IgnoredLines::parse(text);
+        self.ignored
+            .write()
+            .await
+            .insert(path.to_path_buf(), (hash, ignored.clone()));
+        ignored
+    }
+
+    /// Decorations of the local under `position`, except those on lines
+    /// ignored by markers of the file and those of types not shown by the
+    /// settings.
+    ///
+    /// Fails with [`progress::AnalysisStatus::Error`] if the file was not
+    /// analyzed, with [`progress::AnalysisStatus::Stale`] if it was analyzed
+    /// from another `text`, queueing a new analysis, and
+    /// with [`progress::AnalysisStatus::NoVariableSelected`] if there is no
+    /// local under the cursor. A selected local may have no decorations.
+    ///
+    /// The decorations come with the id of the selection, which changes with
+    /// the selected locals, the text and the analysis results of the file.
+    /// They are limited by [`Self::limit`], and computed at most once per
+    /// selection.
     async fn decos(
         &self,
         filepath: &Path,
-        position: Loc,
-    ) -> Result<Vec<decoration::Deco>, progress::AnalysisStatus> {
-        let mut selected = decoration::SelectLocal::new(position);
-        let mut error = progress::AnalysisStatus::Error;
-        if let Some(analyzed) = &*self.analyzed.read().await {
+        text: &str,
+        index: &SourceIndex,
+        ignored: &IgnoredLines,
+        position: CharOffset,
+        cursor: CursorOptions,
+    ) -> Result<Selection, progress::AnalysisStatus> {
+        let settings = self.settings.read().await.clone();
+        let options = SelectionOptions {
+            show_temporaries: *self.show_temporaries.read().await,
+            teaching_mode: *self.teaching_mode.read().await,
+            summary: cursor.summary,
+            selected_index: cursor.selected_index,
+            settings: &settings,
+        };
+        let file_key = path_norm::normalize_path(filepath);
+        let analyzed = self.analyzed.read().await;
+        let Some(Crate(files)) = &*analyzed else {
+            log::debug!("No analysis data available yet");
+            return Err(progress::AnalysisStatus::Error);
+        };
+        log::debug!("Analysis data available, {} files analyzed", files.len());
+        let Some(file) = files.get(&file_key) else {
             log::debug!(
-                "Analysis data available, {} files analyzed",
-                analyzed.0.len()
+                "File {} not found in analysis results. Available files: {:?}",
+                filepath.display(),
+                files.keys().collect::<Vec<_>>()
             );
-            let mut found_file = false;
-            for (filename, file) in &analyzed.0 {
-                if filepath == PathBuf::from(filename) {
-                    found_file = true;
-                    log::debug!("Found file {filename}, {} items", file.items.len());
-                    if !file.items.is_empty() {
-                        error = progress::AnalysisStatus::Finished;
-                    }
-                    for item in &file.items {
-                        range_ops::mir_visit(item, &mut selected);
-                    }
-                }
-            }
-            if !found_file {
-                log::debug!(
-                    "File {} not found in analysis results. Available files: {:?}",
-                    filepath.display(),
-                    analyzed.0.keys().collect::<Vec<_>>()
-                );
-            }
+            return Err(progress::AnalysisStatus::Error);
+        };
+        let source_hash = mir_cache::source_hash(text);
+        if file.source_hash != source_hash {
+            log::debug!("Results of {} are stale", filepath.display());
+            // queueing spawns an analysis, which needs to write the results
+            drop(analyzed);
+            self.queue_reanalysis(filepath, &source_hash).await;
+            return Err(progress::AnalysisStatus::Stale);
+        }
 
-            log::debug!("Selected local: {:?}", selected.selected());
-            let mut calc = decoration::CalcDecos::new(selected.selected().iter().copied());
-            for (filename, file) in &analyzed.0 {
-                if filepath == PathBuf::from(filename) {
-                    for item in &file.items {
-                        range_ops::mir_visit(item, &mut calc);
-                    }
-                }
-            }
-            calc.handle_overlapping();
-            let decos = calc.decorations();
+        log::debug!("Found file {file_key}, {} items", file.items.len());
+        let bounds = self.bounds.read().await;
+        let file_selection = FileSelection::new(
+            file,
+            text,
+            index,
+            position,
+            bounds.get(&file_key).map(Vec::as_slice),
+            &options,
+        );
+        drop(bounds);
+        let locals = &file_selection.locals;
+        log::debug!("Selected local: {locals:?}");
+        if locals.is_empty() {
+            return Err(progress::AnalysisStatus::NoVariableSelected);
+        }
+        let selection_options = (
+            options.summary,
+            options.show_temporaries,
+            options.teaching_mode,
+            &settings.display_kinds,
+        );
+        let selection_id = self
+            .selection_id(filepath, locals, &source_hash, selection_options)
+            .await;
+        let cached = self.cursor_cache.write().await.get(filepath, selection_id);
+        let decos = if let Some(decos) = cached {
+            log::debug!("Reuse {} decorations of the selection", decos.len());
+            decos
+        } else {
+            let decos = file_selection.decorations(index, ignored, &options);
             log::debug!("Calculated {} decorations", decos.len());
-            if decos.is_empty() {
-                Err(error)
-            } else {
-                Ok(decos)
-            }
+            self.cursor_cache.write().await.insert(
+                filepath.to_path_buf(),
+                selection_id,
+                decos.clone(),
+            );
+            decos
+        };
+        let mut selection = self.limit(selection_id, decos, position, index).await;
+        let FileSelection {
+            items, candidates, ..
+        } = file_selection;
+        selection.candidates = candidates;
+        selection.functions =
+            decoration::FunctionInfo::of_decorations(&items, &selection.items, index);
+        Ok(selection)
+    }
+
+    /// `decos` of the selection with `selection_id`, limited to the
+    /// `max_decorations` initialization option around `position`.
+    async fn limit(
+        &self,
+        selection_id: u64,
+        decos: Vec<decoration::Deco>,
+        position: CharOffset,
+        index: &SourceIndex,
+    ) -> Selection {
+        let total = decos.len();
+        let max_decorations = *self.max_decorations.read().await;
+        let decos = lsp_budget::limit(decos, position, max_decorations);
+        let id = if decos.len() < total {
+            log::debug!("Limited {total} decorations to {}", decos.len());
+            selection_hash((selection_id, u32::from(position)))
         } else {
-            log::debug!("No analysis data available yet");
-            Err(error)
+            selection_id
+        };
+        Selection {
+            id,
+            items: decos.iter().map(|deco| deco.to_lsp_range(index)).collect(),
+            total,
+            candidates: Vec::new(),
+            functions: BTreeMap::new(),
         }
     }
 
+    /// Id of the decorations of `locals` in the file at `filepath` with the
+    /// text of `source_hash`, computed with `options`, which changes with the
+    /// analysis results of the file.
+    async fn selection_id(
+        &self,
+        filepath: &Path,
+        locals: &[FnLocal],
+        source_hash: &str,
+        options: impl Hash,
+    ) -> u64 {
+        let file_key = path_norm::normalize_path(filepath);
+        let updated = self
+            .updated
+            .read()
+            .await
+            .iter()
+            .find(|(filename, _)| **filename == file_key)
+            .map(|(_, time)| *time);
+        selection_hash((locals, source_hash, updated, options))
+    }
+
+    /// Slowest analyzed functions and analysis totals per crate.
+    pub async fn stats(&self, params: StatsRequest) -> jsonrpc::Result<StatsResponse> {
+        Ok(StatsResponse::new(&*self.stats.read().await, params.limit))
+    }
+
+    /// Files with analysis results and the packages still being analyzed.
+    pub async fn status(&self) -> jsonrpc::Result<StatusReport> {
+        let analyzers = self.analyzers.read().await.clone();
+        let pending_packages = self
+            .process_tokens
+            .read()
+            .await
+            .values()
+            .flat_map(|(target, scope, _)| match scope {
+                ProcessScope::Workspace => analyzers
+                    .iter()
+                    .filter(|analyzer| analyzer.target_path() == target)
+                    .flat_map(Analyzer::members)
+                    .collect(),
+                ProcessScope::Package(member) => vec![member.clone()],
+                ProcessScope::File(path) | ProcessScope::WarmUp(path) => {
+                    vec![path.display().to_string()]
+                }
+            })
+            .collect();
+        Ok(StatusReport::new(
+            *self.status.read().await,
+            self.analyzed.read().await.as_ref(),
+            &*self.updated.read().await,
+            pending_packages,
+            self.failed_crates.read().await.clone(),
+        ))
+    }
+
+    /// Decorations at the cursor, or only a note that they are unchanged if
+    /// the client has those of the current selection.
     pub async fn cursor(
         &self,
         params: decoration::CursorRequest,
-    ) -> jsonrpc::Result<decoration::Decorations> {
+    ) -> jsonrpc::Result<decoration::CursorResponse> {
+        let previous_selection_id = params.previous_selection_id;
+        let decorations = self.decorations_at_cursor(params).await;
+        Ok(decoration::CursorResponse::new(
+            decorations,
+            previous_selection_id,
+        ))
+    }
+
+    async fn decorations_at_cursor(
+        &self,
+        params: decoration::CursorRequest,
+    ) -> decoration::Decorations {
         let is_analyzed = self.analyzed.read().await.is_some();
         let status = *self.status.read().await;
         if let Some(path) = params.path()
-            && let Ok(text) = fs::read_to_string(&path)
+            && let Some(text) = self.read_document(&path).await
         {
+            let index = self.documents.read().await.index(&text);
             let position = params.position();
-            let pos = Loc::from(text_conversion::line_char_to_index(
-                &text,
-                position.line,
-                position.character,
-            ));
-            let (decos, status) = match self.decos(&path, pos).await {
-                Ok(v) => (v, status),
+            let ignored = self.ignored_lines(&path, &text).await;
+            if ignored.contains(position.line) {
+                return decoration::Decorations {
+                    is_analyzed,
+                    status: progress::AnalysisStatus::Finished,
+                    path: Some(path),
+                    selection_id: None,
+                    items: Vec::new(),
+                    truncated: false,
+                    total: 0,
+                    candidates: Vec::new(),
+                    functions: BTreeMap::new(),
+                };
+            }
+            let pos = index.position_to_char(position);
+            let (selection, status) = match self
+                .decos(
+                    &path,
+                    &text,
+                    &index,
+                    &ignored,
+                    pos,
+                    CursorOptions {
+                        summary: params.summary(),
+                        selected_index: params.selected_index,
+                    },
+                )
+                .await
+            {
+                Ok(selection) => (Some(selection), status),
+                Err(progress::AnalysisStatus::Stale) => (None, progress::AnalysisStatus::Stale),
                 Err(e) => (
-                    Vec::new(),
+                    None,
                     if status == progress::AnalysisStatus::Finished {
                         e
                     } else {
@@ -280,62 +1332,182 @@ impl Backend {
                     },
                 ),
             };
-            let items = decos.into_iter().map(|v| v.to_lsp_range(&text)).collect();
-            return Ok(decoration::Decorations {
+            let (selection_id, items, total, candidates, functions) = selection.map_or(
+                (None, Vec::new(), 0, Vec::new(), BTreeMap::new()),
+                |selection| {
+                    (
+                        Some(selection.id),
+                        selection.items,
+                        selection.total,
+                        selection.candidates,
+                        selection.functions,
+                    )
+                },
+            );
+            return decoration::Decorations {
                 is_analyzed,
                 status,
                 path: Some(path),
+                selection_id,
+                truncated: items.len() < total,
+                total,
                 items,
-            });
+                candidates,
+                functions,
+            };
         }
-        Ok(decoration::Decorations {
+        decoration::Decorations {
             is_analyzed,
             status,
             path: None,
+            selection_id: None,
             items: Vec::new(),
+            truncated: false,
+            total: 0,
+            candidates: Vec::new(),
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// Decorations at the cursor summarized per line, see [`Self::cursor`].
+    pub async fn line_hints(
+        &self,
+        params: decoration::CursorRequest,
+    ) -> jsonrpc::Result<LineHintsResponse> {
+        let decorations = self.decorations_at_cursor(params).await;
+        Ok(LineHintsResponse::new(
+            decorations.is_analyzed,
+            decorations.status,
+            decorations.path,
+            &decorations.items,
+        ))
+    }
+
+    /// Publish the ownership diagnostics of `path` once no newer cursor
+    /// position arrived for [`lsp_ownership::DEBOUNCE`].
+    ///
+    /// A pending publication picks up the latest position of the file, so
+    /// superseded positions are never computed.
+    async fn schedule_ownership_diagnostics(&self, path: PathBuf) {
+        let mut tasks = self.ownership_tasks.write().await;
+        if tasks.get(&path).is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+        let backend = self.clone();
+        let file = path.clone();
+        let task = tokio::spawn(async move {
+            time::sleep(lsp_ownership::DEBOUNCE).await;
+            // positions arriving from now on schedule another publication
+            backend.ownership_tasks.write().await.remove(&file);
+            let position = backend.ownership_state.read().await.enabled_position(&file);
+            if let Some(position) = position {
+                backend.publish_ownership_diagnostics(&file, position).await;
+            }
+        });
+        tasks.insert(path, task);
+    }
+
+    /// Schedule the ownership diagnostics of the enabled files among `paths`,
+    /// the keys of analyzed files, or of all enabled files.
+    ///
+    /// Boxed, as publishing may start the analysis awaiting this.
+    fn republish_ownership_diagnostics<'a>(
+        &'a self,
+        paths: Option<&'a [String]>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            let enabled = self.ownership_state.read().await.enabled();
+            for (path, _) in enabled {
+                if paths.is_none_or(|paths| paths.contains(&path_norm::normalize_path(&path))) {
+                    self.schedule_ownership_diagnostics(path).await;
+                }
+            }
         })
     }
 
-    /// Publish ownership decorations as standard LSP diagnostics for a file
+    /// Publish ownership decorations as standard LSP diagnostics for a file,
+    /// unless they are the ones published last or the file was disabled
+    /// meanwhile
     async fn publish_ownership_diagnostics(&self, path: &Path, position: lsp_types::Position) {
         log::debug!(
             "publish_ownership_diagnostics called for {} at {position:?}",
             path.display()
         );
-        if let Ok(text) = fs::read_to_string(path) {
-            let pos = Loc::from(text_conversion::line_char_to_index(
-                &text,
-                position.line,
-                position.character,
-            ));
-
-            let diagnostics = match self.decos(path, pos).await {
-                Ok(decos) => {
-                    log::debug!("Got {} decorations", decos.len());
-                    decos
-                        .into_iter()
-                        .filter(decoration::Deco::should_show_as_diagnostic)
-                        .map(|d| d.to_lsp_range(&text).to_diagnostic())
-                        .collect()
-                }
-                Err(e) => {
-                    log::debug!("No decorations, status: {e:?}");
-                    Vec::new()
-                }
-            };
+        let Some(text) = self.read_document(path).await else {
+            log::error!("Failed to read file {}", path.display());
+            return;
+        };
+        let position = lsp_ownership::clamp_to_text(position, &text);
+        let index = self.documents.read().await.index(&text);
+        let ignored = self.ignored_lines(path, &text).await;
+        let pos = index.position_to_char(position);
+        let uri = lsp_types::Url::from_file_path(path).unwrap();
 
-            log::debug!("Publishing {} diagnostics", diagnostics.len());
-            let uri = lsp_types::Url::from_file_path(path).unwrap();
-            self.client
-                .publish_diagnostics(uri, diagnostics, None)
-                .await;
+        let decos = if ignored.contains(position.line) {
+            log::debug!("Cursor on an ignored line");
+            Ok(Vec::new())
         } else {
-            log::error!("Failed to read file {}", path.display());
+            let cursor = CursorOptions {
+                summary: true,
+                selected_index: None,
+            };
+            self.decos(path, &text, &index, &ignored, pos, cursor)
+                .await
+                .map(|selection| selection.items)
+        };
+        let diagnostics: Vec<_> = match decos {
+            Ok(decos) => {
+                log::debug!("Got {} decorations", decos.len());
+                decos
+                    .into_iter()
+                    .filter(decoration::Deco::should_show_as_diagnostic)
+                    .map(|d| d.to_diagnostic(&uri))
+                    .collect()
+            }
+            Err(progress::AnalysisStatus::NoVariableSelected) => {
+                log::debug!("No variable selected, keep the last diagnostics");
+                return;
+            }
+            Err(e) => {
+                log::debug!("No decorations, status: {e:?}");
+                Vec::new()
+            }
+        };
+
+        // held while publishing, so that disabling the file clears after
+        let mut state = self.ownership_state.write().await;
+        if state.enabled_position(path).is_none() {
+            log::debug!("Ownership diagnostics were disabled meanwhile");
+            return;
+        }
+        if !state.record_published(path, &diagnostics) {
+            log::debug!("Diagnostics unchanged, not publishing");
+            return;
         }
+        log::debug!("Publishing {} diagnostics", diagnostics.len());
+        self.client
+            .publish_diagnostics(uri, diagnostics, None)
+            .await;
+        drop(state);
+    }
+
+    /// Forget a file's ownership diagnostics position, cancel their pending
+    /// publication and clear them
+    async fn disable_ownership_diagnostics(&self, path: &Path) {
+        self.ownership_state
+            .write()
+            .await
+            .enabled_files
+            .remove(path);
+        if let Some(task) = self.ownership_tasks.write().await.remove(path) {
+            task.abort();
+        }
+        self.clear_ownership_diagnostics(path).await;
     }
 
     /// Clear ownership diagnostics for a file
     async fn clear_ownership_diagnostics(&self, path: &Path) {
+        self.ownership_state.write().await.forget_published(path);
         if let Ok(uri) = lsp_types::Url::from_file_path(path) {
             self.client.publish_diagnostics(uri, Vec::new(), None).await;
         }
@@ -362,22 +1534,23 @@ impl Backend {
                         "Parsed args: path={}, position={position:?}",
                         path.display()
                     );
-                    let mut state = self.ownership_state.write().await;
-                    let entry = state
-                        .enabled_files
-                        .entry(path.clone())
-                        .or_insert((false, None));
-                    entry.0 = !entry.0;
-                    entry.1 = Some(position);
-                    let enabled = entry.0;
-                    drop(state);
-
+                    let enabled = self
+                        .ownership_state
+                        .read()
+                        .await
+                        .enabled_position(&path)
+                        .is_none();
                     if enabled {
-                        log::debug!("Publishing ownership diagnostics for {}", path.display());
-                        self.publish_ownership_diagnostics(&path, position).await;
+                        self.ownership_state
+                            .write()
+                            .await
+                            .enabled_files
+                            .insert(path.clone(), (true, Some(position)));
+                        log::debug!("Scheduling ownership diagnostics for {}", path.display());
+                        self.schedule_ownership_diagnostics(path).await;
                     } else {
                         log::debug!("Clearing ownership diagnostics for {}", path.display());
-                        self.clear_ownership_diagnostics(&path).await;
+                        self.disable_ownership_diagnostics(&path).await;
                     }
                     Ok(Some(serde_json::json!({ "enabled": enabled })))
                 } else {
@@ -394,7 +1567,7 @@ impl Backend {
                         .enabled_files
                         .insert(path.clone(), (true, Some(position)));
                     drop(state);
-                    self.publish_ownership_diagnostics(&path, position).await;
+                    self.schedule_ownership_diagnostics(path).await;
                     Ok(Some(serde_json::json!({ "enabled": true })))
                 } else {
                     Err(jsonrpc::Error::invalid_params(
@@ -404,10 +1577,7 @@ impl Backend {
             }
             CMD_DISABLE_OWNERSHIP => {
                 if let Some((path, _)) = Self::parse_position_args(&params.arguments) {
-                    let mut state = self.ownership_state.write().await;
-                    state.enabled_files.insert(path.clone(), (false, None));
-                    drop(state);
-                    self.clear_ownership_diagnostics(&path).await;
+                    self.disable_ownership_diagnostics(&path).await;
                     Ok(Some(serde_json::json!({ "enabled": false })))
                 } else {
                     Err(jsonrpc::Error::invalid_params(
@@ -416,13 +1586,64 @@ impl Backend {
                 }
             }
             CMD_ANALYZE => {
-                self.do_analyze().await;
+                // Arguments: [] or [{ scope, uri, all_targets, all_features }]
+                let params = match params.arguments.first() {
+                    Some(args) => serde_json::from_value(args.clone()).map_err(|e| {
+                        jsonrpc::Error::invalid_params(format!("invalid analyze arguments: {e}"))
+                    })?,
+                    None => AnalyzeRequest::default(),
+                };
+                self.analyze_scope(params).await?;
                 Ok(Some(serde_json::json!({ "status": "analyzing" })))
             }
+            CMD_COMPARE_SELECTIONS => {
+                // Arguments: [document_uri, line_a, character_a, line_b, character_b]
+                let Some((uri, a, b)) = Self::parse_selection_pair_args(&params.arguments) else {
+                    return Err(jsonrpc::Error::invalid_params(
+                        "Expected arguments: [document_uri, line_a, character_a, line_b, \
+                         character_b]",
+                    ));
+                };
+                let decorations_at = |position| {
+                    self.decorations_at_cursor(decoration::CursorRequest {
+                        position,
+                        document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                        summary: None,
+                        previous_selection_id: None,
+                        selected_index: None,
+                    })
+                };
+                let a = decorations_at(a).await.items;
+                let b = decorations_at(b).await.items;
+                Ok(Some(serde_json::json!(SelectionDiff::new(a, b))))
+            }
             _ => Err(jsonrpc::Error::method_not_found()),
         }
     }
 
+    /// Parse the arguments of [`CMD_COMPARE_SELECTIONS`]:
+    /// [`uri_string`, `line_a`, `character_a`, `line_b`, `character_b`]
+    fn parse_selection_pair_args(
+        args: &[serde_json::Value],
+    ) -> Option<(lsp_types::Url, lsp_types::Position, lsp_types::Position)> {
+        let [uri, numbers @ ..] = args else {
+            return None;
+        };
+        let uri = lsp_types::Url::parse(uri.as_str()?).ok()?;
+        let numbers = numbers
+            .iter()
+            .map(|n| u32::try_from(n.as_u64()?).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [line_a, character_a, line_b, character_b] = numbers[..] else {
+            return None;
+        };
+        Some((
+            uri,
+            lsp_types::Position::new(line_a, character_a),
+            lsp_types::Position::new(line_b, character_b),
+        ))
+    }
+
     /// Parse position arguments from command: [`uri_string`, line, character]
     fn parse_position_args(args: &[serde_json::Value]) -> Option<(PathBuf, lsp_types::Position)> {
         if args.is_empty() {
@@ -441,39 +1662,109 @@ impl Backend {
         Some((path, lsp_types::Position { line, character }))
     }
 
+    /// Analyze the workspace at `path` once.
+    ///
+    /// Returns the results if anything was analyzed, with the files of all
+    /// crates merged into one crate named after `path` and the analysis
+    /// timings as meta section, and the packages that could not be analyzed
+    /// with the reason. Fails if `path` cannot be analyzed at all.
     pub async fn check_with_options(
         path: impl AsRef<Path>,
-        all_targets: bool,
+        target_kinds: Vec<TargetKind>,
         all_features: bool,
-    ) -> bool {
+        force: bool,
+        polonius_algo: Option<AlgorithmChoice>,
+    ) -> Result<Option<(Workspace, Vec<(String, String)>)>, AnalyzerError> {
         let path = path.as_ref();
         let (service, _) = LspService::build(Self::new).finish();
         let backend = service.inner();
+        *backend.polonius_algo.write().await = polonius_algo;
+        *backend.target_kinds.write().await = target_kinds;
 
-        if backend.add_analyze_target(path).await {
-            backend
-                .analyze_with_options(all_targets, all_features)
-                .await;
-            while backend.processes.write().await.join_next().await.is_some() {}
-            backend
-                .analyzed
-                .read()
-                .await
-                .as_ref()
-                .is_some_and(|v| !v.0.is_empty())
-        } else {
-            false
+        backend.add_analyze_target(path).await?;
+        backend
+            .analyze_with_options(all_features, false, force)
+            .await;
+        while backend.processes.write().await.join_next().await.is_some() {}
+        let Some(analyzed) = backend
+            .analyzed
+            .write()
+            .await
+            .take()
+            .filter(|v| !v.0.is_empty())
+        else {
+            return Ok(None);
+        };
+        let stats = mem::take(&mut *backend.stats.write().await);
+        let failed_crates = mem::take(&mut *backend.failed_crates.write().await);
+        Ok(Some((
+            Workspace {
+                crates: HashMap::from([(path.display().to_string(), analyzed)]),
+                meta: Some(stats),
+            },
+            failed_crates,
+        )))
+    }
+
+    #[must_use]
+    pub fn subprocesses(&self) -> Subprocesses {
+        Subprocesses {
+            processes: self.processes.clone(),
+            process_tokens: self.process_tokens.clone(),
         }
     }
 
     pub async fn shutdown_subprocesses(&self) {
-        {
-            let mut tokens = self.process_tokens.write().await;
-            while let Some((_, token)) = tokens.pop_last() {
-                token.cancel();
-            }
+        self.subprocesses().shutdown().await;
+    }
+
+    /// Stop the analyses and exit once the client process with `process_id`
+    /// is no longer alive. The client is watched only once per server.
+    fn watch_client(&self, process_id: u32) {
+        if self.client_watched.swap(true, Ordering::SeqCst) {
+            return;
         }
-        self.processes.write().await.shutdown().await;
+        let subprocesses = self.subprocesses();
+        tokio::spawn(async move {
+            lsp_health::wait_for_client_exit(
+                ClientProcess(process_id),
+                lsp_health::health_interval(),
+            )
+            .await;
+            log::warn!("client process {process_id} is dead, shutting down");
+            subprocesses.shutdown().await;
+            process::exit(0);
+        });
+    }
+}
+
+/// LSP diagnostic for a compiler error at `span`, in the position encoding
+/// of `index`, the index of the text of its file, if it can be read.
+fn build_diagnostic(
+    message: String,
+    span: &DiagnosticSpan,
+    index: Option<&SourceIndex>,
+) -> lsp_types::Diagnostic {
+    // compiler spans are 1-based and count characters
+    let position = |line: usize, column: usize| {
+        let position = LineCol::new(
+            u32::try_from(line.saturating_sub(1)).unwrap_or(u32::MAX),
+            u32::try_from(column.saturating_sub(1)).unwrap_or(u32::MAX),
+        );
+        index.map_or_else(
+            || lsp_types::Position::new(position.line, position.character),
+            |index| index.line_col_to_position(position),
+        )
+    };
+    lsp_types::Diagnostic {
+        range: lsp_types::Range {
+            start: position(span.line_start, span.column_start),
+            end: position(span.line_end, span.column_end),
+        },
+        severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+        source: Some(BUILD_DIAGNOSTIC_SOURCE.to_string()),
+        message,
+        ..Default::default()
     }
 }
 
@@ -483,6 +1774,28 @@ impl LanguageServer for Backend {
         &self,
         params: lsp_types::InitializeParams,
     ) -> jsonrpc::Result<lsp_types::InitializeResult> {
+        let options: InitializationOptions = match params.initialization_options {
+            Some(options) => serde_json::from_value(options).map_err(|e| {
+                jsonrpc::Error::invalid_params(format!("invalid initialization options: {e}"))
+            })?,
+            None => InitializationOptions::default(),
+        };
+        *self.polonius_algo.write().await = options.polonius_algo;
+        *self.target_kinds.write().await = options.target_kinds;
+        *self.show_temporaries.write().await = options.show_temporaries;
+        *self.teaching_mode.write().await = options.teaching_mode;
+        *self.max_decorations.write().await = options
+            .max_decorations
+            .unwrap_or(lsp_budget::DEFAULT_MAX_DECORATIONS);
+        let position_encoding = PositionEncoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        );
+        self.documents.write().await.set_encoding(position_encoding);
+
         let mut workspaces = Vec::new();
         if let Some(root) = params.root_uri
             && let Ok(path) = root.to_file_path()
@@ -493,9 +1806,9 @@ impl LanguageServer for Backend {
             workspaces.extend(wss.iter().filter_map(|v| v.uri.to_file_path().ok()));
         }
         for path in workspaces {
-            self.add_analyze_target(&path).await;
+            self.add_analyze_target_or_warn(&path).await;
         }
-        self.do_analyze().await;
+        self.do_analyze(false).await;
 
         let sync_options = lsp_types::TextDocumentSyncOptions {
             open_close: Some(true),
@@ -517,12 +1830,14 @@ impl LanguageServer for Backend {
                 CMD_ENABLE_OWNERSHIP.to_string(),
                 CMD_DISABLE_OWNERSHIP.to_string(),
                 CMD_ANALYZE.to_string(),
+                CMD_COMPARE_SELECTIONS.to_string(),
             ],
             work_done_progress_options: lsp_types::WorkDoneProgressOptions::default(),
         };
         // Advertise code action support
         let code_action_provider = lsp_types::CodeActionProviderCapability::Simple(true);
         let server_cap = lsp_types::ServerCapabilities {
+            position_encoding: Some(position_encoding.kind()),
             text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Options(sync_options)),
             workspace: Some(workspace_cap),
             execute_command_provider: Some(execute_command_provider),
@@ -533,17 +1848,6 @@ impl LanguageServer for Backend {
             capabilities: server_cap,
             ..Default::default()
         };
-        let health_checker = async move {
-            if let Some(process_id) = params.process_id {
-                loop {
-                    time::sleep(time::Duration::from_secs(30)).await;
-                    assert!(
-                        process_alive::state(process_alive::Pid::from(process_id)).is_alive(),
-                        "The client process is dead"
-                    );
-                }
-            }
-        };
         if params
             .capabilities
             .window
@@ -552,7 +1856,10 @@ impl LanguageServer for Backend {
         {
             *self.work_done_progress.write().await = true;
         }
-        tokio::spawn(health_checker);
+        // without a process id, the end of the connection stops the server
+        if let Some(process_id) = params.process_id {
+            self.watch_client(process_id);
+        }
         Ok(init_res)
     }
 
@@ -560,28 +1867,101 @@ impl LanguageServer for Backend {
         &self,
         params: lsp_types::DidChangeWorkspaceFoldersParams,
     ) -> () {
+        for removed in params.event.removed {
+            if let Ok(path) = removed.uri.to_file_path() {
+                self.remove_analyze_target(&path).await;
+            }
+        }
         for added in params.event.added {
             if let Ok(path) = added.uri.to_file_path()
-                && self.add_analyze_target(&path).await
+                && self.add_analyze_target_or_warn(&path).await.is_some()
             {
-                self.do_analyze().await;
+                self.do_analyze(false).await;
             }
         }
     }
 
     async fn did_open(&self, params: lsp_types::DidOpenTextDocumentParams) {
-        if let Ok(path) = params.text_document.uri.to_file_path()
-            && path.is_file()
-            && params.text_document.language_id == "rust"
-            && self.add_analyze_target(&path).await
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return;
+        };
+        if params.text_document.language_id != "rust" {
+            return;
+        }
+        self.documents
+            .write()
+            .await
+            .open(path.clone(), params.text_document.text);
+        self.touch(&path).await;
+        if !path.is_file() {
+            return;
+        }
+        if let Some(bytes) = oversized(&path) {
+            log::warn!(
+                "skip analysis of {}, its {bytes} bytes exceed the limit of {MAX_FILE_BYTES_ENV}",
+                path.display()
+            );
+            *self.status.write().await = progress::AnalysisStatus::Skipped;
+            Self::warn_skipped(&self.client, &path, bytes).await;
+        } else if let Some(added) = self.add_analyze_target_or_warn(&path).await {
+            // Opening another file of a known target only needs a new analysis
+            // when the previous results were discarded and none is running.
+            let stale = self.analyzed.read().await.is_none()
+                && *self.status.read().await != progress::AnalysisStatus::Analyzing;
+            if added || stale {
+                self.do_analyze(false).await;
+            }
+        }
+    }
+
+    /// Apply the changes to the open document. Its results turn stale, and
+    /// are analyzed again once decorations are requested.
+    async fn did_change(&self, params: lsp_types::DidChangeTextDocumentParams) {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return;
+        };
+        if !self
+            .documents
+            .write()
+            .await
+            .change(&path, params.content_changes)
         {
-            self.do_analyze().await;
+            log::debug!("{} changed without being open", path.display());
+            return;
         }
+        self.touch(&path).await;
     }
 
-    async fn did_change(&self, _params: lsp_types::DidChangeTextDocumentParams) {
-        *self.analyzed.write().await = None;
-        self.shutdown_subprocesses().await;
+    async fn did_close(&self, params: lsp_types::DidCloseTextDocumentParams) {
+        if let Ok(path) = params.text_document.uri.to_file_path() {
+            self.documents.write().await.close(&path);
+        }
+    }
+
+    async fn did_save(&self, _params: lsp_types::DidSaveTextDocumentParams) {
+        if self.settings.read().await.auto_analyze_on_save {
+            self.do_analyze(false).await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: lsp_types::DidChangeConfigurationParams) {
+        let Some(settings) = Settings::from_configuration(&params.settings) else {
+            log::debug!("no `{SETTINGS_SECTION}` section in the changed configuration");
+            return;
+        };
+        log::info!("settings changed: {settings:?}");
+        let previous = mem::replace(&mut *self.settings.write().await, settings.clone());
+        self.cursor_cache.write().await.clear();
+        if previous.analyzes_differently(&settings) {
+            *self.analyzed.write().await = None;
+            self.bounds.write().await.clear();
+            self.do_analyze(false).await;
+        } else if previous.display_kinds != settings.display_kinds {
+            let enabled = self.ownership_state.read().await.enabled();
+            for (path, position) in enabled {
+                self.publish_ownership_diagnostics(&path, position).await;
+            }
+        }
     }
 
     async fn code_action(
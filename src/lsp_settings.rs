@@ -0,0 +1,162 @@
+//! Settings changed at runtime by the `workspace/didChangeConfiguration`
+//! notification.
+
+use serde_json::Value;
+
+/// Section of the configuration holding the settings
+pub const SETTINGS_SECTION: &str = "ferrous-owl";
+
+/// Section read if there is no [`SETTINGS_SECTION`], as named by clients
+/// configured for `RustOwl`
+const LEGACY_SETTINGS_SECTION: &str = "rustowl";
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Settings {
+    /// Analyze targets of every kind instead of the requested ones
+    pub all_targets: bool,
+    /// Analyze with all features of the packages enabled
+    pub all_features: bool,
+    /// Decoration types shown, as in their `type` field, or all if empty
+    pub display_kinds: Vec<String>,
+    /// Analyze again when a document is saved
+    pub auto_analyze_on_save: bool,
+}
+
+impl Settings {
+    /// Settings of the section in the `settings` of a configuration change,
+    /// or `None` if it has no such section.
+    ///
+    /// Settings missing from the section keep their defaults, as do those
+    /// with values of the wrong type. Unknown settings are ignored.
+    #[must_use]
+    pub fn from_configuration(settings: &Value) -> Option<Self> {
+        let section = settings
+            .get(SETTINGS_SECTION)
+            .or_else(|| settings.get(LEGACY_SETTINGS_SECTION))?
+            .as_object()?;
+        let mut parsed = Self::default();
+        for (key, value) in section {
+            match (key.as_str(), value) {
+                ("allTargets", Value::Bool(all_targets)) => parsed.all_targets = *all_targets,
+                ("allFeatures", Value::Bool(all_features)) => parsed.all_features = *all_features,
+                ("autoAnalyzeOnSave", Value::Bool(on_save)) => {
+                    parsed.auto_analyze_on_save = *on_save;
+                }
+                ("displayKinds", Value::Array(kinds)) if kinds.iter().all(Value::is_string) => {
+                    // diagnostic codes spell the types with dashes
+                    parsed.display_kinds = kinds
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|kind| kind.replace('-', "_"))
+                        .collect();
+                }
+                ("allTargets" | "allFeatures" | "autoAnalyzeOnSave" | "displayKinds", _) => {
+                    log::warn!("ignoring setting `{key}` with a value of the wrong type: {value}");
+                }
+                _ => log::debug!("ignoring unknown setting `{key}`"),
+            }
+        }
+        Some(parsed)
+    }
+
+    /// Whether decorations of type `kind` are shown.
+    #[must_use]
+    pub fn shows(&self, kind: &str) -> bool {
+        self.display_kinds.is_empty() || self.display_kinds.iter().any(|shown| shown == kind)
+    }
+
+    /// Whether results analyzed with these settings are invalid for `other`.
+    #[must_use]
+    pub const fn analyzes_differently(&self, other: &Self) -> bool {
+        self.all_targets != other.all_targets || self.all_features != other.all_features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_partial_settings_keep_defaults() {
+        let settings = Settings::from_configuration(&json!({
+            "ferrous-owl": { "allFeatures": true }
+        }))
+        .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                all_features: true,
+                ..Settings::default()
+            }
+        );
+        assert!(settings.shows("move"));
+        assert!(settings.shows("imm_borrow"));
+    }
+
+    #[test]
+    fn test_all_settings() {
+        let settings = Settings::from_configuration(&json!({
+            "ferrous-owl": {
+                "allTargets": true,
+                "allFeatures": false,
+                "displayKinds": ["move", "mut-borrow"],
+                "autoAnalyzeOnSave": true
+            }
+        }))
+        .unwrap();
+        assert!(settings.all_targets);
+        assert!(settings.auto_analyze_on_save);
+        assert_eq!(settings.display_kinds, vec!["move", "mut_borrow"]);
+        assert!(settings.shows("mut_borrow"));
+        assert!(!settings.shows("imm_borrow"));
+    }
+
+    #[test]
+    fn test_wrong_types_and_unknown_keys_are_ignored() {
+        let settings = Settings::from_configuration(&json!({
+            "rustowl": {
+                "allTargets": "yes",
+                "allFeatures": true,
+                "displayKinds": ["move", 1],
+                "autoAnalyzeOnSave": null,
+                "theme": "dark"
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                all_features: true,
+                ..Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_section() {
+        assert_eq!(Settings::from_configuration(&json!({})), None);
+        assert_eq!(Settings::from_configuration(&Value::Null), None);
+        assert_eq!(
+            Settings::from_configuration(&json!({ "ferrous-owl": [] })),
+            None
+        );
+    }
+
+    #[test]
+    fn test_analysis_options_changed() {
+        let current = Settings::default();
+        let display_only = Settings {
+            display_kinds: vec!["move".to_owned()],
+            auto_analyze_on_save: true,
+            ..Settings::default()
+        };
+        assert!(!current.analyzes_differently(&display_only));
+        let all_targets = Settings {
+            all_targets: true,
+            ..Settings::default()
+        };
+        assert!(current.analyzes_differently(&all_targets));
+    }
+}
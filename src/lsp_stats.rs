@@ -0,0 +1,179 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{AnalysisTimings, WorkspaceMeta};
+
+/// Number of functions reported when the request does not give a limit
+const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct StatsRequest {
+    /// Number of slowest functions to report
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FunctionStats {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub file: String,
+    pub fn_id: u32,
+    #[serde(flatten)]
+    pub timings: AnalysisTimings,
+    pub total_ms: f64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CrateStats {
+    pub name: String,
+    /// Number of analyzed functions
+    pub functions: usize,
+    /// Number of the functions read from the MIR cache
+    pub cache_hits: usize,
+    #[serde(flatten)]
+    pub timings: AnalysisTimings,
+    pub total_ms: f64,
+}
+
+/// Slowest functions and totals per crate, both slowest first
+#[derive(Serialize, Clone, Debug)]
+pub struct StatsResponse {
+    pub slowest: Vec<FunctionStats>,
+    pub crates: Vec<CrateStats>,
+}
+
+impl StatsResponse {
+    #[must_use]
+    pub fn new(meta: &WorkspaceMeta, limit: Option<usize>) -> Self {
+        let mut slowest = Vec::new();
+        let mut crates = Vec::new();
+        for (krate, files) in &meta.timings {
+            let mut total = CrateStats {
+                name: krate.clone(),
+                functions: 0,
+                cache_hits: 0,
+                timings: AnalysisTimings::default(),
+                total_ms: 0.0,
+            };
+            for (file, functions) in files {
+                for (&fn_id, &timings) in functions {
+                    total.functions += 1;
+                    total.cache_hits += usize::from(timings.cached);
+                    total.timings = total.timings + timings;
+                    slowest.push(FunctionStats {
+                        krate: krate.clone(),
+                        file: file.clone(),
+                        fn_id,
+                        timings,
+                        total_ms: timings.total_ms(),
+                    });
+                }
+            }
+            total.total_ms = total.timings.total_ms();
+            crates.push(total);
+        }
+
+        slowest.sort_by(|a, b| {
+            b.total_ms
+                .total_cmp(&a.total_ms)
+                .then_with(|| (&a.file, a.fn_id).cmp(&(&b.file, b.fn_id)))
+        });
+        slowest.truncate(limit.unwrap_or(DEFAULT_LIMIT));
+        crates.sort_by(|a, b| {
+            b.total_ms
+                .total_cmp(&a.total_ms)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Self { slowest, crates }
+    }
+}
+
+impl fmt::Display for StatsResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>10} {:>10} {:>10}  function",
+            "total ms", "init", "polonius", "collect"
+        )?;
+        for function in &self.slowest {
+            let timings = function.timings;
+            writeln!(
+                f,
+                "{:>10.2} {:>10.2} {:>10.2} {:>10.2}  {}:{} (fn {})",
+                function.total_ms,
+                timings.init_ms,
+                timings.polonius_ms,
+                timings.collect_ms,
+                function.krate,
+                function.file,
+                function.fn_id
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>10} {:>10} {:>10} {:>10}  crate (functions, cached)",
+            "total ms", "init", "polonius", "collect"
+        )?;
+        for krate in &self.crates {
+            let timings = krate.timings;
+            writeln!(
+                f,
+                "{:>10.2} {:>10.2} {:>10.2} {:>10.2}  {} ({}, {})",
+                krate.total_ms,
+                timings.init_ms,
+                timings.polonius_ms,
+                timings.collect_ms,
+                krate.name,
+                krate.functions,
+                krate.cache_hits
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timings(polonius_ms: f64) -> AnalysisTimings {
+        AnalysisTimings {
+            init_ms: 1.0,
+            polonius_ms,
+            collect_ms: 0.5,
+            cached: polonius_ms < f64::EPSILON,
+        }
+    }
+
+    #[test]
+    fn test_stats_sorted_and_limited() {
+        let mut meta = WorkspaceMeta::default();
+        for (krate, fn_id, polonius_ms) in
+            [("a", 1, 2.0), ("a", 2, 10.0), ("a", 4, 0.0), ("b", 3, 4.0)]
+        {
+            meta.merge(WorkspaceMeta::with_timings(
+                krate.to_owned(),
+                format!("{krate}/lib.rs"),
+                fn_id,
+                timings(polonius_ms),
+            ));
+        }
+
+        let stats = StatsResponse::new(&meta, Some(2));
+        let slowest: Vec<_> = stats.slowest.iter().map(|f| f.fn_id).collect();
+        assert_eq!(slowest, vec![2, 3], "slowest functions first");
+        assert!((stats.slowest[0].total_ms - 11.5).abs() < f64::EPSILON);
+
+        let crates: Vec<_> = stats
+            .crates
+            .iter()
+            .map(|c| (c.name.as_str(), c.functions, c.cache_hits))
+            .collect();
+        assert_eq!(crates, vec![("a", 3, 1), ("b", 1, 0)], "totals per crate");
+        assert!((stats.crates[0].timings.polonius_ms - 12.0).abs() < f64::EPSILON);
+    }
+}
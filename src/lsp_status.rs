@@ -0,0 +1,167 @@
+//! Which files have analysis results, reported by the `ferrous-owl/status`
+//! request and announced by the `ferrous-owl/didUpdateAnalysis` notification.
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::notification::Notification;
+
+use crate::{lsp_progress::AnalysisStatus, models::Crate};
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct FileStatus {
+    pub path: String,
+    /// Number of analyzed bodies, closures included
+    pub functions: usize,
+    /// Milliseconds since the Unix epoch at which results of the file last
+    /// arrived
+    pub last_updated: u128,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StatusReport {
+    pub status: AnalysisStatus,
+    /// Files with analysis results, by path
+    pub analyzed_files: Vec<FileStatus>,
+    /// Workspace members of the running analyses, by name
+    pub pending_packages: Vec<String>,
+    /// Workspace packages that could not be analyzed, with the reason
+    pub failed_crates: Vec<(String, String)>,
+}
+
+impl StatusReport {
+    /// Report on the results in `analyzed`, which arrived at the times in
+    /// `updated`.
+    #[must_use]
+    pub fn new(
+        status: AnalysisStatus,
+        analyzed: Option<&Crate>,
+        updated: &HashMap<String, SystemTime>,
+        mut pending_packages: Vec<String>,
+        failed_crates: Vec<(String, String)>,
+    ) -> Self {
+        let mut analyzed_files: Vec<_> = analyzed
+            .iter()
+            .flat_map(|krate| &krate.0)
+            .map(|(path, file)| FileStatus {
+                path: path.clone(),
+                functions: file.items_in("").count(),
+                last_updated: updated
+                    .get(path)
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_millis()),
+            })
+            .collect();
+        analyzed_files.sort_by(|a, b| a.path.cmp(&b.path));
+        pending_packages.sort();
+        pending_packages.dedup();
+        Self {
+            status,
+            analyzed_files,
+            pending_packages,
+            failed_crates,
+        }
+    }
+}
+
+/// Files whose analysis results were added or replaced
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct AnalysisUpdate {
+    pub paths: Vec<String>,
+}
+
+/// Sent to the client with the files of every analysis result that arrives,
+/// so that it can mark which files have data.
+pub enum DidUpdateAnalysis {}
+
+impl Notification for DidUpdateAnalysis {
+    type Params = AnalysisUpdate;
+    const METHOD: &'static str = "ferrous-owl/didUpdateAnalysis";
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::models::{File, Function, Item, ItemKind};
+
+    fn file(functions: u32) -> File {
+        File {
+            items: (1..=functions)
+                .map(|fn_id| Item::Function {
+                    span: None,
+                    kind: ItemKind::Fn,
+                    function: Function {
+                        fn_id,
+                        fn_key: u64::from(fn_id),
+                        fn_name: String::new(),
+                        arg_count: 0,
+                        basic_blocks: Vec::new(),
+                        decls: Vec::new(),
+                        captures: Vec::new(),
+                        borrow_edges: Vec::new(),
+                        clone_edges: Vec::new(),
+                        await_points: Vec::new(),
+                        part: None,
+                        context: String::new(),
+                    },
+                })
+                .collect(),
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        }
+    }
+
+    #[test]
+    fn test_status_report_lists_files() {
+        let analyzed = Crate(HashMap::from([
+            ("/ws/src/lib.rs".to_owned(), file(2)),
+            ("/ws/src/a.rs".to_owned(), file(1)),
+        ]));
+        let updated = HashMap::from([(
+            "/ws/src/lib.rs".to_owned(),
+            UNIX_EPOCH + Duration::from_millis(1500),
+        )]);
+        let report = StatusReport::new(
+            AnalysisStatus::Analyzing,
+            Some(&analyzed),
+            &updated,
+            vec!["b".to_owned(), "a".to_owned(), "b".to_owned()],
+            vec![("c".to_owned(), "crashed".to_owned())],
+        );
+        assert_eq!(
+            report.analyzed_files,
+            vec![
+                FileStatus {
+                    path: "/ws/src/a.rs".to_owned(),
+                    functions: 1,
+                    last_updated: 0,
+                },
+                FileStatus {
+                    path: "/ws/src/lib.rs".to_owned(),
+                    functions: 2,
+                    last_updated: 1500,
+                },
+            ]
+        );
+        assert_eq!(report.pending_packages, vec!["a", "b"]);
+        assert_eq!(
+            serde_json::to_value(&report).unwrap()["failed_crates"],
+            serde_json::json!([["c", "crashed"]])
+        );
+
+        let empty = StatusReport::new(
+            AnalysisStatus::Error,
+            None,
+            &updated,
+            Vec::new(),
+            Vec::new(),
+        );
+        assert!(empty.analyzed_files.is_empty());
+    }
+}
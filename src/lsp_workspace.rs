@@ -1,31 +1,416 @@
 use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env, error, fmt, fs, io,
     path::{Path, PathBuf},
-    process::Stdio,
+    process::{Output, Stdio},
     sync::Arc,
 };
 
+use cargo_metadata::{
+    TargetKind as CargoTargetKind,
+    diagnostic::{Diagnostic, DiagnosticLevel, DiagnosticSpan},
+};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "compiler")]
+use tempfile::TempDir;
+#[cfg(feature = "compiler")]
+use tokio::task;
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    io::{AsyncBufReadExt, BufReader, Lines},
     process::{self, Command},
     sync::{Notify, mpsc},
-    task,
 };
 
-use crate::{models::Workspace, rustc_wrapper as compiler, toolchain};
+use crate::{
+    lsp_cargo_lock,
+    lsp_fingerprint::{self, StoredResults},
+    mir_algorithm::{AlgorithmChoice, POLONIUS_ALGO_ENV},
+    mir_cache::CacheHeader,
+    models::{SplitFunctions, Workspace},
+    project_json::{self, CrateType, ProjectJson, ProjectJsonError},
+    toolchain::{self, ToolchainError},
+};
+#[cfg(feature = "compiler")]
+use crate::{mir_cache, path_norm, rustc_wrapper as compiler};
+
+/// Log the lines cargo writes to `stderr` as info messages, and send an
+/// event whenever cargo waits for a lock.
+async fn forward_stderr(
+    mut stderr: Lines<BufReader<process::ChildStderr>>,
+    sender: mpsc::Sender<AnalyzerEvent>,
+) {
+    while let Ok(Some(line)) = stderr.next_line().await {
+        if lsp_cargo_lock::is_lock_message(&line) {
+            let _ = sender.send(AnalyzerEvent::WaitingForLock).await;
+        }
+        log::info!("{line}");
+    }
+}
+
+/// Cargo command running `compiler`, or else the current executable, as the
+/// compiler.
+///
+/// Its output on stderr is shown only if info messages are logged.
+fn cargo_command(compiler: Option<&Path>) -> Result<Command, ToolchainError> {
+    let mut command = compiler.map_or_else(
+        toolchain::setup_cargo_command,
+        toolchain::setup_cargo_command_with,
+    )?;
+    if log::max_level()
+        .to_level()
+        .is_none_or(|v| v < log::Level::Info)
+    {
+        command.stderr(Stdio::null());
+    }
+    Ok(command)
+}
+
+/// Runs the commands of an [`Analyzer`], replaced by fabricated outcomes in
+/// tests
+trait CommandRunner {
+    async fn output(&mut self, command: Command) -> io::Result<Output>;
+}
+
+/// Runs commands as child processes
+struct ChildProcesses;
+
+impl CommandRunner for ChildProcesses {
+    async fn output(&mut self, mut command: Command) -> io::Result<Output> {
+        command.output().await
+    }
+}
+
+/// Reasons a path cannot be analyzed
+#[derive(Debug)]
+pub enum AnalyzerError {
+    /// Cargo is not installed, or not in `PATH`
+    CargoNotFound,
+    /// `cargo metadata` failed for a path in a cargo package, with what it
+    /// wrote to stderr
+    MetadataFailed { stderr: String },
+    /// The path is neither in a cargo package, nor in a workspace with a
+    /// `rust-project.json`, nor a Rust file
+    NotARustTarget { path: PathBuf },
+    /// Cargo could not be run
+    Io(io::Error),
+    /// The toolchain of the analysis is unusable
+    Toolchain(ToolchainError),
+    /// The `rust-project.json` of the workspace is invalid
+    Project(ProjectJsonError),
+}
+
+impl AnalyzerError {
+    /// Whether the user can fix the error, so that it is shown in the editor
+    /// rather than only logged.
+    #[must_use]
+    pub const fn is_actionable(&self) -> bool {
+        matches!(self, Self::CargoNotFound | Self::MetadataFailed { .. })
+    }
+
+    /// Error of `cargo metadata` exiting with `output`, or of running it.
+    fn of_metadata(output: io::Result<Output>) -> Result<cargo_metadata::Metadata, Self> {
+        let output = output.map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                Self::CargoNotFound
+            } else {
+                Self::Io(e)
+            }
+        })?;
+        if !output.status.success() {
+            return Err(Self::MetadataFailed {
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+        cargo_metadata::MetadataCommand::parse(String::from_utf8_lossy(&output.stdout)).map_err(
+            |e| Self::MetadataFailed {
+                stderr: e.to_string(),
+            },
+        )
+    }
+}
+
+impl fmt::Display for AnalyzerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CargoNotFound => write!(
+                f,
+                "cargo was not found, install a Rust toolchain with rustup or add cargo to PATH"
+            ),
+            Self::MetadataFailed { stderr } if stderr.is_empty() => {
+                write!(f, "`cargo metadata` failed")
+            }
+            Self::MetadataFailed { stderr } => write!(f, "`cargo metadata` failed: {stderr}"),
+            Self::NotARustTarget { path } => write!(
+                f,
+                "{} is neither in a cargo package, nor in a workspace with a {}, nor a Rust file",
+                path.display(),
+                project_json::FILE_NAME
+            ),
+            Self::Io(_) => write!(f, "cannot run `cargo metadata`"),
+            Self::Toolchain(_) => write!(f, "the toolchain is unusable"),
+            Self::Project(_) => write!(f, "cannot load the workspace without cargo"),
+        }
+    }
+}
+
+impl error::Error for AnalyzerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Toolchain(e) => Some(e),
+            Self::Project(e) => Some(e),
+            Self::CargoNotFound | Self::MetadataFailed { .. } | Self::NotARustTarget { .. } => None,
+        }
+    }
+}
 
 fn set_cache_path(cmd: &mut Command, target_dir: impl AsRef<Path>) {
     cmd.env(toolchain::CACHE_DIR_ENV, target_dir.as_ref().join("cache"));
 }
 
+/// Environment variable overriding the size in bytes above which files
+/// outside of cargo packages are not analyzed
+pub const MAX_FILE_BYTES_ENV: &str = "FERROUS_OWL_MAX_FILE_BYTES";
+
+const DEFAULT_MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Size limit of files from [`MAX_FILE_BYTES_ENV`], else the default.
+fn max_file_bytes() -> u64 {
+    env::var(MAX_FILE_BYTES_ENV)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_FILE_BYTES)
+}
+
+/// Size of the file at `path` in bytes, if it exceeds the limit of
+/// [`MAX_FILE_BYTES_ENV`].
+#[must_use]
+pub fn oversized(path: &Path) -> Option<u64> {
+    let bytes = fs::metadata(path).ok()?.len();
+    (bytes > max_file_bytes()).then_some(bytes)
+}
+
+/// Kind of cargo target to analyze
+#[derive(
+    Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+}
+
+impl TargetKind {
+    pub const ALL: [Self; 5] = [Self::Lib, Self::Bin, Self::Example, Self::Test, Self::Bench];
+
+    /// Kinds analyzed when none are selected
+    pub const DEFAULT: [Self; 2] = [Self::Lib, Self::Bin];
+
+    /// Kind of target of a crate of a `rust-project.json`
+    #[cfg_attr(
+        not(feature = "compiler"),
+        allow(dead_code, reason = "only built in this process")
+    )]
+    const fn of_crate(crate_type: CrateType) -> Self {
+        match crate_type {
+            CrateType::Bin => Self::Bin,
+            CrateType::Lib | CrateType::ProcMacro => Self::Lib,
+        }
+    }
+
+    const fn cargo_flag(self) -> &'static str {
+        match self {
+            Self::Lib => "--lib",
+            Self::Bin => "--bins",
+            Self::Example => "--examples",
+            Self::Test => "--tests",
+            Self::Bench => "--benches",
+        }
+    }
+
+    /// The selected kinds, or [`Self::DEFAULT`] if there are none.
+    #[must_use]
+    pub fn selection(kinds: &[Self]) -> BTreeSet<Self> {
+        if kinds.is_empty() {
+            Self::DEFAULT.into()
+        } else {
+            kinds.iter().copied().collect()
+        }
+    }
+}
+
+/// Cargo flags checking the targets of `kinds`.
+///
+/// Cargo rejects `--lib` if no package has a library, so it is left out in
+/// that case unless it is the only selected kind.
+fn cargo_target_args(kinds: &BTreeSet<TargetKind>, has_lib: bool) -> Vec<&'static str> {
+    kinds
+        .iter()
+        .filter(|&&kind| has_lib || kind != TargetKind::Lib || kinds.len() == 1)
+        .map(|kind| kind.cargo_flag())
+        .collect()
+}
+
+/// Arguments of `cargo check` for the workspace member `member`, or every
+/// member if `None`.
+/// Cargo profile of the analysis. It inherits `dev` and is declared on the
+/// command line, its output lands in the `owl` directory of the target
+/// directory the user's own builds use.
+const PROFILE: &str = "owl";
+const PROFILE_CONFIG: &str = "profile.owl.inherits=\"dev\"";
+
+const PROFILE_ARGS: [&str; 4] = ["--profile", PROFILE, "--config", PROFILE_CONFIG];
+
+fn cargo_check_args<'a>(
+    member: Option<&'a str>,
+    target_args: Vec<&'a str>,
+    all_features: bool,
+) -> Vec<&'a str> {
+    let mut args = vec!["check"];
+    args.extend(PROFILE_ARGS);
+    if let Some(member) = member {
+        args.extend(["--package", member]);
+    } else {
+        args.push("--workspace");
+    }
+    args.extend(target_args);
+    if all_features {
+        args.push("--all-features");
+    }
+    args.extend_from_slice(&["--keep-going", "--message-format=json"]);
+    args
+}
+
+/// Whether one of `packages` has a library target.
+fn has_lib_target(packages: &[&cargo_metadata::Package]) -> bool {
+    packages
+        .iter()
+        .flat_map(|package| &package.targets)
+        .flat_map(|target| &target.kind)
+        .any(|kind| {
+            matches!(
+                kind,
+                CargoTargetKind::Lib
+                    | CargoTargetKind::RLib
+                    | CargoTargetKind::DyLib
+                    | CargoTargetKind::CDyLib
+                    | CargoTargetKind::StaticLib
+                    | CargoTargetKind::ProcMacro
+            )
+        })
+}
+
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct CargoCheckMessageTarget {
     name: String,
+    #[serde(default)]
+    kind: Vec<String>,
 }
 #[derive(serde::Deserialize, Clone, Debug)]
 #[serde(tag = "reason", rename_all = "kebab-case")]
 pub enum CargoCheckMessage {
-    CompilerArtifact { target: CargoCheckMessageTarget },
-    BuildFinished {},
+    CompilerArtifact {
+        #[serde(default)]
+        package_id: String,
+        target: CargoCheckMessageTarget,
+    },
+    CompilerMessage {
+        #[serde(default)]
+        package_id: String,
+        message: Diagnostic,
+    },
+    BuildFinished {
+        success: bool,
+    },
+}
+
+/// Events of the lines cargo writes to `stdout` while it checks the
+/// workspace packages with the compiler of the analysis.
+///
+/// Cargo keeps going after a package fails to build, so the packages that
+/// failed are only known once the build finished.
+struct CargoOutput {
+    root: PathBuf,
+    package_count: usize,
+    /// Names of the analyzed packages, by package id
+    packages: HashMap<String, String>,
+    /// Ids of the analyzed packages with a checked crate
+    checked: HashSet<String>,
+    /// First error of each analyzed package, by package id
+    errors: HashMap<String, String>,
+    success: bool,
+}
+
+impl CargoOutput {
+    fn new(root: PathBuf, package_count: usize, packages: HashMap<String, String>) -> Self {
+        Self {
+            root,
+            package_count,
+            packages,
+            checked: HashSet::new(),
+            errors: HashMap::new(),
+            success: false,
+        }
+    }
+
+    /// Events of the JSON `line`, none if it is no cargo message.
+    fn events(&mut self, line: &str) -> Vec<AnalyzerEvent> {
+        match serde_json::from_str(line) {
+            Ok(CargoCheckMessage::CompilerArtifact { package_id, target }) => {
+                log::debug!("crate {} checked", target.name);
+                // the build script runs before the crates of its package
+                if !target.kind.iter().any(|kind| kind == "custom-build") {
+                    self.checked.insert(package_id);
+                }
+                vec![AnalyzerEvent::CrateChecked {
+                    package: target.name,
+                    package_count: self.package_count,
+                }]
+            }
+            Ok(CargoCheckMessage::CompilerMessage {
+                package_id,
+                message,
+            }) => {
+                if matches!(message.level, DiagnosticLevel::Error | DiagnosticLevel::Ice)
+                    && self.packages.contains_key(&package_id)
+                {
+                    self.errors
+                        .entry(package_id)
+                        .or_insert_with(|| message.message.clone());
+                }
+                AnalyzerEvent::compile_error(&self.root, message)
+                    .into_iter()
+                    .collect()
+            }
+            Ok(CargoCheckMessage::BuildFinished { success }) => {
+                self.success = success;
+                if success {
+                    return Vec::new();
+                }
+                let mut failed: Vec<_> = self
+                    .packages
+                    .iter()
+                    .filter(|(id, _)| !self.checked.contains(*id) || self.errors.contains_key(*id))
+                    .collect();
+                failed.sort_by_key(|(_, name)| *name);
+                failed
+                    .into_iter()
+                    .map(|(id, name)| AnalyzerEvent::CrateFailed {
+                        package: name.clone(),
+                        message: self
+                            .errors
+                            .get(id)
+                            .cloned()
+                            .unwrap_or_else(|| "the package failed to build".to_owned()),
+                    })
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        }
+    }
 }
 
 pub enum AnalyzerEvent {
@@ -34,56 +419,141 @@ pub enum AnalyzerEvent {
         package_count: usize,
     },
     Analyzed(Workspace),
+    /// Cargo waits for a lock held by another cargo process
+    WaitingForLock,
+    /// The compiler rejected the analyzed code
+    CompileError {
+        file: PathBuf,
+        message: String,
+        span: DiagnosticSpan,
+    },
+    /// The file is too large to be analyzed, see [`MAX_FILE_BYTES_ENV`]
+    #[cfg_attr(
+        not(feature = "compiler"),
+        allow(dead_code, reason = "only files analyzed in this process are skipped")
+    )]
+    Skipped {
+        file: PathBuf,
+        bytes: u64,
+    },
+    /// Cargo could not check the workspace package, for example because the
+    /// compiler crashed, while it went on with the others
+    CrateFailed {
+        package: String,
+        message: String,
+    },
 }
 
-#[derive(Clone)]
+impl AnalyzerEvent {
+    /// Event for a compiler error located in a file.
+    ///
+    /// Other diagnostics, like warnings or the final "aborting due to previous
+    /// error" message, result in `None`. Relative file names are resolved
+    /// against `root`.
+    fn compile_error(root: &Path, diagnostic: Diagnostic) -> Option<Self> {
+        if diagnostic.level != DiagnosticLevel::Error {
+            return None;
+        }
+        let span = diagnostic.spans.into_iter().find(|span| span.is_primary)?;
+        let message = match &span.label {
+            Some(label) => format!("{}: {label}", diagnostic.message),
+            None => diagnostic.message,
+        };
+        Some(Self::CompileError {
+            file: root.join(&span.file_name),
+            message,
+            span,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Analyzer {
     path: PathBuf,
     metadata: Option<cargo_metadata::Metadata>,
+    /// Crate graph of a workspace built without cargo, see
+    /// [`crate::project_json`]
+    project: Option<ProjectJson>,
+    /// Binary cargo runs as the compiler, the current executable if `None`
+    compiler: Option<PathBuf>,
 }
 
 impl Analyzer {
-    pub async fn new(path: impl AsRef<Path>) -> Result<Self, ()> {
-        let path = path.as_ref().to_path_buf();
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self, AnalyzerError> {
+        Self::load(path.as_ref(), None, &mut ChildProcesses).await
+    }
+
+    /// Analyzer running `compiler` as the compiler of packages instead of
+    /// the current executable, which must be the `ferrous-owl` binary
+    /// otherwise.
+    pub async fn with_compiler(
+        path: impl AsRef<Path>,
+        compiler: PathBuf,
+    ) -> Result<Self, AnalyzerError> {
+        Self::load(path.as_ref(), Some(compiler), &mut ChildProcesses).await
+    }
 
-        let mut cargo_cmd = toolchain::setup_cargo_command();
+    /// Analyzer of the package containing `path`, of the workspace described
+    /// by a `rust-project.json` in the directory `path`, or of the Rust file
+    /// `path`, in this order. `cargo metadata` runs with `runner`.
+    async fn load(
+        path: &Path,
+        compiler: Option<PathBuf>,
+        runner: &mut impl CommandRunner,
+    ) -> Result<Self, AnalyzerError> {
+        let path = path.to_path_buf();
+        let dir = if path.is_file() {
+            path.parent().unwrap()
+        } else {
+            &path
+        };
+        if !dir.is_dir() {
+            return Err(AnalyzerError::NotARustTarget { path });
+        }
 
+        let mut cargo_cmd = cargo_command(compiler.as_deref()).map_err(AnalyzerError::Toolchain)?;
         cargo_cmd
             .args([
                 "metadata".to_owned(),
                 "--filter-platform".to_owned(),
                 toolchain::HOST_TUPLE.to_owned(),
             ])
-            .current_dir(if path.is_file() {
-                path.parent().unwrap()
-            } else {
-                &path
-            })
+            .current_dir(dir)
             .stdout(Stdio::piped())
-            .stderr(Stdio::null());
+            .stderr(Stdio::piped());
 
-        let metadata = if let Ok(child) = cargo_cmd.spawn()
-            && let Ok(output) = child.wait_with_output().await
-        {
-            let data = String::from_utf8_lossy(&output.stdout);
-            cargo_metadata::MetadataCommand::parse(data).ok()
-        } else {
-            None
+        let error = match AnalyzerError::of_metadata(runner.output(cargo_cmd).await) {
+            Ok(metadata) => {
+                return Ok(Self {
+                    path: metadata.workspace_root.as_std_path().to_path_buf(),
+                    metadata: Some(metadata),
+                    project: None,
+                    compiler,
+                });
+            }
+            Err(error) => error,
         };
-
-        if let Some(metadata) = metadata {
+        if let Some(project) = ProjectJson::discover(&path) {
             Ok(Self {
-                path: metadata.workspace_root.as_std_path().to_path_buf(),
-                metadata: Some(metadata),
+                path,
+                metadata: None,
+                project: Some(project.map_err(AnalyzerError::Project)?),
+                compiler,
             })
         } else if path.is_file() && path.extension().is_some_and(|v| v == "rs") {
+            log::debug!("{}: {error}, analyze the single file", path.display());
             Ok(Self {
                 path,
                 metadata: None,
+                project: None,
+                compiler,
             })
+        } else if matches!(error, AnalyzerError::MetadataFailed { .. })
+            && !path.ancestors().any(|dir| dir.join("Cargo.toml").is_file())
+        {
+            Err(AnalyzerError::NotARustTarget { path })
         } else {
-            log::warn!("Invalid analysis target: {}", path.display());
-            Err(())
+            Err(error)
         }
     }
     #[must_use]
@@ -91,93 +561,230 @@ impl Analyzer {
         &self.path
     }
 
-    pub async fn analyze(&self, all_targets: bool, all_features: bool) -> AnalyzeEventIter {
+    /// Lock file cargo holds while it builds in the directory of the
+    /// analysis, if the target is a package.
+    #[must_use]
+    pub fn build_lock(&self) -> Option<PathBuf> {
+        let metadata = self.metadata.as_ref()?;
+        Some(
+            metadata
+                .target_directory
+                .as_std_path()
+                .join(PROFILE)
+                .join(".cargo-lock"),
+        )
+    }
+
+    /// Analyze the target, reusing the results of the previous analysis if
+    /// the sources did not change, unless `force` is set.
+    ///
+    /// `polonius_algo` overrides the algorithm of the compiler processes, a
+    /// single file is analyzed in this process and uses its environment.
+    pub async fn analyze(
+        &self,
+        target_kinds: &[TargetKind],
+        all_features: bool,
+        force: bool,
+        polonius_algo: Option<AlgorithmChoice>,
+    ) -> AnalyzeEventIter {
         if let Some(metadata) = &self.metadata
             && metadata.root_package().is_some()
         {
-            self.analyze_package(metadata, all_targets, all_features)
+            let target_kinds = TargetKind::selection(target_kinds);
+            self.analyze_package(
+                metadata,
+                None,
+                target_kinds,
+                all_features,
+                force,
+                polonius_algo,
+            )
+            .await
+        } else if let Some(project) = &self.project {
+            let target_kinds = TargetKind::selection(target_kinds);
+            self.analyze_project(project, None, &target_kinds).await
+        } else {
+            self.analyze_single_file(&self.path, None).await
+        }
+    }
+
+    /// Analyze only the workspace member `package`.
+    ///
+    /// Results of a single member are neither reused nor stored, as they do
+    /// not cover the workspace.
+    pub async fn analyze_member(
+        &self,
+        package: &str,
+        target_kinds: &[TargetKind],
+        all_features: bool,
+        polonius_algo: Option<AlgorithmChoice>,
+    ) -> AnalyzeEventIter {
+        if let Some(metadata) = &self.metadata
+            && metadata.root_package().is_some()
+        {
+            let target_kinds = TargetKind::selection(target_kinds);
+            self.analyze_package(
+                metadata,
+                Some(package),
+                target_kinds,
+                all_features,
+                true,
+                polonius_algo,
+            )
+            .await
+        } else if let Some(project) = &self.project {
+            let target_kinds = TargetKind::selection(target_kinds);
+            self.analyze_project(project, Some(package), &target_kinds)
                 .await
         } else {
-            self.analyze_single_file(&self.path).await
+            self.analyze_single_file(&self.path, None).await
         }
     }
 
+    /// Names of the workspace members, or the path of the file for a single
+    /// file target.
+    #[must_use]
+    pub fn members(&self) -> Vec<String> {
+        if let Some(project) = &self.project {
+            return project
+                .members()
+                .map(|index| project.crate_name(index))
+                .collect();
+        }
+        self.metadata.as_ref().map_or_else(
+            || vec![self.path.display().to_string()],
+            |metadata| {
+                metadata
+                    .workspace_packages()
+                    .into_iter()
+                    .map(|package| package.name.to_string())
+                    .collect()
+            },
+        )
+    }
+
+    /// Name of the workspace member whose directory contains `path`, the
+    /// innermost one if members are nested.
+    #[must_use]
+    pub fn member_of(&self, path: &Path) -> Option<String> {
+        if let Some(project) = &self.project {
+            return project
+                .member_of(path)
+                .map(|index| project.crate_name(index));
+        }
+        self.metadata
+            .as_ref()?
+            .workspace_packages()
+            .into_iter()
+            .filter_map(|package| {
+                let dir = package.manifest_path.parent()?.as_std_path();
+                path.starts_with(dir)
+                    .then(|| (dir.components().count(), package.name.to_string()))
+            })
+            .max_by_key(|(depth, _)| *depth)
+            .map(|(_, name)| name)
+    }
+
     async fn analyze_package(
         &self,
         metadata: &cargo_metadata::Metadata,
-        all_targets: bool,
+        member: Option<&str>,
+        target_kinds: BTreeSet<TargetKind>,
         all_features: bool,
+        force: bool,
+        polonius_algo: Option<AlgorithmChoice>,
     ) -> AnalyzeEventIter {
-        let package_name = metadata.root_package().as_ref().unwrap().name.to_string();
-        let target_dir = metadata.target_directory.as_std_path().join("owl");
-        log::info!("clear cargo cache");
-        let mut command = toolchain::setup_cargo_command();
-        command
-            .args(["clean", "--package", &package_name])
-            .env("CARGO_TARGET_DIR", &target_dir)
-            .current_dir(&self.path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
-        command.spawn().unwrap().wait().await.ok();
-
-        let mut command = toolchain::setup_cargo_command();
-
-        let mut args = vec!["check", "--workspace"];
-        if all_targets {
-            args.push("--all-targets");
+        let package_name = member.map_or_else(
+            || metadata.root_package().as_ref().unwrap().name.to_string(),
+            str::to_owned,
+        );
+        let cargo_target_dir = metadata.target_directory.as_std_path();
+        let target_dir = cargo_target_dir.join(PROFILE);
+        // without override, compiler processes inherit the environment
+        let polonius_algo = polonius_algo.or_else(|| AlgorithmChoice::from_env().ok().flatten());
+        let mut packages = metadata.workspace_packages();
+        if member.is_some() {
+            packages.retain(|package| *package.name == *package_name);
         }
-        if all_features {
-            args.push("--all-features");
+        let target_args = cargo_target_args(&target_kinds, has_lib_target(&packages));
+        let mut stored = StoredResults {
+            header: CacheHeader::current(),
+            fingerprints: lsp_fingerprint::workspace_fingerprints(metadata),
+            target_kinds,
+            all_features,
+            polonius_algo: polonius_algo.unwrap_or_default(),
+            results: Vec::new(),
+        };
+        let whole_workspace = member.is_none();
+        if whole_workspace
+            && !force
+            && let Some(results) = StoredResults::load(&target_dir, &stored)
+        {
+            log::info!("sources of {package_name} unchanged, reuse analysis results");
+            return AnalyzeEventIter::replay(results);
         }
-        args.extend_from_slice(&["--keep-going", "--message-format=json"]);
+
+        let Ok(mut command) =
+            cargo_command(self.compiler.as_deref()).inspect_err(|e| log::error!("{e}"))
+        else {
+            return AnalyzeEventIter::from_events(Vec::new());
+        };
+        self.clean_package(cargo_target_dir, &package_name).await;
 
         command
-            .args(args)
-            .env("CARGO_TARGET_DIR", &target_dir)
+            .args(cargo_check_args(member, target_args, all_features))
+            .env("CARGO_TARGET_DIR", cargo_target_dir)
             .env_remove("RUSTC_WRAPPER")
             .current_dir(&self.path)
             .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
             .kill_on_drop(true);
 
-        set_cache_path(&mut command, target_dir);
-
-        if log::max_level()
-            .to_level()
-            .is_none_or(|v| v < log::Level::Info)
-        {
-            command.stderr(Stdio::null());
+        set_cache_path(&mut command, &target_dir);
+        if let Some(polonius_algo) = polonius_algo {
+            command.env(POLONIUS_ALGO_ENV, polonius_algo.to_string());
         }
 
         let package_count = metadata.packages.len();
+        let analyzed = packages
+            .iter()
+            .map(|package| (package.id.repr.clone(), package.name.to_string()))
+            .collect();
+        let mut output = CargoOutput::new(self.path.clone(), package_count, analyzed);
 
         log::info!("start analyzing package {package_name}");
         let mut child = command.spawn().unwrap();
         let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        let stderr = BufReader::new(child.stderr.take().unwrap()).lines();
 
         let (sender, receiver) = mpsc::channel(1024);
+        tokio::spawn(forward_stderr(stderr, sender.clone()));
         let notify = Arc::new(Notify::new());
         let notify_c = notify.clone();
         let _handle = tokio::spawn(async move {
+            let mut split_functions = SplitFunctions::default();
+            let mut degraded = false;
             // prevent command from dropped
             while let Ok(Some(line)) = stdout.next_line().await {
-                if let Ok(CargoCheckMessage::CompilerArtifact { target }) =
-                    serde_json::from_str(&line)
-                {
-                    let checked = target.name;
-                    log::debug!("crate {checked} checked");
-
-                    let event = AnalyzerEvent::CrateChecked {
-                        package: checked,
-                        package_count,
-                    };
+                for event in output.events(&line) {
                     let _ = sender.send(event).await;
                 }
-                if let Ok(ws) = serde_json::from_str::<Workspace>(&line) {
+                let Ok(ws) = serde_json::from_str::<Workspace>(&line) else {
+                    continue;
+                };
+                degraded |= ws.meta.as_ref().is_some_and(|meta| meta.degraded);
+                if let Some(ws) = split_functions.reassemble(ws) {
+                    stored.results.push(ws.clone());
                     let event = AnalyzerEvent::Analyzed(ws);
                     let _ = sender.send(event).await;
                 }
             }
             log::debug!("stdout closed");
+            // cancelled or failed analyses must run again, as must functions
+            // whose analysis timed out
+            if output.success && whole_workspace && !degraded {
+                stored.store(&target_dir);
+            }
             notify_c.notify_one();
         });
 
@@ -188,10 +795,207 @@ impl Analyzer {
         }
     }
 
+    /// Remove the build output of `package`, so that cargo runs the analysis
+    /// of its crates again.
+    async fn clean_package(&self, target_dir: &Path, package: &str) {
+        log::info!("clear cargo cache");
+        let Ok(mut command) =
+            cargo_command(self.compiler.as_deref()).inspect_err(|e| log::error!("{e}"))
+        else {
+            return;
+        };
+        command
+            .args(["clean", "--package", package])
+            .args(PROFILE_ARGS)
+            .env("CARGO_TARGET_DIR", target_dir)
+            .current_dir(&self.path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        command.spawn().unwrap().wait().await.ok();
+    }
+
+    /// Whether the target is a package, which is analyzed by cargo, rather
+    /// than a single file analyzed in this process.
+    #[must_use]
+    pub fn is_package(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.root_package().is_some())
+    }
+
+    /// Whether the target is a single file, rather than a cargo package or a
+    /// workspace described by a `rust-project.json`.
+    #[must_use]
+    pub fn is_single_file(&self) -> bool {
+        !self.is_package() && self.project.is_none()
+    }
+
+    /// Analyze the workspace members of `project` selected by `target_kinds`,
+    /// or only the one named `member`.
+    #[cfg(not(feature = "compiler"))]
+    #[allow(clippy::unused_async, reason = "like the analysis in this process")]
+    async fn analyze_project(
+        &self,
+        _project: &ProjectJson,
+        _member: Option<&str>,
+        _target_kinds: &BTreeSet<TargetKind>,
+    ) -> AnalyzeEventIter {
+        log::error!(
+            "cannot analyze {}: workspaces without cargo need the `compiler` feature",
+            self.path.display()
+        );
+        AnalyzeEventIter::from_events(Vec::new())
+    }
+
+    /// Analyze the workspace members of `project` selected by `target_kinds`,
+    /// or only the one named `member`, in this process and one after the
+    /// other.
+    ///
+    /// Their dependencies from elsewhere are built once into the `owl`
+    /// directory of `target` at the root of the workspace, and reused by
+    /// later analyses. Like single files, the analysis uses the environment
+    /// of this process.
+    #[cfg(feature = "compiler")]
+    #[allow(clippy::unused_async, reason = "like the analysis of packages")]
+    async fn analyze_project(
+        &self,
+        project: &ProjectJson,
+        member: Option<&str>,
+        target_kinds: &BTreeSet<TargetKind>,
+    ) -> AnalyzeEventIter {
+        let sysroot = match toolchain::get_sysroot() {
+            Ok(sysroot) => sysroot,
+            Err(e) => {
+                log::error!("cannot analyze {}: {e}", self.path.display());
+                return AnalyzeEventIter::from_events(Vec::new());
+            }
+        };
+        let analyzed: Vec<_> = project
+            .members()
+            .filter(|&index| {
+                member.map_or_else(
+                    || target_kinds.contains(&TargetKind::of_crate(project.crate_type(index))),
+                    |member| project.crate_name(index) == member,
+                )
+            })
+            .collect();
+        let out_dir = self.path.join("target").join(PROFILE).join("deps");
+        if let Err(e) = fs::create_dir_all(&out_dir) {
+            log::error!("cannot create {}: {e}", out_dir.display());
+            return AnalyzeEventIter::from_events(Vec::new());
+        }
+
+        let (sender, receiver) = mpsc::channel(1024);
+        let notify = Arc::new(Notify::new());
+        let notify_c = notify.clone();
+        let project = project.clone();
+        let root = self.path.clone();
+
+        log::info!("start analyzing {}", root.display());
+
+        let _handle = tokio::spawn(async move {
+            let crates = project.needed(&analyzed);
+            let package_count = crates.len();
+            for index in crates {
+                let name = project.crate_name(index);
+                let args = project.rustc_args(index, &out_dir);
+                let built = if project.is_built(index) {
+                    build_project_crate(&project, index, args.clone(), &out_dir, &sysroot).await
+                } else {
+                    true
+                };
+                if built && analyzed.contains(&index) {
+                    let handle = compiler::spawn_crate_analysis(args, &sysroot);
+                    if !forward_analysis(handle, &root, &sender).await {
+                        break;
+                    }
+                }
+                let event = AnalyzerEvent::CrateChecked {
+                    package: name,
+                    package_count,
+                };
+                if sender.send(event).await.is_err() {
+                    break;
+                }
+            }
+            notify_c.notify_one();
+        });
+
+        AnalyzeEventIter {
+            receiver,
+            notify,
+            _child: None,
+        }
+    }
+
+    /// Analyze the file at `path` on its own, without the other files of
+    /// its package, from `text` if it has unsaved changes.
+    #[cfg(not(feature = "compiler"))]
+    #[allow(clippy::unused_async, reason = "like the analysis in this process")]
+    pub async fn analyze_single_file(
+        &self,
+        path: &Path,
+        _text: Option<String>,
+    ) -> AnalyzeEventIter {
+        log::error!(
+            "cannot analyze {}: single files need the `compiler` feature",
+            path.display()
+        );
+        AnalyzeEventIter::from_events(Vec::new())
+    }
+
+    /// Analyze the file at `path` on its own, without the other files of
+    /// its package.
+    ///
+    /// If `text` differs from the file on disk, it is analyzed instead, from
+    /// a copy of the file in a temporary directory, and its results and
+    /// errors are reported for `path`.
+    #[cfg(feature = "compiler")]
     #[allow(clippy::unused_async, reason = "required by async closure signature")]
-    async fn analyze_single_file(&self, path: &Path) -> AnalyzeEventIter {
-        let sysroot = toolchain::get_sysroot();
+    pub async fn analyze_single_file(&self, path: &Path, text: Option<String>) -> AnalyzeEventIter {
         let path = path.to_path_buf();
+        let text = text.filter(|text| fs::read_to_string(&path).ok().as_ref() != Some(text));
+        let oversized = text.as_ref().map_or_else(
+            || oversized(&path),
+            |text| {
+                u64::try_from(text.len())
+                    .ok()
+                    .filter(|bytes| *bytes > max_file_bytes())
+            },
+        );
+        if let Some(bytes) = oversized {
+            log::warn!(
+                "skip analysis of {}, its {bytes} bytes exceed the limit of {MAX_FILE_BYTES_ENV}",
+                path.display()
+            );
+            return AnalyzeEventIter::from_events(vec![AnalyzerEvent::Skipped {
+                file: path,
+                bytes,
+            }]);
+        }
+        let sysroot = match toolchain::get_sysroot() {
+            Ok(sysroot) => sysroot,
+            Err(e) => {
+                log::error!("cannot analyze {}: {e}", path.display());
+                return AnalyzeEventIter::from_events(Vec::new());
+            }
+        };
+
+        let (mirror, compiled) = match text.map(|text| write_mirror(&path, &text)).transpose() {
+            Ok(Some((mirror, compiled))) => (Some(mirror), compiled),
+            Ok(None) => (None, path.clone()),
+            Err(e) => {
+                log::error!("cannot copy the unsaved text of {}: {e}", path.display());
+                return AnalyzeEventIter::from_events(Vec::new());
+            }
+        };
+
+        // the copies of unsaved text are at a new path every time
+        let cache_dir = if mirror.is_some() {
+            None
+        } else {
+            mir_cache::single_file_cache_dir(&path)
+        };
 
         let (sender, receiver) = mpsc::channel(1024);
         let notify = Arc::new(Notify::new());
@@ -200,18 +1004,37 @@ impl Analyzer {
         log::info!("start analyzing {}", path.display());
 
         let _handle = tokio::spawn(async move {
-            let handle = compiler::spawn_analysis(&path, &sysroot);
+            let _mirror_guard = mirror;
+            let handle = compiler::spawn_analysis(&compiled, &sysroot, cache_dir);
 
             let compiler::AnalysisHandle {
                 mut results,
+                mut diagnostics,
                 thread,
             } = handle;
-            while let Some(ws) = results.recv().await {
+            let compiled_key = path_norm::normalize_path(&compiled);
+            let path_key = path_norm::normalize_path(&path);
+            while let Some(mut ws) = results.recv().await {
+                ws.rename_file(&compiled_key, &path_key);
                 let event = AnalyzerEvent::Analyzed(ws);
                 if sender.send(event).await.is_err() {
                     break;
                 }
             }
+            let root = compiled.parent().unwrap_or(&compiled);
+            while let Some(diagnostic) = diagnostics.recv().await {
+                let Some(mut event) = AnalyzerEvent::compile_error(root, diagnostic) else {
+                    continue;
+                };
+                if let AnalyzerEvent::CompileError { file, .. } = &mut event
+                    && *file == compiled
+                {
+                    file.clone_from(&path);
+                }
+                if sender.send(event).await.is_err() {
+                    break;
+                }
+            }
 
             let join_result = task::spawn_blocking(move || thread.join()).await;
             match join_result {
@@ -232,12 +1055,110 @@ impl Analyzer {
     }
 }
 
+/// Build crate `index` of `project` with the compiler arguments `args`,
+/// unless it comes from elsewhere and its output in `out_dir` exists.
+/// Returns whether the output exists.
+#[cfg(feature = "compiler")]
+async fn build_project_crate(
+    project: &ProjectJson,
+    index: usize,
+    args: Vec<String>,
+    out_dir: &Path,
+    sysroot: &Path,
+) -> bool {
+    let name = project.crate_name(index);
+    let artifact = out_dir.join(project.artifact(index));
+    if !project.crates[index].is_workspace_member && artifact.exists() {
+        log::debug!("reuse {}", artifact.display());
+        return true;
+    }
+    log::info!("build crate {name}");
+    let sysroot = sysroot.to_path_buf();
+    match task::spawn_blocking(move || compiler::build_crate(&args, &sysroot)).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => {
+            log::warn!("cannot build crate {name}: {e}");
+            false
+        }
+        Err(e) => {
+            log::error!("Join task failed: {e}");
+            false
+        }
+    }
+}
+
+/// Send the results and then the errors of the analysis `handle` of a crate
+/// of the workspace at `root`, returning whether the receiver is still
+/// there.
+#[cfg(feature = "compiler")]
+async fn forward_analysis(
+    handle: compiler::AnalysisHandle,
+    root: &Path,
+    sender: &mpsc::Sender<AnalyzerEvent>,
+) -> bool {
+    let compiler::AnalysisHandle {
+        mut results,
+        mut diagnostics,
+        thread,
+    } = handle;
+    while let Some(ws) = results.recv().await {
+        if sender.send(AnalyzerEvent::Analyzed(ws)).await.is_err() {
+            return false;
+        }
+    }
+    while let Some(diagnostic) = diagnostics.recv().await {
+        if let Some(event) = AnalyzerEvent::compile_error(root, diagnostic)
+            && sender.send(event).await.is_err()
+        {
+            return false;
+        }
+    }
+    match task::spawn_blocking(move || thread.join()).await {
+        Ok(Ok(Ok(_))) => log::info!("Compiler finished successfully"),
+        Ok(Ok(Err(e))) => log::warn!("Compiler error: {e}"),
+        Ok(Err(_)) => log::error!("Compiler thread panicked"),
+        Err(e) => log::error!("Join task failed: {e}"),
+    }
+    true
+}
+
+/// Copy of the file at `path` with `text`, in a temporary directory that
+/// lives as long as the returned one.
+#[cfg(feature = "compiler")]
+fn write_mirror(path: &Path, text: &str) -> io::Result<(TempDir, PathBuf)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let mirror = tempfile::tempdir()?;
+    let compiled = mirror.path().join(name);
+    fs::write(&compiled, text)?;
+    Ok((mirror, compiled))
+}
+
 pub struct AnalyzeEventIter {
     receiver: mpsc::Receiver<AnalyzerEvent>,
     notify: Arc<Notify>,
     _child: Option<process::Child>,
 }
 impl AnalyzeEventIter {
+    /// Events of stored analysis results.
+    fn replay(results: Vec<Workspace>) -> Self {
+        Self::from_events(results.into_iter().map(AnalyzerEvent::Analyzed).collect())
+    }
+
+    /// Events known in advance.
+    fn from_events(events: Vec<AnalyzerEvent>) -> Self {
+        let (sender, receiver) = mpsc::channel(events.len().max(1));
+        for event in events {
+            let _ = sender.try_send(event);
+        }
+        Self {
+            receiver,
+            notify: Arc::new(Notify::new()),
+            _child: None,
+        }
+    }
+
     pub async fn next_event(&mut self) -> Option<AnalyzerEvent> {
         tokio::select! {
             v = self.receiver.recv() => v,
@@ -245,3 +1166,267 @@ impl AnalyzeEventIter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_target_kinds() {
+        let kinds = TargetKind::selection(&[]);
+        assert_eq!(cargo_target_args(&kinds, true), vec!["--lib", "--bins"]);
+        assert_eq!(cargo_target_args(&kinds, false), vec!["--bins"]);
+    }
+
+    #[test]
+    fn test_selected_target_kinds() {
+        let kinds = TargetKind::selection(&[TargetKind::Example, TargetKind::Lib]);
+        assert_eq!(cargo_target_args(&kinds, true), vec!["--lib", "--examples"]);
+        assert_eq!(cargo_target_args(&kinds, false), vec!["--examples"]);
+
+        let lib_only = TargetKind::selection(&[TargetKind::Lib, TargetKind::Lib]);
+        assert_eq!(cargo_target_args(&lib_only, false), vec!["--lib"]);
+
+        let all = TargetKind::selection(&TargetKind::ALL);
+        assert_eq!(
+            cargo_target_args(&all, true),
+            vec!["--lib", "--bins", "--examples", "--tests", "--benches"]
+        );
+    }
+
+    #[test]
+    fn test_cargo_check_args() {
+        assert_eq!(
+            cargo_check_args(None, vec!["--lib"], false),
+            vec![
+                "check",
+                "--profile",
+                "owl",
+                "--config",
+                "profile.owl.inherits=\"dev\"",
+                "--workspace",
+                "--lib",
+                "--keep-going",
+                "--message-format=json"
+            ]
+        );
+        assert_eq!(
+            cargo_check_args(Some("member"), vec!["--bins"], true),
+            vec![
+                "check",
+                "--profile",
+                "owl",
+                "--config",
+                "profile.owl.inherits=\"dev\"",
+                "--package",
+                "member",
+                "--bins",
+                "--all-features",
+                "--keep-going",
+                "--message-format=json"
+            ]
+        );
+    }
+
+    const GOOD: &str = "path+file:///ws/good#0.1.0";
+    const BROKEN: &str = "path+file:///ws/broken#0.1.0";
+
+    fn cargo_output() -> CargoOutput {
+        let packages = HashMap::from([
+            (GOOD.to_owned(), "good".to_owned()),
+            (BROKEN.to_owned(), "broken".to_owned()),
+        ]);
+        CargoOutput::new(PathBuf::from("/ws"), 2, packages)
+    }
+
+    fn artifact(package_id: &str, name: &str, kind: &str) -> String {
+        serde_json::json!({
+            "reason": "compiler-artifact",
+            "package_id": package_id,
+            "target": { "name": name, "kind": [kind] },
+        })
+        .to_string()
+    }
+
+    fn message(package_id: &str, level: &str, text: &str) -> String {
+        serde_json::json!({
+            "reason": "compiler-message",
+            "package_id": package_id,
+            "message": {
+                "message": text,
+                "code": null,
+                "level": level,
+                "spans": [],
+                "children": [],
+                "rendered": null,
+            },
+        })
+        .to_string()
+    }
+
+    fn failed(events: &[AnalyzerEvent]) -> Vec<(&str, &str)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                AnalyzerEvent::CrateFailed { package, message } => {
+                    Some((package.as_str(), message.as_str()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cargo_output_reports_crashed_package() {
+        let mut output = cargo_output();
+        let checked = output.events(&artifact(GOOD, "good", "lib"));
+        assert!(matches!(
+            checked.as_slice(),
+            [AnalyzerEvent::CrateChecked { package, package_count: 2 }] if package == "good"
+        ));
+        assert!(output.events("not json").is_empty());
+        let ice = message(BROKEN, "error: internal compiler error", "unexpected panic");
+        assert!(output.events(&ice).is_empty(), "no file to show it in");
+        let aborting = message(BROKEN, "error", "aborting due to 1 previous error");
+        assert!(output.events(&aborting).is_empty());
+
+        let events = output.events(r#"{"reason":"build-finished","success":false}"#);
+        assert_eq!(failed(&events), vec![("broken", "unexpected panic")]);
+        assert!(!output.success);
+    }
+
+    #[test]
+    fn test_cargo_output_reports_packages_without_crates() {
+        let mut output = cargo_output();
+        output.events(&artifact(BROKEN, "build-script-build", "custom-build"));
+        let warning = message(GOOD, "warning", "unused variable");
+        output.events(&warning);
+        let events = output.events(r#"{"reason":"build-finished","success":false}"#);
+        assert_eq!(
+            failed(&events),
+            vec![
+                ("broken", "the package failed to build"),
+                ("good", "the package failed to build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cargo_output_of_successful_build() {
+        let mut output = cargo_output();
+        output.events(&artifact(GOOD, "good", "lib"));
+        output.events(&artifact(BROKEN, "broken", "bin"));
+        // errors of dependencies are not errors of the workspace
+        output.events(&message("registry+dep#1.0.0", "error", "dependency"));
+        let events = output.events(r#"{"reason":"build-finished","success":true}"#);
+        assert!(events.is_empty());
+        assert!(output.success);
+    }
+
+    /// Runs no command, the outcome of `cargo metadata` is the one of the
+    /// closure.
+    struct Fabricated<F>(F);
+
+    impl<F: FnMut() -> io::Result<Output>> CommandRunner for Fabricated<F> {
+        async fn output(&mut self, _: Command) -> io::Result<Output> {
+            (self.0)()
+        }
+    }
+
+    /// Output of a command exiting with `code`.
+    #[cfg(unix)]
+    fn exited(code: i32, stdout: &str, stderr: &str) -> Output {
+        use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.into(),
+            stderr: stderr.into(),
+        }
+    }
+
+    async fn load(
+        path: &Path,
+        outcome: impl FnMut() -> io::Result<Output>,
+    ) -> Result<Analyzer, AnalyzerError> {
+        Analyzer::load(path, None, &mut Fabricated(outcome)).await
+    }
+
+    #[tokio::test]
+    async fn test_missing_cargo_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = load(dir.path(), || Err(io::ErrorKind::NotFound.into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AnalyzerError::CargoNotFound), "{error:?}");
+        assert!(error.is_actionable());
+
+        let error = load(dir.path(), || Err(io::ErrorKind::PermissionDenied.into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AnalyzerError::Io(_)), "{error:?}");
+        assert!(!error.is_actionable());
+        assert!(error::Error::source(&error).is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_metadata_of_package_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "[package").unwrap();
+        let error = load(dir.path(), || {
+            Ok(exited(101, "", "error: failed to parse manifest\n"))
+        })
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&error, AnalyzerError::MetadataFailed { stderr } if stderr == "error: failed to parse manifest"),
+            "{error:?}"
+        );
+        assert!(error.is_actionable());
+
+        let error = load(dir.path(), || Ok(exited(0, "not metadata", "")))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, AnalyzerError::MetadataFailed { .. }),
+            "{error:?}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_paths_outside_of_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("notes.txt");
+        let main = dir.path().join("main.rs");
+        fs::write(&notes, "").unwrap();
+        fs::write(&main, "fn main() {}").unwrap();
+        let no_manifest = || Ok(exited(101, "", "error: could not find `Cargo.toml`"));
+
+        let error = load(&notes, no_manifest).await.unwrap_err();
+        assert!(
+            matches!(&error, AnalyzerError::NotARustTarget { path } if *path == notes),
+            "{error:?}"
+        );
+        assert!(!error.is_actionable());
+
+        let analyzer = load(&main, no_manifest).await.unwrap();
+        assert_eq!(analyzer.target_path(), main);
+        assert!(!analyzer.is_package());
+
+        let error = load(&dir.path().join("missing"), || unreachable!())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, AnalyzerError::NotARustTarget { .. }),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn test_target_kind_serde() {
+        let kinds: Vec<TargetKind> = serde_json::from_str(r#"["example","bench"]"#).unwrap();
+        assert_eq!(kinds, vec![TargetKind::Example, TargetKind::Bench]);
+        assert!(serde_json::from_str::<TargetKind>(r#""examples""#).is_err());
+    }
+}
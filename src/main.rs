@@ -3,14 +3,7 @@
 use std::{env, process::exit};
 
 use clap::Parser;
-use ferrous_owl::{Cli, run_as_rustc_wrapper};
-
-fn initialize_logging() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .format_timestamp(None)
-        .target(env_logger::Target::Stderr)
-        .init();
-}
+use ferrous_owl::{Cli, init_logging, run_as_rustc_wrapper};
 
 /// Check if invoked as a compiler by cargo.
 ///
@@ -29,10 +22,11 @@ async fn main() {
         .unwrap();
 
     if is_invoked_as_compiler() {
-        initialize_logging();
+        init_logging(None);
         exit(run_as_rustc_wrapper());
     }
 
-    initialize_logging();
-    Cli::parse().run().await;
+    let cli = Cli::parse();
+    init_logging(cli.log_file.clone());
+    cli.run().await;
 }
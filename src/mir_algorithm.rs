@@ -0,0 +1,121 @@
+//! Polonius algorithms the borrow check facts are computed with.
+
+use std::{env, error, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable selecting the Polonius algorithm, see
+/// [`AlgorithmChoice`]
+pub const POLONIUS_ALGO_ENV: &str = "FERROUS_OWL_POLONIUS_ALGO";
+
+/// Polonius algorithm computing the borrow check facts of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlgorithmChoice {
+    /// Slow reference implementation, for debugging precision
+    Naive,
+    #[default]
+    DatafrogOpt,
+    /// Fast, with borrows live wherever their origins may hold them
+    LocationInsensitive,
+    /// Location insensitive check, precise one only for functions with
+    /// potential errors
+    Hybrid,
+}
+
+impl AlgorithmChoice {
+    pub const ALL: [Self; 4] = [
+        Self::Naive,
+        Self::DatafrogOpt,
+        Self::LocationInsensitive,
+        Self::Hybrid,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Naive => "naive",
+            Self::DatafrogOpt => "datafrog-opt",
+            Self::LocationInsensitive => "location-insensitive",
+            Self::Hybrid => "hybrid",
+        }
+    }
+
+    /// Algorithm selected by [`POLONIUS_ALGO_ENV`], if any.
+    pub fn from_env() -> Result<Option<Self>, ParseAlgorithmError> {
+        env::var(POLONIUS_ALGO_ENV)
+            .ok()
+            .map(|value| value.parse())
+            .transpose()
+    }
+}
+
+impl fmt::Display for AlgorithmChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AlgorithmChoice {
+    type Err = ParseAlgorithmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Self::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == s)
+            .ok_or_else(|| ParseAlgorithmError(s.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAlgorithmError(String);
+
+impl fmt::Display for ParseAlgorithmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let allowed: Vec<_> = AlgorithmChoice::ALL
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        write!(
+            f,
+            "unknown Polonius algorithm `{}`, expected one of: {}",
+            self.0,
+            allowed.join(", ")
+        )
+    }
+}
+
+impl error::Error for ParseAlgorithmError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algorithm_choice_parsing() {
+        for algorithm in AlgorithmChoice::ALL {
+            assert_eq!(algorithm.to_string().parse(), Ok(algorithm));
+        }
+        assert_eq!(" naive\n".parse(), Ok(AlgorithmChoice::Naive));
+        assert_eq!(AlgorithmChoice::default(), AlgorithmChoice::DatafrogOpt);
+
+        let error = "DatafrogOpt".parse::<AlgorithmChoice>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown Polonius algorithm `DatafrogOpt`, expected one of: naive, datafrog-opt, \
+             location-insensitive, hybrid"
+        );
+    }
+
+    #[test]
+    fn test_algorithm_choice_serde_matches_names() {
+        for algorithm in AlgorithmChoice::ALL {
+            let json = serde_json::to_string(&algorithm).unwrap();
+            assert_eq!(json, format!("\"{algorithm}\""));
+            assert_eq!(
+                serde_json::from_str::<AlgorithmChoice>(&json).unwrap(),
+                algorithm
+            );
+        }
+    }
+}
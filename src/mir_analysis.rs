@@ -1,20 +1,58 @@
-use std::{collections::HashMap, env::current_dir, fs::read_to_string, future::Future, pin::Pin};
+use std::{
+    collections::{HashMap, HashSet},
+    env::current_dir,
+    fs::read_to_string,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex},
+    time::Instant,
+};
 
 use rustc_borrowck::consumers::{
-    ConsumerOptions, PoloniusInput, PoloniusOutput, get_body_with_borrowck_facts,
+    ConsumerOptions, PoloniusInput, PoloniusLocationTable, PoloniusOutput,
+    get_body_with_borrowck_facts,
+};
+use rustc_hir::{
+    def::DefKind,
+    def_id::{LOCAL_CRATE, LocalDefId},
 };
-use rustc_hir::def_id::{LOCAL_CRATE, LocalDefId};
 use rustc_middle::{
-    mir::{BasicBlock, Local},
+    mir::{BasicBlock, Body, Local, Location},
     ty::TyCtxt,
 };
-use rustc_span::Span;
+use rustc_span::{ExpnKind, Span};
 
 use crate::{
-    mir_cache, mir_polonius, mir_transform,
-    models::{FnLocal, Function, Loc, MirBasicBlock, MirDecl, Range},
+    mir_algorithm::AlgorithmChoice,
+    mir_cache::{self, CachedFunction},
+    mir_polonius, mir_transform,
+    models::{
+        AnalysisTimings, FnLocal, Function, Item, ItemKind, MirBasicBlock, MirDecl, MirTerminator,
+        Range, TypeInterner,
+    },
+    path_norm, range_ops,
+    text_conversion::SourceIndex,
 };
 
+impl From<AlgorithmChoice> for polonius_engine::Algorithm {
+    fn from(value: AlgorithmChoice) -> Self {
+        match value {
+            AlgorithmChoice::Naive => Self::Naive,
+            AlgorithmChoice::DatafrogOpt => Self::DatafrogOpt,
+            AlgorithmChoice::LocationInsensitive => Self::LocationInsensitive,
+            AlgorithmChoice::Hybrid => Self::Hybrid,
+        }
+    }
+}
+
+/// Algorithm for the borrow check, validated when the compiler starts.
+static POLONIUS_ALGORITHM: LazyLock<AlgorithmChoice> = LazyLock::new(|| {
+    AlgorithmChoice::from_env()
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+});
+
 pub type MirAnalyzeFuture = Pin<Box<dyn Future<Output = MirAnalyzer> + Send + Sync>>;
 
 #[derive(Clone, Debug)]
@@ -22,177 +60,474 @@ pub struct AnalyzeResult {
     pub file_name: String,
     pub file_hash: String,
     pub mir_hash: String,
-    pub analyzed: Function,
+    pub analyzed: Item,
+    /// Types the declarations of `analyzed` index, see
+    /// [`crate::models::DeclType`]
+    pub types: Vec<String>,
+    /// Lifetimes and borrows are missing as their analysis took too long;
+    /// must not be cached
+    pub degraded: bool,
+    pub timings: AnalysisTimings,
 }
 
 pub enum MirAnalyzerInitResult {
-    Cached(AnalyzeResult),
-    Analyzer(MirAnalyzeFuture),
+    Cached(Box<AnalyzeResult>),
+    Analyzer {
+        analyzer: MirAnalyzeFuture,
+        /// Analyzer without borrow check results, see [`MirAnalyzer::degraded`]
+        fallback: Box<MirAnalyzer>,
+    },
+    /// The function cannot be analyzed, for the given reason
+    Skipped(String),
 }
 
-pub fn range_from_span(source: &str, span: Span, offset: u32) -> Option<Range> {
-    let from = Loc::from_byte_pos(source, span.lo().0, offset);
-    let until = Loc::from_byte_pos(source, span.hi().0, offset);
-    Range::new(from, until)
+/// Range of `span` in the file of `source`, see [`macro_range_from_span`].
+pub fn range_from_span(source: &SourceIndex, span: Span) -> Option<Range> {
+    macro_range_from_span(source, span).map(|(range, _)| range)
+}
+
+/// Range of `span` in the file of `source`, and whether it comes from a
+/// macro expansion.
+///
+/// Code expanded from a macro is shown at the outermost macro call, except
+/// for the tokens written inside the call, like the arguments of `format!`,
+/// which keep their own range. Desugarings like `?` are not macros. Spans
+/// outside of the file have no range.
+pub fn macro_range_from_span(source: &SourceIndex, span: Span) -> Option<(Range, bool)> {
+    let call_site = macro_call_site(span);
+    let span = call_site.map_or(span, |call_site| {
+        span.find_ancestor_inside(call_site).unwrap_or(call_site)
+    });
+    if !source.contains(span.lo().0, span.hi().0) {
+        return None;
+    }
+    let from = source.byte_to_char(span.lo().0);
+    let until = source.byte_to_char(span.hi().0);
+    Range::new(from, until).map(|range| (range, call_site.is_some()))
+}
+
+/// Outermost macro call `span` is expanded from, if any.
+fn macro_call_site(span: Span) -> Option<Span> {
+    let mut call_site = None;
+    let mut current = span;
+    while current.from_expansion() {
+        let expansion = current.ctxt().outer_expn_data();
+        if matches!(expansion.kind, ExpnKind::Macro(..)) {
+            call_site = Some(expansion.call_site);
+        }
+        current = expansion.call_site;
+    }
+    call_site
 }
 
 pub fn sort_locs(v: &mut [(BasicBlock, usize)]) {
     v.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
 }
 
+/// Reference counted pointers, whose clones share one allocation, by the last
+/// segment of their path
+const SHARED_POINTER_TYPES: &[&str] = &["Rc", "Arc"];
+
+/// Whether values of the type `ty`, as printed by the compiler, are
+/// reference counted pointers. References to them are not.
+#[must_use]
+pub fn is_shared_pointer(ty: &str) -> bool {
+    let path = ty.split('<').next().unwrap_or_default().trim();
+    // references and pointers start with a symbol
+    path.chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | ':'))
+        && path
+            .rsplit("::")
+            .next()
+            .is_some_and(|name| SHARED_POINTER_TYPES.contains(&name))
+}
+
+/// Whether the text of a callee, like `clone` of a method call or
+/// `Arc::clone`, names `Clone::clone`.
+#[must_use]
+pub fn is_clone_callee(callee: &str) -> bool {
+    callee.trim().rsplit("::").next() == Some("clone")
+}
+
+/// Clone edges of the calls of `clone` from
+/// [`mir_transform::collect_clone_calls`] whose receiver and result are both
+/// reference counted pointers, see [`Function::clone_edges`].
+fn clone_edges(
+    calls: &[(Local, Local)],
+    local_decls: &HashMap<Local, String>,
+    fn_key: u64,
+) -> Vec<(FnLocal, FnLocal)> {
+    let shared = |local| {
+        local_decls
+            .get(local)
+            .is_some_and(|ty| is_shared_pointer(ty))
+    };
+    calls
+        .iter()
+        .filter(|(receiver, destination)| shared(receiver) && shared(destination))
+        .map(|(receiver, destination)| {
+            (
+                FnLocal::new(receiver.as_u32(), fn_key),
+                FnLocal::new(destination.as_u32(), fn_key),
+            )
+        })
+        .collect()
+}
+
+#[derive(Clone)]
 pub struct MirAnalyzer {
     file_name: String,
     local_decls: HashMap<Local, String>,
     user_vars: HashMap<Local, (Range, String)>,
-    input: PoloniusInput,
+    /// Lexical scopes of user variables, see
+    /// [`mir_transform::collect_user_var_scopes`]
+    user_var_scopes: HashMap<Local, Range>,
+    /// Locals that are dropped somewhere in the function
+    dropped: HashSet<Local>,
+    /// Assignments to user variables, see
+    /// [`mir_transform::collect_user_var_writes`]
+    user_var_writes: Vec<(Local, Location, Range)>,
+    /// Blocks, return locations and destinations of calls, see
+    /// [`mir_transform::collect_calls`]
+    calls: Vec<(BasicBlock, Location, Local)>,
     basic_blocks: Vec<MirBasicBlock>,
     fn_id: LocalDefId,
+    /// See [`Function::fn_key`]
+    fn_key: u64,
+    /// See [`Function::fn_name`]
+    fn_name: String,
+    kind: ItemKind,
+    /// Signature span, see [`Item::span`]
+    span: Option<Range>,
+    arg_count: u32,
+    captures: Vec<(FnLocal, FnLocal)>,
+    borrow_edges: Vec<(FnLocal, FnLocal)>,
+    /// See [`Function::clone_edges`]
+    clone_edges: Vec<(FnLocal, FnLocal)>,
+    /// See [`Function::await_points`]
+    await_points: Vec<Range>,
     file_hash: String,
     mir_hash: String,
     accurate_live: HashMap<Local, Vec<Range>>,
     must_live: HashMap<Local, Vec<Range>>,
+    /// Part of `must_live` required by the function signature
+    must_live_from_signature: HashMap<Local, Vec<Range>>,
     shared_live: HashMap<Local, Vec<Range>>,
     mutable_live: HashMap<Local, Vec<Range>>,
+    shared_mut_live: HashMap<Local, Vec<Range>>,
     drop_range: HashMap<Local, Vec<Range>>,
+    dead_writes: HashMap<Local, Vec<Range>>,
+    /// Locals borrowed by the value each call returns, by the block of the
+    /// call, see [`mir_polonius::call_borrows`]
+    call_borrows: HashMap<BasicBlock, Vec<Local>>,
+    timings: AnalysisTimings,
+}
+
+/// Path, hash and index of a source file
+type SourceFile = (String, String, Arc<SourceIndex>);
+
+/// Source files read so far, by their start offset in the source map
+static SOURCE_FILES: LazyLock<Mutex<HashMap<u32, SourceFile>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Forget the source files read so far, before another compilation in this
+/// process, which reuses their offsets for other files or texts.
+pub fn forget_source_files() {
+    SOURCE_FILES.lock().unwrap().clear();
+}
+
+/// Path, hash and index of the file containing `span`, read once per file.
+///
+/// Fails for spans outside of readable local files, with the reason.
+fn read_source(tcx: TyCtxt<'_>, span: Span) -> Result<SourceFile, String> {
+    let source_map = tcx.sess.source_map();
+
+    let file_name = source_map.span_to_filename(span);
+    let source_file = source_map
+        .get_source_file(&file_name)
+        .ok_or_else(|| format!("no source file {}", file_name.prefer_local()))?;
+    let offset = source_file.start_pos.0;
+    let mut files = SOURCE_FILES.lock().unwrap();
+    if let Some(file) = files.get(&offset) {
+        return Ok(file.clone());
+    }
+
+    let display_name = file_name.prefer_local().to_string();
+    let local_path = file_name
+        .into_local_path()
+        .ok_or_else(|| format!("{display_name} is not a local file"))?;
+    let working_dir = current_dir().map_err(|e| format!("no working directory: {e}"))?;
+    let file_name = source_map.path_mapping().to_embeddable_absolute_path(
+        rustc_span::RealFileName::LocalPath(local_path),
+        &rustc_span::RealFileName::LocalPath(working_dir),
+    );
+    let path = file_name.to_path(rustc_span::FileNameDisplayPreference::Local);
+    let source =
+        read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let file = (
+        path_norm::normalize_path(path),
+        mir_cache::source_hash(&source),
+        Arc::new(SourceIndex::new(&source).with_offset(offset)),
+    );
+    files.insert(offset, file.clone());
+    drop(files);
+    Ok(file)
+}
+
+/// Analysis of a function from the MIR cache of the crate, loading the
+/// cache first if needed.
+fn cached_function(
+    tcx: TyCtxt<'_>,
+    file_name: &str,
+    file_hash: &str,
+    mir_hash: &str,
+) -> Option<CachedFunction> {
+    let mut cache = mir_cache::CACHE.lock().unwrap();
+    if cache.is_none() {
+        *cache = mir_cache::get_cache(&tcx.crate_name(LOCAL_CRATE).to_string());
+    }
+    cache.as_mut()?.get_cache(file_name, file_hash, mir_hash)
+}
+
+/// Kind of the item whose body has the definition kind `def_kind`.
+const fn item_kind(def_kind: DefKind) -> ItemKind {
+    match def_kind {
+        DefKind::Static { .. } => ItemKind::Static,
+        DefKind::Const | DefKind::AssocConst | DefKind::AnonConst | DefKind::InlineConst => {
+            ItemKind::Const
+        }
+        _ => ItemKind::Fn,
+    }
+}
+
+/// Types of the locals of `body`, as printed by the compiler.
+fn local_types(body: &Body<'_>) -> HashMap<Local, String> {
+    body.local_decls
+        .iter_enumerated()
+        .map(|(local, decl)| (local, decl.ty.to_string()))
+        .collect()
+}
+
+/// Milliseconds elapsed since `start`.
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
 }
 
 impl MirAnalyzer {
     pub fn init(tcx: TyCtxt<'_>, fn_id: LocalDefId) -> MirAnalyzerInitResult {
+        let start = Instant::now();
         let mut facts =
             get_body_with_borrowck_facts(tcx, fn_id, ConsumerOptions::PoloniusInputFacts);
-        let input = *facts.input_facts.take().unwrap();
-        let location_table = facts.location_table.take().unwrap();
-
-        let source_map = tcx.sess.source_map();
-
-        let file_name = source_map.span_to_filename(facts.body.span);
-        let source_file = source_map.get_source_file(&file_name).unwrap();
-        let offset = source_file.start_pos.0;
-        let file_name = source_map.path_mapping().to_embeddable_absolute_path(
-            rustc_span::RealFileName::LocalPath(file_name.into_local_path().unwrap()),
-            &rustc_span::RealFileName::LocalPath(current_dir().unwrap()),
-        );
-        let path = file_name.to_path(rustc_span::FileNameDisplayPreference::Local);
-        let source = read_to_string(path).unwrap();
-        let file_name = path.to_string_lossy().to_string();
+        let (Some(input), Some(location_table)) =
+            (facts.input_facts.take(), facts.location_table.take())
+        else {
+            return MirAnalyzerInitResult::Skipped("no borrow check facts".to_owned());
+        };
+        let (file_name, file_hash, source) = match read_source(tcx, facts.body.span) {
+            Ok(file) => file,
+            Err(reason) => return MirAnalyzerInitResult::Skipped(reason),
+        };
         log::debug!("facts of {fn_id:?} prepared; start analyze of {fn_id:?}");
 
-        let local_decls = facts
-            .body
-            .local_decls
-            .iter_enumerated()
-            .map(|(local, decl)| (local, decl.ty.to_string()))
-            .collect();
+        let local_decls = local_types(&facts.body);
 
-        let mir_hash = mir_cache::Hasher::get_hash(
-            tcx,
-            mir_transform::erase_region_variables(tcx, facts.body.clone()),
-        );
-        let file_hash = mir_cache::Hasher::get_hash(tcx, &source);
-        let mut cache = mir_cache::CACHE.lock().unwrap();
-
-        if cache.is_none() {
-            *cache = mir_cache::get_cache(&tcx.crate_name(LOCAL_CRATE).to_string());
-        }
-        if let Some(cache) = cache.as_mut()
-            && let Some(analyzed) = cache.get_cache(&file_hash, &mir_hash)
-        {
+        let fn_key = mir_transform::fn_key(tcx, fn_id);
+        let body = mir_transform::erase_region_variables(tcx, facts.body.clone());
+        // the same body in another function must not share its cache entry
+        let mir_hash = mir_cache::Hasher::get_hash(tcx, (fn_key, body));
+        if let Some(cached) = cached_function(tcx, &file_name, &file_hash, &mir_hash) {
             log::debug!("MIR cache hit: {fn_id:?}");
-            return MirAnalyzerInitResult::Cached(AnalyzeResult {
+            return MirAnalyzerInitResult::Cached(Box::new(AnalyzeResult {
                 file_name,
                 file_hash,
                 mir_hash,
-                analyzed,
-            });
+                analyzed: cached.item,
+                types: cached.types,
+                degraded: false,
+                timings: AnalysisTimings::cached(elapsed_ms(start)),
+            }));
         }
-        drop(cache);
 
-        let user_vars = mir_transform::collect_user_vars(&source, offset, &facts.body);
+        let user_vars = mir_transform::collect_user_vars(&source, &facts.body);
+        let user_var_writes =
+            mir_transform::collect_user_var_writes(&source, &facts.body, &user_vars);
+        let user_var_scopes =
+            mir_transform::collect_user_var_scopes(&source, &facts.body, &user_vars);
 
+        let closure_like = tcx.is_closure_like(fn_id.to_def_id());
+        let origins =
+            mir_transform::ReceiverOrigins::new(tcx, &facts.body, &user_vars, closure_like);
         let basic_blocks = mir_transform::collect_basic_blocks(
-            fn_id,
+            tcx,
+            fn_key,
             &source,
-            offset,
             &facts.body.basic_blocks,
             tcx.sess.source_map(),
+            &origins,
+            closure_like,
         );
+        let calls = mir_transform::collect_calls(&facts.body);
+        let clone_calls =
+            mir_transform::collect_clone_calls(tcx, &facts.body, &origins, closure_like);
+        let clone_edges = clone_edges(&clone_calls, &local_decls, fn_key);
+        let captures = mir_transform::collect_captures(tcx, fn_key, &facts.body, closure_like);
+        let await_points = mir_transform::collect_await_points(&source, &facts.body.basic_blocks);
+        let span = range_from_span(&source, tcx.def_span(fn_id));
+        let arg_count = u32::try_from(facts.body.arg_count).unwrap_or(u32::MAX);
 
         let borrow_data = mir_transform::BorrowMap::new(&facts.borrow_set);
+        let borrow_edges = borrow_data.borrow_edges(fn_key);
 
-        let analyzer = Box::pin(async move {
-            log::debug!("start re-computing borrow check with dump: true");
-            let output_datafrog =
-                PoloniusOutput::compute(&input, polonius_engine::Algorithm::DatafrogOpt, true);
-            log::debug!("borrow check finished");
-
-            let accurate_live =
-                mir_polonius::get_accurate_live(&output_datafrog, &location_table, &basic_blocks);
-
-            let must_live = mir_polonius::get_must_live(
-                &output_datafrog,
-                &location_table,
-                &borrow_data,
-                &basic_blocks,
-            );
-
-            let (shared_live, mutable_live) = mir_polonius::get_borrow_live(
-                &output_datafrog,
-                &location_table,
-                &borrow_data,
-                &basic_blocks,
-            );
-
-            let drop_range =
-                mir_polonius::drop_range(&output_datafrog, &location_table, &basic_blocks);
-
-            Self {
-                file_name,
-                local_decls,
-                user_vars,
-                input,
-                basic_blocks,
-                fn_id,
-                file_hash,
-                mir_hash,
-                accurate_live,
-                must_live,
-                shared_live,
-                mutable_live,
-                drop_range,
-            }
-        });
-        MirAnalyzerInitResult::Analyzer(analyzer)
+        let dropped = input
+            .var_dropped_at
+            .iter()
+            .map(|(local, _)| *local)
+            .collect();
+        let base = Self {
+            file_name,
+            local_decls,
+            user_vars,
+            user_var_scopes,
+            dropped,
+            user_var_writes,
+            calls,
+            basic_blocks,
+            fn_id,
+            fn_key,
+            fn_name: tcx.def_path_str(fn_id),
+            kind: item_kind(tcx.def_kind(fn_id)),
+            span,
+            arg_count,
+            captures,
+            borrow_edges,
+            clone_edges,
+            await_points,
+            file_hash,
+            mir_hash,
+            accurate_live: HashMap::new(),
+            must_live: HashMap::new(),
+            must_live_from_signature: HashMap::new(),
+            shared_live: HashMap::new(),
+            mutable_live: HashMap::new(),
+            shared_mut_live: HashMap::new(),
+            drop_range: HashMap::new(),
+            dead_writes: HashMap::new(),
+            call_borrows: HashMap::new(),
+            timings: AnalysisTimings {
+                init_ms: elapsed_ms(start),
+                ..AnalysisTimings::default()
+            },
+        };
+        let fallback = Box::new(base.clone());
+
+        let analyzer =
+            Box::pin(async move { base.with_borrow_check(&input, &location_table, &borrow_data) });
+        MirAnalyzerInitResult::Analyzer { analyzer, fallback }
     }
 
-    fn collect_decls(&self) -> Vec<MirDecl> {
-        let user_vars = &self.user_vars;
-        let lives = &self.accurate_live;
-        let must_live_at = &self.must_live;
+    /// Fill in the ranges derived from the borrow check of the function.
+    fn with_borrow_check(
+        self,
+        input: &PoloniusInput,
+        location_table: &PoloniusLocationTable,
+        borrow_data: &mir_transform::BorrowMap,
+    ) -> Self {
+        let start = Instant::now();
+        let basic_blocks = &self.basic_blocks;
+        log::debug!("start re-computing borrow check with dump: true");
+        let mut output_datafrog =
+            PoloniusOutput::compute(input, (*POLONIUS_ALGORITHM).into(), true);
+        mir_polonius::fill_location_insensitive(&mut output_datafrog, input);
+        log::debug!("borrow check finished");
+
+        let accurate_live =
+            mir_polonius::get_accurate_live(&output_datafrog, location_table, basic_blocks);
+
+        let (must_live, must_live_from_signature) = mir_polonius::get_must_live(
+            &output_datafrog,
+            location_table,
+            borrow_data,
+            basic_blocks,
+            &input.universal_region,
+        );
 
-        let drop_range = &self.drop_range;
+        let (shared_place_live, mutable_place_live) = mir_polonius::get_borrow_live(
+            &output_datafrog,
+            location_table,
+            borrow_data,
+            basic_blocks,
+        );
+        let shared_mut_live =
+            mir_polonius::shared_mut_live(&shared_place_live, &mutable_place_live);
+        let shared_live = mir_polonius::borrow_live_by_local(&shared_place_live);
+        let mutable_live = mir_polonius::borrow_live_by_local(&mutable_place_live);
+
+        let drop_range = mir_polonius::drop_range(&output_datafrog, location_table, basic_blocks);
+        let dead_writes =
+            mir_polonius::dead_writes(&output_datafrog, location_table, &self.user_var_writes);
+        let call_borrows = mir_polonius::call_borrows(
+            &output_datafrog,
+            location_table,
+            borrow_data,
+            &input.use_of_var_derefs_origin,
+            &self.calls,
+        );
+
+        Self {
+            accurate_live,
+            must_live,
+            must_live_from_signature,
+            shared_live,
+            mutable_live,
+            shared_mut_live,
+            drop_range,
+            dead_writes,
+            call_borrows,
+            timings: AnalysisTimings {
+                polonius_ms: elapsed_ms(start),
+                ..self.timings
+            },
+            ..self
+        }
+    }
+
+    /// Declarations of the locals, with their types in `interner` and their
+    /// ranges sorted without duplicates, which overlapping facts produce.
+    fn collect_decls(&self, interner: &mut TypeInterner) -> Vec<MirDecl> {
+        let user_vars = &self.user_vars;
+        let ranges = |map: &HashMap<Local, Vec<Range>>, local: &Local| {
+            range_ops::sorted_ranges(map.get(local).cloned().unwrap_or_default())
+        };
         self.local_decls
             .iter()
             .map(|(local, ty)| {
-                let ty = ty.clone();
-                let must_live_at = must_live_at.get(local).cloned().unwrap_or(Vec::new());
-                let lives = lives.get(local).cloned().unwrap_or(Vec::new());
-                let shared_borrow = self.shared_live.get(local).cloned().unwrap_or(Vec::new());
-                let mutable_borrow = self.mutable_live.get(local).cloned().unwrap_or(Vec::new());
+                let ty = interner.intern(ty);
+                let must_live_at = ranges(&self.must_live, local);
+                let must_live_from_signature = ranges(&self.must_live_from_signature, local);
+                let lives = ranges(&self.accurate_live, local);
+                let shared_borrow = ranges(&self.shared_live, local);
+                let mutable_borrow = ranges(&self.mutable_live, local);
+                let shared_mut = ranges(&self.shared_mut_live, local);
                 let drop = self.is_drop(*local);
-                let drop_range = drop_range.get(local).cloned().unwrap_or(Vec::new());
-                let fn_local = FnLocal::new(local.as_u32(), self.fn_id.local_def_index.as_u32());
+                let drop_range = ranges(&self.drop_range, local);
+                let fn_local = FnLocal::new(local.as_u32(), self.fn_key);
+                let dead_writes = ranges(&self.dead_writes, local);
                 if let Some((span, name)) = user_vars.get(local).cloned() {
                     MirDecl::User {
                         local: fn_local,
                         name,
                         span,
+                        scope: self.user_var_scopes.get(local).copied(),
                         ty,
                         lives,
                         shared_borrow,
                         mutable_borrow,
+                        shared_mut,
                         must_live_at,
+                        must_live_from_signature,
                         drop,
                         drop_range,
+                        dead_writes,
                     }
                 } else {
                     MirDecl::Other {
@@ -201,9 +536,12 @@ impl MirAnalyzer {
                         lives,
                         shared_borrow,
                         mutable_borrow,
+                        shared_mut,
                         drop,
                         drop_range,
                         must_live_at,
+                        must_live_from_signature,
+                        dead_writes,
                     }
                 }
             })
@@ -211,28 +549,213 @@ impl MirAnalyzer {
     }
 
     fn is_drop(&self, local: Local) -> bool {
-        for (drop_local, _) in &self.input.var_dropped_at {
-            if *drop_local == local {
-                return true;
-            }
-        }
-        false
+        self.dropped.contains(&local)
     }
 
     #[must_use]
     pub fn analyze(self) -> AnalyzeResult {
-        let decls = self.collect_decls();
-        let basic_blocks = self.basic_blocks;
+        let start = Instant::now();
+        let mut interner = TypeInterner::default();
+        let decls = self.collect_decls(&mut interner);
+        let timings = AnalysisTimings {
+            collect_ms: elapsed_ms(start),
+            ..self.timings
+        };
+        let mut basic_blocks = self.basic_blocks;
+        for (block, borrowed) in self.call_borrows {
+            if let Some(MirTerminator::Call { borrows_from, .. }) = basic_blocks
+                .get_mut(block.as_usize())
+                .and_then(|bb| bb.terminator.as_mut())
+            {
+                *borrows_from = borrowed
+                    .into_iter()
+                    .map(|local| FnLocal::new(local.as_u32(), self.fn_key))
+                    .collect();
+            }
+        }
 
         AnalyzeResult {
             file_name: self.file_name,
             file_hash: self.file_hash,
             mir_hash: self.mir_hash,
-            analyzed: Function {
-                fn_id: self.fn_id.local_def_index.as_u32(),
-                basic_blocks,
-                decls,
+            analyzed: Item::Function {
+                span: self.span,
+                kind: self.kind,
+                function: Function {
+                    fn_id: self.fn_id.local_def_index.as_u32(),
+                    fn_key: self.fn_key,
+                    fn_name: self.fn_name,
+                    arg_count: self.arg_count,
+                    basic_blocks,
+                    decls,
+                    captures: self.captures,
+                    borrow_edges: self.borrow_edges,
+                    clone_edges: self.clone_edges,
+                    await_points: self.await_points,
+                    part: None,
+                    context: String::new(),
+                },
             },
+            types: interner.into_types(),
+            degraded: false,
+            timings,
         }
     }
+
+    /// Result with declarations and basic blocks only, for functions whose
+    /// borrow check analysis did not finish in time.
+    #[must_use]
+    pub fn degraded(self) -> AnalyzeResult {
+        let result = Self {
+            accurate_live: HashMap::new(),
+            must_live: HashMap::new(),
+            must_live_from_signature: HashMap::new(),
+            shared_live: HashMap::new(),
+            mutable_live: HashMap::new(),
+            shared_mut_live: HashMap::new(),
+            drop_range: HashMap::new(),
+            dead_writes: HashMap::new(),
+            call_borrows: HashMap::new(),
+            ..self
+        }
+        .analyze();
+        AnalyzeResult {
+            degraded: true,
+            ..result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hir::def_id::DefIndex;
+
+    use super::*;
+    use crate::models::CharOffset;
+
+    #[test]
+    fn test_is_shared_pointer() {
+        for ty in [
+            "std::rc::Rc<String>",
+            "std::sync::Arc<std::sync::Mutex<i32>>",
+            "Rc<i32>",
+            "alloc::sync::Arc<[u8]>",
+        ] {
+            assert!(is_shared_pointer(ty), "{ty}");
+        }
+        for ty in [
+            "&std::rc::Rc<String>",
+            "std::rc::Weak<String>",
+            "std::boxed::Box<std::rc::Rc<i32>>",
+            "String",
+            "my::Arcade<u8>",
+        ] {
+            assert!(!is_shared_pointer(ty), "{ty}");
+        }
+    }
+
+    #[test]
+    fn test_is_clone_callee() {
+        for callee in ["clone", "Arc::clone", "Rc::<i32>::clone", "Clone::clone"] {
+            assert!(is_clone_callee(callee), "{callee}");
+        }
+        for callee in ["clone_from", "cloned", "Arc::new", "to_owned"] {
+            assert!(!is_clone_callee(callee), "{callee}");
+        }
+    }
+
+    #[test]
+    fn test_clone_edges_need_shared_pointers() {
+        let local = Local::from_u32;
+        let local_decls = HashMap::from([
+            (local(1), "std::sync::Arc<String>".to_owned()),
+            (local(2), "std::sync::Arc<String>".to_owned()),
+            (local(3), "String".to_owned()),
+            (local(4), "String".to_owned()),
+        ]);
+        let calls = [
+            (local(1), local(2)),
+            (local(3), local(4)),
+            (local(1), local(5)),
+        ];
+        assert_eq!(
+            clone_edges(&calls, &local_decls, 7),
+            vec![(FnLocal::new(1, 7), FnLocal::new(2, 7))]
+        );
+    }
+
+    #[test]
+    fn test_degraded_result_drops_borrow_check_ranges() {
+        let local = Local::from_u32(1);
+        let span = Range::new(CharOffset::from(4u32), CharOffset::from(5u32)).unwrap();
+        let lives = vec![Range::new(CharOffset::from(4u32), CharOffset::from(20u32)).unwrap()];
+        let analyzer = MirAnalyzer {
+            file_name: "lib.rs".to_owned(),
+            local_decls: HashMap::from([(local, "String".to_owned())]),
+            user_vars: HashMap::from([(local, (span, "s".to_owned()))]),
+            user_var_scopes: HashMap::new(),
+            dropped: HashSet::from([local]),
+            user_var_writes: Vec::new(),
+            calls: Vec::new(),
+            basic_blocks: Vec::new(),
+            fn_id: LocalDefId {
+                local_def_index: DefIndex::from_u32(3),
+            },
+            fn_key: 30,
+            fn_name: String::new(),
+            kind: ItemKind::Fn,
+            span: None,
+            arg_count: 0,
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            file_hash: "file".to_owned(),
+            mir_hash: "mir".to_owned(),
+            accurate_live: HashMap::from([(local, lives.clone())]),
+            must_live: HashMap::from([(local, lives.clone())]),
+            must_live_from_signature: HashMap::new(),
+            shared_live: HashMap::from([(local, lives.clone())]),
+            mutable_live: HashMap::from([(local, lives.clone())]),
+            shared_mut_live: HashMap::from([(local, lives.clone())]),
+            drop_range: HashMap::from([(local, lives.clone())]),
+            dead_writes: HashMap::from([(local, lives)]),
+            call_borrows: HashMap::new(),
+            timings: AnalysisTimings::default(),
+        };
+
+        let result = analyzer.degraded();
+        assert!(result.degraded);
+        assert_eq!(result.analyzed.function().fn_id, 3);
+        assert_eq!(result.analyzed.function().fn_key, 30);
+        assert_eq!(result.mir_hash, "mir");
+        let [
+            MirDecl::User {
+                name,
+                span: decl_span,
+                lives,
+                shared_borrow,
+                mutable_borrow,
+                shared_mut,
+                drop,
+                drop_range,
+                must_live_at,
+                dead_writes,
+                ..
+            },
+        ] = result.analyzed.function().decls.as_slice()
+        else {
+            panic!("expected one user declaration");
+        };
+        assert_eq!(name, "s");
+        assert_eq!(*decl_span, span);
+        assert!(*drop);
+        assert!(lives.is_empty());
+        assert!(shared_borrow.is_empty());
+        assert!(mutable_borrow.is_empty());
+        assert!(shared_mut.is_empty());
+        assert!(drop_range.is_empty());
+        assert!(must_live_at.is_empty());
+        assert!(dead_writes.is_empty());
+    }
 }
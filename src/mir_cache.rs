@@ -1,33 +1,83 @@
+#![cfg_attr(
+    not(feature = "compiler"),
+    allow(dead_code, reason = "only the compiler reads and writes the cache")
+)]
+
 use std::{
-    collections::HashMap,
-    env,
-    fs::{self, OpenOptions},
-    io::Write,
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    hash::Hash,
+    io::{self, ErrorKind, Write},
+    mem,
+    path::{Path, PathBuf},
+    process,
     sync::{LazyLock, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(feature = "compiler")]
 use rustc_data_structures::stable_hasher::{HashStable, StableHasher};
+#[cfg(feature = "compiler")]
 use rustc_middle::ty::TyCtxt;
+#[cfg(feature = "compiler")]
 use rustc_query_system::ich::StableHashingContext;
+#[cfg(feature = "compiler")]
 use rustc_stable_hash::{FromStableHash, SipHasher128Hash};
 use serde::{Deserialize, Serialize};
 
-use crate::{models::Function, toolchain::CACHE_DIR_ENV};
+use crate::{
+    models::Item,
+    toolchain::{CACHE_DIR_ENV, RUSTC_COMMIT_HASH},
+};
 
 fn get_cache_path() -> Option<PathBuf> {
     env::var(CACHE_DIR_ENV).map(PathBuf::from).ok()
 }
 
+/// Directory of the caches of the user, like `~/.cache` on Linux
+fn user_cache_dir() -> Option<PathBuf> {
+    let var = |name| {
+        env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
+    }
+}
+
+/// Cache directory of the file at `path` in `base`, named by a hash of the
+/// path
+fn single_file_dir(base: &Path, path: &Path) -> PathBuf {
+    base.join("single")
+        .join(hash_parts([path.as_os_str().as_encoded_bytes()]))
+}
+
+/// Cache directory of the file at `path` analyzed on its own, in
+/// [`CACHE_DIR_ENV`] if set, else in the cache directory of the user
+#[must_use]
+pub fn single_file_cache_dir(path: &Path) -> Option<PathBuf> {
+    let base = get_cache_path().or_else(|| Some(user_cache_dir()?.join(env!("CARGO_PKG_NAME"))))?;
+    Some(single_file_dir(&base, path))
+}
+
 pub static CACHE: LazyLock<Mutex<Option<CacheData>>> = LazyLock::new(|| Mutex::new(None));
 
+#[cfg(feature = "compiler")]
 #[derive(Debug, Clone)]
 struct StableHashString(String);
+#[cfg(feature = "compiler")]
 impl StableHashString {
     pub fn get(self) -> String {
         self.0
     }
 }
+#[cfg(feature = "compiler")]
 impl FromStableHash for StableHashString {
     type Hash = SipHasher128Hash;
     fn from(hash: Self::Hash) -> Self {
@@ -38,11 +88,24 @@ impl FromStableHash for StableHashString {
     }
 }
 
+#[cfg(feature = "compiler")]
+#[derive(Debug, Clone, Copy)]
+struct StableHashU64(u64);
+#[cfg(feature = "compiler")]
+impl FromStableHash for StableHashU64 {
+    type Hash = SipHasher128Hash;
+    fn from(hash: Self::Hash) -> Self {
+        Self(hash.0[0])
+    }
+}
+
+#[cfg(feature = "compiler")]
 pub struct Hasher<'a> {
     hasher: StableHasher,
     hash_ctx: StableHashingContext<'a>,
 }
 
+#[cfg(feature = "compiler")]
 impl<'tcx> Hasher<'tcx> {
     #[must_use]
     pub fn new(tcx: TyCtxt<'tcx>) -> Self {
@@ -66,84 +129,620 @@ impl<'tcx> Hasher<'tcx> {
     }
 }
 
-/// Single file cache body
+/// Hash of `parts`, for data that is hashed outside of a compiler session
+#[cfg(feature = "compiler")]
+#[must_use]
+pub fn hash_parts<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = StableHasher::default();
+    for part in parts {
+        part.hash(&mut hasher);
+    }
+    hasher.finish::<StableHashString>().get()
+}
+
+/// Hash of `parts` without the stable hasher of the compiler, so it differs
+/// from the hashes of builds with the `compiler` feature
+#[cfg(not(feature = "compiler"))]
+#[must_use]
+pub fn hash_parts<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> String {
+    use std::hash::{DefaultHasher, Hasher as _};
+
+    let mut hasher = DefaultHasher::new();
+    for part in parts {
+        part.hash(&mut hasher);
+    }
+    format!("{:x}", hasher.finish())
+}
+
+/// Hash of the text of a source file, the same in the compiler and in the
+/// language server
+#[must_use]
+pub fn source_hash(source: &str) -> String {
+    hash_parts([source.as_bytes()])
+}
+
+/// Key of a function, from its crate and its definition path like
+/// `module::{impl#0}::method`
+///
+/// Definition indices differ between targets of a crate, as `cfg(test)`
+/// items shift them, while the path stays the same.
+#[cfg(feature = "compiler")]
+#[must_use]
+pub fn fn_key(krate: &str, def_path: &str) -> u64 {
+    let mut hasher = StableHasher::default();
+    krate.hash(&mut hasher);
+    def_path.hash(&mut hasher);
+    hasher.finish::<StableHashU64>().0
+}
+
+/// Version of the cache layout and of the cached models, bump when
+/// [`Item`] or anything it contains changes shape
+const CACHE_SCHEMA_VERSION: u32 = 17;
+
+/// Cached analysis result of a function
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CachedFunction {
+    pub item: Item,
+    /// Types the declarations of `item` index, see
+    /// [`crate::models::DeclType`]
+    pub types: Vec<String>,
+}
+
+/// Versions a cache shard was written with
+///
+/// Shards written by another version of ferrous-owl or the compiler are
+/// discarded, as their ranges and models may not match.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct CacheHeader {
+    owl_version: String,
+    rustc_commit: String,
+    schema: u32,
+}
+
+impl CacheHeader {
+    #[must_use]
+    pub fn current() -> Self {
+        Self {
+            owl_version: env!("CARGO_PKG_VERSION").to_owned(),
+            rustc_commit: RUSTC_COMMIT_HASH.to_owned(),
+            schema: CACHE_SCHEMA_VERSION,
+        }
+    }
+}
+
+impl fmt::Display for CacheHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ferrous-owl {}, rustc {}, schema {}",
+            self.owl_version, self.rustc_commit, self.schema
+        )
+    }
+}
+
+/// Cache of a single source file
 ///
-/// this is a map: file hash -> (MIR body hash -> analyze result)
+/// `files` is a map: file hash -> (MIR body hash -> analyze result)
 ///
 /// Note: Cache can be utilized when neither
 /// the MIR body nor the entire file is modified.
 #[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(transparent)]
-pub struct CacheData(HashMap<String, HashMap<String, Function>>);
+struct Shard {
+    header: CacheHeader,
+    files: HashMap<String, HashMap<String, CachedFunction>>,
+}
+
+impl Shard {
+    fn new(header: CacheHeader) -> Self {
+        Self {
+            header,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Read the shard at `path` with the deltas written since it was last
+    /// compacted, or start a new one if there is none written with `header`.
+    ///
+    /// The deltas are merged into the shard at `path` and removed.
+    fn load(path: &Path, header: &CacheHeader) -> Self {
+        with_lock(path, || {
+            let mut shard = Self::read(path, header).unwrap_or_else(|| Self::new(header.clone()));
+            let deltas = deltas(path);
+            if deltas.is_empty() {
+                return shard;
+            }
+            for delta in &deltas {
+                if let Some(delta) = Self::read(delta, header) {
+                    shard.merge(delta);
+                }
+            }
+            match shard.write(path) {
+                Ok(()) => {
+                    for delta in deltas {
+                        fs::remove_file(delta).ok();
+                    }
+                }
+                Err(e) => log::warn!("failed to compact incremental cache file: {e}"),
+            }
+            shard
+        })
+    }
+
+    /// Read the shard at `path` if it was written with `header`.
+    ///
+    /// Shards of other versions are removed and unreadable ones are
+    /// quarantined, see [`quarantine`].
+    fn read(path: &Path, header: &CacheHeader) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct HeaderOnly {
+            header: CacheHeader,
+        }
+
+        let s = match fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("failed to read incremental cache file: {e}");
+                return None;
+            }
+        };
+        let corrupt = match serde_json::from_str::<HeaderOnly>(&s) {
+            Ok(HeaderOnly { header: found }) if found == *header => {
+                match serde_json::from_str::<Self>(&s) {
+                    Ok(shard) => {
+                        log::debug!("cache read: {}", path.display());
+                        return Some(shard);
+                    }
+                    Err(e) => format!("cannot be read: {e}"),
+                }
+            }
+            Ok(HeaderOnly { header: found }) => {
+                log::info!(
+                    "discarding cache file {}, it is written by {found}, not {header}",
+                    path.display()
+                );
+                fs::remove_file(path).ok();
+                return None;
+            }
+            Err(e) => format!("has no valid header: {e}"),
+        };
+        log::warn!("quarantining cache file {}, it {corrupt}", path.display());
+        quarantine(path);
+        None
+    }
+
+    /// Add the functions of `other`, which replace equal keys.
+    fn merge(&mut self, other: Self) {
+        for (file_hash, functions) in other.files {
+            self.files.entry(file_hash).or_default().extend(functions);
+        }
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, serde_json::to_string(self).unwrap().as_bytes())?;
+        log::debug!("incremental cache saved: {}", path.display());
+        Ok(())
+    }
+}
+
+/// Write `contents` to `path` through a temporary file in the same
+/// directory renamed over it, so that readers and crashes never leave a
+/// partly written file.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".tmp-{}", process::id()));
+    let temp = PathBuf::from(temp);
+    let written = File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    let renamed = written.and_then(|()| fs::rename(&temp, path));
+    if renamed.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    renamed
+}
+
+/// Move the unreadable cache file at `path` aside, next to it with the
+/// suffix `.corrupt-<unix time>`, to be inspected rather than read again.
+fn quarantine(path: &Path) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let mut corrupt = path.as_os_str().to_owned();
+    corrupt.push(format!(".corrupt-{time}"));
+    if let Err(e) = fs::rename(path, &corrupt) {
+        log::warn!("failed to quarantine cache file: {e}");
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Run `f` holding the advisory lock of the shard at `path`, or without it
+/// if it cannot be taken.
+///
+/// Wrappers compiling targets of the same crate in parallel share its
+/// shards.
+fn with_lock<T>(path: &Path, f: impl FnOnce() -> T) -> T {
+    let lock = path.with_extension("lock");
+    let locked = lock
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&lock)
+        })
+        .and_then(|file| file.lock().map(|()| file));
+    if let Err(e) = &locked {
+        log::warn!("failed to lock incremental cache file: {e}");
+    }
+    // unlocked when the file is closed
+    f()
+}
+
+/// Path of the delta of the shard at `path` written by this process, merged
+/// into the shard when it is next loaded
+fn delta_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}-{}.json", process::id()))
+}
+
+/// Deltas of the shard at `path` written since it was last compacted
+fn deltas(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}-", stem.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut deltas: Vec<_> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|delta| {
+            delta.extension().is_some_and(|ext| ext == "json")
+                && delta
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .collect();
+    deltas.sort();
+    deltas
+}
+
+/// Path of the cache shard of the source file `file_name` in the cache
+/// directory `dir` of a crate
+fn shard_path(dir: &Path, file_name: &str) -> PathBuf {
+    dir.join(format!("{}.json", hash_parts([file_name.as_bytes()])))
+}
+
+/// Cache of a crate, split into one shard per source file
+///
+/// Shards are read when first used and only changed shards are written.
+#[derive(Clone, Debug)]
+pub struct CacheData {
+    dir: PathBuf,
+    header: CacheHeader,
+    shards: HashMap<String, Shard>,
+    changed: HashSet<String>,
+}
 impl CacheData {
     #[must_use]
-    pub fn new() -> Self {
-        Self(HashMap::new())
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            header: CacheHeader::current(),
+            shards: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    fn shard(&mut self, file_name: &str) -> &mut Shard {
+        self.shards
+            .entry(file_name.to_owned())
+            .or_insert_with(|| Shard::load(&shard_path(&self.dir, file_name), &self.header))
     }
+
     #[must_use]
-    pub fn get_cache(&self, file_hash: &str, mir_hash: &str) -> Option<Function> {
-        self.0.get(file_hash).and_then(|v| v.get(mir_hash)).cloned()
+    pub fn get_cache(
+        &mut self,
+        file_name: &str,
+        file_hash: &str,
+        mir_hash: &str,
+    ) -> Option<CachedFunction> {
+        self.shard(file_name)
+            .files
+            .get(file_hash)
+            .and_then(|v| v.get(mir_hash))
+            .cloned()
     }
-    pub fn insert_cache(&mut self, file_hash: String, mir_hash: String, analyzed: Function) {
-        self.0
+
+    /// Add `analyzed`, marking its shard as changed unless it was cached
+    /// already.
+    pub fn insert_cache(
+        &mut self,
+        file_name: &str,
+        file_hash: String,
+        mir_hash: String,
+        analyzed: CachedFunction,
+    ) {
+        let known = self
+            .shard(file_name)
+            .files
             .entry(file_hash)
             .or_default()
-            .insert(mir_hash, analyzed);
+            .insert(mir_hash, analyzed)
+            .is_some();
+        if !known {
+            self.changed.insert(file_name.to_owned());
+        }
     }
-}
 
-impl Default for CacheData {
-    fn default() -> Self {
-        Self::new()
+    /// Write the shards changed since the last write, as deltas of this
+    /// process that are merged into the shards when they are next loaded.
+    pub fn write(&mut self) {
+        for file_name in mem::take(&mut self.changed) {
+            if let Some(shard) = self.shards.get(&file_name) {
+                let path = shard_path(&self.dir, &file_name);
+                if let Err(e) = with_lock(&path, || shard.write(&delta_path(&path))) {
+                    log::warn!("failed to write incremental cache file: {e}");
+                }
+            }
+        }
     }
 }
 
-/// Get cache data
+/// Get cache data of `krate`
 ///
 /// If cache is not enabled, then return None.
-/// If file is not exists, it returns empty [`CacheData`].
+/// Caches of older versions, which were a single file per crate, are removed.
 #[must_use]
 pub fn get_cache(krate: &str) -> Option<CacheData> {
-    if let Some(cache_path) = get_cache_path() {
-        let cache_path = cache_path.join(format!("{krate}.json"));
-        let s = match fs::read_to_string(&cache_path) {
-            Ok(v) => v,
-            Err(e) => {
-                log::warn!("failed to read incremental cache file: {e}");
-                return Some(CacheData::new());
-            }
-        };
-        let read = serde_json::from_str(&s).ok();
-        log::info!("cache read: {}", cache_path.display());
-        read
-    } else {
-        None
+    let cache_path = get_cache_path()?;
+    let legacy = cache_path.join(format!("{krate}.json"));
+    if legacy.is_file() {
+        log::info!("discarding unversioned cache file {}", legacy.display());
+        fs::remove_file(&legacy).ok();
     }
+    Some(CacheData::new(cache_path.join(krate)))
 }
 
-pub fn write_cache(krate: &str, cache: &CacheData) {
-    if let Some(cache_path) = get_cache_path() {
-        if let Err(e) = fs::create_dir_all(&cache_path) {
-            log::warn!("failed to create cache dir: {e}");
-            return;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(fn_id: u32) -> CachedFunction {
+        CachedFunction {
+            item: serde_json::from_value(serde_json::json!({
+                "function": {
+                    "span": null,
+                    "function": {
+                        "fn_id": fn_id,
+                        "arg_count": 0,
+                        "basic_blocks": [],
+                        "decls": [],
+                    },
+                },
+            }))
+            .unwrap(),
+            types: Vec::new(),
         }
-        let cache_path = cache_path.join(format!("{krate}.json"));
-        let s = serde_json::to_string(cache).unwrap();
-        let mut f = match OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&cache_path)
-        {
-            Ok(v) => v,
-            Err(e) => {
-                log::warn!("failed to open incremental cache file: {e}");
-                return;
+    }
+
+    #[test]
+    fn test_single_files_have_a_directory_each() {
+        let base = Path::new("/cache");
+        let lib = single_file_dir(base, Path::new("/tmp/a/lib.rs"));
+        assert!(lib.starts_with("/cache/single"), "{}", lib.display());
+        assert_eq!(lib, single_file_dir(base, Path::new("/tmp/a/lib.rs")));
+        assert_ne!(lib, single_file_dir(base, Path::new("/tmp/b/lib.rs")));
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        cache.insert_cache(
+            "src/lib.rs",
+            "file".to_owned(),
+            "mir".to_owned(),
+            function(3),
+        );
+        cache.write();
+
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        let cached = cache.get_cache("src/lib.rs", "file", "mir");
+        assert_eq!(cached.map(|f| f.item.function().fn_id), Some(3));
+        assert!(cache.get_cache("src/lib.rs", "file", "other").is_none());
+        assert!(cache.get_cache("src/main.rs", "file", "mir").is_none());
+    }
+
+    #[test]
+    fn test_cache_with_old_header_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shard_path(dir.path(), "src/lib.rs");
+        for header in [
+            CacheHeader {
+                owl_version: "0.0.0".to_owned(),
+                ..CacheHeader::current()
+            },
+            CacheHeader {
+                rustc_commit: "0000000".to_owned(),
+                ..CacheHeader::current()
+            },
+            CacheHeader {
+                schema: 0,
+                ..CacheHeader::current()
+            },
+        ] {
+            let mut shard = Shard::new(header.clone());
+            shard
+                .files
+                .entry("file".to_owned())
+                .or_default()
+                .insert("mir".to_owned(), function(3));
+            shard.write(&path).unwrap();
+
+            let mut cache = CacheData::new(dir.path().to_path_buf());
+            assert!(
+                cache.get_cache("src/lib.rs", "file", "mir").is_none(),
+                "cache of {header} is used"
+            );
+            assert!(!path.exists(), "cache of {header} is not removed");
+        }
+    }
+
+    #[test]
+    fn test_cache_without_header_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shard_path(dir.path(), "src/lib.rs");
+        fs::write(&path, r#"{"file":{"mir":{"fn_id":3}}}"#).unwrap();
+
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        assert!(cache.get_cache("src/lib.rs", "file", "mir").is_none());
+        assert!(!path.exists());
+    }
+
+    /// Names of the files in `dir`, sorted
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_corrupt_cache_is_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shard_path(dir.path(), "src/lib.rs");
+        let mut shard = Shard::new(CacheHeader::current());
+        shard
+            .files
+            .entry("file".to_owned())
+            .or_default()
+            .insert("mir".to_owned(), function(3));
+        let written = serde_json::to_string(&shard).unwrap();
+        // cut off by a crash while writing
+        fs::write(&path, &written[..written.len() / 2]).unwrap();
+
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        assert!(cache.get_cache("src/lib.rs", "file", "mir").is_none());
+        assert!(!path.exists());
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(
+            file_names(dir.path())
+                .iter()
+                .any(|file| file.starts_with(&format!("{name}.corrupt-"))),
+            "{:?}",
+            file_names(dir.path())
+        );
+    }
+
+    #[test]
+    fn test_deltas_are_merged_into_the_shard() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shard_path(dir.path(), "src/lib.rs");
+        let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+        for (pid, functions) in [(1, [("a", 1), ("both", 2)]), (2, [("b", 3), ("both", 4)])] {
+            let mut delta = Shard::new(CacheHeader::current());
+            for (mir, fn_id) in functions {
+                delta
+                    .files
+                    .entry("file".to_owned())
+                    .or_default()
+                    .insert(mir.to_owned(), function(fn_id));
             }
-        };
-        if let Err(e) = f.write_all(s.as_bytes()) {
-            log::warn!("failed to write incremental cache file: {e}");
+            delta
+                .write(&dir.path().join(format!("{stem}-{pid}.json")))
+                .unwrap();
         }
-        log::info!("incremental cache saved: {}", cache_path.display());
+
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        let fn_id = |cache: &mut CacheData, mir| {
+            cache
+                .get_cache("src/lib.rs", "file", mir)
+                .map(|cached| cached.item.function().fn_id)
+        };
+        assert_eq!(fn_id(&mut cache, "a"), Some(1));
+        assert_eq!(fn_id(&mut cache, "b"), Some(3));
+        // the delta sorted last wins
+        assert_eq!(fn_id(&mut cache, "both"), Some(4));
+        assert_eq!(
+            file_names(dir.path()),
+            vec![format!("{stem}.json"), format!("{stem}.lock")],
+            "the deltas are compacted into the shard"
+        );
+
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        assert_eq!(fn_id(&mut cache, "b"), Some(3));
+    }
+
+    #[test]
+    fn test_writes_replace_the_file_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shard.json");
+        fs::write(&path, "old").unwrap();
+        write_atomically(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(
+            file_names(dir.path()),
+            vec!["shard.json"],
+            "no temporary file is left"
+        );
+
+        // a failed rename leaves the file as it was
+        let blocked = dir.path().join("dir.json");
+        fs::create_dir_all(blocked.join("inner")).unwrap();
+        assert!(write_atomically(&blocked, b"new").is_err());
+        assert!(blocked.is_dir());
+        assert_eq!(file_names(dir.path()), vec!["dir.json", "shard.json"]);
+    }
+
+    #[test]
+    fn test_writes_are_deltas_of_the_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = shard_path(dir.path(), "src/lib.rs");
+        let mut cache = CacheData::new(dir.path().to_path_buf());
+        cache.insert_cache(
+            "src/lib.rs",
+            "file".to_owned(),
+            "mir".to_owned(),
+            function(3),
+        );
+        cache.write();
+        assert!(!path.exists());
+        assert!(delta_path(&path).exists());
+        assert_eq!(deltas(&path), vec![delta_path(&path)]);
+    }
+
+    #[cfg(feature = "compiler")]
+    #[test]
+    fn test_fn_key() {
+        let key = fn_key("krate", "module::function");
+        assert_eq!(key, fn_key("krate", "module::function"), "keys are stable");
+        assert_ne!(key, fn_key("other", "module::function"));
+        assert_ne!(key, fn_key("krate", "module::{impl#0}::function"));
+        assert_ne!(fn_key("ab", "c"), fn_key("a", "bc"));
+    }
+
+    #[test]
+    fn test_shard_path() {
+        let dir = Path::new("/cache/krate");
+        let lib = shard_path(dir, "src/lib.rs");
+        assert_eq!(lib, shard_path(dir, "src/lib.rs"), "paths are stable");
+        assert_eq!(lib.parent(), Some(dir));
+        assert_eq!(lib.extension().and_then(|e| e.to_str()), Some("json"));
+        assert_ne!(lib, shard_path(dir, "src/main.rs"));
+        assert_ne!(
+            lib.file_name(),
+            shard_path(dir, "src/a/lib.rs").file_name(),
+            "files of the same name in other directories get their own shard"
+        );
     }
 }
@@ -1,9 +1,11 @@
 use std::collections::{HashMap, HashSet};
 
 use rayon::prelude::*;
-use rustc_borrowck::consumers::{PoloniusLocationTable, PoloniusOutput};
+use rustc_borrowck::consumers::{
+    PoloniusInput, PoloniusLocationTable, PoloniusOutput, PoloniusRegionVid,
+};
 use rustc_index::Idx;
-use rustc_middle::mir::Local;
+use rustc_middle::mir::{BasicBlock, Local, Location};
 
 use crate::{
     mir_transform::{BorrowData, BorrowMap, rich_locations_to_ranges},
@@ -27,13 +29,20 @@ pub fn get_accurate_live(
     )
 }
 
-/// returns (shared, mutable)
+/// Borrowed local and field path, see
+/// [`crate::mir_transform::field_projection`]
+pub type BorrowedPlace = (Local, Vec<u32>);
+
+/// returns (shared, mutable), per borrowed place
 pub fn get_borrow_live(
     datafrog: &PoloniusOutput,
     location_table: &PoloniusLocationTable,
     borrow_map: &BorrowMap,
     basic_blocks: &[MirBasicBlock],
-) -> (HashMap<Local, Vec<Range>>, HashMap<Local, Vec<Range>>) {
+) -> (
+    HashMap<BorrowedPlace, Vec<Range>>,
+    HashMap<BorrowedPlace, Vec<Range>>,
+) {
     let output = datafrog;
     let mut shared_borrows = HashMap::new();
     let mut mutable_borrows = HashMap::new();
@@ -41,15 +50,29 @@ pub fn get_borrow_live(
         let location = location_table.to_rich_location(*location_idx);
         for borrow_idx in borrow_idc {
             match borrow_map.get_from_borrow_index(*borrow_idx) {
-                Some((_, BorrowData::Shared { borrowed, .. })) => {
+                Some((
+                    _,
+                    BorrowData::Shared {
+                        borrowed,
+                        projection,
+                        ..
+                    },
+                )) => {
                     shared_borrows
-                        .entry(*borrowed)
+                        .entry((*borrowed, projection.clone()))
                         .or_insert_with(Vec::new)
                         .push(location);
                 }
-                Some((_, BorrowData::Mutable { borrowed, .. })) => {
+                Some((
+                    _,
+                    BorrowData::Mutable {
+                        borrowed,
+                        projection,
+                        ..
+                    },
+                )) => {
                     mutable_borrows
-                        .entry(*borrowed)
+                        .entry((*borrowed, projection.clone()))
                         .or_insert_with(Vec::new)
                         .push(location);
                 }
@@ -60,9 +83,9 @@ pub fn get_borrow_live(
     (
         shared_borrows
             .into_par_iter()
-            .map(|(local, locations)| {
+            .map(|(place, locations)| {
                 (
-                    local,
+                    place,
                     range_ops::eliminated_ranges(rich_locations_to_ranges(
                         basic_blocks,
                         &locations,
@@ -72,9 +95,9 @@ pub fn get_borrow_live(
             .collect(),
         mutable_borrows
             .into_par_iter()
-            .map(|(local, locations)| {
+            .map(|(place, locations)| {
                 (
-                    local,
+                    place,
                     range_ops::eliminated_ranges(rich_locations_to_ranges(
                         basic_blocks,
                         &locations,
@@ -85,12 +108,150 @@ pub fn get_borrow_live(
     )
 }
 
+/// Fill the loans live at each point, and the loans of the origins there,
+/// which location insensitive algorithms leave empty.
+///
+/// A loan is live where it flows from where it is issued, along the control
+/// flow until it is killed, while an origin containing it anywhere is live.
+/// This over-approximates the facts of the location sensitive algorithms, as
+/// the subsets of the origins hold at all points.
+pub fn fill_location_insensitive(datafrog: &mut PoloniusOutput, input: &PoloniusInput) {
+    if !datafrog.origin_contains_loan_at.is_empty() {
+        return;
+    }
+    let mut origins_of_loan: HashMap<_, Vec<_>> = HashMap::new();
+    for (origin, loans) in &datafrog.origin_contains_loan_anywhere {
+        for loan in loans {
+            origins_of_loan.entry(*loan).or_default().push(*origin);
+        }
+    }
+    let mut successors: HashMap<_, Vec<_>> = HashMap::new();
+    for (from, to) in &input.cfg_edge {
+        successors.entry(*from).or_default().push(*to);
+    }
+    let killed: HashSet<_> = input.loan_killed_at.iter().copied().collect();
+
+    for (_, loan, issued) in &input.loan_issued_at {
+        let Some(origins) = origins_of_loan.get(loan) else {
+            continue;
+        };
+        let mut visited = HashSet::from([*issued]);
+        let mut pending = vec![*issued];
+        while let Some(point) = pending.pop() {
+            let live: Vec<_> = datafrog
+                .origin_live_on_entry
+                .get(&point)
+                .into_iter()
+                .flatten()
+                .filter(|origin| origins.contains(origin))
+                .copied()
+                .collect();
+            for origin in &live {
+                datafrog
+                    .origin_contains_loan_at
+                    .entry(point)
+                    .or_default()
+                    .entry(*origin)
+                    .or_default()
+                    .insert(*loan);
+            }
+            if !live.is_empty() {
+                datafrog.loan_live_at.entry(point).or_default().push(*loan);
+            }
+            // the issuing origin holds the loan at its issue, even if dead
+            if (live.is_empty() && point != *issued) || killed.contains(&(*loan, point)) {
+                continue;
+            }
+            for next in successors.get(&point).into_iter().flatten() {
+                if visited.insert(*next) {
+                    pending.push(*next);
+                }
+            }
+        }
+    }
+}
+
+/// Merge borrow ranges of all places of each local.
+#[must_use]
+pub fn borrow_live_by_local(
+    borrow_live: &HashMap<BorrowedPlace, Vec<Range>>,
+) -> HashMap<Local, Vec<Range>> {
+    let mut by_local: HashMap<Local, Vec<Range>> = HashMap::new();
+    for ((local, _), ranges) in borrow_live {
+        by_local.entry(*local).or_default().extend(ranges);
+    }
+    by_local
+        .into_iter()
+        .map(|(local, ranges)| (local, range_ops::eliminated_ranges(ranges)))
+        .collect()
+}
+
+/// Ranges where a shared and a mutable borrow of overlapping places of the
+/// same local are live at once.
+///
+/// Places overlap when the field path of one is a prefix of the other, so
+/// borrows of disjoint fields do not conflict.
+#[must_use]
+pub fn shared_mut_live(
+    shared_live: &HashMap<BorrowedPlace, Vec<Range>>,
+    mutable_live: &HashMap<BorrowedPlace, Vec<Range>>,
+) -> HashMap<Local, Vec<Range>> {
+    let mut shared_mut: HashMap<Local, Vec<Range>> = HashMap::new();
+    for ((shared_local, shared_path), shared) in shared_live {
+        for ((mutable_local, mutable_path), mutable) in mutable_live {
+            if shared_local != mutable_local
+                || !(shared_path.starts_with(mutable_path) || mutable_path.starts_with(shared_path))
+            {
+                continue;
+            }
+            let mut ranges = shared.clone();
+            ranges.extend_from_slice(mutable);
+            shared_mut
+                .entry(*shared_local)
+                .or_default()
+                .extend(range_ops::common_ranges(&ranges));
+        }
+    }
+    shared_mut
+        .into_iter()
+        .map(|(local, ranges)| (local, range_ops::eliminated_ranges(ranges)))
+        .collect()
+}
+
+/// Ranges of the `writes` whose value is not live right after the write,
+/// i.e. is overwritten or dropped without being read.
+#[must_use]
+pub fn dead_writes(
+    datafrog: &PoloniusOutput,
+    location_table: &PoloniusLocationTable,
+    writes: &[(Local, Location, Range)],
+) -> HashMap<Local, Vec<Range>> {
+    let mut dead = HashMap::new();
+    for (local, location, range) in writes {
+        let after = location_table.start_index(location.successor_within_block());
+        let live = datafrog
+            .var_live_on_entry
+            .get(&after)
+            .is_some_and(|locals| locals.contains(local));
+        if !live {
+            dead.entry(*local).or_insert_with(Vec::new).push(*range);
+        }
+    }
+    dead
+}
+
+/// Ranges each local must live in because of the borrows it holds or lends.
+///
+/// returns (all, required by the signature), where the latter are the ranges
+/// due to borrows flowing into a `universal_regions` of the function
+/// signature, such as the lifetime of a returned reference
 pub fn get_must_live(
     datafrog: &PoloniusOutput,
     location_table: &PoloniusLocationTable,
     borrow_map: &BorrowMap,
     basic_blocks: &[MirBasicBlock],
-) -> HashMap<Local, Vec<Range>> {
+    universal_regions: &[PoloniusRegionVid],
+) -> (HashMap<Local, Vec<Range>>, HashMap<Local, Vec<Range>>) {
     // obtain a map that region -> region contained locations
     let mut region_locations = HashMap::new();
     for (location_idx, region_idc) in &datafrog.origin_live_on_entry {
@@ -121,18 +282,24 @@ pub fn get_must_live(
     }
 
     // check all regions' subset that must be satisfied
+    // location insensitive algorithms only compute the subsets anywhere
     let mut subsets = HashMap::new();
-    for subset in datafrog.subset.values() {
-        for (sup, subs) in subset {
-            subsets
-                .entry(*sup)
-                .or_insert_with(HashSet::new)
-                .extend(subs.iter().copied());
-        }
+    for (sup, subs) in datafrog
+        .subset
+        .values()
+        .flatten()
+        .chain(&datafrog.subset_anywhere)
+    {
+        subsets
+            .entry(*sup)
+            .or_insert_with(HashSet::new)
+            .extend(subs.iter().copied());
     }
     // obtain a map that region -> locations
     // a region must contains the locations
+    // and the same for the locations due to universal regions only
     let mut region_must_locations = HashMap::new();
+    let mut region_signature_locations = HashMap::new();
     for (sup, subs) in &subsets {
         for sub in subs {
             if let Some(locs) = region_locations.get(sub) {
@@ -140,6 +307,12 @@ pub fn get_must_live(
                     .entry(*sup)
                     .or_insert_with(HashSet::new)
                     .extend(locs.iter().copied());
+                if universal_regions.contains(sub) {
+                    region_signature_locations
+                        .entry(*sup)
+                        .or_insert_with(HashSet::new)
+                        .extend(locs.iter().copied());
+                }
             }
         }
     }
@@ -147,45 +320,98 @@ pub fn get_must_live(
     // a local must lives in the locations
     // This includes BOTH the local holding the reference AND the local being
     // borrowed
-    let mut local_must_locations = HashMap::new();
-    for region_borrows in datafrog.origin_contains_loan_at.values() {
-        for (region, borrows) in region_borrows {
-            for borrow in borrows {
-                if let Some(locs) = region_must_locations.get(region) {
-                    // Track must-live for the local that holds the reference
-                    if let Some(assigned_local) = borrow_assigned_local.get(borrow) {
-                        local_must_locations
-                            .entry(*assigned_local)
-                            .or_insert_with(HashSet::new)
-                            .extend(locs.iter().copied());
-                    }
-                    // Also track must-live for the borrowed local (the source of the borrow)
-                    if let Some(borrowed_local) = borrow_borrowed_local.get(borrow) {
-                        local_must_locations
-                            .entry(*borrowed_local)
-                            .or_insert_with(HashSet::new)
-                            .extend(locs.iter().copied());
+    let local_must_locations = |region_must_locations: &HashMap<_, HashSet<_>>| {
+        let mut local_must_locations = HashMap::new();
+        for region_borrows in datafrog.origin_contains_loan_at.values() {
+            for (region, borrows) in region_borrows {
+                for borrow in borrows {
+                    if let Some(locs) = region_must_locations.get(region) {
+                        // Track must-live for the local that holds the reference
+                        if let Some(assigned_local) = borrow_assigned_local.get(borrow) {
+                            local_must_locations
+                                .entry(*assigned_local)
+                                .or_insert_with(HashSet::new)
+                                .extend(locs.iter().copied());
+                        }
+                        // Also track must-live for the borrowed local (the source of the borrow)
+                        if let Some(borrowed_local) = borrow_borrowed_local.get(borrow) {
+                            local_must_locations
+                                .entry(*borrowed_local)
+                                .or_insert_with(HashSet::new)
+                                .extend(locs.iter().copied());
+                        }
                     }
                 }
             }
         }
-    }
+        local_must_locations
+            .iter()
+            .map(|(local, locations)| {
+                (
+                    *local,
+                    range_ops::eliminated_ranges(rich_locations_to_ranges(
+                        basic_blocks,
+                        &locations
+                            .iter()
+                            .map(|v| location_table.to_rich_location(*v))
+                            .collect::<Vec<_>>(),
+                    )),
+                )
+            })
+            .collect()
+    };
 
-    local_must_locations
-        .iter()
-        .map(|(local, locations)| {
-            (
-                *local,
-                range_ops::eliminated_ranges(rich_locations_to_ranges(
-                    basic_blocks,
-                    &locations
-                        .iter()
-                        .map(|v| location_table.to_rich_location(*v))
-                        .collect::<Vec<_>>(),
-                )),
-            )
+    (
+        local_must_locations(&region_must_locations),
+        local_must_locations(&region_signature_locations),
+    )
+}
+
+/// Locals borrowed by the loans each call returns, by the block of the call.
+///
+/// `calls` are the block, return location and destination of each call, and
+/// `var_origins` the origins in the type of each local. The loans in the
+/// origins of the destination, where the call returns to, are those flowing
+/// from the arguments into the returned value.
+#[must_use]
+pub fn call_borrows(
+    datafrog: &PoloniusOutput,
+    location_table: &PoloniusLocationTable,
+    borrow_map: &BorrowMap,
+    var_origins: &[(Local, PoloniusRegionVid)],
+    calls: &[(BasicBlock, Location, Local)],
+) -> HashMap<BasicBlock, Vec<Local>> {
+    let borrowed: HashMap<_, _> = borrow_map
+        .iter_with_index()
+        .map(|(borrow_idx, (_location, borrow_data))| match borrow_data {
+            BorrowData::Shared { borrowed, .. } | BorrowData::Mutable { borrowed, .. } => {
+                (borrow_idx, *borrowed)
+            }
         })
-        .collect()
+        .collect();
+    let mut borrows = HashMap::new();
+    for (block, returned, destination) in calls {
+        let Some(loans) = datafrog
+            .origin_contains_loan_at
+            .get(&location_table.start_index(*returned))
+        else {
+            continue;
+        };
+        let mut locals: Vec<_> = var_origins
+            .iter()
+            .filter(|(local, _)| local == destination)
+            .filter_map(|(_, origin)| loans.get(origin))
+            .flatten()
+            .filter_map(|loan| borrowed.get(loan).copied())
+            .filter(|local| local != destination)
+            .collect();
+        locals.sort_unstable();
+        locals.dedup();
+        if !locals.is_empty() {
+            borrows.insert(*block, locals);
+        }
+    }
+    borrows
 }
 
 /// obtain map from local id to living range
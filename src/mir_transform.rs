@@ -1,20 +1,30 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::LazyLock,
+};
 
 use rayon::prelude::*;
 use rustc_borrowck::consumers::{BorrowIndex, BorrowSet, RichLocation};
-use rustc_hir::def_id::LocalDefId;
+use rustc_hir::def_id::{LOCAL_CRATE, LocalDefId};
 use rustc_middle::{
     mir::{
-        BasicBlocks, Body, BorrowKind, Local, Location, Operand, Rvalue, Statement, StatementKind,
-        Terminator, TerminatorKind, VarDebugInfoContents,
+        AggregateKind, BasicBlock, BasicBlocks, Body, BorrowKind, CallSource, Local, Location,
+        Operand, Place, ProjectionElem, Rvalue, Statement, StatementKind, Terminator,
+        TerminatorKind, VarDebugInfoContents,
     },
-    ty::{TyCtxt, TypeFoldable, TypeFolder},
+    ty::{CAPTURE_STRUCT_LOCAL, TyCtxt, TypeFoldable, TypeFolder},
 };
-use rustc_span::source_map::SourceMap;
+use rustc_span::{ExpnKind, Span, source_map::SourceMap};
 
 use crate::{
-    mir_analysis::{range_from_span, sort_locs},
-    models::{FnLocal, MirBasicBlock, MirRval, MirStatement, MirTerminator, Range},
+    mir_analysis::{is_clone_callee, macro_range_from_span, range_from_span, sort_locs},
+    mir_cache,
+    models::{
+        CallReceiver, FnLocal, MirBasicBlock, MirRval, MirStatement, MirTerminator, Range,
+        ReceiverKind,
+    },
+    text_conversion::SourceIndex,
 };
 
 /// `RegionEraser` to erase region variables from MIR body
@@ -34,6 +44,15 @@ impl<'tcx> TypeFolder<TyCtxt<'tcx>> for RegionEraser<'tcx> {
     }
 }
 
+/// See [`crate::models::Function::fn_key`]
+#[must_use]
+pub fn fn_key(tcx: TyCtxt<'_>, fn_id: LocalDefId) -> u64 {
+    mir_cache::fn_key(
+        tcx.crate_name(LOCAL_CRATE).as_str(),
+        &tcx.def_path(fn_id.to_def_id()).to_string_no_crate_verbose(),
+    )
+}
+
 /// Erase region variables in MIR body
 /// Refer: [`RegionEraser`]
 pub fn erase_region_variables<'tcx>(tcx: TyCtxt<'tcx>, body: Body<'tcx>) -> Body<'tcx> {
@@ -43,47 +62,171 @@ pub fn erase_region_variables<'tcx>(tcx: TyCtxt<'tcx>, body: Body<'tcx>) -> Body
 }
 
 /// collect user defined variables from debug info in MIR
-pub fn collect_user_vars(
-    source: &str,
-    offset: u32,
-    body: &Body<'_>,
-) -> HashMap<Local, (Range, String)> {
+///
+/// Their spans are those of the bindings, so that each binding of a tuple or
+/// struct pattern is selected on its own name.
+///
+/// Entries pointing into a projection are skipped: in closure bodies these are
+/// the captured upvars, which would otherwise be attributed to the environment
+/// local `_1` at the span of the captured variable's declaration.
+pub fn collect_user_vars(source: &SourceIndex, body: &Body<'_>) -> HashMap<Local, (Range, String)> {
     body.var_debug_info
         // this cannot be par_iter since body cannot send
         .iter()
         .filter_map(|debug| match &debug.value {
-            VarDebugInfoContents::Place(place) => {
-                range_from_span(source, debug.source_info.span, offset)
+            // locals of macros, like the `args` of `format!`, are not
+            // written by the user
+            _ if matches!(
+                debug.source_info.span.ctxt().outer_expn_data().kind,
+                ExpnKind::Macro(..)
+            ) =>
+            {
+                None
+            }
+            VarDebugInfoContents::Place(place) if place.projection.is_empty() => {
+                range_from_span(source, debug.source_info.span)
                     .map(|range| (place.local, (range, debug.name.as_str().to_owned())))
             }
-            VarDebugInfoContents::Const(_) => None,
+            VarDebugInfoContents::Place(_) | VarDebugInfoContents::Const(_) => None,
         })
         .collect()
 }
 
+/// Lexical scope of each user variable, from its declaration to the end of
+/// the block declaring it.
+#[must_use]
+pub fn collect_user_var_scopes(
+    source: &SourceIndex,
+    body: &Body<'_>,
+    user_vars: &HashMap<Local, (Range, String)>,
+) -> HashMap<Local, Range> {
+    body.var_debug_info
+        .iter()
+        .filter_map(|debug| {
+            let VarDebugInfoContents::Place(place) = &debug.value else {
+                return None;
+            };
+            let (decl, _) = user_vars.get(&place.local)?;
+            let scope = body.source_scopes[debug.source_info.scope].span;
+            let scope = range_from_span(source, scope)?;
+            let range = Range::new(scope.from().max(decl.from()), scope.until())?;
+            Some((place.local, range))
+        })
+        .collect()
+}
+
+/// Assignments overwriting a whole user variable, with their location and
+/// range.
+///
+/// `let` bindings are followed by a `FakeRead` of the variable, which is not
+/// a real use, so the location of such a write is moved past its fake reads.
+#[must_use]
+pub fn collect_user_var_writes(
+    source: &SourceIndex,
+    body: &Body<'_>,
+    user_vars: &HashMap<Local, (Range, String)>,
+) -> Vec<(Local, Location, Range)> {
+    body.basic_blocks
+        .iter_enumerated()
+        .flat_map(|(block, data)| {
+            data.statements
+                .iter()
+                .enumerate()
+                .filter_map(move |(statement_index, statement)| {
+                    let StatementKind::Assign(assign) = &statement.kind else {
+                        return None;
+                    };
+                    let place = assign.0;
+                    if !place.projection.is_empty() || !user_vars.contains_key(&place.local) {
+                        return None;
+                    }
+                    let fake_reads = data.statements[statement_index + 1..]
+                        .iter()
+                        .take_while(|next| {
+                            matches!(&next.kind, StatementKind::FakeRead(read) if read.1.local == place.local)
+                        })
+                        .count();
+                    let location = Location {
+                        block,
+                        statement_index: statement_index + fake_reads,
+                    };
+                    range_from_span(source, statement.source_info.span)
+                        .map(|range| (place.local, location, range))
+                })
+        })
+        .collect()
+}
+
+/// Local id standing in for the upvar stored in field `field` of a closure or
+/// coroutine environment.
+///
+/// Upvars are not locals of the closure body but fields of its environment
+/// (`_1`), so they are numbered downwards from `u32::MAX` to stay clear of
+/// real locals.
+#[must_use]
+pub const fn upvar_local(field: u32) -> u32 {
+    u32::MAX - field
+}
+
+/// Local id of the root of `place`, resolving accesses to captured upvars in
+/// closure-like bodies to their [`upvar_local`].
+fn place_local(place: &Place<'_>, closure_like: bool) -> u32 {
+    if closure_like && place.local == CAPTURE_STRUCT_LOCAL {
+        let field = place
+            .projection
+            .iter()
+            .find(|elem| !matches!(elem, ProjectionElem::Deref));
+        if let Some(ProjectionElem::Field(field, _)) = field {
+            return upvar_local(field.as_u32());
+        }
+    }
+    place.local.as_u32()
+}
+
+/// Local of `dest` if moving `moved` into it moves a part of a local into a
+/// whole one, as pattern bindings do with the matched value.
+fn partial_move_destination(
+    moved: &Place<'_>,
+    dest: &Place<'_>,
+    fn_key: u64,
+    closure_like: bool,
+) -> Option<FnLocal> {
+    let upvar = closure_like && moved.local == CAPTURE_STRUCT_LOCAL;
+    (!upvar && !moved.projection.is_empty() && dest.projection.is_empty())
+        .then(|| FnLocal::new(dest.local.as_u32(), fn_key))
+}
+
 fn convert_rvalue(
-    fn_id: LocalDefId,
-    source: &str,
-    offset: u32,
+    fn_key: u64,
+    source: &SourceIndex,
     span: rustc_span::Span,
-    rval: &Rvalue<'_>,
+    (dest, rval): (&Place<'_>, &Rvalue<'_>),
+    closure_like: bool,
 ) -> Option<MirRval> {
     match rval {
         Rvalue::Use(Operand::Move(p)) => {
-            let local = p.local;
-            range_from_span(source, span, offset).map(|range| MirRval::Move {
-                target_local: FnLocal::new(local.as_u32(), fn_id.local_def_index.as_u32()),
+            let local = place_local(p, closure_like);
+            macro_range_from_span(source, span).map(|(range, from_macro)| MirRval::Move {
+                target_local: FnLocal::new(local, fn_key),
                 range,
+                moved_into: partial_move_destination(p, dest, fn_key, closure_like),
+                from_macro,
             })
         }
         Rvalue::Ref(_region, kind, place) => {
             let mutable = matches!(kind, BorrowKind::Mut { .. });
-            let local = place.local;
-            range_from_span(source, span, offset).map(|range| MirRval::Borrow {
-                target_local: FnLocal::new(local.as_u32(), fn_id.local_def_index.as_u32()),
+            let local = place_local(place, closure_like);
+            // upvars are reached through the closure environment, so their
+            // borrows count as borrows of the captured variable
+            let through_deref = !(closure_like && place.local == CAPTURE_STRUCT_LOCAL)
+                && place.projection.contains(&ProjectionElem::Deref);
+            macro_range_from_span(source, span).map(|(range, from_macro)| MirRval::Borrow {
+                target_local: FnLocal::new(local, fn_key),
                 range,
                 mutable,
                 outlive: None,
+                through_deref,
+                from_macro,
             })
         }
         _ => None,
@@ -91,91 +234,403 @@ fn convert_rvalue(
 }
 
 fn convert_statement(
-    fn_id: LocalDefId,
-    source: &str,
-    offset: u32,
+    fn_key: u64,
+    source: &SourceIndex,
     statement: &Statement<'_>,
+    closure_like: bool,
 ) -> Option<MirStatement> {
     let span = statement.source_info.span;
     match &statement.kind {
         StatementKind::Assign(v) => {
             let (place, rval) = &**v;
-            let target_local_index = place.local.as_u32();
-            let rv = convert_rvalue(fn_id, source, offset, span, rval);
-            range_from_span(source, span, offset).map(|range| MirStatement::Assign {
-                target_local: FnLocal::new(target_local_index, fn_id.local_def_index.as_u32()),
+            let target_local = FnLocal::new(place.local.as_u32(), fn_key);
+            let rv = convert_rvalue(fn_key, source, span, (place, rval), closure_like);
+            let through_ref = place
+                .projection
+                .contains(&ProjectionElem::Deref)
+                .then_some(target_local);
+            macro_range_from_span(source, span).map(|(range, from_macro)| MirStatement::Assign {
+                target_local,
                 range,
                 rval: rv,
+                through_ref,
+                from_macro,
             })
         }
-        _ => range_from_span(source, span, offset).map(|range| MirStatement::Other { range }),
+        _ => range_from_span(source, span).map(|range| MirStatement::Other { range }),
+    }
+}
+
+/// Origins of the values of locals, to find the variable a method call
+/// receiver was taken from.
+///
+/// Receivers are prepared in temporaries: moved or borrowed from the
+/// variable, possibly cast, and dereferenced by `Deref` calls for
+/// auto-deref.
+pub struct ReceiverOrigins {
+    /// Local a local's value is taken from, with how it is taken if that
+    /// decides the receiver kind
+    origins: HashMap<u32, (u32, Option<ReceiverKind>)>,
+    /// User variables, where tracing stops
+    user_vars: HashSet<u32>,
+}
+
+impl ReceiverOrigins {
+    #[must_use]
+    pub fn new<'tcx>(
+        tcx: TyCtxt<'tcx>,
+        body: &Body<'tcx>,
+        user_vars: &HashMap<Local, (Range, String)>,
+        closure_like: bool,
+    ) -> Self {
+        let lang_items = tcx.lang_items();
+        let deref_traits = [lang_items.deref_trait(), lang_items.deref_mut_trait()];
+        let mut origins = HashMap::new();
+        for data in body.basic_blocks.iter() {
+            for statement in &data.statements {
+                let StatementKind::Assign(v) = &statement.kind else {
+                    continue;
+                };
+                let (place, rval) = &**v;
+                if !place.projection.is_empty() {
+                    continue;
+                }
+                let origin = match rval {
+                    Rvalue::Use(Operand::Move(from)) => Some((from, Some(ReceiverKind::Move))),
+                    Rvalue::Ref(_, kind, from) => {
+                        let kind = if matches!(kind, BorrowKind::Mut { .. }) {
+                            ReceiverKind::RefMut
+                        } else {
+                            ReceiverKind::Ref
+                        };
+                        Some((from, Some(kind)))
+                    }
+                    Rvalue::Cast(_, Operand::Move(from) | Operand::Copy(from), _) => {
+                        Some((from, None))
+                    }
+                    _ => None,
+                };
+                if let Some((from, kind)) = origin {
+                    origins.insert(
+                        place.local.as_u32(),
+                        (place_local(from, closure_like), kind),
+                    );
+                }
+            }
+            if let Some(Terminator {
+                kind:
+                    TerminatorKind::Call {
+                        func,
+                        args,
+                        destination,
+                        ..
+                    },
+                ..
+            }) = &data.terminator
+                && destination.projection.is_empty()
+                && let Some((def_id, _)) = func.const_fn_def()
+                && deref_traits.contains(&tcx.trait_of_item(def_id))
+                && let Some(Operand::Move(from) | Operand::Copy(from)) =
+                    args.first().map(|arg| &arg.node)
+            {
+                origins.insert(
+                    destination.local.as_u32(),
+                    (place_local(from, closure_like), None),
+                );
+            }
+        }
+        Self {
+            origins,
+            user_vars: user_vars.keys().map(|local| local.as_u32()).collect(),
+        }
+    }
+
+    /// Local the receiver `operand` is taken from, and how.
+    ///
+    /// Copied receivers are neither moved nor borrowed and yield `None`.
+    fn receiver(&self, operand: &Operand<'_>, closure_like: bool) -> Option<(u32, ReceiverKind)> {
+        let Operand::Move(place) = operand else {
+            return None;
+        };
+        let mut local = place_local(place, closure_like);
+        let mut kind = None;
+        let mut visited = HashSet::new();
+        while !self.user_vars.contains(&local)
+            && visited.insert(local)
+            && let Some(&(from, how)) = self.origins.get(&local)
+        {
+            kind = kind.or(how);
+            local = from;
+        }
+        Some((local, kind.unwrap_or(ReceiverKind::Move)))
+    }
+}
+
+/// Span of the callee name within the call expression `fn_span`, or
+/// `fn_span` when the callee has no span of its own in there, as for calls of
+/// function pointers.
+fn callee_span(func: &Operand<'_>, fn_span: Span) -> Span {
+    let Operand::Constant(constant) = func else {
+        return fn_span;
+    };
+    let lo = constant.span.lo().max(fn_span.lo());
+    let hi = constant.span.hi().min(fn_span.hi());
+    if constant.span.eq_ctxt(fn_span) && lo < hi {
+        fn_span.with_lo(lo).with_hi(hi)
+    } else {
+        fn_span
     }
 }
 
 fn convert_terminator(
-    fn_id: LocalDefId,
-    source: &str,
-    offset: u32,
+    tcx: TyCtxt<'_>,
+    fn_key: u64,
+    source: &SourceIndex,
     terminator: &Terminator<'_>,
+    origins: &ReceiverOrigins,
+    closure_like: bool,
 ) -> Option<MirTerminator> {
     match &terminator.kind {
         TerminatorKind::Drop { place, .. } => {
-            range_from_span(source, terminator.source_info.span, offset).map(|range| {
+            macro_range_from_span(source, terminator.source_info.span).map(|(range, from_macro)| {
                 MirTerminator::Drop {
-                    local: FnLocal::new(place.local.as_u32(), fn_id.local_def_index.as_u32()),
+                    local: FnLocal::new(place.local.as_u32(), fn_key),
                     range,
+                    from_macro,
                 }
             })
         }
         TerminatorKind::Call {
+            func,
             destination,
             fn_span,
+            args,
             ..
-        } => range_from_span(source, *fn_span, offset).map(|fn_span| MirTerminator::Call {
-            destination_local: FnLocal::new(
-                destination.local.as_u32(),
-                fn_id.local_def_index.as_u32(),
-            ),
-            fn_span,
-        }),
-        _ => range_from_span(source, terminator.source_info.span, offset)
+        } => {
+            // the receiver of a method call comes before the method name
+            let receiver = args
+                .first()
+                .filter(|arg| arg.span.hi() <= fn_span.lo())
+                .and_then(|arg| {
+                    let (local, kind) = origins.receiver(&arg.node, closure_like)?;
+                    Some(CallReceiver {
+                        local: FnLocal::new(local, fn_key),
+                        range: range_from_span(source, arg.span)?,
+                        kind,
+                    })
+                });
+            // calls expanded from macros like `vec!` are shown on the macro call
+            let fn_span = if fn_span.from_expansion() {
+                fn_span.source_callsite()
+            } else {
+                *fn_span
+            };
+            let call_expr_span = range_from_span(source, fn_span)?;
+            Some(MirTerminator::Call {
+                destination_local: FnLocal::new(destination.local.as_u32(), fn_key),
+                fn_span: range_from_span(source, callee_span(func, fn_span))
+                    .unwrap_or(call_expr_span),
+                callee: func
+                    .const_fn_def()
+                    .map(|(def_id, _)| tcx.def_path_str(def_id)),
+                call_expr_span,
+                range: range_from_span(source, terminator.source_info.span),
+                receiver,
+                // filled in after the borrow check
+                borrows_from: Vec::new(),
+            })
+        }
+        _ => range_from_span(source, terminator.source_info.span)
             .map(|range| MirTerminator::Other { range }),
     }
 }
 
 /// Collect and transform [`BasicBlocks`] into our data structure
 /// [`MirBasicBlock`]s.
+///
+/// `closure_like` tells whether the body belongs to a closure or coroutine,
+/// whose upvar accesses are then attributed to [`upvar_local`]s.
 pub fn collect_basic_blocks(
-    fn_id: LocalDefId,
-    source: &str,
-    offset: u32,
+    tcx: TyCtxt<'_>,
+    fn_key: u64,
+    source: &SourceIndex,
     basic_blocks: &BasicBlocks<'_>,
     source_map: &SourceMap,
+    origins: &ReceiverOrigins,
+    closure_like: bool,
 ) -> Vec<MirBasicBlock> {
     basic_blocks
         .iter_enumerated()
         .map(|(_bb, bb_data)| {
-            let statements: Vec<_> = bb_data
+            // not par_iter: the macro expansions of spans are only known on
+            // the compiler's thread
+            let statements = bb_data
                 .statements
                 .iter()
                 .filter(|stmt| stmt.source_info.span.is_visible(source_map))
+                .filter_map(|statement| convert_statement(fn_key, source, statement, closure_like))
                 .collect();
-            let statements = statements
-                .par_iter()
-                .filter_map(|statement| convert_statement(fn_id, source, offset, statement))
-                .collect();
-            let terminator = bb_data
-                .terminator
-                .as_ref()
-                .and_then(|term| convert_terminator(fn_id, source, offset, term));
+            let terminator = bb_data.terminator.as_ref().and_then(|term| {
+                convert_terminator(tcx, fn_key, source, term, origins, closure_like)
+            });
             MirBasicBlock {
                 statements,
                 terminator,
+                is_cleanup: bb_data.is_cleanup,
             }
         })
         .collect()
 }
 
+/// Ranges of the points where a coroutine body is suspended, which for
+/// `async` bodies are their `.await`s.
+#[must_use]
+pub fn collect_await_points(source: &SourceIndex, basic_blocks: &BasicBlocks<'_>) -> Vec<Range> {
+    basic_blocks
+        .iter()
+        .filter_map(|data| {
+            let terminator = data.terminator.as_ref()?;
+            matches!(terminator.kind, TerminatorKind::Yield { .. })
+                .then(|| range_from_span(source, terminator.source_info.span))
+                .flatten()
+        })
+        .collect()
+}
+
+/// Calls written in `body` that return, as their block, the location they
+/// return to, and their destination. Calls inserted by the compiler, like
+/// the `deref` of deref coercions, and calls of macro expansions are left
+/// out.
+#[must_use]
+pub fn collect_calls(body: &Body<'_>) -> Vec<(BasicBlock, Location, Local)> {
+    body.basic_blocks
+        .iter_enumerated()
+        .filter_map(|(bb, data)| {
+            let terminator = data.terminator.as_ref()?;
+            let TerminatorKind::Call {
+                destination,
+                target: Some(target),
+                call_source: CallSource::Normal,
+                ..
+            } = &terminator.kind
+            else {
+                return None;
+            };
+            if terminator.source_info.span.from_expansion() {
+                return None;
+            }
+            Some((bb, target.start_location(), destination.local))
+        })
+        .collect()
+}
+
+/// Calls of `clone` in `body`, recognized by the text of the callee, as
+/// pairs of the local the receiver is taken from and the destination.
+#[must_use]
+pub fn collect_clone_calls(
+    tcx: TyCtxt<'_>,
+    body: &Body<'_>,
+    origins: &ReceiverOrigins,
+    closure_like: bool,
+) -> Vec<(Local, Local)> {
+    let source_map = tcx.sess.source_map();
+    body.basic_blocks
+        .iter()
+        .filter_map(|data| {
+            let TerminatorKind::Call {
+                func,
+                destination,
+                fn_span,
+                args,
+                ..
+            } = &data.terminator.as_ref()?.kind
+            else {
+                return None;
+            };
+            let callee = source_map
+                .span_to_snippet(callee_span(func, *fn_span))
+                .ok()?;
+            if !is_clone_callee(&callee) {
+                return None;
+            }
+            let (receiver, _) = origins.receiver(&args.first()?.node, closure_like)?;
+            Some((Local::from_u32(receiver), destination.local))
+        })
+        .collect()
+}
+
+/// Collect the captures of closures and coroutines created in `body`.
+///
+/// Each capture is returned as a pair of the upvar in the closure body and the
+/// local of `body` it captures. Captures by reference are traced back through
+/// the temporary holding the reference to the borrowed local.
+#[must_use]
+pub fn collect_captures(
+    tcx: TyCtxt<'_>,
+    fn_key: u64,
+    body: &Body<'_>,
+    closure_like: bool,
+) -> Vec<(FnLocal, FnLocal)> {
+    let assignments = || {
+        body.basic_blocks
+            .iter()
+            .flat_map(|bb| &bb.statements)
+            .filter_map(|statement| match &statement.kind {
+                StatementKind::Assign(v) => Some(&**v),
+                _ => None,
+            })
+    };
+    let references: HashMap<Local, &Place<'_>> = assignments()
+        .filter_map(|(place, rval)| match rval {
+            Rvalue::Ref(_, _, borrowed) if place.projection.is_empty() => {
+                Some((place.local, borrowed))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut captures = Vec::new();
+    for (_, rval) in assignments() {
+        let Rvalue::Aggregate(kind, operands) = rval else {
+            continue;
+        };
+        let (AggregateKind::Closure(closure_id, _)
+        | AggregateKind::Coroutine(closure_id, _)
+        | AggregateKind::CoroutineClosure(closure_id, _)) = **kind
+        else {
+            continue;
+        };
+        let Some(closure_id) = closure_id.as_local() else {
+            continue;
+        };
+        for (field, operand) in operands.iter_enumerated() {
+            let (Operand::Move(place) | Operand::Copy(place)) = operand else {
+                continue;
+            };
+            let root = if place.projection.is_empty()
+                && let Some(borrowed) = references.get(&place.local)
+            {
+                borrowed
+            } else {
+                place
+            };
+            captures.push((
+                FnLocal::new(upvar_local(field.as_u32()), self::fn_key(tcx, closure_id)),
+                FnLocal::new(place_local(root, closure_like), fn_key),
+            ));
+        }
+    }
+    captures
+}
+
+/// Set to `1` to keep the locations of cleanup blocks in live ranges, for
+/// debugging
+pub const INCLUDE_CLEANUP_ENV: &str = "FERROUS_OWL_INCLUDE_CLEANUP";
+
+/// Whether [`INCLUDE_CLEANUP_ENV`] keeps cleanup blocks in live ranges.
+static INCLUDE_CLEANUP: LazyLock<bool> =
+    LazyLock::new(|| env::var(INCLUDE_CLEANUP_ENV).is_ok_and(|value| value.trim() == "1"));
+
 fn statement_location_to_range(
     basic_blocks: &[MirBasicBlock],
     basic_block: usize,
@@ -190,6 +645,11 @@ fn statement_location_to_range(
     })
 }
 
+/// Ranges of the statements at `locations`.
+///
+/// Locations in cleanup blocks are left out unless [`INCLUDE_CLEANUP_ENV`]
+/// is set: their drops run only while unwinding, and their spans, like the
+/// end of a scope, would extend ranges past the call that panicked.
 #[must_use]
 pub fn rich_locations_to_ranges(
     basic_blocks: &[MirBasicBlock],
@@ -197,7 +657,16 @@ pub fn rich_locations_to_ranges(
 ) -> Vec<Range> {
     let mut starts = Vec::new();
     let mut mids = Vec::new();
+    let cleanup = |block: BasicBlock| {
+        basic_blocks
+            .get(block.index())
+            .is_some_and(|bb| bb.is_cleanup)
+    };
     for rich in locations {
+        let (RichLocation::Start(l) | RichLocation::Mid(l)) = rich;
+        if !*INCLUDE_CLEANUP && cleanup(l.block) {
+            continue;
+        }
         match rich {
             RichLocation::Start(l) => {
                 starts.push((l.block, l.statement_index));
@@ -223,10 +692,38 @@ pub fn rich_locations_to_ranges(
         .collect()
 }
 
+/// Field indices leading to a borrowed place from its local.
+///
+/// Dereferences are skipped as they are implied by the types along the path.
+/// The path ends at the first other projection, such as an index, which is
+/// treated as borrowing the whole place before it.
+#[must_use]
+pub fn field_projection(place: Place<'_>) -> Vec<u32> {
+    place
+        .projection
+        .iter()
+        .filter(|elem| !matches!(elem, ProjectionElem::Deref))
+        .map_while(|elem| match elem {
+            ProjectionElem::Field(field, _) => Some(field.as_u32()),
+            _ => None,
+        })
+        .collect()
+}
+
 /// Our representation of [`rustc_borrowck::consumers::BorrowData`]
 pub enum BorrowData {
-    Shared { borrowed: Local, _assigned: Local },
-    Mutable { borrowed: Local, _assigned: Local },
+    Shared {
+        borrowed: Local,
+        /// See [`field_projection`]
+        projection: Vec<u32>,
+        _assigned: Local,
+    },
+    Mutable {
+        borrowed: Local,
+        /// See [`field_projection`]
+        projection: Vec<u32>,
+        assigned: Local,
+    },
 }
 
 /// A map type from [`BorrowIndex`] to [`BorrowData`]
@@ -244,11 +741,13 @@ impl BorrowMap {
             let data = if data.kind().mutability().is_mut() {
                 BorrowData::Mutable {
                     borrowed: data.borrowed_place().local,
-                    _assigned: data.assigned_place().local,
+                    projection: field_projection(data.borrowed_place()),
+                    assigned: data.assigned_place().local,
                 }
             } else {
                 BorrowData::Shared {
                     borrowed: data.borrowed_place().local,
+                    projection: field_projection(data.borrowed_place()),
                     _assigned: data.assigned_place().local,
                 }
             };
@@ -272,6 +771,23 @@ impl BorrowMap {
     pub const fn local_map(&self) -> &HashMap<Local, HashSet<BorrowIndex>> {
         &self.local_map
     }
+    /// Pairs of (reference, mutably borrowed local), see
+    /// [`crate::models::Function::borrow_edges`]
+    #[must_use]
+    pub fn borrow_edges(&self, fn_key: u64) -> Vec<(FnLocal, FnLocal)> {
+        self.location_map
+            .iter()
+            .filter_map(|(_, data)| match data {
+                BorrowData::Mutable {
+                    borrowed, assigned, ..
+                } => Some((
+                    FnLocal::new(assigned.as_u32(), fn_key),
+                    FnLocal::new(borrowed.as_u32(), fn_key),
+                )),
+                BorrowData::Shared { .. } => None,
+            })
+            .collect()
+    }
     /// Iterate over borrows with their indices
     pub fn iter_with_index(&self) -> impl Iterator<Item = (BorrowIndex, &(Location, BorrowData))> {
         self.location_map
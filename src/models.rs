@@ -1,112 +1,143 @@
 use core::fmt::Display;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet, btree_map::Entry},
     fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
     ops::{Add, Sub},
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FnLocal {
     pub id: u32,
-    pub fn_id: u32,
+    /// [`Function::fn_key`] of the function the local belongs to
+    #[serde(with = "fn_key_string")]
+    pub fn_key: u64,
+}
+
+/// [`Function::fn_key`] written as a string, as JavaScript numbers cannot
+/// hold every `u64` exactly, and read from a string or a number.
+mod fn_key_string {
+    use super::{Deserialize, Deserializer, Serializer, de};
+
+    #[allow(
+        clippy::trivially_copy_pass_by_ref,
+        reason = "signature required by serde's `with` attribute"
+    )]
+    pub fn serialize<S: Serializer>(fn_key: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(fn_key)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum FnKey {
+            Number(u64),
+            String(String),
+        }
+        match FnKey::deserialize(deserializer)? {
+            FnKey::Number(fn_key) => Ok(fn_key),
+            FnKey::String(fn_key) => fn_key.parse().map_err(de::Error::custom),
+        }
+    }
 }
 
 impl FnLocal {
     #[must_use]
-    pub const fn new(id: u32, fn_id: u32) -> Self {
-        Self { id, fn_id }
+    pub const fn new(id: u32, fn_key: u64) -> Self {
+        Self { id, fn_key }
     }
 }
 
+/// Offset of a character in a source file with its CR characters stripped,
+/// see [`crate::SourceIndex`]
+///
+/// Offsets only move by a signed number of characters, so that lines, which
+/// are a [`LineCol`], cannot be added to them.
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 #[serde(transparent)]
-pub struct Loc(u32);
+pub struct CharOffset(u32);
 
-impl Loc {
-    #[must_use]
-    pub fn from_byte_pos(source: &str, byte_pos: u32, offset: u32) -> Self {
-        let byte_pos = byte_pos.saturating_sub(offset);
-        // it seems that the compiler is ignoring CR
-        let source_clean = source.replace('\r', "");
-
-        // Convert byte position to character position safely
-        if source_clean.len() < byte_pos as usize {
-            return Self::from(source_clean.chars().count());
-        }
-
-        // Find the character index corresponding to the byte position
-
-        source_clean
-            .char_indices()
-            .position(|(byte_idx, _)| (byte_pos as usize) <= byte_idx)
-            .map_or_else(
-                || {
-                    #[allow(
-                        clippy::cast_possible_truncation,
-                        reason = "count is bounded by string length"
-                    )]
-                    Self(source_clean.chars().count() as u32)
-                },
-                Self::from,
-            )
-    }
-}
+/// Former name of [`CharOffset`]
+#[deprecated(note = "use `CharOffset`, or `LineCol` for lines")]
+pub type Loc = CharOffset;
 
-impl Display for Loc {
+impl Display for CharOffset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
-impl Add<i32> for Loc {
+impl Add<i32> for CharOffset {
     type Output = Self;
     fn add(self, rhs: i32) -> Self::Output {
         Self(self.0.saturating_add_signed(rhs))
     }
 }
 
-impl Sub<i32> for Loc {
+impl Sub<i32> for CharOffset {
     type Output = Self;
     fn sub(self, rhs: i32) -> Self::Output {
         Self(self.0.saturating_add_signed(-rhs))
     }
 }
 
-impl From<u32> for Loc {
+impl From<u32> for CharOffset {
     fn from(value: u32) -> Self {
         Self(value)
     }
 }
 
-impl From<u64> for Loc {
+impl From<u64> for CharOffset {
     fn from(value: u64) -> Self {
         Self(u32::try_from(value).unwrap_or(u32::MAX))
     }
 }
 
-impl From<usize> for Loc {
+impl From<usize> for CharOffset {
     fn from(value: usize) -> Self {
         Self(u32::try_from(value).unwrap_or(u32::MAX))
     }
 }
 
-impl From<Loc> for u32 {
-    fn from(value: Loc) -> Self {
+impl From<CharOffset> for u32 {
+    fn from(value: CharOffset) -> Self {
         value.0
     }
 }
 
+/// Zero-based line and character of a position in a source file, the
+/// character counted as in [`CharOffset`]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct LineCol {
+    pub line: u32,
+    pub character: u32,
+}
+
+impl LineCol {
+    #[must_use]
+    pub const fn new(line: u32, character: u32) -> Self {
+        Self { line, character }
+    }
+}
+
+impl From<LineCol> for (u32, u32) {
+    fn from(value: LineCol) -> Self {
+        (value.line, value.character)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Range {
-    from: Loc,
-    until: Loc,
+    from: CharOffset,
+    until: CharOffset,
 }
 
 impl Range {
     #[must_use]
-    pub const fn new(from: Loc, until: Loc) -> Option<Self> {
+    pub const fn new(from: CharOffset, until: CharOffset) -> Option<Self> {
         if until.0 <= from.0 {
             None
         } else {
@@ -114,11 +145,11 @@ impl Range {
         }
     }
     #[must_use]
-    pub const fn from(self) -> Loc {
+    pub const fn from(self) -> CharOffset {
         self.from
     }
     #[must_use]
-    pub const fn until(self) -> Loc {
+    pub const fn until(self) -> CharOffset {
         self.until
     }
     #[must_use]
@@ -128,29 +159,284 @@ impl Range {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "ReadFile")]
 pub struct File {
-    pub items: Vec<Function>,
+    pub items: Vec<Item>,
+    /// Types of the declarations of the items, see [`DeclType`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub types: Vec<String>,
+    /// Hash of the text the items were analyzed from, see
+    /// [`crate::mir_cache::source_hash`]
+    #[serde(default)]
+    pub source_hash: String,
+    /// Whether the items come from an analysis of the file on its own, which
+    /// lacks the rest of its package, and give way to those of the package
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub provisional: bool,
+}
+
+/// [`File`] as read, with the types of former versions not interned yet
+#[derive(Deserialize)]
+struct ReadFile {
+    items: Vec<Item>,
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    source_hash: String,
+    #[serde(default)]
+    provisional: bool,
+}
+
+impl From<ReadFile> for File {
+    fn from(read: ReadFile) -> Self {
+        let mut file = Self {
+            items: read.items,
+            types: read.types,
+            source_hash: read.source_hash,
+            provisional: read.provisional,
+        };
+        if file
+            .items
+            .iter()
+            .flat_map(|item| &item.function().decls)
+            .any(|decl| matches!(decl.ty(), DeclType::Name(_)))
+        {
+            file.intern_types();
+        }
+        file
+    }
+}
+
+impl File {
+    /// Intern the types named by the declarations, dropping unused ones.
+    pub fn intern_types(&mut self) {
+        let mut interner = TypeInterner::default();
+        for item in &mut self.items {
+            item.function_mut()
+                .reintern_types(&self.types, &mut interner);
+        }
+        self.types = interner.into_types();
+    }
+
+    /// Merge the functions of `other`, keeping one function per `fn_key` and
+    /// [`Function::context`].
+    ///
+    /// The same file may be analyzed for several targets, e.g. a library and
+    /// its tests, where `cfg(test)` code may add locals. The versions of both
+    /// are kept, see [`Self::items_in`]; of duplicates within one context the
+    /// one with more declarations wins. Items end up sorted by `fn_key`, so
+    /// merging is deterministic and idempotent.
+    ///
+    /// Results of another version of the source replace the current ones, as
+    /// do the first results of the package for [`Self::provisional`] ones.
+    ///
+    /// Parts of split functions, see [`Function::split`], are joined once
+    /// all of them are merged and kept as they are until then.
+    pub fn merge(&mut self, other: Self) {
+        if self.source_hash != other.source_hash || self.provisional && !other.provisional {
+            *self = other;
+            return;
+        }
+        if other.provisional && !self.provisional {
+            return;
+        }
+        let mut interner = TypeInterner::new(mem::take(&mut self.types));
+        let mut others = other.items;
+        for item in &mut others {
+            item.function_mut()
+                .reintern_types(&other.types, &mut interner);
+        }
+        self.types = interner.into_types();
+        let mut items: BTreeMap<(u64, String), Item> = BTreeMap::new();
+        let mut parts: BTreeMap<(u64, u64), BTreeMap<u32, Item>> = BTreeMap::new();
+        for item in mem::take(&mut self.items).into_iter().chain(others) {
+            let function = item.function();
+            if let Some(part) = function.part {
+                parts
+                    .entry((function.fn_key, part.whole))
+                    .or_default()
+                    .insert(part.index, item);
+            } else {
+                Self::insert_item(&mut items, item);
+            }
+        }
+        let mut incomplete = Vec::new();
+        for parts in parts.into_values() {
+            match Item::join(parts) {
+                Ok(item) => Self::insert_item(&mut items, item),
+                Err(parts) => incomplete.extend(parts.into_values()),
+            }
+        }
+        self.items = items.into_values().chain(incomplete).collect();
+    }
+
+    fn insert_item(items: &mut BTreeMap<(u64, String), Item>, item: Item) {
+        let function = item.function();
+        match items.entry((function.fn_key, function.context.clone())) {
+            Entry::Vacant(entry) => {
+                entry.insert(item);
+            }
+            Entry::Occupied(mut entry) => {
+                if entry.get().function().decls.len() < function.decls.len() {
+                    entry.insert(item);
+                }
+            }
+        }
+    }
+
+    /// Items of the file with one version of each function: the one analyzed
+    /// in `context` if there is one, else the other.
+    pub fn items_in<'a>(&'a self, context: &'a str) -> impl Iterator<Item = &'a Item> {
+        let preferred: HashSet<u64> = self
+            .items
+            .iter()
+            .map(Item::function)
+            .filter(|function| function.context == context)
+            .map(|function| function.fn_key)
+            .collect();
+        self.items.iter().filter(move |item| {
+            let function = item.function();
+            function.context == context || !preferred.contains(&function.fn_key)
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(transparent)]
-pub struct Workspace(pub HashMap<String, Crate>);
+pub struct Workspace {
+    #[serde(flatten)]
+    pub crates: HashMap<String, Crate>,
+    /// Information about the analysis itself, under a key no crate can be
+    /// named
+    #[serde(default, rename = "$meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<WorkspaceMeta>,
+}
 
 impl Workspace {
-    #[cfg(test)]
+    #[must_use]
+    pub const fn new(crates: HashMap<String, Crate>) -> Self {
+        Self { crates, meta: None }
+    }
+
     pub fn merge(&mut self, other: Self) {
-        let Self(crates) = other;
-        for (name, krate) in crates {
-            if let Some(insert) = self.0.get_mut(&name) {
+        for (name, krate) in other.crates {
+            if let Some(insert) = self.crates.get_mut(&name) {
                 insert.merge(krate);
             } else {
-                self.0.insert(name, krate);
+                self.crates.insert(name, krate);
+            }
+        }
+        if let Some(meta) = other.meta {
+            self.meta.get_or_insert_default().merge(meta);
+        }
+    }
+
+    /// Move the results of the file `from` to the file `to`, as for a copy
+    /// of `to` analyzed in place of it.
+    pub fn rename_file(&mut self, from: &str, to: &str) {
+        for krate in self.crates.values_mut() {
+            if let Some(file) = krate.0.remove(from) {
+                krate.0.insert(to.to_owned(), file);
+            }
+        }
+        for files in self
+            .meta
+            .iter_mut()
+            .flat_map(|meta| meta.timings.values_mut())
+        {
+            if let Some(functions) = files.remove(from) {
+                files.insert(to.to_owned(), functions);
             }
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Wall time spent on the analysis of a function, in milliseconds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(
+    clippy::struct_field_names,
+    reason = "the unit is part of the serialized names"
+)]
+pub struct AnalysisTimings {
+    /// Collecting spans, basic blocks and borrows from the MIR
+    pub init_ms: f64,
+    /// Computing the Polonius output and the ranges derived from it
+    pub polonius_ms: f64,
+    /// Collecting the declarations of the result
+    pub collect_ms: f64,
+    /// Whether the result was read from the MIR cache, which only takes
+    /// `init_ms`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl AnalysisTimings {
+    /// Timings of a result read from the MIR cache in `init_ms`
+    #[must_use]
+    pub const fn cached(init_ms: f64) -> Self {
+        Self {
+            init_ms,
+            polonius_ms: 0.0,
+            collect_ms: 0.0,
+            cached: true,
+        }
+    }
+
+    #[must_use]
+    pub fn total_ms(self) -> f64 {
+        self.init_ms + self.polonius_ms + self.collect_ms
+    }
+}
+
+impl Add for AnalysisTimings {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            init_ms: self.init_ms + rhs.init_ms,
+            polonius_ms: self.polonius_ms + rhs.polonius_ms,
+            collect_ms: self.collect_ms + rhs.collect_ms,
+            cached: self.cached && rhs.cached,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WorkspaceMeta {
+    /// Analysis timings by crate, file and `fn_id`
+    #[serde(default)]
+    pub timings: HashMap<String, HashMap<String, BTreeMap<u32, AnalysisTimings>>>,
+    /// Whether a function was analyzed without borrow check results, after
+    /// its analysis timed out
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+}
+
+impl WorkspaceMeta {
+    /// Meta section with the timings of a single function.
+    #[must_use]
+    pub fn with_timings(krate: String, file: String, fn_id: u32, timings: AnalysisTimings) -> Self {
+        Self {
+            timings: HashMap::from([(
+                krate,
+                HashMap::from([(file, BTreeMap::from([(fn_id, timings)]))]),
+            )]),
+            degraded: false,
+        }
+    }
+
+    /// Merge the timings of `other`, which replace earlier timings of the
+    /// same function.
+    pub fn merge(&mut self, other: Self) {
+        self.degraded |= other.degraded;
+        for (krate, files) in other.timings {
+            let krate = self.timings.entry(krate).or_default();
+            for (file, functions) in files {
+                krate.entry(file).or_default().extend(functions);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(transparent)]
 pub struct Crate(pub HashMap<String, File>);
 
@@ -159,8 +445,7 @@ impl Crate {
         let Self(files) = other;
         for (file, mir) in files {
             if let Some(insert) = self.0.get_mut(&file) {
-                insert.items.extend_from_slice(&mir.items);
-                insert.items.dedup_by(|a, b| a.fn_id == b.fn_id);
+                insert.merge(mir);
             } else {
                 self.0.insert(file, mir);
             }
@@ -174,12 +459,27 @@ pub enum MirRval {
     Move {
         target_local: FnLocal,
         range: Range,
+        /// Binding a part of `target_local` is moved into, as by the pattern
+        /// of a `match` arm or an `if let`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        moved_into: Option<FnLocal>,
+        /// Whether the range is the call of the macro the code is expanded
+        /// from
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        from_macro: bool,
     },
     Borrow {
         target_local: FnLocal,
         range: Range,
         mutable: bool,
         outlive: Option<Range>,
+        /// Whether the borrowed place is behind a reference, as in `&*r`
+        #[serde(default)]
+        through_deref: bool,
+        /// Whether the range is the call of the macro the code is expanded
+        /// from
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        from_macro: bool,
     },
 }
 
@@ -198,6 +498,13 @@ pub enum MirStatement {
         target_local: FnLocal,
         range: Range,
         rval: Option<MirRval>,
+        /// Reference the assigned place is reached through, as in `*r = v`
+        #[serde(default)]
+        through_ref: Option<FnLocal>,
+        /// Whether the range is the call of the macro the code is expanded
+        /// from
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        from_macro: bool,
     },
     Other {
         range: Range,
@@ -215,16 +522,59 @@ impl MirStatement {
     }
 }
 
+/// How a method call takes its receiver
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverKind {
+    /// By value, as `self`
+    Move,
+    /// As `&self`
+    Ref,
+    /// As `&mut self`
+    RefMut,
+}
+
+/// Local passed as the receiver of a method call
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CallReceiver {
+    pub local: FnLocal,
+    /// The receiver expression, before the method name
+    pub range: Range,
+    pub kind: ReceiverKind,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum MirTerminator {
     Drop {
         local: FnLocal,
         range: Range,
+        /// Whether the range is the call of the macro the code is expanded
+        /// from
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        from_macro: bool,
     },
     Call {
         destination_local: FnLocal,
+        /// The callee name, or the path to it
         fn_span: Range,
+        /// Path of the called function resolved by the compiler, like
+        /// `std::mem::forget`, if it is known statically
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        callee: Option<String>,
+        /// The callee with its arguments, from the method name on for method
+        /// calls
+        call_expr_span: Range,
+        /// The whole call expression, including the receiver of method calls
+        #[serde(default)]
+        range: Option<Range>,
+        #[serde(default)]
+        receiver: Option<CallReceiver>,
+        /// Locals whose borrows flow into the returned value, which keeps
+        /// them borrowed, like both arguments of
+        /// `fn pick<'a>(a: &'a str, b: &'a str) -> &'a str`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        borrows_from: Vec<FnLocal>,
     },
     Other {
         range: Range,
@@ -234,7 +584,7 @@ impl MirTerminator {
     #[must_use]
     pub const fn range(&self) -> Range {
         match self {
-            Self::Call { fn_span, .. } => *fn_span,
+            Self::Call { call_expr_span, .. } => *call_expr_span,
             Self::Drop { range, .. } | Self::Other { range } => *range,
         }
     }
@@ -244,6 +594,77 @@ impl MirTerminator {
 pub struct MirBasicBlock {
     pub statements: Vec<MirStatement>,
     pub terminator: Option<MirTerminator>,
+    /// Whether the block only runs while unwinding from a panic
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_cleanup: bool,
+}
+
+/// Type of a declaration, as printed by the compiler
+///
+/// Types are interned per [`File`], in [`File::types`]. Payloads of former
+/// versions name the type instead, which is interned when their file is read.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(untagged)]
+pub enum DeclType {
+    Index(u32),
+    Name(String),
+}
+
+impl DeclType {
+    /// Name of the type, looked up in the `types` of its file.
+    #[must_use]
+    pub fn name<'a>(&'a self, types: &'a [String]) -> &'a str {
+        match self {
+            Self::Index(index) => types.get(*index as usize).map_or("", String::as_str),
+            Self::Name(name) => name,
+        }
+    }
+}
+
+impl From<&str> for DeclType {
+    fn from(name: &str) -> Self {
+        Self::Name(name.to_owned())
+    }
+}
+
+/// Table of type names, each stored once, see [`File::types`]
+#[derive(Default, Debug)]
+pub struct TypeInterner {
+    types: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl TypeInterner {
+    /// Interner adding to the table `types`, whose indices stay the same.
+    #[must_use]
+    pub fn new(types: Vec<String>) -> Self {
+        let indices = (0..)
+            .zip(&types)
+            .map(|(index, name)| (name.clone(), index))
+            .collect();
+        Self { types, indices }
+    }
+
+    /// Index of `name` in the table, adding it if it is new.
+    pub fn intern(&mut self, name: &str) -> DeclType {
+        if let Some(index) = self.indices.get(name) {
+            return DeclType::Index(*index);
+        }
+        let index = u32::try_from(self.types.len()).unwrap_or(u32::MAX);
+        self.types.push(name.to_owned());
+        self.indices.insert(name.to_owned(), index);
+        DeclType::Index(index)
+    }
+
+    /// Index of the type `ty`, looked up in the table `types` of its file.
+    pub fn reintern(&mut self, ty: &DeclType, types: &[String]) -> DeclType {
+        self.intern(ty.name(types))
+    }
+
+    #[must_use]
+    pub fn into_types(self) -> Vec<String> {
+        self.types
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -253,68 +674,452 @@ pub enum MirDecl {
         local: FnLocal,
         name: String,
         span: Range,
-        ty: String,
+        /// Lexical scope, from the declaration to the end of the enclosing
+        /// block
+        #[serde(default)]
+        scope: Option<Range>,
+        ty: DeclType,
         lives: Vec<Range>,
         shared_borrow: Vec<Range>,
         mutable_borrow: Vec<Range>,
+        /// Ranges where shared and mutable borrows of overlapping places are
+        /// live at once
+        #[serde(default)]
+        shared_mut: Vec<Range>,
         drop: bool,
         drop_range: Vec<Range>,
         must_live_at: Vec<Range>,
+        /// Part of `must_live_at` required by a lifetime of the function
+        /// signature
+        #[serde(default)]
+        must_live_from_signature: Vec<Range>,
+        /// Assignments whose value is never read
+        #[serde(default)]
+        dead_writes: Vec<Range>,
     },
     Other {
         local: FnLocal,
-        ty: String,
+        ty: DeclType,
         lives: Vec<Range>,
         shared_borrow: Vec<Range>,
         mutable_borrow: Vec<Range>,
+        /// Ranges where shared and mutable borrows of overlapping places are
+        /// live at once
+        #[serde(default)]
+        shared_mut: Vec<Range>,
         drop: bool,
         drop_range: Vec<Range>,
         must_live_at: Vec<Range>,
+        /// Part of `must_live_at` required by a lifetime of the function
+        /// signature
+        #[serde(default)]
+        must_live_from_signature: Vec<Range>,
+        /// Assignments whose value is never read
+        #[serde(default)]
+        dead_writes: Vec<Range>,
     },
 }
 
+impl MirDecl {
+    #[must_use]
+    pub const fn ty(&self) -> &DeclType {
+        match self {
+            Self::User { ty, .. } | Self::Other { ty, .. } => ty,
+        }
+    }
+}
+
+/// Kind of item a body belongs to
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    /// Function or closure
+    #[default]
+    Fn,
+    /// Initializer of a `static`
+    Static,
+    /// Value of a `const`, including inline and anonymous constants like
+    /// array lengths
+    Const,
+}
+
+/// Item analyzed in a file, with the range and kind of its declaration
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Item {
+    /// Function, closure or body of a `static` or `const`, see [`ItemKind`]
+    Function {
+        /// Signature of the function, from the `fn` keyword to the return
+        /// type
+        span: Option<Range>,
+        #[serde(default)]
+        kind: ItemKind,
+        function: Function,
+    },
+}
+
+impl Item {
+    #[must_use]
+    pub const fn function(&self) -> &Function {
+        match self {
+            Self::Function { function, .. } => function,
+        }
+    }
+
+    pub const fn function_mut(&mut self) -> &mut Function {
+        match self {
+            Self::Function { function, .. } => function,
+        }
+    }
+
+    #[must_use]
+    pub const fn span(&self) -> Option<Range> {
+        match self {
+            Self::Function { span, .. } => *span,
+        }
+    }
+
+    #[must_use]
+    pub const fn kind(&self) -> ItemKind {
+        match self {
+            Self::Function { kind, .. } => *kind,
+        }
+    }
+
+    /// Parts of the item, see [`Function::split`], each with the span and
+    /// kind of the item.
+    #[must_use]
+    pub fn split(self, max_bytes: usize) -> Vec<Self> {
+        match self {
+            Self::Function {
+                span,
+                kind,
+                function,
+            } => function
+                .split(max_bytes)
+                .into_iter()
+                .map(|function| Self::Function {
+                    span,
+                    kind,
+                    function,
+                })
+                .collect(),
+        }
+    }
+
+    /// The item split into `parts`, by their index, or the parts back if
+    /// some are missing.
+    fn join(parts: BTreeMap<u32, Self>) -> Result<Self, BTreeMap<u32, Self>> {
+        let Some(&Self::Function { span, kind, .. }) = parts.values().next() else {
+            return Err(parts);
+        };
+        let item = |function| Self::Function {
+            span,
+            kind,
+            function,
+        };
+        let functions = parts
+            .into_iter()
+            .map(|(index, part)| match part {
+                Self::Function { function, .. } => (index, function),
+            })
+            .collect();
+        Function::join(functions).map(item).map_err(|parts| {
+            parts
+                .into_iter()
+                .map(|(index, function)| (index, item(function)))
+                .collect()
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Function {
+    /// Index of the definition, only unique within one compilation of a crate
     pub fn_id: u32,
+    /// Hash of the crate name and the definition path, the same in every
+    /// target the function is compiled for, see [`crate::mir_cache::fn_key`]
+    #[serde(default, with = "fn_key_string")]
+    pub fn_key: u64,
+    /// Path of the function within its crate, like `module::Type::method`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub fn_name: String,
+    /// Number of parameters, locals `1..=arg_count` of the body
+    #[serde(default)]
+    pub arg_count: u32,
     pub basic_blocks: Vec<MirBasicBlock>,
     pub decls: Vec<MirDecl>,
+    /// Pairs of (closure or coroutine upvar, local of this function it
+    /// captures), one per capture of a closure created in this function.
+    #[serde(default)]
+    pub captures: Vec<(FnLocal, FnLocal)>,
+    /// Pairs of (reference, local it mutably borrows), one per mutable borrow
+    /// in this function.
+    #[serde(default)]
+    pub borrow_edges: Vec<(FnLocal, FnLocal)>,
+    /// Pairs of (`Rc` or `Arc`, its clone), one per call of `clone` on a
+    /// reference counted pointer in this function
+    #[serde(default)]
+    pub clone_edges: Vec<(FnLocal, FnLocal)>,
+    /// Points where the body of an `async` block or function is suspended,
+    /// at its `.await`s
+    #[serde(default)]
+    pub await_points: Vec<Range>,
+    /// Which part of the function this is, if it was split, see
+    /// [`Self::split`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part: Option<FunctionPart>,
+    /// Compilation the function was analyzed in: empty for the library or
+    /// binary, [`TEST_CONTEXT`] for its test harness
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub context: String,
+}
+
+/// [`Function::context`] of functions compiled with `cfg(test)`
+pub const TEST_CONTEXT: &str = "test";
+
+/// Part `index` of the `count` parts of a split function
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FunctionPart {
+    pub index: u32,
+    pub count: u32,
+    /// Hash of the serialized function, telling apart the parts of its
+    /// versions from different compilations
+    pub whole: u64,
+}
+
+fn json(value: &impl Serialize) -> Vec<u8> {
+    serde_json::to_vec(value).unwrap_or_default()
+}
+
+fn json_len(value: &impl Serialize) -> usize {
+    json(value).len()
+}
+
+impl Function {
+    /// Re-index the types of the declarations, from the table `types` of
+    /// their file into `interner`.
+    pub fn reintern_types(&mut self, types: &[String], interner: &mut TypeInterner) {
+        for decl in &mut self.decls {
+            let (MirDecl::User { ty, .. } | MirDecl::Other { ty, .. }) = decl;
+            *ty = interner.reintern(ty, types);
+        }
+    }
+
+    /// Parts of the function that serialize to at most `max_bytes` each,
+    /// unless a single basic block is larger, or the function itself if it
+    /// fits.
+    ///
+    /// The basic blocks are spread over the parts in order. The first part
+    /// keeps the declarations, captures, borrow and clone edges and await
+    /// points.
+    #[must_use]
+    pub fn split(mut self, max_bytes: usize) -> Vec<Self> {
+        let whole = json(&self);
+        if whole.len() <= max_bytes {
+            return vec![self];
+        }
+        let mut hasher = DefaultHasher::new();
+        whole.hash(&mut hasher);
+        let whole = hasher.finish();
+        let blocks = mem::take(&mut self.basic_blocks);
+        // the largest part numbers, so that sizes are not underestimated
+        self.part = Some(FunctionPart {
+            index: u32::MAX,
+            count: u32::MAX,
+            whole,
+        });
+        let empty = Self {
+            basic_blocks: Vec::new(),
+            decls: Vec::new(),
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            fn_name: self.fn_name.clone(),
+            context: self.context.clone(),
+            ..self
+        };
+        let empty_len = json_len(&empty);
+        let mut used = json_len(&self);
+        let mut parts = vec![self];
+        for block in blocks {
+            // the separating comma
+            let block_len = json_len(&block) + 1;
+            if used + block_len > max_bytes
+                && parts
+                    .last()
+                    .is_some_and(|part| !part.basic_blocks.is_empty())
+            {
+                parts.push(empty.clone());
+                used = empty_len;
+            }
+            used += block_len;
+            if let Some(part) = parts.last_mut() {
+                part.basic_blocks.push(block);
+            }
+        }
+        let count = u32::try_from(parts.len()).unwrap_or(u32::MAX);
+        for (index, part) in (0..).zip(&mut parts) {
+            part.part = (count > 1).then_some(FunctionPart {
+                index,
+                count,
+                whole,
+            });
+        }
+        parts
+    }
+
+    /// The function split into `parts`, by their index, or the parts
+    /// back if some are missing.
+    fn join(mut parts: BTreeMap<u32, Self>) -> Result<Self, BTreeMap<u32, Self>> {
+        let count = parts
+            .values()
+            .next()
+            .and_then(|first| first.part)
+            .map(|p| p.count);
+        let complete = count.is_some_and(|count| {
+            parts
+                .values()
+                .all(|part| part.part.is_some_and(|p| p.count == count))
+                && parts.keys().copied().eq(0..count)
+        });
+        if !complete {
+            return Err(parts);
+        }
+        let Some((_, mut function)) = parts.pop_first() else {
+            return Err(parts);
+        };
+        for rest in parts.into_values() {
+            function.basic_blocks.extend(rest.basic_blocks);
+            function.decls.extend(rest.decls);
+            function.captures.extend(rest.captures);
+            function.borrow_edges.extend(rest.borrow_edges);
+            function.clone_edges.extend(rest.clone_edges);
+            function.await_points.extend(rest.await_points);
+        }
+        function.part = None;
+        Ok(function)
+    }
+}
+
+/// Parts of split functions received so far, waiting for the rest of their
+/// function
+#[derive(Default, Debug)]
+pub struct SplitFunctions(HashMap<String, Crate>);
+
+impl SplitFunctions {
+    /// `workspace` with the parts of split functions replaced by the
+    /// functions all their parts complete, keeping the other parts.
+    ///
+    /// Returns `None` if nothing is left of `workspace`.
+    pub fn reassemble(&mut self, mut workspace: Workspace) -> Option<Workspace> {
+        for (name, krate) in &mut workspace.crates {
+            for (file_name, file) in &mut krate.0 {
+                if file.items.iter().all(|item| item.function().part.is_none()) {
+                    continue;
+                }
+                let source_hash = file.source_hash.clone();
+                let received = mem::replace(
+                    file,
+                    File {
+                        items: Vec::new(),
+                        types: Vec::new(),
+                        source_hash,
+                        provisional: file.provisional,
+                    },
+                );
+                let pending = self.0.entry(name.clone()).or_default();
+                pending
+                    .0
+                    .entry(file_name.clone())
+                    .or_insert_with(|| File {
+                        items: Vec::new(),
+                        types: Vec::new(),
+                        source_hash: received.source_hash.clone(),
+                        provisional: received.provisional,
+                    })
+                    .merge(received);
+                if let Some(merged) = pending.0.get_mut(file_name) {
+                    let (incomplete, complete) = mem::take(&mut merged.items)
+                        .into_iter()
+                        .partition(|item: &Item| item.function().part.is_some());
+                    merged.items = incomplete;
+                    file.items = complete;
+                    file.types.clone_from(&merged.types);
+                }
+                pending.0.retain(|_, file| !file.items.is_empty());
+            }
+            krate.0.retain(|_, file| !file.items.is_empty());
+        }
+        self.0.retain(|_, krate| !krate.0.is_empty());
+        workspace.crates.retain(|_, krate| !krate.0.is_empty());
+        (!workspace.crates.is_empty() || workspace.meta.is_some()).then_some(workspace)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::marker::PhantomData;
+
     use super::*;
 
     #[test]
     fn test_loc_arithmetic_memory_safety() {
-        let loc = Loc::from_byte_pos("test string with unicode 🦀", 5, 0);
+        let loc = CharOffset(5);
         let loc2 = loc + 2;
         let loc3 = loc2 - 1;
 
         assert_eq!(loc3.0, loc.0 + 1);
 
-        let loc_zero = Loc(0);
+        let loc_zero = CharOffset(0);
         let loc_underflow = loc_zero - 10;
         assert_eq!(loc_underflow.0, 0);
 
-        let loc_large = Loc(u32::MAX - 10);
+        let loc_large = CharOffset(u32::MAX - 10);
         let loc_add = loc_large + 5;
         assert_eq!(loc_add.0, u32::MAX - 5);
     }
 
+    /// Whether offsets can be moved by a `T`: the inherent constant, which
+    /// takes precedence, only exists if they can.
+    struct Addable<T>(PhantomData<T>);
+
+    trait NotAddable {
+        const ADDABLE: bool = false;
+    }
+
+    impl<T> NotAddable for Addable<T> {}
+
+    impl<T> Addable<T>
+    where
+        CharOffset: Add<T>,
+    {
+        const ADDABLE: bool = true;
+    }
+
+    // lines are `u32`, so the tests do not build once they can be added
+    const _: () = assert!(
+        Addable::<i32>::ADDABLE
+            && !Addable::<u32>::ADDABLE
+            && !Addable::<LineCol>::ADDABLE
+            && !Addable::<CharOffset>::ADDABLE
+    );
+
     #[test]
     fn test_range_creation_and_validation() {
-        let valid_range = Range::new(Loc(0), Loc(10)).unwrap();
+        let valid_range = Range::new(CharOffset(0), CharOffset(10)).unwrap();
         assert_eq!(valid_range.from().0, 0);
         assert_eq!(valid_range.until().0, 10);
         assert_eq!(valid_range.size(), 10);
 
-        let invalid_range = Range::new(Loc(10), Loc(5));
+        let invalid_range = Range::new(CharOffset(10), CharOffset(5));
         assert!(invalid_range.is_none());
 
-        let same_pos_range = Range::new(Loc(5), Loc(5));
+        let same_pos_range = Range::new(CharOffset(5), CharOffset(5));
         assert!(same_pos_range.is_none());
 
-        let large_range = Range::new(Loc(0), Loc(u32::MAX)).unwrap();
+        let large_range = Range::new(CharOffset(0), CharOffset(u32::MAX)).unwrap();
         assert_eq!(large_range.size(), u32::MAX);
     }
 
@@ -337,9 +1142,28 @@ mod tests {
         assert_eq!(map.get(&fn_local2), Some(&"second"));
     }
 
+    #[test]
+    fn test_fn_key_is_written_as_a_string() {
+        let local = FnLocal::new(1, u64::MAX);
+        let json = serde_json::to_value(local).unwrap();
+        assert_eq!(json["fn_key"], "18446744073709551615");
+        assert_eq!(serde_json::from_value::<FnLocal>(json).unwrap(), local);
+        // as written by former versions
+        let number = serde_json::json!({ "id": 1, "fn_key": 7 });
+        assert_eq!(
+            serde_json::from_value::<FnLocal>(number).unwrap(),
+            FnLocal::new(1, 7)
+        );
+    }
+
     #[test]
     fn test_file_model_operations() {
-        let mut file = File { items: Vec::new() };
+        let mut file = File {
+            items: Vec::new(),
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        };
 
         assert_eq!(file.items.len(), 0);
         assert!(file.items.is_empty());
@@ -353,43 +1177,632 @@ mod tests {
 
     #[test]
     fn test_workspace_operations() {
-        let mut workspace = Workspace(HashMap::new());
+        let mut workspace = Workspace::new(HashMap::new());
         let mut crate1 = Crate(HashMap::new());
         let mut crate2 = Crate(HashMap::new());
 
-        crate1
-            .0
-            .insert("lib.rs".to_string(), File { items: Vec::new() });
-        crate1
-            .0
-            .insert("main.rs".to_string(), File { items: Vec::new() });
+        crate1.0.insert(
+            "lib.rs".to_string(),
+            File {
+                items: Vec::new(),
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        );
+        crate1.0.insert(
+            "main.rs".to_string(),
+            File {
+                items: Vec::new(),
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        );
 
-        crate2
-            .0
-            .insert("helper.rs".to_string(), File { items: Vec::new() });
+        crate2.0.insert(
+            "helper.rs".to_string(),
+            File {
+                items: Vec::new(),
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        );
 
-        workspace.0.insert("crate1".to_string(), crate1);
-        workspace.0.insert("crate2".to_string(), crate2);
+        workspace.crates.insert("crate1".to_string(), crate1);
+        workspace.crates.insert("crate2".to_string(), crate2);
 
-        assert_eq!(workspace.0.len(), 2);
-        assert!(workspace.0.contains_key("crate1"));
-        assert!(workspace.0.contains_key("crate2"));
+        assert_eq!(workspace.crates.len(), 2);
+        assert!(workspace.crates.contains_key("crate1"));
+        assert!(workspace.crates.contains_key("crate2"));
 
-        let mut other_workspace = Workspace(HashMap::new());
+        let mut other_workspace = Workspace::new(HashMap::new());
         let crate3 = Crate(HashMap::new());
-        other_workspace.0.insert("crate3".to_string(), crate3);
+        other_workspace.crates.insert("crate3".to_string(), crate3);
 
         workspace.merge(other_workspace);
-        assert_eq!(workspace.0.len(), 3);
-        assert!(workspace.0.contains_key("crate3"));
+        assert_eq!(workspace.crates.len(), 3);
+        assert!(workspace.crates.contains_key("crate3"));
+    }
+
+    /// Item of `function`, without a signature
+    fn item(function: Function) -> Item {
+        Item::Function {
+            span: None,
+            kind: ItemKind::Fn,
+            function,
+        }
+    }
+
+    fn function_with_decls(fn_id: u32, decls: u32) -> Function {
+        function_with_key(fn_id, u64::from(fn_id), decls)
+    }
+
+    fn function_with_key(fn_id: u32, fn_key: u64, decls: u32) -> Function {
+        Function {
+            fn_id,
+            fn_key,
+            fn_name: String::new(),
+            arg_count: 0,
+            basic_blocks: Vec::new(),
+            decls: (0..decls)
+                .map(|id| MirDecl::Other {
+                    local: FnLocal::new(id, fn_key),
+                    ty: "i32".into(),
+                    lives: Vec::new(),
+                    shared_borrow: Vec::new(),
+                    mutable_borrow: Vec::new(),
+                    shared_mut: Vec::new(),
+                    drop: false,
+                    drop_range: Vec::new(),
+                    must_live_at: Vec::new(),
+                    must_live_from_signature: Vec::new(),
+                    dead_writes: Vec::new(),
+                })
+                .collect(),
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            part: None,
+            context: String::new(),
+        }
+    }
+
+    fn fn_ids(krate: &Crate, file: &str) -> Vec<u32> {
+        krate.0[file]
+            .items
+            .iter()
+            .map(|f| f.function().fn_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_crate_merge_dedups_interleaved_functions() {
+        let file = |ids: &[u32]| File {
+            items: ids
+                .iter()
+                .map(|id| item(function_with_decls(*id, 1)))
+                .collect(),
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        };
+        let mut krate = Crate(HashMap::from([("lib.rs".to_string(), file(&[1, 2, 3]))]));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[3, 1, 4, 2]),
+        )])));
+
+        assert_eq!(fn_ids(&krate, "lib.rs"), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_crate_merge_prefers_function_with_more_decls() {
+        let mut krate = Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            File {
+                items: vec![
+                    item(function_with_decls(1, 2)),
+                    item(function_with_decls(2, 3)),
+                ],
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        )]));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            File {
+                items: vec![
+                    item(function_with_decls(2, 1)),
+                    item(function_with_decls(1, 5)),
+                ],
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        )])));
+
+        let decls: Vec<_> = krate.0["lib.rs"]
+            .items
+            .iter()
+            .map(|f| (f.function().fn_id, f.function().decls.len()))
+            .collect();
+        assert_eq!(decls, vec![(1, 5), (2, 3)]);
+    }
+
+    #[test]
+    fn test_crate_merge_replaces_other_source_version() {
+        let file = |ids: &[u32], source_hash: &str| File {
+            items: ids
+                .iter()
+                .map(|id| item(function_with_decls(*id, 1)))
+                .collect(),
+            types: Vec::new(),
+            source_hash: source_hash.to_string(),
+            provisional: false,
+        };
+        let mut krate = Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[1, 2, 3], "old"),
+        )]));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[2], "new"),
+        )])));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[4], "new"),
+        )])));
+
+        assert_eq!(fn_ids(&krate, "lib.rs"), vec![2, 4]);
+        assert_eq!(krate.0["lib.rs"].source_hash, "new");
+    }
+
+    #[test]
+    fn test_crate_merge_replaces_provisional_results() {
+        let file = |ids: &[u32], provisional: bool| File {
+            items: ids
+                .iter()
+                .map(|id| item(function_with_decls(*id, 1)))
+                .collect(),
+            types: Vec::new(),
+            source_hash: "hash".to_string(),
+            provisional,
+        };
+        let mut krate = Crate(HashMap::from([("lib.rs".to_string(), file(&[1, 2], true))]));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[3], false),
+        )])));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[4], false),
+        )])));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            file(&[5], true),
+        )])));
+
+        assert_eq!(fn_ids(&krate, "lib.rs"), vec![3, 4]);
+        assert!(!krate.0["lib.rs"].provisional);
+    }
+
+    #[test]
+    fn test_crate_merge_keeps_each_context() {
+        let in_context = |id: u32, decls: u32, context: &str| {
+            item(Function {
+                context: context.to_string(),
+                ..function_with_decls(id, decls)
+            })
+        };
+        let mut krate = Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            File {
+                items: vec![in_context(1, 1, ""), in_context(2, 1, "")],
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        )]));
+        krate.merge(Crate(HashMap::from([(
+            "lib.rs".to_string(),
+            File {
+                items: vec![
+                    in_context(1, 2, TEST_CONTEXT),
+                    in_context(3, 1, TEST_CONTEXT),
+                ],
+                types: Vec::new(),
+                source_hash: String::new(),
+                provisional: false,
+            },
+        )])));
+
+        let file = &krate.0["lib.rs"];
+        assert_eq!(file.items.len(), 4, "versions of both contexts are kept");
+        let versions = |context| {
+            file.items_in(context)
+                .map(Item::function)
+                .map(|f| (f.fn_id, f.context.as_str()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions(""), vec![(1, ""), (2, ""), (3, TEST_CONTEXT)]);
+        assert_eq!(
+            versions(TEST_CONTEXT),
+            vec![(1, TEST_CONTEXT), (2, ""), (3, TEST_CONTEXT)]
+        );
+    }
+
+    #[test]
+    fn test_workspace_rename_file() {
+        let file = File {
+            items: vec![item(function_with_decls(1, 1))],
+            types: Vec::new(),
+            source_hash: "hash".to_string(),
+            provisional: false,
+        };
+        let mut workspace = Workspace {
+            crates: HashMap::from([(
+                "owl".to_string(),
+                Crate(HashMap::from([("/tmp/copy/owl.rs".to_string(), file)])),
+            )]),
+            meta: Some(WorkspaceMeta::with_timings(
+                "owl".to_string(),
+                "/tmp/copy/owl.rs".to_string(),
+                1,
+                AnalysisTimings::default(),
+            )),
+        };
+        workspace.rename_file("/tmp/copy/owl.rs", "/src/owl.rs");
+
+        let files: Vec<_> = workspace.crates["owl"].0.keys().collect();
+        assert_eq!(files, vec!["/src/owl.rs"]);
+        assert_eq!(workspace.crates["owl"].0["/src/owl.rs"].source_hash, "hash");
+        let timed: Vec<_> = workspace.meta.unwrap().timings["owl"]
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(timed, vec!["/src/owl.rs"]);
+    }
+
+    #[test]
+    fn test_workspace_meta_round_trip() {
+        let timings = AnalysisTimings {
+            init_ms: 1.5,
+            polonius_ms: 20.25,
+            collect_ms: 0.5,
+            cached: true,
+        };
+        let workspace = Workspace {
+            crates: HashMap::from([("krate".to_string(), Crate(HashMap::new()))]),
+            meta: Some(WorkspaceMeta::with_timings(
+                "krate".to_string(),
+                "lib.rs".to_string(),
+                7,
+                timings,
+            )),
+        };
+
+        let json = serde_json::to_string(&workspace).unwrap();
+        let workspace: Workspace = serde_json::from_str(&json).unwrap();
+        assert_eq!(workspace.crates.len(), 1, "meta is not a crate");
+        assert!(workspace.crates.contains_key("krate"));
+        let mut meta = workspace.meta.expect("meta section is kept");
+        assert_eq!(meta.timings["krate"]["lib.rs"][&7], timings);
+        assert!(!meta.degraded);
+
+        let degraded = WorkspaceMeta {
+            degraded: true,
+            ..WorkspaceMeta::default()
+        };
+        let json = serde_json::to_string(&degraded).unwrap();
+        meta.merge(serde_json::from_str(&json).unwrap());
+        assert!(meta.degraded, "a degraded function degrades the workspace");
+    }
+
+    #[test]
+    fn test_workspace_without_meta() {
+        let workspace: Workspace =
+            serde_json::from_str(r#"{"krate":{"lib.rs":{"items":[]}}}"#).unwrap();
+        assert!(workspace.meta.is_none());
+        assert!(workspace.crates["krate"].0["lib.rs"].items.is_empty());
+
+        let json = serde_json::to_string(&workspace).unwrap();
+        assert!(
+            !json.contains("$meta"),
+            "no meta section is written: {json}"
+        );
+    }
+
+    #[test]
+    fn test_item_kind_defaults_to_fn() {
+        let item: Item = serde_json::from_str(
+            r#"{"function":{"span":null,"function":{"fn_id":1,"arg_count":0,"basic_blocks":[],"decls":[]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(item.kind(), ItemKind::Fn);
+
+        let Item::Function { function, .. } = item;
+        let item = Item::Function {
+            span: None,
+            kind: ItemKind::Static,
+            function,
+        };
+        let json = serde_json::to_string(&item).unwrap();
+        assert!(json.contains(r#""kind":"static""#), "{json}");
+    }
+
+    #[test]
+    fn test_file_interns_named_types() {
+        let decl = |id: u32, ty: &str| {
+            serde_json::json!({
+                "type": "other",
+                "local": { "id": id, "fn_key": 1 },
+                "ty": ty,
+                "lives": [],
+                "shared_borrow": [],
+                "mutable_borrow": [],
+                "drop": false,
+                "drop_range": [],
+                "must_live_at": [],
+            })
+        };
+        let former = serde_json::json!({
+            "items": [{
+                "function": {
+                    "span": null,
+                    "function": {
+                        "fn_id": 1,
+                        "arg_count": 0,
+                        "basic_blocks": [],
+                        "decls": [
+                            decl(0, "alloc::string::String"),
+                            decl(1, "i32"),
+                            decl(2, "alloc::string::String"),
+                        ],
+                    },
+                },
+            }],
+        });
+        let file: File = serde_json::from_value(former).unwrap();
+        assert_eq!(file.types, ["alloc::string::String", "i32"]);
+        let types: Vec<_> = file.items[0]
+            .function()
+            .decls
+            .iter()
+            .map(MirDecl::ty)
+            .collect();
+        assert_eq!(
+            types,
+            [
+                &DeclType::Index(0),
+                &DeclType::Index(1),
+                &DeclType::Index(0)
+            ]
+        );
+
+        let json = serde_json::to_string(&file).unwrap();
+        assert!(json.contains(r#""ty":0"#), "{json}");
+        assert_eq!(json.matches("alloc::string::String").count(), 1, "{json}");
+        let read: File = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.types, file.types);
+        assert_eq!(read.items[0].function().decls[2].ty(), &DeclType::Index(0));
+    }
+
+    #[test]
+    fn test_file_merge_reindexes_types() {
+        let typed = |fn_id: u32, types: &[&str]| {
+            let mut file = file(vec![function_with_decls(fn_id, 2)]);
+            file.types = types.iter().map(|&ty| ty.to_owned()).collect();
+            for (index, decl) in (0..).zip(&mut file.items[0].function_mut().decls) {
+                let (MirDecl::User { ty, .. } | MirDecl::Other { ty, .. }) = decl;
+                *ty = DeclType::Index(index);
+            }
+            file
+        };
+        let mut merged = typed(1, &["i32", "String"]);
+        merged.merge(typed(2, &["String", "u8"]));
+
+        assert_eq!(merged.types, ["i32", "String", "u8"]);
+        let names = |function: &Function| -> Vec<String> {
+            function
+                .decls
+                .iter()
+                .map(|decl| decl.ty().name(&merged.types).to_owned())
+                .collect()
+        };
+        assert_eq!(names(merged.items[0].function()), ["i32", "String"]);
+        assert_eq!(names(merged.items[1].function()), ["String", "u8"]);
+    }
+
+    #[test]
+    fn test_workspace_merge_is_idempotent() {
+        let other = Workspace::new(HashMap::from([(
+            "krate".to_string(),
+            Crate(HashMap::from([(
+                "lib.rs".to_string(),
+                File {
+                    items: vec![
+                        item(function_with_decls(2, 1)),
+                        item(function_with_decls(1, 1)),
+                    ],
+                    types: Vec::new(),
+                    source_hash: String::new(),
+                    provisional: false,
+                },
+            )])),
+        )]));
+        let mut workspace = Workspace::new(HashMap::new());
+        workspace.merge(other.clone());
+        workspace.merge(other.clone());
+        workspace.merge(other);
+
+        assert_eq!(workspace.crates.len(), 1);
+        assert_eq!(fn_ids(&workspace.crates["krate"], "lib.rs"), vec![1, 2]);
+    }
+
+    /// Function with `blocks` basic blocks of one statement each, the
+    /// statement ranges numbering the blocks.
+    fn function_with_blocks(fn_key: u64, blocks: u32) -> Function {
+        let mut function = function_with_key(1, fn_key, 3);
+        function.basic_blocks = (0..blocks)
+            .map(|block| MirBasicBlock {
+                statements: vec![MirStatement::Other {
+                    range: Range::new(CharOffset(block), CharOffset(block + 1)).unwrap(),
+                }],
+                terminator: None,
+                is_cleanup: false,
+            })
+            .collect();
+        function
+    }
+
+    fn block_starts(function: &Function) -> Vec<u32> {
+        function
+            .basic_blocks
+            .iter()
+            .flat_map(|block| &block.statements)
+            .map(|statement| match statement {
+                MirStatement::Other { range } => range.from().0,
+                _ => unreachable!("only other statements are generated"),
+            })
+            .collect()
+    }
+
+    fn file(functions: Vec<Function>) -> File {
+        File {
+            items: functions.into_iter().map(item).collect(),
+            types: Vec::new(),
+            source_hash: "hash".to_owned(),
+            provisional: false,
+        }
+    }
+
+    #[test]
+    fn test_split_function_round_trip() {
+        let function = function_with_blocks(7, 40);
+        let whole_len = json_len(&function);
+        assert_eq!(function.clone().split(whole_len).len(), 1);
+
+        let parts = function.clone().split(whole_len / 4);
+        assert!(parts.len() > 3, "{} parts", parts.len());
+        assert!(parts.iter().all(|part| json_len(part) <= whole_len / 4));
+        assert_eq!(parts[0].decls.len(), 3);
+        assert!(parts[1..].iter().all(|part| part.decls.is_empty()));
+        assert!(parts.iter().all(|part| part.fn_key == 7 && part.fn_id == 1));
+
+        // parts arrive in any order and any number of times
+        let mut merged = file(Vec::new());
+        for part in parts[1..]
+            .iter()
+            .rev()
+            .chain(&parts[1..])
+            .chain(&parts[..1])
+        {
+            merged.merge(file(vec![part.clone()]));
+        }
+        assert_eq!(merged.items.len(), 1);
+        let joined = merged.items[0].function();
+        assert_eq!(joined.part, None);
+        assert_eq!(joined.decls.len(), 3);
+        assert_eq!(block_starts(joined), block_starts(&function));
+    }
+
+    #[test]
+    fn test_split_item_keeps_its_span_and_kind() {
+        let span = Range::new(CharOffset(0), CharOffset(10)).unwrap();
+        let item = Item::Function {
+            span: Some(span),
+            kind: ItemKind::Static,
+            function: function_with_blocks(7, 40),
+        };
+        let parts = item.split(500);
+        assert!(parts.len() > 1, "{} parts", parts.len());
+        assert!(
+            parts
+                .iter()
+                .all(|part| part.span() == Some(span) && part.kind() == ItemKind::Static)
+        );
+
+        let mut merged = file(Vec::new());
+        merged.merge(File {
+            items: parts,
+            ..file(Vec::new())
+        });
+        assert_eq!(merged.items.len(), 1);
+        assert_eq!(merged.items[0].span(), Some(span));
+        assert_eq!(merged.items[0].kind(), ItemKind::Static);
+        assert_eq!(merged.items[0].function().basic_blocks.len(), 40);
+    }
+
+    #[test]
+    fn test_single_oversized_block_is_its_own_part() {
+        let parts = function_with_blocks(7, 3).split(1);
+        let positions: Vec<_> = parts
+            .iter()
+            .filter_map(|part| part.part)
+            .map(|part| (part.index, part.count))
+            .collect();
+        assert_eq!(positions, vec![(0, 3), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn test_incomplete_parts_are_kept_apart() {
+        let parts = function_with_blocks(7, 40).split(500);
+        let mut merged = file(vec![function_with_decls(7, 1)]);
+        merged.merge(file(parts[1..].to_vec()));
+        assert_eq!(merged.items.len(), parts.len());
+        assert_eq!(merged.items[0].function().part, None);
+        assert_eq!(merged.items[0].function().decls.len(), 1);
+
+        merged.merge(file(parts[..1].to_vec()));
+        assert_eq!(merged.items.len(), 1);
+        assert_eq!(merged.items[0].function().decls.len(), 3);
+        assert_eq!(merged.items[0].function().basic_blocks.len(), 40);
+    }
+
+    #[test]
+    fn test_split_functions_reassemble_across_workspaces() {
+        let workspace = |items: Vec<Function>| {
+            Workspace::new(HashMap::from([(
+                "krate".to_owned(),
+                Crate(HashMap::from([("lib.rs".to_owned(), file(items))])),
+            )]))
+        };
+        let parts = function_with_blocks(7, 40).split(500);
+        let mut split = SplitFunctions::default();
+
+        let (last, rest) = parts.split_last().unwrap();
+        for part in rest {
+            assert!(split.reassemble(workspace(vec![part.clone()])).is_none());
+        }
+        let whole = split
+            .reassemble(workspace(vec![last.clone(), function_with_decls(8, 1)]))
+            .unwrap();
+        let items = &whole.crates["krate"].0["lib.rs"].items;
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.function().part.is_none()));
+        assert_eq!(items[0].function().basic_blocks.len(), 40);
+        assert!(split.0.is_empty());
     }
 
     #[test]
     fn test_function_model_complex_operations() {
         let function = Function {
             fn_id: 42,
+            fn_key: 42,
+            fn_name: String::new(),
+            arg_count: 0,
             basic_blocks: Vec::new(),
             decls: Vec::new(),
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            part: None,
+            context: String::new(),
         };
 
         let function_clone = function.clone();
@@ -407,8 +1820,17 @@ mod tests {
         for i in 0..100 {
             functions.push(Function {
                 fn_id: i,
+                fn_key: u64::from(i),
+                fn_name: String::new(),
+                arg_count: 0,
                 basic_blocks: Vec::new(),
                 decls: Vec::new(),
+                captures: Vec::new(),
+                borrow_edges: Vec::new(),
+                clone_edges: Vec::new(),
+                await_points: Vec::new(),
+                part: None,
+                context: String::new(),
             });
         }
 
@@ -417,8 +1839,17 @@ mod tests {
 
         let large_function = Function {
             fn_id: 999,
+            fn_key: 999,
+            fn_name: String::new(),
+            arg_count: 0,
             basic_blocks: Vec::with_capacity(1000),
             decls: Vec::with_capacity(500),
+            captures: Vec::new(),
+            borrow_edges: Vec::new(),
+            clone_edges: Vec::new(),
+            await_points: Vec::new(),
+            part: None,
+            context: String::new(),
         };
 
         assert!(large_function.basic_blocks.capacity() >= 1000);
@@ -443,7 +1874,12 @@ mod tests {
         assert!(!concatenated.is_empty());
 
         let unicode_string = "🦀 Rust 🔥 Memory Safety 🛡️".to_string();
-        let _file = File { items: Vec::new() };
+        let _file = File {
+            items: Vec::new(),
+            types: Vec::new(),
+            source_hash: String::new(),
+            provisional: false,
+        };
 
         assert!(unicode_string.len() > unicode_string.chars().count());
     }
@@ -471,7 +1907,7 @@ mod tests {
 
             for fn_local in vec {
                 assert!(fn_local.id < 5);
-                assert!(fn_local.fn_id < 20);
+                assert!(fn_local.fn_key < 20);
             }
         }
 
@@ -491,7 +1927,7 @@ mod tests {
 
     #[test]
     fn test_serialization_structures() {
-        let range = Range::new(Loc(10), Loc(20)).unwrap();
+        let range = Range::new(CharOffset(10), CharOffset(20)).unwrap();
         let fn_local = FnLocal::new(1, 2);
 
         let range_clone = range;
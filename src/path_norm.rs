@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+/// Key of the file at `path` in analysis results.
+///
+/// The compiler and the editor spell the same file differently on Windows:
+/// verbatim `\\?\` prefixes, drive letters in either case and both kinds of
+/// separators. Keys are equal for all of these spellings. Elsewhere only `.`
+/// components and repeated separators are removed, without touching the file
+/// system, so paths that do not exist (anymore) keep their key.
+pub fn normalize_path(path: &Path) -> String {
+    normalize(&path.to_string_lossy(), cfg!(windows))
+}
+
+fn normalize(path: &str, windows: bool) -> String {
+    if windows {
+        normalize_windows(path)
+    } else {
+        Path::new(path)
+            .components()
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+fn normalize_windows(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.strip_prefix("//?/UNC/").map_or_else(
+        || path.strip_prefix("//?/").unwrap_or(&path).to_owned(),
+        |share| format!("//{share}"),
+    );
+    let unc = path.starts_with("//");
+    // paths of file URIs, like `/c:/src`
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+        _ => &path,
+    };
+
+    let mut normalized = String::from(if unc {
+        "//"
+    } else if path.starts_with('/') {
+        "/"
+    } else {
+        ""
+    });
+    let mut first = true;
+    for part in path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+    {
+        if !first {
+            normalized.push('/');
+        }
+        if first && part.len() == 2 && part.ends_with(':') {
+            normalized.push_str(&part.to_ascii_uppercase());
+        } else {
+            normalized.push_str(part);
+        }
+        first = false;
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_spellings_have_the_same_key() {
+        let pairs = [
+            (r"C:\proj\src\lib.rs", "c:/proj/src/lib.rs"),
+            (r"\\?\C:\proj\src\lib.rs", r"c:\proj\src\lib.rs"),
+            ("//?/c:/proj/src/lib.rs", "C:/proj/src/lib.rs"),
+            ("/c:/proj/src/lib.rs", r"C:\proj\src\lib.rs"),
+            (r"C:\proj\.\src\\lib.rs", "C:/proj/src/lib.rs"),
+            (r"\\?\UNC\server\share\lib.rs", r"\\server\share\lib.rs"),
+        ];
+        for (compiler, editor) in pairs {
+            assert_eq!(
+                normalize(compiler, true),
+                normalize(editor, true),
+                "{compiler} and {editor}"
+            );
+        }
+        assert_eq!(
+            normalize(r"\\?\c:\proj\src\lib.rs", true),
+            "C:/proj/src/lib.rs"
+        );
+        assert_eq!(
+            normalize(r"\\?\UNC\server\share\lib.rs", true),
+            "//server/share/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_windows_different_files_have_different_keys() {
+        assert_ne!(
+            normalize(r"C:\proj\src\lib.rs", true),
+            normalize(r"D:\proj\src\lib.rs", true)
+        );
+        assert_ne!(
+            normalize(r"C:\proj\src\lib.rs", true),
+            normalize(r"C:\proj\src\main.rs", true)
+        );
+    }
+
+    #[test]
+    fn test_unix_paths_need_not_exist() {
+        assert_eq!(
+            normalize("/no/such/./dir//lib.rs", false),
+            "/no/such/dir/lib.rs"
+        );
+        assert_eq!(normalize("/proj/src/lib.rs", false), "/proj/src/lib.rs");
+        assert_eq!(normalize("src/lib.rs", false), "src/lib.rs");
+    }
+
+    #[test]
+    fn test_compared_keys_match_stored_keys() {
+        // results are stored under the key of the path the compiler used and
+        // looked up with the key of the path the editor sent
+        let stored = normalize_path(Path::new("/proj/src/./lib.rs"));
+        assert_eq!(stored, normalize_path(Path::new("/proj/src/lib.rs")));
+        assert_ne!(stored, normalize_path(Path::new("/proj/src/main.rs")));
+        assert_eq!(normalize_path(Path::new(&stored)), stored);
+    }
+}
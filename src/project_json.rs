@@ -0,0 +1,460 @@
+//! Workspaces built without cargo, like Bazel or Buck workspaces, described
+//! by a `rust-project.json` in the format of rust-analyzer.
+//!
+//! The file lists the crates of the workspace with their root modules,
+//! editions, `cfg` options and dependencies. The crates that are workspace
+//! members are analyzed, the others are only built, once, so that members
+//! can depend on them.
+
+use std::{
+    collections::HashMap,
+    env::consts::{DLL_PREFIX, DLL_SUFFIX},
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// Name of the description of a workspace, at its root
+pub const FILE_NAME: &str = "rust-project.json";
+
+/// Edition of crates that announce none
+const DEFAULT_EDITION: &str = "2021";
+
+/// Reasons a `rust-project.json` cannot be used
+#[derive(Debug)]
+pub enum ProjectJsonError {
+    Read(io::Error),
+    Parse(serde_json::Error),
+    /// A dependency refers to a crate index out of range
+    UnknownCrate {
+        dependent: usize,
+        dependency: usize,
+    },
+    /// The crate depends on itself, directly or not
+    Cycle {
+        krate: usize,
+    },
+}
+
+impl fmt::Display for ProjectJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(e) => write!(f, "cannot read {FILE_NAME}: {e}"),
+            Self::Parse(e) => write!(f, "invalid {FILE_NAME}: {e}"),
+            Self::UnknownCrate {
+                dependent,
+                dependency,
+            } => write!(
+                f,
+                "crate {dependent} of {FILE_NAME} depends on crate {dependency}, which does not \
+                 exist"
+            ),
+            Self::Cycle { krate } => {
+                write!(f, "crate {krate} of {FILE_NAME} depends on itself")
+            }
+        }
+    }
+}
+
+impl error::Error for ProjectJsonError {}
+
+/// Dependency of a crate, as an index into [`ProjectJson::crates`]
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    #[serde(rename = "crate")]
+    pub krate: usize,
+    /// Name the dependent refers to the crate by
+    pub name: String,
+}
+
+/// Crate of a `rust-project.json`
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProjectCrate {
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Root module, relative to the directory of the `rust-project.json`
+    /// unless it is absolute
+    pub root_module: PathBuf,
+    #[serde(default = "default_edition")]
+    pub edition: String,
+    #[serde(default)]
+    pub deps: Vec<Dependency>,
+    /// Options like `test` or `feature="std"`, passed with `--cfg`
+    #[serde(default)]
+    pub cfg: Vec<String>,
+    /// Whether the crate belongs to the workspace, rather than being a
+    /// dependency from elsewhere
+    #[serde(default = "default_member")]
+    pub is_workspace_member: bool,
+    #[serde(default)]
+    pub is_proc_macro: bool,
+}
+
+fn default_edition() -> String {
+    DEFAULT_EDITION.to_owned()
+}
+
+const fn default_member() -> bool {
+    true
+}
+
+/// Kind of output of a crate, passed with `--crate-type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrateType {
+    Lib,
+    Bin,
+    ProcMacro,
+}
+
+impl CrateType {
+    const fn flag(self) -> &'static str {
+        match self {
+            Self::Lib => "--crate-type=rlib",
+            Self::Bin => "--crate-type=bin",
+            Self::ProcMacro => "--crate-type=proc-macro",
+        }
+    }
+}
+
+/// Crate graph of a `rust-project.json`
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProjectJson {
+    pub crates: Vec<ProjectCrate>,
+    /// Indices of the crates, each after its dependencies
+    #[serde(skip)]
+    order: Vec<usize>,
+}
+
+impl ProjectJson {
+    /// Description of the workspace at `root`, if it has one.
+    pub fn discover(root: &Path) -> Option<Result<Self, ProjectJsonError>> {
+        let path = root.join(FILE_NAME);
+        path.is_file().then(|| Self::load(&path))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ProjectJsonError> {
+        let text = fs::read_to_string(path).map_err(ProjectJsonError::Read)?;
+        Self::parse(&text, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Crate graph of `text`, with root modules resolved against `root`.
+    pub fn parse(text: &str, root: &Path) -> Result<Self, ProjectJsonError> {
+        let mut project: Self = serde_json::from_str(text).map_err(ProjectJsonError::Parse)?;
+        for krate in &mut project.crates {
+            krate.root_module = root.join(&krate.root_module);
+        }
+        project.order = project.build_order()?;
+        Ok(project)
+    }
+
+    /// Indices of the crates, each after its dependencies.
+    fn build_order(&self) -> Result<Vec<usize>, ProjectJsonError> {
+        /// Crates not visited yet, being visited, and visited
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Mark {
+            New,
+            Open,
+            Done,
+        }
+        fn visit(
+            project: &ProjectJson,
+            krate: usize,
+            marks: &mut [Mark],
+            order: &mut Vec<usize>,
+        ) -> Result<(), ProjectJsonError> {
+            match marks[krate] {
+                Mark::Done => return Ok(()),
+                Mark::Open => return Err(ProjectJsonError::Cycle { krate }),
+                Mark::New => {}
+            }
+            marks[krate] = Mark::Open;
+            for dep in &project.crates[krate].deps {
+                if dep.krate >= project.crates.len() {
+                    return Err(ProjectJsonError::UnknownCrate {
+                        dependent: krate,
+                        dependency: dep.krate,
+                    });
+                }
+                visit(project, dep.krate, marks, order)?;
+            }
+            marks[krate] = Mark::Done;
+            order.push(krate);
+            Ok(())
+        }
+
+        let mut marks = vec![Mark::New; self.crates.len()];
+        let mut order = Vec::with_capacity(self.crates.len());
+        for krate in 0..self.crates.len() {
+            visit(self, krate, &mut marks, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// Name the compiler gives crate `index`: its display name, else the
+    /// name of its root module, as an identifier.
+    #[must_use]
+    pub fn crate_name(&self, index: usize) -> String {
+        let krate = &self.crates[index];
+        let name = krate.display_name.clone().unwrap_or_else(|| {
+            krate.root_module.file_stem().map_or_else(
+                || format!("crate{index}"),
+                |stem| stem.to_string_lossy().into_owned(),
+            )
+        });
+        name.replace(|c: char| !c.is_alphanumeric() && c != '_', "_")
+    }
+
+    /// Kind of crate `index`: a procedural macro if it is marked as one, a
+    /// binary if its root module is a `main.rs` or in a `bin` directory, else
+    /// a library.
+    #[must_use]
+    pub fn crate_type(&self, index: usize) -> CrateType {
+        let krate = &self.crates[index];
+        let root = &krate.root_module;
+        if krate.is_proc_macro {
+            CrateType::ProcMacro
+        } else if root.file_name().is_some_and(|name| name == "main.rs")
+            || root
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == "bin")
+        {
+            CrateType::Bin
+        } else {
+            CrateType::Lib
+        }
+    }
+
+    /// Indices of the workspace members.
+    pub fn members(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.crates.len()).filter(|&index| self.crates[index].is_workspace_member)
+    }
+
+    /// Workspace member whose root module is in the innermost directory
+    /// containing `path`.
+    #[must_use]
+    pub fn member_of(&self, path: &Path) -> Option<usize> {
+        self.members()
+            .filter_map(|index| {
+                let dir = self.crates[index].root_module.parent()?;
+                path.starts_with(dir)
+                    .then(|| (dir.components().count(), index))
+            })
+            .max_by_key(|(depth, _)| *depth)
+            .map(|(_, index)| index)
+    }
+
+    /// The crates `analyzed` and their dependencies, each after its
+    /// dependencies.
+    #[must_use]
+    pub fn needed(&self, analyzed: &[usize]) -> Vec<usize> {
+        let mut needed = vec![false; self.crates.len()];
+        let mut pending = analyzed.to_vec();
+        while let Some(index) = pending.pop() {
+            if !needed[index] {
+                needed[index] = true;
+                pending.extend(self.crates[index].deps.iter().map(|dep| dep.krate));
+            }
+        }
+        self.order
+            .iter()
+            .copied()
+            .filter(|&index| needed[index])
+            .collect()
+    }
+
+    /// Whether crate `index` is built rather than analyzed: crates from
+    /// elsewhere and procedural macros, which dependents load.
+    #[must_use]
+    pub fn is_built(&self, index: usize) -> bool {
+        let krate = &self.crates[index];
+        !krate.is_workspace_member || krate.is_proc_macro
+    }
+
+    /// File name of the output of crate `index` that dependents use: the
+    /// metadata of analyzed libraries, the library of built ones.
+    #[must_use]
+    pub fn artifact(&self, index: usize) -> String {
+        let name = self.crate_name(index);
+        match self.crate_type(index) {
+            CrateType::ProcMacro => format!("{DLL_PREFIX}{name}{DLL_SUFFIX}"),
+            _ if self.is_built(index) => format!("lib{name}.rlib"),
+            _ => format!("lib{name}.rmeta"),
+        }
+    }
+
+    /// Compiler arguments of crate `index`, with outputs and dependencies in
+    /// `out_dir`: metadata only if it is analyzed, else a library.
+    #[must_use]
+    pub fn rustc_args(&self, index: usize, out_dir: &Path) -> Vec<String> {
+        let krate = &self.crates[index];
+        let crate_type = self.crate_type(index);
+        let emit = if self.is_built(index) {
+            "link"
+        } else {
+            "metadata"
+        };
+        let mut args = vec![
+            format!("--crate-name={}", self.crate_name(index)),
+            format!("--edition={}", krate.edition),
+            crate_type.flag().to_owned(),
+            format!("--emit={emit}"),
+            format!("--out-dir={}", out_dir.display()),
+            format!("-Ldependency={}", out_dir.display()),
+        ];
+        for cfg in &krate.cfg {
+            args.extend(["--cfg".to_owned(), cfg.clone()]);
+        }
+        if crate_type == CrateType::ProcMacro {
+            args.extend(["--extern".to_owned(), "proc_macro".to_owned()]);
+        }
+        let mut externs: HashMap<&str, String> = HashMap::new();
+        for dep in &krate.deps {
+            let artifact = out_dir.join(self.artifact(dep.krate));
+            externs.insert(&dep.name, artifact.display().to_string());
+        }
+        let mut externs: Vec<_> = externs.into_iter().collect();
+        externs.sort();
+        for (name, artifact) in externs {
+            args.extend(["--extern".to_owned(), format!("{name}={artifact}")]);
+        }
+        args.push(krate.root_module.display().to_string());
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary using a library of the workspace, which uses a library from
+    /// elsewhere
+    const PROJECT: &str = r#"{
+        "sysroot_src": "/sysroot/lib/rustlib/src/rust/library",
+        "crates": [
+            {
+                "display_name": "app",
+                "root_module": "app/src/main.rs",
+                "edition": "2024",
+                "deps": [{ "crate": 1, "name": "core_lib" }],
+                "cfg": ["feature=\"fast\""],
+                "is_workspace_member": true
+            },
+            {
+                "display_name": "core-lib",
+                "root_module": "core/lib.rs",
+                "edition": "2021",
+                "deps": [{ "crate": 2, "name": "either" }]
+            },
+            {
+                "display_name": "either",
+                "root_module": "/registry/either/src/lib.rs",
+                "edition": "2018",
+                "deps": [],
+                "is_workspace_member": false
+            }
+        ]
+    }"#;
+
+    fn project() -> ProjectJson {
+        ProjectJson::parse(PROJECT, Path::new("/ws")).unwrap()
+    }
+
+    #[test]
+    fn test_crates_come_after_their_dependencies() {
+        let project = project();
+        assert_eq!(project.needed(&[0]), vec![2, 1, 0]);
+        assert_eq!(project.needed(&[1]), vec![2, 1]);
+        assert_eq!(project.members().collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_crate_names_and_types() {
+        let project = project();
+        assert_eq!(project.crate_name(1), "core_lib");
+        assert_eq!(project.crate_type(0), CrateType::Bin);
+        assert_eq!(project.crate_type(1), CrateType::Lib);
+        assert_eq!(
+            project.crates[1].root_module,
+            Path::new("/ws/core/lib.rs"),
+            "relative to the directory of the file"
+        );
+        assert_eq!(
+            project.crates[2].root_module,
+            Path::new("/registry/either/src/lib.rs")
+        );
+        assert_eq!(project.member_of(Path::new("/ws/core/sub/mod.rs")), Some(1));
+        assert_eq!(
+            project.member_of(Path::new("/registry/either/src/lib.rs")),
+            None,
+            "only members"
+        );
+    }
+
+    #[test]
+    fn test_rustc_args() {
+        let project = project();
+        let out_dir = Path::new("/out");
+        assert_eq!(
+            project.rustc_args(0, out_dir),
+            vec![
+                "--crate-name=app",
+                "--edition=2024",
+                "--crate-type=bin",
+                "--emit=metadata",
+                "--out-dir=/out",
+                "-Ldependency=/out",
+                "--cfg",
+                "feature=\"fast\"",
+                "--extern",
+                "core_lib=/out/libcore_lib.rmeta",
+                "/ws/app/src/main.rs",
+            ]
+        );
+        let either = project.rustc_args(2, out_dir);
+        assert!(either.contains(&"--emit=link".to_owned()), "{either:?}");
+        assert!(
+            project
+                .rustc_args(1, out_dir)
+                .contains(&"either=/out/libeither.rlib".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_defaults() {
+        let project = ProjectJson::parse(
+            r#"{ "crates": [{ "root_module": "lib.rs" }] }"#,
+            Path::new(""),
+        )
+        .unwrap();
+        assert_eq!(project.crates[0].edition, DEFAULT_EDITION);
+        assert!(project.crates[0].is_workspace_member);
+        assert_eq!(project.crate_name(0), "lib");
+    }
+
+    #[test]
+    fn test_invalid_graphs() {
+        let unknown =
+            r#"{ "crates": [{ "root_module": "a.rs", "deps": [{ "crate": 3, "name": "b" }] }] }"#;
+        assert!(matches!(
+            ProjectJson::parse(unknown, Path::new("")),
+            Err(ProjectJsonError::UnknownCrate {
+                dependent: 0,
+                dependency: 3
+            })
+        ));
+        let cycle = r#"{ "crates": [
+            { "root_module": "a.rs", "deps": [{ "crate": 1, "name": "b" }] },
+            { "root_module": "b.rs", "deps": [{ "crate": 0, "name": "a" }] }
+        ] }"#;
+        assert!(matches!(
+            ProjectJson::parse(cycle, Path::new("")),
+            Err(ProjectJsonError::Cycle { .. })
+        ));
+        assert!(matches!(
+            ProjectJson::parse("{}", Path::new("")),
+            Err(ProjectJsonError::Parse(_))
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::models::{Function, MirDecl, MirStatement, MirTerminator, Range};
+use crate::models::{CharOffset, Item, MirDecl, MirRval, MirStatement, MirTerminator, Range};
 
 #[must_use]
 pub fn is_super_range(r1: Range, r2: Range) -> bool {
@@ -19,17 +19,29 @@ pub fn common_range(r1: Range, r2: Range) -> Option<Range> {
     Range::new(from, until)
 }
 
+/// Parts covered by at least two of `ranges`, merged and sorted.
 #[must_use]
 pub fn common_ranges(ranges: &[Range]) -> Vec<Range> {
-    let mut common_ranges = Vec::new();
-    for i in 0..ranges.len() {
-        for j in i + 1..ranges.len() {
-            if let Some(common) = common_range(ranges[i], ranges[j]) {
-                common_ranges.push(common);
-            }
+    // at the same location, ends come before starts: touching ranges have
+    // nothing in common
+    let mut bounds: Vec<(CharOffset, i32)> = ranges
+        .iter()
+        .flat_map(|range| [(range.from(), 1), (range.until(), -1)])
+        .collect();
+    bounds.sort_unstable();
+
+    let mut common = Vec::new();
+    let mut depth = 0;
+    let mut start = None;
+    for (loc, delta) in bounds {
+        depth += delta;
+        if 2 <= depth {
+            start.get_or_insert(loc);
+        } else if let Some(from) = start.take() {
+            common.extend(Range::new(from, loc));
         }
     }
-    eliminated_ranges(common_ranges)
+    eliminated_ranges(common)
 }
 
 /// merge two ranges, result is superset of two ranges
@@ -45,57 +57,137 @@ pub fn merge_ranges(r1: Range, r2: Range) -> Option<Range> {
 }
 
 /// eliminate common ranges and flatten ranges
+///
+/// Overlapping and adjacent ranges are merged, the result is sorted.
 #[must_use]
 pub fn eliminated_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
-    let mut i = 0;
-    'outer: while i < ranges.len() {
-        let mut j = 0;
-        while j < ranges.len() {
-            if i != j
-                && let Some(merged) = merge_ranges(ranges[i], ranges[j])
-            {
-                ranges[i] = merged;
-                ranges.remove(j);
-                continue 'outer;
-            }
-            j += 1;
+    ranges.sort_unstable_by_key(|range| range.from());
+    let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        if let Some(last) = merged.last_mut()
+            && let Some(union) = merge_ranges(*last, range)
+        {
+            *last = union;
+        } else {
+            merged.push(range);
         }
-        i += 1;
     }
-    ranges
+    merged
 }
 
+/// `ranges` sorted by start and end, without duplicates.
+///
+/// Unlike [`eliminated_ranges`], overlapping ranges are kept apart.
 #[must_use]
-pub fn exclude_ranges(mut from: Vec<Range>, excludes: &[Range]) -> Vec<Range> {
-    let mut i = 0;
-    'outer: while i < from.len() {
-        let mut j = 0;
-        while j < excludes.len() {
-            if let Some(common) = common_range(from[i], excludes[j]) {
-                if let Some(r) = Range::new(from[i].from(), common.from() - 1) {
-                    from.push(r);
-                }
-                if let Some(r) = Range::new(common.until() + 1, from[i].until()) {
-                    from.push(r);
-                }
-                from.remove(i);
-                continue 'outer;
+pub fn sorted_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+    ranges.sort_unstable_by_key(|range| (range.from(), range.until()));
+    ranges.dedup();
+    ranges
+}
+
+/// Static interval tree: ranges sorted by start, where the middle of each
+/// slice is the root of its subtree and knows the largest end in it.
+struct IntervalTree {
+    /// Ranges with their index in the input
+    ranges: Vec<(Range, usize)>,
+    max_until: Vec<CharOffset>,
+}
+
+impl IntervalTree {
+    fn new(ranges: &[Range]) -> Self {
+        let mut ranges: Vec<_> = ranges.iter().copied().zip(0..).collect();
+        ranges.sort_unstable_by_key(|(range, _)| range.from());
+        let max_until = ranges.iter().map(|(range, _)| range.until()).collect();
+        let mut tree = Self { ranges, max_until };
+        tree.fill_max_until(0, tree.ranges.len());
+        tree
+    }
+
+    fn fill_max_until(&mut self, lo: usize, hi: usize) -> Option<CharOffset> {
+        if hi <= lo {
+            return None;
+        }
+        let mid = lo.midpoint(hi);
+        let left = self.fill_max_until(lo, mid);
+        let right = self.fill_max_until(mid + 1, hi);
+        let max = [left, right]
+            .into_iter()
+            .flatten()
+            .fold(self.max_until[mid], CharOffset::max);
+        self.max_until[mid] = max;
+        Some(max)
+    }
+
+    /// Input indices of the ranges sharing a nonempty part with `range`.
+    fn overlapping(&self, range: Range) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.collect_overlapping(range, 0, self.ranges.len(), &mut found);
+        found
+    }
+
+    fn collect_overlapping(&self, range: Range, lo: usize, hi: usize, found: &mut Vec<usize>) {
+        if hi <= lo {
+            return;
+        }
+        let mid = lo.midpoint(hi);
+        if self.max_until[mid] <= range.from() {
+            return;
+        }
+        self.collect_overlapping(range, lo, mid, found);
+        let (candidate, index) = self.ranges[mid];
+        if candidate.from() < range.until() {
+            if range.from() < candidate.until() {
+                found.push(index);
             }
-            j += 1;
+            self.collect_overlapping(range, mid + 1, hi, found);
+        }
+    }
+}
+
+/// Cut `exclude` out of `pieces`, sorted disjoint parts of a range.
+///
+/// A piece sharing a nonempty part with `exclude` loses one more location on
+/// each side of it; pieces merely touching `exclude` are kept.
+fn cut(pieces: &mut Vec<Range>, exclude: Range) {
+    let first = pieces.partition_point(|piece| piece.until() <= exclude.from());
+    let last = first + pieces[first..].partition_point(|piece| piece.from() < exclude.until());
+    if first == last {
+        return;
+    }
+    let left = Range::new(pieces[first].from(), exclude.from() - 1);
+    let right = Range::new(exclude.until() + 1, pieces[last - 1].until());
+    pieces.splice(first..last, left.into_iter().chain(right));
+}
+
+/// Remove `excludes` from the ranges of `from`, merging and sorting the rest.
+///
+/// Excludes are applied in order, each to the parts left by the previous
+/// ones, see [`cut`].
+#[must_use]
+pub fn exclude_ranges(from: Vec<Range>, excludes: &[Range]) -> Vec<Range> {
+    let tree = IntervalTree::new(excludes);
+    let mut remaining = Vec::with_capacity(from.len());
+    for range in from {
+        let mut overlapping = tree.overlapping(range);
+        overlapping.sort_unstable();
+        let mut pieces = vec![range];
+        for index in overlapping {
+            cut(&mut pieces, excludes[index]);
         }
-        i += 1;
+        remaining.extend(pieces);
     }
-    eliminated_ranges(from)
+    eliminated_ranges(remaining)
 }
 
 pub trait MirVisitor {
-    fn visit_func(&mut self, _func: &Function) {}
+    fn visit_item(&mut self, _item: &Item) {}
     fn visit_decl(&mut self, _decl: &MirDecl) {}
     fn visit_stmt(&mut self, _stmt: &MirStatement) {}
     fn visit_term(&mut self, _term: &MirTerminator) {}
 }
-pub fn mir_visit(func: &Function, visitor: &mut impl MirVisitor) {
-    visitor.visit_func(func);
+pub fn mir_visit(item: &Item, visitor: &mut impl MirVisitor) {
+    visitor.visit_item(item);
+    let func = item.function();
     for decl in &func.decls {
         visitor.visit_decl(decl);
     }
@@ -108,3 +200,320 @@ pub fn mir_visit(func: &Function, visitor: &mut impl MirVisitor) {
         }
     }
 }
+
+/// Smallest range containing every range of `item` a cursor can select
+/// something at: its signature, the declarations of its variables, its
+/// statements and its terminators. `None` if it has none.
+#[must_use]
+pub fn function_bounds(item: &Item) -> Option<Range> {
+    let func = item.function();
+    let decls = func.decls.iter().filter_map(|decl| match decl {
+        MirDecl::User { span, .. } => Some(*span),
+        MirDecl::Other { .. } => None,
+    });
+    let statements = func
+        .basic_blocks
+        .iter()
+        .flat_map(|bb| &bb.statements)
+        .flat_map(|stmt| {
+            let rval = match stmt {
+                MirStatement::Assign {
+                    rval: Some(MirRval::Move { range, .. } | MirRval::Borrow { range, .. }),
+                    ..
+                } => Some(*range),
+                _ => None,
+            };
+            [Some(stmt.range()), rval]
+        })
+        .flatten();
+    let terminators = func
+        .basic_blocks
+        .iter()
+        .filter_map(|bb| bb.terminator.as_ref())
+        .flat_map(|term| {
+            let (range, receiver) = match term {
+                MirTerminator::Call {
+                    range, receiver, ..
+                } => (*range, receiver.map(|receiver| receiver.range)),
+                MirTerminator::Drop { .. } | MirTerminator::Other { .. } => (None, None),
+            };
+            [Some(term.range()), range, receiver]
+        })
+        .flatten();
+    item.span()
+        .into_iter()
+        .chain(decls)
+        .chain(statements)
+        .chain(terminators)
+        .reduce(|bounds, range| {
+            Range::new(
+                bounds.from().min(range.from()),
+                bounds.until().max(range.until()),
+            )
+            .unwrap_or(bounds)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FnLocal, ItemKind, MirBasicBlock};
+
+    /// The pairwise implementations replaced by the sweeps, as reference.
+    mod pairwise {
+        use super::super::{common_range, merge_ranges};
+        use crate::models::Range;
+
+        pub fn common_ranges(ranges: &[Range]) -> Vec<Range> {
+            let mut common_ranges = Vec::new();
+            for i in 0..ranges.len() {
+                for j in i + 1..ranges.len() {
+                    if let Some(common) = common_range(ranges[i], ranges[j]) {
+                        common_ranges.push(common);
+                    }
+                }
+            }
+            eliminated_ranges(common_ranges)
+        }
+
+        pub fn eliminated_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+            let mut i = 0;
+            'outer: while i < ranges.len() {
+                let mut j = 0;
+                while j < ranges.len() {
+                    if i != j
+                        && let Some(merged) = merge_ranges(ranges[i], ranges[j])
+                    {
+                        ranges[i] = merged;
+                        ranges.remove(j);
+                        continue 'outer;
+                    }
+                    j += 1;
+                }
+                i += 1;
+            }
+            ranges
+        }
+
+        pub fn exclude_ranges(mut from: Vec<Range>, excludes: &[Range]) -> Vec<Range> {
+            let mut i = 0;
+            'outer: while i < from.len() {
+                let mut j = 0;
+                while j < excludes.len() {
+                    if let Some(common) = common_range(from[i], excludes[j]) {
+                        if let Some(r) = Range::new(from[i].from(), common.from() - 1) {
+                            from.push(r);
+                        }
+                        if let Some(r) = Range::new(common.until() + 1, from[i].until()) {
+                            from.push(r);
+                        }
+                        from.remove(i);
+                        continue 'outer;
+                    }
+                    j += 1;
+                }
+                i += 1;
+            }
+            eliminated_ranges(from)
+        }
+    }
+
+    fn range(from: u32, until: u32) -> Range {
+        Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+    }
+
+    fn sorted(mut ranges: Vec<Range>) -> Vec<Range> {
+        ranges.sort_by_key(|range| (range.from(), range.until()));
+        ranges
+    }
+
+    /// Xorshift generator, reproducible without a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, bound: u32) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            u32::try_from(self.0 % u64::from(bound)).unwrap()
+        }
+
+        fn ranges(&mut self, count: u32, space: u32, max_len: u32) -> Vec<Range> {
+            (0..self.below(count))
+                .map(|_| {
+                    let from = self.below(space);
+                    range(from, from + 1 + self.below(max_len))
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_eliminated_ranges() {
+        assert_eq!(eliminated_ranges(Vec::new()), Vec::new());
+        assert_eq!(
+            eliminated_ranges(vec![range(5, 10), range(0, 5)]),
+            vec![range(0, 10)],
+            "adjacent ranges are merged"
+        );
+        assert_eq!(
+            eliminated_ranges(vec![range(0, 10), range(2, 4)]),
+            vec![range(0, 10)],
+            "nested ranges are merged"
+        );
+        assert_eq!(
+            eliminated_ranges(vec![range(3, 4), range(3, 4)]),
+            vec![range(3, 4)],
+            "identical ranges are merged"
+        );
+        assert_eq!(
+            eliminated_ranges(vec![range(8, 9), range(0, 2), range(1, 3), range(4, 6)]),
+            vec![range(0, 3), range(4, 6), range(8, 9)],
+            "disjoint ranges are kept in order"
+        );
+    }
+
+    #[test]
+    fn test_common_ranges() {
+        assert_eq!(common_ranges(&[range(0, 5), range(5, 10)]), Vec::new());
+        assert_eq!(
+            common_ranges(&[range(0, 10), range(2, 4)]),
+            vec![range(2, 4)]
+        );
+        assert_eq!(
+            common_ranges(&[range(3, 7), range(3, 7)]),
+            vec![range(3, 7)]
+        );
+        assert_eq!(
+            common_ranges(&[range(0, 4), range(2, 6), range(4, 8)]),
+            vec![range(2, 6)],
+            "touching common parts are merged"
+        );
+        assert_eq!(common_ranges(&[range(0, 4)]), Vec::new());
+    }
+
+    #[test]
+    fn test_exclude_ranges() {
+        assert_eq!(
+            exclude_ranges(vec![range(0, 20)], &[range(5, 10)]),
+            vec![range(0, 4), range(11, 20)],
+            "one more location is removed on each side"
+        );
+        assert_eq!(
+            exclude_ranges(vec![range(0, 5)], &[range(5, 8)]),
+            vec![range(0, 5)],
+            "adjacent ranges are kept"
+        );
+        assert_eq!(
+            exclude_ranges(vec![range(2, 4)], &[range(0, 10)]),
+            Vec::new(),
+            "nested ranges are removed"
+        );
+        assert_eq!(
+            exclude_ranges(vec![range(2, 4)], &[range(2, 4)]),
+            Vec::new()
+        );
+        assert_eq!(
+            exclude_ranges(vec![range(0, 20)], &[range(5, 10), range(10, 11)]),
+            vec![range(0, 4), range(11, 20)],
+            "the second exclude only touches what the first left"
+        );
+        assert_eq!(
+            exclude_ranges(vec![range(0, 20)], &[range(10, 11), range(5, 10)]),
+            vec![range(0, 4), range(12, 20)],
+            "excludes apply in order"
+        );
+        assert_eq!(
+            exclude_ranges(vec![range(0, 5), range(3, 10)], &[range(5, 8)]),
+            vec![range(0, 5), range(9, 10)],
+            "ranges are cut before they are merged"
+        );
+    }
+
+    #[test]
+    fn test_sweeps_match_pairwise() {
+        let mut rng = Rng(0x5eed);
+        for _ in 0..2000 {
+            let ranges = rng.ranges(30, 200, 40);
+            let excludes = rng.ranges(30, 200, 20);
+            assert_eq!(
+                common_ranges(&ranges),
+                sorted(pairwise::common_ranges(&ranges)),
+                "common ranges of {ranges:?}"
+            );
+            assert_eq!(
+                eliminated_ranges(ranges.clone()),
+                sorted(pairwise::eliminated_ranges(ranges.clone())),
+                "eliminated ranges of {ranges:?}"
+            );
+            assert_eq!(
+                exclude_ranges(ranges.clone(), &excludes),
+                sorted(pairwise::exclude_ranges(ranges.clone(), &excludes)),
+                "{ranges:?} without {excludes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_function_bounds() {
+        let function = serde_json::from_value(serde_json::json!({
+            "fn_id": 1,
+            "basic_blocks": [],
+            "decls": [],
+        }))
+        .unwrap();
+        let mut item = Item::Function {
+            span: None,
+            kind: ItemKind::Fn,
+            function,
+        };
+        assert_eq!(function_bounds(&item), None);
+
+        let Item::Function { span, .. } = &mut item;
+        *span = Some(range(10, 20));
+        item.function_mut().basic_blocks = vec![MirBasicBlock {
+            statements: vec![MirStatement::Other {
+                range: range(30, 40),
+            }],
+            terminator: Some(MirTerminator::Call {
+                destination_local: FnLocal::new(1, 0),
+                fn_span: range(50, 55),
+                callee: None,
+                call_expr_span: range(50, 60),
+                range: Some(range(25, 60)),
+                receiver: None,
+                borrows_from: Vec::new(),
+            }),
+            is_cleanup: false,
+        }];
+        assert_eq!(function_bounds(&item), Some(range(10, 60)));
+    }
+
+    #[test]
+    fn test_interval_tree_finds_overlapping() {
+        let mut rng = Rng(0xfeed);
+        for _ in 0..500 {
+            let ranges = rng.ranges(40, 300, 50);
+            let tree = IntervalTree::new(&ranges);
+            let from = rng.below(300);
+            let query = range(from, from + 1 + rng.below(60));
+            let mut found = tree.overlapping(query);
+            found.sort_unstable();
+            let expected: Vec<_> = (0..ranges.len())
+                .filter(|&i| common_range(ranges[i], query).is_some())
+                .collect();
+            assert_eq!(
+                found, expected,
+                "ranges of {ranges:?} overlapping {query:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sorted_ranges_drop_duplicates() {
+        assert_eq!(
+            sorted_ranges(vec![range(5, 9), range(0, 4), range(5, 9), range(0, 6)]),
+            vec![range(0, 4), range(0, 6), range(5, 9)]
+        );
+    }
+}
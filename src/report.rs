@@ -0,0 +1,1129 @@
+//! Reports of analysis results: compact summaries printed by
+//! `check --watch`, ownership reports of all variables written by
+//! `check --report`, and decorations of all variables printed by
+//! `check --emit-decorations-for`.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{
+    deco_kind::DecoKind,
+    lsp_decoration::{CalcDecos, Deco},
+    models::{File, MirDecl, Workspace},
+    range_ops,
+    text_conversion::SourceIndex,
+};
+
+/// Number of decorations pointing at possible optimizations in a file, with
+/// every variable selected
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct FileCounts {
+    pub moves: usize,
+    pub shared_mut: usize,
+    pub outlive: usize,
+}
+
+impl FileCounts {
+    const fn count(&mut self, deco: &Deco) {
+        match deco {
+            Deco::Move { .. } => self.moves += 1,
+            Deco::SharedMut { .. } => self.shared_mut += 1,
+            Deco::Outlive { .. } => self.outlive += 1,
+            _ => {}
+        }
+    }
+}
+
+/// Decoration counts by file name
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct Summary {
+    pub files: BTreeMap<String, FileCounts>,
+}
+
+impl Summary {
+    #[must_use]
+    pub fn new(workspace: &Workspace) -> Self {
+        let mut files = BTreeMap::<String, FileCounts>::new();
+        for krate in workspace.crates.values() {
+            for (name, file) in &krate.0 {
+                // functions of the library are counted over their test versions
+                let locals = file
+                    .items_in("")
+                    .flat_map(|item| &item.function().decls)
+                    .filter_map(|decl| match decl {
+                        MirDecl::User { local, .. } => Some(*local),
+                        MirDecl::Other { .. } => None,
+                    });
+                let captures: Vec<_> = file
+                    .items_in("")
+                    .flat_map(|item| item.function().captures.iter().copied())
+                    .collect();
+                let mut calc = CalcDecos::new(locals)
+                    .with_captures(&captures)
+                    .with_types(&file.types);
+                for item in file.items_in("") {
+                    range_ops::mir_visit(item, &mut calc);
+                }
+                // a file analyzed for several crates is counted once
+                let mut counts = FileCounts::default();
+                for deco in &calc.decorations() {
+                    counts.count(deco);
+                }
+                files.insert(name.clone(), counts);
+            }
+        }
+        Self { files }
+    }
+
+    /// The summary with file names relative to `root` where possible.
+    #[must_use]
+    pub fn relative_to(self, root: &Path) -> Self {
+        let files = self
+            .files
+            .into_iter()
+            .map(|(name, counts)| {
+                let relative = Path::new(&name)
+                    .strip_prefix(root)
+                    .map_or(name.clone(), |path| path.display().to_string());
+                (relative, counts)
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// Files whose counts differ from `previous`, in file name order.
+    ///
+    /// Files missing on one side count as having no decorations there.
+    #[must_use]
+    pub fn diff(&self, previous: &Self) -> Vec<FileDiff> {
+        let mut names: Vec<_> = self.files.keys().chain(previous.files.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let before = previous.files.get(name).copied().unwrap_or_default();
+                let after = self.files.get(name).copied().unwrap_or_default();
+                (before != after).then(|| FileDiff {
+                    file: PathBuf::from(name),
+                    before,
+                    after,
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>7} {:>11} {:>8}  file",
+            "moves", "shared-mut", "outlive"
+        )?;
+        for (name, counts) in &self.files {
+            writeln!(
+                f,
+                "{:>7} {:>11} {:>8}  {name}",
+                counts.moves, counts.shared_mut, counts.outlive
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Change of the counts of a file between two analyses
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileDiff {
+    pub file: PathBuf,
+    pub before: FileCounts,
+    pub after: FileCounts,
+}
+
+impl fmt::Display for FileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes: Vec<_> = [
+            ("moves", self.before.moves, self.after.moves),
+            ("shared-mut", self.before.shared_mut, self.after.shared_mut),
+            ("outlive", self.before.outlive, self.after.outlive),
+        ]
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(kind, before, after)| format!("{kind} {before} -> {after}"))
+        .collect();
+        write!(f, "{}: {}", self.file.display(), changes.join(", "))
+    }
+}
+
+/// Format of an ownership report
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, clap::ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    #[value(name = "md")]
+    Markdown,
+    /// Single file with inline styles
+    Html,
+}
+
+/// Kinds of decorations in ownership reports, each highlighted over the
+/// preceding ones where they overlap
+const REPORTED_KINDS: [&str; 6] = [
+    "lifetime",
+    "imm_borrow",
+    "mut_borrow",
+    "move",
+    "shared_mut",
+    "outlive",
+];
+
+/// Zero-based line and column
+type Position = (u32, u32);
+
+/// Decoration of a variable in an ownership report
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReportEntry {
+    /// Decoration type, like `move`
+    pub kind: &'static str,
+    pub from: Position,
+    pub until: Position,
+    pub hover_text: String,
+}
+
+impl fmt::Display for ReportEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}:{}-{}:{}: {}",
+            self.kind,
+            self.from.0 + 1,
+            self.from.1 + 1,
+            self.until.0 + 1,
+            self.until.1 + 1,
+            self.hover_text.replace('\n', " ")
+        )
+    }
+}
+
+/// User variable with its decorations, computed with all variables of its
+/// function selected
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VariableReport {
+    pub name: String,
+    pub ty: String,
+    pub declared: Position,
+    /// Decorations in the order of their positions
+    pub entries: Vec<ReportEntry>,
+}
+
+impl VariableReport {
+    /// First and last line of the declaration and the decorations
+    fn lines(&self) -> (u32, u32) {
+        self.entries.iter().fold(
+            (self.declared.0, self.declared.0),
+            |(first, last), entry| (first.min(entry.from.0), last.max(entry.until.0)),
+        )
+    }
+}
+
+/// Part of an excerpt line, highlighted as the decoration type if any
+type Segment = (Option<&'static str>, String);
+
+/// Source line of an excerpt, split where its highlighting changes
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct ExcerptLine {
+    /// One-based line number
+    number: u32,
+    segments: Vec<Segment>,
+}
+
+/// Source lines of a variable, at most the maximum number of lines
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Excerpt {
+    lines: Vec<ExcerptLine>,
+    /// Lines left out after the maximum
+    omitted: u32,
+}
+
+/// Variables of a file with the source lines they are highlighted in
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct FileReport {
+    /// Source lines, without line endings
+    pub lines: Vec<String>,
+    /// Variables in the order of their declarations
+    pub variables: Vec<VariableReport>,
+}
+
+impl FileReport {
+    fn new(file: &File, source: &str) -> Self {
+        let variables = variable_reports(file, source, |kind| REPORTED_KINDS.contains(&kind));
+        let lines = source
+            .replace('\r', "")
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        Self { lines, variables }
+    }
+
+    /// Lines of `variable` with its decorations highlighted, the first
+    /// `max_lines` of them.
+    fn excerpt(&self, variable: &VariableReport, max_lines: usize) -> Excerpt {
+        let (first, last) = variable.lines();
+        let count = last - first + 1;
+        let shown = u32::try_from(max_lines).unwrap_or(u32::MAX).min(count);
+        let lines = (first..first + shown)
+            .map(|line| {
+                let text = self.lines.get(line as usize).map_or("", String::as_str);
+                ExcerptLine {
+                    number: line + 1,
+                    segments: highlight(text, line, &variable.entries),
+                }
+            })
+            .collect();
+        Excerpt {
+            lines,
+            omitted: count - shown,
+        }
+    }
+}
+
+/// User variables of `file` in the order of their declarations, with their
+/// decorations of the types `keep` accepts, computed with all variables of
+/// their function selected.
+fn variable_reports(file: &File, source: &str, keep: impl Fn(&str) -> bool) -> Vec<VariableReport> {
+    let index = SourceIndex::new(source);
+    let mut variables = Vec::new();
+    for item in file.items_in("") {
+        let decls: Vec<_> = item
+            .function()
+            .decls
+            .iter()
+            .filter_map(|decl| match decl {
+                MirDecl::User {
+                    local,
+                    name,
+                    span,
+                    ty,
+                    ..
+                } => Some((*local, name, span, ty)),
+                MirDecl::Other { .. } => None,
+            })
+            .collect();
+        let mut calc = CalcDecos::new(decls.iter().map(|(local, ..)| *local))
+            .with_types(&file.types)
+            .with_source(source);
+        range_ops::mir_visit(item, &mut calc);
+        let decorations = calc.decorations();
+        for (local, name, span, ty) in decls {
+            let (lifetimes, mut entries): (Vec<_>, Vec<_>) = decorations
+                .iter()
+                .filter(|deco| deco.local() == local && keep(deco.kind()))
+                .map(|deco| ReportEntry {
+                    kind: deco.kind(),
+                    from: index.char_to_line_col(deco.range().from()).into(),
+                    until: index.char_to_line_col(deco.range().until()).into(),
+                    hover_text: deco.hover_text().to_owned(),
+                })
+                .partition(|entry| entry.kind == "lifetime");
+            entries.extend(lifetime_span(lifetimes));
+            entries.sort_by_key(|entry| (entry.from, entry.until));
+            variables.push(VariableReport {
+                name: name.clone(),
+                ty: ty.name(&file.types).to_owned(),
+                declared: index.char_to_line_col(span.from()).into(),
+                entries,
+            });
+        }
+    }
+    variables.sort_by_key(|variable| variable.declared);
+    variables
+}
+
+/// Single entry from the start of the first to the end of the last of the
+/// `lifetimes` of a variable, which are split at every statement.
+fn lifetime_span(lifetimes: Vec<ReportEntry>) -> Option<ReportEntry> {
+    lifetimes.into_iter().reduce(|span, entry| ReportEntry {
+        from: span.from.min(entry.from),
+        until: span.until.max(entry.until),
+        ..span
+    })
+}
+
+/// Note on the number of lines left out of an excerpt.
+fn omitted_note(omitted: u32) -> String {
+    if omitted == 1 {
+        "1 more line not shown".to_owned()
+    } else {
+        format!("{omitted} more lines not shown")
+    }
+}
+
+/// `text` of `line` split into segments with the decoration type of
+/// `entries` highlighting them.
+fn highlight(text: &str, line: u32, entries: &[ReportEntry]) -> Vec<Segment> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut kinds = vec![None; chars.len()];
+    for entry in entries {
+        if line < entry.from.0 || entry.until.0 < line {
+            continue;
+        }
+        let priority = REPORTED_KINDS.iter().position(|kind| *kind == entry.kind);
+        let start = if line == entry.from.0 {
+            entry.from.1
+        } else {
+            0
+        } as usize;
+        let end = if line == entry.until.0 {
+            entry.until.1 as usize
+        } else {
+            chars.len()
+        };
+        for kind in kinds.iter_mut().take(end).skip(start) {
+            *kind = (*kind).max(priority);
+        }
+    }
+    let mut segments: Vec<Segment> = Vec::new();
+    for (c, priority) in chars.into_iter().zip(kinds) {
+        let kind = priority.map(|priority| REPORTED_KINDS[priority]);
+        match segments.last_mut() {
+            Some((last, text)) if *last == kind => text.push(c),
+            _ => segments.push((kind, c.to_string())),
+        }
+    }
+    segments
+}
+
+/// User variables with their lifetimes, borrows, moves, shared-mut
+/// conflicts and outlive requirements, by file name
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct OwnershipReport {
+    pub files: BTreeMap<String, FileReport>,
+}
+
+impl OwnershipReport {
+    /// Report of the files in `workspace`, with their text from
+    /// `read_source`. Files it returns no text for are left out.
+    #[must_use]
+    pub fn new(workspace: &Workspace, read_source: impl Fn(&str) -> Option<String>) -> Self {
+        let mut files = BTreeMap::new();
+        for krate in workspace.crates.values() {
+            for (name, file) in &krate.0 {
+                // a file analyzed for several crates is reported once
+                if files.contains_key(name) {
+                    continue;
+                }
+                let Some(source) = read_source(name) else {
+                    log::warn!("cannot read {name}, it is left out of the report");
+                    continue;
+                };
+                files.insert(name.clone(), FileReport::new(file, &source));
+            }
+        }
+        Self { files }
+    }
+
+    /// The report with file names relative to `root` where possible.
+    #[must_use]
+    pub fn relative_to(self, root: &Path) -> Self {
+        let files = self
+            .files
+            .into_iter()
+            .map(|(name, file)| {
+                let relative = Path::new(&name)
+                    .strip_prefix(root)
+                    .map_or(name.clone(), |path| path.display().to_string());
+                (relative, file)
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// The report as a document in `format`, with at most `max_lines`
+    /// source lines per variable.
+    #[must_use]
+    pub fn render(&self, format: ReportFormat, max_lines: usize) -> String {
+        match format {
+            ReportFormat::Markdown => Markdown(self, max_lines).to_string(),
+            ReportFormat::Html => Html(self, max_lines).to_string(),
+        }
+    }
+}
+
+/// Ownership report with the maximum number of source lines per variable,
+/// as Markdown
+struct Markdown<'a>(&'a OwnershipReport, usize);
+
+impl fmt::Display for Markdown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(report, max_lines) = self;
+        writeln!(f, "# Ownership report")?;
+        for (name, file) in &report.files {
+            writeln!(f, "\n## {name}")?;
+            for variable in &file.variables {
+                writeln!(
+                    f,
+                    "\n### `{}`: `{}`, line {}\n",
+                    variable.name,
+                    variable.ty,
+                    variable.declared.0 + 1
+                )?;
+                for entry in &variable.entries {
+                    writeln!(f, "- {entry}")?;
+                }
+                let excerpt = file.excerpt(variable, *max_lines);
+                let width = excerpt
+                    .lines
+                    .last()
+                    .map_or(1, |line| line.number.to_string().len());
+                writeln!(f, "\n```text")?;
+                for line in &excerpt.lines {
+                    let text: String = line
+                        .segments
+                        .iter()
+                        .map(|(_, text)| text.as_str())
+                        .collect();
+                    writeln!(f, "{:>width$} | {text}", line.number)?;
+                    // lifetimes span most lines, only the other decorations
+                    // are marked
+                    let marks: String = line
+                        .segments
+                        .iter()
+                        .map(|(kind, text)| {
+                            let mark = if kind.is_some_and(|kind| kind != "lifetime") {
+                                "^"
+                            } else {
+                                " "
+                            };
+                            mark.repeat(text.chars().count())
+                        })
+                        .collect();
+                    if !marks.trim().is_empty() {
+                        writeln!(f, "{:>width$} | {}", "", marks.trim_end())?;
+                    }
+                }
+                writeln!(f, "```")?;
+                if excerpt.omitted > 0 {
+                    writeln!(f, "\n{}", omitted_note(excerpt.omitted))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+const HTML_STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; }
+.ln { color: #888; user-select: none; }
+mark { border-radius: 2px; }
+mark.lifetime { background: #e6f4ea; }
+mark.imm_borrow { background: #d0e4ff; }
+mark.mut_borrow { background: #ffe0b2; }
+mark.move { background: #fff59d; }
+mark.shared_mut { background: #ffccbc; }
+mark.outlive { background: #ffcdd2; }";
+
+/// `text` with the characters that are special in HTML escaped.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Ownership report with the maximum number of source lines per variable,
+/// as a self-contained HTML document
+struct Html<'a>(&'a OwnershipReport, usize);
+
+impl fmt::Display for Html<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(report, max_lines) = self;
+        writeln!(f, "<!DOCTYPE html>")?;
+        writeln!(f, "<html lang=\"en\">")?;
+        writeln!(f, "<head>\n<meta charset=\"utf-8\">")?;
+        writeln!(f, "<title>Ownership report</title>")?;
+        writeln!(f, "<style>\n{HTML_STYLE}\n</style>\n</head>")?;
+        writeln!(f, "<body>\n<h1>Ownership report</h1>")?;
+        for (name, file) in &report.files {
+            writeln!(f, "<h2>{}</h2>", escape_html(name))?;
+            for variable in &file.variables {
+                writeln!(
+                    f,
+                    "<h3><code>{}</code>: <code>{}</code>, line {}</h3>",
+                    escape_html(&variable.name),
+                    escape_html(&variable.ty),
+                    variable.declared.0 + 1
+                )?;
+                if !variable.entries.is_empty() {
+                    writeln!(f, "<ul>")?;
+                    for entry in &variable.entries {
+                        writeln!(
+                            f,
+                            "<li><mark class=\"{}\">{}</mark></li>",
+                            entry.kind,
+                            escape_html(&entry.to_string())
+                        )?;
+                    }
+                    writeln!(f, "</ul>")?;
+                }
+                let excerpt = file.excerpt(variable, *max_lines);
+                let width = excerpt
+                    .lines
+                    .last()
+                    .map_or(1, |line| line.number.to_string().len());
+                write!(f, "<pre>")?;
+                for line in &excerpt.lines {
+                    write!(f, "<span class=\"ln\">{:>width$} </span>", line.number)?;
+                    for (kind, text) in &line.segments {
+                        match kind {
+                            Some(kind) => {
+                                write!(f, "<mark class=\"{kind}\">{}</mark>", escape_html(text))?;
+                            }
+                            None => write!(f, "{}", escape_html(text))?,
+                        }
+                    }
+                    writeln!(f)?;
+                }
+                writeln!(f, "</pre>")?;
+                if excerpt.omitted > 0 {
+                    writeln!(f, "<p>{}</p>", omitted_note(excerpt.omitted))?;
+                }
+            }
+        }
+        writeln!(f, "</body>\n</html>")
+    }
+}
+
+/// Decoration of a variable printed by `check --emit-decorations-for`, as a
+/// line of JSON
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct EmittedDecoration {
+    /// File name relative to the workspace root
+    pub file: String,
+    pub variable: String,
+    pub kind: DecoKind,
+    /// Written as one-based `line:column`
+    #[serde(serialize_with = "serialize_one_based")]
+    pub start: Position,
+    /// Written as one-based `line:column`
+    #[serde(serialize_with = "serialize_one_based")]
+    pub end: Position,
+    pub message: String,
+}
+
+#[allow(
+    clippy::trivially_copy_pass_by_ref,
+    reason = "serde passes fields by reference"
+)]
+fn serialize_one_based<S: Serializer>(
+    position: &Position,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{}:{}", position.0 + 1, position.1 + 1))
+}
+
+/// Decorations of all user variables of the files in `workspace` matching
+/// any of `patterns`, with their text from `read_source`, in the order of
+/// file names and positions.
+///
+/// Relative patterns match the file names relative to `root`, absolute ones
+/// the full names. Patterns matching no file are warned about, and files
+/// `read_source` returns no text for are left out.
+pub fn emitted_decorations(
+    workspace: &Workspace,
+    root: &Path,
+    patterns: &[String],
+    read_source: impl Fn(&str) -> Option<String>,
+) -> Vec<EmittedDecoration> {
+    let mut files = BTreeMap::new();
+    let mut matched = vec![false; patterns.len()];
+    for krate in workspace.crates.values() {
+        for (name, file) in &krate.0 {
+            let relative = Path::new(name).strip_prefix(root).map_or_else(
+                |_| name.clone(),
+                |path| {
+                    path.components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                },
+            );
+            for (pattern, matched) in patterns.iter().zip(&mut matched) {
+                let target = if Path::new(pattern).is_absolute() {
+                    name
+                } else {
+                    &relative
+                };
+                if glob_matches(pattern, target) {
+                    *matched = true;
+                    // a file analyzed for several crates is emitted once
+                    files.entry(relative.clone()).or_insert((name, file));
+                }
+            }
+        }
+    }
+    for (pattern, matched) in patterns.iter().zip(matched) {
+        if !matched {
+            log::warn!("no analyzed file matches {pattern}");
+        }
+    }
+    let mut emitted = Vec::new();
+    for (relative, (name, file)) in files {
+        let Some(source) = read_source(name) else {
+            log::warn!("cannot read {name}, its decorations are not emitted");
+            continue;
+        };
+        for variable in variable_reports(file, &source, |_| true) {
+            for entry in variable.entries {
+                let Ok(kind) = entry.kind.parse() else {
+                    continue;
+                };
+                emitted.push(EmittedDecoration {
+                    file: relative.clone(),
+                    variable: variable.name.clone(),
+                    kind,
+                    start: entry.from,
+                    end: entry.until,
+                    message: entry.hover_text,
+                });
+            }
+        }
+    }
+    emitted
+}
+
+/// Whether the `/`-separated `path` matches `pattern`, in which `**` stands
+/// for any number of components, `*` for any characters of a component and
+/// `?` for one character.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let components = |text: &'_ str| -> Vec<Vec<char>> {
+        text.split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .map(|component| component.chars().collect())
+            .collect()
+    };
+    components_match(&components(pattern), &components(path))
+}
+
+fn components_match(pattern: &[Vec<char>], path: &[Vec<char>]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if *first == ['*', '*'] => {
+            (0..=path.len()).any(|skipped| components_match(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(component, path)| {
+            component_matches(first, component) && components_match(rest, path)
+        }),
+    }
+}
+
+fn component_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => {
+            (0..=text.len()).any(|skipped| component_matches(rest, &text[skipped..]))
+        }
+        Some((c, rest)) => text.split_first().is_some_and(|(first, text)| {
+            (*c == '?' || c == first) && component_matches(rest, text)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::models::{
+        CharOffset, Crate, File, FnLocal, Function, Item, ItemKind, MirBasicBlock, MirRval,
+        MirStatement, Range,
+    };
+
+    fn range(from: u32, until: u32) -> Range {
+        Range::new(CharOffset::from(from), CharOffset::from(until)).unwrap()
+    }
+
+    fn user_decl(id: u32, shared_mut: Vec<Range>) -> MirDecl {
+        MirDecl::User {
+            local: FnLocal::new(id, 1),
+            name: format!("v{id}"),
+            span: range(0, 1),
+            scope: None,
+            ty: "String".into(),
+            lives: vec![range(0, 100)],
+            shared_borrow: Vec::new(),
+            mutable_borrow: Vec::new(),
+            shared_mut,
+            drop: false,
+            drop_range: Vec::new(),
+            must_live_at: Vec::new(),
+            must_live_from_signature: Vec::new(),
+            dead_writes: Vec::new(),
+        }
+    }
+
+    fn move_of(id: u32, at: u32) -> MirStatement {
+        MirStatement::Assign {
+            target_local: FnLocal::new(99, 1),
+            range: range(at, at + 1),
+            rval: Some(MirRval::Move {
+                target_local: FnLocal::new(id, 1),
+                range: range(at, at + 1),
+                moved_into: None,
+                from_macro: false,
+            }),
+            through_ref: None,
+            from_macro: false,
+        }
+    }
+
+    /// Workspace of one crate with `files`, each holding a single function
+    /// with the given declarations and statements.
+    fn workspace(files: Vec<(&str, Vec<MirDecl>, Vec<MirStatement>)>) -> Workspace {
+        let files = files
+            .into_iter()
+            .map(|(name, decls, statements)| {
+                let item = Item::Function {
+                    span: None,
+                    kind: ItemKind::Fn,
+                    function: Function {
+                        fn_id: 1,
+                        fn_key: 1,
+                        fn_name: String::new(),
+                        arg_count: 0,
+                        basic_blocks: vec![MirBasicBlock {
+                            statements,
+                            terminator: None,
+                            is_cleanup: false,
+                        }],
+                        decls,
+                        captures: Vec::new(),
+                        borrow_edges: Vec::new(),
+                        clone_edges: Vec::new(),
+                        await_points: Vec::new(),
+                        part: None,
+                        context: String::new(),
+                    },
+                };
+                (
+                    name.to_owned(),
+                    File {
+                        items: vec![item],
+                        types: Vec::new(),
+                        source_hash: String::new(),
+                        provisional: false,
+                    },
+                )
+            })
+            .collect();
+        Workspace::new(HashMap::from([("demo".to_owned(), Crate(files))]))
+    }
+
+    fn counts(moves: usize, shared_mut: usize, outlive: usize) -> FileCounts {
+        FileCounts {
+            moves,
+            shared_mut,
+            outlive,
+        }
+    }
+
+    #[test]
+    fn test_summary_counts_per_file() {
+        let summary = Summary::new(&workspace(vec![
+            (
+                "/ws/src/lib.rs",
+                vec![user_decl(1, vec![range(10, 20)]), user_decl(2, Vec::new())],
+                vec![move_of(1, 30), move_of(2, 40)],
+            ),
+            ("/ws/src/a.rs", vec![user_decl(1, Vec::new())], Vec::new()),
+        ]));
+        assert_eq!(summary.files["/ws/src/lib.rs"], counts(2, 1, 0));
+        assert_eq!(summary.files["/ws/src/a.rs"], counts(0, 0, 0));
+    }
+
+    #[test]
+    fn test_summary_relative_to_root() {
+        let summary = Summary::new(&workspace(vec![
+            ("/ws/src/lib.rs", Vec::new(), Vec::new()),
+            ("/elsewhere/b.rs", Vec::new(), Vec::new()),
+        ]))
+        .relative_to(Path::new("/ws"));
+        let names: Vec<_> = summary.files.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["/elsewhere/b.rs", "src/lib.rs"]);
+    }
+
+    #[test]
+    fn test_diff_against_previous_run() {
+        let previous = Summary::new(&workspace(vec![
+            (
+                "lib.rs",
+                vec![user_decl(1, Vec::new())],
+                vec![move_of(1, 5)],
+            ),
+            (
+                "same.rs",
+                vec![user_decl(1, Vec::new())],
+                vec![move_of(1, 5)],
+            ),
+            (
+                "removed.rs",
+                vec![user_decl(1, vec![range(1, 2)])],
+                Vec::new(),
+            ),
+        ]));
+        let current = Summary::new(&workspace(vec![
+            (
+                "lib.rs",
+                vec![user_decl(1, Vec::new()), user_decl(2, Vec::new())],
+                vec![move_of(1, 5), move_of(2, 8)],
+            ),
+            (
+                "same.rs",
+                vec![user_decl(1, Vec::new())],
+                vec![move_of(1, 5)],
+            ),
+            (
+                "added.rs",
+                vec![user_decl(1, Vec::new())],
+                vec![move_of(1, 5)],
+            ),
+        ]));
+
+        let diff = current.diff(&previous);
+        let lines: Vec<_> = diff.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "added.rs: moves 0 -> 1",
+                "lib.rs: moves 1 -> 2",
+                "removed.rs: shared-mut 1 -> 0",
+            ]
+        );
+        assert!(current.diff(&current).is_empty());
+    }
+
+    #[test]
+    fn test_summary_table() {
+        let summary = Summary::new(&workspace(vec![(
+            "lib.rs",
+            vec![user_decl(1, Vec::new())],
+            vec![move_of(1, 5)],
+        )]));
+        assert_eq!(
+            summary.to_string(),
+            "  moves  shared-mut  outlive  file\n      1           0        0  lib.rs\n"
+        );
+    }
+
+    /// Source of 10 lines of 12 characters each, `let vN = 0;`
+    fn source() -> String {
+        (0..10)
+            .map(|i| format!("let v{i} = 0;\n"))
+            .collect::<Vec<_>>()
+            .concat()
+    }
+
+    fn entry(kind: &'static str, from: Position, until: Position) -> ReportEntry {
+        ReportEntry {
+            kind,
+            from,
+            until,
+            hover_text: kind.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_ownership_report_groups_by_file_and_variable() {
+        let report = OwnershipReport::new(
+            &workspace(vec![
+                (
+                    "/ws/src/lib.rs",
+                    vec![user_decl(2, Vec::new()), user_decl(1, vec![range(14, 16)])],
+                    vec![move_of(1, 30)],
+                ),
+                (
+                    "/ws/src/gone.rs",
+                    vec![user_decl(1, Vec::new())],
+                    Vec::new(),
+                ),
+            ]),
+            |name| (name != "/ws/src/gone.rs").then(source),
+        )
+        .relative_to(Path::new("/ws"));
+
+        let names: Vec<_> = report.files.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            vec!["src/lib.rs"],
+            "files without source are left out"
+        );
+        let file = &report.files["src/lib.rs"];
+        assert_eq!(file.lines.len(), 10);
+        let variables: Vec<_> = file.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(variables, vec!["v2", "v1"], "in the order of declarations");
+
+        let v1 = &file.variables[1];
+        let kinds: Vec<_> = v1.entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, vec!["lifetime", "shared_mut", "move"]);
+        assert_eq!((v1.entries[0].from, v1.entries[0].until), ((0, 0), (8, 4)));
+        assert_eq!((v1.entries[1].from, v1.entries[1].until), ((1, 2), (1, 4)));
+        assert_eq!((v1.entries[2].from, v1.entries[2].until), ((2, 6), (2, 7)));
+        let kinds: Vec<_> = file.variables[0].entries.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec!["lifetime"], "decorations of other variables");
+    }
+
+    #[test]
+    fn test_highlight_prefers_later_kinds() {
+        let entries = [
+            entry("lifetime", (0, 0), (2, 3)),
+            entry("move", (1, 2), (1, 4)),
+        ];
+        assert_eq!(
+            highlight("let a = b;", 1, &entries),
+            vec![
+                (Some("lifetime"), "le".to_owned()),
+                (Some("move"), "t ".to_owned()),
+                (Some("lifetime"), "a = b;".to_owned()),
+            ]
+        );
+        assert_eq!(
+            highlight("abcd", 2, &entries),
+            vec![(Some("lifetime"), "abc".to_owned()), (None, "d".to_owned())]
+        );
+        assert_eq!(
+            highlight("abcd", 3, &entries),
+            vec![(None, "abcd".to_owned())]
+        );
+    }
+
+    fn report_of(variable: VariableReport) -> OwnershipReport {
+        OwnershipReport {
+            files: BTreeMap::from([(
+                "src/lib.rs".to_owned(),
+                FileReport {
+                    lines: vec![
+                        "let a = Vec::<u8>::new();".to_owned(),
+                        "let b = a;".to_owned(),
+                        "drop(b);".to_owned(),
+                    ],
+                    variables: vec![variable],
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_markdown_marks_decorations() {
+        let report = report_of(VariableReport {
+            name: "a".to_owned(),
+            ty: "Vec<u8>".to_owned(),
+            declared: (0, 4),
+            entries: vec![
+                entry("lifetime", (0, 4), (1, 9)),
+                entry("move", (1, 8), (1, 9)),
+            ],
+        });
+        assert_eq!(
+            report.render(ReportFormat::Markdown, 40),
+            "# Ownership report\n\n## src/lib.rs\n\n### `a`: `Vec<u8>`, line 1\n\n- lifetime \
+             1:5-2:10: lifetime\n- move 2:9-2:10: move\n\n```text\n1 | let a = \
+             Vec::<u8>::new();\n2 | let b = a;\n  |         ^\n```\n"
+        );
+        assert!(
+            report.render(ReportFormat::Markdown, 1).ends_with(
+                "```text\n1 | let a = Vec::<u8>::new();\n```\n\n1 more line not shown\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_html_escapes_and_highlights() {
+        let report = report_of(VariableReport {
+            name: "a".to_owned(),
+            ty: "Vec<u8>".to_owned(),
+            declared: (0, 4),
+            entries: vec![entry("imm_borrow", (0, 8), (0, 17))],
+        });
+        let html = report.render(ReportFormat::Html, 40);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<style>"), "styles are inline");
+        assert!(html.contains("<h3><code>a</code>: <code>Vec&lt;u8&gt;</code>, line 1</h3>"));
+        assert!(
+            html.contains(
+                "<span class=\"ln\">1 </span>let a = <mark \
+                 class=\"imm_borrow\">Vec::&lt;u8&gt;</mark>::new();\n"
+            ),
+            "{html}"
+        );
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+
+    #[test]
+    fn test_glob_matches_components() {
+        assert!(glob_matches("src/lib.rs", "src/lib.rs"));
+        assert!(glob_matches("./src//lib.rs", "src/lib.rs"));
+        assert!(glob_matches("src/*.rs", "src/lib.rs"));
+        assert!(
+            !glob_matches("src/*.rs", "src/a/lib.rs"),
+            "`*` stays in a component"
+        );
+        assert!(glob_matches("src/**/*.rs", "src/lib.rs"));
+        assert!(glob_matches("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(glob_matches("**", "src/a/lib.rs"));
+        assert!(glob_matches("src/l?b.rs", "src/lib.rs"));
+        assert!(!glob_matches("src/l?b.rs", "src/lb.rs"));
+        assert!(!glob_matches("src/*.rs", "src/lib.rsx"));
+        assert!(!glob_matches("src", "src/lib.rs"));
+    }
+
+    #[test]
+    fn test_emitted_decorations_of_matching_files() {
+        let workspace = workspace(vec![
+            (
+                "/ws/src/lib.rs",
+                vec![user_decl(1, vec![range(14, 16)])],
+                vec![move_of(1, 30)],
+            ),
+            (
+                "/ws/src/other.rs",
+                vec![user_decl(1, Vec::new())],
+                Vec::new(),
+            ),
+            (
+                "/ws/tests/it.rs",
+                vec![user_decl(1, Vec::new())],
+                Vec::new(),
+            ),
+        ]);
+        let emitted = emitted_decorations(
+            &workspace,
+            Path::new("/ws"),
+            &["src/l*.rs".to_owned(), "/ws/src/lib.rs".to_owned()],
+            |_| Some(source()),
+        );
+
+        assert!(emitted.iter().all(|deco| deco.file == "src/lib.rs"));
+        let kinds: Vec<_> = emitted.iter().map(|deco| deco.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![DecoKind::Lifetime, DecoKind::SharedMut, DecoKind::Move],
+            "once per file, though matched twice"
+        );
+        let line = serde_json::to_value(&emitted[1]).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "file": "src/lib.rs",
+                "variable": "v1",
+                "kind": "shared-mut",
+                "start": "2:3",
+                "end": "2:5",
+                "message": emitted[1].message,
+            })
+        );
+    }
+}
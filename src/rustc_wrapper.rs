@@ -1,27 +1,35 @@
 use std::{
     collections::HashMap,
-    env, error, fmt,
+    env, error,
+    ffi::OsStr,
+    fmt,
+    io::{self, Write},
     panic::{AssertUnwindSafe, catch_unwind},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{LazyLock, Mutex, atomic::AtomicBool},
     thread,
+    time::Duration,
 };
 
+use cargo_metadata::diagnostic::Diagnostic;
+use rustc_errors::{ColorConfig, emitter::HumanReadableErrorType, json::JsonEmitter};
 use rustc_hir::def_id::{LOCAL_CRATE, LocalDefId};
 use rustc_interface::interface;
 use rustc_middle::{mir::ConcreteOpaqueTypes, query::queries, ty::TyCtxt, util::Providers};
-use rustc_session::config;
+use rustc_session::{config, parse::ParseSess};
 use tempfile::NamedTempFile;
 use tokio::{
     runtime::{Builder, Runtime},
     sync::mpsc,
-    task::JoinSet,
+    task::{self, JoinError, JoinSet},
+    time,
 };
 
 use crate::{
-    mir_analysis::{AnalyzeResult, MirAnalyzer, MirAnalyzerInitResult},
-    mir_cache,
-    models::{Crate, File, Workspace},
+    mir_algorithm::AlgorithmChoice,
+    mir_analysis::{self, AnalyzeResult, MirAnalyzeFuture, MirAnalyzer, MirAnalyzerInitResult},
+    mir_cache::{self, CachedFunction},
+    models::{Crate, File, Item, TEST_CONTEXT, Workspace, WorkspaceMeta},
 };
 
 #[derive(Debug)]
@@ -43,38 +51,128 @@ impl error::Error for AnalysisError {}
 
 pub struct AnalysisHandle {
     pub results: mpsc::UnboundedReceiver<Workspace>,
+    /// Diagnostics emitted by the compiler, closed once the compiler finished
+    pub diagnostics: mpsc::UnboundedReceiver<Diagnostic>,
     pub thread: thread::JoinHandle<Result<i32, AnalysisError>>,
 }
 
+/// Set by cargo when it compiles a package it was asked to build, rather than
+/// one of their dependencies
+const PRIMARY_PACKAGE_ENV: &str = "CARGO_PRIMARY_PACKAGE";
+
+/// Whether cargo invoked the wrapper with `args` for a package to analyze.
+///
+/// Cargo wraps all path dependencies, but only the packages of the analysis
+/// are primary. Their dependencies are checked without analysis.
+fn analyzes_package(args: &[String], primary_package: Option<&OsStr>) -> bool {
+    is_wrapper_mode(args) && primary_package.is_some()
+}
+
+/// Whether cargo invoked this binary as workspace wrapper of itself, as set
+/// up by `setup_cargo_command`.
+fn is_wrapper_mode(args: &[String]) -> bool {
+    args.first().is_some_and(|first| args.get(1) == Some(first))
+}
+
 #[must_use]
 pub fn run_as_rustc_wrapper() -> i32 {
-    run_compiler(&env::args().collect::<Vec<_>>())
+    let args: Vec<String> = env::args().collect();
+    if is_wrapper_mode(&args)
+        && !analyzes_package(&args, env::var_os(PRIMARY_PACKAGE_ENV).as_deref())
+    {
+        return rustc_driver::catch_with_exit_code(|| {
+            rustc_driver::run_compiler(&args[1..], &mut PassthroughCallback);
+        });
+    }
+    run_compiler(&args)
 }
 
+/// Analyze the single file at `file`, caching its functions in `cache_dir`
+/// if given.
 #[must_use]
-pub fn spawn_analysis(file: &Path, sysroot: &Path) -> AnalysisHandle {
-    let (sender, receiver) = mpsc::unbounded_channel();
-
+pub fn spawn_analysis(file: &Path, sysroot: &Path, cache_dir: Option<PathBuf>) -> AnalysisHandle {
     let output_file = NamedTempFile::new().expect("Failed to create temp file for compiler output");
-    let output_path = output_file.path().to_string_lossy().to_string();
-
-    let mut args = vec![
-        env!("CARGO_PKG_NAME").to_string(),
-        env!("CARGO_PKG_NAME").to_string(),
-        format!("--sysroot={}", sysroot.display()),
+    let args = vec![
         "--crate-type=lib".to_string(),
-        format!("-o{output_path}"),
+        "--edition=2021".to_string(),
+        format!("-o{}", output_file.path().display()),
+        file.to_string_lossy().to_string(),
     ];
-    args.push(file.to_string_lossy().to_string());
+    spawn(args, sysroot, Some(output_file), cache_dir)
+}
+
+/// Analyze the crate compiled with the compiler arguments `args`, which
+/// name its outputs.
+#[must_use]
+pub fn spawn_crate_analysis(args: Vec<String>, sysroot: &Path) -> AnalysisHandle {
+    spawn(args, sysroot, None, None)
+}
+
+/// Compile the crate of the compiler arguments `args` without analysis,
+/// like `rustc` does.
+pub fn build_crate(args: &[String], sysroot: &Path) -> Result<i32, AnalysisError> {
+    let mut args = args.to_vec();
+    args.splice(
+        0..0,
+        [
+            env!("CARGO_PKG_NAME").to_string(),
+            format!("--sysroot={}", sysroot.display()),
+        ],
+    );
+    let thread = thread::Builder::new()
+        .name("ferrous-owl-compiler".to_string())
+        .stack_size(128 * 1024 * 1024)
+        .spawn(move || catch_unwind(AssertUnwindSafe(|| run_compiler(&args))))
+        .expect("Failed to spawn compiler thread");
+    match thread.join() {
+        Ok(Ok(0)) => Ok(0),
+        Ok(Ok(exit_code)) => Err(AnalysisError::CompilationFailed(exit_code)),
+        Ok(Err(_)) | Err(_) => Err(AnalysisError::RustcPanic),
+    }
+}
+
+/// Analyze the crate of `args` on a thread of its own, keeping
+/// `output_file` until it finished.
+///
+/// The MIR cache is read from `cache_dir`, else from [`CACHE_DIR_ENV`] as
+/// in the compiler wrapper, and is not kept for the next analysis.
+/// Analyses in this process run one after the other, as they share the
+/// senders of their results.
+///
+/// [`CACHE_DIR_ENV`]: crate::toolchain::CACHE_DIR_ENV
+fn spawn(
+    args: Vec<String>,
+    sysroot: &Path,
+    output_file: Option<NamedTempFile>,
+    cache_dir: Option<PathBuf>,
+) -> AnalysisHandle {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let (diagnostic_sender, diagnostic_receiver) = mpsc::unbounded_channel();
+
+    let mut args = args;
+    args.splice(
+        0..0,
+        [
+            env!("CARGO_PKG_NAME").to_string(),
+            env!("CARGO_PKG_NAME").to_string(),
+            format!("--sysroot={}", sysroot.display()),
+        ],
+    );
 
     let thread = thread::Builder::new()
         .name("ferrous-owl-compiler".to_string())
         .stack_size(128 * 1024 * 1024)
         .spawn(move || {
             let _output_guard = output_file;
+            let _running = RUNNING.lock().unwrap();
             *RESULT_SENDER.lock().unwrap() = Some(sender);
+            *DIAGNOSTIC_SENDER.lock().unwrap() = Some(diagnostic_sender);
+            *mir_cache::CACHE.lock().unwrap() = cache_dir.map(mir_cache::CacheData::new);
+            mir_analysis::forget_source_files();
             let result = catch_unwind(AssertUnwindSafe(|| run_compiler(&args)));
             *RESULT_SENDER.lock().unwrap() = None;
+            *DIAGNOSTIC_SENDER.lock().unwrap() = None;
+            *mir_cache::CACHE.lock().unwrap() = None;
 
             result.map_or(Err(AnalysisError::RustcPanic), |exit_code| {
                 if exit_code == 0 {
@@ -88,15 +186,21 @@ pub fn spawn_analysis(file: &Path, sysroot: &Path) -> AnalysisHandle {
 
     AnalysisHandle {
         results: receiver,
+        diagnostics: diagnostic_receiver,
         thread,
     }
 }
 
 static ATOMIC_TRUE: AtomicBool = AtomicBool::new(true);
-static TASKS: LazyLock<Mutex<JoinSet<AnalyzeResult>>> =
+/// Analyses of functions, yielding `None` for functions that failed
+static TASKS: LazyLock<Mutex<JoinSet<Option<AnalyzeResult>>>> =
     LazyLock::new(|| Mutex::new(JoinSet::new()));
 static RESULT_SENDER: LazyLock<Mutex<Option<mpsc::UnboundedSender<Workspace>>>> =
     LazyLock::new(|| Mutex::new(None));
+static DIAGNOSTIC_SENDER: LazyLock<Mutex<Option<mpsc::UnboundedSender<Diagnostic>>>> =
+    LazyLock::new(|| Mutex::new(None));
+/// Held by the analysis running in this process, see [`spawn`]
+static RUNNING: Mutex<()> = Mutex::new(());
 
 static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
     let worker_threads = thread::available_parallelism()
@@ -112,8 +216,7 @@ static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
 });
 
 fn run_compiler(args: &[String]) -> i32 {
-    let is_wrapper_mode = args.first() == args.get(1);
-    let args: Vec<String> = if is_wrapper_mode {
+    let args: Vec<String> = if is_wrapper_mode(args) {
         args.iter().skip(1).cloned().collect()
     } else {
         return rustc_driver::catch_with_exit_code(|| {
@@ -131,6 +234,14 @@ fn run_compiler(args: &[String]) -> i32 {
         });
     }
 
+    match AlgorithmChoice::from_env() {
+        Ok(algorithm) => log::info!("polonius algorithm: {}", algorithm.unwrap_or_default()),
+        Err(e) => {
+            log::error!("{e}");
+            return 1;
+        }
+    }
+
     rustc_driver::catch_with_exit_code(|| {
         rustc_driver::run_compiler(&args, &mut AnalyzerCallback);
     })
@@ -150,6 +261,12 @@ impl rustc_driver::Callbacks for AnalyzerCallback {
         config.opts.incremental = None;
         config.override_queries = Some(override_queries);
         config.make_codegen_backend = None;
+
+        if let Some(sender) = DIAGNOSTIC_SENDER.lock().unwrap().clone() {
+            config.psess_created = Some(Box::new(move |psess| {
+                install_diagnostic_emitter(psess, sender);
+            }));
+        }
     }
 
     fn after_expansion(
@@ -161,12 +278,14 @@ impl rustc_driver::Callbacks for AnalyzerCallback {
 
         #[allow(clippy::await_holding_lock, reason = "lock duration is minimal")]
         RUNTIME.block_on(async move {
-            while let Some(Ok(result)) = { TASKS.lock().unwrap().join_next().await } {
+            while let Some(joined) = { TASKS.lock().unwrap().join_next().await } {
                 log::info!("one task joined");
-                send_result(tcx, result);
+                if let Some(result) = analysis_result(joined) {
+                    send_result(tcx, result);
+                }
             }
-            if let Some(cache) = mir_cache::CACHE.lock().unwrap().as_ref() {
-                mir_cache::write_cache(&tcx.crate_name(LOCAL_CRATE).to_string(), cache);
+            if let Some(cache) = mir_cache::CACHE.lock().unwrap().as_mut() {
+                cache.write();
             }
         });
 
@@ -178,6 +297,50 @@ impl rustc_driver::Callbacks for AnalyzerCallback {
     }
 }
 
+/// Route the diagnostics of an in-process analysis to `sender` instead of
+/// printing them to stderr.
+fn install_diagnostic_emitter(psess: &ParseSess, sender: mpsc::UnboundedSender<Diagnostic>) {
+    let fallback_bundle = rustc_errors::fallback_fluent_bundle(
+        rustc_driver::DEFAULT_LOCALE_RESOURCES.to_vec(),
+        false,
+    );
+    let emitter = JsonEmitter::new(
+        Box::new(DiagnosticWriter {
+            buffer: Vec::new(),
+            sender,
+        }),
+        Some(psess.clone_source_map()),
+        fallback_bundle,
+        false,
+        HumanReadableErrorType::Default,
+        ColorConfig::Never,
+    );
+    psess.dcx().set_emitter(Box::new(emitter));
+}
+
+/// Parses the JSON lines written by rustc's JSON emitter into [`Diagnostic`]s.
+struct DiagnosticWriter {
+    buffer: Vec<u8>,
+    sender: mpsc::UnboundedSender<Diagnostic>,
+}
+
+impl Write for DiagnosticWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            if let Ok(diagnostic) = serde_json::from_slice(&line) {
+                let _ = self.sender.send(diagnostic);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 fn override_queries(_session: &rustc_session::Session, local: &mut Providers) {
     local.mir_borrowck = mir_borrowck;
 }
@@ -191,16 +354,29 @@ fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId) -> queries::mir_borrowck::P
     {
         let mut tasks = TASKS.lock().unwrap();
         match analyzer {
-            MirAnalyzerInitResult::Cached(cached) => send_result(tcx, cached),
-            MirAnalyzerInitResult::Analyzer(analyzer) => {
-                tasks.spawn_on(async move { analyzer.await.analyze() }, RUNTIME.handle());
+            MirAnalyzerInitResult::Cached(cached) => send_result(tcx, *cached),
+            MirAnalyzerInitResult::Analyzer { analyzer, fallback } => {
+                let span = tcx
+                    .sess
+                    .source_map()
+                    .span_to_embeddable_string(tcx.def_span(def_id));
+                let item = format!("`{}` at {span}", tcx.def_path_str(def_id));
+                tasks.spawn_on(
+                    analyze_with_timeout(analyzer, *fallback, item),
+                    RUNTIME.handle(),
+                );
+            }
+            MirAnalyzerInitResult::Skipped(reason) => {
+                log::warn!("skipping analysis of {def_id:?}: {reason}");
             }
         }
 
         log::debug!("there are {} tasks", tasks.len());
-        while let Some(Ok(result)) = tasks.try_join_next() {
+        while let Some(joined) = tasks.try_join_next() {
             log::debug!("one task joined");
-            send_result(tcx, result);
+            if let Some(result) = analysis_result(joined) {
+                send_result(tcx, result);
+            }
         }
     }
 
@@ -213,27 +389,157 @@ fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId) -> queries::mir_borrowck::P
         .alloc(ConcreteOpaqueTypes(indexmap::IndexMap::default())))
 }
 
-fn send_result(tcx: TyCtxt<'_>, analyzed: AnalyzeResult) {
-    if let Some(cache) = mir_cache::CACHE.lock().unwrap().as_mut() {
+/// Environment variable overriding [`DEFAULT_FN_TIMEOUT`], in seconds
+pub const FN_TIMEOUT_ENV: &str = "FERROUS_OWL_FN_TIMEOUT_SECS";
+
+/// Time the borrow check analysis of a single function may take
+const DEFAULT_FN_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn fn_timeout() -> Duration {
+    env::var(FN_TIMEOUT_ENV)
+        .ok()
+        .and_then(|secs| secs.trim().parse().ok())
+        .map_or(DEFAULT_FN_TIMEOUT, Duration::from_secs)
+}
+
+/// Run `analyzer` on a blocking thread, falling back to the degraded result
+/// of `fallback` if it does not finish within [`fn_timeout`].
+///
+/// The computation is not interrupted on timeout, its result is discarded.
+/// A panic of the analysis skips the function described by `item`, so the
+/// other functions of the crate keep their results.
+async fn analyze_with_timeout(
+    analyzer: MirAnalyzeFuture,
+    fallback: MirAnalyzer,
+    item: String,
+) -> Option<AnalyzeResult> {
+    let timeout = fn_timeout();
+    let analysis = task::spawn_blocking(move || {
+        catch_unwind(AssertUnwindSafe(|| RUNTIME.block_on(analyzer).analyze()))
+    });
+    match time::timeout(timeout, analysis).await {
+        Ok(Ok(Ok(result))) => Some(result),
+        Ok(Ok(Err(_)) | Err(_)) => {
+            log::warn!("analysis of {item} panicked, skipping it");
+            None
+        }
+        Err(_) => {
+            log::warn!(
+                "analysis of {item} timed out after {timeout:?}, showing it without lifetimes and \
+                 borrows"
+            );
+            let mut result = fallback.degraded();
+            result.timings.polonius_ms = timeout.as_secs_f64() * 1000.0;
+            Some(result)
+        }
+    }
+}
+
+/// Result of a joined analysis task, if it produced one.
+fn analysis_result<T>(joined: Result<Option<T>, JoinError>) -> Option<T> {
+    joined.unwrap_or_else(|e| {
+        log::warn!("analysis task failed: {e}");
+        None
+    })
+}
+
+/// Size in bytes of the analysis result of a function above which it is
+/// printed in parts, keeping lines short for the reader of the output
+const MAX_RESULT_BYTES: usize = 8 * 1024 * 1024;
+
+fn send_result(tcx: TyCtxt<'_>, mut analyzed: AnalyzeResult) {
+    if !analyzed.degraded
+        && let Some(cache) = mir_cache::CACHE.lock().unwrap().as_mut()
+    {
         cache.insert_cache(
+            &analyzed.file_name,
             analyzed.file_hash.clone(),
             analyzed.mir_hash.clone(),
-            analyzed.analyzed.clone(),
+            CachedFunction {
+                item: analyzed.analyzed.clone(),
+                types: analyzed.types.clone(),
+            },
         );
     }
 
-    let krate = Crate(HashMap::from([(
-        analyzed.file_name.clone(),
-        File {
-            items: vec![analyzed.analyzed],
-        },
-    )]));
+    // cached results are shared by the compilations with and without `--test`
+    if tcx.sess.opts.test {
+        TEST_CONTEXT.clone_into(&mut analyzed.analyzed.function_mut().context);
+    }
+
     let crate_name = tcx.crate_name(LOCAL_CRATE).to_string();
-    let workspace = Workspace(HashMap::from([(crate_name, krate)]));
+    let mut meta = Some(WorkspaceMeta {
+        degraded: analyzed.degraded,
+        ..WorkspaceMeta::with_timings(
+            crate_name.clone(),
+            analyzed.file_name.clone(),
+            analyzed.analyzed.function().fn_id,
+            analyzed.timings,
+        )
+    });
+    let workspace = |item: Item, meta: Option<WorkspaceMeta>| {
+        let krate = Crate(HashMap::from([(
+            analyzed.file_name.clone(),
+            File {
+                items: vec![item],
+                types: analyzed.types.clone(),
+                source_hash: analyzed.file_hash.clone(),
+                provisional: false,
+            },
+        )]));
+        Workspace {
+            crates: HashMap::from([(crate_name.clone(), krate)]),
+            meta,
+        }
+    };
 
     if let Some(sender) = RESULT_SENDER.lock().unwrap().as_ref() {
-        let _ = sender.send(workspace);
+        let _ = sender.send(workspace(analyzed.analyzed, meta));
     } else {
-        println!("{}", serde_json::to_string(&workspace).unwrap());
+        // the timings go with the first part
+        for part in analyzed.analyzed.split(MAX_RESULT_BYTES) {
+            let workspace = workspace(part, meta.take());
+            println!("{}", serde_json::to_string(&workspace).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_failed_tasks_do_not_stop_joining() {
+        RUNTIME.block_on(async {
+            let mut tasks: JoinSet<Option<u32>> = JoinSet::new();
+            tasks.spawn(async { panic!("analysis panicked") });
+            tasks.spawn(async { None });
+            tasks.spawn(async { Some(1) });
+            let mut results = Vec::new();
+            while let Some(joined) = tasks.join_next().await {
+                results.extend(analysis_result(joined));
+            }
+            assert_eq!(results, vec![1]);
+        });
+    }
+
+    #[test]
+    fn test_only_primary_packages_are_analyzed() {
+        let wrapped = args(&["ferrous-owl", "ferrous-owl", "--crate-name", "app"]);
+        let primary = Some(OsStr::new("1"));
+        assert!(analyzes_package(&wrapped, primary));
+        assert!(
+            !analyzes_package(&wrapped, None),
+            "dependencies are checked"
+        );
+        // dependencies outside of the workspace are compiled without wrapper
+        let plain = args(&["ferrous-owl", "--crate-name", "serde"]);
+        assert!(!analyzes_package(&plain, primary));
+        assert!(!analyzes_package(&plain, None));
+        assert!(!analyzes_package(&[], primary));
     }
 }
@@ -1,37 +1,21 @@
+pub mod case_files;
 pub mod lsp_client;
 pub mod runner;
 
-use std::{env, fmt, fs, io, path::PathBuf};
+use std::{
+    env,
+    ffi::OsString,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 pub use lsp_client::LspClient;
-pub use runner::{run_test, setup_workspace};
+pub use runner::{TestPackage, run_test, setup_workspace};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub enum DecoKind {
-    Lifetime,
-    ImmBorrow,
-    MutBorrow,
-    Move,
-    Call,
-    SharedMut,
-    Outlive,
-}
-
-impl fmt::Display for DecoKind {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Lifetime => write!(f, "lifetime"),
-            Self::ImmBorrow => write!(f, "imm-borrow"),
-            Self::MutBorrow => write!(f, "mut-borrow"),
-            Self::Move => write!(f, "move"),
-            Self::Call => write!(f, "call"),
-            Self::SharedMut => write!(f, "shared-mut"),
-            Self::Outlive => write!(f, "outlive"),
-        }
-    }
-}
+pub use crate::deco_kind::DecoKind;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExpectedDeco {
@@ -42,6 +26,46 @@ pub struct ExpectedDeco {
     pub line: Option<u32>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub message_contains: Option<String>,
+    /// Exact number of matching decorations, instead of at least one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<DecoSpan>,
+    /// First line of the range the matching decorations cover, see
+    /// [`Self::spanning_lines`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_from: Option<u32>,
+    /// Last line of the range the matching decorations cover
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_to: Option<u32>,
+    /// Text of the code the decoration covers exactly, resolved to
+    /// [`Self::span`] by the runner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub covering_text: Option<String>,
+    /// Whether the borrow is taken implicitly by auto-ref of a method
+    /// receiver, with a code ending in
+    /// [`AUTO_BORROW_SUFFIX`](crate::AUTO_BORROW_SUFFIX)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_borrow: Option<bool>,
+}
+
+/// Range of a decoration, in 0-based lines and characters of the test code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecoSpan {
+    pub start_line: u32,
+    pub start_char: u32,
+    pub end_line: u32,
+    pub end_char: u32,
+}
+
+impl fmt::Display for DecoSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start_line, self.start_char, self.end_line, self.end_char
+        )
+    }
 }
 
 impl ExpectedDeco {
@@ -52,6 +76,12 @@ impl ExpectedDeco {
             text_match: None,
             line: None,
             message_contains: None,
+            count: None,
+            span: None,
+            line_from: None,
+            line_to: None,
+            covering_text: None,
+            auto_borrow: None,
         }
     }
 
@@ -73,6 +103,56 @@ impl ExpectedDeco {
         self
     }
 
+    /// Expect the borrow to be taken implicitly by auto-ref of a method
+    /// receiver, or explicitly with `&`.
+    #[must_use]
+    pub const fn with_auto_borrow(mut self, implicit: bool) -> Self {
+        self.auto_borrow = Some(implicit);
+        self
+    }
+
+    /// Expect the decoration to cover exactly this range of the test code.
+    #[must_use]
+    pub const fn spanning(
+        mut self,
+        start_line: u32,
+        start_char: u32,
+        end_line: u32,
+        end_char: u32,
+    ) -> Self {
+        self.span = Some(DecoSpan {
+            start_line,
+            start_char,
+            end_line,
+            end_char,
+        });
+        self
+    }
+
+    /// Expect the decorations matching the other expectations to start on
+    /// line `from` and end on line `to` of the test code together, since a
+    /// lifetime is split into the statements it is live at.
+    #[must_use]
+    pub const fn spanning_lines(mut self, from: u32, to: u32) -> Self {
+        self.line_from = Some(from);
+        self.line_to = Some(to);
+        self
+    }
+
+    /// Expect the decoration to cover exactly the first occurrence of `text`
+    /// in the test code, or on the line given by [`Self::on_line`].
+    #[must_use]
+    pub fn covering_text(mut self, text: &str) -> Self {
+        self.covering_text = Some(text.to_string());
+        self
+    }
+
+    #[must_use]
+    pub const fn times(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+
     #[must_use]
     pub const fn move_deco() -> Self {
         Self::new(DecoKind::Move)
@@ -93,6 +173,16 @@ impl ExpectedDeco {
         Self::new(DecoKind::Call)
     }
 
+    #[must_use]
+    pub const fn drop_deco() -> Self {
+        Self::new(DecoKind::Drop)
+    }
+
+    #[must_use]
+    pub const fn dead_write() -> Self {
+        Self::new(DecoKind::DeadWrite)
+    }
+
     #[must_use]
     pub const fn lifetime() -> Self {
         Self::new(DecoKind::Lifetime)
@@ -107,6 +197,216 @@ impl ExpectedDeco {
     pub const fn outlive() -> Self {
         Self::new(DecoKind::Outlive)
     }
+
+    #[must_use]
+    pub const fn allocation() -> Self {
+        Self::new(DecoKind::Allocation)
+    }
+
+    #[must_use]
+    pub const fn summary() -> Self {
+        Self::new(DecoKind::Summary)
+    }
+
+    #[must_use]
+    pub const fn binding() -> Self {
+        Self::new(DecoKind::Binding)
+    }
+
+    #[must_use]
+    pub const fn held_across_await() -> Self {
+        Self::new(DecoKind::HeldAcrossAwait)
+    }
+
+    #[must_use]
+    pub const fn shared_clone() -> Self {
+        Self::new(DecoKind::SharedClone)
+    }
+
+    #[must_use]
+    pub const fn conflict() -> Self {
+        Self::new(DecoKind::Conflict)
+    }
+
+    #[must_use]
+    pub const fn leak_or_forget() -> Self {
+        Self::new(DecoKind::LeakOrForget)
+    }
+}
+
+/// Status of the analysis in a `ferrous-owl/cursor` response, mirroring the
+/// server's `AnalysisStatus`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStatusKind {
+    Analyzing,
+    Finished,
+    Error,
+    NoVariableSelected,
+    Stale,
+    Skipped,
+}
+
+/// Decoration that must not be reported, anywhere or only on a line or at a
+/// text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ForbiddenDecoRepr")]
+pub struct ForbiddenDeco {
+    pub kind: DecoKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_match: Option<String>,
+}
+
+/// Forbidden decorations used to be plain kinds.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ForbiddenDecoRepr {
+    Kind(DecoKind),
+    Deco {
+        kind: DecoKind,
+        #[serde(default)]
+        line: Option<u32>,
+        #[serde(default)]
+        text_match: Option<String>,
+    },
+}
+
+impl From<ForbiddenDecoRepr> for ForbiddenDeco {
+    fn from(repr: ForbiddenDecoRepr) -> Self {
+        match repr {
+            ForbiddenDecoRepr::Kind(kind) => Self::new(kind),
+            ForbiddenDecoRepr::Deco {
+                kind,
+                line,
+                text_match,
+            } => Self {
+                kind,
+                line,
+                text_match,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ForbiddenDeco {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(line) = self.line {
+            write!(f, " on line {line}")?;
+        }
+        if let Some(text) = &self.text_match {
+            write!(f, " at '{text}'")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<DecoKind> for ForbiddenDeco {
+    fn from(kind: DecoKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl ForbiddenDeco {
+    #[must_use]
+    pub const fn new(kind: DecoKind) -> Self {
+        Self {
+            kind,
+            line: None,
+            text_match: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn on_line(mut self, line: u32) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    #[must_use]
+    pub fn at_text(mut self, text: &str) -> Self {
+        self.text_match = Some(text.to_string());
+        self
+    }
+
+    /// Expected decoration matching the same diagnostics.
+    #[must_use]
+    pub fn as_expected(&self) -> ExpectedDeco {
+        ExpectedDeco {
+            line: self.line,
+            text_match: self.text_match.clone(),
+            ..ExpectedDeco::new(self.kind)
+        }
+    }
+}
+
+/// Environment variable overriding the default time in seconds to wait for
+/// analysis to finish.
+pub const ANALYSIS_TIMEOUT_ENV: &str = "FERROUS_OWL_TEST_ANALYSIS_TIMEOUT";
+
+/// Environment variable overriding the default time in seconds to wait for
+/// ownership diagnostics.
+pub const DIAGNOSTICS_TIMEOUT_ENV: &str = "FERROUS_OWL_TEST_DIAG_TIMEOUT";
+
+/// Environment variable that, set to `1`, removes test workspaces left over
+/// from earlier runs instead of failing.
+pub const CLEAN_STALE_ENV: &str = "FERROUS_OWL_TEST_CLEAN_STALE";
+
+const DEFAULT_ANALYSIS_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout set on the test case, else from the environment variable value
+/// in seconds, else the default.
+fn resolve_timeout(
+    explicit: Option<Duration>,
+    env_value: Option<&str>,
+    default: Duration,
+) -> Duration {
+    explicit
+        .or_else(|| {
+            env_value
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        })
+        .unwrap_or(default)
+}
+
+/// (De)serialize optional durations as seconds.
+mod duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    #[allow(
+        clippy::ref_option,
+        reason = "signature required by serde's `with` attribute"
+    )]
+    pub fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(duration) => serializer.serialize_some(&duration.as_secs_f64()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<f64>::deserialize(deserializer)?
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// Compiler error expected to be reported instead of decorations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpectedBuildError {
+    pub line: u32,
+    pub message_contains: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -122,29 +422,127 @@ pub struct TestCase {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub expected_decos: Vec<ExpectedDeco>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
-    pub forbidden_decos: Vec<DecoKind>,
+    pub forbidden_decos: Vec<ForbiddenDeco>,
+    /// Exact number of decorations per kind
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expected_counts: Vec<(DecoKind, u32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expected_build_errors: Vec<ExpectedBuildError>,
+    /// No decorations at all at the cursor of a finished analysis
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub expect_no_decorations: bool,
+    /// Status of the cursor response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_status: Option<AnalysisStatusKind>,
+    /// Time to wait for analysis, see [`ANALYSIS_TIMEOUT_ENV`]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "duration_secs"
+    )]
+    pub analysis_timeout: Option<Duration>,
+    /// Time to wait for ownership diagnostics, see [`DIAGNOSTICS_TIMEOUT_ENV`]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "duration_secs"
+    )]
+    pub diagnostics_timeout: Option<Duration>,
+    /// Further files of the crate as pairs of a path relative to `src/` and
+    /// its contents, see [`Self::with_file`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_files: Vec<(String, String)>,
+    /// Path of the extra file the cursor is in, else it is in the code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor_file: Option<String>,
 }
 
 impl TestCase {
+    /// Test case analyzing `code`, without its common indentation.
+    ///
+    /// # Panics
+    ///
+    /// If lines of `code` mix tabs and spaces in their common indentation,
+    /// which makes cursor columns ambiguous.
     #[must_use]
     pub fn new(name: &str, code: &str) -> Self {
+        if let Some(index) = mixed_indentation(code, TAB_WIDTH) {
+            panic!(
+                "test case `{name}` mixes tabs and spaces in the indentation of its lines, first \
+                 at line {} of the code",
+                index + 1
+            );
+        }
         Self {
             name: name.to_string(),
-            code: dedent(code),
+            code: dedent(code, TAB_WIDTH),
             cursor_text: None,
             cursor_line: None,
             cursor_char: None,
             expected_decos: Vec::new(),
             forbidden_decos: Vec::new(),
+            expected_counts: Vec::new(),
+            expected_build_errors: Vec::new(),
+            expect_no_decorations: false,
+            expected_status: None,
+            analysis_timeout: None,
+            diagnostics_timeout: None,
+            extra_files: Vec::new(),
+            cursor_file: None,
         }
     }
 
+    /// Add the file at `path`, relative to `src/` of the crate, with
+    /// `contents` without their common indentation.
+    ///
+    /// Test cases with extra files run in a crate of their own: the code is
+    /// the module `test_source`, and a `lib.rs` declaring it and the modules
+    /// of the top-level files is generated, unless one of the files is the
+    /// `lib.rs`. The code reaches the other modules by `crate::`.
+    ///
+    /// # Panics
+    ///
+    /// If lines of `contents` mix tabs and spaces in their common
+    /// indentation, like [`Self::new`].
+    #[must_use]
+    pub fn with_file(mut self, path: &str, contents: &str) -> Self {
+        if let Some(index) = mixed_indentation(contents, TAB_WIDTH) {
+            panic!(
+                "test case `{}` mixes tabs and spaces in the indentation of the lines of {path}, \
+                 first at line {}",
+                self.name,
+                index + 1
+            );
+        }
+        self.extra_files
+            .push((path.to_string(), dedent(contents, TAB_WIDTH)));
+        self
+    }
+
     #[must_use]
     pub fn cursor_on(mut self, text: &str) -> Self {
         self.cursor_text = Some(text.to_string());
         self
     }
 
+    /// Place the cursor on `text` in the extra file at `path`, see
+    /// [`Self::with_file`]. Expected decorations are then checked in that
+    /// file.
+    #[must_use]
+    pub fn cursor_in_file(mut self, path: &str, text: &str) -> Self {
+        self.cursor_file = Some(path.to_string());
+        self.cursor_on(text)
+    }
+
+    /// Contents of the file the cursor is in.
+    #[must_use]
+    pub fn cursor_code(&self) -> &str {
+        self.cursor_file
+            .as_ref()
+            .and_then(|path| self.extra_files.iter().find(|(file, _)| file == path))
+            .map_or(&self.code, |(_, contents)| contents)
+    }
+
     #[must_use]
     pub const fn cursor_at(mut self, line: u32, character: u32) -> Self {
         self.cursor_line = Some(line);
@@ -152,6 +550,38 @@ impl TestCase {
         self
     }
 
+    #[must_use]
+    pub const fn with_analysis_timeout(mut self, timeout: Duration) -> Self {
+        self.analysis_timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_diagnostics_timeout(mut self, timeout: Duration) -> Self {
+        self.diagnostics_timeout = Some(timeout);
+        self
+    }
+
+    /// Time to wait for analysis to finish.
+    #[must_use]
+    pub fn analysis_timeout(&self) -> Duration {
+        resolve_timeout(
+            self.analysis_timeout,
+            env::var(ANALYSIS_TIMEOUT_ENV).ok().as_deref(),
+            DEFAULT_ANALYSIS_TIMEOUT,
+        )
+    }
+
+    /// Time to wait for ownership diagnostics after toggling them.
+    #[must_use]
+    pub fn diagnostics_timeout(&self) -> Duration {
+        resolve_timeout(
+            self.diagnostics_timeout,
+            env::var(DIAGNOSTICS_TIMEOUT_ENV).ok().as_deref(),
+            DEFAULT_DIAGNOSTICS_TIMEOUT,
+        )
+    }
+
     #[must_use]
     pub fn expect(mut self, deco: ExpectedDeco) -> Self {
         self.expected_decos.push(deco);
@@ -163,6 +593,12 @@ impl TestCase {
         self.expect(ExpectedDeco::move_deco())
     }
 
+    /// Expect a lifetime from line `from` to line `to` of the test code.
+    #[must_use]
+    pub fn expect_lifetime_spanning_lines(self, from: u32, to: u32) -> Self {
+        self.expect(ExpectedDeco::lifetime().spanning_lines(from, to))
+    }
+
     #[must_use]
     pub fn expect_move_at(self, text: &str) -> Self {
         self.expect(ExpectedDeco::move_deco().at_text(text))
@@ -198,6 +634,16 @@ impl TestCase {
         self.expect(ExpectedDeco::call().at_text(text))
     }
 
+    #[must_use]
+    pub fn expect_drop(self) -> Self {
+        self.expect(ExpectedDeco::drop_deco())
+    }
+
+    #[must_use]
+    pub fn expect_dead_write(self) -> Self {
+        self.expect(ExpectedDeco::dead_write())
+    }
+
     #[must_use]
     pub fn expect_lifetime(self) -> Self {
         self.expect(ExpectedDeco::lifetime())
@@ -218,9 +664,18 @@ impl TestCase {
         self.expect(ExpectedDeco::outlive())
     }
 
+    /// Forbid a kind of decoration, or one on a line or at a text given as
+    /// [`ForbiddenDeco`].
     #[must_use]
-    pub fn forbid(mut self, kind: DecoKind) -> Self {
-        self.forbidden_decos.push(kind);
+    pub fn forbid(mut self, deco: impl Into<ForbiddenDeco>) -> Self {
+        self.forbidden_decos.push(deco.into());
+        self
+    }
+
+    /// Expect exactly `count` decorations of `kind`.
+    #[must_use]
+    pub fn expect_count(mut self, kind: DecoKind, count: u32) -> Self {
+        self.expected_counts.push((kind, count));
         self
     }
 
@@ -234,6 +689,21 @@ impl TestCase {
         self.forbid(DecoKind::Outlive)
     }
 
+    #[must_use]
+    pub fn forbid_drop(self) -> Self {
+        self.forbid(DecoKind::Drop)
+    }
+
+    #[must_use]
+    pub fn forbid_dead_write(self) -> Self {
+        self.forbid(DecoKind::DeadWrite)
+    }
+
+    #[must_use]
+    pub fn forbid_shared_mut(self) -> Self {
+        self.forbid(DecoKind::SharedMut)
+    }
+
     #[must_use]
     pub fn forbid_imm_borrow(self) -> Self {
         self.forbid(DecoKind::ImmBorrow)
@@ -244,17 +714,58 @@ impl TestCase {
         self.forbid(DecoKind::MutBorrow)
     }
 
+    /// Expect the code to fail to compile with an error on `line`.
+    #[must_use]
+    pub fn expect_build_error(mut self, line: u32, message: &str) -> Self {
+        self.expected_build_errors.push(ExpectedBuildError {
+            line,
+            message_contains: message.to_string(),
+        });
+        self
+    }
+
+    /// Expect neither ownership diagnostics nor decorations in the cursor
+    /// response, once the analysis finished with or without a variable at the
+    /// cursor.
+    #[must_use]
+    pub const fn expect_no_decorations(mut self) -> Self {
+        self.expect_no_decorations = true;
+        self
+    }
+
+    /// Expect the cursor response to report `status`.
+    #[must_use]
+    pub const fn expect_status(mut self, status: AnalysisStatusKind) -> Self {
+        self.expected_status = Some(status);
+        self
+    }
+
+    /// Whether the code does not compile, so that it would break the crate
+    /// of other test cases.
+    #[must_use]
+    pub fn breaks_build(&self) -> bool {
+        !self.expected_build_errors.is_empty()
+            || self.expected_status == Some(AnalysisStatusKind::Error)
+    }
+
+    /// Whether the test case needs a crate of its own, as its code does not
+    /// compile or spans several files.
+    #[must_use]
+    pub fn runs_isolated(&self) -> bool {
+        self.breaks_build() || !self.extra_files.is_empty()
+    }
+
     #[must_use]
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).expect("TestCase serialization should not fail")
     }
 
     pub fn run(&self) {
-        let owl_binary = find_owl_binary();
+        let server = TestServer::from_env().unwrap_or_else(|e| panic!("{e}"));
         let workspace_dir =
             create_test_workspace(&self.name, 0).expect("Failed to create test workspace");
 
-        let result = run_test_in_workspace(&owl_binary, self, &workspace_dir);
+        let result = run_test_in_workspace(&server, self, &workspace_dir);
         let _ = fs::remove_dir_all(&workspace_dir);
 
         assert!(
@@ -266,14 +777,56 @@ impl TestCase {
     }
 }
 
-fn dedent(code: &str) -> String {
-    let lines: Vec<&str> = code.lines().collect();
-    if lines.is_empty() {
-        return String::new();
+/// Columns a tab advances to the next multiple of in test code
+const TAB_WIDTH: usize = 4;
+
+/// Column after `c` at `column`, with tabs advancing to the next multiple of
+/// `tab_width`.
+const fn advance(column: usize, c: char, tab_width: usize) -> usize {
+    if c == '\t' {
+        column + tab_width - column % tab_width
+    } else {
+        column + 1
+    }
+}
+
+/// Width in columns of the leading whitespace of `line`.
+fn indent_width(line: &str, tab_width: usize) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .fold(0, |column, c| advance(column, c, tab_width))
+}
+
+/// `line` without its first `columns` columns of leading whitespace.
+///
+/// A tab straddling the cut is replaced by the spaces beyond it, and lines of
+/// fewer columns of whitespace become empty.
+fn strip_indent(line: &str, columns: usize, tab_width: usize) -> String {
+    let mut column = 0;
+    for (index, c) in line.char_indices() {
+        if column >= columns || !c.is_whitespace() {
+            return line[index..].to_owned();
+        }
+        column = advance(column, c, tab_width);
+        if column > columns {
+            let rest = &line[index + c.len_utf8()..];
+            return format!("{}{rest}", " ".repeat(column - columns));
+        }
     }
+    String::new()
+}
 
-    let first_non_empty = lines.iter().position(|l| !l.trim().is_empty());
-    let last_non_empty = lines.iter().rposition(|l| !l.trim().is_empty());
+fn is_non_empty(line: &str) -> bool {
+    !line.trim().is_empty()
+}
+
+/// Remove the leading and trailing empty lines of `code` and the indentation
+/// common to its other lines, measured in columns.
+fn dedent(code: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let lines: Vec<&str> = code.lines().collect();
+    let first_non_empty = lines.iter().position(|l| is_non_empty(l));
+    let last_non_empty = lines.iter().rposition(|l| is_non_empty(l));
 
     let (Some(start), Some(end)) = (first_non_empty, last_non_empty) else {
         return String::new();
@@ -283,24 +836,44 @@ fn dedent(code: &str) -> String {
 
     let min_indent = trimmed_lines
         .iter()
-        .filter(|l| !l.trim().is_empty())
-        .map(|l| l.len() - l.trim_start().len())
+        .filter(|l| is_non_empty(l))
+        .map(|l| indent_width(l, tab_width))
         .min()
         .unwrap_or(0);
 
     trimmed_lines
         .iter()
-        .map(|l| {
-            if l.len() >= min_indent {
-                &l[min_indent..]
-            } else {
-                l.trim_start()
-            }
-        })
+        .map(|l| strip_indent(l, min_indent, tab_width))
         .collect::<Vec<_>>()
         .join("\n")
 }
 
+/// Index of the first line of `code` that indents with tabs where an earlier
+/// line indents with spaces, or the other way around, within the indentation
+/// common to all non-empty lines.
+fn mixed_indentation(code: &str, tab_width: usize) -> Option<usize> {
+    let tab_width = tab_width.max(1);
+    let min_indent = code
+        .lines()
+        .filter(|l| is_non_empty(l))
+        .map(|l| indent_width(l, tab_width))
+        .min()?;
+    let mut seen = None;
+    for (index, line) in code.lines().enumerate().filter(|(_, l)| is_non_empty(l)) {
+        let mut column = 0;
+        for c in line.chars() {
+            if column >= min_indent {
+                break;
+            }
+            if matches!(c, ' ' | '\t') && *seen.get_or_insert(c) != c {
+                return Some(index);
+            }
+            column = advance(column, c, tab_width);
+        }
+    }
+    None
+}
+
 #[derive(Debug)]
 pub struct TestResult {
     pub name: String,
@@ -308,17 +881,79 @@ pub struct TestResult {
     pub error: Option<String>,
 }
 
-/// Run multiple test cases in parallel and assert all pass.
-/// This is much more efficient than running each test individually.
-/// Uses in-process LSP testing instead of spawning cargo subprocesses.
+impl From<runner::TestResult> for TestResult {
+    fn from(result: runner::TestResult) -> Self {
+        Self {
+            name: result.name,
+            passed: result.passed,
+            error: if result.passed {
+                None
+            } else {
+                Some(result.message)
+            },
+        }
+    }
+}
+
+/// Environment variable that makes [`run_tests`] run every test case against
+/// its own server and workspace instead of batching them, for debugging.
+pub const ISOLATED_TESTS_ENV: &str = "FERROUS_OWL_ISOLATED_TESTS";
+
+/// Run multiple test cases and assert all pass.
+///
+/// All test cases are analyzed together in one workspace by a single server,
+/// each in its own module. Set [`ISOLATED_TESTS_ENV`] to run every test case
+/// against a fresh server instead. Test cases expecting build errors or an
+/// analysis error always run isolated, as they would break the shared crate,
+/// and so do test cases with extra files, which bring a crate of their own.
 pub fn run_tests(tests: &[TestCase]) {
     use std::fmt::Write;
 
-    use rayon::prelude::*;
+    let server = TestServer::from_env().unwrap_or_else(|e| panic!("{e}"));
+
+    let results = if env::var_os(ISOLATED_TESTS_ENV).is_some() {
+        run_isolated(&server, tests)
+    } else {
+        let (broken, compiling): (Vec<_>, Vec<_>) =
+            tests.iter().cloned().partition(TestCase::runs_isolated);
+        let mut results = run_batched(&server, &compiling);
+        results.extend(run_isolated(&server, &broken));
+        results
+    };
+
+    let failures: Vec<_> = results.iter().filter(|r| !r.passed).collect();
+
+    if !failures.is_empty() {
+        let mut msg = format!("{} test(s) failed:\n", failures.len());
+        for f in &failures {
+            let _ = write!(
+                msg,
+                "\n--- {} ---\n{}\n",
+                f.name,
+                f.error.as_deref().unwrap_or("")
+            );
+        }
+        panic!("{msg}");
+    }
+
+    eprintln!("{} passed, 0 failed", results.len());
+}
 
-    let owl_binary = find_owl_binary();
+/// Run the test cases of all `*.toml` and `*.json` files in `dir`, see
+/// [`case_files`], and assert all pass.
+///
+/// All files are validated before any server starts, failing with the
+/// problems of every file at once.
+pub fn run_tests_from_dir(dir: impl AsRef<Path>) {
+    let tests = case_files::load_test_cases(dir.as_ref()).unwrap_or_else(|e| panic!("{e}"));
+    run_tests(&tests);
+}
 
-    let results: Vec<_> = tests
+/// Run each test case in parallel in its own workspace and server.
+fn run_isolated(server: &TestServer, tests: &[TestCase]) -> Vec<TestResult> {
+    use rayon::prelude::*;
+
+    tests
         .par_iter()
         .enumerate()
         .map(|(index, test)| {
@@ -333,32 +968,153 @@ pub fn run_tests(tests: &[TestCase]) {
                 }
             };
 
-            let result = run_test_in_workspace(&owl_binary, test, &workspace_dir);
+            let result = run_test_in_workspace(server, test, &workspace_dir);
 
             let _ = fs::remove_dir_all(&workspace_dir);
             result
         })
-        .collect();
+        .collect()
+}
 
-    let failures: Vec<_> = results.iter().filter(|r| !r.passed).collect();
+/// Run all test cases in one workspace analyzed by a single server.
+fn run_batched(server: &TestServer, tests: &[TestCase]) -> Vec<TestResult> {
+    static BATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-    if !failures.is_empty() {
-        let mut msg = format!("{} test(s) failed:\n", failures.len());
-        for f in &failures {
-            let _ = write!(
-                msg,
-                "\n--- {} ---\n{}\n",
-                f.name,
-                f.error.as_deref().unwrap_or("")
-            );
+    let batch = BATCH_COUNT.fetch_add(1, Ordering::Relaxed);
+    let results = create_test_workspace("batch", batch).and_then(|workspace_dir| {
+        let results = run_batch_in_workspace(server, tests, &workspace_dir);
+        let _ = fs::remove_dir_all(&workspace_dir);
+        results
+    });
+
+    results.unwrap_or_else(|e| {
+        tests
+            .iter()
+            .map(|test| TestResult {
+                name: test.name.clone(),
+                passed: false,
+                error: Some(format!("Batch error: {e}")),
+            })
+            .collect()
+    })
+}
+
+/// Environment variable with the path of a prebuilt ferrous-owl binary that
+/// [`run_tests`] starts instead of the one built next to the test executable.
+pub const OWL_BINARY_ENV: &str = "FERROUS_OWL_TEST_BIN";
+
+/// Environment variable with the `host:port` of a running server, started by
+/// `ferrous-owl --listen`, that [`run_tests`] connects to instead of starting
+/// servers itself.
+///
+/// Such a server serves one client at a time and refuses others meanwhile,
+/// so test runs against it must not run in parallel, e.g. with
+/// `--test-threads 1`.
+pub const LSP_ADDR_ENV: &str = "FERROUS_OWL_TEST_LSP_ADDR";
+
+/// Time after which a started server is killed, failing its tests instead of
+/// hanging them
+const SERVER_WATCHDOG: Duration = Duration::from_secs(20 * 60);
+
+/// Number of lines at the end of the stderr of a server shown with failures
+const SERVER_LOG_LINES: usize = 40;
+
+/// Language server the test cases run against
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TestServer {
+    /// Binary built next to the test executable, started for every workspace
+    Built(PathBuf),
+    /// Binary from [`OWL_BINARY_ENV`], started for every workspace
+    Binary(PathBuf),
+    /// Running server at the address from [`LSP_ADDR_ENV`]
+    Address(String),
+}
+
+impl TestServer {
+    /// Server chosen by [`OWL_BINARY_ENV`] and [`LSP_ADDR_ENV`], else the
+    /// built binary.
+    pub fn from_env() -> io::Result<Self> {
+        Self::select(
+            env::var_os(OWL_BINARY_ENV),
+            env::var_os(LSP_ADDR_ENV),
+            find_owl_binary,
+        )
+    }
+
+    /// Server for the values of [`OWL_BINARY_ENV`] and [`LSP_ADDR_ENV`];
+    /// empty values count as unset. `find_built` is only called if neither
+    /// is set.
+    fn select(
+        binary: Option<OsString>,
+        address: Option<OsString>,
+        find_built: impl FnOnce() -> io::Result<PathBuf>,
+    ) -> io::Result<Self> {
+        let binary = binary.filter(|value| !value.is_empty());
+        let address = address.filter(|value| !value.is_empty());
+        match (binary, address) {
+            (Some(_), Some(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{OWL_BINARY_ENV} and {LSP_ADDR_ENV} are both set, unset one of them"),
+            )),
+            (Some(binary), None) => {
+                let path = PathBuf::from(binary);
+                if path.is_file() {
+                    Ok(Self::Binary(path))
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "{OWL_BINARY_ENV} is set to {}, which is not a file",
+                            path.display()
+                        ),
+                    ))
+                }
+            }
+            (None, Some(address)) => address.into_string().map(Self::Address).map_err(|value| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{LSP_ADDR_ENV} is not a valid address: {}", value.display()),
+                )
+            }),
+            (None, None) => find_built().map(Self::Built),
         }
-        panic!("{msg}");
     }
 
-    eprintln!("{} passed, 0 failed", results.len());
+    /// Start a server, or connect to the running one.
+    pub fn start(&self) -> io::Result<LspClient> {
+        let context =
+            |e: io::Error, attempt: String| io::Error::new(e.kind(), format!("{attempt}: {e}"));
+        match self {
+            Self::Built(path) => LspClient::start(&path.to_string_lossy(), &[])
+                .map(|client| client.with_watchdog(SERVER_WATCHDOG))
+                .map_err(|e| {
+                    context(
+                        e,
+                        format!("failed to start the built server {}", path.display()),
+                    )
+                }),
+            Self::Binary(path) => LspClient::start(&path.to_string_lossy(), &[])
+                .map(|client| client.with_watchdog(SERVER_WATCHDOG))
+                .map_err(|e| {
+                    context(
+                        e,
+                        format!(
+                            "failed to start the server {} from {OWL_BINARY_ENV}",
+                            path.display()
+                        ),
+                    )
+                }),
+            Self::Address(address) => LspClient::connect(address.as_str()).map_err(|e| {
+                context(
+                    e,
+                    format!("failed to connect to the server at {address} from {LSP_ADDR_ENV}"),
+                )
+            }),
+        }
+    }
 }
 
-fn find_owl_binary() -> String {
+fn find_owl_binary() -> io::Result<PathBuf> {
     let exe_dir = env::current_exe()
         .ok()
         .and_then(|p| p.parent().map(PathBuf::from));
@@ -367,19 +1123,25 @@ fn find_owl_binary() -> String {
         // Check same directory (when run from target/debug)
         let owl = dir.join("ferrous-owl");
         if owl.exists() {
-            return owl.to_string_lossy().to_string();
+            return Ok(owl);
         }
 
         // Check parent's deps directory (when run as test executable)
         if let Some(parent) = dir.parent() {
             let owl = parent.join("ferrous-owl");
             if owl.exists() {
-                return owl.to_string_lossy().to_string();
+                return Ok(owl);
             }
         }
     }
 
-    panic!("Could not find ferrous-owl binary. Run `cargo build` first.");
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "Could not find ferrous-owl binary. Run `cargo build` first, or set {OWL_BINARY_ENV} \
+             or {LSP_ADDR_ENV}."
+        ),
+    ))
 }
 
 fn create_test_workspace(test_name: &str, index: usize) -> io::Result<String> {
@@ -393,13 +1155,47 @@ fn create_test_workspace(test_name: &str, index: usize) -> io::Result<String> {
     setup_workspace(&base_dir.to_string_lossy(), &workspace_name)
 }
 
-fn run_test_in_workspace(owl_binary: &str, test: &TestCase, workspace_dir: &str) -> TestResult {
+/// Server options of test runs: test code is analyzed as well, so files
+/// shared by the library and its test harness are covered.
+fn initialization_options() -> serde_json::Value {
+    serde_json::json!({ "target_kinds": ["lib", "test"] })
+}
+
+fn run_batch_in_workspace(
+    server: &TestServer,
+    tests: &[TestCase],
+    workspace_dir: &str,
+) -> io::Result<Vec<TestResult>> {
+    let files = runner::write_batch_workspace(tests, workspace_dir)?;
+
+    let mut client = server.start()?;
+    let workspace_uri = format!("file://{workspace_dir}");
+    client.initialize_with_options(&workspace_uri, &initialization_options())?;
+
+    let results = runner::run_batch(&mut client, tests, &files);
+
+    let _ = client.shutdown();
+
+    let results =
+        results.map_err(|e| io::Error::new(e.kind(), with_server_log(e.to_string(), &client)))?;
+    Ok(results
+        .into_iter()
+        .map(|mut result| {
+            if !result.passed {
+                result.message = with_server_log(result.message, &client);
+            }
+            TestResult::from(result)
+        })
+        .collect())
+}
+
+fn run_test_in_workspace(server: &TestServer, test: &TestCase, workspace_dir: &str) -> TestResult {
     let result = (|| -> io::Result<TestResult> {
-        let mut client = LspClient::start(owl_binary, &[])?;
+        let mut client = server.start()?;
         let workspace_uri = format!("file://{workspace_dir}");
-        client.initialize(&workspace_uri)?;
+        client.initialize_with_options(&workspace_uri, &initialization_options())?;
 
-        let result =
+        let mut result =
             run_test(&mut client, test, workspace_dir).unwrap_or_else(|e| runner::TestResult {
                 name: test.name.clone(),
                 passed: false,
@@ -407,16 +1203,11 @@ fn run_test_in_workspace(owl_binary: &str, test: &TestCase, workspace_dir: &str)
             });
 
         let _ = client.shutdown();
+        if !result.passed {
+            result.message = with_server_log(result.message, &client);
+        }
 
-        Ok(TestResult {
-            name: result.name,
-            passed: result.passed,
-            error: if result.passed {
-                None
-            } else {
-                Some(result.message)
-            },
-        })
+        Ok(TestResult::from(result))
     })();
 
     result.unwrap_or_else(|e| TestResult {
@@ -425,3 +1216,231 @@ fn run_test_in_workspace(owl_binary: &str, test: &TestCase, workspace_dir: &str)
         error: Some(format!("LSP client error: {e}")),
     })
 }
+
+/// `message` followed by the end of the stderr of the server of `client`, if
+/// it wrote any.
+fn with_server_log(message: String, client: &LspClient) -> String {
+    let Some(log) = client.server_log().filter(|log| !log.trim().is_empty()) else {
+        return message;
+    };
+    let lines: Vec<_> = log.lines().collect();
+    let tail = lines[lines.len().saturating_sub(SERVER_LOG_LINES)..].join("\n");
+    format!("{message}\n\nstderr of the server:\n{tail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeouts_serde_round_trip() {
+        let test = TestCase::new("timeouts", "fn test() {}")
+            .with_analysis_timeout(Duration::from_millis(1500))
+            .with_diagnostics_timeout(Duration::from_secs(5));
+        let json = test.to_json();
+        assert!(json.contains(r#""analysis_timeout":1.5"#));
+        assert!(json.contains(r#""diagnostics_timeout":5.0"#));
+
+        let parsed: TestCase = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.analysis_timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(parsed.diagnostics_timeout, Some(Duration::from_secs(5)));
+
+        let unset: TestCase = serde_json::from_str(&TestCase::new("unset", "").to_json()).unwrap();
+        assert_eq!(unset.analysis_timeout, None);
+        assert_eq!(unset.diagnostics_timeout, None);
+    }
+
+    #[test]
+    fn test_extra_files_serde_round_trip() {
+        let test = TestCase::new("files", "fn test() {}")
+            .with_file("helper.rs", "\n    pub fn take(_: String) {}\n")
+            .cursor_in_file("helper.rs", "take");
+        let json = test.to_json();
+        assert!(json.contains(r#""extra_files":[["helper.rs","pub fn take(_: String) {}"]]"#));
+        assert!(json.contains(r#""cursor_file":"helper.rs""#));
+
+        let parsed: TestCase = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.cursor_code(), "pub fn take(_: String) {}");
+        assert_eq!(parsed.cursor_text.as_deref(), Some("take"));
+        assert!(parsed.runs_isolated());
+
+        let single = TestCase::new("single", "fn test() {}");
+        assert!(!single.to_json().contains("extra_files"));
+        assert_eq!(single.cursor_code(), "fn test() {}");
+        assert!(!single.runs_isolated());
+    }
+
+    #[test]
+    fn test_forbidden_decos_accept_plain_kinds() {
+        let test: TestCase = serde_json::from_str(
+            r#"{"name":"old","code":"","forbidden_decos":["move",{"kind":"drop","line":3}]}"#,
+        )
+        .unwrap();
+        let forbidden: Vec<_> = test
+            .forbidden_decos
+            .iter()
+            .map(|deco| (deco.kind, deco.line))
+            .collect();
+        assert_eq!(
+            forbidden,
+            vec![(DecoKind::Move, None), (DecoKind::Drop, Some(3))]
+        );
+    }
+
+    #[test]
+    fn test_forbidden_decos_and_counts_serde_round_trip() {
+        let test = TestCase::new("scoped", "fn test() {}")
+            .forbid(DecoKind::Move)
+            .forbid(ForbiddenDeco::new(DecoKind::Call).on_line(2).at_text("f"))
+            .expect_count(DecoKind::ImmBorrow, 2);
+        let json = test.to_json();
+        assert!(json.contains(r#""expected_counts":[["imm-borrow",2]]"#));
+
+        let parsed: TestCase = serde_json::from_str(&json).unwrap();
+        let call = &parsed.forbidden_decos[1];
+        assert_eq!(parsed.forbidden_decos[0].kind, DecoKind::Move);
+        assert_eq!(parsed.forbidden_decos[0].line, None);
+        assert_eq!(call.kind, DecoKind::Call);
+        assert_eq!(call.line, Some(2));
+        assert_eq!(call.text_match.as_deref(), Some("f"));
+        assert_eq!(parsed.expected_counts, vec![(DecoKind::ImmBorrow, 2)]);
+    }
+
+    #[test]
+    fn test_timeout_precedence() {
+        let default = Duration::from_secs(30);
+        let explicit = Some(Duration::from_secs(2));
+        assert_eq!(
+            resolve_timeout(explicit, Some("60"), default),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            resolve_timeout(None, Some("60"), default),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            resolve_timeout(None, Some(" 0.5 "), default),
+            Duration::from_millis(500)
+        );
+        assert_eq!(resolve_timeout(None, Some("soon"), default), default);
+        assert_eq!(resolve_timeout(None, Some("-1"), default), default);
+        assert_eq!(resolve_timeout(None, None, default), default);
+    }
+
+    #[test]
+    fn test_dedent_spaces() {
+        let code = "\n        fn test() {\n            let x = 1;\n        }\n    ";
+        assert_eq!(dedent(code, 4), "fn test() {\n    let x = 1;\n}");
+    }
+
+    #[test]
+    fn test_dedent_tabs() {
+        let code = "\n\t\tfn test() {\n\t\t\tlet x = 1;\n\t\t}\n";
+        assert_eq!(dedent(code, 4), "fn test() {\n\tlet x = 1;\n}");
+    }
+
+    #[test]
+    fn test_dedent_mixed_indentation() {
+        // a tab reaches column 4 like four spaces
+        let code = "    fn test() {\n\t    let x = 1;\n    }";
+        assert_eq!(dedent(code, 4), "fn test() {\n    let x = 1;\n}");
+        // a tab straddling the common indentation leaves its remaining columns
+        let code = "  a\n\tb";
+        assert_eq!(dedent(code, 4), "a\n  b");
+        assert_eq!(dedent(code, 8), "a\n      b");
+    }
+
+    #[test]
+    fn test_dedent_short_and_non_ascii_lines() {
+        // whitespace-only lines shorter than the common indentation
+        let code = "    let a = 1;\n  \n\n    let b = 2;";
+        assert_eq!(dedent(code, 4), "let a = 1;\n\n\nlet b = 2;");
+        // multi-byte characters are never split
+        let code = "\u{3000}\u{3000}let é = 1;\n\u{3000}\u{3000}\u{3000}é";
+        assert_eq!(dedent(code, 4), "let é = 1;\n\u{3000}é");
+        assert_eq!(dedent(" \n\t\n", 4), "");
+    }
+
+    #[test]
+    fn test_mixed_indentation() {
+        assert_eq!(mixed_indentation("    a\n        b", 4), None);
+        assert_eq!(mixed_indentation("\ta\n\t\tb", 4), None);
+        assert_eq!(mixed_indentation("\ta\n    b", 4), Some(1));
+        assert_eq!(mixed_indentation("\n    a\n\n\t b", 4), Some(3));
+        // tabs and spaces beyond the common indentation are unambiguous
+        assert_eq!(mixed_indentation("  a\n  \tb", 4), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "mixes tabs and spaces")]
+    fn test_case_rejects_mixed_indentation() {
+        let _ = TestCase::new("mixed", "\n    fn test() {\n\t}\n");
+    }
+
+    #[allow(clippy::unnecessary_wraps, reason = "stands in for `find_owl_binary`")]
+    fn built() -> io::Result<PathBuf> {
+        Ok(PathBuf::from("/built/ferrous-owl"))
+    }
+
+    fn not_built() -> io::Result<PathBuf> {
+        Err(io::Error::other("the built binary must not be looked up"))
+    }
+
+    #[test]
+    fn test_server_defaults_to_built_binary() {
+        let server = TestServer::select(None, None, built).unwrap();
+        assert_eq!(
+            server,
+            TestServer::Built(PathBuf::from("/built/ferrous-owl"))
+        );
+        let server = TestServer::select(Some("".into()), Some("".into()), built).unwrap();
+        assert_eq!(
+            server,
+            TestServer::Built(PathBuf::from("/built/ferrous-owl"))
+        );
+    }
+
+    #[test]
+    fn test_server_from_binary_env() {
+        let binary = tempfile::NamedTempFile::new().unwrap();
+        let server = TestServer::select(Some(binary.path().into()), None, not_built).unwrap();
+        assert_eq!(server, TestServer::Binary(binary.path().to_path_buf()));
+
+        let error =
+            TestServer::select(Some("/missing/ferrous-owl".into()), None, not_built).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains(OWL_BINARY_ENV));
+        assert!(error.to_string().contains("/missing/ferrous-owl"));
+    }
+
+    #[test]
+    fn test_server_from_address_env() {
+        let server = TestServer::select(None, Some("127.0.0.1:9257".into()), not_built).unwrap();
+        assert_eq!(server, TestServer::Address("127.0.0.1:9257".to_owned()));
+
+        let error = TestServer::select(
+            Some("/bin/sh".into()),
+            Some("127.0.0.1:9257".into()),
+            not_built,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains(OWL_BINARY_ENV));
+        assert!(error.to_string().contains(LSP_ADDR_ENV));
+    }
+
+    #[test]
+    fn test_server_connection_error_names_mode() {
+        use std::net::UdpSocket;
+
+        // bound but not listening, so connecting is refused
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        let Err(error) = TestServer::Address(address.clone()).start() else {
+            panic!("connected to {address}");
+        };
+        let message = error.to_string();
+        assert!(message.contains(LSP_ADDR_ENV), "{message}");
+        assert!(message.contains(&address), "{message}");
+    }
+}
@@ -0,0 +1,332 @@
+//! Test cases read from TOML and JSON files, one test case per file.
+//!
+//! The files mirror the fields of [`TestCase`], for example:
+//!
+//! ```toml
+//! name = "move_to_drop"
+//! code = """
+//!     fn test() {
+//!         let s = String::new();
+//!         drop(s);
+//!     }
+//! """
+//! cursor_text = "s = String"
+//!
+//! [[expected_decos]]
+//! kind = "move"
+//! line = 2
+//! ```
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    error, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use super::{DecoKind, TAB_WIDTH, TestCase, dedent, mixed_indentation};
+
+/// Problems found in the test case files of a directory, all reported at
+/// once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTestCases(pub Vec<String>);
+
+impl fmt::Display for InvalidTestCases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} problem(s) in test case files:", self.0.len())?;
+        for problem in &self.0 {
+            write!(f, "\n  {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for InvalidTestCases {}
+
+/// Formats of test case files, by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Test cases of all `*.toml` and `*.json` files in `dir`, in the order of
+/// their file names.
+///
+/// Every file is checked before any is returned: for syntax, unknown
+/// decoration kinds, empty or mixed-indented code, and names used by more
+/// than one file.
+pub fn load_test_cases(dir: &Path) -> Result<Vec<TestCase>, InvalidTestCases> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        InvalidTestCases(vec![format!(
+            "{}: cannot read directory: {e}",
+            dir.display()
+        )])
+    })?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && Format::of(path).is_some())
+        .collect();
+    paths.sort();
+
+    let mut problems = Vec::new();
+    let mut cases = Vec::new();
+    for path in paths {
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| vec![format!("cannot read file: {e}")])
+            .and_then(|text| parse_test_case(&path, &text));
+        match parsed {
+            Ok(case) => cases.push((path, case)),
+            Err(file_problems) => problems.extend(
+                file_problems
+                    .into_iter()
+                    .map(|problem| format!("{}: {problem}", path.display())),
+            ),
+        }
+    }
+    problems.extend(duplicate_names(&cases));
+
+    if problems.is_empty() {
+        Ok(cases.into_iter().map(|(_, case)| case).collect())
+    } else {
+        Err(InvalidTestCases(problems))
+    }
+}
+
+/// Test case of the file at `path` with contents `text`, with its code and
+/// extra files dedented like [`TestCase::new`] and [`TestCase::with_file`]
+/// do.
+fn parse_test_case(path: &Path, text: &str) -> Result<TestCase, Vec<String>> {
+    let value = match Format::of(path) {
+        Some(Format::Toml) => toml::from_str::<Value>(text).map_err(|e| e.message().to_owned()),
+        Some(Format::Json) => serde_json::from_str::<Value>(text).map_err(|e| e.to_string()),
+        None => Err("not a .toml or .json file".to_owned()),
+    }
+    .map_err(|e| vec![format!("invalid syntax: {e}")])?;
+
+    let unknown_kinds = unknown_kinds(&value);
+    if !unknown_kinds.is_empty() {
+        return Err(unknown_kinds);
+    }
+    let mut case: TestCase =
+        serde_json::from_value(value).map_err(|e| vec![format!("invalid test case: {e}")])?;
+
+    if case.code.trim().is_empty() {
+        return Err(vec![format!("test case `{}` has empty code", case.name)]);
+    }
+    if let Some(index) = mixed_indentation(&case.code, TAB_WIDTH) {
+        return Err(vec![format!(
+            "test case `{}` mixes tabs and spaces in the indentation of its lines, first at line \
+             {} of the code",
+            case.name,
+            index + 1
+        )]);
+    }
+    case.code = dedent(&case.code, TAB_WIDTH);
+    for (path, contents) in &mut case.extra_files {
+        if let Some(index) = mixed_indentation(contents, TAB_WIDTH) {
+            return Err(vec![format!(
+                "test case `{}` mixes tabs and spaces in the indentation of the lines of {path}, \
+                 first at line {}",
+                case.name,
+                index + 1
+            )]);
+        }
+        *contents = dedent(contents, TAB_WIDTH);
+    }
+    Ok(case)
+}
+
+/// Unknown decoration kinds in the expected, forbidden and counted
+/// decorations of a test case file, which would otherwise only report the
+/// first one.
+fn unknown_kinds(value: &Value) -> Vec<String> {
+    let kind_of = |field: &str, entry: &Value| match field {
+        "expected_decos" => entry.get("kind").cloned(),
+        "forbidden_decos" => entry.get("kind").cloned().or_else(|| Some(entry.clone())),
+        _ => entry.get(0).cloned(),
+    };
+    let mut problems = Vec::new();
+    for field in ["expected_decos", "forbidden_decos", "expected_counts"] {
+        let Some(entries) = value.get(field).and_then(Value::as_array) else {
+            continue;
+        };
+        for (index, entry) in entries.iter().enumerate() {
+            if let Some(Value::String(kind)) = kind_of(field, entry)
+                && let Err(e) = kind.parse::<DecoKind>()
+            {
+                problems.push(format!("{e} in {field}[{index}]"));
+            }
+        }
+    }
+    problems
+}
+
+/// Problems for test case names used by more than one file.
+fn duplicate_names(cases: &[(PathBuf, TestCase)]) -> Vec<String> {
+    let mut first_paths: HashMap<&str, &Path> = HashMap::new();
+    let mut problems = Vec::new();
+    for (path, case) in cases {
+        match first_paths.entry(&case.name) {
+            Entry::Occupied(first) => problems.push(format!(
+                "{}: test case name `{}` is already used by {}",
+                path.display(),
+                case.name,
+                first.get().display()
+            )),
+            Entry::Vacant(entry) => {
+                entry.insert(path);
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOVE_TOML: &str = r#"
+name = "move_to_drop"
+cursor_text = "s = String"
+code = """
+    fn test() {
+        let s = String::new();
+        drop(s);
+    }
+"""
+
+[[expected_decos]]
+kind = "move"
+line = 2
+"#;
+
+    fn write_cases(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, text) in files {
+            fs::write(dir.path().join(name), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_toml_case_is_dedented() {
+        let case = parse_test_case(Path::new("move.toml"), MOVE_TOML).unwrap();
+        assert_eq!(case.name, "move_to_drop");
+        assert_eq!(
+            case.code,
+            "fn test() {\n    let s = String::new();\n    drop(s);\n}"
+        );
+        assert_eq!(case.cursor_text.as_deref(), Some("s = String"));
+        assert_eq!(case.expected_decos[0].kind, DecoKind::Move);
+        assert_eq!(case.expected_decos[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_json_case() {
+        let json = r#"{
+            "name": "mut_borrow_push",
+            "code": "fn test() {\n    let mut v = Vec::new();\n    v.push(1);\n}",
+            "cursor_line": 1,
+            "cursor_char": 12,
+            "expected_decos": [{ "kind": "mut-borrow", "line": 2 }],
+            "forbidden_decos": ["move", "imm_borrow"]
+        }"#;
+        let case = parse_test_case(Path::new("mut_borrow.json"), json).unwrap();
+        assert_eq!((case.cursor_line, case.cursor_char), (Some(1), Some(12)));
+        assert_eq!(case.expected_decos[0].kind, DecoKind::MutBorrow);
+        assert_eq!(case.forbidden_decos[0].kind, DecoKind::Move);
+        // the names of the `type` field of decorations are accepted as well
+        assert_eq!(case.forbidden_decos[1].kind, DecoKind::ImmBorrow);
+    }
+
+    #[test]
+    fn test_unknown_kinds_are_all_reported() {
+        let toml = r#"
+name = "typos"
+code = "fn test() {}"
+forbidden_decos = ["mut_borow"]
+expected_counts = [["move", 1], ["moves", 2]]
+
+[[expected_decos]]
+kind = "borrow"
+"#;
+        assert_eq!(
+            parse_test_case(Path::new("typos.toml"), toml).unwrap_err(),
+            vec![
+                "unknown decoration kind `borrow` in expected_decos[0]",
+                "unknown decoration kind `mut_borow` in forbidden_decos[0]",
+                "unknown decoration kind `moves` in expected_counts[1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_code_and_missing_fields() {
+        let empty = "name = \"empty\"\ncode = \"\"\"\n   \n\"\"\"\n";
+        assert_eq!(
+            parse_test_case(Path::new("empty.toml"), empty).unwrap_err(),
+            vec!["test case `empty` has empty code"]
+        );
+        let nameless = r#"{ "code": "fn test() {}" }"#;
+        assert_eq!(
+            parse_test_case(Path::new("nameless.json"), nameless).unwrap_err(),
+            vec!["invalid test case: missing field `name`"]
+        );
+        assert!(
+            parse_test_case(Path::new("broken.toml"), "name = ").unwrap_err()[0]
+                .starts_with("invalid syntax: "),
+            "syntax errors should be reported as such"
+        );
+    }
+
+    #[test]
+    fn test_directory_problems_are_collected() {
+        let duplicate = MOVE_TOML.replace("[[expected_decos]]", "[[forbidden_decos]]");
+        let dir = write_cases(&[
+            ("a.toml", MOVE_TOML),
+            ("b.toml", &duplicate),
+            ("c.json", r#"{ "name": "c", "code": "" }"#),
+            ("notes.txt", "not a test case"),
+        ]);
+        let InvalidTestCases(problems) = load_test_cases(dir.path()).unwrap_err();
+        let path = |name: &str| dir.path().join(name).display().to_string();
+        assert_eq!(
+            problems,
+            vec![
+                format!("{}: test case `c` has empty code", path("c.json")),
+                format!(
+                    "{}: test case name `move_to_drop` is already used by {}",
+                    path("b.toml"),
+                    path("a.toml")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_cases_in_file_name_order() {
+        let dir = write_cases(&[
+            ("b.json", r#"{ "name": "b", "code": "fn b() {}" }"#),
+            ("a.toml", MOVE_TOML),
+        ]);
+        let names: Vec<_> = load_test_cases(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|case| case.name)
+            .collect();
+        assert_eq!(names, vec!["move_to_drop", "b"]);
+    }
+}
@@ -2,51 +2,107 @@
 
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Result, Write},
-    process::{Child, ChildStdin, ChildStdout, Command, Stdio, id as process_id},
-    sync::mpsc::{self, Receiver, Sender},
+    env, error, fmt,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio, id as process_id},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
+use serde::Deserialize;
 use serde_json::{Value, json};
 
-use super::ExpectedDeco;
-use crate::models::Loc;
+use super::{AnalysisStatusKind, DecoKind, DecoSpan, ExpectedDeco};
+use crate::{deco_kind::DiagnosticCode, lsp_framing, models::LineCol};
+
+/// Time diagnostics are awaited for after the response to a toggle, see
+/// [`LspClient::toggle_ownership_and_wait`]
+const PUBLISH_WAIT: Duration = Duration::from_secs(1);
 
 /// Received diagnostic from LSP.
 #[derive(Debug, Clone)]
 pub struct ReceivedDiagnostic {
     pub code: String,
-    pub line: Loc,
+    pub start: LineCol,
+    pub end: LineCol,
     pub message: String,
 }
 
 impl ReceivedDiagnostic {
     /// Parse from LSP diagnostic JSON.
     pub fn from_lsp(value: &Value) -> Option<Self> {
-        let code = value.get("code")?.as_str()?;
+        let code = value.get("code").and_then(Value::as_str).unwrap_or("");
         let range = value.get("range")?;
         let start = range.get("start")?;
+        let end = range.get("end")?;
         let message = value.get("message").and_then(Value::as_str).unwrap_or("");
-
-        let line = Loc::from(start.get("line")?.as_u64()?);
+        let position = |position: &Value| {
+            let number = |key| u32::try_from(position.get(key)?.as_u64()?).ok();
+            Some(LineCol::new(number("line")?, number("character")?))
+        };
 
         Some(Self {
             code: code.to_string(),
-            line,
+            start: position(start)?,
+            end: position(end)?,
             message: message.to_string(),
         })
     }
 
+    /// Parse from a decoration of a `ferrous-owl/cursor` response, with the
+    /// code of its diagnostic.
+    ///
+    /// Lifetimes are only received this way, as they are not published as
+    /// diagnostics.
+    pub fn from_decoration(value: &Value) -> Option<Self> {
+        let kind: DecoKind = value.get("type")?.as_str()?.parse().ok()?;
+        let implicit = value.get("implicit").and_then(Value::as_bool) == Some(true);
+        Self::from_lsp(&json!({
+            "code": if implicit { kind.auto_borrow_code() } else { kind.diagnostic_code() },
+            "range": value.get("range")?,
+            "message": value.get("hover_text"),
+        }))
+    }
+
+    /// Range of the diagnostic.
+    #[must_use]
+    pub const fn span(&self) -> DecoSpan {
+        DecoSpan {
+            start_line: self.start.line,
+            start_char: self.start.character,
+            end_line: self.end.line,
+            end_char: self.end.character,
+        }
+    }
+
+    /// Kind of decoration, or `None` if the code is not one of a decoration.
+    #[must_use]
+    pub fn kind(&self) -> Option<DecoKind> {
+        DiagnosticCode::parse(&self.code).map(|code| code.kind)
+    }
+
+    /// Whether the code is one of an implicit borrow of a method receiver.
+    #[must_use]
+    pub fn is_auto_borrow(&self) -> bool {
+        DiagnosticCode::parse(&self.code).is_some_and(|code| code.auto_borrow)
+    }
+
     /// Check if this diagnostic matches an expected decoration.
     #[must_use]
     pub fn matches(&self, expected: &ExpectedDeco) -> bool {
-        // Check kind matches (code contains the kind, e.g., "ferrous-owl:mut-borrow")
-        let kind_matches = self.code.ends_with(&format!(":{}", expected.kind));
+        // The source before the kind differs between builds of the package
+        let kind_matches = self.kind() == Some(expected.kind);
 
         // Check line if specified
-        let line_matches = expected.line.is_none_or(|l| self.line == Loc::from(l));
+        let line_matches = expected.line.is_none_or(|line| self.start.line == line);
 
         // Check text_match if specified (look in message)
         let text_matches = expected
@@ -60,28 +116,111 @@ impl ReceivedDiagnostic {
             .as_ref()
             .is_none_or(|m| self.message.contains(m));
 
-        kind_matches && line_matches && text_matches && message_matches
+        // Check the range if specified
+        let span_matches = expected.span.is_none_or(|span| self.span() == span);
+
+        let auto_borrow_matches = expected
+            .auto_borrow
+            .is_none_or(|implicit| self.is_auto_borrow() == implicit);
+
+        kind_matches
+            && line_matches
+            && text_matches
+            && message_matches
+            && span_matches
+            && auto_borrow_matches
+    }
+}
+
+/// Result of a `ferrous-owl/cursor` request
+#[derive(Debug, Clone, Deserialize)]
+pub struct Decorations {
+    pub is_analyzed: bool,
+    pub status: AnalysisStatusKind,
+    #[serde(default, rename = "decorations")]
+    pub items: Vec<Value>,
+    /// Whether decorations were left out of `items`
+    #[serde(default)]
+    pub truncated: bool,
+    /// Number of decorations, including the ones left out
+    #[serde(default)]
+    pub total: usize,
+}
+
+/// Analysis did not finish in time, returned by
+/// [`LspClient::wait_for_analysis`] as source of a [`ErrorKind::TimedOut`]
+/// error.
+#[derive(Debug, Clone)]
+pub struct AnalysisTimeout {
+    pub timeout: Duration,
+    /// Title of the last ownership code action, describing the server state
+    pub last_title: Option<String>,
+}
+
+impl fmt::Display for AnalysisTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Timeout waiting for analysis after {:?}, last code action: ",
+            self.timeout
+        )?;
+        match &self.last_title {
+            Some(title) => write!(f, "{title:?}"),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+impl error::Error for AnalysisTimeout {}
+
+/// Transport to the server
+enum Connection {
+    /// Server process started by the client, over stdio, shared with the
+    /// watchdog
+    Process(Arc<Mutex<Child>>),
+    /// Server started elsewhere, over TCP
+    Socket(TcpStream),
+}
+
+impl Connection {
+    fn close(&mut self) {
+        match self {
+            Self::Process(child) => {
+                let mut child = child.lock().unwrap_or_else(PoisonError::into_inner);
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+            Self::Socket(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
     }
 }
 
 /// LSP JSON-RPC client for testing the ferrous-owl language server.
 pub struct LspClient {
-    child: Child,
-    writer: BufWriter<ChildStdin>,
+    connection: Connection,
+    writer: BufWriter<Box<dyn Write + Send>>,
     receiver: Receiver<Value>,
     _reader_thread: JoinHandle<()>,
     request_id: i64,
     pending_requests: HashMap<i64, String>,
+    /// File receiving the stderr of a started server
+    server_log: Option<PathBuf>,
+    /// Stops the watchdog when dropped
+    watchdog: Option<Sender<()>>,
 }
 
 impl LspClient {
-    /// Start a new LSP server process.
+    /// Start a new LSP server process, with its stderr written to a new
+    /// file that is removed with the client, see [`Self::server_log`].
     pub fn start(command: &str, args: &[&str]) -> Result<Self> {
+        let server_log = new_server_log()?;
         let mut cmd = Command::new(command);
         cmd.args(args)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::null());
+            .stderr(File::create(&server_log)?);
 
         let mut child = cmd.spawn()?;
 
@@ -94,21 +233,77 @@ impl LspClient {
             .take()
             .ok_or_else(|| Error::other("Failed to get stdout"))?;
 
-        let writer = BufWriter::new(stdin);
+        let mut client = Self::new(
+            Connection::Process(Arc::new(Mutex::new(child))),
+            stdin,
+            stdout,
+        );
+        client.server_log = Some(server_log);
+        Ok(client)
+    }
+
+    /// Connect to a server listening at `address`, as started by
+    /// `ferrous-owl --listen`.
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        let writer = stream.try_clone()?;
+        let reader = stream.try_clone()?;
+        Ok(Self::new(Connection::Socket(stream), writer, reader))
+    }
+
+    fn new(
+        connection: Connection,
+        writer: impl Write + Send + 'static,
+        reader: impl Read + Send + 'static,
+    ) -> Self {
+        let writer = BufWriter::new(Box::new(writer) as Box<dyn Write + Send>);
         let (sender, receiver) = mpsc::channel();
 
         let reader_thread = thread::spawn(move || {
-            read_messages(stdout, &sender);
+            read_messages(reader, &sender);
         });
 
-        Ok(Self {
-            child,
+        Self {
+            connection,
             writer,
             receiver,
             _reader_thread: reader_thread,
             request_id: 0,
             pending_requests: HashMap::new(),
-        })
+            server_log: None,
+            watchdog: None,
+        }
+    }
+
+    /// Kill the started server if it still runs after `limit`, so that a
+    /// hung server fails the test instead of blocking it. Connected servers
+    /// are left alone.
+    #[must_use]
+    pub fn with_watchdog(mut self, limit: Duration) -> Self {
+        if let Connection::Process(child) = &self.connection {
+            let child = Arc::clone(child);
+            let (stop, stopped) = mpsc::channel::<()>();
+            thread::spawn(move || {
+                if stopped.recv_timeout(limit) == Err(RecvTimeoutError::Timeout) {
+                    log::warn!("killing the server, still running after {limit:?}");
+                    let _ = child.lock().unwrap_or_else(PoisonError::into_inner).kill();
+                }
+            });
+            self.watchdog = Some(stop);
+        }
+        self
+    }
+
+    /// File with the stderr of the server, if the client started it.
+    #[must_use]
+    pub fn server_log_path(&self) -> Option<&Path> {
+        self.server_log.as_deref()
+    }
+
+    /// Stderr of the server so far, if the client started it.
+    #[must_use]
+    pub fn server_log(&self) -> Option<String> {
+        fs::read_to_string(self.server_log.as_ref()?).ok()
     }
 
     /// Send an LSP request and return the request ID.
@@ -116,12 +311,15 @@ impl LspClient {
         self.request_id += 1;
         let id = self.request_id;
 
-        let request = json!({
+        let mut request = json!({
             "jsonrpc": "2.0",
             "id": id,
             "method": method,
-            "params": params
         });
+        // requests without parameters omit them
+        if !params.is_null() {
+            request["params"] = params.clone();
+        }
 
         self.pending_requests.insert(id, method.to_string());
         self.send_message(&request)?;
@@ -139,14 +337,7 @@ impl LspClient {
     }
 
     fn send_message(&mut self, message: &Value) -> Result<()> {
-        let content = serde_json::to_string(message)?;
-        let header = format!("Content-Length: {}\r\n\r\n", content.len());
-
-        self.writer.write_all(header.as_bytes())?;
-        self.writer.write_all(content.as_bytes())?;
-        self.writer.flush()?;
-
-        Ok(())
+        lsp_framing::write_message(&mut self.writer, message)
     }
 
     /// Receive the next message with a timeout.
@@ -183,9 +374,50 @@ impl LspClient {
 
     /// Initialize the LSP connection with standard capabilities.
     pub fn initialize(&mut self, root_uri: &str) -> Result<Value> {
-        let params = json!({
+        self.initialize_with_options(root_uri, &Value::Null)
+    }
+
+    /// Initialize the LSP connection with standard capabilities and the
+    /// server specific `options`.
+    pub fn initialize_with_options(&mut self, root_uri: &str, options: &Value) -> Result<Value> {
+        self.initialize_with_params(&Self::initialize_params(root_uri, options))
+    }
+
+    /// Initialize the LSP connection with standard capabilities and support
+    /// for work done progress, whose creation the caller has to answer, see
+    /// [`Self::wait_for_server_request`].
+    pub fn initialize_with_work_done_progress(&mut self, root_uri: &str) -> Result<Value> {
+        let mut params = Self::initialize_params(root_uri, &Value::Null);
+        params["capabilities"]["window"] = json!({ "workDoneProgress": true });
+        self.initialize_with_params(&params)
+    }
+
+    /// Initialize the LSP connection with standard capabilities and the
+    /// position `encodings` the client supports, like `"utf-8"`.
+    pub fn initialize_with_position_encodings(
+        &mut self,
+        root_uri: &str,
+        encodings: &[&str],
+    ) -> Result<Value> {
+        let mut params = Self::initialize_params(root_uri, &Value::Null);
+        params["capabilities"]["general"] = json!({ "positionEncodings": encodings });
+        self.initialize_with_params(&params)
+    }
+
+    fn initialize_with_params(&mut self, params: &Value) -> Result<Value> {
+        let id = self.send_request("initialize", params)?;
+        let response = self.wait_for_response(id, Duration::from_secs(30))?;
+
+        self.send_notification("initialized", &json!({}))?;
+
+        Ok(response)
+    }
+
+    fn initialize_params(root_uri: &str, options: &Value) -> Value {
+        json!({
             "processId": process_id(),
             "rootUri": root_uri,
+            "initializationOptions": options,
             "capabilities": {
                 "textDocument": {
                     "publishDiagnostics": {
@@ -200,14 +432,7 @@ impl LspClient {
                     }
                 }
             }
-        });
-
-        let id = self.send_request("initialize", &params)?;
-        let response = self.wait_for_response(id, Duration::from_secs(30))?;
-
-        self.send_notification("initialized", &json!({}))?;
-
-        Ok(response)
+        })
     }
 
     /// Open a text document in the server.
@@ -225,6 +450,26 @@ impl LspClient {
         )
     }
 
+    /// Apply `changes`, which are `TextDocumentContentChangeEvent`s, to an
+    /// open document in the server, without saving it.
+    pub fn change_document(&mut self, uri: &str, version: i32, changes: &[Value]) -> Result<()> {
+        self.send_notification(
+            "textDocument/didChange",
+            &json!({
+                "textDocument": { "uri": uri, "version": version },
+                "contentChanges": changes
+            }),
+        )
+    }
+
+    /// Close a text document in the server.
+    pub fn close_document(&mut self, uri: &str) -> Result<()> {
+        self.send_notification(
+            "textDocument/didClose",
+            &json!({ "textDocument": { "uri": uri } }),
+        )
+    }
+
     /// Request shutdown and exit.
     pub fn shutdown(&mut self) -> Result<()> {
         log::debug!("Sending shutdown request...");
@@ -232,9 +477,8 @@ impl LspClient {
         let _ = self.wait_for_response(id, Duration::from_secs(2));
         log::debug!("Sending exit notification...");
         self.send_notification("exit", &json!(null))?;
-        log::debug!("Killing child process...");
-        let _ = self.child.kill();
-        let _ = self.child.wait();
+        log::debug!("Closing connection...");
+        self.connection.close();
         log::debug!("Shutdown complete");
         Ok(())
     }
@@ -266,6 +510,172 @@ impl LspClient {
         )
     }
 
+    /// Notify the server of added and removed workspace folders.
+    pub fn change_workspace_folders(&mut self, added: &[&str], removed: &[&str]) -> Result<()> {
+        let folders = |uris: &[&str]| -> Vec<Value> {
+            uris.iter()
+                .map(|uri| json!({ "uri": uri, "name": uri }))
+                .collect()
+        };
+        self.send_notification(
+            "workspace/didChangeWorkspaceFolders",
+            &json!({
+                "event": {
+                    "added": folders(added),
+                    "removed": folders(removed)
+                }
+            }),
+        )
+    }
+
+    /// Notify the server of changed settings, given as the section of the
+    /// configuration.
+    pub fn change_configuration(&mut self, section: &Value) -> Result<()> {
+        self.send_notification(
+            "workspace/didChangeConfiguration",
+            &json!({ "settings": { "ferrous-owl": section } }),
+        )
+    }
+
+    /// Request the decorations at a position.
+    pub fn cursor(&mut self, uri: &str, line: u32, character: u32) -> Result<Value> {
+        let id = self.send_request(
+            "ferrous-owl/cursor",
+            &json!({
+                "position": { "line": line, "character": character },
+                "document": { "uri": uri }
+            }),
+        )?;
+        self.wait_for_response(id, Duration::from_secs(30))
+    }
+
+    /// Request the decorations at a position and parse the result.
+    pub fn cursor_request(&mut self, uri: &str, line: u32, character: u32) -> Result<Decorations> {
+        let response = self.cursor(uri, line, character)?;
+        let result = response.get("result").cloned().unwrap_or_default();
+        serde_json::from_value(result).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid cursor response {response}: {e}"),
+            )
+        })
+    }
+
+    /// Request the decorations at a position, which are not sent again if
+    /// they are those of `previous_selection_id`.
+    pub fn cursor_since(
+        &mut self,
+        uri: &str,
+        line: u32,
+        character: u32,
+        previous_selection_id: u64,
+    ) -> Result<Value> {
+        let id = self.send_request(
+            "ferrous-owl/cursor",
+            &json!({
+                "position": { "line": line, "character": character },
+                "document": { "uri": uri },
+                "previous_selection_id": previous_selection_id
+            }),
+        )?;
+        self.wait_for_response(id, Duration::from_secs(30))
+    }
+
+    /// Request the decorations at a position, without the summary at the
+    /// declaration of the selected variable.
+    pub fn cursor_without_summary(
+        &mut self,
+        uri: &str,
+        line: u32,
+        character: u32,
+    ) -> Result<Value> {
+        let id = self.send_request(
+            "ferrous-owl/cursor",
+            &json!({
+                "position": { "line": line, "character": character },
+                "document": { "uri": uri },
+                "summary": false
+            }),
+        )?;
+        self.wait_for_response(id, Duration::from_secs(30))
+    }
+
+    /// Request the decorations at a position, summarized per line.
+    pub fn line_hints(&mut self, uri: &str, line: u32, character: u32) -> Result<Value> {
+        let id = self.send_request(
+            "ferrous-owl/lineHints",
+            &json!({
+                "position": { "line": line, "character": character },
+                "document": { "uri": uri }
+            }),
+        )?;
+        self.wait_for_response(id, Duration::from_secs(30))
+    }
+
+    /// Compare the decorations at positions `a` and `b`, given as
+    /// (line, character), with the `ferrous-owl.compareSelections` command.
+    pub fn compare_selections(&mut self, uri: &str, a: (u32, u32), b: (u32, u32)) -> Result<Value> {
+        let id = self.execute_command(
+            "ferrous-owl.compareSelections",
+            &[json!(uri), json!(a.0), json!(a.1), json!(b.0), json!(b.1)],
+        )?;
+        self.wait_for_response(id, Duration::from_secs(30))
+    }
+
+    /// Request the files with analysis results.
+    pub fn status(&mut self) -> Result<Value> {
+        let id = self.send_request("ferrous-owl/status", &json!(null))?;
+        self.wait_for_response(id, Duration::from_secs(30))
+    }
+
+    /// Wait for the next request of `method` from the server, returning it
+    /// with its id.
+    ///
+    /// Other messages received meanwhile are dropped.
+    pub fn wait_for_server_request(&mut self, method: &str, timeout: Duration) -> Result<Value> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some(msg) = self.receive_message(Duration::from_millis(100))?
+                && msg.get("id").is_some()
+                && msg.get("method").and_then(Value::as_str) == Some(method)
+            {
+                return Ok(msg);
+            }
+        }
+        Err(Error::new(
+            ErrorKind::TimedOut,
+            format!("Timeout waiting for {method} request"),
+        ))
+    }
+
+    /// Answer the `request` of the server with `result`.
+    pub fn respond(&mut self, request: &Value, result: &Value) -> Result<()> {
+        self.send_message(&json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": result,
+        }))
+    }
+
+    /// Wait for the next notification of `method`, returning its parameters.
+    ///
+    /// Other messages received meanwhile are dropped.
+    pub fn wait_for_notification(&mut self, method: &str, timeout: Duration) -> Result<Value> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some(mut msg) = self.receive_message(Duration::from_millis(100))?
+                && msg.get("id").is_none()
+                && msg.get("method").and_then(Value::as_str) == Some(method)
+            {
+                return Ok(msg["params"].take());
+            }
+        }
+        Err(Error::new(
+            ErrorKind::TimedOut,
+            format!("Timeout waiting for {method} notification"),
+        ))
+    }
+
     /// Wait for analysis to complete by polling code actions.
     pub fn wait_for_analysis(
         &mut self,
@@ -276,6 +686,7 @@ impl LspClient {
     ) -> Result<()> {
         log::info!("Waiting for analysis to complete...");
         let start = Instant::now();
+        let mut last_title = None;
         while start.elapsed() < timeout {
             log::debug!("Sending code action request...");
             let response = self.code_action(uri, line, character)?;
@@ -284,27 +695,104 @@ impl LspClient {
                 log::debug!("Got {} actions", actions.len());
                 // Check if any action title indicates analysis is complete (not "analyzing" or
                 // "waiting")
-                let ready = actions.iter().any(|a| {
-                    let title = a.get("title").and_then(Value::as_str);
+                let titles = actions
+                    .iter()
+                    .filter_map(|a| a.get("title").and_then(Value::as_str))
+                    .filter(|t| t.contains("Ferrous"));
+                for title in titles {
                     log::debug!("Action title: {title:?}");
-                    title.is_some_and(|t| {
-                        t.contains("Ferrous") && !t.contains("analyzing") && !t.contains("waiting")
-                    })
-                });
-                if ready {
-                    log::info!("Analysis complete!");
-                    return Ok(());
+                    if !title.contains("analyzing") && !title.contains("waiting") {
+                        log::info!("Analysis complete!");
+                        return Ok(());
+                    }
+                    last_title = Some(title.to_string());
                 }
             }
             thread::sleep(Duration::from_millis(500));
         }
         Err(Error::new(
             ErrorKind::TimedOut,
-            "Timeout waiting for analysis",
+            AnalysisTimeout {
+                timeout,
+                last_title,
+            },
+        ))
+    }
+
+    /// Result of a cursor request at `line` and `character` of `uri`, once it
+    /// has decorations.
+    pub fn wait_for_decorations(
+        &mut self,
+        uri: &str,
+        line: u32,
+        character: u32,
+        timeout: Duration,
+    ) -> Result<Value> {
+        let start = Instant::now();
+        loop {
+            let response = self.cursor(uri, line, character)?;
+            if response["result"]["decorations"]
+                .as_array()
+                .is_some_and(|items| !items.is_empty())
+            {
+                return Ok(response["result"].clone());
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!("Timeout waiting for decorations at {line}:{character} of {uri}"),
+                ));
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// Wait for the first non-empty diagnostics published for `uri`.
+    pub fn wait_for_diagnostics(
+        &mut self,
+        uri: &str,
+        timeout: Duration,
+    ) -> Result<Vec<ReceivedDiagnostic>> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some(msg) = self.receive_message(Duration::from_millis(100))?
+                && let Some(diag_array) = published_diagnostics(&msg, uri)
+                && !diag_array.is_empty()
+            {
+                log::info!("Got {} diagnostics", diag_array.len());
+                return Ok(diag_array
+                    .iter()
+                    .filter_map(ReceivedDiagnostic::from_lsp)
+                    .collect());
+            }
+        }
+        Err(Error::new(
+            ErrorKind::TimedOut,
+            "Timeout waiting for diagnostics",
+        ))
+    }
+
+    /// Wait until empty diagnostics are published for `uri`.
+    pub fn wait_for_cleared_diagnostics(&mut self, uri: &str, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if let Some(msg) = self.receive_message(Duration::from_millis(100))?
+                && published_diagnostics(&msg, uri).is_some_and(Vec::is_empty)
+            {
+                return Ok(());
+            }
+        }
+        Err(Error::new(
+            ErrorKind::TimedOut,
+            "Timeout waiting for diagnostics to be cleared",
         ))
     }
 
     /// Execute toggle ownership command and wait for diagnostics.
+    ///
+    /// Only diagnostics published for `uri` are collected. They are published
+    /// shortly after the response, and not at all if no variable is at the
+    /// position, so they are awaited for [`PUBLISH_WAIT`] at most.
     pub fn toggle_ownership_and_wait(
         &mut self,
         uri: &str,
@@ -321,7 +809,8 @@ impl LspClient {
 
         let start = Instant::now();
         let mut diagnostics = Vec::new();
-        let mut got_response = false;
+        let mut published = false;
+        let mut responded_at: Option<Instant> = None;
 
         while start.elapsed() < timeout {
             if let Some(msg) = self.receive_message(Duration::from_millis(100))? {
@@ -331,40 +820,25 @@ impl LspClient {
                 );
                 if msg.get("id").and_then(Value::as_i64) == Some(cmd_id) {
                     log::info!("Got command response");
-                    got_response = true;
+                    responded_at = Some(Instant::now());
                 }
-                if msg.get("method").and_then(Value::as_str)
-                    == Some("textDocument/publishDiagnostics")
-                    && let Some(params) = msg.get("params")
-                    && let Some(diag_array) = params.get("diagnostics").and_then(Value::as_array)
-                {
+                if let Some(diag_array) = published_diagnostics(&msg, uri) {
                     log::info!("Got {} diagnostics", diag_array.len());
-                    for diag in diag_array {
-                        if let Some(received) = ReceivedDiagnostic::from_lsp(diag) {
-                            diagnostics.push(received);
-                        }
-                    }
+                    published = true;
+                    diagnostics.extend(diag_array.iter().filter_map(ReceivedDiagnostic::from_lsp));
                 }
+            }
 
-                if got_response {
-                    thread::sleep(Duration::from_millis(200));
-                    while let Some(msg) = self.receive_message(Duration::from_millis(50))? {
-                        if msg.get("method").and_then(Value::as_str)
-                            == Some("textDocument/publishDiagnostics")
-                            && let Some(params) = msg.get("params")
-                            && let Some(diag_array) =
-                                params.get("diagnostics").and_then(Value::as_array)
-                        {
-                            for diag in diag_array {
-                                if let Some(received) = ReceivedDiagnostic::from_lsp(diag) {
-                                    diagnostics.push(received);
-                                }
-                            }
-                        }
+            if responded_at.is_some_and(|at| published || at.elapsed() >= PUBLISH_WAIT) {
+                thread::sleep(Duration::from_millis(200));
+                while let Some(msg) = self.receive_message(Duration::from_millis(50))? {
+                    if let Some(diag_array) = published_diagnostics(&msg, uri) {
+                        diagnostics
+                            .extend(diag_array.iter().filter_map(ReceivedDiagnostic::from_lsp));
                     }
-                    log::info!("Returning {} total diagnostics", diagnostics.len());
-                    return Ok(diagnostics);
                 }
+                log::info!("Returning {} total diagnostics", diagnostics.len());
+                return Ok(diagnostics);
             }
         }
 
@@ -375,49 +849,55 @@ impl LspClient {
 
 impl Drop for LspClient {
     fn drop(&mut self) {
-        let _ = self.child.kill();
+        self.watchdog = None;
+        self.connection.close();
+        if let Some(server_log) = &self.server_log {
+            let _ = fs::remove_file(server_log);
+        }
     }
 }
 
-/// Background reader function that runs in a separate thread.
-fn read_messages(stdout: ChildStdout, sender: &Sender<Value>) {
-    let mut reader = BufReader::new(stdout);
-
-    loop {
-        let mut header = String::new();
-        if reader.read_line(&mut header).unwrap_or(0) == 0 {
-            break;
-        }
-
-        let content_length = parse_content_length(&header);
-        if content_length == 0 {
-            continue;
-        }
+/// New file in the temporary directory for the stderr of a started server
+fn new_server_log() -> Result<PathBuf> {
+    static SERVERS: AtomicUsize = AtomicUsize::new(0);
 
-        let mut empty = String::new();
-        if reader.read_line(&mut empty).unwrap_or(0) == 0 {
-            break;
-        }
+    let dir = env::temp_dir().join("owl-tests").join("logs");
+    fs::create_dir_all(&dir)?;
+    let server = SERVERS.fetch_add(1, Ordering::Relaxed);
+    Ok(dir.join(format!("server_{}_{server}.log", process_id())))
+}
 
-        let mut content = vec![0u8; content_length];
-        if reader.read_exact(&mut content).is_err() {
-            break;
-        }
+/// Background reader function that runs in a separate thread.
+///
+/// Malformed messages are skipped, until the server closes its output.
+fn read_messages(reader: impl Read, sender: &Sender<Value>) {
+    let mut reader = BufReader::new(reader);
 
-        if let Ok(msg) = serde_json::from_slice::<Value>(&content)
-            && sender.send(msg).is_err()
-        {
-            break;
+    loop {
+        match lsp_framing::read_message(&mut reader) {
+            Ok(msg) => {
+                if sender.send(msg).is_err() {
+                    break;
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::InvalidData => {
+                log::warn!("skipping malformed message of the server: {error}");
+            }
+            Err(_) => break,
         }
     }
 }
 
-fn parse_content_length(header: &str) -> usize {
-    header
-        .trim()
-        .strip_prefix("Content-Length: ")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0)
+/// Diagnostics of a `textDocument/publishDiagnostics` notification for `uri`.
+fn published_diagnostics<'a>(msg: &'a Value, uri: &str) -> Option<&'a Vec<Value>> {
+    if msg.get("method").and_then(Value::as_str) != Some("textDocument/publishDiagnostics") {
+        return None;
+    }
+    let params = msg.get("params")?;
+    if params.get("uri").and_then(Value::as_str) != Some(uri) {
+        return None;
+    }
+    params.get("diagnostics").and_then(Value::as_array)
 }
 
 /// Create a file URI from a path.
@@ -425,3 +905,59 @@ fn parse_content_length(header: &str) -> usize {
 pub fn file_uri(path: &str) -> String {
     format!("file://{path}")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(code: &str) -> ReceivedDiagnostic {
+        ReceivedDiagnostic {
+            code: code.to_owned(),
+            start: LineCol::new(0, 0),
+            end: LineCol::new(0, 1),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_foreign_source_matches() {
+        assert!(received("rustowl:move").matches(&ExpectedDeco::move_deco()));
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown() {
+        for code in [
+            "",
+            "move",
+            ":move",
+            "ferrous-owl:",
+            "ferrous-owl:borrow",
+            "a:move:x",
+        ] {
+            assert_eq!(DiagnosticCode::parse(code), None, "{code}");
+            assert_eq!(received(code).kind(), None, "{code}");
+        }
+    }
+
+    #[test]
+    fn test_borrow_kinds_do_not_cross_match() {
+        let imm = received("ferrous-owl:imm-borrow");
+        let mut_ = received("ferrous-owl:mut-borrow");
+        assert!(imm.matches(&ExpectedDeco::imm_borrow()));
+        assert!(!imm.matches(&ExpectedDeco::mut_borrow()));
+        assert!(mut_.matches(&ExpectedDeco::mut_borrow()));
+        assert!(!mut_.matches(&ExpectedDeco::imm_borrow()));
+    }
+
+    #[test]
+    fn test_auto_borrows_match_borrows() {
+        let auto = received("ferrous-owl:imm-borrow:auto-borrow");
+        let explicit = received("ferrous-owl:imm-borrow");
+        assert_eq!(auto.kind(), Some(DecoKind::ImmBorrow));
+        assert!(auto.matches(&ExpectedDeco::imm_borrow()));
+        assert!(auto.matches(&ExpectedDeco::imm_borrow().with_auto_borrow(true)));
+        assert!(!auto.matches(&ExpectedDeco::imm_borrow().with_auto_borrow(false)));
+        assert!(explicit.matches(&ExpectedDeco::imm_borrow().with_auto_borrow(false)));
+        assert!(!explicit.matches(&ExpectedDeco::imm_borrow().with_auto_borrow(true)));
+    }
+}
@@ -1,11 +1,21 @@
 //! Test runner utilities for ferrous-owl LSP decoration tests.
 
-use std::{fs, io::Result, time::Duration};
+use std::{
+    env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{Error, ErrorKind, Result},
+    path::Path,
+    process, result,
+    time::Duration,
+};
+
+use serde_json::Value;
 
 use super::{
-    TestCase,
-    lsp_client::{LspClient, ReceivedDiagnostic, file_uri},
+    AnalysisStatusKind, CLEAN_STALE_ENV, DecoKind, DecoSpan, ExpectedDeco, TestCase,
+    lsp_client::{Decorations, LspClient, ReceivedDiagnostic, file_uri},
 };
+use crate::models::LineCol;
 
 /// Result of running a test case.
 pub struct TestResult {
@@ -14,89 +24,540 @@ pub struct TestResult {
     pub message: String,
 }
 
+impl TestResult {
+    /// Failure of `test`, described by `message`.
+    fn failed(test: &TestCase, message: String) -> Self {
+        Self {
+            name: test.name.clone(),
+            passed: false,
+            message,
+        }
+    }
+}
+
+/// Crate-level attributes prepended to every single-file test source file.
+///
+/// Shifts all lines of the test code down by one, see [`LineMapper`].
+/// Multi-file test cases have the attributes in their generated `lib.rs`
+/// instead.
+const PRELUDE: &str = "#![allow(dead_code)]\n";
+
+/// Converts lines between the code of a test case and the file it is written
+/// to, which starts with the [`PRELUDE`] for single-file test cases.
+///
+/// Positions sent to the server are converted with [`Self::to_file`] and
+/// diagnostics received from it with [`Self::to_test`], once each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineMapper {
+    /// Lines before the test code in the file
+    prelude_lines: u32,
+}
+
+impl LineMapper {
+    /// Mapper of the file with the cursor of `test`.
+    #[must_use]
+    pub fn new(test: &TestCase) -> Self {
+        Self::with_prelude(test.extra_files.is_empty())
+    }
+
+    /// Mapper of a file that starts with the [`PRELUDE`] if `prelude`.
+    #[must_use]
+    pub fn with_prelude(prelude: bool) -> Self {
+        let lines = PRELUDE.lines().count();
+        Self {
+            prelude_lines: if prelude {
+                u32::try_from(lines).unwrap()
+            } else {
+                0
+            },
+        }
+    }
+
+    /// Line of the file of the test code line `line`.
+    #[must_use]
+    pub const fn to_file(self, line: u32) -> u32 {
+        line + self.prelude_lines
+    }
+
+    /// Line of the test code of the file line `line`, the first one for
+    /// lines of the prelude.
+    #[must_use]
+    pub const fn to_test_line(self, line: u32) -> u32 {
+        line.saturating_sub(self.prelude_lines)
+    }
+
+    /// Position of the test code of the file position `position`.
+    #[must_use]
+    pub const fn to_test_position(self, position: LineCol) -> LineCol {
+        LineCol::new(self.to_test_line(position.line), position.character)
+    }
+
+    /// `diagnostic` received for the file, in lines of the test code.
+    #[must_use]
+    pub fn to_test(self, diagnostic: &ReceivedDiagnostic) -> ReceivedDiagnostic {
+        ReceivedDiagnostic {
+            start: self.to_test_position(diagnostic.start),
+            end: self.to_test_position(diagnostic.end),
+            ..diagnostic.clone()
+        }
+    }
+}
+
 /// Run a single test case against the LSP server.
 pub fn run_test(
     client: &mut LspClient,
     test: &TestCase,
     workspace_dir: &str,
 ) -> Result<TestResult> {
-    let test_file = format!("{workspace_dir}/test_source.rs");
-    let code_with_attrs = format!("#![allow(dead_code)]\n{}", test.code);
-    fs::write(&test_file, &code_with_attrs)?;
+    let cursor = match cursor_in_file(test) {
+        Ok(cursor) => cursor,
+        Err(message) => return Ok(TestResult::failed(test, message)),
+    };
+    let (test_file, contents) = if test.extra_files.is_empty() {
+        let test_file = format!("{workspace_dir}/test_source.rs");
+        let code_with_attrs = format!("{PRELUDE}{}", test.code);
+        fs::write(&test_file, &code_with_attrs)?;
+        (test_file, code_with_attrs)
+    } else {
+        write_crate_files(test, workspace_dir)?
+    };
 
     let file_uri = file_uri(&test_file);
 
-    client.open_document(&file_uri, "rust", &code_with_attrs)?;
+    client.open_document(&file_uri, "rust", &contents)?;
+
+    let result = if test.expected_build_errors.is_empty() {
+        client.wait_for_analysis(
+            &file_uri,
+            cursor.line,
+            cursor.character,
+            test.analysis_timeout(),
+        )?;
+        check_decorations(client, test, &file_uri, cursor)
+    } else {
+        check_build_errors(client, test, &file_uri)
+    };
+
+    let _ = fs::remove_file(&test_file);
+    log::info!("Test file cleaned up");
+
+    result
+}
 
-    let (line, character) = resolve_cursor_position(test);
-    let adjusted_line = line + 1; // Account for prepended #![allow(dead_code)]
-    log::info!("Using cursor position: line={adjusted_line}, char={character}");
+/// Write the code and the extra files of `test` under `src/` of the crate in
+/// `workspace_dir`, see [`TestCase::with_file`].
+///
+/// Returns the path and contents of the file with the cursor.
+fn write_crate_files(test: &TestCase, workspace_dir: &str) -> Result<(String, String)> {
+    let src_dir = format!("{workspace_dir}/src");
+    let mut files = vec![("test_source.rs".to_owned(), test.code.clone())];
+    files.extend(test.extra_files.iter().cloned());
+    if !test.extra_files.iter().any(|(path, _)| path == "lib.rs") {
+        files.push(("lib.rs".to_owned(), generated_lib(&files)));
+    }
+
+    for (path, contents) in &files {
+        let file = Path::new(&src_dir).join(path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, contents)?;
+    }
 
-    client.wait_for_analysis(&file_uri, adjusted_line, character, Duration::from_secs(30))?;
+    let cursor_file = test.cursor_file.as_deref().unwrap_or("test_source.rs");
+    Ok((
+        format!("{src_dir}/{cursor_file}"),
+        test.cursor_code().to_owned(),
+    ))
+}
+
+/// `lib.rs` declaring the modules of the top-level `files`: a file
+/// `helper.rs` or a directory `helper/` declare the module `helper`.
+fn generated_lib(files: &[(String, String)]) -> String {
+    let mut modules: Vec<&str> = Vec::new();
+    for (path, _) in files {
+        let module = path
+            .split_once('/')
+            .map_or_else(|| path.trim_end_matches(".rs"), |(dir, _)| dir);
+        if !modules.contains(&module) {
+            modules.push(module);
+        }
+    }
+    let declarations: Vec<_> = modules.iter().map(|m| format!("pub mod {m};")).collect();
+    format!("{PRELUDE}{}\n", declarations.join("\n"))
+}
+
+/// Name of the module holding the test case `test_name` in a batched
+/// workspace.
+#[must_use]
+pub fn module_name(test_name: &str) -> String {
+    let sanitized: String = test_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("case_{sanitized}")
+}
+
+/// Names of distinct modules holding the test cases named `test_names` in a
+/// batched workspace, in order. A [`module_name`] already taken by an
+/// earlier test case gets the first free numbered suffix.
+#[must_use]
+pub fn module_names<'a>(test_names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut modules: Vec<String> = Vec::new();
+    for test_name in test_names {
+        let base = module_name(test_name);
+        let mut module = base.clone();
+        let mut suffix = 1;
+        while modules.contains(&module) {
+            module = format!("{base}_{suffix}");
+            suffix += 1;
+        }
+        modules.push(module);
+    }
+    modules
+}
+
+/// Write every test case into its own module of the crate in `workspace_dir`.
+///
+/// Returns the path of the source file of each test case, in order.
+pub fn write_batch_workspace(tests: &[TestCase], workspace_dir: &str) -> Result<Vec<String>> {
+    let modules = module_names(tests.iter().map(|test| test.name.as_str()));
+    for (test, module) in tests.iter().zip(&modules) {
+        fs::write(
+            format!("{workspace_dir}/src/{module}.rs"),
+            format!("{PRELUDE}{}", test.code),
+        )?;
+    }
+
+    let lib: Vec<_> = modules.iter().map(|m| format!("pub mod {m};")).collect();
+    fs::write(format!("{workspace_dir}/src/lib.rs"), lib.join("\n"))?;
+
+    Ok(modules
+        .iter()
+        .map(|m| format!("{workspace_dir}/src/{m}.rs"))
+        .collect())
+}
 
-    let diagnostics = client.toggle_ownership_and_wait(
-        &file_uri,
-        adjusted_line,
-        character,
-        Duration::from_secs(10),
-    )?;
+/// Least time to wait for the analysis of a batch, which compiles every test
+/// case of it
+const MIN_BATCH_ANALYSIS_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Test cases of a batch whose analysis is given the time of a single one
+const CASES_PER_ANALYSIS_TIMEOUT: usize = 10;
+
+/// Time to wait for the analysis of the batch of `tests`: the longest
+/// [`TestCase::analysis_timeout`] for every started
+/// [`CASES_PER_ANALYSIS_TIMEOUT`] test cases, but at least
+/// [`MIN_BATCH_ANALYSIS_TIMEOUT`].
+fn batch_analysis_timeout(tests: &[TestCase]) -> Duration {
+    let longest = tests
+        .iter()
+        .map(TestCase::analysis_timeout)
+        .max()
+        .unwrap_or_default();
+    let shares = tests.len().div_ceil(CASES_PER_ANALYSIS_TIMEOUT);
+    (longest * u32::try_from(shares).unwrap_or(u32::MAX)).max(MIN_BATCH_ANALYSIS_TIMEOUT)
+}
+
+/// Run test cases written by [`write_batch_workspace`] against a server that
+/// analyzes the whole workspace once.
+///
+/// Errors of individual test cases are reported in their result and do not
+/// abort the remaining ones.
+pub fn run_batch(
+    client: &mut LspClient,
+    tests: &[TestCase],
+    files: &[String],
+) -> Result<Vec<TestResult>> {
+    for (test, file) in tests.iter().zip(files) {
+        client.open_document(&file_uri(file), "rust", &format!("{PRELUDE}{}", test.code))?;
+    }
+
+    let cursors: Vec<_> = tests.iter().map(cursor_in_file).collect();
+    let first = cursors.iter().zip(files).find_map(|(cursor, file)| {
+        let cursor = cursor.as_ref().ok()?;
+        Some((cursor, file))
+    });
+    if let Some((cursor, file)) = first {
+        let timeout = batch_analysis_timeout(tests);
+        client.wait_for_analysis(&file_uri(file), cursor.line, cursor.character, timeout)?;
+    }
+
+    Ok(tests
+        .iter()
+        .zip(files)
+        .zip(cursors)
+        .map(|((test, file), cursor)| match cursor {
+            Ok(cursor) => check_decorations(client, test, &file_uri(file), cursor)
+                .unwrap_or_else(|e| TestResult::failed(test, format!("Error: {e}"))),
+            Err(message) => TestResult::failed(test, message),
+        })
+        .collect())
+}
+
+/// Toggle ownership diagnostics at the cursor of `test` in the already
+/// analyzed file `file_uri` and verify them.
+fn check_decorations(
+    client: &mut LspClient,
+    test: &TestCase,
+    file_uri: &str,
+    cursor: LineCol,
+) -> Result<TestResult> {
+    let LineCol { line, character } = cursor;
+    log::info!("Using cursor position: line={line}, char={character}");
+
+    let mut diagnostics =
+        client.toggle_ownership_and_wait(file_uri, line, character, test.diagnostics_timeout())?;
     log::info!("Got {} diagnostics, verifying...", diagnostics.len());
+    let expects_lifetimes = test
+        .expected_decos
+        .iter()
+        .any(|deco| deco.kind == DecoKind::Lifetime);
+    let cursor =
+        (test.expect_no_decorations || test.expected_status.is_some() || expects_lifetimes)
+            .then(|| client.cursor_request(file_uri, line, character))
+            .transpose()?;
+    if expects_lifetimes && let Some(cursor) = &cursor {
+        diagnostics.extend(
+            cursor
+                .items
+                .iter()
+                .filter_map(ReceivedDiagnostic::from_decoration)
+                .filter(|deco| deco.kind() == Some(DecoKind::Lifetime)),
+        );
+    }
+    let lines = LineMapper::new(test);
+    let diagnostics: Vec<_> = diagnostics.iter().map(|d| lines.to_test(d)).collect();
 
-    let result = verify_decorations(test, &diagnostics);
-    log::info!("Verification complete: passed={}", result.0);
+    let (passed, mut message) = verify_decorations(test, &diagnostics, cursor.as_ref());
+    log::info!("Verification complete: passed={passed}");
+    if !passed {
+        message.push_str(&cursor_notes(test, LineCol::new(line, character)));
+    }
 
-    let _ = fs::remove_file(&test_file);
-    log::info!("Test file cleaned up");
+    Ok(TestResult {
+        name: test.name.clone(),
+        passed,
+        message,
+    })
+}
+
+/// Wait for the compiler errors published for `file_uri` and verify them.
+fn check_build_errors(
+    client: &mut LspClient,
+    test: &TestCase,
+    file_uri: &str,
+) -> Result<TestResult> {
+    let lines = LineMapper::new(test);
+    let diagnostics: Vec<_> = client
+        .wait_for_diagnostics(file_uri, test.analysis_timeout())?
+        .iter()
+        .map(|d| lines.to_test(d))
+        .collect();
+
+    let missing: Vec<_> = test
+        .expected_build_errors
+        .iter()
+        .filter(|expected| {
+            !diagnostics.iter().any(|r| {
+                r.start.line == expected.line && r.message.contains(&expected.message_contains)
+            })
+        })
+        .map(|expected| format!("Expected {expected:?} not found."))
+        .collect();
+
+    let (passed, message) = if missing.is_empty() {
+        (true, "All build errors match".to_string())
+    } else {
+        let received: Vec<_> = diagnostics
+            .iter()
+            .map(|r| format!("  line {} '{}'", r.start.line, r.message))
+            .collect();
+        (
+            false,
+            format!(
+                "Missing:\n{}\nReceived:\n{}",
+                missing.join("\n"),
+                received.join("\n")
+            ),
+        )
+    };
 
     Ok(TestResult {
         name: test.name.clone(),
-        passed: result.0,
-        message: result.1,
+        passed,
+        message,
     })
 }
 
-/// Resolve the cursor position from the test case.
-/// Returns (line, character) as 0-indexed values.
-fn resolve_cursor_position(test: &TestCase) -> (u32, u32) {
-    if let (Some(line), Some(char)) = (test.cursor_line, test.cursor_char) {
-        return (line, char);
+/// Cursor position of `test` in its source file, see [`LineMapper`], or a
+/// message why there is none, see [`resolve_cursor_position`].
+fn cursor_in_file(test: &TestCase) -> result::Result<LineCol, String> {
+    let position = resolve_cursor_position(test)?;
+    Ok(LineCol::new(
+        LineMapper::new(test).to_file(position.line),
+        position.character,
+    ))
+}
+
+/// Cursor position of `test` in its code, the start of the code if it has
+/// none.
+///
+/// Fails with a message showing the numbered code if the position of
+/// [`TestCase::cursor_at`] is not in the code, or the text of
+/// [`TestCase::cursor_on`] is not found in it.
+fn resolve_cursor_position(test: &TestCase) -> result::Result<LineCol, String> {
+    let code = test.cursor_code();
+    if let (Some(line), Some(character)) = (test.cursor_line, test.cursor_char) {
+        let position = LineCol::new(line, character);
+        return if contains_position(code, position) {
+            Ok(position)
+        } else {
+            Err(format!(
+                "cursor_at({line}, {character}) is not in the code:\n{}",
+                numbered_code(code)
+            ))
+        };
     }
 
     if let Some(ref text) = test.cursor_text {
-        for (line_idx, line_content) in test.code.lines().enumerate() {
-            if let Some(col) = line_content.find(text) {
-                #[allow(
-                    clippy::cast_possible_truncation,
-                    reason = "line/column indices fit in u32"
-                )]
-                return (line_idx as u32, col as u32);
-            }
+        return find_text(code, text).ok_or_else(|| {
+            format!(
+                "cursor_on({text:?}) is not found in the code:\n{}",
+                numbered_code(code)
+            )
+        });
+    }
+
+    Ok(LineCol::default())
+}
+
+/// Position of the first occurrence of `text` in `code`.
+fn find_text(code: &str, text: &str) -> Option<LineCol> {
+    code.lines().enumerate().find_map(|(line, content)| {
+        let byte = content.find(text)?;
+        #[allow(
+            clippy::cast_possible_truncation,
+            reason = "line/column indices fit in u32"
+        )]
+        Some(LineCol::new(
+            line as u32,
+            content[..byte].chars().count() as u32,
+        ))
+    })
+}
+
+/// Whether `position` is on a line of `code`, at most at its end.
+fn contains_position(code: &str, position: LineCol) -> bool {
+    code.lines()
+        .nth(position.line as usize)
+        .is_some_and(|line| position.character as usize <= line.chars().count())
+}
+
+/// `code` with the zero-based number of each line, as counted by
+/// [`TestCase::cursor_at`].
+fn numbered_code(code: &str) -> String {
+    use std::fmt::Write;
+
+    let width = code.lines().count().saturating_sub(1).to_string().len();
+    let mut numbered = String::new();
+    for (number, line) in code.lines().enumerate() {
+        let _ = writeln!(numbered, "{number:>width$} | {line}");
+    }
+    numbered
+}
+
+/// What a cursor at `character` of `line` is inside of, if it is inside a
+/// comment or a string literal, as far as the line alone tells.
+fn enclosing_literal(line: &str, character: u32) -> Option<&'static str> {
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut previous = None;
+    for c in line.chars().take(character as usize) {
+        if in_string {
+            in_string = c != '"' || escaped;
+            escaped = c == '\\' && !escaped;
+        } else if c == '"' {
+            in_string = true;
+            previous = None;
+        } else if c == '/' && previous == Some('/') {
+            return Some("comment");
+        } else {
+            previous = Some(c);
         }
-        log::warn!("cursor_text '{text}' not found in code, defaulting to (0, 0)");
     }
+    in_string.then_some("string literal")
+}
 
-    (0, 0)
+/// Lines appended to a failure of `test` with the cursor at `position` of
+/// its source file: the position, and a warning if the text of the cursor
+/// is in a comment or string literal.
+fn cursor_notes(test: &TestCase, position: LineCol) -> String {
+    use std::fmt::Write;
+
+    let mut notes = format!(
+        "\nCursor at line {}, character {} of the file.",
+        position.line, position.character
+    );
+    let test_line = LineMapper::new(test).to_test_line(position.line);
+    let literal = test
+        .cursor_text
+        .as_ref()
+        .zip(test.cursor_code().lines().nth(test_line as usize))
+        .and_then(|(text, line)| Some((text, enclosing_literal(line, position.character)?)));
+    if let Some((text, literal)) = literal {
+        let _ = write!(
+            notes,
+            "\nWarning: cursor_on({text:?}) is inside a {literal}, anchor the cursor on code \
+             instead."
+        );
+    }
+    notes
 }
 
-fn verify_decorations(test: &TestCase, received: &[ReceivedDiagnostic]) -> (bool, String) {
-    // Adjust received lines by -1 to account for prepended #![allow(dead_code)]
-    let adjusted: Vec<_> = received
+/// Check the decorations `received` from the toggle, in lines of the test
+/// code, and the `cursor` response, if requested, against the expectations
+/// of `test`.
+fn verify_decorations(
+    test: &TestCase,
+    received: &[ReceivedDiagnostic],
+    cursor: Option<&Decorations>,
+) -> (bool, String) {
+    let mut missing = Vec::new();
+    let expected: Vec<_> = test
+        .expected_decos
         .iter()
-        .map(|r| ReceivedDiagnostic {
-            code: r.code.clone(),
-            line: r.line - 1,
-            message: r.message.clone(),
+        .filter_map(|exp| {
+            let resolved = resolve_covering_text(exp, test.cursor_code());
+            if resolved.is_none() {
+                missing.push(format!("Covering text of {exp:?} not found in code."));
+            }
+            resolved
         })
         .collect();
-    let received = &adjusted;
-
-    let expected = &test.expected_decos;
-    let forbidden = &test.forbidden_decos;
-
-    let mut missing = Vec::new();
     let mut matched = vec![false; received.len()];
 
-    for exp in expected {
+    for exp in &expected {
+        if exp.line_from.is_some() || exp.line_to.is_some() {
+            missing.extend(spanned_lines_problem(exp, received, &mut matched));
+            continue;
+        }
+        if let Some(count) = exp.count {
+            let found = received.iter().filter(|r| r.matches(exp)).count();
+            if found != count {
+                missing.push(format!("Expected {exp:?} {count} time(s), found {found}."));
+            }
+            for (i, r) in received.iter().enumerate() {
+                matched[i] |= r.matches(exp);
+            }
+            continue;
+        }
         let found = received.iter().enumerate().any(|(i, r)| {
             if r.matches(exp) && !matched[i] {
                 matched[i] = true;
@@ -111,54 +572,264 @@ fn verify_decorations(test: &TestCase, received: &[ReceivedDiagnostic]) -> (bool
         }
     }
 
-    let mut forbidden_found = Vec::new();
-    for kind in forbidden {
-        for r in received {
-            if r.code.ends_with(&format!(":{kind}")) {
-                forbidden_found.push(format!(
-                    "Forbidden {kind} found at line {} '{}'",
-                    r.line, r.message
-                ));
-            }
+    for &(kind, count) in &test.expected_counts {
+        let expected = ExpectedDeco::new(kind);
+        let found: Vec<_> = received
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.matches(&expected))
+            .map(|(i, r)| {
+                matched[i] = true;
+                describe(r)
+            })
+            .collect();
+        if found.len() != count as usize {
+            missing.push(format!(
+                "Expected {count} {kind} decoration(s), found {}:\n{}",
+                found.len(),
+                found.join("\n")
+            ));
         }
     }
 
+    let forbidden_found = find_forbidden(test, received);
+    missing.extend(cursor.map_or_else(Vec::new, |cursor| cursor_problems(test, cursor)));
+    if test.expect_no_decorations && !received.is_empty() {
+        missing.push(format!(
+            "Expected no decorations, received {}.",
+            received.len()
+        ));
+    }
+
+    let unknown: Vec<_> = received
+        .iter()
+        .filter(|r| r.kind().is_none())
+        .map(describe)
+        .collect();
+
     let unexpected: Vec<_> = received
         .iter()
         .enumerate()
-        .filter(|(i, _)| !matched[*i])
-        .map(|(_, r)| format!("  {} at line {} '{}'", r.code, r.line, r.message))
+        .filter(|(i, r)| !matched[*i] && r.kind().is_some())
+        .map(|(_, r)| describe(r))
         .collect();
 
-    if missing.is_empty() && forbidden_found.is_empty() {
+    if missing.is_empty() && forbidden_found.is_empty() && unknown.is_empty() {
         (true, "All decorations match".to_string())
     } else {
-        let mut msg = String::new();
-        if !missing.is_empty() {
-            msg.push_str("Missing:\n");
-            msg.push_str(&missing.join("\n"));
-        }
-        if !forbidden_found.is_empty() {
-            if !msg.is_empty() {
-                msg.push('\n');
-            }
-            msg.push_str("Forbidden:\n");
-            msg.push_str(&forbidden_found.join("\n"));
+        (
+            false,
+            failure_message(&[
+                ("Missing", &missing),
+                ("Forbidden", &forbidden_found),
+                ("Unknown diagnostic codes", &unknown),
+                ("Received", &unexpected),
+            ]),
+        )
+    }
+}
+
+/// Message describing how the lines the decorations matching `expected`
+/// cover together differ from [`ExpectedDeco::spanning_lines`]. Marks those
+/// decorations as `matched`.
+fn spanned_lines_problem(
+    expected: &ExpectedDeco,
+    received: &[ReceivedDiagnostic],
+    matched: &mut [bool],
+) -> Option<String> {
+    let mut spanned: Option<(u32, u32)> = None;
+    for (i, r) in received.iter().enumerate() {
+        if r.matches(expected) {
+            matched[i] = true;
+            spanned = Some(spanned.map_or((r.start.line, r.end.line), |(from, to)| {
+                (from.min(r.start.line), to.max(r.end.line))
+            }));
         }
-        if !unexpected.is_empty() {
-            if !msg.is_empty() {
-                msg.push('\n');
-            }
-            msg.push_str("Received:\n");
-            msg.push_str(&unexpected.join("\n"));
+    }
+    let Some((from, to)) = spanned else {
+        return Some(format!("Expected {expected:?} not found."));
+    };
+    let differs = |line: Option<u32>, found: u32| line.is_some_and(|line| line != found);
+    (differs(expected.line_from, from) || differs(expected.line_to, to))
+        .then(|| format!("Expected {expected:?}, found lines {from} to {to}."))
+}
+
+/// Messages describing how the `cursor` response differs from the
+/// expectations of `test`.
+fn cursor_problems(test: &TestCase, cursor: &Decorations) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(expected) = test.expected_status
+        && cursor.status != expected
+    {
+        problems.push(format!(
+            "Expected status {expected:?}, got {:?}.",
+            cursor.status
+        ));
+    }
+    if test.expect_no_decorations {
+        let finished = matches!(
+            cursor.status,
+            AnalysisStatusKind::Finished | AnalysisStatusKind::NoVariableSelected
+        );
+        if !finished {
+            problems.push(format!(
+                "Expected a finished analysis, got status {:?}.",
+                cursor.status
+            ));
+        }
+        if !cursor.items.is_empty() {
+            problems.push(format!(
+                "Expected no decorations at the cursor, got {}.",
+                cursor.items.len()
+            ));
         }
-        (false, msg)
     }
+    problems
+}
+
+/// Messages describing the received decorations forbidden by `test`.
+fn find_forbidden(test: &TestCase, received: &[ReceivedDiagnostic]) -> Vec<String> {
+    test.forbidden_decos
+        .iter()
+        .filter_map(|deco| {
+            let pattern = deco.as_expected();
+            let found: Vec<_> = received
+                .iter()
+                .filter(|r| r.matches(&pattern))
+                .map(describe)
+                .collect();
+            (!found.is_empty()).then(|| format!("Forbidden {deco} found:\n{}", found.join("\n")))
+        })
+        .collect()
+}
+
+/// Message listing the non-empty sections of a failed verification.
+fn failure_message(sections: &[(&str, &Vec<String>)]) -> String {
+    sections
+        .iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(title, lines)| format!("{title}:\n{}", lines.join("\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `expected` with its covering text resolved to a span of `code`, or `None`
+/// if the text is not found.
+fn resolve_covering_text(expected: &ExpectedDeco, code: &str) -> Option<ExpectedDeco> {
+    let Some(text) = &expected.covering_text else {
+        return Some(expected.clone());
+    };
+    Some(ExpectedDeco {
+        span: Some(covering_span(code, text, expected.line)?),
+        ..expected.clone()
+    })
+}
+
+/// Span of the first occurrence of `text` in `code`, starting on `line` if
+/// given.
+fn covering_span(code: &str, text: &str, line: Option<u32>) -> Option<DecoSpan> {
+    let line_start: usize = line.map_or(0, |line| {
+        code.split_inclusive('\n')
+            .take(line as usize)
+            .map(str::len)
+            .sum()
+    });
+    let start = line_start + code[line_start..].find(text)?;
+    let position = |byte: usize| {
+        let before = &code[..byte];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        #[allow(
+            clippy::cast_possible_truncation,
+            reason = "line/column indices fit in u32"
+        )]
+        let position = (
+            before.matches('\n').count() as u32,
+            before[line_start..].chars().count() as u32,
+        );
+        position
+    };
+    let (start_line, start_char) = position(start);
+    if line.is_some_and(|line| line != start_line) {
+        return None;
+    }
+    let (end_line, end_char) = position(start + text.len());
+    Some(DecoSpan {
+        start_line,
+        start_char,
+        end_line,
+        end_char,
+    })
 }
 
-/// Set up a workspace directory for testing.
+/// Line of a failure message describing a received decoration.
+fn describe(r: &ReceivedDiagnostic) -> String {
+    format!(
+        "  {} at line {} ({}) '{}'",
+        r.code,
+        r.start.line,
+        r.span(),
+        r.message
+    )
+}
+
+/// Longest package name [`sanitize_package_name`] returns.
+const MAX_PACKAGE_NAME_LEN: usize = 64;
+
+/// `name` as a valid cargo package name: lowercase, with `-` for characters
+/// cargo does not allow, starting with a letter and at most
+/// [`MAX_PACKAGE_NAME_LEN`] long.
+#[must_use]
+pub fn sanitize_package_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if !sanitized.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        sanitized.insert(0, 't');
+    }
+    sanitized.truncate(MAX_PACKAGE_NAME_LEN);
+    sanitized
+}
+
+/// Package and directory name of the test workspace `name`: sanitized, with
+/// a hash of `name` telling apart names that sanitize the same, like ones
+/// differing by case.
+fn workspace_name(name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    // the 8 hex digits of the hash and a `-` fit after it
+    let mut sanitized = sanitize_package_name(name);
+    sanitized.truncate(MAX_PACKAGE_NAME_LEN - 9);
+    format!("{sanitized}-{:08x}", hasher.finish() >> 32)
+}
+
+/// Set up a workspace directory for the test `name`, returning its path.
+///
+/// Fails if the directory is left over non-empty from an earlier run,
+/// unless [`CLEAN_STALE_ENV`] is `1`, which removes it.
 pub fn setup_workspace(base_dir: &str, name: &str) -> Result<String> {
+    let name = workspace_name(name);
     let workspace_dir = format!("{base_dir}/{name}");
+    let stale = fs::read_dir(&workspace_dir).is_ok_and(|mut entries| entries.next().is_some());
+    if stale {
+        if env::var(CLEAN_STALE_ENV).is_ok_and(|value| value == "1") {
+            fs::remove_dir_all(&workspace_dir)?;
+        } else {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "test workspace {workspace_dir} is left over from an earlier run, remove it \
+                     or set {CLEAN_STALE_ENV}=1"
+                ),
+            ));
+        }
+    }
     fs::create_dir_all(&workspace_dir)?;
 
     let cargo_toml = format!(
@@ -174,3 +845,365 @@ edition = "2021"
 
     Ok(workspace_dir)
 }
+
+/// Package of a test in the temporary directory, whose `src/lib.rs` is
+/// `code`, see [`setup_workspace`].
+pub struct TestPackage {
+    /// Directory of the package
+    pub dir: String,
+    /// Path of `src/lib.rs`
+    pub file: String,
+    /// URI of `src/lib.rs`
+    pub uri: String,
+    code: String,
+}
+
+impl TestPackage {
+    /// Create the package of the test `name`, which is unique to the process.
+    pub fn new(name: &str, code: &str) -> Result<Self> {
+        let base_dir = env::temp_dir().join("owl-tests");
+        let dir = setup_workspace(
+            &base_dir.to_string_lossy(),
+            &format!("{name}_{}", process::id()),
+        )?;
+        let file = format!("{dir}/src/lib.rs");
+        fs::write(&file, code)?;
+        Ok(Self {
+            uri: file_uri(&file),
+            dir,
+            file,
+            code: code.to_owned(),
+        })
+    }
+
+    /// URI of the directory of the package
+    #[must_use]
+    pub fn dir_uri(&self) -> String {
+        file_uri(&self.dir)
+    }
+
+    /// Language server started with `command`, initialized on the package
+    /// and with `src/lib.rs` open.
+    pub fn start_server(&self, command: &str) -> Result<LspClient> {
+        self.start_server_with_options(command, &Value::Null)
+    }
+
+    /// Like [`Self::start_server`], initialized with the initialization
+    /// `options`.
+    pub fn start_server_with_options(&self, command: &str, options: &Value) -> Result<LspClient> {
+        let mut client = LspClient::start(command, &[])?;
+        client.initialize_with_options(&self.dir_uri(), options)?;
+        client.open_document(&self.uri, "rust", &self.code)?;
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_batch_analysis_timeout_grows_with_the_batch() {
+        let cases = |count: usize| -> Vec<_> {
+            (0..count)
+                .map(|i| {
+                    TestCase::new(&format!("case {i}"), "fn test() {}")
+                        .with_analysis_timeout(Duration::from_secs(40))
+                })
+                .collect()
+        };
+        assert_eq!(batch_analysis_timeout(&[]), MIN_BATCH_ANALYSIS_TIMEOUT);
+        assert_eq!(
+            batch_analysis_timeout(&cases(3)),
+            MIN_BATCH_ANALYSIS_TIMEOUT
+        );
+        assert_eq!(
+            batch_analysis_timeout(&cases(45)),
+            Duration::from_secs(5 * 40)
+        );
+    }
+
+    #[test]
+    fn test_package_name_sanitization() {
+        for (name, sanitized) in [
+            ("move_to_drop", "move_to_drop"),
+            ("Move To Drop", "move-to-drop"),
+            ("nested/case.rs", "nested-case-rs"),
+            ("2 phase", "t2-phase"),
+            ("_private", "t_private"),
+            ("", "t"),
+        ] {
+            assert_eq!(sanitize_package_name(name), sanitized, "{name}");
+        }
+        assert_eq!(sanitize_package_name(&"a".repeat(100)).len(), 64);
+    }
+
+    #[test]
+    fn test_workspace_names_differing_by_case_do_not_collide() {
+        let lower = workspace_name("move_case");
+        let upper = workspace_name("Move_Case");
+        assert!(lower.starts_with("move_case-"), "{lower}");
+        assert!(upper.starts_with("move_case-"), "{upper}");
+        assert_ne!(lower, upper);
+        assert_eq!(workspace_name("move_case"), lower);
+        assert!(workspace_name(&"a".repeat(100)).len() <= 64);
+    }
+
+    #[test]
+    fn test_stale_workspace_is_refused() {
+        let base_dir = env::temp_dir().join("owl-tests");
+        let base_dir = base_dir.to_string_lossy();
+        let name = format!("stale_{}", process::id());
+        let dir = setup_workspace(&base_dir, &name).unwrap();
+        fs::write(format!("{dir}/src/lib.rs"), "").unwrap();
+        let error = setup_workspace(&base_dir, &name).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert!(error.to_string().contains(CLEAN_STALE_ENV), "{error}");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(setup_workspace(&base_dir, &name).unwrap(), dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_name_sanitization() {
+        assert_eq!(module_name("move_to_drop"), "case_move_to_drop");
+        assert_eq!(module_name("move-to-drop"), "case_move_to_drop");
+        assert_eq!(module_name("Move To Drop"), "case_move_to_drop");
+        assert_eq!(module_name("2 phase"), "case_2_phase");
+        assert_eq!(
+            module_names(["a", "A", "a_1", "a-1"]),
+            ["case_a", "case_a_1", "case_a_1_1", "case_a_1_2"]
+        );
+    }
+
+    #[test]
+    fn test_generated_lib_declares_top_level_modules() {
+        let file = |path: &str| (path.to_owned(), String::new());
+        let lib = generated_lib(&[
+            file("test_source.rs"),
+            file("helper.rs"),
+            file("util/mod.rs"),
+            file("util/inner.rs"),
+        ]);
+        assert_eq!(
+            lib,
+            "#![allow(dead_code)]\npub mod test_source;\npub mod helper;\npub mod util;\n"
+        );
+    }
+
+    #[test]
+    fn test_cursor_in_extra_file_has_no_prelude() {
+        let test = TestCase::new("files", "fn test() {}")
+            .with_file(
+                "helper.rs",
+                "\n    pub fn f() {\n        let t = 1;\n    }\n",
+            )
+            .cursor_in_file("helper.rs", "t = 1");
+        assert_eq!(cursor_in_file(&test), Ok(LineCol::new(1, 8)));
+        let single = TestCase::new("single", "fn test() {\n    let t = 1;\n}").cursor_on("t = 1");
+        assert_eq!(cursor_in_file(&single), Ok(LineCol::new(2, 8)));
+    }
+
+    #[test]
+    fn test_cursor_outside_the_code_fails() {
+        let code = "fn test() {\n    let s = 1;\n}";
+        let inside = TestCase::new("inside", code).cursor_at(1, 14);
+        assert_eq!(resolve_cursor_position(&inside), Ok(LineCol::new(1, 14)));
+
+        let past_line = TestCase::new("past_line", code).cursor_at(1, 15);
+        let past_lines = TestCase::new("past_lines", code).cursor_at(50, 10);
+        for test in [past_line, past_lines] {
+            let message = resolve_cursor_position(&test).unwrap_err();
+            assert!(message.contains("is not in the code"), "{message}");
+            assert!(message.contains("1 |     let s = 1;"), "{message}");
+        }
+        let missing = TestCase::new("missing", code).cursor_on("t = 1");
+        let message = cursor_in_file(&missing).unwrap_err();
+        assert!(
+            message.starts_with("cursor_on(\"t = 1\") is not found"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_cursor_text_counts_characters() {
+        let test = TestCase::new("multibyte", "let é = \"ü\"; let s = 1;").cursor_on("s = 1");
+        assert_eq!(resolve_cursor_position(&test), Ok(LineCol::new(0, 17)));
+    }
+
+    #[test]
+    fn test_numbered_code() {
+        let code: Vec<_> = (0..11).map(|i| format!("line{i}")).collect();
+        let numbered = numbered_code(&code.join("\n"));
+        assert!(
+            numbered.starts_with(" 0 | line0\n 1 | line1\n"),
+            "{numbered}"
+        );
+        assert!(numbered.ends_with("10 | line10\n"), "{numbered}");
+        assert_eq!(numbered_code("fn f() {}"), "0 | fn f() {}\n");
+    }
+
+    #[test]
+    fn test_enclosing_literal() {
+        let line = r#"let s = "a \" // b"; // s = 1"#;
+        assert_eq!(enclosing_literal(line, 4), None);
+        assert_eq!(enclosing_literal(line, 10), Some("string literal"));
+        assert_eq!(enclosing_literal(line, 15), Some("string literal"));
+        assert_eq!(enclosing_literal(line, 20), None);
+        assert_eq!(enclosing_literal(line, 25), Some("comment"));
+    }
+
+    #[test]
+    fn test_cursor_notes_warn_about_comments() {
+        let test = TestCase::new("comment", "fn test() {\n    // s = 1\n}").cursor_on("s = 1");
+        let cursor = cursor_in_file(&test).unwrap();
+        let notes = cursor_notes(&test, cursor);
+        assert!(
+            notes.contains("Cursor at line 2, character 7 of the file"),
+            "{notes}"
+        );
+        assert!(notes.contains("inside a comment"), "{notes}");
+        let code = TestCase::new("code", "fn test() {\n    let s = 1;\n}").cursor_on("s = 1");
+        assert!(!cursor_notes(&code, cursor_in_file(&code).unwrap()).contains("Warning"));
+    }
+
+    #[test]
+    fn test_covering_span() {
+        let code = "fn test() {\n    let s = String::new();\n    consume(s);\n}";
+        let span = |start_line, start_char, end_line, end_char| DecoSpan {
+            start_line,
+            start_char,
+            end_line,
+            end_char,
+        };
+        assert_eq!(covering_span(code, "s", None), Some(span(0, 5, 0, 6)));
+        assert_eq!(covering_span(code, "s", Some(1)), Some(span(1, 8, 1, 9)));
+        assert_eq!(
+            covering_span(code, "(s)", Some(2)),
+            Some(span(2, 11, 2, 14))
+        );
+        assert_eq!(
+            covering_span(code, "String::new();\n    consume", None),
+            Some(span(1, 12, 2, 11)),
+            "text may span lines"
+        );
+        assert_eq!(covering_span(code, "let", Some(2)), None);
+        assert_eq!(covering_span(code, "missing", None), None);
+    }
+
+    #[test]
+    fn test_covering_text_resolves_to_span() {
+        let expected = ExpectedDeco::move_deco().on_line(2).covering_text("s");
+        let resolved = resolve_covering_text(&expected, "let s = 1;\nlet t = 2;\nf(s);").unwrap();
+        assert_eq!(
+            resolved.span,
+            Some(DecoSpan {
+                start_line: 2,
+                start_char: 2,
+                end_line: 2,
+                end_char: 3
+            })
+        );
+        assert!(resolve_covering_text(&expected, "let s = 1;").is_none());
+    }
+
+    #[test]
+    fn test_unknown_codes_are_reported() {
+        let diagnostic = |code: &str| ReceivedDiagnostic {
+            code: code.to_owned(),
+            start: LineCol::new(1, 0),
+            end: LineCol::new(1, 1),
+            message: String::new(),
+        };
+        let test = TestCase::new("unknown", "fn test() {}").expect_move();
+        let (passed, _) = verify_decorations(&test, &[diagnostic("rustowl:move")], None);
+        assert!(passed);
+
+        let (passed, message) = verify_decorations(
+            &test,
+            &[diagnostic("rustowl:move"), diagnostic("ferrous-owl:borrow")],
+            None,
+        );
+        assert!(!passed);
+        assert!(
+            message.contains("Unknown diagnostic codes:\n  ferrous-owl:borrow"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_line_mapper_round_trips() {
+        for (prelude, file_line) in [(true, 3), (false, 2)] {
+            let mapper = LineMapper::with_prelude(prelude);
+            assert_eq!(mapper.to_file(2), file_line);
+            assert_eq!(mapper.to_test_line(mapper.to_file(2)), 2);
+        }
+        let single = TestCase::new("single", "fn test() {}");
+        assert_eq!(LineMapper::new(&single), LineMapper::with_prelude(true));
+        let multi = single.with_file("helper.rs", "pub fn f() {}");
+        assert_eq!(LineMapper::new(&multi), LineMapper::with_prelude(false));
+        assert_eq!(
+            LineMapper::with_prelude(true).to_test_line(0),
+            0,
+            "prelude lines map to the first line"
+        );
+    }
+
+    #[test]
+    fn test_spanning_lines_covers_all_pieces() {
+        let piece = |line: u32, end_line: u32| ReceivedDiagnostic {
+            code: DecoKind::Lifetime.diagnostic_code(),
+            start: LineCol::new(line, 0),
+            end: LineCol::new(end_line, 1),
+            message: String::new(),
+        };
+        let received = [piece(2, 2), piece(3, 4), piece(5, 5)];
+        let spanning = |from, to| {
+            let test =
+                TestCase::new("span", "fn test() {}").expect_lifetime_spanning_lines(from, to);
+            verify_decorations(&test, &received, None).0
+        };
+        assert!(spanning(2, 5));
+        assert!(!spanning(2, 4));
+        assert!(!spanning(3, 5));
+    }
+
+    #[test]
+    fn test_cursor_status_is_verified() {
+        let cursor = |status, decorations: usize| Decorations {
+            is_analyzed: true,
+            status,
+            items: vec![Value::Null; decorations],
+            truncated: false,
+            total: decorations,
+        };
+        let test = TestCase::new("status", "fn test() {}").expect_no_decorations();
+        let (passed, _) = verify_decorations(
+            &test,
+            &[],
+            Some(&cursor(AnalysisStatusKind::NoVariableSelected, 0)),
+        );
+        assert!(passed);
+        let (passed, message) =
+            verify_decorations(&test, &[], Some(&cursor(AnalysisStatusKind::Finished, 1)));
+        assert!(!passed);
+        assert!(
+            message.contains("no decorations at the cursor"),
+            "{message}"
+        );
+        let (passed, message) =
+            verify_decorations(&test, &[], Some(&cursor(AnalysisStatusKind::Analyzing, 0)));
+        assert!(!passed);
+        assert!(message.contains("finished analysis"), "{message}");
+
+        let test = test.expect_status(AnalysisStatusKind::Error);
+        let (passed, message) =
+            verify_decorations(&test, &[], Some(&cursor(AnalysisStatusKind::Finished, 0)));
+        assert!(!passed);
+        assert!(
+            message.contains("Expected status Error, got Finished"),
+            "{message}"
+        );
+    }
+}
@@ -1,44 +1,418 @@
-use crate::models::Loc;
-#[must_use]
-pub fn index_to_line_char(s: &str, idx: Loc) -> (u32, u32) {
-    let mut line = 0;
-    let mut col = 0;
-    // it seems that the compiler is ignoring CR
-    for (i, c) in s.replace('\r', "").chars().enumerate() {
-        #[allow(
-            clippy::cast_possible_truncation,
-            reason = "source files are typically less than 2^32 characters"
-        )]
-        if idx == Loc::from(u32::try_from(i).unwrap_or(u32::MAX)) {
-            return (line, col);
-        }
-        if c == '\n' {
-            line += 1;
-            col = 0;
-        } else if c != '\r' {
-            col += 1;
-        }
-    }
-    (0, 0)
+use tower_lsp::lsp_types;
+
+use crate::{
+    lsp_documents::PositionEncoding,
+    models::{CharOffset, LineCol},
+};
+
+/// Character positions of a source file, built once so that converting byte
+/// positions and line/column pairs does not rescan the file.
+///
+/// It seems that the compiler is ignoring CR, so CR characters are stripped
+/// before indexing.
+#[derive(Debug, Clone)]
+pub struct SourceIndex {
+    /// Byte position of the start of the file in the compiler's source map
+    offset: u32,
+    /// Byte offset of each character in the CR-stripped source
+    char_starts: Vec<u32>,
+    /// Character index of the start of each line
+    line_starts: Vec<u32>,
+    /// Length of the CR-stripped source in bytes
+    bytes: u32,
+    /// Unit the characters of LSP positions count, see
+    /// [`Self::position_to_char`]
+    encoding: PositionEncoding,
 }
-#[must_use]
-pub fn line_char_to_index(s: &str, mut line: u32, char: u32) -> u32 {
-    let mut col = 0;
-    // it seems that the compiler is ignoring CR
-    for (i, c) in s.replace('\r', "").chars().enumerate() {
-        if line == 0 && col == char {
-            #[allow(
-                clippy::cast_possible_truncation,
-                reason = "source files are typically less than 2^32 characters"
-            )]
-            return u32::try_from(i).unwrap_or(0);
-        }
-        if c == '\n' && 0 < line {
-            line -= 1;
-            col = 0;
-        } else if c != '\r' {
-            col += 1;
-        }
-    }
-    0
+
+fn to_u32(value: usize) -> u32 {
+    u32::try_from(value).unwrap_or(u32::MAX)
+}
+
+impl SourceIndex {
+    #[must_use]
+    pub fn new(source: &str) -> Self {
+        let mut char_starts = Vec::with_capacity(source.len());
+        let mut line_starts = vec![0];
+        let mut byte = 0;
+        for c in source.chars().filter(|&c| c != '\r') {
+            char_starts.push(to_u32(byte));
+            byte += c.len_utf8();
+            if c == '\n' {
+                line_starts.push(to_u32(char_starts.len()));
+            }
+        }
+        Self {
+            offset: 0,
+            char_starts,
+            line_starts,
+            bytes: to_u32(byte),
+            encoding: PositionEncoding::Utf32,
+        }
+    }
+
+    /// Index for a file starting at byte position `offset` of the compiler's
+    /// source map, which is subtracted in [`Self::byte_to_char`].
+    #[must_use]
+    pub fn with_offset(self, offset: u32) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Whether the compiler byte positions from `lo` to `hi` are in the file.
+    #[must_use]
+    pub const fn contains(&self, lo: u32, hi: u32) -> bool {
+        self.offset <= lo && lo <= hi && hi <= self.offset.saturating_add(self.bytes)
+    }
+
+    /// Index whose LSP positions count characters in `encoding`, instead of
+    /// counting code points.
+    #[must_use]
+    pub fn with_encoding(self, encoding: PositionEncoding) -> Self {
+        Self { encoding, ..self }
+    }
+
+    fn char_count(&self) -> u32 {
+        to_u32(self.char_starts.len())
+    }
+
+    /// Character position of the compiler byte position `byte_pos`.
+    ///
+    /// Positions inside a character map to the next character, positions
+    /// past the end to the end of the file.
+    #[must_use]
+    pub fn byte_to_char(&self, byte_pos: u32) -> CharOffset {
+        let byte_pos = byte_pos.saturating_sub(self.offset);
+        CharOffset::from(self.char_starts.partition_point(|&start| start < byte_pos))
+    }
+
+    /// Byte offset of `idx` in the source without CR characters, its length
+    /// if `idx` is past its end.
+    #[must_use]
+    pub fn char_to_byte(&self, idx: CharOffset) -> usize {
+        let byte = self
+            .char_starts
+            .get(u32::from(idx) as usize)
+            .copied()
+            .unwrap_or(self.bytes);
+        byte as usize
+    }
+
+    /// Line and character of `idx`, the start of the file if it is not in
+    /// the file.
+    #[must_use]
+    pub fn char_to_line_col(&self, idx: CharOffset) -> LineCol {
+        let idx = u32::from(idx);
+        if self.char_count() <= idx {
+            return LineCol::default();
+        }
+        let line = self.line_starts.partition_point(|&start| start <= idx) - 1;
+        LineCol::new(to_u32(line), idx - self.line_starts[line])
+    }
+
+    /// Character offset of `position`.
+    ///
+    /// Characters past the end of the line continue on the following lines;
+    /// positions outside the file map to `0`.
+    #[must_use]
+    pub fn line_col_to_char(&self, position: LineCol) -> CharOffset {
+        let idx = self
+            .line_starts
+            .get(position.line as usize)
+            .map(|start| start.saturating_add(position.character))
+            .filter(|&idx| idx < self.char_count());
+        CharOffset::from(idx.unwrap_or(0))
+    }
+
+    /// Code units of the character `idx` in the encoding of the index
+    fn units(&self, idx: u32) -> u32 {
+        let start = self.char_starts[idx as usize];
+        let end = self
+            .char_starts
+            .get(idx as usize + 1)
+            .copied()
+            .unwrap_or(self.bytes);
+        self.encoding.units_of_utf8(end - start)
+    }
+
+    /// Character offset of the LSP `position`, whose character counts code
+    /// units in the encoding of the index.
+    ///
+    /// Like in [`Self::line_col_to_char`], characters past the end of the
+    /// line continue on the following lines and positions outside the file
+    /// map to `0`. A character in the middle of a code point stands for its
+    /// start.
+    #[must_use]
+    pub fn position_to_char(&self, position: lsp_types::Position) -> CharOffset {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return CharOffset::from(0u32);
+        };
+        let mut idx = start;
+        let mut units = 0;
+        while idx < self.char_count() {
+            units += self.units(idx);
+            if units > position.character {
+                break;
+            }
+            idx += 1;
+        }
+        let idx = Some(idx).filter(|&idx| idx < self.char_count());
+        CharOffset::from(idx.unwrap_or(0))
+    }
+
+    /// LSP position of `idx`, whose character counts code units in the
+    /// encoding of the index, the start of the file if `idx` is not in the
+    /// file.
+    #[must_use]
+    pub fn char_to_position(&self, idx: CharOffset) -> lsp_types::Position {
+        self.line_col_to_position(self.char_to_line_col(idx))
+    }
+
+    /// LSP position of the character `position.character` of its line, in
+    /// code units of the encoding of the index.
+    ///
+    /// Unlike [`Self::line_col_to_char`], the end of the file is kept, and
+    /// positions on lines outside the file are returned unchanged.
+    #[must_use]
+    pub fn line_col_to_position(&self, position: LineCol) -> lsp_types::Position {
+        let LineCol { line, character } = position;
+        let Some(&start) = self.line_starts.get(line as usize) else {
+            return lsp_types::Position { line, character };
+        };
+        let end = start.saturating_add(character).min(self.char_count());
+        let character = (start..end).map(|idx| self.units(idx)).sum();
+        lsp_types::Position { line, character }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scan per conversion, as done before [`SourceIndex`].
+    mod reference {
+        use crate::models::{CharOffset, LineCol};
+
+        pub fn byte_to_char(source: &str, byte_pos: u32, offset: u32) -> CharOffset {
+            let byte_pos = byte_pos.saturating_sub(offset);
+            let source_clean = source.replace('\r', "");
+            if source_clean.len() < byte_pos as usize {
+                return CharOffset::from(source_clean.chars().count());
+            }
+            source_clean
+                .char_indices()
+                .position(|(byte_idx, _)| (byte_pos as usize) <= byte_idx)
+                .map_or_else(
+                    || CharOffset::from(source_clean.chars().count()),
+                    CharOffset::from,
+                )
+        }
+
+        pub fn char_to_line_col(s: &str, idx: CharOffset) -> LineCol {
+            let mut line = 0;
+            let mut col = 0;
+            for (i, c) in s.replace('\r', "").chars().enumerate() {
+                if idx == CharOffset::from(i) {
+                    return LineCol::new(line, col);
+                }
+                if c == '\n' {
+                    line += 1;
+                    col = 0;
+                } else {
+                    col += 1;
+                }
+            }
+            LineCol::default()
+        }
+
+        pub fn line_col_to_char(s: &str, mut line: u32, char: u32) -> CharOffset {
+            let mut col = 0;
+            for (i, c) in s.replace('\r', "").chars().enumerate() {
+                if line == 0 && col == char {
+                    return CharOffset::from(i);
+                }
+                if c == '\n' && 0 < line {
+                    line -= 1;
+                    col = 0;
+                } else {
+                    col += 1;
+                }
+            }
+            CharOffset::from(0u32)
+        }
+    }
+
+    const SOURCES: &[&str] = &[
+        "",
+        "fn main() {}\n",
+        "fn main() {\r\n    let s = \"🦀\";\r\n}\r\n",
+        "let ü = 'é';\nlet 名前 = \"日本\";\n\n// end",
+        "\r\n\r\n\n",
+        "no trailing newline 🦀",
+    ];
+
+    #[test]
+    fn test_byte_to_char_matches_scan() {
+        for source in SOURCES {
+            let index = SourceIndex::new(source);
+            for byte_pos in 0..=u32::try_from(source.len()).unwrap() + 2 {
+                assert_eq!(
+                    index.byte_to_char(byte_pos),
+                    reference::byte_to_char(source, byte_pos, 0),
+                    "byte {byte_pos} of {source:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_to_char_with_offset() {
+        let source = SOURCES[2];
+        let index = SourceIndex::new(source).with_offset(100);
+        for byte_pos in 90..130 {
+            assert_eq!(
+                index.byte_to_char(byte_pos),
+                reference::byte_to_char(source, byte_pos, 100),
+                "byte {byte_pos}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_contains_positions_of_the_file() {
+        let index = SourceIndex::new("fn main() {}\r\n").with_offset(100);
+        assert!(index.contains(100, 113));
+        assert!(index.contains(113, 113));
+        assert!(!index.contains(99, 105), "starts before the file");
+        assert!(!index.contains(105, 114), "ends after the file");
+        assert!(!index.contains(105, 104));
+    }
+
+    #[test]
+    fn test_char_to_byte_slices_characters() {
+        let source = "let 名前 = \"🦀\";\r\n";
+        let stripped = source.replace('\r', "");
+        let index = SourceIndex::new(source);
+        let slice = |from: u32, until: u32| {
+            &stripped[index.char_to_byte(CharOffset::from(from))
+                ..index.char_to_byte(CharOffset::from(until))]
+        };
+        assert_eq!(slice(4, 6), "名前");
+        assert_eq!(slice(10, 11), "🦀");
+        assert_eq!(slice(12, 14), ";\n");
+        assert_eq!(slice(12, 20), ";\n", "past the end");
+    }
+
+    #[test]
+    fn test_char_to_line_col_matches_scan() {
+        for source in SOURCES {
+            let index = SourceIndex::new(source);
+            for idx in 0..=source.chars().count() + 2 {
+                let idx = CharOffset::from(idx);
+                assert_eq!(
+                    index.char_to_line_col(idx),
+                    reference::char_to_line_col(source, idx),
+                    "char {idx} of {source:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_col_round_trips_with_crlf_and_multibyte() {
+        let source = "fn main() {\r\n    let 名前 = \"🦀\";\r\n}\r\n";
+        let index = SourceIndex::new(source);
+        // `🦀` is one character after `    let 名前 = "`
+        let crab = index.line_col_to_char(LineCol::new(1, 14));
+        assert_eq!(crab, CharOffset::from(26u32));
+        assert_eq!(index.char_to_line_col(crab), LineCol::new(1, 14));
+        let byte = source.replace('\r', "").find('🦀').unwrap();
+        assert_eq!(index.byte_to_char(u32::try_from(byte).unwrap()), crab);
+        let closing = index.line_col_to_char(LineCol::new(2, 0));
+        assert_eq!(index.char_to_line_col(closing), LineCol::new(2, 0));
+        assert_eq!(index.char_to_line_col(closing - 1), LineCol::new(1, 17));
+    }
+
+    #[test]
+    fn test_line_col_converts_to_lsp_positions() {
+        let index = SourceIndex::new("a\nbcd\n\nefghijk\n");
+        let position = LineCol::new(3, 5);
+        let idx = index.line_col_to_char(position);
+        let lsp = index.char_to_position(idx);
+        assert_eq!((lsp.line, lsp.character), (3, 5));
+        assert_eq!(index.position_to_char(lsp), idx);
+        assert_eq!(<(u32, u32)>::from(position), (3, 5));
+    }
+
+    /// The example of the protocol: `b` of `a𐐀b` is at character 5 in UTF-8,
+    /// 3 in UTF-16 and 2 in UTF-32.
+    #[test]
+    fn test_lsp_positions_count_code_units_of_the_encoding() {
+        let source = "x\na𐐀b\n";
+        let b = CharOffset::from(4u32);
+        for (encoding, character) in [
+            (PositionEncoding::Utf8, 5),
+            (PositionEncoding::Utf16, 3),
+            (PositionEncoding::Utf32, 2),
+        ] {
+            let index = SourceIndex::new(source).with_encoding(encoding);
+            let position = lsp_types::Position::new(1, character);
+            assert_eq!(index.position_to_char(position), b, "{encoding:?}");
+            assert_eq!(index.char_to_position(b), position, "{encoding:?}");
+        }
+        // inside `𐐀`
+        let index = SourceIndex::new(source).with_encoding(PositionEncoding::Utf16);
+        assert_eq!(
+            index.position_to_char(lsp_types::Position::new(1, 2)),
+            CharOffset::from(3u32)
+        );
+    }
+
+    #[test]
+    fn test_line_col_to_position_keeps_the_end_of_the_file() {
+        let index = SourceIndex::new("x\naéb").with_encoding(PositionEncoding::Utf8);
+        assert_eq!(
+            index.line_col_to_position(LineCol::new(1, 2)),
+            lsp_types::Position::new(1, 3)
+        );
+        assert_eq!(
+            index.line_col_to_position(LineCol::new(1, 3)),
+            lsp_types::Position::new(1, 4)
+        );
+        assert_eq!(
+            index.line_col_to_position(LineCol::new(4, 2)),
+            lsp_types::Position::new(4, 2)
+        );
+    }
+
+    #[test]
+    fn test_utf32_positions_match_line_col() {
+        for source in SOURCES {
+            let index = SourceIndex::new(source);
+            for line in 0..6 {
+                for col in 0..30 {
+                    assert_eq!(
+                        index.position_to_char(lsp_types::Position::new(line, col)),
+                        index.line_col_to_char(LineCol::new(line, col)),
+                        "line {line} col {col} of {source:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_col_to_char_matches_scan() {
+        for source in SOURCES {
+            let index = SourceIndex::new(source);
+            for line in 0..6 {
+                for col in 0..30 {
+                    assert_eq!(
+                        index.line_col_to_char(LineCol::new(line, col)),
+                        reference::line_col_to_char(source, line, col),
+                        "line {line} col {col} of {source:?}"
+                    );
+                }
+            }
+        }
+    }
 }
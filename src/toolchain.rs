@@ -1,45 +1,137 @@
 use std::{
     collections::VecDeque,
-    env,
-    ffi::OsString,
+    env::{self, consts::EXE_SUFFIX},
+    error,
+    ffi::{OsStr, OsString},
+    fmt, fs,
     path::{Path, PathBuf},
-    process::{Command, exit},
+    process::{Command, Output},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+use serde::Serialize;
 use tokio::process::Command as TokioCommand;
 
+use crate::logging::{self, LOG_FILE_ENV};
+
 /// Host target triple (set at compile time in build.rs)
 pub const HOST_TUPLE: &str = env!("HOST_TUPLE");
 
 /// Sysroot path captured at compile time
 const COMPILE_TIME_SYSROOT: &str = env!("COMPILE_TIME_SYSROOT");
 
+/// Commit hash of the compiler whose libraries are linked (set in build.rs)
+pub const RUSTC_COMMIT_HASH: &str = env!("RUSTC_COMMIT_HASH");
+
 /// Environment variable for cache directory path
 pub const CACHE_DIR_ENV: &str = "FERROUS_OWL_CACHE_DIR";
 
+/// Environment variable that forbids network access and toolchain
+/// installation when set to `1`, like `--offline`
+pub const OFFLINE_ENV: &str = "FERROUS_OWL_OFFLINE";
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Never access the network or run rustup, as requested by `--offline`.
+pub fn set_offline() {
+    OFFLINE.store(true, Ordering::Relaxed);
+}
+
+/// Whether `--offline` or [`OFFLINE_ENV`] forbid network access and toolchain
+/// installation.
+#[must_use]
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed) || env::var(OFFLINE_ENV).is_ok_and(|value| value.trim() == "1")
+}
+
+/// Reasons the toolchain used for analysis is unusable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolchainError {
+    /// No sysroot was found, and rustc could not be asked for one
+    SysrootNotFound,
+    /// `what` is missing, and would have to be installed over the network
+    OfflineMissing { what: String },
+}
+
+impl ToolchainError {
+    /// Exit code of the CLI failing with this error
+    #[must_use]
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::SysrootNotFound => 1,
+            Self::OfflineMissing { .. } => 2,
+        }
+    }
+}
+
+impl fmt::Display for ToolchainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SysrootNotFound => write!(
+                f,
+                "could not determine the Rust sysroot, set RUSTOWL_SYSROOT or ensure rustc is in \
+                 PATH"
+            ),
+            Self::OfflineMissing { what } => {
+                let toolchain = Path::new(COMPILE_TIME_SYSROOT)
+                    .file_name()
+                    .map_or_else(|| "nightly".into(), OsStr::to_string_lossy);
+                write!(
+                    f,
+                    "{what} is missing and cannot be installed offline; install it beforehand \
+                     with `rustup toolchain install {toolchain} --component rustc-dev rust-src \
+                     llvm-tools`, or set RUSTOWL_SYSROOT to an installed toolchain"
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for ToolchainError {}
+
 /// Returns the Rust sysroot path for the compiler.
 ///
 /// Resolution order:
 /// 1. `RUSTOWL_SYSROOT` environment variable
 /// 2. Compile-time sysroot (embedded in binary)
-#[must_use]
-pub fn get_sysroot() -> PathBuf {
-    if let Ok(sysroot) = env::var("RUSTOWL_SYSROOT") {
-        let path = PathBuf::from(sysroot);
-        if path.is_dir() {
-            log::info!("Using sysroot from RUSTOWL_SYSROOT: {}", path.display());
-            return path;
-        }
+/// 3. The sysroot printed by `rustc`, unless offline, as rustup may install the
+///    toolchain it runs
+pub fn get_sysroot() -> Result<PathBuf, ToolchainError> {
+    resolve_sysroot(
+        env::var_os("RUSTOWL_SYSROOT").map(PathBuf::from),
+        Path::new(COMPILE_TIME_SYSROOT),
+        is_offline(),
+        &mut |command| command.output().ok(),
+    )
+}
+
+/// Resolves the sysroot like [`get_sysroot`], running commands with `run`.
+fn resolve_sysroot(
+    from_env: Option<PathBuf>,
+    compile_time: &Path,
+    offline: bool,
+    run: &mut dyn FnMut(&mut Command) -> Option<Output>,
+) -> Result<PathBuf, ToolchainError> {
+    if let Some(path) = from_env
+        && path.is_dir()
+    {
+        log::info!("Using sysroot from RUSTOWL_SYSROOT: {}", path.display());
+        return Ok(path);
     }
 
-    let path = PathBuf::from(COMPILE_TIME_SYSROOT);
-    if path.is_dir() {
-        log::info!("Using compile-time sysroot: {}", path.display());
-        return path;
+    if compile_time.is_dir() {
+        log::info!("Using compile-time sysroot: {}", compile_time.display());
+        return Ok(compile_time.to_path_buf());
+    }
+
+    if offline {
+        return Err(ToolchainError::OfflineMissing {
+            what: format!("the toolchain sysroot {}", compile_time.display()),
+        });
     }
 
     // Fallback to runtime detection
-    if let Ok(output) = Command::new("rustc").arg("--print").arg("sysroot").output()
+    if let Some(output) = run(Command::new("rustc").arg("--print").arg("sysroot"))
         && output.status.success()
     {
         let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -49,14 +141,11 @@ pub fn get_sysroot() -> PathBuf {
                 "Using sysroot from rustc (compile-time sysroot not found): {}",
                 path.display()
             );
-            return path;
+            return Ok(path);
         }
     }
 
-    log::error!(
-        "Could not determine Rust sysroot. Set RUSTOWL_SYSROOT or ensure rustc is in PATH."
-    );
-    exit(1);
+    Err(ToolchainError::SysrootNotFound)
 }
 
 /// Returns the path to the current executable.
@@ -68,23 +157,40 @@ fn current_exe_path() -> PathBuf {
 ///
 /// Sets up environment variables so cargo uses the current binary
 /// as the compiler wrapper.
-#[must_use]
-pub fn setup_cargo_command() -> TokioCommand {
+pub fn setup_cargo_command() -> Result<TokioCommand, ToolchainError> {
+    setup_cargo_command_with(&current_exe_path())
+}
+
+/// Like [`setup_cargo_command`], with `exe_path` as the compiler wrapper, for
+/// processes that are not the `ferrous-owl` binary.
+pub fn setup_cargo_command_with(exe_path: &Path) -> Result<TokioCommand, ToolchainError> {
+    Ok(cargo_command(exe_path, &get_sysroot()?, is_offline()))
+}
+
+/// Cargo command running `exe_path` as the compiler with `sysroot`, which
+/// never accesses the network if `offline`.
+fn cargo_command(exe_path: &Path, sysroot: &Path, offline: bool) -> TokioCommand {
     let mut command = TokioCommand::new("cargo");
-    let exe_path = current_exe_path();
-    let sysroot = get_sysroot();
+    if offline {
+        // before the subcommand, as for every global option
+        command.arg("--offline").env(OFFLINE_ENV, "1");
+    }
 
     command
         .env("FERROUS_OWL_AS_RUSTC", "1")
-        .env("RUSTC", &exe_path)
-        .env("RUSTC_WORKSPACE_WRAPPER", &exe_path)
+        .env("RUSTC", exe_path)
+        .env("RUSTC_WORKSPACE_WRAPPER", exe_path)
         .env("RUSTC_BOOTSTRAP", "1")
         .env(
             "CARGO_ENCODED_RUSTFLAGS",
             format!("--sysroot={}", sysroot.display()),
         );
 
-    prepend_library_path(&mut command, &sysroot);
+    if let Some(log_file) = logging::log_file_path() {
+        command.env(LOG_FILE_ENV, log_file);
+    }
+
+    prepend_library_path(&mut command, sysroot);
     command
 }
 
@@ -116,3 +222,268 @@ fn prepend_to_path_var(var: &str, new_path: &Path) -> OsString {
     paths.push_front(new_path.to_path_buf());
     env::join_paths(paths).expect("Failed to join paths")
 }
+
+/// Outcome of a single toolchain health check.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolchainCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl ToolchainCheck {
+    fn new(name: &'static str, result: Result<String, String>) -> Self {
+        let ok = result.is_ok();
+        let detail = result.unwrap_or_else(|e| e);
+        Self { name, ok, detail }
+    }
+}
+
+/// Health of the toolchain used for analysis.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolchainStatus {
+    pub ok: bool,
+    pub checks: Vec<ToolchainCheck>,
+}
+
+impl ToolchainStatus {
+    fn new(checks: Vec<ToolchainCheck>) -> Self {
+        Self {
+            ok: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+impl fmt::Display for ToolchainStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or(0);
+        for check in &self.checks {
+            let status = if check.ok { "OK" } else { "FAIL" };
+            writeln!(f, "{:width$}  {status:4}  {}", check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the toolchain this binary was built against and the tools used to
+/// run analyses.
+#[must_use]
+pub fn toolchain_status() -> ToolchainStatus {
+    let toolchain = Path::new(COMPILE_TIME_SYSROOT);
+    let rustc = toolchain.join("bin").join(format!("rustc{EXE_SUFFIX}"));
+    let version = Command::new(&rustc)
+        .arg("-vV")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned());
+    let cargo =
+        env::var_os("PATH").and_then(|paths| find_in_path(&format!("cargo{EXE_SUFFIX}"), &paths));
+
+    ToolchainStatus::new(vec![
+        ToolchainCheck::new("toolchain", check_dir(toolchain)),
+        ToolchainCheck::new(
+            "rustc",
+            check_rustc_version(version.as_deref(), RUSTC_COMMIT_HASH),
+        ),
+        ToolchainCheck::new(
+            "sysroot",
+            get_sysroot().map_or_else(|e| Err(e.to_string()), |sysroot| check_dir(&sysroot)),
+        ),
+        ToolchainCheck::new(
+            "cargo",
+            cargo.map_or_else(
+                || Err("not found in PATH".to_owned()),
+                |cargo| check_executable(&cargo),
+            ),
+        ),
+    ])
+}
+
+fn check_dir(path: &Path) -> Result<String, String> {
+    if path.is_dir() {
+        Ok(path.display().to_string())
+    } else {
+        Err(format!("{} does not exist", path.display()))
+    }
+}
+
+/// Checks that the output of `rustc -vV` reports `expected_commit`.
+fn check_rustc_version(version: Option<&str>, expected_commit: &str) -> Result<String, String> {
+    let version = version.ok_or_else(|| "failed to run rustc -vV".to_owned())?;
+    let release = version.lines().next().unwrap_or_default().to_owned();
+    let commit = version
+        .lines()
+        .find_map(|line| line.strip_prefix("commit-hash: "))
+        .ok_or_else(|| format!("{release}: no commit hash"))?;
+    if commit == expected_commit {
+        Ok(release)
+    } else {
+        Err(format!(
+            "{release}: commit {commit} differs from {expected_commit} built against"
+        ))
+    }
+}
+
+fn check_executable(path: &Path) -> Result<String, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    };
+    #[cfg(not(unix))]
+    let executable = metadata.is_file();
+    if executable {
+        Ok(path.display().to_string())
+    } else {
+        Err(format!("{} is not executable", path.display()))
+    }
+}
+
+/// First file called `name` in the directories of the `PATH`-like `paths`.
+fn find_in_path(name: &str, paths: &OsStr) -> Option<PathBuf> {
+    env::split_paths(paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION: &str = "rustc 1.89.0-nightly (255aa2208 2025-06-19)
+binary: rustc
+commit-hash: 255aa220821c05c3eac7605fce4ea1c9ab2cbdb4
+host: x86_64-unknown-linux-gnu
+";
+
+    #[test]
+    fn test_missing_sysroot_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_dir(dir.path()).is_ok());
+        let missing = dir.path().join("missing-sysroot");
+        let error = check_dir(&missing).unwrap_err();
+        assert!(error.contains("does not exist"));
+    }
+
+    #[test]
+    fn test_rustc_version_must_match_commit() {
+        let commit = "255aa220821c05c3eac7605fce4ea1c9ab2cbdb4";
+        assert_eq!(
+            check_rustc_version(Some(VERSION), commit).unwrap(),
+            "rustc 1.89.0-nightly (255aa2208 2025-06-19)"
+        );
+        assert!(
+            check_rustc_version(Some(VERSION), "0000")
+                .unwrap_err()
+                .contains("differs")
+        );
+        assert!(check_rustc_version(Some("rustc 1.0.0"), commit).is_err());
+        assert!(check_rustc_version(None, commit).is_err());
+    }
+
+    #[test]
+    fn test_cargo_must_be_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let cargo = dir.path().join("cargo");
+        fs::write(&cargo, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert!(check_executable(&cargo).is_err());
+            fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        assert!(check_executable(&cargo).is_ok());
+        assert!(check_executable(&dir.path().join("missing")).is_err());
+
+        let paths =
+            env::join_paths([dir.path().join("missing"), dir.path().to_path_buf()]).unwrap();
+        assert_eq!(find_in_path("cargo", &paths), Some(cargo));
+        assert_eq!(find_in_path("rustc", &paths), None);
+    }
+
+    /// Runs no command, recording the programs that would run.
+    fn recorder(programs: &mut Vec<OsString>) -> impl FnMut(&mut Command) -> Option<Output> {
+        |command| {
+            programs.push(command.get_program().to_owned());
+            None
+        }
+    }
+
+    #[test]
+    fn test_offline_missing_sysroot_runs_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing-sysroot");
+        let mut programs = Vec::new();
+        let error =
+            resolve_sysroot(None, &missing, true, &mut recorder(&mut programs)).unwrap_err();
+        assert!(programs.is_empty(), "{programs:?}");
+        assert!(
+            matches!(error, ToolchainError::OfflineMissing { ref what } if what.contains("missing-sysroot"))
+        );
+        assert_eq!(error.exit_code(), 2);
+        assert!(
+            error.to_string().contains("rustup toolchain install"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_missing_sysroot_asks_rustc_not_rustup() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing-sysroot");
+        let mut programs = Vec::new();
+        let error =
+            resolve_sysroot(None, &missing, false, &mut recorder(&mut programs)).unwrap_err();
+        assert_eq!(error, ToolchainError::SysrootNotFound);
+        assert_eq!(error.exit_code(), 1);
+        assert_eq!(programs, vec![OsString::from("rustc")]);
+    }
+
+    #[test]
+    fn test_sysroot_from_env_is_preferred() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing-sysroot");
+        let mut programs = Vec::new();
+        let sysroot = resolve_sysroot(
+            Some(dir.path().to_path_buf()),
+            &missing,
+            true,
+            &mut recorder(&mut programs),
+        );
+        assert_eq!(sysroot, Ok(dir.path().to_path_buf()));
+        assert!(programs.is_empty(), "{programs:?}");
+    }
+
+    #[test]
+    fn test_offline_cargo_command() {
+        let args = |offline| {
+            cargo_command(Path::new("/owl"), Path::new("/sysroot"), offline)
+                .as_std()
+                .get_args()
+                .map(OsStr::to_owned)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(args(true), vec![OsString::from("--offline")]);
+        assert!(args(false).is_empty());
+    }
+
+    #[test]
+    fn test_status_fails_if_any_check_fails() {
+        let status = ToolchainStatus::new(vec![
+            ToolchainCheck::new("toolchain", Ok("/toolchain".to_owned())),
+            ToolchainCheck::new("sysroot", Err("/missing does not exist".to_owned())),
+        ]);
+        assert!(!status.ok);
+        let table = status.to_string();
+        assert!(table.contains("toolchain  OK    /toolchain"));
+        assert!(table.contains("sysroot    FAIL  /missing does not exist"));
+    }
+}
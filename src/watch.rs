@@ -0,0 +1,315 @@
+//! Analysis of a workspace again whenever its sources change, for
+//! `check --watch`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    lsp_fingerprint,
+    lsp_workspace::{Analyzer, AnalyzerError, AnalyzerEvent, TargetKind},
+    mir_algorithm::AlgorithmChoice,
+    models::{Crate, Workspace},
+    report::Summary,
+};
+
+/// Time without further changes after which a burst of changes is analyzed
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    pub target_kinds: Vec<TargetKind>,
+    pub all_features: bool,
+    /// Analyze the first round even if the sources did not change since the
+    /// last check
+    pub force: bool,
+    pub polonius_algo: Option<AlgorithmChoice>,
+    /// Time between two looks at the sources
+    pub poll_interval: Duration,
+}
+
+/// Part of the workspace analyzed in a round
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Scope {
+    Workspace,
+    Member(String),
+}
+
+impl Scope {
+    /// Scope covering the changed `paths`.
+    ///
+    /// Manifest changes may affect every member, so they are analyzed with the
+    /// whole workspace.
+    fn of_changes(analyzer: &Analyzer, paths: &BTreeSet<PathBuf>) -> Self {
+        if paths
+            .iter()
+            .any(|path| path.file_name().is_some_and(|name| name == "Cargo.toml"))
+        {
+            return Self::Workspace;
+        }
+        let members: BTreeSet<_> = paths.iter().map(|path| analyzer.member_of(path)).collect();
+        match members.into_iter().collect::<Vec<_>>().as_slice() {
+            [Some(member)] => Self::Member(member.clone()),
+            _ => Self::Workspace,
+        }
+    }
+
+    /// Scope covering both `self` and `other`.
+    fn union(self, other: &Self) -> Self {
+        if self == *other {
+            self
+        } else {
+            Self::Workspace
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Workspace => f.write_str("workspace"),
+            Self::Member(member) => write!(f, "package {member}"),
+        }
+    }
+}
+
+/// Modification time and size of each source file
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+fn snapshot(root: &Path) -> Snapshot {
+    lsp_fingerprint::source_files(root)
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            Some((path, (metadata.modified().ok(), metadata.len())))
+        })
+        .collect()
+}
+
+/// Paths added, removed or modified between `before` and `after`.
+fn changed_paths(before: &Snapshot, after: &Snapshot) -> BTreeSet<PathBuf> {
+    before
+        .keys()
+        .chain(after.keys())
+        .filter(|path| before.get(*path) != after.get(*path))
+        .cloned()
+        .collect()
+}
+
+/// Polls the sources under a root for changes
+struct Watcher {
+    root: PathBuf,
+    poll_interval: Duration,
+    snapshot: Snapshot,
+}
+
+impl Watcher {
+    fn new(root: PathBuf, poll_interval: Duration) -> Self {
+        let snapshot = snapshot(&root);
+        Self {
+            root,
+            poll_interval,
+            snapshot,
+        }
+    }
+
+    /// Paths changed since the last call, once no further change happened for
+    /// [`DEBOUNCE`].
+    ///
+    /// Cancel safe: changes seen by a dropped call are returned by the next.
+    async fn changes(&mut self) -> BTreeSet<PathBuf> {
+        let mut current = loop {
+            time::sleep(self.poll_interval).await;
+            let current = snapshot(&self.root);
+            if current != self.snapshot {
+                break current;
+            }
+        };
+        loop {
+            time::sleep(DEBOUNCE).await;
+            let next = snapshot(&self.root);
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        let changed = changed_paths(&self.snapshot, &current);
+        self.snapshot = current;
+        changed
+    }
+}
+
+/// Analyze `scope`, returning the results by crate, or `None` if cancelled.
+async fn analyze_round(
+    analyzer: Analyzer,
+    scope: Scope,
+    options: WatchOptions,
+    force: bool,
+    cancellation_token: CancellationToken,
+) -> Option<HashMap<String, Crate>> {
+    let mut iter = match &scope {
+        Scope::Workspace => {
+            analyzer
+                .analyze(
+                    &options.target_kinds,
+                    options.all_features,
+                    force,
+                    options.polonius_algo,
+                )
+                .await
+        }
+        Scope::Member(member) => {
+            analyzer
+                .analyze_member(
+                    member,
+                    &options.target_kinds,
+                    options.all_features,
+                    options.polonius_algo,
+                )
+                .await
+        }
+    };
+    let mut crates = HashMap::<String, Crate>::new();
+    loop {
+        let event = tokio::select! {
+            () = cancellation_token.cancelled() => return None,
+            event = iter.next_event() => event,
+        };
+        match event {
+            Some(AnalyzerEvent::Analyzed(ws)) => {
+                for (name, krate) in ws.crates {
+                    if let Some(merged) = crates.get_mut(&name) {
+                        merged.merge(krate);
+                    } else {
+                        crates.insert(name, krate);
+                    }
+                }
+            }
+            Some(AnalyzerEvent::CompileError { file, message, .. }) => {
+                log::warn!("{}: {message}", file.display());
+            }
+            Some(AnalyzerEvent::CrateFailed { package, message }) => {
+                log::warn!("cannot analyze {package}: {message}");
+            }
+            // skipped files are logged by the analyzer
+            Some(
+                AnalyzerEvent::CrateChecked { .. }
+                | AnalyzerEvent::Skipped { .. }
+                | AnalyzerEvent::WaitingForLock,
+            ) => {}
+            None => return Some(crates),
+        }
+    }
+}
+
+/// How a round ended
+enum RoundEnd {
+    Finished(Option<HashMap<String, Crate>>),
+    /// Sources changed before the round finished
+    Interrupted(BTreeSet<PathBuf>),
+}
+
+/// Analyze the workspace at `path` and again after each change of its
+/// sources, printing a summary of every round and how it differs from the
+/// previous one. Runs until the process is stopped.
+pub async fn watch(path: &Path, options: WatchOptions) -> Result<(), AnalyzerError> {
+    let analyzer = Analyzer::new(path).await?;
+    let root = analyzer.target_path().to_path_buf();
+    let mut watcher = Watcher::new(root.clone(), options.poll_interval);
+    let mut results = Workspace {
+        crates: HashMap::new(),
+        meta: None,
+    };
+    let mut previous: Option<Summary> = None;
+    let mut scope = Scope::Workspace;
+    let mut force = options.force;
+
+    for round in 1.. {
+        log::info!("analysis round {round} of the {scope}");
+        let cancellation_token = CancellationToken::new();
+        let mut handle = tokio::spawn(analyze_round(
+            analyzer.clone(),
+            scope.clone(),
+            options.clone(),
+            force,
+            cancellation_token.clone(),
+        ));
+        let end = tokio::select! {
+            result = &mut handle => RoundEnd::Finished(result.ok().flatten()),
+            changed = watcher.changes() => RoundEnd::Interrupted(changed),
+        };
+        match end {
+            RoundEnd::Finished(crates) => {
+                force = false;
+                results.crates.extend(crates.unwrap_or_default());
+                let summary = Summary::new(&results).relative_to(&root);
+                println!("round {round} ({scope}):");
+                print!("{summary}");
+                if let Some(previous) = &previous {
+                    let diff = summary.diff(previous);
+                    if diff.is_empty() {
+                        println!("no changes since round {}", round - 1);
+                    } else {
+                        println!("changes since round {}:", round - 1);
+                        for file in diff {
+                            println!("  {file}");
+                        }
+                    }
+                }
+                previous = Some(summary);
+                scope = Scope::of_changes(&analyzer, &watcher.changes().await);
+            }
+            RoundEnd::Interrupted(changed) => {
+                log::info!("sources changed, cancel analysis round {round}");
+                cancellation_token.cancel();
+                handle.await.ok();
+                // the cancelled scope still has to be analyzed
+                scope = scope.union(&Scope::of_changes(&analyzer, &changed));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_paths() {
+        let at = |secs| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        let before = Snapshot::from([
+            (PathBuf::from("a.rs"), (at(1), 10)),
+            (PathBuf::from("b.rs"), (at(1), 10)),
+            (PathBuf::from("c.rs"), (at(1), 10)),
+        ]);
+        let after = Snapshot::from([
+            (PathBuf::from("a.rs"), (at(1), 10)),
+            (PathBuf::from("b.rs"), (at(2), 10)),
+            (PathBuf::from("d.rs"), (at(1), 10)),
+        ]);
+        let changed: Vec<_> = changed_paths(&before, &after).into_iter().collect();
+        assert_eq!(
+            changed,
+            vec![
+                PathBuf::from("b.rs"),
+                PathBuf::from("c.rs"),
+                PathBuf::from("d.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scope_union() {
+        let member = |name: &str| Scope::Member(name.to_owned());
+        assert_eq!(member("a").union(&member("a")), member("a"));
+        assert_eq!(member("a").union(&member("b")), Scope::Workspace);
+        assert_eq!(Scope::Workspace.union(&member("a")), Scope::Workspace);
+    }
+}
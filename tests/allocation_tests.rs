@@ -0,0 +1,109 @@
+#![feature(rustc_private)]
+
+//! Tests for heap allocations of selected variables.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+pub fn test() -> usize {
+    let b = Box::new(5);
+    let n = *b + 1;
+    let v = vec![1, 2, 3];
+    n + v.len()
+}
+";
+
+/// Decorations at each of `positions` in `code`, once the analysis finished.
+fn decorations_at(name: &str, code: &str, positions: &[(u32, u32)]) -> Vec<Vec<Value>> {
+    let package = TestPackage::new(name, code).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    let decorations = positions
+        .iter()
+        .map(|&(line, character)| {
+            loop {
+                let response = client.cursor(&package.uri, line, character).unwrap();
+                let result = &response["result"];
+                if result["is_analyzed"] == json!(true) && result["status"] == json!("finished") {
+                    break result["decorations"]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default();
+                }
+                assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+                thread::sleep(Duration::from_millis(500));
+            }
+        })
+        .collect();
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+    decorations
+}
+
+fn hover_texts<'a>(decorations: &'a [Value], ty: &str) -> Vec<&'a str> {
+    decorations
+        .iter()
+        .filter(|deco| deco["type"] == json!(ty))
+        .filter_map(|deco| deco["hover_text"].as_str())
+        .collect()
+}
+
+#[test]
+fn box_lifetime_owns_heap_allocation() {
+    let decorations = decorations_at("allocation", CODE, &[(1, 8), (2, 8), (3, 8)]);
+    let [boxed, number, vector] = decorations.as_slice() else {
+        unreachable!()
+    };
+
+    let lifetimes = hover_texts(boxed, "lifetime");
+    assert!(!lifetimes.is_empty(), "{boxed:#?}");
+    assert!(
+        lifetimes
+            .iter()
+            .all(|text| *text == "lifetime of variable `b` (owns heap allocation)"),
+        "{lifetimes:?}"
+    );
+    let allocations: Vec<_> = boxed
+        .iter()
+        .filter(|deco| deco["type"] == json!("allocation"))
+        .collect();
+    assert_eq!(allocations.len(), 1, "{boxed:#?}");
+    assert_eq!(
+        allocations[0]["hover_text"],
+        json!("heap allocation for variable `b`")
+    );
+    assert_eq!(
+        allocations[0]["range"]["start"],
+        json!({ "line": 1, "character": 12 })
+    );
+    assert_eq!(
+        allocations[0]["range"]["end"],
+        json!({ "line": 1, "character": 20 })
+    );
+
+    assert!(
+        hover_texts(number, "lifetime")
+            .iter()
+            .all(|text| *text == "lifetime of variable `n`"),
+        "{number:#?}"
+    );
+    assert!(hover_texts(number, "allocation").is_empty(), "{number:#?}");
+
+    assert_eq!(
+        hover_texts(vector, "allocation"),
+        vec!["heap allocation for variable `v`"],
+        "{vector:#?}"
+    );
+}
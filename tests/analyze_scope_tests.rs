@@ -0,0 +1,89 @@
+#![feature(rustc_private)]
+
+//! Tests for analyzing only a file or a package of the workspace.
+
+use std::{env, fmt::Write, fs, process, time::Duration};
+
+use ferrous_owl::LspClient;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+/// Workspace with the members `small` and `large`, and an empty folder
+/// next to them, returning the path of the workspace.
+fn create_workspace() -> String {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("analyze_scope_{}", process::id()));
+    let dir = dir.to_string_lossy().into_owned();
+    fs::create_dir_all(format!("{dir}/empty")).unwrap();
+    fs::write(
+        format!("{dir}/Cargo.toml"),
+        "[workspace]\nmembers = [\"small\", \"large\"]\nresolver = \"2\"\n",
+    )
+    .unwrap();
+    let mut large = String::new();
+    for i in 0..200 {
+        writeln!(
+            large,
+            "pub fn f{i}() -> usize {{\n    let v = vec![{i}];\n    v.len()\n}}"
+        )
+        .unwrap();
+    }
+    for (name, code) in [("small", CODE), ("large", &large)] {
+        fs::create_dir_all(format!("{dir}/{name}/src")).unwrap();
+        fs::write(
+            format!("{dir}/{name}/Cargo.toml"),
+            format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"),
+        )
+        .unwrap();
+        fs::write(format!("{dir}/{name}/src/lib.rs"), code).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn file_scope_analyzes_only_the_file() {
+    let dir = create_workspace();
+    let small_uri = format!("file://{dir}/small/src/lib.rs");
+    let large_uri = format!("file://{dir}/large/src/lib.rs");
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    // nothing is analyzed in a folder without packages
+    client.initialize(&format!("file://{dir}/empty")).unwrap();
+
+    let id = client
+        .execute_command("ferrous-owl.analyze", &[json!({ "scope": "file" })])
+        .unwrap();
+    let response = client.wait_for_response(id, TIMEOUT).unwrap();
+    assert_eq!(response["error"]["code"], json!(-32602), "{response}");
+
+    let id = client
+        .send_request(
+            "ferrous-owl/analyze",
+            &json!({ "scope": "file", "uri": small_uri }),
+        )
+        .unwrap();
+    let response = client.wait_for_response(id, TIMEOUT).unwrap();
+    assert!(response.get("error").is_none(), "{response}");
+    client
+        .wait_for_decorations(&small_uri, 1, 8, TIMEOUT)
+        .unwrap();
+
+    let large = client.cursor(&large_uri, 1, 8).unwrap();
+    assert_eq!(large["result"]["decorations"], json!([]));
+    let status = client.status().unwrap();
+    let analyzed: Vec<_> = status["result"]["analyzed_files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["path"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(analyzed.len(), 1, "{analyzed:?}");
+    assert!(analyzed[0].ends_with("small/src/lib.rs"), "{analyzed:?}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,70 @@
+// only linking the compiler needs the feature, not the API itself
+#![cfg_attr(feature = "compiler", feature(rustc_private))]
+
+//! Tests of the library API, which also builds without the `compiler`
+//! feature.
+
+use std::{
+    error,
+    path::{Path, PathBuf},
+};
+
+use ferrous_owl::api::{self, AnalysisError, AnalyzeOptions, AnalyzerError};
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/dummy")
+}
+
+/// Needs the binary, which is only built with the `compiler` feature.
+#[cfg(feature = "compiler")]
+#[tokio::test]
+async fn decorations_of_analyzed_fixture() {
+    use std::fs;
+
+    use ferrous_owl::{LineCol, SourceIndex};
+
+    let options = AnalyzeOptions {
+        compiler: Some(env!("CARGO_BIN_EXE_ferrous-owl").into()),
+        ..AnalyzeOptions::default()
+    };
+    let workspace = api::analyze_package(&fixture(), options)
+        .await
+        .expect("fixture should be analyzed");
+    assert!(!workspace.crates.is_empty(), "no crate analyzed");
+
+    let file = fixture().join("src/lib.rs");
+    let source = fs::read_to_string(&file).unwrap();
+    // `guard` in `let guard = self.inner.lock().unwrap();`
+    let offset = SourceIndex::new(&source).line_col_to_char(LineCol::new(85, 12));
+    let kinds: Vec<_> = api::decorations_for(&workspace, &file, offset)
+        .iter()
+        .map(api::Deco::kind)
+        .collect();
+    assert!(kinds.contains(&"lifetime"), "{kinds:?}");
+    assert!(kinds.contains(&"imm_borrow"), "{kinds:?}");
+
+    let blank_line = SourceIndex::new(&source).line_col_to_char(LineCol::new(89, 0));
+    assert!(
+        api::decorations_for(&workspace, &file, blank_line).is_empty(),
+        "no variable is selected on a blank line"
+    );
+}
+
+#[tokio::test]
+async fn missing_target_is_invalid() {
+    let missing = fixture().join("does-not-exist");
+    let error = api::analyze_package(&missing, AnalyzeOptions::default())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            &error,
+            AnalysisError::InvalidTarget {
+                path,
+                source: AnalyzerError::NotARustTarget { .. },
+            } if *path == missing
+        ),
+        "{error:?}"
+    );
+    assert!(error::Error::source(&error).is_some());
+}
@@ -0,0 +1,78 @@
+#![feature(rustc_private)]
+
+//! Tests for borrows and guards held across `.await` points.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, ForbiddenDeco, TestCase, run_tests};
+
+fn guard_held_across_await() -> TestCase {
+    TestCase::new(
+        "guard_held_across_await",
+        r#"
+        use std::sync::Mutex;
+
+        async fn tick() {}
+
+        pub async fn test(m: &Mutex<u32>) {
+            let guard = m.lock().unwrap();
+            tick().await;
+            drop(guard);
+        }
+    "#,
+    )
+    .cursor_on("guard = m")
+    .expect(
+        ExpectedDeco::held_across_await()
+            .on_line(6)
+            .with_message("borrow of variable `guard` is held across this await"),
+    )
+}
+
+fn guard_dropped_before_await() -> TestCase {
+    TestCase::new(
+        "guard_dropped_before_await",
+        r#"
+        use std::sync::Mutex;
+
+        async fn tick() {}
+
+        pub async fn test(m: &Mutex<u32>) {
+            let guard = m.lock().unwrap();
+            drop(guard);
+            tick().await;
+        }
+    "#,
+    )
+    .cursor_on("guard = m")
+    .forbid(ForbiddenDeco::new(DecoKind::HeldAcrossAwait))
+}
+
+fn borrow_held_across_await() -> TestCase {
+    TestCase::new(
+        "borrow_held_across_await",
+        r#"
+        async fn tick() {}
+
+        pub async fn test() -> usize {
+            let v = vec![1, 2, 3];
+            let r = &v;
+            tick().await;
+            r.len()
+        }
+    "#,
+    )
+    .cursor_on("v = vec")
+    .expect(
+        ExpectedDeco::held_across_await()
+            .on_line(5)
+            .with_message("borrow of variable `v` is held across this await"),
+    )
+}
+
+#[test]
+fn all_await_tests() {
+    run_tests(&[
+        guard_held_across_await(),
+        guard_dropped_before_await(),
+        borrow_held_across_await(),
+    ]);
+}
@@ -0,0 +1,76 @@
+#![feature(rustc_private)]
+
+//! Tests for calls whose returned reference keeps their arguments borrowed.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, ForbiddenDeco, TestCase, run_tests};
+
+const RETURNED_BORROW: &str = "borrowed by the value returned from this call";
+
+const PICK: &str = r"
+    fn pick<'a>(a: &'a str, b: &'a str) -> &'a str {
+        if a.len() > b.len() { a } else { b }
+    }
+
+    fn test() -> usize {
+        let first = String::from('a');
+        let second = String::from('b');
+        let picked = pick(&first, &second);
+        picked.len()
+    }
+    ";
+
+#[test]
+fn all_borrows_from_tests() {
+    run_tests(&[
+        TestCase::new("first_argument_of_picker", PICK)
+            .cursor_on("first = String")
+            .expect(
+                ExpectedDeco::imm_borrow()
+                    .on_line(7)
+                    .with_message(RETURNED_BORROW),
+            ),
+        TestCase::new("second_argument_of_picker", PICK)
+            .cursor_on("second = String")
+            .expect(
+                ExpectedDeco::imm_borrow()
+                    .on_line(7)
+                    .with_message(RETURNED_BORROW),
+            ),
+        TestCase::new(
+            "mutable_argument",
+            r"
+            fn first(v: &mut Vec<u8>) -> &mut u8 {
+                &mut v[0]
+            }
+
+            fn test() {
+                let mut v = vec![1, 2];
+                let x = first(&mut v);
+                *x = 3;
+            }
+            ",
+        )
+        .cursor_on("mut v = vec")
+        .expect(
+            ExpectedDeco::mut_borrow()
+                .on_line(6)
+                .with_message(RETURNED_BORROW),
+        ),
+        TestCase::new(
+            "owned_return_value",
+            r"
+            fn length(s: &str) -> usize {
+                s.len()
+            }
+
+            fn test() -> usize {
+                let s = String::from('a');
+                length(&s)
+            }
+            ",
+        )
+        .cursor_on("s = String")
+        .expect(ExpectedDeco::imm_borrow().on_line(6))
+        .forbid(ForbiddenDeco::new(DecoKind::ImmBorrow).at_text(RETURNED_BORROW)),
+    ]);
+}
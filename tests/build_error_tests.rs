@@ -0,0 +1,23 @@
+#![feature(rustc_private)]
+
+//! Tests for compiler errors reported when the analyzed code does not build.
+
+use ferrous_owl::{TestCase, run_tests};
+
+fn type_mismatch() -> TestCase {
+    TestCase::new(
+        "build_error_type_mismatch",
+        r"
+        fn test() {
+            let s: String = 42;
+            drop(s);
+        }
+    ",
+    )
+    .expect_build_error(1, "mismatched types")
+}
+
+#[test]
+fn all_build_error_tests() {
+    run_tests(&[type_mismatch()]);
+}
@@ -2,7 +2,7 @@
 
 //! Tests for function call decoration detection.
 
-use ferrous_owl::{DecoKind, TestCase, run_tests};
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
 
 fn call_string_new() -> TestCase {
     TestCase::new(
@@ -182,6 +182,44 @@ fn call_collect() -> TestCase {
     .expect_call()
 }
 
+fn call_nested_outer_on_callee() -> TestCase {
+    TestCase::new(
+        "call_nested_outer_on_callee",
+        r#"
+        fn inner(x: u32) -> u32 {
+            x + 1
+        }
+
+        fn outer(x: u32) -> u32 {
+            x * 2
+        }
+
+        fn test() {
+            let y = outer(inner(1));
+            drop(y);
+        }
+    "#,
+    )
+    .cursor_on("y = outer")
+    .expect(ExpectedDeco::call().on_line(9).covering_text("outer"))
+    .expect_count(DecoKind::Call, 1)
+}
+
+fn call_method_chain_on_method_name() -> TestCase {
+    TestCase::new(
+        "call_method_chain_on_method_name",
+        r#"
+        fn test() {
+            let n = " a ".trim().len();
+            drop(n);
+        }
+    "#,
+    )
+    .cursor_on("n = ")
+    .expect(ExpectedDeco::call().on_line(1).covering_text("len"))
+    .expect_count(DecoKind::Call, 1)
+}
+
 #[test]
 fn all_call_tests() {
     run_tests(&[
@@ -197,5 +235,7 @@ fn all_call_tests() {
         call_to_string(),
         call_default(),
         call_collect(),
+        call_nested_outer_on_callee(),
+        call_method_chain_on_method_name(),
     ]);
 }
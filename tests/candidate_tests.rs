@@ -0,0 +1,88 @@
+#![feature(rustc_private)]
+
+//! Tests for choosing among the locals under the cursor.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+use std::collections::HashMap;
+
+pub fn test(map: HashMap<String, u8>, key: String) -> Option<u8> {
+    map.get(&key).copied()
+}
+";
+
+/// Cursor result on the `key` argument of `get`, selecting the candidate at
+/// `selected_index`.
+fn cursor(client: &mut LspClient, uri: &str, selected_index: Option<usize>) -> Value {
+    let id = client
+        .send_request(
+            "ferrous-owl/cursor",
+            &json!({
+                "position": { "line": 3, "character": 14 },
+                "document": { "uri": uri },
+                "selected_index": selected_index,
+            }),
+        )
+        .unwrap();
+    client.wait_for_response(id, TIMEOUT).unwrap()["result"].clone()
+}
+
+/// Whether `result` has decorations, all of the local of `candidate`.
+fn decorates(result: &Value, candidate: &Value) -> bool {
+    result["decorations"].as_array().is_some_and(|decorations| {
+        !decorations.is_empty()
+            && decorations
+                .iter()
+                .all(|deco| deco["local"] == candidate["local"])
+    })
+}
+
+#[test]
+fn candidates_under_the_cursor_can_be_selected() {
+    let package = TestPackage::new("candidates", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    let default = loop {
+        let result = cursor(&mut client, &package.uri, None);
+        if result["candidates"]
+            .as_array()
+            .is_some_and(|candidates| !candidates.is_empty())
+        {
+            break result;
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for {result}");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let candidates = default["candidates"].as_array().unwrap().clone();
+    assert_eq!(candidates.len(), 3, "{candidates:#?}");
+    assert_eq!(candidates[0]["name"], json!("key"));
+    assert_eq!(candidates[0]["ty"], json!("std::string::String"));
+    // the borrow of `key` and the result of `get`
+    assert_eq!(candidates[1]["ty"], json!("&std::string::String"));
+    assert_eq!(candidates[2]["ty"], json!("std::option::Option<&u8>"));
+    assert!(decorates(&default, &candidates[0]), "{default:#?}");
+
+    let second = cursor(&mut client, &package.uri, Some(1));
+    assert_ne!(second["selection_id"], default["selection_id"]);
+    assert_eq!(second["candidates"], default["candidates"]);
+    assert!(decorates(&second, &candidates[1]), "{second:#?}");
+
+    // out of range, the default selection
+    let default_again = cursor(&mut client, &package.uri, Some(3));
+    assert_eq!(default_again["selection_id"], default["selection_id"]);
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,80 @@
+#![feature(rustc_private)]
+
+//! Tests for decorations inside closures and async blocks that capture the
+//! selected variable.
+
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
+
+fn capture_imm_borrow() -> TestCase {
+    // `s.len()` inside the closure borrows the captured upvar, which is
+    // attributed to `s` of the enclosing function.
+    TestCase::new(
+        "capture_imm_borrow",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            let f = || s.len();
+            let _ = f();
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .with_message("captured by closure"),
+    )
+}
+
+fn capture_move_used_in_closure() -> TestCase {
+    TestCase::new(
+        "capture_move_used_in_closure",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            let f = move || drop(s);
+            f();
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::move_deco()
+            .on_line(2)
+            .with_message("captured by closure"),
+    )
+}
+
+fn capture_async_block_across_await() -> TestCase {
+    TestCase::new(
+        "capture_async_block_across_await",
+        r#"
+        async fn tick() {}
+
+        fn test() {
+            let s = String::from("hello");
+            let fut = async {
+                let r = &s;
+                tick().await;
+                r.len()
+            };
+            drop(fut);
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(5)
+            .with_message("captured by closure"),
+    )
+}
+
+#[test]
+fn all_capture_tests() {
+    run_tests(&[
+        capture_imm_borrow(),
+        capture_move_used_in_closure(),
+        capture_async_block_across_await(),
+    ]);
+}
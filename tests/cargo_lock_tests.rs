@@ -0,0 +1,56 @@
+#![feature(rustc_private)]
+
+//! Tests for analysis waiting until another cargo process releases the
+//! build directory.
+
+use std::{
+    fs::{self, File},
+    thread,
+    time::Duration,
+};
+
+use ferrous_owl::TestPackage;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `s` on line 1
+const CODE: &str = "\
+pub fn test() -> usize {
+    let s = String::new();
+    s.len()
+}
+";
+
+#[test]
+fn analysis_waits_for_cargo_lock() {
+    let package = TestPackage::new("cargo_lock", CODE).expect("Failed to create workspace");
+
+    // held like cargo holds it while it builds
+    let lock_dir = format!("{}/target/owl", package.dir);
+    fs::create_dir_all(&lock_dir).expect("Failed to create build directory");
+    let lock = File::create(format!("{lock_dir}/.cargo-lock")).expect("Failed to create lock");
+    lock.lock().expect("Failed to lock");
+
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    thread::sleep(Duration::from_secs(3));
+    let waiting = client.cursor(&package.uri, 1, 8).expect("cursor request");
+    assert_eq!(waiting["result"]["status"], "analyzing", "{waiting:#}");
+    assert!(
+        waiting["result"]["decorations"]
+            .as_array()
+            .is_none_or(Vec::is_empty),
+        "analyzed while the build directory is locked: {waiting:#}"
+    );
+
+    lock.unlock().expect("Failed to unlock");
+    let result = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    assert_eq!(result["status"], "finished", "{result:#}");
+
+    client.shutdown().unwrap();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,25 @@
+#![feature(rustc_private)]
+
+//! Runs the test cases of the files in `tests/cases`, or in the directory
+//! given with `--dir <path>`:
+//!
+//! ```sh
+//! cargo test --test case_files -- --dir path/to/cases
+//! ```
+
+use std::{env, path::PathBuf};
+
+use ferrous_owl::run_tests_from_dir;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    while let Some(arg) = args.next() {
+        if arg == "--dir" {
+            dir = args.next().expect("--dir needs a path").into();
+        } else if let Some(path) = arg.strip_prefix("--dir=") {
+            dir = path.into();
+        }
+    }
+    run_tests_from_dir(dir);
+}
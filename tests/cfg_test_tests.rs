@@ -0,0 +1,39 @@
+#![feature(rustc_private)]
+
+//! Tests for code analyzed both for the library and for its tests.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
+
+const CODE: &str = r"
+pub fn lib_fn() {
+    let s = String::new();
+    #[cfg(test)]
+    let t = s.clone();
+    drop(s);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_fn() {
+        let u = String::new();
+        drop(u);
+    }
+}
+";
+
+#[test]
+fn all_cfg_test_tests() {
+    run_tests(&[
+        TestCase::new("cursor_in_lib_fn", CODE)
+            .cursor_on("s = String")
+            .expect(ExpectedDeco::move_deco().on_line(4).covering_text("s"))
+            .forbid(DecoKind::ImmBorrow),
+        TestCase::new("cursor_in_test_only_statement", CODE)
+            .cursor_on("s.clone()")
+            .expect(ExpectedDeco::imm_borrow().on_line(3).covering_text("s")),
+        TestCase::new("cursor_in_test_fn", CODE)
+            .cursor_on("u = String")
+            .expect(ExpectedDeco::move_deco().on_line(12).covering_text("u")),
+    ]);
+}
@@ -0,0 +1,65 @@
+#![feature(rustc_private)]
+
+//! Tests for leaving the paths that only run while unwinding out of live
+//! ranges.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::Value;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `v` is moved into `drop`, but is dropped at the end of `test` if
+/// `may_panic` unwinds.
+const CODE: &str = "\
+fn may_panic(n: usize) -> usize {
+    assert!(n < 10);
+    n
+}
+
+pub fn test() -> usize {
+    let v = vec![1];
+    let m = v.len();
+    drop(v);
+    may_panic(m)
+}
+";
+
+#[test]
+fn moved_variable_is_not_live_after_a_call_that_can_unwind() {
+    let package = TestPackage::new("cleanup", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    let decorations = loop {
+        let response = client.cursor(&package.uri, 6, 8).expect("cursor request");
+        if let Some(items) = response["result"]["decorations"]
+            .as_array()
+            .filter(|items| !items.is_empty())
+        {
+            break items.clone();
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for {response}");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let on_line = |kind: &str, line: u64| {
+        decorations
+            .iter()
+            .any(|deco: &Value| deco["type"] == kind && deco["range"]["start"]["line"] == line)
+    };
+    assert!(on_line("lifetime", 7), "{decorations:#?}");
+    assert!(on_line("move", 8), "{decorations:#?}");
+    for line in [9, 10] {
+        assert!(!on_line("lifetime", line), "{decorations:#?}");
+        assert!(!on_line("drop", line), "{decorations:#?}");
+    }
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
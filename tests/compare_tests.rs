@@ -0,0 +1,87 @@
+#![feature(rustc_private)]
+
+//! Tests for the `ferrous-owl.compareSelections` command.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `a` on line 1 is moved, `b` on line 2 is borrowed
+const CODE: &str = "pub fn test() {
+    let a = String::new();
+    let b = String::new();
+    let moved = a;
+    let borrowed = &b;
+    drop(moved);
+    let _ = borrowed;
+}
+";
+
+fn kinds(decorations: &Value) -> Vec<&str> {
+    decorations
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|deco| deco["type"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn moved_and_borrowed_variables_differ() {
+    let package = TestPackage::new("compare", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    loop {
+        let response = client.cursor(&package.uri, 1, 8).unwrap();
+        if response["result"]["status"] == json!("finished")
+            && response["result"]["decorations"]
+                .as_array()
+                .is_some_and(|items| !items.is_empty())
+        {
+            break;
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    }
+    let response = client
+        .compare_selections(&package.uri, (1, 8), (2, 8))
+        .unwrap();
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+
+    let diff = &response["result"];
+    let only_a = kinds(&diff["only_a"]);
+    let only_b = kinds(&diff["only_b"]);
+    let common = kinds(&diff["common"]);
+    assert!(only_a.contains(&"move"), "{diff:#?}");
+    assert!(!only_b.contains(&"move"), "{diff:#?}");
+    assert!(only_b.contains(&"imm_borrow"), "{diff:#?}");
+    assert!(!only_a.contains(&"imm_borrow"), "{diff:#?}");
+    assert!(common.contains(&"lifetime"), "{diff:#?}");
+}
+
+#[test]
+fn missing_positions_are_rejected() {
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client.initialize("file:///tmp").unwrap();
+    let id = client
+        .execute_command(
+            "ferrous-owl.compareSelections",
+            &[json!("file:///tmp/lib.rs"), json!(1), json!(8)],
+        )
+        .unwrap();
+    let response = client
+        .wait_for_response(id, Duration::from_secs(30))
+        .unwrap();
+    let _ = client.shutdown();
+    assert!(response["error"].is_object(), "{response:#?}");
+}
@@ -0,0 +1,64 @@
+#![feature(rustc_private)]
+
+//! Tests for moves of a variable where a borrow of it is still live.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
+
+#[test]
+fn all_conflict_tests() {
+    run_tests(&[
+        TestCase::new(
+            "return_while_borrowed_in_condition",
+            r"
+            fn test(s: String) -> String {
+                let r = &s;
+                if r.is_empty() {
+                    return s;
+                }
+                s
+            }
+            ",
+        )
+        .cursor_on("s: String")
+        .expect(
+            ExpectedDeco::conflict()
+                .on_line(3)
+                .covering_text("s")
+                .with_message("moved here while an immutable borrow is still live"),
+        )
+        .expect(ExpectedDeco::move_deco().on_line(5)),
+        TestCase::new(
+            "return_while_mutably_borrowed_in_condition",
+            r"
+            fn test(mut v: Vec<u8>) -> Vec<u8> {
+                let r = &mut v;
+                if r.is_empty() {
+                    return v;
+                }
+                v
+            }
+            ",
+        )
+        .cursor_on("v: Vec")
+        .expect(
+            ExpectedDeco::conflict()
+                .on_line(3)
+                .covering_text("v")
+                .with_message("moved here while a mutable borrow is still live"),
+        ),
+        TestCase::new(
+            "borrow_ends_before_move",
+            r"
+            fn take(_: usize, _: String) {}
+
+            fn test() {
+                let s = String::new();
+                take(s.len(), s);
+            }
+            ",
+        )
+        .cursor_on("s = String")
+        .expect(ExpectedDeco::move_deco().on_line(4))
+        .forbid(DecoKind::Conflict),
+    ]);
+}
@@ -0,0 +1,106 @@
+#![feature(rustc_private)]
+
+//! Tests for not sending unchanged decorations at the cursor again.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+#[test]
+fn unchanged_decorations_are_not_sent_again() {
+    let package = TestPackage::new("cursor_delta", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    let full = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    let selection_id = full["selection_id"].as_u64().expect("no selection id");
+    assert!(
+        selection_id < 1 << 53,
+        "{selection_id} is not exact in JavaScript"
+    );
+
+    // the same variable, selected from its use
+    let unchanged = client
+        .cursor_since(&package.uri, 2, 9, selection_id)
+        .unwrap()["result"]
+        .clone();
+    assert_eq!(unchanged["unchanged"], json!(true), "{unchanged:#?}");
+    assert_eq!(unchanged["selection_id"], json!(selection_id));
+    assert!(unchanged.get("decorations").is_none(), "{unchanged:#?}");
+
+    // another selection is sent in full
+    let other = client
+        .cursor_since(&package.uri, 0, 0, selection_id)
+        .unwrap()["result"]
+        .clone();
+    assert!(other.get("unchanged").is_none(), "{other:#?}");
+    assert!(other["decorations"].is_array(), "{other:#?}");
+
+    fs::write(&package.file, format!("// inserted\n{CODE}")).expect("Failed to rewrite source");
+    let start = Instant::now();
+    let changed = loop {
+        let result = client
+            .cursor_since(&package.uri, 2, 8, selection_id)
+            .unwrap()["result"]
+            .clone();
+        assert!(result.get("unchanged").is_none(), "{result:#?}");
+        if result["decorations"]
+            .as_array()
+            .is_some_and(|items| !items.is_empty())
+        {
+            break result;
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+    assert_ne!(changed["selection_id"], json!(selection_id), "{changed:#?}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
+
+#[test]
+fn changed_display_kinds_send_decorations_again() {
+    let package = TestPackage::new("cursor_delta_kinds", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    let full = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    let selection_id = full["selection_id"].as_u64().expect("no selection id");
+
+    client
+        .change_configuration(&json!({ "displayKinds": ["move"] }))
+        .unwrap();
+    let filtered = client
+        .cursor_since(&package.uri, 1, 8, selection_id)
+        .unwrap()["result"]
+        .clone();
+    assert!(filtered.get("unchanged").is_none(), "{filtered:#?}");
+    assert_ne!(
+        filtered["selection_id"],
+        json!(selection_id),
+        "{filtered:#?}"
+    );
+    let types: Vec<_> = filtered["decorations"]
+        .as_array()
+        .expect("decorations sent again")
+        .iter()
+        .map(|deco| deco["type"].clone())
+        .collect();
+    assert_eq!(types, vec![json!("move")], "{filtered:#?}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,84 @@
+#![feature(rustc_private)]
+
+//! Tests for the status reported when no variable is under the cursor.
+
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n\n    drop(s);\n}\n";
+
+/// Execute `command` at `line` and `character` and return whether
+/// diagnostics were published for `file_uri` until shortly after the
+/// response.
+fn publishes_diagnostics(
+    client: &mut LspClient,
+    command: &str,
+    file_uri: &str,
+    line: u32,
+    character: u32,
+) -> bool {
+    let id = client
+        .execute_command(command, &[json!(file_uri), json!(line), json!(character)])
+        .expect("execute command");
+    let mut published = false;
+    let mut responded_at = None;
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT
+        && responded_at.is_none_or(|at: Instant| at.elapsed() < Duration::from_secs(1))
+    {
+        let Some(msg) = client.receive_message(Duration::from_millis(100)).unwrap() else {
+            continue;
+        };
+        if msg["id"].as_i64() == Some(id) {
+            responded_at = Some(Instant::now());
+        }
+        if msg["method"] == "textDocument/publishDiagnostics" && msg["params"]["uri"] == file_uri {
+            published = true;
+        }
+    }
+    published
+}
+
+#[test]
+fn cursor_without_variable_keeps_selection() {
+    let package = TestPackage::new("cursor_status", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    let selected = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    assert_eq!(selected["status"], json!("finished"));
+
+    let blank = client.cursor(&package.uri, 2, 0).unwrap();
+    assert_eq!(blank["result"]["status"], json!("no_variable_selected"));
+    assert_eq!(blank["result"]["decorations"], json!([]));
+
+    assert!(publishes_diagnostics(
+        &mut client,
+        "ferrous-owl.enableOwnership",
+        &package.uri,
+        1,
+        8
+    ));
+    assert!(
+        !publishes_diagnostics(
+            &mut client,
+            "ferrous-owl.enableOwnership",
+            &package.uri,
+            2,
+            0
+        ),
+        "diagnostics of the last selection are kept"
+    );
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,88 @@
+#![feature(rustc_private)]
+
+//! Tests for dead-write decoration detection.
+
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
+
+fn overwritten_before_read() -> TestCase {
+    TestCase::new(
+        "overwritten_before_read",
+        r#"
+        fn test() -> i32 {
+            let mut x = 1;
+            x = 2;
+            x
+        }
+    "#,
+    )
+    .cursor_on("mut x")
+    .expect(
+        ExpectedDeco::dead_write()
+            .on_line(1)
+            .with_message("never read")
+            .times(1),
+    )
+}
+
+fn assigned_after_last_read() -> TestCase {
+    TestCase::new(
+        "assigned_after_last_read",
+        r#"
+        fn test() -> usize {
+            let mut s = String::from("hello");
+            let n = s.len();
+            s = String::new();
+            n
+        }
+    "#,
+    )
+    .cursor_on("mut s")
+    .expect(ExpectedDeco::dead_write().on_line(3).times(1))
+}
+
+fn constructed_then_returned() -> TestCase {
+    // Constructor pattern: every assigned value is read.
+    TestCase::new(
+        "constructed_then_returned",
+        r#"
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        fn test(x: i32) -> Point {
+            let p = Point { x, y: 0 };
+            return p;
+        }
+    "#,
+    )
+    .cursor_on("p = Point")
+    .forbid_dead_write()
+}
+
+fn accumulated_in_loop() -> TestCase {
+    TestCase::new(
+        "accumulated_in_loop",
+        r#"
+        fn test(v: &[i32]) -> i32 {
+            let mut sum = 0;
+            for x in v {
+                sum = sum + x;
+            }
+            sum
+        }
+    "#,
+    )
+    .cursor_on("mut sum")
+    .forbid_dead_write()
+}
+
+#[test]
+fn all_dead_write_tests() {
+    run_tests(&[
+        overwritten_before_read(),
+        assigned_after_last_read(),
+        constructed_then_returned(),
+        accumulated_in_loop(),
+    ]);
+}
@@ -0,0 +1,102 @@
+#![feature(rustc_private)]
+
+//! Tests that dependencies of the analyzed packages are only checked.
+
+use std::{
+    env, fs, process, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::LspClient;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const APP: &str = "pub fn app() -> String {\n    let s = dep::dep();\n    s\n}\n";
+
+const DEP: &str = "pub fn dep() -> String {\n    let s = String::new();\n    s\n}\n";
+
+/// Write a workspace at `dir` whose member `app` depends on the member `dep`.
+fn write_workspace(dir: &str) {
+    for (member, code) in [("app", APP), ("dep", DEP)] {
+        fs::create_dir_all(format!("{dir}/{member}/src")).unwrap();
+        fs::write(format!("{dir}/{member}/src/lib.rs"), code).unwrap();
+    }
+    fs::write(
+        format!("{dir}/Cargo.toml"),
+        "[workspace]\nmembers = [\"app\", \"dep\"]\nresolver = \"2\"\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{dir}/app/Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \
+         \"2021\"\n\n[dependencies]\ndep = { path = \"../dep\" }\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{dir}/dep/Cargo.toml"),
+        "[package]\nname = \"dep\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+}
+
+/// Time the results of `path` last arrived in the status report `status`.
+fn last_updated(status: &Value, path: &str) -> Option<u64> {
+    status["analyzed_files"]
+        .as_array()?
+        .iter()
+        .find(|file| file["path"] == json!(path))?["last_updated"]
+        .as_u64()
+}
+
+#[test]
+fn member_analysis_only_checks_dependencies() {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("dependency_{}", process::id()))
+        .to_string_lossy()
+        .to_string();
+    write_workspace(&dir);
+    let app = format!("{dir}/app/src/lib.rs");
+    let dep = format!("{dir}/dep/src/lib.rs");
+    let app_uri = format!("file://{app}");
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client.initialize(&format!("file://{dir}/app")).unwrap();
+    client.open_document(&app_uri, "rust", APP).unwrap();
+    client
+        .wait_for_decorations(&app_uri, 1, 8, TIMEOUT)
+        .unwrap();
+    let status = client.status().unwrap()["result"].clone();
+    let dep_updated = last_updated(&status, &dep).expect("dependency member not analyzed");
+
+    // both change, but only `app` is analyzed again, building `dep` as its
+    // dependency
+    fs::write(&dep, format!("// changed\n{DEP}")).unwrap();
+    let changed = format!("// changed\n{APP}");
+    fs::write(&app, &changed).unwrap();
+    client
+        .change_document(&app_uri, 2, &[json!({ "text": changed })])
+        .unwrap();
+    let stale = client.cursor(&app_uri, 2, 8).unwrap();
+    assert_eq!(stale["result"]["status"], json!("stale"), "{stale:#?}");
+    let start = Instant::now();
+    let status = loop {
+        let result = client.cursor(&app_uri, 2, 8).unwrap()["result"].clone();
+        let status = client.status().unwrap()["result"].clone();
+        if result["status"] == json!("finished") && status["pending_packages"] == json!([]) {
+            break status;
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(last_updated(&status, &app).is_some(), "{status:#?}");
+    assert_eq!(
+        last_updated(&status, &dep),
+        Some(dep_updated),
+        "dependency analyzed again: {status:#?}"
+    );
+}
@@ -0,0 +1,89 @@
+#![feature(rustc_private)]
+
+//! Tests for drop-point decoration detection.
+
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
+
+fn explicit_drop() -> TestCase {
+    // The value is moved into `drop` and dropped there, so no drop is shown
+    // at the end of the scope.
+    TestCase::new(
+        "explicit_drop",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            drop(s);
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect_move()
+    .forbid_drop()
+}
+
+fn end_of_scope_drop() -> TestCase {
+    TestCase::new(
+        "end_of_scope_drop",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            let _n = s.len();
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::drop_deco()
+            .on_line(3)
+            .with_message("variable `s` dropped here"),
+    )
+}
+
+fn drop_in_one_branch() -> TestCase {
+    // Moved in the `if` branch, dropped at the end of the scope otherwise.
+    TestCase::new(
+        "drop_in_one_branch",
+        r#"
+        fn test(c: bool) {
+            let s = String::from("hello");
+            if c {
+                drop(s);
+            }
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect_move()
+    .expect(ExpectedDeco::drop_deco().on_line(5))
+}
+
+fn moved_in_each_branch() -> TestCase {
+    // Moved in both branches, so `s` itself is never dropped.
+    TestCase::new(
+        "moved_in_each_branch",
+        r#"
+        fn test(c: bool) {
+            let s = String::from("hello");
+            if c {
+                let _t = s;
+            } else {
+                let _u = s;
+            }
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::move_deco().on_line(3))
+    .expect(ExpectedDeco::move_deco().on_line(5))
+    .forbid_drop()
+}
+
+#[test]
+fn all_drop_tests() {
+    run_tests(&[
+        explicit_drop(),
+        end_of_scope_drop(),
+        drop_in_one_branch(),
+        moved_in_each_branch(),
+    ]);
+}
@@ -0,0 +1,90 @@
+#![feature(rustc_private)]
+
+//! Tests for the decorations printed by `check --emit-decorations-for`.
+
+use std::{
+    env, fs,
+    process::{Command, Output},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::Value;
+
+/// `v` borrowed shared by `v.len()` while borrowed mutably on line 3
+const SHARED_MUT: &str = "\
+pub fn push_len() {
+    let mut v = vec![1];
+    v.push(v.len());
+}
+";
+
+const CLEAN: &str = "\
+pub fn len() -> usize {
+    let s = String::new();
+    s.len()
+}
+";
+
+/// Output of `check --emit-decorations-for src/**/*.rs` on a workspace of
+/// `src/lib.rs` with a module `src/inner/code.rs` of `code`.
+fn emit(name: &str, code: &str) -> Output {
+    let package = TestPackage::new(&format!("emit_{name}"), "pub mod inner;\n")
+        .expect("Failed to create workspace");
+    let dir = &package.dir;
+    fs::create_dir_all(format!("{dir}/src/inner")).expect("Failed to create module");
+    fs::write(format!("{dir}/src/inner.rs"), "pub mod code;\n").expect("Failed to write source");
+    fs::write(format!("{dir}/src/inner/code.rs"), code).expect("Failed to write source");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["check", dir, "--emit-decorations-for", "src/**/code.rs"])
+        .args(["--fail-on", "shared-mut,outlive"])
+        .output()
+        .expect("Failed to run ferrous-owl");
+    let _ = fs::remove_dir_all(dir);
+    output
+}
+
+fn lines(output: &Output) -> Vec<Value> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("line of JSON"))
+        .collect()
+}
+
+#[test]
+fn shared_mut_fails_the_check() {
+    let output = emit("shared_mut", SHARED_MUT);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{stderr}");
+
+    let lines = lines(&output);
+    let shared_mut = lines
+        .iter()
+        .find(|line| line["kind"] == "shared-mut")
+        .unwrap_or_else(|| panic!("no shared-mut in {lines:#?}"));
+    assert_eq!(shared_mut["file"], "src/inner/code.rs");
+    assert_eq!(shared_mut["variable"], "v");
+    assert_eq!(shared_mut["start"], "3:14", "at the shared borrow");
+    assert!(
+        shared_mut["message"]
+            .as_str()
+            .is_some_and(|m| !m.is_empty()),
+        "{shared_mut}"
+    );
+}
+
+#[test]
+fn clean_file_passes_the_check() {
+    let output = emit("clean", CLEAN);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{stderr}");
+
+    let lines = lines(&output);
+    assert!(
+        lines
+            .iter()
+            .any(|line| line["variable"] == "s" && line["kind"] == "lifetime"),
+        "{lines:#?}"
+    );
+    assert!(lines.iter().all(|line| line["file"] == "src/inner/code.rs"));
+}
@@ -0,0 +1,35 @@
+#![feature(rustc_private)]
+
+//! Tests for cursors without decorations and the status they report.
+
+use ferrous_owl::{AnalysisStatusKind, TestCase, run_tests};
+
+#[test]
+fn all_expect_status_tests() {
+    run_tests(&[
+        TestCase::new(
+            "cursor_on_comment",
+            r"
+            fn test() {
+                // nothing owned here
+                let s = String::new();
+                drop(s);
+            }
+            ",
+        )
+        .cursor_on("nothing")
+        .expect_no_decorations()
+        .expect_status(AnalysisStatusKind::NoVariableSelected),
+        TestCase::new(
+            "broken_snippet",
+            r"
+            fn test() {
+                let s: String = 1;
+                drop(s);
+            }
+            ",
+        )
+        .cursor_on("s:")
+        .expect_status(AnalysisStatusKind::Error),
+    ]);
+}
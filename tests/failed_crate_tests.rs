@@ -0,0 +1,103 @@
+#![feature(rustc_private)]
+
+//! Tests for workspaces with a package that cannot be analyzed.
+
+use std::{
+    env, fs, process,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::LspClient;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+/// Package `good` with the workspace member `broken`, whose build script
+/// fails, returning the path of the workspace.
+fn create_workspace(name: &str) -> String {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("{name}_{}", process::id()));
+    let dir = dir.to_string_lossy().into_owned();
+    let _ = fs::remove_dir_all(&dir);
+    for member in [".", "broken"] {
+        let package = if member == "." { "good" } else { member };
+        fs::create_dir_all(format!("{dir}/{member}/src")).unwrap();
+        fs::write(
+            format!("{dir}/{member}/Cargo.toml"),
+            format!("[package]\nname = \"{package}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n"),
+        )
+        .unwrap();
+        fs::write(format!("{dir}/{member}/src/lib.rs"), CODE).unwrap();
+    }
+    let mut manifest = fs::read_to_string(format!("{dir}/Cargo.toml")).unwrap();
+    manifest.push_str("\n[workspace]\nmembers = [\"broken\"]\n");
+    fs::write(format!("{dir}/Cargo.toml"), manifest).unwrap();
+    fs::write(
+        format!("{dir}/broken/build.rs"),
+        "fn main() {\n    panic!(\"broken on purpose\");\n}\n",
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn check_reports_partial_success() {
+    let dir = create_workspace("failed_crate_check");
+    let output = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["check", &dir])
+        .output()
+        .expect("Failed to run ferrous-owl");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "{stderr}");
+    assert!(stderr.contains("cannot analyze broken"), "{stderr}");
+    assert!(!stderr.contains("cannot analyze good"), "{stderr}");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn status_lists_failed_crates() {
+    let dir = create_workspace("failed_crate_status");
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client.initialize(&format!("file://{dir}")).unwrap();
+
+    let start = Instant::now();
+    let status = loop {
+        let status = client.status().unwrap();
+        if status["result"]["status"] != json!("analyzing")
+            && status["result"]["failed_crates"]
+                .as_array()
+                .is_some_and(|failed| !failed.is_empty())
+        {
+            break status["result"].clone();
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for {status}");
+        thread::sleep(Duration::from_millis(500));
+    };
+    assert_eq!(status["status"], json!("finished"), "{status}");
+    let failed: Vec<_> = status["failed_crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|failed: &Value| failed[0].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(failed, vec!["broken"]);
+    assert!(
+        status["analyzed_files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|file| file["path"]
+                .as_str()
+                .unwrap()
+                .ends_with(&format!("{dir}/src/lib.rs"))),
+        "{status}"
+    );
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,79 @@
+#![feature(rustc_private)]
+
+//! Tests for the functions the decorations at the cursor belong to.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::TestPackage;
+use serde_json::Value;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `s` on line 1 and `t` on line 7
+const CODE: &str = "\
+pub fn first() -> usize {
+    let s = String::new();
+    s.len()
+}
+
+pub mod inner {
+    pub fn second() -> usize {
+        let t = vec![1];
+        t.len()
+    }
+}
+";
+
+/// Name of the function of each decoration in `result`.
+fn function_names(result: &Value) -> Vec<&str> {
+    result["decorations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|deco| {
+            let key = deco["local"]["fn_key"]
+                .as_str()
+                .unwrap_or_else(|| panic!("fn_key is not a string in {deco}"));
+            result["functions"][&key]["name"]
+                .as_str()
+                .unwrap_or_else(|| panic!("no function {key} in {result:#}"))
+        })
+        .collect()
+}
+
+#[test]
+fn decorations_name_their_function() {
+    let package = TestPackage::new("function_map", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let first = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    let names = function_names(&first);
+    assert!(names.iter().all(|&name| name == "first"), "{first:#}");
+    assert_eq!(
+        first["functions"].as_object().unwrap().len(),
+        1,
+        "{first:#}"
+    );
+
+    let second = client
+        .wait_for_decorations(&package.uri, 7, 12, TIMEOUT)
+        .unwrap();
+    let names = function_names(&second);
+    assert!(
+        names.iter().all(|&name| name == "inner::second"),
+        "{second:#}"
+    );
+    let function = second["functions"].as_object().unwrap().values().next();
+    assert_eq!(
+        function.map(|function| function["span"]["start"]["line"].clone()),
+        Some(6.into()),
+        "{second:#}"
+    );
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,89 @@
+#![feature(rustc_private)]
+
+//! Tests for writing the graph of a function with `graph`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+const CODE: &str = "\
+pub fn other() -> usize {
+    let v = vec![1];
+    v.len()
+}
+
+pub fn test() -> usize {
+    let s = String::from(\"owl\");
+    let r = &s;
+    let n = r.len();
+    let t = s;
+    n + t.len()
+}
+";
+
+/// Directory with the file `owl.rs` of [`CODE`].
+fn fixture(name: &str) -> PathBuf {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("{name}_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("owl.rs"), CODE).unwrap();
+    dir
+}
+
+fn graph(dir: &Path, function: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .arg("graph")
+        .args(["--fn", function])
+        .arg("--path")
+        .arg(dir.join("owl.rs"))
+        .arg("--out")
+        .arg(dir.join("graph.dot"))
+        .output()
+        .expect("Failed to run ferrous-owl")
+}
+
+#[test]
+fn graph_of_a_function_by_name() {
+    let dir = fixture("graph_by_name");
+    let output = graph(&dir, "test");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "graph failed:\n{stderr}");
+
+    let dot = fs::read_to_string(dir.join("graph.dot")).expect("graph written");
+    assert!(dot.starts_with("digraph \"test\" {"), "{dot}");
+    for label in [
+        "s: std::string::String",
+        "r: &std::string::String",
+        "t: std::string::String",
+    ] {
+        assert!(dot.contains(label), "no node `{label}`:\n{dot}");
+    }
+    assert!(!dot.contains("v: "), "only the selected function:\n{dot}");
+    assert!(dot.contains("borrow, line 8"), "{dot}");
+    assert!(dot.contains("style=bold"), "a move:\n{dot}");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn graph_of_a_function_by_line() {
+    let dir = fixture("graph_by_line");
+    let output = graph(&dir, "1");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "graph failed:\n{stderr}");
+
+    let dot = fs::read_to_string(dir.join("graph.dot")).expect("graph written");
+    assert!(dot.contains("v: std::vec::Vec<i32>"), "{dot}");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn unknown_function() {
+    let dir = fixture("graph_unknown");
+    let output = graph(&dir, "missing");
+    assert!(!output.status.success());
+    assert!(!dir.join("graph.dot").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,83 @@
+#![feature(rustc_private)]
+
+//! Tests for stopping the server once its client is gone.
+
+use std::{
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{HEALTH_INTERVAL_ENV, lsp_framing::write_message};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Start a server over stdio, checking its client every second.
+fn start_server() -> Child {
+    Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .env(HEALTH_INTERVAL_ENV, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to run ferrous-owl")
+}
+
+fn send(stdin: &mut ChildStdin, message: &Value) {
+    write_message(stdin, message).unwrap();
+}
+
+fn initialize(stdin: &mut ChildStdin, process_id: Option<u32>) {
+    send(
+        stdin,
+        &json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": { "processId": process_id, "capabilities": {} },
+        }),
+    );
+}
+
+/// Exit status of `server`, unless it is still running after [`TIMEOUT`].
+fn wait_for_exit(server: &mut Child) -> Option<ExitStatus> {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        if let Some(status) = server.try_wait().unwrap() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    server.kill().ok();
+    None
+}
+
+#[test]
+fn exits_cleanly_when_client_process_dies() {
+    let mut client = Command::new("true").spawn().expect("Failed to run true");
+    let dead_pid = client.id();
+    client.wait().unwrap();
+
+    let mut server = start_server();
+    let mut stdin = server.stdin.take().unwrap();
+    initialize(&mut stdin, Some(dead_pid));
+    // a second initialize must not start another checker
+    initialize(&mut stdin, Some(dead_pid));
+
+    // stdin stays open, so only the dead client stops the server
+    let status = wait_for_exit(&mut server).expect("server still running");
+    assert!(status.success(), "{status}");
+    drop(stdin);
+}
+
+#[test]
+fn exits_at_end_of_input_without_process_id() {
+    let mut server = start_server();
+    let mut stdin = server.stdin.take().unwrap();
+    initialize(&mut stdin, None);
+    drop(stdin);
+
+    let status = wait_for_exit(&mut server).expect("server still running");
+    assert!(status.success(), "{status}");
+}
@@ -0,0 +1,47 @@
+#![feature(rustc_private)]
+
+//! Tests for excluding code from decoration with ignore markers.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
+
+fn ignore_next_line_hides_move() -> TestCase {
+    TestCase::new(
+        "ignore_next_line_hides_move",
+        r#"
+        fn test() -> String {
+            let s = String::new();
+            let _n = s.len();
+            // ferrous-owl:ignore-next-line
+            let t = s;
+            t
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::imm_borrow().on_line(2))
+    .forbid(DecoKind::Move)
+}
+
+fn ignored_function_has_no_decorations() -> TestCase {
+    TestCase::new(
+        "ignored_function_has_no_decorations",
+        r#"
+        /// ferrous-owl:ignore
+        fn test() -> String {
+            let s = String::new();
+            let t = s;
+            t
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .forbid(DecoKind::Move)
+}
+
+#[test]
+fn all_ignore_tests() {
+    run_tests(&[
+        ignore_next_line_hides_move(),
+        ignored_function_has_no_decorations(),
+    ]);
+}
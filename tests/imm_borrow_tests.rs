@@ -2,7 +2,7 @@
 
 //! Tests for immutable borrow decoration detection.
 
-use ferrous_owl::{TestCase, run_tests};
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
 
 fn imm_borrow_println() -> TestCase {
     TestCase::new(
@@ -179,6 +179,83 @@ fn imm_borrow_clone() -> TestCase {
     .expect_imm_borrow()
 }
 
+fn imm_borrow_plain_message() -> TestCase {
+    TestCase::new(
+        "imm_borrow_plain_message",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            let _r = &s;
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .with_message("immutable borrow"),
+    )
+}
+
+fn imm_borrow_reborrow() -> TestCase {
+    TestCase::new(
+        "imm_borrow_reborrow",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            let r = &s;
+            let _r2 = &*r;
+        }
+    "#,
+    )
+    .cursor_on("r = &s")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(3)
+            .with_message("immutable reborrow of variable `r`"),
+    )
+}
+
+fn imm_borrow_reborrow_of_owner() -> TestCase {
+    TestCase::new(
+        "imm_borrow_reborrow_of_owner",
+        r#"
+        fn test() {
+            let s = String::from("hello");
+            let r = &s;
+            let _r2 = &*r;
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .with_message("immutable borrow"),
+    )
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(3)
+            .with_message("immutable reborrow through `r`"),
+    )
+}
+
+fn imm_borrow_span_is_argument() -> TestCase {
+    TestCase::new(
+        "imm_borrow_span_is_argument",
+        r#"
+        fn show(_n: u8, _s: &String) {}
+
+        fn test() {
+            let text = String::from("hello");
+            show(1 + 2, &text);
+        }
+    "#,
+    )
+    .cursor_on("text = String")
+    .expect(ExpectedDeco::imm_borrow().on_line(4).covering_text("&text"))
+}
+
 #[test]
 fn all_imm_borrow_tests() {
     run_tests(&[
@@ -194,5 +271,9 @@ fn all_imm_borrow_tests() {
         imm_borrow_comparison(),
         imm_borrow_is_empty(),
         imm_borrow_clone(),
+        imm_borrow_plain_message(),
+        imm_borrow_reborrow(),
+        imm_borrow_reborrow_of_owner(),
+        imm_borrow_span_is_argument(),
     ]);
 }
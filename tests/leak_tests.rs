@@ -0,0 +1,73 @@
+#![feature(rustc_private)]
+
+//! Tests for calls that take ownership of a variable without running its
+//! destructor.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
+
+#[test]
+fn all_leak_tests() {
+    run_tests(&[
+        TestCase::new(
+            "mem_forget",
+            r"
+            fn test() {
+                let s = String::new();
+                std::mem::forget(s);
+            }
+            ",
+        )
+        .cursor_on("s = String")
+        .expect(
+            ExpectedDeco::leak_or_forget().on_line(2).with_message(
+                "ownership transferred without running the destructor (`mem::forget`)",
+            ),
+        ),
+        TestCase::new(
+            "manually_drop_new",
+            r"
+            use std::mem::ManuallyDrop;
+
+            fn test() -> ManuallyDrop<Vec<u8>> {
+                let v = vec![1, 2, 3];
+                ManuallyDrop::new(v)
+            }
+            ",
+        )
+        .cursor_on("v = vec")
+        .expect(
+            ExpectedDeco::leak_or_forget()
+                .on_line(4)
+                .with_message("(`ManuallyDrop::new`)"),
+        ),
+        TestCase::new(
+            "box_leak",
+            r"
+            fn test() -> &'static mut i32 {
+                let b = Box::new(1);
+                Box::leak(b)
+            }
+            ",
+        )
+        .cursor_on("b = Box")
+        .expect(
+            ExpectedDeco::leak_or_forget()
+                .on_line(2)
+                .with_message("(`Box::leak`)"),
+        ),
+        TestCase::new(
+            "function_named_like_forget",
+            r"
+            fn forget_me(_: String) {}
+
+            fn test() {
+                let s = String::new();
+                forget_me(s);
+            }
+            ",
+        )
+        .cursor_on("s = String")
+        .expect(ExpectedDeco::move_deco().on_line(4))
+        .forbid(DecoKind::LeakOrForget),
+    ]);
+}
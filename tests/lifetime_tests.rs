@@ -155,9 +155,28 @@ fn lifetime_nested_struct() -> TestCase {
     .expect_move() // inner is moved into _outer
 }
 
+fn lifetime_spans_block_scope() -> TestCase {
+    TestCase::new(
+        "lifetime_spans_block_scope",
+        r#"
+        fn test() -> usize {
+            let n = {
+                let s = String::from("owl");
+                let upper = s.to_uppercase();
+                upper.len()
+            };
+            n
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect_lifetime_spanning_lines(2, 5)
+}
+
 #[test]
 fn all_lifetime_tests() {
     run_tests(&[
+        lifetime_spans_block_scope(),
         lifetime_basic_reference(),
         lifetime_function_param(),
         lifetime_struct_field(),
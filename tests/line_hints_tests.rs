@@ -0,0 +1,68 @@
+#![feature(rustc_private)]
+
+//! Tests for the decorations summarized per line by `ferrous-owl/lineHints`.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `s` is moved on line 2
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    let t = s;\n    drop(t);\n}\n";
+
+#[test]
+fn line_hints_summarize_decorations_per_line() {
+    let package = TestPackage::new("line_hints", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    let result = loop {
+        let response = client.line_hints(&package.uri, 1, 8).unwrap();
+        let result = response["result"].clone();
+        if result["status"] == json!("finished")
+            && result["lines"]
+                .as_array()
+                .is_some_and(|lines| !lines.is_empty())
+        {
+            break result;
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+
+    assert_eq!(result["is_analyzed"], json!(true));
+    assert!(result["path"].is_string(), "{result:#?}");
+    let lines = result["lines"].as_array().unwrap();
+    let numbers: Vec<u64> = lines
+        .iter()
+        .map(|line| line["line"].as_u64().unwrap())
+        .collect();
+    assert!(numbers.is_sorted(), "{numbers:?}");
+    for line in lines {
+        for hint in line["hints"].as_array().unwrap() {
+            assert!(hint["kind"].is_string(), "{hint}");
+            assert!(hint["count"].as_u64().unwrap() >= 1, "{hint}");
+            assert!(hint["short_label"].is_string(), "{hint}");
+        }
+    }
+    let moved: &Value = lines
+        .iter()
+        .find(|line| line["line"] == json!(2))
+        .unwrap_or_else(|| panic!("no hints on line 2 in {result:#?}"));
+    assert!(
+        moved["hints"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "kind": "move", "count": 1, "short_label": "←move" })),
+        "{moved:#?}"
+    );
+}
@@ -0,0 +1,91 @@
+#![feature(rustc_private)]
+
+//! Tests for serving the language server over TCP with `--listen`.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Read},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::LspClient;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Start a server on a free port with the extra `args`, returning it with
+/// the address it listens at.
+fn listen(args: &[&str]) -> (Child, String) {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["--listen", "127.0.0.1:0"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to run ferrous-owl");
+    let mut line = String::new();
+    BufReader::new(server.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let address = line
+        .trim()
+        .strip_prefix("listening at ")
+        .unwrap_or_else(|| panic!("unexpected output {line:?}"))
+        .to_owned();
+    (server, address)
+}
+
+#[test]
+fn single_client_is_served_until_it_disconnects() {
+    let (mut server, address) = listen(&["--single-client"]);
+    let dir = env::temp_dir();
+
+    let mut client = LspClient::connect(address.as_str()).unwrap();
+    let response = client
+        .initialize(&format!("file://{}", dir.display()))
+        .unwrap();
+    assert!(response["result"]["capabilities"].is_object(), "{response}");
+
+    // a second client is disconnected right away
+    let mut second = TcpStream::connect(&address).unwrap();
+    second.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(second.read(&mut [0; 1]).unwrap(), 0);
+
+    client.shutdown().unwrap();
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = server.try_wait().unwrap() {
+            break status;
+        }
+        assert!(start.elapsed() < TIMEOUT, "server did not exit");
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert!(status.success(), "{status}");
+}
+
+#[test]
+fn clients_are_served_one_after_another() {
+    let (mut server, address) = listen(&[]);
+    let root = format!("file://{}", env::temp_dir().display());
+
+    for _ in 0..2 {
+        // refused until the server is done with the previous client
+        let start = Instant::now();
+        let (mut client, response) = loop {
+            let mut client = LspClient::connect(address.as_str()).unwrap();
+            if let Ok(response) = client.initialize(&root) {
+                break (client, response);
+            }
+            assert!(start.elapsed() < TIMEOUT, "next client not served");
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert!(response["result"]["capabilities"].is_object(), "{response}");
+        client.shutdown().unwrap();
+    }
+    assert!(server.try_wait().unwrap().is_none(), "server exited");
+
+    let _ = server.kill();
+    let _ = server.wait();
+}
@@ -0,0 +1,51 @@
+#![feature(rustc_private)]
+
+//! Tests for writing the log to a file.
+
+use std::{env, fs, process::Command};
+
+use ferrous_owl::{LOG_FILE_ENV, TestPackage};
+
+#[test]
+fn check_writes_json_log_file() {
+    let package = TestPackage::new(
+        "log_file",
+        "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n",
+    )
+    .expect("Failed to create workspace");
+    let log_file = format!("{}.log", package.dir);
+    fs::remove_file(&log_file).ok();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["check", &package.dir])
+        .env("RUST_LOG", "info")
+        .env(LOG_FILE_ENV, &log_file)
+        .output()
+        .expect("Failed to run ferrous-owl");
+    assert!(
+        output.status.success(),
+        "check failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let log = fs::read_to_string(&log_file).expect("log file not written");
+    let lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("log line is not JSON"))
+        .collect();
+    let message =
+        |line: &serde_json::Value| line["message"].as_str().unwrap_or_default().to_owned();
+
+    assert!(
+        lines
+            .iter()
+            .any(|line| message(line) == "heartbeat" && line["status"].is_string()),
+        "no heartbeat in log:\n{log}"
+    );
+    assert!(
+        lines
+            .iter()
+            .any(|line| message(line).starts_with("start analyzing package")),
+        "no analysis start in log:\n{log}"
+    );
+}
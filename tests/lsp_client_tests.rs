@@ -0,0 +1,43 @@
+#![feature(rustc_private)]
+
+//! Tests for the server log and the watchdog of the test client.
+
+use std::time::{Duration, Instant};
+
+use ferrous_owl::LspClient;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[test]
+fn server_log_captures_stderr() {
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &["--no-such-flag"])
+        .expect("Failed to start ferrous-owl");
+    let path = client
+        .server_log_path()
+        .expect("log of a started server")
+        .to_owned();
+
+    // the server exits on the flag, closing its output
+    assert!(client.receive_message(TIMEOUT).is_err());
+    let log = client.server_log().expect("readable log");
+    assert!(log.contains("--no-such-flag"), "{log}");
+
+    drop(client);
+    assert!(!path.exists(), "log removed with the client");
+}
+
+#[test]
+fn watchdog_kills_a_hung_server() {
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[])
+        .expect("Failed to start ferrous-owl")
+        .with_watchdog(Duration::from_millis(500));
+
+    // never initialized, the server waits for input until it is killed
+    let start = Instant::now();
+    assert!(client.receive_message(TIMEOUT).is_err());
+    assert!(
+        start.elapsed() < TIMEOUT,
+        "killed after {:?}",
+        start.elapsed()
+    );
+}
@@ -0,0 +1,98 @@
+#![feature(rustc_private)]
+
+//! Tests for decorations of code expanded from macros, shown at the macro
+//! call.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
+
+#[test]
+fn all_macro_tests() {
+    run_tests(&[
+        TestCase::new(
+            "move_into_vec",
+            r"
+            fn test() -> Vec<String> {
+                let s = String::new();
+                vec![s]
+            }
+            ",
+        )
+        .cursor_on("s =")
+        .expect(ExpectedDeco::move_deco().on_line(2).covering_text("s")),
+        TestCase::new(
+            "borrow_in_format",
+            r#"
+            fn test() -> String {
+                let s = String::new();
+                format!("{}", s)
+            }
+            "#,
+        )
+        .cursor_on("s =")
+        .expect(
+            ExpectedDeco::imm_borrow()
+                .on_line(2)
+                .covering_text("s")
+                .with_message("(in macro expansion)"),
+        ),
+        TestCase::new(
+            "drop_in_user_macro",
+            r#"
+            macro_rules! reset {
+                ($v:ident) => {
+                    $v = String::new()
+                };
+            }
+
+            fn test() -> String {
+                let mut s = String::from("a");
+                reset!(s);
+                s
+            }
+            "#,
+        )
+        .cursor_on("s =")
+        .expect(
+            ExpectedDeco::drop_deco()
+                .on_line(8)
+                .covering_text("reset!(s)")
+                .with_message("(in macro expansion)"),
+        ),
+        TestCase::new(
+            "repeated_expansion",
+            r#"
+            macro_rules! show_twice {
+                ($v:ident) => {
+                    println!("{}", $v);
+                    println!("{}", $v);
+                };
+            }
+
+            fn test() {
+                let s = String::new();
+                show_twice!(s);
+            }
+            "#,
+        )
+        .cursor_on("s =")
+        .expect_count(DecoKind::ImmBorrow, 1)
+        .expect(
+            ExpectedDeco::imm_borrow()
+                .on_line(9)
+                .covering_text("show_twice!(s)")
+                .with_message("(in macro expansion)"),
+        ),
+        TestCase::new(
+            "vec_of_clones",
+            r"
+            fn test() -> Vec<String> {
+                let s = String::new();
+                vec![s.clone(); 3]
+            }
+            ",
+        )
+        .cursor_on("s =")
+        .expect_count(DecoKind::ImmBorrow, 1)
+        .expect(ExpectedDeco::imm_borrow().on_line(2).covering_text("s")),
+    ]);
+}
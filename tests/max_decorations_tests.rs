@@ -0,0 +1,78 @@
+#![feature(rustc_private)]
+
+//! Tests for limiting the decorations of a selection with `max_decorations`.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Borrows of `s` in the generated function
+const BORROWS: usize = 300;
+
+const MAX_DECORATIONS: usize = 50;
+
+/// A function borrowing `s`, declared on line 1, on each of [`BORROWS`]
+/// lines.
+fn generated_code() -> String {
+    let mut code =
+        "pub fn test() -> usize {\n    let s = String::new();\n    let mut n = 0;\n".to_owned();
+    for _ in 0..BORROWS {
+        code.push_str("    n += s.len();\n");
+    }
+    code.push_str("    n\n}\n");
+    code
+}
+
+/// Cursor response at `s` of the generated code, once the analysis finished.
+fn cursor_at_declaration(options: &Value) -> Value {
+    let code = generated_code();
+    let package = TestPackage::new("max_decorations", &code).expect("Failed to create workspace");
+    let mut client = package
+        .start_server_with_options(env!("CARGO_BIN_EXE_ferrous-owl"), options)
+        .unwrap();
+
+    let start = Instant::now();
+    let result = loop {
+        let response = client.cursor(&package.uri, 1, 8).unwrap();
+        let result = response["result"].clone();
+        if result["is_analyzed"] == json!(true)
+            && result["status"] == json!("finished")
+            && result["decorations"]
+                .as_array()
+                .is_some_and(|items| !items.is_empty())
+        {
+            break result;
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+    result
+}
+
+#[test]
+fn decorations_are_limited_to_the_nearest_events() {
+    let result = cursor_at_declaration(&json!({ "max_decorations": MAX_DECORATIONS }));
+    let items = result["decorations"].as_array().unwrap();
+    assert_eq!(items.len(), MAX_DECORATIONS);
+    assert_eq!(result["truncated"], json!(true));
+    let total = usize::try_from(result["total"].as_u64().unwrap()).unwrap();
+    assert!(total > BORROWS, "total of {total}");
+
+    for item in items {
+        assert_ne!(item["type"], json!("lifetime"), "events first: {item}");
+        let line = item["range"]["start"]["line"].as_u64().unwrap();
+        assert!(
+            line < 3 + 2 * MAX_DECORATIONS as u64,
+            "events nearest to the cursor: {item}"
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 //! Tests for move decoration detection.
 
-use ferrous_owl::{TestCase, run_tests};
+use ferrous_owl::{DecoKind, ExpectedDeco, ForbiddenDeco, TestCase, run_tests};
 
 fn move_to_drop() -> TestCase {
     TestCase::new(
@@ -199,6 +199,55 @@ fn move_if_let() -> TestCase {
     .expect_move()
 }
 
+fn move_match_arm_into_binding() -> TestCase {
+    TestCase::new(
+        "move_match_arm_into_binding",
+        r#"
+        fn test() {
+            let opt = Some(String::new());
+            match opt {
+                Some(inner) => drop(inner),
+                None => {}
+            }
+        }
+    "#,
+    )
+    .cursor_on("opt = Some")
+    .expect(
+        ExpectedDeco::move_deco()
+            .on_line(3)
+            .covering_text("inner")
+            .with_message("partially moved into binding `inner`"),
+    )
+    .expect(
+        ExpectedDeco::binding()
+            .on_line(3)
+            .with_message("binding `inner` owns the part moved out of `opt`"),
+    )
+    .forbid(ForbiddenDeco::new(DecoKind::Move).on_line(4))
+}
+
+fn move_if_let_into_binding() -> TestCase {
+    TestCase::new(
+        "move_if_let_into_binding",
+        r#"
+        fn test() {
+            let opt = Some(String::new());
+            if let Some(x) = opt {
+                drop(x);
+            }
+        }
+    "#,
+    )
+    .cursor_on("opt = Some")
+    .expect(
+        ExpectedDeco::move_deco()
+            .on_line(2)
+            .covering_text("x")
+            .with_message("partially moved into binding `x`"),
+    )
+}
+
 fn move_for_loop() -> TestCase {
     TestCase::new(
         "move_for_loop",
@@ -215,6 +264,171 @@ fn move_for_loop() -> TestCase {
     .expect_move()
 }
 
+fn move_reported_once_with_test_target() -> TestCase {
+    // With test targets, the file is analyzed for both the library and its
+    // test harness, which must not duplicate decorations.
+    TestCase::new(
+        "move_reported_once_with_test_target",
+        r#"
+        fn test() {
+            let s = String::new();
+            drop(s);
+        }
+
+        #[cfg(test)]
+        mod tests {
+            #[test]
+            fn it_works() {
+                super::test();
+            }
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::move_deco().times(1))
+}
+
+fn move_only_on_use_line() -> TestCase {
+    TestCase::new(
+        "move_only_on_use_line",
+        r#"
+        fn test() {
+            let s = String::new();
+            let _t = s.len();
+            drop(s);
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::move_deco().on_line(3))
+    .forbid(ForbiddenDeco::new(DecoKind::Move).on_line(2))
+    .expect_count(DecoKind::Move, 1)
+}
+
+fn move_in_static_initializer() -> TestCase {
+    TestCase::new(
+        "move_in_static_initializer",
+        r#"
+        static NAME: Option<String> = {
+            let s = String::new();
+            Some(s)
+        };
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect_move()
+}
+
+fn move_in_const_initializer() -> TestCase {
+    TestCase::new(
+        "move_in_const_initializer",
+        r#"
+        struct Token;
+
+        const LEN: usize = {
+            let t = Token;
+            let _u = t;
+            1
+        };
+    "#,
+    )
+    .cursor_on("t = Token")
+    .expect_move()
+}
+
+fn move_span_is_argument() -> TestCase {
+    TestCase::new(
+        "move_span_is_argument",
+        r#"
+        fn consume(_n: u8, _s: String) {}
+
+        fn test() {
+            let text = String::from("hello");
+            consume(1 + 2, text);
+        }
+    "#,
+    )
+    .cursor_on("text = String")
+    .expect(ExpectedDeco::move_deco().on_line(4).covering_text("text"))
+}
+
+fn move_explains_type_and_suggests_borrow() -> TestCase {
+    TestCase::new(
+        "move_explains_type_and_suggests_borrow",
+        r#"
+        fn test() {
+            let s = String::new();
+            drop(s);
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::move_deco().on_line(2).with_message(
+        "`s: std::string::String` moved here (`std::string::String` does not implement `Copy`) — \
+         consider passing `&s` if the callee only reads it",
+    ))
+}
+
+fn move_into_return_suggests_nothing() -> TestCase {
+    TestCase::new(
+        "move_into_return_suggests_nothing",
+        r#"
+        fn test() -> String {
+            let s = String::new();
+            return s;
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::move_deco().on_line(2).with_message(
+        "`s: std::string::String` moved here (`std::string::String` does not implement `Copy`)",
+    ))
+    .forbid(ForbiddenDeco::new(DecoKind::Move).at_text("consider passing"))
+}
+
+const OWNERSHIP_TRANSFERRED: &str =
+    "ownership transferred; this variable is no longer responsible for dropping the value";
+
+fn move_into_returned_vec_transfers_ownership() -> TestCase {
+    TestCase::new(
+        "move_into_returned_vec_transfers_ownership",
+        r#"
+        pub fn test() -> Vec<String> {
+            let s = String::new();
+            let mut v = Vec::new();
+            v.push(s);
+            v
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::move_deco()
+            .on_line(3)
+            .with_message(OWNERSHIP_TRANSFERRED),
+    )
+}
+
+fn move_before_local_drop_transfers_nothing() -> TestCase {
+    TestCase::new(
+        "move_before_local_drop_transfers_nothing",
+        r#"
+        fn consume(_s: String) {}
+
+        pub fn test(c: bool) {
+            let s = String::new();
+            if c {
+                consume(s);
+            }
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect_move()
+    .expect_drop()
+    .forbid(ForbiddenDeco::new(DecoKind::Move).at_text(OWNERSHIP_TRANSFERRED))
+}
+
 #[test]
 fn all_move_tests() {
     run_tests(&[
@@ -231,6 +445,17 @@ fn all_move_tests() {
         move_assignment(),
         move_match_arm(),
         move_if_let(),
+        move_match_arm_into_binding(),
+        move_if_let_into_binding(),
         move_for_loop(),
+        move_reported_once_with_test_target(),
+        move_only_on_use_line(),
+        move_in_static_initializer(),
+        move_in_const_initializer(),
+        move_span_is_argument(),
+        move_explains_type_and_suggests_borrow(),
+        move_into_return_suggests_nothing(),
+        move_into_returned_vec_transfers_ownership(),
+        move_before_local_drop_transfers_nothing(),
     ]);
 }
@@ -0,0 +1,52 @@
+#![feature(rustc_private)]
+
+//! Tests for test cases spanning several files of a crate.
+
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
+
+#[test]
+fn all_multi_file_tests() {
+    run_tests(&[
+        TestCase::new(
+            "move_into_other_file",
+            r"
+            use crate::helper::take;
+
+            fn test() {
+                let s = String::new();
+                take(s);
+            }
+            ",
+        )
+        .with_file(
+            "helper.rs",
+            r"
+            pub fn take(s: String) {
+                drop(s);
+            }
+            ",
+        )
+        .cursor_on("s = String")
+        .expect(ExpectedDeco::move_deco().on_line(4).covering_text("s")),
+        TestCase::new(
+            "cursor_in_nested_file",
+            r"
+            fn test() {
+                crate::util::helper::take(String::new());
+            }
+            ",
+        )
+        .with_file("util/mod.rs", "pub mod helper;")
+        .with_file(
+            "util/helper.rs",
+            r"
+            pub fn take(s: String) {
+                let t = s;
+                drop(t);
+            }
+            ",
+        )
+        .cursor_in_file("util/helper.rs", "t = s")
+        .expect(ExpectedDeco::move_deco().on_line(2).covering_text("t")),
+    ]);
+}
@@ -2,7 +2,7 @@
 
 //! Tests for mutable borrow decoration detection.
 
-use ferrous_owl::{TestCase, run_tests};
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
 
 fn mut_borrow_push() -> TestCase {
     TestCase::new(
@@ -216,6 +216,129 @@ fn mut_borrow_dedup() -> TestCase {
     .expect_mut_borrow()
 }
 
+fn mut_borrow_write_through_reference() -> TestCase {
+    TestCase::new(
+        "mut_borrow_write_through_reference",
+        r#"
+        fn test() -> i32 {
+            let mut x = 0;
+            let r = &mut x;
+            *r += 1;
+            x
+        }
+    "#,
+    )
+    .cursor_on("mut x")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(3)
+            .with_message("written through `&mut` reference `r`"),
+    )
+}
+
+fn mut_borrow_write_field_through_reference() -> TestCase {
+    TestCase::new(
+        "mut_borrow_write_field_through_reference",
+        r#"
+        fn test() -> (i32, i32) {
+            let mut p = (0, 0);
+            let r = &mut p;
+            r.0 = 5;
+            p
+        }
+    "#,
+    )
+    .cursor_on("mut p")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(3)
+            .with_message("written through"),
+    )
+}
+
+fn mut_borrow_write_through_reborrow() -> TestCase {
+    TestCase::new(
+        "mut_borrow_write_through_reborrow",
+        r#"
+        fn test() -> i32 {
+            let mut x = 0;
+            let r = &mut x;
+            let inner = &mut *r;
+            *inner = 3;
+            x
+        }
+    "#,
+    )
+    .cursor_on("mut x")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(4)
+            .with_message("written through `&mut` reference `inner`"),
+    )
+}
+
+fn mut_borrow_reborrow() -> TestCase {
+    TestCase::new(
+        "mut_borrow_reborrow",
+        r#"
+        fn test() {
+            let mut s = String::new();
+            let r = &mut s;
+            let r2 = &mut *r;
+            r2.push('a');
+        }
+    "#,
+    )
+    .cursor_on("r = &mut s")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(3)
+            .with_message("mutable reborrow of variable `r`"),
+    )
+}
+
+fn mut_borrow_reborrow_of_owner() -> TestCase {
+    TestCase::new(
+        "mut_borrow_reborrow_of_owner",
+        r#"
+        fn test() {
+            let mut s = String::new();
+            let r = &mut s;
+            let r2 = &mut *r;
+            r2.push('a');
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(3)
+            .with_message("mutable reborrow through `r`"),
+    )
+}
+
+fn mut_borrow_span_is_argument() -> TestCase {
+    TestCase::new(
+        "mut_borrow_span_is_argument",
+        r#"
+        fn extend(_n: u8, s: &mut String) {
+            s.push('a');
+        }
+
+        fn test() {
+            let mut text = String::new();
+            extend(1 + 2, &mut text);
+        }
+    "#,
+    )
+    .cursor_on("text = String")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(6)
+            .covering_text("&mut text"),
+    )
+}
+
 #[test]
 fn all_mut_borrow_tests() {
     run_tests(&[
@@ -234,5 +357,11 @@ fn all_mut_borrow_tests() {
         mut_borrow_reverse(),
         mut_borrow_retain(),
         mut_borrow_dedup(),
+        mut_borrow_write_through_reference(),
+        mut_borrow_write_field_through_reference(),
+        mut_borrow_write_through_reborrow(),
+        mut_borrow_reborrow(),
+        mut_borrow_reborrow_of_owner(),
+        mut_borrow_span_is_argument(),
     ]);
 }
@@ -0,0 +1,54 @@
+#![feature(rustc_private)]
+
+//! Tests for decorations where a local is required to live but is not.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, run_tests};
+
+fn outlive_returned_reference() -> TestCase {
+    // the reborrow of `v` is returned, so `v` must live as long as the
+    // lifetime of the signature; reborrows through shared references create
+    // no loan, hence the mutable one
+    TestCase::new(
+        "outlive_returned_reference",
+        r#"
+        fn first<'a>(v: &'a mut String) -> &'a mut String {
+            let s = &mut *v;
+            s
+        }
+    "#,
+    )
+    .cursor_on("v: &'a")
+    .expect(
+        ExpectedDeco::outlive()
+            .on_line(3)
+            .with_message("must live as long as the function's returned reference"),
+    )
+    .expect_count(DecoKind::Outlive, 1)
+}
+
+fn outlive_local_reborrow() -> TestCase {
+    TestCase::new(
+        "outlive_local_reborrow",
+        r#"
+        fn test() {
+            let mut v = vec![1];
+            let a = &mut v;
+            let b = &mut *a;
+            b.push(2);
+            v.push(3);
+        }
+    "#,
+    )
+    .cursor_on("a = &mut")
+    .expect(
+        ExpectedDeco::outlive()
+            .covering_text("b.push(2)")
+            .with_message("variable `a` is required to live here"),
+    )
+    .expect_count(DecoKind::Outlive, 1)
+}
+
+#[test]
+fn all_outlive_tests() {
+    run_tests(&[outlive_returned_reference(), outlive_local_reborrow()]);
+}
@@ -0,0 +1,48 @@
+#![feature(rustc_private)]
+
+//! Tests for keeping ownership diagnostics across analyses.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::TestPackage;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+pub fn test() -> usize {
+    let s = String::from(\"owl\");
+    let n = s.len();
+    drop(s);
+    n
+}
+";
+
+#[test]
+fn ownership_diagnostics_are_published_again_after_analysis() {
+    let package =
+        TestPackage::new("ownership_republish", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    client
+        .wait_for_analysis(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+
+    let diagnostics = client
+        .toggle_ownership_and_wait(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    assert!(!diagnostics.is_empty(), "no diagnostics after enabling");
+
+    client
+        .execute_command("ferrous-owl.analyze", &[json!({ "force": true })])
+        .unwrap();
+    client
+        .wait_for_cleared_diagnostics(&package.uri, TIMEOUT)
+        .unwrap();
+    let republished = client.wait_for_diagnostics(&package.uri, TIMEOUT).unwrap();
+    assert_eq!(republished.len(), diagnostics.len(), "{republished:?}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
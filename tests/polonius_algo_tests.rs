@@ -0,0 +1,106 @@
+#![feature(rustc_private)]
+
+//! Tests for choosing the Polonius algorithm of the borrow check.
+
+use std::{
+    env, fs,
+    process::{Command, Output},
+    time::Duration,
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Run `ferrous-owl check` on a fresh crate with extra arguments and
+/// environment.
+fn check(name: &str, args: &[&str], envs: &[(&str, &str)]) -> Output {
+    let package = TestPackage::new(
+        name,
+        "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n",
+    )
+    .expect("Failed to create workspace");
+
+    Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .arg("check")
+        .args(args)
+        .arg(&package.dir)
+        .env("RUST_LOG", "info")
+        .envs(envs.iter().copied())
+        .output()
+        .expect("Failed to run ferrous-owl")
+}
+
+#[test]
+fn polonius_algo_flag_selects_algorithm() {
+    let output = check("polonius_naive", &["--polonius-algo", "naive"], &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "check failed:\n{stderr}");
+    assert!(
+        stderr.contains("polonius algorithm: naive"),
+        "algorithm not reported:\n{stderr}"
+    );
+}
+
+#[test]
+fn polonius_algo_flag_rejects_unknown_algorithm() {
+    let output = check("polonius_bogus_flag", &["--polonius-algo", "bogus"], &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("naive, datafrog-opt, location-insensitive, hybrid"),
+        "allowed algorithms not listed:\n{stderr}"
+    );
+}
+
+#[test]
+fn polonius_algo_env_rejects_unknown_algorithm() {
+    let output = check(
+        "polonius_bogus_env",
+        &[],
+        &[("FERROUS_OWL_POLONIUS_ALGO", "bogus")],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("unknown Polonius algorithm `bogus`"),
+        "invalid algorithm not reported:\n{stderr}"
+    );
+}
+
+#[test]
+fn location_insensitive_algorithms_keep_live_borrows() {
+    for algorithm in ["location-insensitive", "hybrid"] {
+        let package = TestPackage::new(
+            &format!("polonius_{}", algorithm.replace('-', "_")),
+            "pub fn test(s: String) -> String {\n    let r = &s;\n    if r.is_empty() {\n        \
+             return s;\n    }\n    s\n}\n",
+        )
+        .expect("Failed to create workspace");
+        let mut client = package
+            .start_server_with_options(
+                env!("CARGO_BIN_EXE_ferrous-owl"),
+                &json!({ "polonius_algo": algorithm }),
+            )
+            .unwrap();
+
+        let result = client
+            .wait_for_decorations(&package.uri, 0, 12, TIMEOUT)
+            .unwrap();
+        let _ = client.shutdown();
+        let _ = fs::remove_dir_all(&package.dir);
+
+        // the move in the condition conflicts with the borrow live there
+        let kinds: Vec<_> = result["decorations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|deco| deco["type"].clone())
+            .collect();
+        assert!(
+            kinds.contains(&json!("conflict")),
+            "no live borrow with {algorithm}: {kinds:?}"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+#![feature(rustc_private)]
+
+//! Tests for positions counted in the encoding negotiated with the client.
+
+use std::{env, fs, process, time::Duration};
+
+use ferrous_owl::LspClient;
+use serde_json::Value;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `é` takes two bytes in UTF-8 but one character, so that columns after it
+/// differ between the encodings
+const CODE: &str =
+    "pub fn test() {\n    let é = 1; let s = String::new();\n    let _ = é; drop(s);\n}\n";
+
+/// Byte column of the last `s` of `line` in `CODE`
+fn byte_column_of_s(line: usize) -> u32 {
+    let text = CODE.lines().nth(line).unwrap();
+    u32::try_from(text.rfind('s').unwrap()).unwrap()
+}
+
+#[test]
+fn utf8_clients_get_byte_columns() {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("position_encoding_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("owl.rs");
+    fs::write(&file, CODE).unwrap();
+    let uri = format!("file://{}", file.display());
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    let response = client
+        .initialize_with_position_encodings(&format!("file://{}", dir.display()), &["utf-8"])
+        .unwrap();
+    assert_eq!(
+        response["result"]["capabilities"]["positionEncoding"],
+        "utf-8"
+    );
+    client.open_document(&uri, "rust", CODE).unwrap();
+
+    // the declaration of `s`, after `é` on its line
+    let result = client
+        .wait_for_decorations(&uri, 1, byte_column_of_s(1), TIMEOUT)
+        .unwrap();
+    let decorations = result["decorations"].as_array().unwrap();
+    let moved: Vec<_> = decorations
+        .iter()
+        .filter(|deco| deco["type"] == "move")
+        .map(|deco| {
+            (
+                deco["range"]["start"]["line"].clone(),
+                deco["range"]["start"]["character"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        moved,
+        [(Value::from(2), Value::from(byte_column_of_s(2)))],
+        "{decorations:?}"
+    );
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,128 @@
+#![feature(rustc_private)]
+
+//! Tests for stopping an analysis when the user cancels its progress.
+
+use std::{
+    env, fs,
+    path::Path,
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Time cargo may take to exit once the analysis is cancelled
+const PROMPTLY: Duration = Duration::from_secs(10);
+
+const CODE: &str = "\
+pub fn test() -> usize {
+    let s = String::new();
+    s.len()
+}
+";
+
+/// Build script writing `started` once cargo runs it, then waiting until
+/// cargo exits and writing `orphaned`.
+fn build_script(started: &Path, orphaned: &Path) -> String {
+    format!(
+        "\
+use std::{{fs, os::unix::process::parent_id, thread, time::{{Duration, Instant}}}};
+
+fn main() {{
+    let cargo = parent_id();
+    fs::write(r\"{}\", \"\").unwrap();
+    let start = Instant::now();
+    while parent_id() == cargo && start.elapsed() < Duration::from_secs(120) {{
+        thread::sleep(Duration::from_millis(100));
+    }}
+    fs::write(r\"{}\", \"\").unwrap();
+}}
+",
+        started.display(),
+        orphaned.display()
+    )
+}
+
+/// Wait until the file at `path` exists.
+fn wait_for_file(path: &Path, timeout: Duration) {
+    let start = Instant::now();
+    while !path.exists() {
+        assert!(
+            start.elapsed() < timeout,
+            "Timeout waiting for {}",
+            path.display()
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Next `$/progress` notification of `token` whose kind is `kind`.
+fn wait_for_progress(client: &mut LspClient, token: &Value, kind: &str) -> Value {
+    loop {
+        let params = client
+            .wait_for_notification("$/progress", TIMEOUT)
+            .expect("progress notification");
+        if params["token"] == *token && params["value"]["kind"] == kind {
+            return params["value"].clone();
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn cancelled_progress_stops_cargo() {
+    let package = TestPackage::new("progress_cancel", CODE).expect("Failed to create workspace");
+    let dir = &package.dir;
+    let started = Path::new(dir).join("build-started");
+    let orphaned = Path::new(dir).join("build-orphaned");
+    fs::write(format!("{dir}/build.rs"), build_script(&started, &orphaned))
+        .expect("Failed to write build script");
+    // no analysis target, so that opening the file starts the only analysis
+    let root = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("progress_cancel_root_{}", process::id()));
+    fs::create_dir_all(&root).expect("Failed to create root");
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client
+        .initialize_with_work_done_progress(&format!("file://{}", root.display()))
+        .unwrap();
+    client.open_document(&package.uri, "rust", CODE).unwrap();
+
+    let create = client
+        .wait_for_server_request("window/workDoneProgress/create", TIMEOUT)
+        .unwrap();
+    client.respond(&create, &Value::Null).unwrap();
+    let token = create["params"]["token"].clone();
+    let begin = wait_for_progress(&mut client, &token, "begin");
+    assert_eq!(begin["cancellable"], true, "{begin:#}");
+
+    wait_for_file(&started, TIMEOUT);
+    let cancelled = Instant::now();
+    client
+        .send_notification("window/workDoneProgress/cancel", &json!({ "token": token }))
+        .unwrap();
+    let end = wait_for_progress(&mut client, &token, "end");
+    assert_eq!(end["message"], "cancelled", "{end:#}");
+    wait_for_file(&orphaned, PROMPTLY);
+    assert!(cancelled.elapsed() < PROMPTLY);
+
+    let start = Instant::now();
+    let status = loop {
+        let response = client.status().unwrap();
+        if response["result"]["status"] != "analyzing" {
+            break response["result"].clone();
+        }
+        assert!(start.elapsed() < PROMPTLY, "{response:#}");
+        thread::sleep(Duration::from_millis(100));
+    };
+    assert_eq!(status["status"], "finished", "{status:#}");
+    assert_eq!(status["pending_packages"], json!([]), "{status:#}");
+
+    client.shutdown().unwrap();
+    let _ = fs::remove_dir_all(dir);
+    let _ = fs::remove_dir_all(&root);
+}
@@ -0,0 +1,4 @@
+pub fn greet() -> usize {
+    let name = String::from("owl");
+    helper::consume(name)
+}
@@ -0,0 +1,72 @@
+#![feature(rustc_private)]
+
+//! Tests for workspaces described by a `rust-project.json` instead of cargo.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ferrous_owl::{
+    LineCol, SourceIndex,
+    api::{self, AnalyzeOptions},
+};
+
+/// Copy of the two crate fixture, whose library from elsewhere is built
+/// into the directory
+fn workspace() -> tempfile::TempDir {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/project_json");
+    let dir = tempfile::tempdir().unwrap();
+    for file in ["rust-project.json", "app/lib.rs", "vendor/helper/lib.rs"] {
+        let copy = dir.path().join(file);
+        fs::create_dir_all(copy.parent().unwrap()).unwrap();
+        fs::copy(fixture.join(file), copy).unwrap();
+    }
+    dir
+}
+
+fn built_helper(root: &Path) -> PathBuf {
+    root.join("target/owl/deps/libhelper.rlib")
+}
+
+#[tokio::test]
+async fn members_are_analyzed_with_their_dependencies() {
+    let dir = workspace();
+    let workspace = api::analyze_package(dir.path(), AnalyzeOptions::default())
+        .await
+        .expect("workspace should be analyzed");
+    assert!(
+        workspace.crates.contains_key("app"),
+        "{:?}",
+        workspace.crates.keys()
+    );
+    assert!(
+        !workspace.crates.contains_key("helper"),
+        "crates from elsewhere are only built"
+    );
+    assert!(built_helper(dir.path()).exists());
+
+    let file = dir.path().join("app/lib.rs");
+    let source = fs::read_to_string(&file).unwrap();
+    // `name` in `let name = String::from("owl");`
+    let offset = SourceIndex::new(&source).line_col_to_char(LineCol::new(1, 9));
+    let kinds: Vec<_> = api::decorations_for(&workspace, &file, offset)
+        .iter()
+        .map(api::Deco::kind)
+        .collect();
+    assert!(kinds.contains(&"move"), "{kinds:?}");
+
+    let modified = fs::metadata(built_helper(dir.path()))
+        .and_then(|metadata| metadata.modified())
+        .unwrap();
+    api::analyze_package(dir.path(), AnalyzeOptions::default())
+        .await
+        .expect("workspace should be analyzed again");
+    assert_eq!(
+        fs::metadata(built_helper(dir.path()))
+            .and_then(|metadata| metadata.modified())
+            .unwrap(),
+        modified,
+        "the built dependency is reused"
+    );
+}
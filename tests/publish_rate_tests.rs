@@ -0,0 +1,71 @@
+#![feature(rustc_private)]
+
+//! Tests for coalescing the ownership diagnostics of rapid cursor moves.
+
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    let t = s.clone();\n    \
+                    drop(s);\n    drop(t);\n}\n";
+
+/// Publications of diagnostics tolerated for a burst of cursor moves
+const MAX_PUBLICATIONS: usize = 3;
+
+#[test]
+fn rapid_cursor_moves_are_coalesced() {
+    let package = TestPackage::new("publish_rate", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+
+    // alternate between `s` and `t`, ending on `t`
+    let ids: Vec<_> = (0..20_u32)
+        .map(|i| {
+            client
+                .execute_command(
+                    "ferrous-owl.enableOwnership",
+                    &[json!(package.uri), json!(1 + i % 2), json!(8)],
+                )
+                .unwrap()
+        })
+        .collect();
+
+    let mut responses = 0;
+    let mut published = Vec::new();
+    let mut last_message = Instant::now();
+    while last_message.elapsed() < Duration::from_secs(2) {
+        let Some(msg) = client.receive_message(Duration::from_millis(100)).unwrap() else {
+            continue;
+        };
+        last_message = Instant::now();
+        if msg["id"].as_i64().is_some_and(|id| ids.contains(&id)) {
+            responses += 1;
+        }
+        if msg["method"] == "textDocument/publishDiagnostics" && msg["params"]["uri"] == package.uri
+        {
+            published.push(msg["params"]["diagnostics"].clone());
+        }
+    }
+
+    assert_eq!(responses, ids.len());
+    assert!(
+        (1..=MAX_PUBLICATIONS).contains(&published.len()),
+        "{} publications: {published:#?}",
+        published.len()
+    );
+    let last = published.last().unwrap().to_string();
+    assert!(last.contains("variable `t`"), "{last}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,137 @@
+#![feature(rustc_private)]
+
+//! Tests for decorations of method call receivers.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, ForbiddenDeco, TestCase, run_tests};
+
+fn receiver_into_iter_moves() -> TestCase {
+    TestCase::new(
+        "receiver_into_iter_moves",
+        r#"
+        fn test() {
+            let items = vec![1, 2];
+            for _item in items.into_iter() {}
+        }
+    "#,
+    )
+    .cursor_on("items = vec")
+    .expect(
+        ExpectedDeco::move_deco()
+            .on_line(2)
+            .covering_text("items")
+            .with_message("moved as method receiver"),
+    )
+}
+
+fn receiver_iter_borrows() -> TestCase {
+    TestCase::new(
+        "receiver_iter_borrows",
+        r#"
+        fn test() {
+            let items = vec![1, 2];
+            let _count = items.iter().count();
+        }
+    "#,
+    )
+    .cursor_on("items = vec")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .covering_text("items")
+            .with_message("implicit immutable borrow for method call `iter`")
+            .with_auto_borrow(true),
+    )
+}
+
+fn receiver_iter_mut_borrows_mutably() -> TestCase {
+    TestCase::new(
+        "receiver_iter_mut_borrows_mutably",
+        r#"
+        fn test() {
+            let mut items = vec![1, 2];
+            for item in items.iter_mut() {
+                *item += 1;
+            }
+        }
+    "#,
+    )
+    .cursor_on("items = vec")
+    .expect(
+        ExpectedDeco::mut_borrow()
+            .on_line(2)
+            .covering_text("items")
+            .with_message("implicit mutable borrow for method call `iter_mut`")
+            .with_auto_borrow(true),
+    )
+}
+
+fn receiver_len_borrows_implicitly() -> TestCase {
+    TestCase::new(
+        "receiver_len_borrows_implicitly",
+        r#"
+        fn test() {
+            let s = String::new();
+            let _n = s.len();
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .covering_text("s")
+            .with_message("implicit immutable borrow for method call `len`")
+            .with_auto_borrow(true),
+    )
+}
+
+fn receiver_borrowed_with_ref_is_explicit() -> TestCase {
+    TestCase::new(
+        "receiver_borrowed_with_ref_is_explicit",
+        r#"
+        fn test() {
+            let s = String::new();
+            let _n = (&s).len();
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .with_message("immutably borrowed as method receiver")
+            .with_auto_borrow(false),
+    )
+    .forbid(ForbiddenDeco::new(DecoKind::ImmBorrow).at_text("implicit"))
+}
+
+fn reference_is_explicit_borrow() -> TestCase {
+    TestCase::new(
+        "reference_is_explicit_borrow",
+        r#"
+        fn test() {
+            let s = String::new();
+            let r = &s;
+            let _n = r.len();
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(
+        ExpectedDeco::imm_borrow()
+            .on_line(2)
+            .with_auto_borrow(false),
+    )
+}
+
+#[test]
+fn all_receiver_tests() {
+    run_tests(&[
+        receiver_into_iter_moves(),
+        receiver_iter_borrows(),
+        receiver_iter_mut_borrows_mutably(),
+        receiver_len_borrows_implicitly(),
+        receiver_borrowed_with_ref_is_explicit(),
+        reference_is_explicit_borrow(),
+    ]);
+}
@@ -0,0 +1,75 @@
+#![feature(rustc_private)]
+
+//! Tests for the lifetime of reference parameters, whose borrow is created
+//! by the caller.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::Value;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+pub fn test(s: &str) -> usize {
+    let n = s.len();
+    let upper = s.to_uppercase();
+    n + upper.len()
+}
+";
+
+/// Whether `decoration` contains the position `line`:`character`.
+fn contains(decoration: &Value, line: u64, character: u64) -> bool {
+    let position = |end: &str| {
+        let position = &decoration["range"][end];
+        (position["line"].as_u64(), position["character"].as_u64())
+    };
+    let at = (Some(line), Some(character));
+    position("start") <= at && at <= position("end")
+}
+
+#[test]
+fn reference_parameter_is_usable_over_its_uses() {
+    let package = TestPackage::new("reference_param", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    // the parameter name in the signature
+    let start = Instant::now();
+    let decorations = loop {
+        let response = client.cursor(&package.uri, 0, 12).expect("cursor request");
+        if let Some(items) = response["result"]["decorations"]
+            .as_array()
+            .filter(|items| !items.is_empty())
+        {
+            break items.clone();
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for {response}");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let lifetimes: Vec<_> = decorations
+        .iter()
+        .filter(|deco| deco["type"] == "lifetime")
+        .collect();
+    assert!(!lifetimes.is_empty(), "{decorations:#?}");
+    assert!(
+        lifetimes
+            .iter()
+            .all(|deco| deco["hover_text"] == "reference parameter `s` is usable here"),
+        "{lifetimes:#?}"
+    );
+    // `s` in `s.len()` and `s.to_uppercase()`
+    for (line, character) in [(1, 12), (2, 16)] {
+        assert!(
+            lifetimes.iter().any(|deco| contains(deco, line, character)),
+            "{line}:{character} {lifetimes:#?}"
+        );
+    }
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,66 @@
+#![feature(rustc_private)]
+
+//! Tests for the ownership reports written by `check --report`.
+
+use std::{env, fs, path::Path, process::Command};
+
+use ferrous_owl::TestPackage;
+
+const CODE: &str = "\
+pub fn consume(s: String) -> usize {
+    s.len()
+}
+
+pub fn test() -> usize {
+    let mut v = vec![1];
+    let r = &v;
+    let n = r.len();
+    v.push(n);
+    let s = String::from(\"owl\");
+    consume(s)
+}
+";
+
+/// Report of `CODE` in `format`, with at most `max_lines` source lines per
+/// variable.
+fn report(format: &str, max_lines: &str) -> String {
+    let package =
+        TestPackage::new(&format!("report_{format}"), CODE).expect("Failed to create workspace");
+    let dir = &package.dir;
+    let report = format!("{dir}/report.{format}");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["check", dir, "--report", &report])
+        .args(["--report-format", format, "--report-max-lines", max_lines])
+        .output()
+        .expect("Failed to run ferrous-owl");
+    assert!(
+        output.status.success(),
+        "check failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let rendered = fs::read_to_string(&report).expect("report not written");
+    let _ = fs::remove_dir_all(dir);
+    rendered
+}
+
+#[test]
+fn markdown_report_matches_golden_file() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/ownership_report.md");
+    let expected = fs::read_to_string(&golden).expect("Failed to read golden file");
+    assert_eq!(report("md", "40"), expected);
+}
+
+#[test]
+fn html_report_is_self_contained() {
+    let html = report("html", "2");
+    assert!(html.starts_with("<!DOCTYPE html>"), "{html}");
+    assert!(html.contains("<style>"), "{html}");
+    assert!(!html.contains("<link"), "{html}");
+    assert!(html.contains("<h2>src/lib.rs</h2>"), "{html}");
+    assert!(
+        html.contains("    consume(<mark class=\"move\">s</mark>)\n"),
+        "{html}"
+    );
+    assert!(html.contains("<p>5 more lines not shown</p>"), "{html}");
+}
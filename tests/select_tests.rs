@@ -0,0 +1,64 @@
+#![feature(rustc_private)]
+
+//! Tests for selecting one of several variables declared on a line.
+
+use ferrous_owl::{DecoKind, ExpectedDeco, ForbiddenDeco, TestCase, run_tests};
+
+const TUPLE: &str = r#"
+        fn test() {
+            let (a, b) = (String::new(), String::new());
+            drop(a);
+            drop(b);
+        }
+    "#;
+
+const STRUCT: &str = r#"
+        struct Pair {
+            x: String,
+            y: String,
+        }
+
+        fn test(p: Pair) {
+            let Pair { x, y } = p;
+            drop(x);
+            drop(y);
+        }
+    "#;
+
+fn select_first_of_tuple() -> TestCase {
+    TestCase::new("select_first_of_tuple", TUPLE)
+        .cursor_on("a, b)")
+        .expect(ExpectedDeco::move_deco().on_line(2).covering_text("a"))
+        .forbid(ForbiddenDeco::new(DecoKind::Move).on_line(3))
+}
+
+fn select_second_of_tuple() -> TestCase {
+    TestCase::new("select_second_of_tuple", TUPLE)
+        .cursor_on("b) =")
+        .expect(ExpectedDeco::move_deco().on_line(3).covering_text("b"))
+        .forbid(ForbiddenDeco::new(DecoKind::Move).on_line(2))
+}
+
+fn select_first_of_struct() -> TestCase {
+    TestCase::new("select_first_of_struct", STRUCT)
+        .cursor_on("x, y }")
+        .expect(ExpectedDeco::move_deco().on_line(7).covering_text("x"))
+        .forbid(ForbiddenDeco::new(DecoKind::Move).on_line(8))
+}
+
+fn select_second_of_struct() -> TestCase {
+    TestCase::new("select_second_of_struct", STRUCT)
+        .cursor_on("y } =")
+        .expect(ExpectedDeco::move_deco().on_line(8).covering_text("y"))
+        .forbid(ForbiddenDeco::new(DecoKind::Move).on_line(7))
+}
+
+#[test]
+fn all_select_tests() {
+    run_tests(&[
+        select_first_of_tuple(),
+        select_second_of_tuple(),
+        select_first_of_struct(),
+        select_second_of_struct(),
+    ]);
+}
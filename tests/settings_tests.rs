@@ -0,0 +1,65 @@
+#![feature(rustc_private)]
+
+//! Tests for settings changed by `workspace/didChangeConfiguration`.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::{DecoKind, TestPackage};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    let r = &s;\n    \
+                    println!(\"{r}\");\n    drop(s);\n}\n";
+
+fn types(result: &Value) -> Vec<String> {
+    result["decorations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|deco| deco["type"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn display_kinds_filter_decorations_and_diagnostics() {
+    let package = TestPackage::new("settings", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    let all = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    assert!(types(&all).contains(&"imm_borrow".to_owned()), "{all:#?}");
+
+    client
+        .execute_command(
+            "ferrous-owl.enableOwnership",
+            &[json!(package.uri), json!(1), json!(8)],
+        )
+        .unwrap();
+    let diagnostics = client.wait_for_diagnostics(&package.uri, TIMEOUT).unwrap();
+    assert!(
+        diagnostics
+            .iter()
+            .any(|d| d.kind() == Some(DecoKind::ImmBorrow)),
+        "{diagnostics:#?}"
+    );
+
+    client
+        .change_configuration(&json!({ "displayKinds": ["move"], "theme": "dark" }))
+        .unwrap();
+    let diagnostics = client.wait_for_diagnostics(&package.uri, TIMEOUT).unwrap();
+    assert!(
+        diagnostics.iter().all(|d| d.kind() == Some(DecoKind::Move)),
+        "{diagnostics:#?}"
+    );
+
+    let moves = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    assert_eq!(types(&moves), vec!["move"], "{moves:#?}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,104 @@
+#![feature(rustc_private)]
+
+//! Tests for clones of `Rc` and `Arc` sharing the allocation of a variable.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::{DecoKind, ExpectedDeco, TestCase, TestPackage, run_tests};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `shared` on line 3, cloned on lines 4 and 5
+const CODE: &str = "\
+use std::sync::Arc;
+
+pub fn test() -> usize {
+    let shared = Arc::new(String::new());
+    let a = Arc::clone(&shared);
+    let b = shared.clone();
+    a.len() + b.len() + shared.len()
+}
+";
+
+#[test]
+fn all_shared_clone_tests() {
+    run_tests(&[
+        TestCase::new(
+            "arc_clones",
+            r"
+            use std::sync::Arc;
+
+            fn test() -> usize {
+                let shared = Arc::new(String::new());
+                let a = Arc::clone(&shared);
+                let b = shared.clone();
+                a.len() + b.len()
+            }
+            ",
+        )
+        .cursor_on("shared =")
+        .expect(
+            ExpectedDeco::shared_clone()
+                .on_line(4)
+                .covering_text("Arc::clone(&shared)")
+                .with_message("shared ownership: cloned into `a`"),
+        )
+        .expect(
+            ExpectedDeco::shared_clone()
+                .on_line(5)
+                .covering_text("shared.clone()")
+                .with_message("shared ownership: cloned into `b`"),
+        ),
+        TestCase::new(
+            "string_clone",
+            r"
+            fn test() -> usize {
+                let s = String::new();
+                let t = s.clone();
+                t.len() + s.len()
+            }
+            ",
+        )
+        .cursor_on("s =")
+        .forbid(DecoKind::SharedClone),
+    ]);
+}
+
+#[test]
+fn lifetime_hover_counts_clones() {
+    let package = TestPackage::new("shared_clone", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    let result = client
+        .wait_for_decorations(&package.uri, 3, 8, TIMEOUT)
+        .unwrap();
+    let decorations = result["decorations"].as_array().unwrap();
+
+    let hover_of = |kind: &str| -> Vec<&str> {
+        decorations
+            .iter()
+            .filter(|deco| deco["type"] == kind)
+            .filter_map(|deco| deco["hover_text"].as_str())
+            .collect()
+    };
+    let lifetimes = hover_of("lifetime");
+    assert!(!lifetimes.is_empty(), "{result:#}");
+    for hover in lifetimes {
+        assert!(
+            hover.ends_with("(shared ownership — 2 live clones in this function)"),
+            "{hover}"
+        );
+    }
+    assert_eq!(
+        hover_of("shared_clone"),
+        vec![
+            "shared ownership: cloned into `a`",
+            "shared ownership: cloned into `b`"
+        ],
+        "{result:#}"
+    );
+
+    client.shutdown().unwrap();
+    let _ = fs::remove_dir_all(&package.dir);
+}
@@ -0,0 +1,116 @@
+#![feature(rustc_private)]
+
+//! Tests for shared-mut decoration detection.
+//!
+//! Shared and mutable borrows of a place can only be live at once through
+//! two-phase borrows, such as a method call with receiver `&mut self` whose
+//! arguments read the receiver.
+
+use ferrous_owl::{TestCase, run_tests};
+
+fn two_phase_whole_variable() -> TestCase {
+    TestCase::new(
+        "two_phase_whole_variable",
+        r#"
+        fn test() {
+            let mut v = vec![1];
+            v.push(v.len());
+        }
+    "#,
+    )
+    .cursor_on("v = vec")
+    .expect_shared_mut()
+}
+
+fn same_field_shared_and_mut() -> TestCase {
+    TestCase::new(
+        "same_field_shared_and_mut",
+        r#"
+        struct S {
+            x: Vec<usize>,
+            y: Vec<usize>,
+        }
+
+        fn test(mut s: S) {
+            s.x.push(s.x.len());
+        }
+    "#,
+    )
+    .cursor_on("mut s")
+    .expect_shared_mut()
+}
+
+fn whole_struct_and_field() -> TestCase {
+    TestCase::new(
+        "whole_struct_and_field",
+        r#"
+        struct S {
+            x: Vec<usize>,
+            y: Vec<usize>,
+        }
+
+        impl S {
+            fn total(&self) -> usize {
+                self.x.len() + self.y.len()
+            }
+        }
+
+        fn test(mut s: S) {
+            s.x.push(s.total());
+        }
+    "#,
+    )
+    .cursor_on("mut s")
+    .expect_shared_mut()
+}
+
+fn disjoint_fields() -> TestCase {
+    TestCase::new(
+        "disjoint_fields",
+        r#"
+        struct S {
+            x: Vec<usize>,
+            y: Vec<usize>,
+        }
+
+        fn test(mut s: S) {
+            let a = &s.x;
+            let b = &mut s.y;
+            b.push(a.len());
+        }
+    "#,
+    )
+    .cursor_on("mut s")
+    .expect_imm_borrow()
+    .expect_mut_borrow()
+    .forbid_shared_mut()
+}
+
+fn disjoint_fields_two_phase() -> TestCase {
+    TestCase::new(
+        "disjoint_fields_two_phase",
+        r#"
+        struct S {
+            x: Vec<usize>,
+            y: Vec<usize>,
+        }
+
+        fn test(mut s: S) {
+            s.y.push(s.x.len());
+        }
+    "#,
+    )
+    .cursor_on("mut s")
+    .forbid_shared_mut()
+}
+
+#[test]
+fn all_shared_mut_tests() {
+    run_tests(&[
+        two_phase_whole_variable(),
+        same_field_shared_and_mut(),
+        whole_struct_and_field(),
+        disjoint_fields(),
+        disjoint_fields_two_phase(),
+    ]);
+}
@@ -0,0 +1,60 @@
+#![feature(rustc_private)]
+
+//! Tests for selecting all parameters from a function signature.
+//!
+//! Lifetime decorations are filtered from diagnostics, so the selection is
+//! observed through the moves and borrows of each parameter.
+
+use ferrous_owl::{ExpectedDeco, TestCase, run_tests};
+
+fn signature_fn_keyword_selects_params() -> TestCase {
+    TestCase::new(
+        "signature_fn_keyword_selects_params",
+        r#"
+        fn test(a: String, b: String) {
+            drop(a);
+            drop(b);
+        }
+    "#,
+    )
+    .cursor_on("fn test")
+    .expect(ExpectedDeco::move_deco().on_line(1).times(1))
+    .expect(ExpectedDeco::move_deco().on_line(2).times(1))
+}
+
+fn signature_fn_name_selects_params() -> TestCase {
+    TestCase::new(
+        "signature_fn_name_selects_params",
+        r#"
+        fn test(a: String, b: String) -> usize {
+            a.len() + b.len()
+        }
+    "#,
+    )
+    .cursor_on("test(")
+    .expect(ExpectedDeco::imm_borrow().on_line(1).times(2))
+}
+
+fn signature_param_selects_single_param() -> TestCase {
+    TestCase::new(
+        "signature_param_selects_single_param",
+        r#"
+        fn test(a: String, b: String) {
+            drop(a);
+            drop(b);
+        }
+    "#,
+    )
+    .cursor_on("b: String")
+    .expect(ExpectedDeco::move_deco().on_line(2))
+    .expect(ExpectedDeco::move_deco().times(1))
+}
+
+#[test]
+fn all_signature_tests() {
+    run_tests(&[
+        signature_fn_keyword_selects_params(),
+        signature_fn_name_selects_params(),
+        signature_param_selects_single_param(),
+    ]);
+}
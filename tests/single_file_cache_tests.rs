@@ -0,0 +1,100 @@
+#![feature(rustc_private)]
+
+//! Tests for caching the analysis of files outside of cargo packages.
+
+use std::{
+    env, fs, process, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{CACHE_DIR_ENV, LspClient};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+pub fn first() -> usize {
+    let s = String::new();
+    s.len()
+}
+
+pub fn second() {
+    let v = vec![1];
+    drop(v);
+}
+";
+
+/// Functions of the crate of the file and how many were read from the cache,
+/// once `done` accepts them.
+fn wait_for_stats(client: &mut LspClient, done: impl Fn(u64, u64) -> bool) -> (u64, u64) {
+    let start = Instant::now();
+    loop {
+        let id = client
+            .send_request("ferrous-owl/stats", &json!({}))
+            .unwrap();
+        let response = client.wait_for_response(id, TIMEOUT).unwrap();
+        let counts = response["result"]["crates"]
+            .as_array()
+            .and_then(|crates| crates.first())
+            .map(|krate: &Value| {
+                (
+                    krate["functions"].as_u64().unwrap(),
+                    krate["cache_hits"].as_u64().unwrap(),
+                )
+            });
+        if let Some((functions, cache_hits)) = counts
+            && done(functions, cache_hits)
+        {
+            return (functions, cache_hits);
+        }
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "Timeout waiting for stats: {response}"
+        );
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+#[test]
+fn second_analysis_of_a_file_is_read_from_the_cache() {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("single_file_cache_{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let cache = dir.join("cache");
+    fs::create_dir_all(&cache).unwrap();
+    let file = dir.join("owl.rs");
+    fs::write(&file, CODE).unwrap();
+    let uri = format!("file://{}", file.display());
+    // the only test of this binary, read by the server it starts
+    unsafe { env::set_var(CACHE_DIR_ENV, &cache) };
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client
+        .initialize(&format!("file://{}", dir.display()))
+        .unwrap();
+    client.open_document(&uri, "rust", CODE).unwrap();
+    let (functions, cache_hits) = wait_for_stats(&mut client, |functions, _| functions >= 2);
+    assert_eq!(cache_hits, 0, "nothing is cached at first");
+    assert!(
+        cache.join("single").is_dir(),
+        "cache written to {}",
+        cache.display()
+    );
+
+    let id = client
+        .send_request(
+            "ferrous-owl/analyze",
+            &json!({ "scope": "file", "uri": uri, "force": true }),
+        )
+        .unwrap();
+    let response = client.wait_for_response(id, TIMEOUT).unwrap();
+    assert!(response.get("error").is_none(), "{response}");
+    let (cached_functions, _) = wait_for_stats(&mut client, |functions, cache_hits| {
+        functions > 0 && cache_hits == functions
+    });
+    assert_eq!(cached_functions, functions, "all functions are cached");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,54 @@
+#![feature(rustc_private)]
+
+//! Tests for crates with functions that cannot be analyzed.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `bytes` does not compile, `s` in `ok` is on line 6
+const CODE: &str = r#"pub fn bytes() -> usize {
+    let data: &[u8] = include_bytes!("missing.bin");
+    data.len()
+}
+
+pub fn ok() {
+    let s = String::new();
+    drop(s);
+}
+"#;
+
+#[test]
+fn other_functions_keep_decorations() {
+    let package = TestPackage::new("skipped", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    let decorations = loop {
+        let response = client.cursor(&package.uri, 6, 8).unwrap();
+        let result = &response["result"];
+        if let Some(items) = result["decorations"].as_array()
+            && !items.is_empty()
+        {
+            break items.clone();
+        }
+        assert_ne!(result["status"], json!("error"), "{result:#?}");
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for decorations");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+
+    assert!(
+        decorations.iter().any(|deco| deco["type"] == json!("move")),
+        "{decorations:#?}"
+    );
+}
@@ -0,0 +1,55 @@
+#![feature(rustc_private)]
+
+//! Tests for results of files that changed on disk after their analysis.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+fn lines(result: &Value) -> Vec<u64> {
+    result["decorations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|deco| deco["range"]["start"]["line"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn changed_file_is_stale_until_analyzed_again() {
+    let package = TestPackage::new("stale", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    let analyzed = client
+        .wait_for_decorations(&package.uri, 1, 8, TIMEOUT)
+        .unwrap();
+    assert_eq!(analyzed["status"], json!("finished"));
+    assert!(lines(&analyzed).contains(&1), "{analyzed:#?}");
+
+    // `s` moves one line down, the old ranges no longer match the text, which
+    // the editor reloads
+    let changed = format!("// inserted\n{CODE}");
+    fs::write(&package.file, &changed).expect("Failed to rewrite source");
+    client
+        .change_document(&package.uri, 2, &[json!({ "text": changed })])
+        .unwrap();
+    let stale = client.cursor(&package.uri, 2, 8).unwrap();
+    assert_eq!(stale["result"]["status"], json!("stale"), "{stale:#?}");
+    assert_eq!(stale["result"]["decorations"], json!([]));
+
+    let recovered = client
+        .wait_for_decorations(&package.uri, 2, 8, TIMEOUT)
+        .unwrap();
+    assert_ne!(recovered["status"], json!("stale"));
+    assert!(lines(&recovered).contains(&2), "{recovered:#?}");
+    assert!(!lines(&recovered).contains(&1), "{recovered:#?}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
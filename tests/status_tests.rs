@@ -0,0 +1,68 @@
+#![feature(rustc_private)]
+
+//! Tests for reporting which files have analysis results.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+pub fn test() -> String {
+    let s = String::new();
+    let t = s;
+    t
+}
+";
+
+#[test]
+fn update_notification_precedes_decorations() {
+    let package = TestPackage::new("status", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    // results of other files may arrive first
+    while !client
+        .wait_for_notification("ferrous-owl/didUpdateAnalysis", TIMEOUT)
+        .unwrap()["paths"]
+        .as_array()
+        .is_some_and(|paths| paths.contains(&json!(package.file)))
+    {}
+
+    let response = client.cursor(&package.uri, 1, 8).unwrap();
+    let decorations = &response["result"]["decorations"];
+    assert!(
+        decorations
+            .as_array()
+            .is_some_and(|decorations| !decorations.is_empty()),
+        "{response}"
+    );
+
+    let status = client.status().unwrap();
+    let files = status["result"]["analyzed_files"].as_array().unwrap();
+    let lib = files
+        .iter()
+        .find(|entry| entry["path"] == json!(package.file))
+        .unwrap_or_else(|| panic!("{status}"));
+    assert_eq!(lib["functions"], json!(1));
+    assert!(lib["last_updated"].as_u64().is_some_and(|ms| ms > 0));
+    assert!(status["result"]["pending_packages"].is_array(), "{status}");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+}
+
+#[test]
+fn status_without_results_is_empty() {
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client
+        .initialize(&format!("file://{}", env::temp_dir().display()))
+        .unwrap();
+    let status = client.status().unwrap();
+    assert_eq!(status["result"]["analyzed_files"], json!([]), "{status}");
+    assert!(status["result"]["status"].is_string(), "{status}");
+    let _ = client.shutdown();
+}
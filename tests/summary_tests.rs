@@ -0,0 +1,90 @@
+#![feature(rustc_private)]
+
+//! Tests for the summary at the declaration of a selected variable.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `s` is borrowed twice and moved once, `v` is borrowed once and dropped at
+/// the end of the function on line 11.
+const CODE: &str = "\
+pub fn test() -> usize {
+    let s = String::new();
+    let a = &s;
+    let b = &s;
+    let n = a.len() + b.len();
+    let t = s;
+    let v = vec![1];
+    let len = v.len();
+    drop(t);
+    n + len
+}
+";
+
+fn summaries(decorations: &Value) -> Vec<&Value> {
+    decorations
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|deco| deco["type"] == json!("summary"))
+        .collect()
+}
+
+#[test]
+fn summary_counts_moves_borrows_and_drops() {
+    let package = TestPackage::new("summary", CODE).expect("Failed to create workspace");
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+
+    let start = Instant::now();
+    let moved = loop {
+        let response = client.cursor(&package.uri, 1, 8).unwrap();
+        let result = &response["result"];
+        if result["status"] == json!("finished")
+            && result["decorations"]
+                .as_array()
+                .is_some_and(|items| !items.is_empty())
+        {
+            break result["decorations"].clone();
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+    let dropped = client.cursor(&package.uri, 6, 8).unwrap()["result"]["decorations"].clone();
+    let without =
+        client.cursor_without_summary(&package.uri, 1, 8).unwrap()["result"]["decorations"].clone();
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+
+    let summary = summaries(&moved);
+    assert_eq!(summary.len(), 1, "{moved:#?}");
+    assert_eq!(
+        summary[0]["hover_text"],
+        json!("variable `s`: moved 1×, borrowed 2× (2 shared, 0 mut)")
+    );
+    assert_eq!(
+        summary[0]["range"],
+        json!({
+            "start": { "line": 1, "character": 8 },
+            "end": { "line": 1, "character": 9 }
+        })
+    );
+
+    let summary = summaries(&dropped);
+    assert_eq!(summary.len(), 1, "{dropped:#?}");
+    assert_eq!(
+        summary[0]["hover_text"],
+        json!("variable `v`: moved 0×, borrowed 1× (1 shared, 0 mut), dropped at line 11")
+    );
+
+    assert!(summaries(&without).is_empty(), "{without:#?}");
+    assert!(!without.as_array().unwrap().is_empty());
+}
@@ -0,0 +1,72 @@
+#![feature(rustc_private)]
+
+//! Tests for selecting the kinds of targets to analyze.
+
+use std::{
+    fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+const EXAMPLE: &str = "fn main() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+/// Poll the decorations of `s` in `file_uri` until `done` accepts them.
+fn wait_for_cursor(client: &mut LspClient, file_uri: &str, done: impl Fn(&Value) -> bool) -> Value {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        let response = client.cursor(file_uri, 1, 8).expect("cursor request");
+        if done(&response["result"]) {
+            return response["result"].clone();
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    panic!("Timeout waiting for decorations of {file_uri}");
+}
+
+fn has_decorations(result: &Value) -> bool {
+    result["decorations"]
+        .as_array()
+        .is_some_and(|items| !items.is_empty())
+}
+
+#[test]
+fn examples_only_analyzed_when_requested() {
+    let package = TestPackage::new("target_kinds", CODE).expect("Failed to create workspace");
+    let dir = &package.dir;
+    fs::create_dir_all(format!("{dir}/examples")).unwrap();
+    fs::write(format!("{dir}/examples/demo.rs"), EXAMPLE).unwrap();
+    let example_uri = format!("file://{dir}/examples/demo.rs");
+
+    let mut client = package
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    wait_for_cursor(&mut client, &package.uri, |result| {
+        has_decorations(result) && result["status"] == "finished"
+    });
+    let example = client.cursor(&example_uri, 1, 8).unwrap();
+    assert_eq!(
+        example["result"]["decorations"],
+        json!([]),
+        "examples are not analyzed by default"
+    );
+
+    let id = client
+        .send_request(
+            "ferrous-owl/analyze",
+            &json!({ "target_kinds": ["lib", "example"] }),
+        )
+        .unwrap();
+    client.wait_for_response(id, TIMEOUT).unwrap();
+    client
+        .wait_for_decorations(&example_uri, 1, 8, TIMEOUT)
+        .unwrap();
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(dir);
+}
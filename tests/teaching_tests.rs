@@ -0,0 +1,109 @@
+#![feature(rustc_private)]
+
+//! Tests for showing the lexical scope of variables with `teaching_mode`.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// `n` is last used in the middle of the block declaring it
+const CODE: &str = "\
+pub fn test() -> u32 {
+    let n = 1;
+    let m = n + 1;
+    let k = m * 2;
+    k + m
+}
+";
+
+/// Position of `n` in [`CODE`]
+const N: (u32, u32) = (1, 8);
+
+/// Decorations at `position` in `code` of a server started with `options`,
+/// once the analysis finished.
+fn decorations_at(name: &str, code: &str, position: (u32, u32), options: &Value) -> Vec<Value> {
+    let package = TestPackage::new(name, code).expect("Failed to create workspace");
+    let mut client = package
+        .start_server_with_options(env!("CARGO_BIN_EXE_ferrous-owl"), options)
+        .unwrap();
+
+    let start = Instant::now();
+    let decorations = loop {
+        let response = client.cursor(&package.uri, position.0, position.1).unwrap();
+        let result = &response["result"];
+        if result["is_analyzed"] == json!(true) && result["status"] == json!("finished") {
+            break result["decorations"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+    decorations
+}
+
+/// Start and end of a decoration, as (line, character) pairs.
+fn bounds(deco: &Value) -> ((u64, u64), (u64, u64)) {
+    let position = |key: &str| {
+        let position = &deco["range"][key];
+        (
+            position["line"].as_u64().unwrap(),
+            position["character"].as_u64().unwrap(),
+        )
+    };
+    (position("start"), position("end"))
+}
+
+fn of_type<'a>(decorations: &'a [Value], ty: &str) -> Vec<&'a Value> {
+    decorations
+        .iter()
+        .filter(|deco| deco["type"] == json!(ty))
+        .collect()
+}
+
+#[test]
+fn scope_continues_after_last_use() {
+    let decorations = decorations_at("teaching_scope", CODE, N, &json!({ "teaching_mode": true }));
+    let lifetimes = of_type(&decorations, "lifetime");
+    let scopes = of_type(&decorations, "scope");
+    assert!(!lifetimes.is_empty(), "no lifetime in {decorations:#?}");
+    assert!(!scopes.is_empty(), "no scope in {decorations:#?}");
+
+    for scope in &scopes {
+        assert_eq!(scope["hover_text"], json!("lexical scope of variable `n`"));
+        let (start, end) = bounds(scope);
+        for lifetime in &lifetimes {
+            let (live_start, live_end) = bounds(lifetime);
+            assert!(
+                end <= live_start || live_end <= start,
+                "scope {scope} overlaps lifetime {lifetime}"
+            );
+        }
+    }
+    // the scope reaches the end of the block, past the last use on line 2
+    let (_, end) = bounds(scopes.last().unwrap());
+    assert!(end >= (4, 0), "scope ends at {end:?}");
+}
+
+#[test]
+fn scope_hidden_by_default() {
+    let decorations = decorations_at("teaching_default", CODE, N, &json!({}));
+    assert!(
+        !of_type(&decorations, "lifetime").is_empty(),
+        "no lifetime in {decorations:#?}"
+    );
+    assert!(
+        of_type(&decorations, "scope").is_empty(),
+        "unexpected scope in {decorations:#?}"
+    );
+}
@@ -0,0 +1,138 @@
+#![feature(rustc_private)]
+
+//! Tests for selecting compiler temporaries in method chains and nested calls.
+
+use std::{
+    env, fs, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::TestPackage;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "\
+use std::sync::Mutex;
+
+pub fn test(m: &Mutex<Vec<u8>>) -> usize {
+    let len = m.lock().unwrap().len();
+    len
+}
+";
+
+/// Position of `.lock()` in [`CODE`]
+const LOCK: (u32, u32) = (3, 16);
+
+/// Decorations at `position` in `code` of a server started with `options`,
+/// once the analysis finished.
+fn decorations_at(name: &str, code: &str, position: (u32, u32), options: &Value) -> Vec<Value> {
+    let package = TestPackage::new(name, code).expect("Failed to create workspace");
+    let mut client = package
+        .start_server_with_options(env!("CARGO_BIN_EXE_ferrous-owl"), options)
+        .unwrap();
+
+    let start = Instant::now();
+    let decorations = loop {
+        let response = client.cursor(&package.uri, position.0, position.1).unwrap();
+        let result = &response["result"];
+        if result["is_analyzed"] == json!(true) && result["status"] == json!("finished") {
+            break result["decorations"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+        }
+        assert!(start.elapsed() < TIMEOUT, "Timeout waiting for analysis");
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&package.dir);
+    decorations
+}
+
+fn is_temporary_lifetime(deco: &Value) -> bool {
+    deco["type"] == json!("lifetime")
+        && deco["hover_text"]
+            .as_str()
+            .is_some_and(|text| text.starts_with("lifetime of temporary of type `"))
+}
+
+#[test]
+fn temporary_guard_lives_until_end_of_statement() {
+    let decorations = decorations_at(
+        "temporaries",
+        CODE,
+        LOCK,
+        &json!({ "show_temporaries": true }),
+    );
+    let lifetimes: Vec<_> = decorations
+        .iter()
+        .filter(|deco| is_temporary_lifetime(deco))
+        .collect();
+    assert!(
+        lifetimes
+            .iter()
+            .all(|deco| deco["hover_text"].as_str().unwrap().contains("MutexGuard")),
+        "{lifetimes:#?}"
+    );
+
+    // overlapping decorations split the lifetime into pieces
+    let on_statement = |deco: &Value, key: &str, character: u32| {
+        deco["range"][key] == json!({ "line": 3, "character": character })
+    };
+    assert!(
+        lifetimes.iter().any(|deco| on_statement(deco, "start", 14)),
+        "lifetime starts at `m.lock()`: {lifetimes:#?}"
+    );
+    assert!(
+        lifetimes.iter().any(|deco| on_statement(deco, "end", 38)),
+        "lifetime ends after `;`: {lifetimes:#?}"
+    );
+}
+
+#[test]
+fn temporaries_are_not_described_by_default() {
+    let decorations = decorations_at("no_temporaries", CODE, LOCK, &json!({}));
+    assert!(
+        !decorations.iter().any(is_temporary_lifetime),
+        "{decorations:#?}"
+    );
+}
+
+#[test]
+fn nested_call_decorates_callee_name() {
+    let code = "\
+fn inner(x: u32) -> u32 {
+    x + 1
+}
+
+pub fn outer(x: u32) -> u32 {
+    x * 2
+}
+
+pub fn test() -> u32 {
+    outer(inner(1))
+}
+";
+    // the result of `inner` is a temporary, selected at its call
+    let decorations = decorations_at(
+        "nested_call",
+        code,
+        (9, 11),
+        &json!({ "show_temporaries": true }),
+    );
+    let calls: Vec<_> = decorations
+        .iter()
+        .filter(|deco| deco["type"] == json!("call"))
+        .map(|deco| &deco["range"])
+        .collect();
+    assert_eq!(
+        calls,
+        vec![&json!({
+            "start": { "line": 9, "character": 10 },
+            "end": { "line": 9, "character": 15 }
+        })],
+        "{decorations:#?}"
+    );
+}
@@ -0,0 +1,83 @@
+#![feature(rustc_private)]
+
+//! Tests for running test cases against a prebuilt binary or a running
+//! server, as chosen by environment variables.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    os::unix::fs::PermissionsExt,
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use ferrous_owl::{ExpectedDeco, LSP_ADDR_ENV, OWL_BINARY_ENV, TestCase, run_tests};
+
+/// Held while the server environment variables are set, as tests of a file
+/// share the process environment
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn simple_move() -> TestCase {
+    TestCase::new(
+        "external_server_move",
+        r#"
+        fn test() -> String {
+            let s = String::new();
+            let t = s;
+            t
+        }
+    "#,
+    )
+    .cursor_on("s = String")
+    .expect(ExpectedDeco::move_deco().on_line(2))
+}
+
+#[test]
+fn runs_binary_from_env() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("invocations");
+    let stub = dir.path().join("owl-stub");
+    fs::write(
+        &stub,
+        format!(
+            "#!/bin/sh\necho \"args:$*\" >> '{}'\nexec '{}' \"$@\"\n",
+            log.display(),
+            env!("CARGO_BIN_EXE_ferrous-owl")
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let _lock = ENV_LOCK.lock().unwrap();
+    // SAFETY: other tests of this file wait for `ENV_LOCK`
+    unsafe { env::set_var(OWL_BINARY_ENV, &stub) };
+    run_tests(&[simple_move()]);
+    unsafe { env::remove_var(OWL_BINARY_ENV) };
+
+    // one server for the batch, speaking LSP over stdio without arguments
+    assert_eq!(fs::read_to_string(&log).unwrap(), "args:\n");
+}
+
+#[test]
+fn connects_to_running_server() {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["--listen", "127.0.0.1:0", "--single-client"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(server.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let address = line.trim().strip_prefix("listening at ").unwrap();
+
+    let _lock = ENV_LOCK.lock().unwrap();
+    // SAFETY: other tests of this file wait for `ENV_LOCK`
+    unsafe { env::set_var(LSP_ADDR_ENV, address) };
+    run_tests(&[simple_move()]);
+    unsafe { env::remove_var(LSP_ADDR_ENV) };
+
+    // the server exits with its only client
+    assert!(server.wait().unwrap().success());
+}
@@ -0,0 +1,78 @@
+#![feature(rustc_private)]
+
+//! Tests for decorations of documents with unsaved changes.
+
+use std::{env, fs, process, time::Duration};
+
+use ferrous_owl::LspClient;
+use serde_json::{Value, json};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+/// Kinds and ranges of the decorations of the cursor `result`
+fn kinds_and_ranges(result: &Value) -> Vec<(Value, Value)> {
+    result["decorations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|deco| (deco["type"].clone(), deco["range"].clone()))
+        .collect()
+}
+
+/// `decorations` moved down by `lines`.
+fn moved_down(decorations: &[(Value, Value)], lines: u64) -> Vec<(Value, Value)> {
+    decorations
+        .iter()
+        .map(|(kind, range)| {
+            let mut range = range.clone();
+            for end in ["start", "end"] {
+                let line = range[end]["line"].as_u64().unwrap();
+                range[end]["line"] = json!(line + lines);
+            }
+            (kind.clone(), range)
+        })
+        .collect()
+}
+
+#[test]
+fn decorations_follow_unsaved_changes() {
+    let dir = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("unsaved_changes_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("owl.rs");
+    fs::write(&file, CODE).unwrap();
+    let uri = format!("file://{}", file.display());
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client
+        .initialize(&format!("file://{}", dir.display()))
+        .unwrap();
+    client.open_document(&uri, "rust", CODE).unwrap();
+    let saved = kinds_and_ranges(&client.wait_for_decorations(&uri, 1, 8, TIMEOUT).unwrap());
+
+    let insert_lines = json!({
+        "range": {
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 0, "character": 0 }
+        },
+        "text": "// not saved\n\n"
+    });
+    client.change_document(&uri, 2, &[insert_lines]).unwrap();
+    let unsaved = kinds_and_ranges(&client.wait_for_decorations(&uri, 3, 8, TIMEOUT).unwrap());
+    assert_eq!(unsaved, moved_down(&saved, 2));
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        CODE,
+        "the file on disk is unchanged"
+    );
+
+    client.close_document(&uri).unwrap();
+    let closed = kinds_and_ranges(&client.wait_for_decorations(&uri, 1, 8, TIMEOUT).unwrap());
+    assert_eq!(closed, saved, "closed documents are read from disk");
+
+    let _ = client.shutdown();
+    let _ = fs::remove_dir_all(&dir);
+}
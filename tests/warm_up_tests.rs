@@ -0,0 +1,118 @@
+#![feature(rustc_private)]
+
+//! Tests for analyzing recently opened files ahead of their packages.
+
+use std::{
+    env, fs,
+    path::Path,
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use ferrous_owl::{LspClient, TestPackage};
+use serde_json::Value;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const LIB: &str = "\
+pub mod helper;
+pub mod other;
+";
+
+/// `s` on line 1, building on its own
+const HELPER: &str = "\
+pub fn take() -> usize {
+    let s = String::new();
+    s.len()
+}
+";
+
+/// Not building without the rest of the crate
+const OTHER: &str = "\
+pub fn twice() -> usize {
+    let n = crate::helper::take();
+    n * 2
+}
+";
+
+/// Build script waiting until the file at `release` exists, which holds
+/// back the analysis of the package.
+fn build_script(release: &Path) -> String {
+    format!(
+        "\
+use std::{{path::Path, thread, time::{{Duration, Instant}}}};
+
+fn main() {{
+    let start = Instant::now();
+    while !Path::new(r\"{}\").exists() && start.elapsed() < Duration::from_secs(120) {{
+        thread::sleep(Duration::from_millis(100));
+    }}
+}}
+",
+        release.display()
+    )
+}
+
+/// Whether the file that does not build on its own has results in `status`.
+fn analyzes_other(status: &Value) -> bool {
+    status["analyzed_files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|analyzed| analyzed["path"].as_str())
+        .any(|path| path.ends_with("src/other.rs"))
+}
+
+#[test]
+fn opened_file_is_decorated_before_its_package() {
+    let package = TestPackage::new("warm_up", LIB).expect("Failed to create workspace");
+    let dir = &package.dir;
+    let release = Path::new(dir).join("release-build");
+    fs::write(format!("{dir}/build.rs"), build_script(&release))
+        .expect("Failed to write build script");
+    fs::write(format!("{dir}/src/other.rs"), OTHER).expect("Failed to write source");
+    let file = format!("{dir}/src/helper.rs");
+    fs::write(&file, HELPER).expect("Failed to write source");
+    let uri = format!("file://{file}");
+    // no analysis target, so that opening the file starts the only analysis
+    let root = env::temp_dir()
+        .join("owl-tests")
+        .join(format!("warm_up_root_{}", process::id()));
+    fs::create_dir_all(&root).expect("Failed to create root");
+
+    let mut client = LspClient::start(env!("CARGO_BIN_EXE_ferrous-owl"), &[]).unwrap();
+    client
+        .initialize(&format!("file://{}", root.display()))
+        .unwrap();
+    client.open_document(&uri, "rust", HELPER).unwrap();
+
+    // the build script holds back the package until released
+    let warm = client.wait_for_decorations(&uri, 1, 8, TIMEOUT).unwrap();
+    let status = client.status().unwrap()["result"].clone();
+    assert_eq!(status["status"], "analyzing", "{status:#}");
+    let pending = status["pending_packages"].as_array().unwrap();
+    assert!(pending.iter().any(|pending| *pending != file), "{status:#}");
+    assert!(!analyzes_other(&status), "{status:#}");
+
+    fs::write(&release, "").expect("Failed to release build script");
+    let start = Instant::now();
+    let status = loop {
+        let response = client.status().unwrap();
+        if response["result"]["status"] != "analyzing" {
+            break response["result"].clone();
+        }
+        assert!(start.elapsed() < TIMEOUT, "{response:#}");
+        thread::sleep(Duration::from_millis(200));
+    };
+    assert_eq!(status["status"], "finished", "{status:#}");
+    assert!(analyzes_other(&status), "{status:#}");
+    let analyzed = client.wait_for_decorations(&uri, 1, 8, TIMEOUT).unwrap();
+    assert_eq!(
+        analyzed["decorations"].as_array().unwrap().len(),
+        warm["decorations"].as_array().unwrap().len()
+    );
+
+    client.shutdown().unwrap();
+    let _ = fs::remove_dir_all(dir);
+    let _ = fs::remove_dir_all(&root);
+}
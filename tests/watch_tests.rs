@@ -0,0 +1,80 @@
+#![feature(rustc_private)]
+
+//! Tests for analyzing again on changes with `check --watch`.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use ferrous_owl::TestPackage;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Receive lines until one starts with `prefix`, returning all of them.
+fn lines_until(lines: &mpsc::Receiver<String>, prefix: &str) -> Vec<String> {
+    let mut received = Vec::new();
+    loop {
+        match lines.recv_timeout(TIMEOUT) {
+            Ok(line) => {
+                let done = line.starts_with(prefix);
+                received.push(line);
+                if done {
+                    return received;
+                }
+            }
+            Err(e) => panic!("no line starting with {prefix:?} ({e}), got {received:?}"),
+        }
+    }
+}
+
+#[test]
+fn watch_analyzes_again_after_change() {
+    let package = TestPackage::new(
+        "watch",
+        "pub fn test() -> String {\n    let s = String::new();\n    let t = s;\n    t\n}\n",
+    )
+    .expect("Failed to create workspace");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .args(["check", "--watch", "--poll-interval", "100", &package.dir])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to run ferrous-owl");
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in stdout.lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    lines_until(&lines, "round 1 ");
+    let first = lines_until(&lines, "      2 ");
+    assert!(
+        first.last().unwrap().ends_with("src/lib.rs"),
+        "unexpected summary: {first:?}"
+    );
+
+    fs::write(
+        &package.file,
+        "pub fn test() -> String {\n    let s = String::new();\n    let t = s;\n    let u = t;\n    u\n}\n",
+    )
+    .expect("Failed to change source");
+    let second = lines_until(&lines, "  src/lib.rs: ");
+    child.kill().ok();
+    child.wait().ok();
+
+    assert!(
+        second.iter().any(|line| line.starts_with("round 2 ")),
+        "no second round: {second:?}"
+    );
+    assert_eq!(second.last().unwrap(), "  src/lib.rs: moves 2 -> 3");
+}
@@ -0,0 +1,61 @@
+#![feature(rustc_private)]
+
+//! Tests for adding and removing workspace folders.
+
+use std::{env, fs, time::Duration};
+
+use ferrous_owl::TestPackage;
+use serde_json::json;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+const CODE: &str = "pub fn test() {\n    let s = String::new();\n    drop(s);\n}\n";
+
+#[test]
+fn removed_workspace_folder_is_forgotten() {
+    let kept = TestPackage::new("kept", CODE).expect("Failed to create workspace");
+    let removed = TestPackage::new("removed", CODE).expect("Failed to create workspace");
+    let kept_file = &kept.uri;
+    let (removed_uri, removed_file) = (removed.dir_uri(), &removed.uri);
+
+    let mut client = kept
+        .start_server(env!("CARGO_BIN_EXE_ferrous-owl"))
+        .unwrap();
+    client
+        .wait_for_decorations(kept_file, 1, 8, TIMEOUT)
+        .unwrap();
+
+    client
+        .change_workspace_folders(&[&removed_uri], &[])
+        .unwrap();
+    client.open_document(removed_file, "rust", CODE).unwrap();
+    client
+        .wait_for_decorations(removed_file, 1, 8, TIMEOUT)
+        .unwrap();
+    client
+        .execute_command(
+            "ferrous-owl.enableOwnership",
+            &[json!(removed_file), json!(1), json!(8)],
+        )
+        .unwrap();
+    let diagnostics = client.wait_for_diagnostics(removed_file, TIMEOUT).unwrap();
+    assert!(!diagnostics.is_empty());
+
+    client
+        .change_workspace_folders(&[], &[&removed_uri])
+        .unwrap();
+    client
+        .wait_for_cleared_diagnostics(removed_file, TIMEOUT)
+        .unwrap();
+
+    let removed_result = client.cursor(removed_file, 1, 8).unwrap();
+    assert_eq!(removed_result["result"]["decorations"], json!([]));
+    let kept_result = client.cursor(kept_file, 1, 8).unwrap();
+    assert_eq!(kept_result["result"]["is_analyzed"], json!(true));
+    assert_ne!(kept_result["result"]["decorations"], json!([]));
+
+    let _ = client.shutdown();
+    for package in [kept, removed] {
+        let _ = fs::remove_dir_all(&package.dir);
+    }
+}